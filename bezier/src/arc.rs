@@ -1,11 +1,13 @@
 //! Elliptic arc related maths and tools.
 
 use std::f32::*;
-use std::ops::Rem;
 
 use {Point, point2, Vec2, vec2, Transform2D, Radians, Line};
-use utils::{tangent, directed_angle};
 
+/// An elliptic arc described with the endpoint parameterization used by SVG.
+///
+/// See the [SVG specification](https://www.w3.org/TR/SVG/implnote.html#ArcImplementationNotes).
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct SvgArc {
     pub from: Point,
     pub to: Point,
@@ -14,6 +16,8 @@ pub struct SvgArc {
     pub flags: ArcFlags,
 }
 
+/// An elliptic arc described with the center parameterization.
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Arc {
     pub center: Point,
     pub radii: Vec2,
@@ -23,6 +27,10 @@ pub struct Arc {
 }
 
 impl Arc {
+    /// Converts from the endpoint parameterization.
+    ///
+    /// Radii that are too small to join the endpoints are scaled up as described in the SVG
+    /// specification. The arc must not be degenerate (see `SvgArc::is_straight_line`).
     pub fn from_svg_arc(arc: &SvgArc) -> Arc {
         debug_assert!(!arc.from.x.is_nan());
         debug_assert!(!arc.from.y.is_nan());
@@ -31,13 +39,10 @@ impl Arc {
         debug_assert!(!arc.radii.x.is_nan());
         debug_assert!(!arc.radii.y.is_nan());
         debug_assert!(!arc.x_rotation.get().is_nan());
+        debug_assert!(!arc.is_straight_line());
 
-        let rx = arc.radii.x;
-        let ry = arc.radii.y;
-
-        assert_ne!(arc.from, arc.to);
-        assert_ne!(rx, 0.0);
-        assert_ne!(ry, 0.0);
+        let mut rx = arc.radii.x.abs();
+        let mut ry = arc.radii.y.abs();
 
         let xr = arc.x_rotation.get() % (2.0 * consts::PI);
         let cos_phi = xr.cos();
@@ -52,8 +57,15 @@ impl Arc {
             -sin_phi * hd_x + cos_phi * hd_y,
         );
 
-        // TODO: sanitize radii
+        // F6.6.2
+        let radii_scale = p.x * p.x / (rx * rx) + p.y * p.y / (ry * ry);
+        if radii_scale > 1.0 {
+            let s = radii_scale.sqrt();
+            rx *= s;
+            ry *= s;
+        }
 
+        // F6.5.2
         let rxry = rx * ry;
         let rxpy = rx * p.y;
         let rypx = ry * p.x;
@@ -62,7 +74,7 @@ impl Arc {
         debug_assert_ne!(sum_of_sq, 0.0);
 
         let sign_coe = if arc.flags.large_arc == arc.flags.sweep {-1.0 } else { 1.0 };
-        let coe = sign_coe * ((rxry * rxry - sum_of_sq) / sum_of_sq).abs().sqrt();
+        let coe = sign_coe * ((rxry * rxry - sum_of_sq) / sum_of_sq).max(0.0).sqrt();
 
         let transformed_cx = coe * rxpy / ry;
         let transformed_cy = -coe * rypx / rx;
@@ -73,39 +85,96 @@ impl Arc {
             sin_phi * transformed_cx + cos_phi * transformed_cy + hs_y
         );
 
+        // F6.5.5 and F6.5.6
         let a = vec2(
             (p.x - transformed_cx) / rx,
             (p.y - transformed_cy) / ry,
         );
-        // TODO
-        let b = -vec2(
+        let b = vec2(
             (-p.x - transformed_cx) / rx,
             (-p.y - transformed_cy) / ry,
         );
 
-        let start_angle = Radians::new(directed_angle(vec2(1.0, 0.0), a));
-
-        let sign_delta = if arc.flags.sweep { 1.0 } else { -1.0 };
-        let sweep_angle = Radians::new(sign_delta * (directed_angle(a, b).abs() % (2.0 * consts::PI)));
+        let start_angle = a.y.atan2(a.x);
+        let mut sweep_angle = (b.y.atan2(b.x) - start_angle) % (2.0 * consts::PI);
+        if arc.flags.sweep && sweep_angle < 0.0 {
+            sweep_angle += 2.0 * consts::PI;
+        } else if !arc.flags.sweep && sweep_angle > 0.0 {
+            sweep_angle -= 2.0 * consts::PI;
+        }
 
         Arc {
             center: center,
-            radii: arc.radii,
-            start_angle: start_angle,
-            sweep_angle: sweep_angle,
+            radii: vec2(rx, ry),
+            start_angle: Radians::new(start_angle),
+            sweep_angle: Radians::new(sweep_angle),
             x_rotation: arc.x_rotation
         }
     }
 
+    /// Approximates the arc with a sequence of quadratic bézier curves, calling the
+    /// callback with the control and end points of each curve.
     pub fn to_quadratic_beziers<F: FnMut(Point, Point)>(&self, cb: &mut F) {
         arc_to_to_quadratic_beziers(self, cb);
     }
 
+    /// Approximates the arc with a sequence of cubic bézier curves (one per quarter of
+    /// ellipse at most), calling the callback with the control and end points of each curve.
+    pub fn to_cubic_beziers<F: FnMut(Point, Point, Point)>(&self, cb: &mut F) {
+        let sweep_angle = self.sweep_angle.get().max(-2.0 * consts::PI).min(2.0 * consts::PI);
+        // The small epsilon avoids an extra curve when the sweep angle is a multiple of a
+        // quarter of ellipse.
+        let n_steps = (sweep_angle.abs() / consts::FRAC_PI_2 - 0.0001).ceil().max(1.0);
+        let step = sweep_angle / n_steps;
+        // Length of the tangents of the control points for a unit circle.
+        let k = 4.0 / 3.0 * (step / 4.0).tan();
+
+        let mut a1 = self.start_angle.get();
+        let mut p1 = self.point_at_angle(Radians::new(a1));
+        for _ in 0..(n_steps as i32) {
+            let a2 = a1 + step;
+            let p2 = self.point_at_angle(Radians::new(a2));
+            let ctrl1 = p1 + self.tangent_at_angle(Radians::new(a1)) * k;
+            let ctrl2 = p2 - self.tangent_at_angle(Radians::new(a2)) * k;
+
+            cb(ctrl1, ctrl2, p2);
+
+            a1 = a2;
+            p1 = p2;
+        }
+    }
+
+    /// Approximates the arc with a sequence of line segments, calling the callback with the
+    /// end point of each segment.
+    pub fn flattened_for_each<F: FnMut(Point)>(&self, tolerance: f32, cb: &mut F) {
+        let radius = self.radii.x.abs().max(self.radii.y.abs());
+        let sweep_angle = self.sweep_angle.get().abs().min(2.0 * consts::PI);
+        // The largest angle for which the distance between a chord and the arc stays
+        // under the tolerance.
+        let max_step = if tolerance < radius {
+            2.0 * (1.0 - tolerance / radius).acos()
+        } else {
+            consts::PI
+        };
+        let n_steps = (sweep_angle / max_step).ceil().max(1.0) as u32;
+
+        for i in 1..(n_steps + 1) {
+            cb(self.sample(i as f32 / n_steps as f32));
+        }
+    }
+
+    /// Sample the arc at a given parameter between 0 (start) and 1 (end).
     pub fn sample(&self, t: f32) -> Point {
-        let angle = Radians::new(self.sweep_angle.get() * t);
+        let angle = Radians::new(self.start_angle.get() + self.sweep_angle.get() * t);
+        self.point_at_angle(angle)
+    }
+
+    /// Returns the position on the ellipse at a given angle.
+    pub fn point_at_angle(&self, angle: Radians) -> Point {
         self.center + sample_ellipse(self.radii, self.x_rotation, angle).to_vector()
     }
 
+    /// Returns the derivative of the position on the ellipse with respect to the angle.
     pub fn tangent_at_angle(&self, angle: Radians) -> Vec2 {
         let a = angle.get();
         Transform2D::create_rotation(self.x_rotation).transform_vector(
@@ -115,8 +184,71 @@ impl Arc {
 }
 
 impl SvgArc {
+    /// Returns true if the arc is degenerate.
+    ///
+    /// As specified by SVG, arcs with a radius equal to zero are rendered as line
+    /// segments and arcs with identical endpoints are omitted.
+    pub fn is_straight_line(&self) -> bool {
+        self.from == self.to || self.radii.x == 0.0 || self.radii.y == 0.0
+    }
+
     pub fn to_quadratic_beziers<F: FnMut(Point, Point)>(&self, cb: &mut F) {
-        Arc::from_svg_arc(self).to_quadratic_beziers(cb);
+        if self.is_straight_line() {
+            cb(self.from, self.to);
+            return;
+        }
+        let to = self.to;
+        let mut last = None;
+        Arc::from_svg_arc(self).to_quadratic_beziers(&mut |ctrl, p| {
+            if let Some((prev_ctrl, prev)) = last {
+                cb(prev_ctrl, prev);
+            }
+            last = Some((ctrl, p));
+        });
+        // Make sure the last point matches the end point exactly.
+        if let Some((ctrl, _)) = last {
+            cb(ctrl, to);
+        }
+    }
+
+    /// Approximates the arc with a sequence of cubic bézier curves ending exactly at `to`.
+    ///
+    /// Degenerate arcs produce a single straight curve.
+    pub fn to_cubic_beziers<F: FnMut(Point, Point, Point)>(&self, cb: &mut F) {
+        if self.is_straight_line() {
+            cb(self.from, self.to, self.to);
+            return;
+        }
+        let to = self.to;
+        let mut last = None;
+        Arc::from_svg_arc(self).to_cubic_beziers(&mut |ctrl1, ctrl2, p| {
+            if let Some((prev_ctrl1, prev_ctrl2, prev)) = last {
+                cb(prev_ctrl1, prev_ctrl2, prev);
+            }
+            last = Some((ctrl1, ctrl2, p));
+        });
+        if let Some((ctrl1, ctrl2, _)) = last {
+            cb(ctrl1, ctrl2, to);
+        }
+    }
+
+    /// Approximates the arc with a sequence of line segments ending exactly at `to`.
+    pub fn flattened_for_each<F: FnMut(Point)>(&self, tolerance: f32, cb: &mut F) {
+        if self.is_straight_line() {
+            cb(self.to);
+            return;
+        }
+        let to = self.to;
+        let mut last = None;
+        Arc::from_svg_arc(self).flattened_for_each(tolerance, &mut |p| {
+            if let Some(prev) = last {
+                cb(prev);
+            }
+            last = Some(p);
+        });
+        if last.is_some() {
+            cb(to);
+        }
     }
 }
 
@@ -140,19 +272,17 @@ fn arc_to_to_quadratic_beziers<F: FnMut(Point, Point)>(
     arc: &Arc,
     call_back: &mut F,
 ) {
-    let sweep_angle = arc.sweep_angle.get().abs().min(consts::PI * 2.0);
+    let sweep_angle = arc.sweep_angle.get().max(-2.0 * consts::PI).min(2.0 * consts::PI);
 
-    let n_steps = (sweep_angle / consts::FRAC_PI_4).ceil();
+    let n_steps = (sweep_angle.abs() / consts::FRAC_PI_4).ceil().max(1.0);
     let step = sweep_angle / n_steps;
 
     for i in 0..(n_steps as i32) {
         let a1 = arc.start_angle.get() + step * (i as f32);
         let a2 = arc.start_angle.get() + step * ((i+1) as f32);
 
-        let v1 = sample_ellipse(arc.radii, arc.x_rotation, Radians::new(a1)).to_vector();
-        let v2 = sample_ellipse(arc.radii, arc.x_rotation, Radians::new(a2)).to_vector();
-        let p1 = arc.center + v1;
-        let p2 = arc.center + v2;
+        let p1 = arc.point_at_angle(Radians::new(a1));
+        let p2 = arc.point_at_angle(Radians::new(a2));
         let l1 = Line { point: p1, vector: arc.tangent_at_angle(Radians::new(a1)) };
         let l2 = Line { point: p2, vector: arc.tangent_at_angle(Radians::new(a2)) };
         let ctrl = l2.intersection(&l1).unwrap();
@@ -166,3 +296,75 @@ fn sample_ellipse(radii: Vec2, x_rotation: Radians, angle: Radians) -> Point {
         &point2(radii.x * angle.get().cos(), radii.y * angle.get().sin())
     )
 }
+
+#[cfg(test)]
+fn svg_arc(from: Point, to: Point, radii: Vec2, large_arc: bool, sweep: bool) -> SvgArc {
+    SvgArc {
+        from: from,
+        to: to,
+        radii: radii,
+        x_rotation: Radians::new(0.0),
+        flags: ArcFlags { large_arc: large_arc, sweep: sweep },
+    }
+}
+
+#[test]
+fn svg_arc_to_center_parameterization() {
+    // Half circles between (0, 0) and (2, 0).
+    let arc = Arc::from_svg_arc(&svg_arc(point2(0.0, 0.0), point2(2.0, 0.0), vec2(1.0, 1.0), false, true));
+    assert!((arc.center - point2(1.0, 0.0)).length() < 0.0001);
+    assert!((arc.sweep_angle.get() - consts::PI).abs() < 0.0001);
+    assert!((arc.sample(0.5) - point2(1.0, -1.0)).length() < 0.0001);
+
+    let arc = Arc::from_svg_arc(&svg_arc(point2(0.0, 0.0), point2(2.0, 0.0), vec2(1.0, 1.0), false, false));
+    assert!((arc.sweep_angle.get() + consts::PI).abs() < 0.0001);
+    assert!((arc.sample(0.5) - point2(1.0, 1.0)).length() < 0.0001);
+
+    // The radii are too small and must be scaled up.
+    let arc = Arc::from_svg_arc(&svg_arc(point2(0.0, 0.0), point2(4.0, 0.0), vec2(1.0, 1.0), false, true));
+    assert!((arc.radii - vec2(2.0, 2.0)).length() < 0.0001);
+    assert!((arc.center - point2(2.0, 0.0)).length() < 0.0001);
+
+    // Quarter of circle, small and large arcs.
+    let small = Arc::from_svg_arc(&svg_arc(point2(1.0, 0.0), point2(0.0, 1.0), vec2(1.0, 1.0), false, true));
+    assert!((small.center - point2(0.0, 0.0)).length() < 0.0001);
+    assert!((small.sweep_angle.get() - consts::FRAC_PI_2).abs() < 0.0001);
+    let large = Arc::from_svg_arc(&svg_arc(point2(1.0, 0.0), point2(0.0, 1.0), vec2(1.0, 1.0), true, true));
+    assert!((large.center - point2(1.0, 1.0)).length() < 0.0001);
+    assert!((large.sweep_angle.get() - 3.0 * consts::FRAC_PI_2).abs() < 0.0001);
+}
+
+#[test]
+fn svg_arc_approximations() {
+    let arc = svg_arc(point2(0.0, 0.0), point2(20.0, 0.0), vec2(10.0, 10.0), true, true);
+    let center = point2(10.0, 0.0);
+
+    let mut num_curves = 0;
+    let mut last = point2(0.0, 0.0);
+    arc.to_cubic_beziers(&mut |ctrl1, ctrl2, to| {
+        // The middle of each curve is close to the circle.
+        let mid = (ctrl1.to_vector() + ctrl2.to_vector()) * 0.375
+            + (last.to_vector() + to.to_vector()) * 0.125;
+        assert!(((mid.to_point() - center).length() - 10.0).abs() < 0.01);
+        num_curves += 1;
+        last = to;
+    });
+    assert_eq!(num_curves, 2);
+    assert_eq!(last, point2(20.0, 0.0));
+
+    let mut num_points = 0;
+    arc.flattened_for_each(0.1, &mut |p| {
+        let d = (p - center).length();
+        assert!(d < 10.0001 && d > 9.9);
+        num_points += 1;
+        last = p;
+    });
+    assert!(num_points > 2);
+    assert_eq!(last, point2(20.0, 0.0));
+
+    // Degenerate arcs.
+    let mut curves = Vec::new();
+    svg_arc(point2(0.0, 0.0), point2(1.0, 0.0), vec2(0.0, 1.0), false, false)
+        .to_cubic_beziers(&mut |c1, c2, to| { curves.push((c1, c2, to)); });
+    assert_eq!(curves, vec![(point2(0.0, 0.0), point2(1.0, 0.0), point2(1.0, 0.0))]);
+}
@@ -0,0 +1,160 @@
+use {QuadraticBezierSegment, CubicBezierSegment};
+#[cfg(test)]
+use Point;
+
+/// Maps distances along a curve to curve parameters and back.
+///
+/// The curve is sampled at evenly spaced parameters, finely enough for the chords between
+/// the samples to approximate the curve within the tolerance. Within a chord, distances are
+/// measured from its start, which is accurate since the chords are short compared to the
+/// curvature.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate lyon_bezier;
+/// # use lyon_bezier::{QuadraticBezierSegment, Point};
+/// # fn main() {
+/// let curve = QuadraticBezierSegment {
+///     from: Point::new(0.0, 0.0),
+///     ctrl: Point::new(10.0, 10.0),
+///     to: Point::new(20.0, 0.0),
+/// };
+/// let arc_length = curve.arc_length_parametrization(0.01);
+/// // The parameters of points spaced by 1.0 along the curve.
+/// let parameters = arc_length.even_parameters(1.0);
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ArcLengthParametrization {
+    // Quadratic curves are elevated, which preserves their parametrization.
+    curve: CubicBezierSegment,
+    // Distance along the curve at the parameter i / (lengths.len() - 1).
+    lengths: Vec<f32>,
+}
+
+impl ArcLengthParametrization {
+    pub fn for_quadratic(curve: &QuadraticBezierSegment, tolerance: f32) -> Self {
+        ArcLengthParametrization::for_cubic(&curve.to_cubic(), tolerance)
+    }
+
+    pub fn for_cubic(curve: &CubicBezierSegment, tolerance: f32) -> Self {
+        let mut flattened_count = 0;
+        curve.flattened_for_each(tolerance, &mut |_| { flattened_count += 1; });
+        // The flattening places its points where the curvature needs them rather than at
+        // evenly spaced parameters, so sample a bit more finely.
+        let count = ::std::cmp::max(flattened_count * 4, 8);
+
+        let mut lengths = Vec::with_capacity(count + 1);
+        let mut length = 0.0;
+        let mut prev = curve.from;
+        lengths.push(0.0);
+        for i in 1..(count + 1) {
+            let p = curve.sample(i as f32 / count as f32);
+            length += (p - prev).length();
+            lengths.push(length);
+            prev = p;
+        }
+
+        return ArcLengthParametrization {
+            curve: *curve,
+            lengths: lengths,
+        };
+    }
+
+    /// Length of the curve.
+    pub fn length(&self) -> f32 { *self.lengths.last().unwrap() }
+
+    /// The parameter at a given distance along the curve, clamped to the curve.
+    pub fn parameter_at_length(&self, length: f32) -> f32 {
+        let total = self.length();
+        if length <= 0.0 || total <= 0.0 {
+            return 0.0;
+        }
+        if length >= total {
+            return 1.0;
+        }
+
+        let idx = match self.lengths.binary_search_by(|l| l.partial_cmp(&length).unwrap()) {
+            Ok(idx) => { return self.parameter(idx); }
+            Err(idx) => idx - 1,
+        };
+
+        // Search the chord for the point at the remaining distance from its start.
+        let start = self.curve.sample(self.parameter(idx));
+        let remaining = length - self.lengths[idx];
+        let mut min = self.parameter(idx);
+        let mut max = self.parameter(idx + 1);
+        for _ in 0..16 {
+            let t = (min + max) * 0.5;
+            if (self.curve.sample(t) - start).length() < remaining {
+                min = t;
+            } else {
+                max = t;
+            }
+        }
+
+        return (min + max) * 0.5;
+    }
+
+    /// The distance along the curve at a given parameter.
+    pub fn length_at_parameter(&self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+        let pieces = self.lengths.len() - 1;
+        let idx = ::std::cmp::min((t * pieces as f32) as usize, pieces - 1);
+        let start = self.curve.sample(self.parameter(idx));
+
+        return self.lengths[idx] + (self.curve.sample(t) - start).length();
+    }
+
+    /// The parameters of points evenly spaced along the curve by a given distance, starting
+    /// at the beginning of the curve.
+    ///
+    /// The end of the curve is only included if the length of the curve is a multiple of
+    /// the spacing.
+    pub fn even_parameters(&self, spacing: f32) -> Vec<f32> {
+        assert!(spacing > 0.0);
+        let mut result = Vec::new();
+        let total = self.length();
+        let mut i = 0;
+        loop {
+            let length = spacing * i as f32;
+            if length > total {
+                break;
+            }
+            result.push(self.parameter_at_length(length));
+            i += 1;
+        }
+
+        return result;
+    }
+
+    fn parameter(&self, idx: usize) -> f32 {
+        idx as f32 / (self.lengths.len() - 1) as f32
+    }
+}
+
+#[test]
+fn arc_length_of_straight_curve() {
+    // A straight curve with unevenly spaced parameters.
+    let curve = CubicBezierSegment {
+        from: Point::new(0.0, 0.0),
+        ctrl1: Point::new(0.0, 0.0),
+        ctrl2: Point::new(0.0, 0.0),
+        to: Point::new(10.0, 0.0),
+    };
+    let arc_length = curve.arc_length_parametrization(0.01);
+
+    assert!((arc_length.length() - 10.0).abs() < 1e-3);
+    for &d in &[0.0, 1.0, 2.5, 5.0, 9.0, 10.0] {
+        let t = arc_length.parameter_at_length(d);
+        assert!((curve.sample(t).x - d).abs() < 0.05);
+        assert!((arc_length.length_at_parameter(t) - d).abs() < 1e-3);
+    }
+
+    // The length isn't exactly 10.0, so the end of the curve may not be included.
+    let parameters = arc_length.even_parameters(2.5);
+    assert!(parameters.len() == 4 || parameters.len() == 5);
+    assert!((curve.sample(parameters[2]).x - 5.0).abs() < 0.05);
+    assert_eq!(parameters[0], 0.0);
+}
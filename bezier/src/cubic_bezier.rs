@@ -1,12 +1,15 @@
 use {Point, Vec2, Rect, rect, Line, LineSegment, Transform2D};
 use up_to_two::UpToTwo;
 use arrayvec::ArrayVec;
+use arc_length::ArcLengthParametrization;
 use flatten_cubic::{flatten_cubic_bezier, find_cubic_bezier_inflection_points};
 pub use flatten_cubic::CubicFlatteningIter;
 pub use cubic_to_quadratic::cubic_to_quadratic;
 use monotone::{XMonotoneParametricCurve, solve_t_for_x};
 use utils::cubic_polynomial_roots;
 
+use std::ops::Range;
+
 /// A 2d curve segment defined by four points: the beginning of the segment, two control
 /// points and the end of the segment.
 ///
@@ -170,6 +173,50 @@ impl CubicBezierSegment {
         flatten_cubic_bezier(*self, tolerance, call_back);
     }
 
+    /// The parameter of the point of the curve that is the closest to a given point.
+    ///
+    /// The curve is sampled coarsely and the closest sample is refined with a ternary
+    /// search between its neighbours.
+    pub fn closest_point_t(&self, p: Point) -> f32 {
+        const SAMPLES: u32 = 16;
+        let mut best_t = 0.0;
+        let mut best_distance = ::std::f32::MAX;
+        for i in 0..(SAMPLES + 1) {
+            let t = i as f32 / SAMPLES as f32;
+            let d = (self.sample(t) - p).square_length();
+            if d < best_distance {
+                best_t = t;
+                best_distance = d;
+            }
+        }
+
+        let step = 1.0 / SAMPLES as f32;
+        let mut min = (best_t - step).max(0.0);
+        let mut max = (best_t + step).min(1.0);
+        for _ in 0..24 {
+            let t1 = min + (max - min) / 3.0;
+            let t2 = max - (max - min) / 3.0;
+            if (self.sample(t1) - p).square_length() < (self.sample(t2) - p).square_length() {
+                max = t2;
+            } else {
+                min = t1;
+            }
+        }
+
+        let t = (min + max) * 0.5;
+        // The search interval includes the coarse sample, which may be closer.
+        if (self.sample(t) - p).square_length() < best_distance {
+            return t;
+        }
+        return best_t;
+    }
+
+    /// Returns a mapping between distances along the curve and curve parameters, see
+    /// `ArcLengthParametrization`.
+    pub fn arc_length_parametrization(&self, tolerance: f32) -> ArcLengthParametrization {
+        ArcLengthParametrization::for_cubic(self, tolerance)
+    }
+
     /// Compute the length of the segment using a flattened approximation.
     pub fn compute_length(&self, tolerance: f32) -> f32 {
         let mut start = self.from;
@@ -197,6 +244,8 @@ impl CubicBezierSegment {
         let b = 6.0 * (self.ctrl2.x - 2.0 * self.ctrl1.x + self.from.x);
         let c = 3.0 * (self.ctrl1.x - self.from.x);
 
+        fn in_range(t: f32) -> bool { t > 0.0 && t < 1.0 }
+
         // If the derivative is a linear function
         if a == 0.0 {
             if b == 0.0 {
@@ -205,13 +254,14 @@ impl CubicBezierSegment {
                     ret.push(0.0);
                 }
             } else {
-                ret.push(-c / b);
+                let t = -c / b;
+                if in_range(t) {
+                    ret.push(t);
+                }
             }
             return ret;
         }
 
-        fn in_range(t: f32) -> bool { t > 0.0 && t < 1.0 }
-
         let discriminant = b * b - 4.0 * a * c;
 
         // There is no Real solution for the equation
@@ -369,7 +419,75 @@ impl CubicBezierSegment {
     }
 
     pub fn line_intersections(&self, line: &Line) -> ArrayVec<[Point; 3]> {
-        if line.vector.square_length() < 1e-6 {
+        let mut result = ArrayVec::new();
+        for t in self.line_intersection_roots(line) {
+            if t <= 0.0 || t >= 1.0 {
+                continue;
+            }
+
+            result.push(self.sample(t));
+        }
+
+        return result;
+    }
+
+    /// Computes the intersections with a line, as parameters along the curve.
+    ///
+    /// Unlike `line_intersections`, intersections at the endpoints of the curve are included.
+    pub fn line_intersections_t(&self, line: &Line) -> ArrayVec<[f32; 3]> {
+        let mut result = ArrayVec::new();
+        for t in self.line_intersection_roots(line) {
+            if t >= -PARAMETER_EPSILON && t <= 1.0 + PARAMETER_EPSILON {
+                result.push(t.max(0.0).min(1.0));
+            }
+        }
+
+        return result;
+    }
+
+    /// Computes the intersections with a line segment, as pairs of parameters along the
+    /// curve and along the segment.
+    pub fn line_segment_intersections_t(&self, segment: &LineSegment) -> ArrayVec<[(f32, f32); 3]> {
+        let mut result = ArrayVec::new();
+        let v = segment.to_vector();
+        let square_length = v.square_length();
+        if square_length < 1e-12 {
+            return result;
+        }
+
+        for t in self.line_intersections_t(&segment.to_line()) {
+            let s = (self.sample(t) - segment.from).dot(v) / square_length;
+            if s >= -PARAMETER_EPSILON && s <= 1.0 + PARAMETER_EPSILON {
+                result.push((t, s.max(0.0).min(1.0)));
+            }
+        }
+
+        return result;
+    }
+
+    /// Computes the intersections with another cubic bézier curve, as pairs of parameters
+    /// along both curves.
+    ///
+    /// The intersections are found by recursively subdividing the curves until the pieces
+    /// that overlap are smaller than the tolerance. Two cubic curves intersect at most nine
+    /// times unless they overlap, in which case up to nine points of the overlap are returned.
+    ///
+    /// Tolerances that are not positive (or NaN) are replaced with a minimal tolerance.
+    pub fn cubic_intersections_t(&self, other: &CubicBezierSegment, tolerance: f32) -> Vec<(f32, f32)> {
+        // f32::max returns the other operand for NaN.
+        let tolerance = tolerance.max(MIN_INTERSECTION_TOLERANCE);
+        let mut result = Vec::new();
+        cubic_intersections_rec(self, 0.0..1.0, other, 0.0..1.0, tolerance, 0, &mut result);
+        result.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        return result;
+    }
+
+    // Roots of the cubic equation of the intersections with a line.
+    fn line_intersection_roots(&self, line: &Line) -> ArrayVec<[f32; 3]> {
+        // The thresholds of the solver are relative to the coefficients, so short vectors
+        // work as long as they are not zero.
+        if line.vector.square_length() == 0.0 {
             return ArrayVec::new();
         }
 
@@ -385,27 +503,12 @@ impl CubicBezierSegment {
 
         let c = line.point.y * line.vector.x - line.point.x * line.vector.y;
 
-        let roots = cubic_polynomial_roots(
+        return cubic_polynomial_roots(
             line.vector.y * p1.x - line.vector.x * p1.y,
             line.vector.y * p2.x - line.vector.x * p2.y,
             line.vector.y * p3.x - line.vector.x * p3.y,
             line.vector.y * p4.x - line.vector.x * p4.y + c,
         );
-
-        let mut result = ArrayVec::new();
-
-        for root in roots {
-            let t = root;
-            if t <= 0.0 || t >= 1.0 {
-                continue;
-            }
-
-            let position = (p1 * t * t * t + p2 * t * t + p3 * t + p4).to_point();
-
-            result.push(position);
-        }
-
-        return result;
     }
 
     pub fn line_segment_intersections(&self, segment: &LineSegment) -> ArrayVec<[Point; 3]> {
@@ -488,6 +591,201 @@ impl YMonotoneCubicBezierSegment {
 // TODO: add this to euclid.
 fn yx(point: Point) -> Point { Point::new(point.y, point.x) }
 
+// Parameters slightly outside of [0, 1] because of floating point imprecision are
+// considered to be on the curve.
+const PARAMETER_EPSILON: f32 = 1e-5;
+
+const MAX_CURVE_INTERSECTIONS: usize = 9;
+const MAX_SUBDIVISION_DEPTH: u32 = 32;
+// Pieces can't be compared to a zero tolerance, since they are never smaller than it.
+const MIN_INTERSECTION_TOLERANCE: f32 = 1e-6;
+
+// The bounding box of the control points, which contains the curve.
+fn hull(curve: &CubicBezierSegment) -> (Point, Point) {
+    let min = Point::new(
+        curve.from.x.min(curve.ctrl1.x).min(curve.ctrl2.x).min(curve.to.x),
+        curve.from.y.min(curve.ctrl1.y).min(curve.ctrl2.y).min(curve.to.y),
+    );
+    let max = Point::new(
+        curve.from.x.max(curve.ctrl1.x).max(curve.ctrl2.x).max(curve.to.x),
+        curve.from.y.max(curve.ctrl1.y).max(curve.ctrl2.y).max(curve.to.y),
+    );
+    return (min, max);
+}
+
+fn cubic_intersections_rec(
+    a: &CubicBezierSegment,
+    ta: Range<f32>,
+    b: &CubicBezierSegment,
+    tb: Range<f32>,
+    tolerance: f32,
+    depth: u32,
+    result: &mut Vec<(f32, f32)>,
+) {
+    debug_assert!(tolerance > 0.0);
+    if result.len() >= MAX_CURVE_INTERSECTIONS {
+        return;
+    }
+
+    let (a_min, a_max) = hull(a);
+    let (b_min, b_max) = hull(b);
+    // Hulls that only touch overlap, so that intersections at the endpoints are found.
+    if a_min.x > b_max.x || b_min.x > a_max.x || a_min.y > b_max.y || b_min.y > a_max.y {
+        return;
+    }
+
+    let a_size = (a_max - a_min).length();
+    let b_size = (b_max - b_min).length();
+    if (a_size <= tolerance && b_size <= tolerance) || depth >= MAX_SUBDIVISION_DEPTH {
+        let t = ((ta.start + ta.end) * 0.5, (tb.start + tb.end) * 0.5);
+        // Neighbouring pieces around an intersection can both reach this point.
+        let (da, db) = ((ta.end - ta.start) * 2.0, (tb.end - tb.start) * 2.0);
+        if !result.iter().any(|r| (r.0 - t.0).abs() <= da && (r.1 - t.1).abs() <= db) {
+            result.push(t);
+        }
+        return;
+    }
+
+    let mid_a = (ta.start + ta.end) * 0.5;
+    let mid_b = (tb.start + tb.end) * 0.5;
+    if a_size > tolerance && b_size > tolerance {
+        let (a1, a2) = a.split(0.5);
+        let (b1, b2) = b.split(0.5);
+        cubic_intersections_rec(&a1, ta.start..mid_a, &b1, tb.start..mid_b, tolerance, depth + 1, result);
+        cubic_intersections_rec(&a1, ta.start..mid_a, &b2, mid_b..tb.end, tolerance, depth + 1, result);
+        cubic_intersections_rec(&a2, mid_a..ta.end, &b1, tb.start..mid_b, tolerance, depth + 1, result);
+        cubic_intersections_rec(&a2, mid_a..ta.end, &b2, mid_b..tb.end, tolerance, depth + 1, result);
+    } else if a_size > tolerance {
+        let (a1, a2) = a.split(0.5);
+        cubic_intersections_rec(&a1, ta.start..mid_a, b, tb.clone(), tolerance, depth + 1, result);
+        cubic_intersections_rec(&a2, mid_a..ta.end, b, tb, tolerance, depth + 1, result);
+    } else {
+        let (b1, b2) = b.split(0.5);
+        cubic_intersections_rec(a, ta.clone(), &b1, tb.start..mid_b, tolerance, depth + 1, result);
+        cubic_intersections_rec(a, ta, &b2, mid_b..tb.end, tolerance, depth + 1, result);
+    }
+}
+
+#[test]
+fn cubic_line_segment_intersections() {
+    // A symmetric arch crossed by a horizontal segment.
+    let curve = CubicBezierSegment {
+        from: Point::new(0.0, 0.0),
+        ctrl1: Point::new(0.0, 10.0),
+        ctrl2: Point::new(10.0, 10.0),
+        to: Point::new(10.0, 0.0),
+    };
+    let segment = LineSegment { from: Point::new(-5.0, 5.0), to: Point::new(15.0, 5.0) };
+
+    let intersections = curve.line_segment_intersections_t(&segment);
+    assert_eq!(intersections.len(), 2);
+    for &(t, s) in &intersections {
+        let p = curve.sample(t);
+        assert!((p.y - 5.0).abs() < 1e-4);
+        assert!((segment.sample(s) - p).length() < 1e-4);
+    }
+    assert!((intersections[0].0 + intersections[1].0 - 1.0).abs() < 1e-4);
+
+    // Intersections at the endpoints are included.
+    let base = LineSegment { from: Point::new(-5.0, 0.0), to: Point::new(15.0, 0.0) };
+    assert_eq!(curve.line_segment_intersections_t(&base).len(), 2);
+    assert_eq!(curve.line_segment_intersections(&base).len(), 0);
+}
+
+#[test]
+fn small_cubic_line_segment_intersections() {
+    // An S-shaped curve crossing the x axis at t = 0, 0.5 and 1, in units of about 1e-3.
+    // The cubic coefficient of the intersection equation is below 1e-6.
+    let curve = CubicBezierSegment {
+        from: Point::new(0.0, 0.0),
+        ctrl1: Point::new(0.0002, 0.001),
+        ctrl2: Point::new(0.0008, -0.001),
+        to: Point::new(0.001, 0.0),
+    };
+    let segment = LineSegment { from: Point::new(0.00045, 0.0), to: Point::new(0.00055, 0.0) };
+
+    let intersections = curve.line_segment_intersections_t(&segment);
+    assert_eq!(intersections.len(), 1);
+    assert!((intersections[0].0 - 0.5).abs() < 1e-3);
+    assert!((intersections[0].1 - 0.5).abs() < 1e-2);
+}
+
+#[test]
+fn near_quadratic_line_intersections() {
+    // The elevation of the quadratic curve (0, 0), (1, 2), (2, 0): the cubic coefficient
+    // of the intersection equation is zero up to rounding errors.
+    let curve = CubicBezierSegment {
+        from: Point::new(0.0, 0.0),
+        ctrl1: Point::new(2.0 / 3.0, 4.0 / 3.0),
+        ctrl2: Point::new(4.0 / 3.0, 4.0 / 3.0),
+        to: Point::new(2.0, 0.0),
+    };
+    let line = Line { point: Point::new(0.0, 0.75), vector: Vec2::new(1.0, 0.0) };
+
+    let mut intersections = curve.line_intersections_t(&line);
+    intersections.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(intersections.len(), 2);
+    assert!((intersections[0] - 0.25).abs() < 1e-4);
+    assert!((intersections[1] - 0.75).abs() < 1e-4);
+}
+
+#[test]
+fn cubic_closest_point() {
+    let curve = CubicBezierSegment {
+        from: Point::new(0.0, 0.0),
+        ctrl1: Point::new(0.0, 10.0),
+        ctrl2: Point::new(10.0, 10.0),
+        to: Point::new(10.0, 0.0),
+    };
+
+    // The top of the symmetric arch.
+    let t = curve.closest_point_t(Point::new(5.0, 20.0));
+    assert!((t - 0.5).abs() < 1e-3);
+    // Beyond the ends.
+    assert_eq!(curve.closest_point_t(Point::new(-5.0, -5.0)), 0.0);
+    assert_eq!(curve.closest_point_t(Point::new(15.0, -5.0)), 1.0);
+}
+
+#[test]
+fn cubic_cubic_intersections() {
+    let a = CubicBezierSegment {
+        from: Point::new(0.0, 0.0),
+        ctrl1: Point::new(0.0, 10.0),
+        ctrl2: Point::new(10.0, 10.0),
+        to: Point::new(10.0, 0.0),
+    };
+    let b = CubicBezierSegment {
+        from: Point::new(0.0, 10.0),
+        ctrl1: Point::new(0.0, 0.0),
+        ctrl2: Point::new(10.0, 0.0),
+        to: Point::new(10.0, 10.0),
+    };
+
+    let intersections = a.cubic_intersections_t(&b, 0.001);
+    assert_eq!(intersections.len(), 2);
+    for &(ta, tb) in &intersections {
+        assert!((a.sample(ta) - b.sample(tb)).length() < 0.01);
+        assert!((a.sample(ta).y - 5.0).abs() < 0.01);
+    }
+
+    let far = CubicBezierSegment {
+        from: Point::new(20.0, 0.0),
+        ctrl1: Point::new(20.0, 10.0),
+        ctrl2: Point::new(30.0, 10.0),
+        to: Point::new(30.0, 0.0),
+    };
+    assert!(a.cubic_intersections_t(&far, 0.001).is_empty());
+
+    // Invalid tolerances fall back to the minimal tolerance.
+    for &tolerance in &[0.0, -1.0, ::std::f32::NAN] {
+        let intersections = a.cubic_intersections_t(&b, tolerance);
+        assert!(!intersections.is_empty());
+        for &(ta, tb) in &intersections {
+            assert!((a.sample(ta) - b.sample(tb)).length() < 0.01);
+        }
+    }
+}
+
 #[test]
 fn bounding_rect_for_cubic_bezier_segment() {
     let a = CubicBezierSegment {
@@ -522,6 +820,23 @@ fn minimum_bounding_rect_for_cubic_bezier_segment() {
     assert!(actual_minimum_bounding_rect.contains_rect(&expected_smaller_bounding_rect));
 }
 
+#[test]
+fn minimum_bounding_rect_with_linear_derivative() {
+    // The x coordinate has a linear derivative which vanishes outside of the curve.
+    let a = CubicBezierSegment {
+        from: Point::new(0.0, 0.0),
+        ctrl1: Point::new(1.0, 2.0),
+        ctrl2: Point::new(3.0, 2.0),
+        to: Point::new(6.0, 0.0),
+    };
+
+    let r = a.minimum_bounding_rect();
+    assert_eq!(r.origin.x, 0.0);
+    assert_eq!(r.max_x(), 6.0);
+    assert_eq!(r.origin.y, 0.0);
+    assert_eq!(r.max_y(), 1.5);
+}
+
 #[test]
 fn find_y_maximum_for_simple_cubic_segment() {
     let a = CubicBezierSegment {
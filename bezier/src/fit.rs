@@ -0,0 +1,248 @@
+//! Fitting cubic bézier curves to sequences of points.
+//!
+//! This follows "An Algorithm for Automatically Fitting Digitized Curves" by Philip J.
+//! Schneider (Graphics Gems, 1990): a curve is fitted by least squares to the points using
+//! their chord-length parametrization, the parametrization is refined with Newton's method
+//! while the error is close to the tolerance, and the points are split in two at the point
+//! of maximum error otherwise.
+
+use {Point, Vec2, CubicBezierSegment};
+
+const MAX_REPARAMETRIZATIONS: u32 = 4;
+
+/// Approximates a sequence of points with a sequence of cubic bézier curves.
+///
+/// The curves pass through the first and last points and every point is within `tolerance`
+/// of the curves. The curves are tangent to each other where they meet, so sharp corners
+/// in the input are rounded within the tolerance.
+///
+/// This is typically used to make freehand strokes compact: a curve replaces the many line
+/// segments of smooth parts of the stroke.
+pub fn fit_cubic_beziers(points: &[Point], tolerance: f32) -> Vec<CubicBezierSegment> {
+    let mut deduplicated: Vec<Point> = Vec::with_capacity(points.len());
+    for &p in points {
+        if deduplicated.last() != Some(&p) {
+            deduplicated.push(p);
+        }
+    }
+
+    let mut result = Vec::new();
+    let n = deduplicated.len();
+    if n < 2 {
+        return result;
+    }
+
+    let tangent_start = (deduplicated[1] - deduplicated[0]).normalize();
+    let tangent_end = (deduplicated[n - 2] - deduplicated[n - 1]).normalize();
+    fit_rec(&deduplicated, tangent_start, tangent_end, tolerance * tolerance, &mut result);
+
+    return result;
+}
+
+fn fit_rec(
+    points: &[Point],
+    tangent_start: Vec2,
+    tangent_end: Vec2,
+    square_tolerance: f32,
+    output: &mut Vec<CubicBezierSegment>,
+) {
+    let n = points.len();
+    if n == 2 {
+        let d = (points[1] - points[0]).length() / 3.0;
+        output.push(CubicBezierSegment {
+            from: points[0],
+            ctrl1: points[0] + tangent_start * d,
+            ctrl2: points[1] + tangent_end * d,
+            to: points[1],
+        });
+        return;
+    }
+
+    let mut parameters = chord_length_parameters(points);
+    let mut curve = least_squares_curve(points, &parameters, tangent_start, tangent_end);
+    let (mut error, mut split) = max_error(points, &parameters, &curve);
+    if error < square_tolerance {
+        output.push(curve);
+        return;
+    }
+
+    // Close enough for a better parametrization to help.
+    if error < square_tolerance * 4.0 {
+        for _ in 0..MAX_REPARAMETRIZATIONS {
+            reparametrize(points, &mut parameters, &curve);
+            curve = least_squares_curve(points, &parameters, tangent_start, tangent_end);
+            let (e, s) = max_error(points, &parameters, &curve);
+            error = e;
+            split = s;
+            if error < square_tolerance {
+                output.push(curve);
+                return;
+            }
+        }
+    }
+
+    let tangent_center = center_tangent(points, split);
+    fit_rec(&points[..(split + 1)], tangent_start, tangent_center, square_tolerance, output);
+    fit_rec(&points[split..], -tangent_center, tangent_end, square_tolerance, output);
+}
+
+fn chord_length_parameters(points: &[Point]) -> Vec<f32> {
+    let mut parameters = Vec::with_capacity(points.len());
+    let mut length = 0.0;
+    parameters.push(0.0);
+    for i in 1..points.len() {
+        length += (points[i] - points[i - 1]).length();
+        parameters.push(length);
+    }
+    for u in &mut parameters {
+        *u /= length;
+    }
+
+    return parameters;
+}
+
+// Finds the lengths of the tangents that best fit the points by least squares.
+fn least_squares_curve(
+    points: &[Point],
+    parameters: &[f32],
+    tangent_start: Vec2,
+    tangent_end: Vec2,
+) -> CubicBezierSegment {
+    let first = points[0];
+    let last = points[points.len() - 1];
+
+    let mut c = [[0.0f32; 2]; 2];
+    let mut x = [0.0f32; 2];
+    for (&p, &u) in points.iter().zip(parameters.iter()) {
+        let (b0, b1, b2, b3) = bernstein(u);
+        let a0 = tangent_start * b1;
+        let a1 = tangent_end * b2;
+        c[0][0] += a0.dot(a0);
+        c[0][1] += a0.dot(a1);
+        c[1][1] += a1.dot(a1);
+
+        let tmp = p.to_vector()
+            - (first.to_vector() * (b0 + b1) + last.to_vector() * (b2 + b3));
+        x[0] += a0.dot(tmp);
+        x[1] += a1.dot(tmp);
+    }
+    c[1][0] = c[0][1];
+
+    let det_c = c[0][0] * c[1][1] - c[1][0] * c[0][1];
+    let det_c0_x = c[0][0] * x[1] - c[1][0] * x[0];
+    let det_x_c1 = x[0] * c[1][1] - x[1] * c[0][1];
+    let (mut alpha_start, mut alpha_end) = if det_c.abs() > 1e-12 {
+        (det_x_c1 / det_c, det_c0_x / det_c)
+    } else {
+        (0.0, 0.0)
+    };
+
+    // Degenerate or backwards tangents: fall back to a third of the chord.
+    let chord = (last - first).length();
+    let epsilon = 1e-6 * chord;
+    if alpha_start < epsilon || alpha_end < epsilon {
+        alpha_start = chord / 3.0;
+        alpha_end = chord / 3.0;
+    }
+
+    return CubicBezierSegment {
+        from: first,
+        ctrl1: first + tangent_start * alpha_start,
+        ctrl2: last + tangent_end * alpha_end,
+        to: last,
+    };
+}
+
+// The largest square distance between the points and the curve, and where it occurs.
+fn max_error(points: &[Point], parameters: &[f32], curve: &CubicBezierSegment) -> (f32, usize) {
+    let n = points.len();
+    let mut max = 0.0;
+    let mut split = n / 2;
+    for i in 1..(n - 1) {
+        let d = (curve.sample(parameters[i]) - points[i]).square_length();
+        if d >= max {
+            max = d;
+            split = i;
+        }
+    }
+
+    return (max, split);
+}
+
+// One iteration of Newton's method on the distance between each point and the curve.
+fn reparametrize(points: &[Point], parameters: &mut [f32], curve: &CubicBezierSegment) {
+    for (&p, u) in points.iter().zip(parameters.iter_mut()) {
+        let d = curve.sample(*u) - p;
+        let d1 = curve.sample_derivative(*u);
+        let d2 = second_derivative(curve, *u);
+        let denominator = d1.dot(d1) + d.dot(d2);
+        if denominator.abs() > 1e-12 {
+            *u = (*u - d.dot(d1) / denominator).max(0.0).min(1.0);
+        }
+    }
+}
+
+fn second_derivative(curve: &CubicBezierSegment, t: f32) -> Vec2 {
+    let a = curve.ctrl2.to_vector() - curve.ctrl1.to_vector() * 2.0 + curve.from.to_vector();
+    let b = curve.to.to_vector() - curve.ctrl2.to_vector() * 2.0 + curve.ctrl1.to_vector();
+    return (a * (1.0 - t) + b * t) * 6.0;
+}
+
+fn center_tangent(points: &[Point], idx: usize) -> Vec2 {
+    let v = points[idx - 1] - points[idx + 1];
+    if v.square_length() > 0.0 {
+        return v.normalize();
+    }
+    // The points around the split are the same, use the perpendicular of the incoming edge.
+    let edge = points[idx] - points[idx - 1];
+    return Vec2::new(-edge.y, edge.x).normalize();
+}
+
+fn bernstein(t: f32) -> (f32, f32, f32, f32) {
+    let t2 = 1.0 - t;
+    return (t2 * t2 * t2, 3.0 * t * t2 * t2, 3.0 * t * t * t2, t * t * t);
+}
+
+#[test]
+fn fit_sampled_curve() {
+    let curve = CubicBezierSegment {
+        from: Point::new(0.0, 0.0),
+        ctrl1: Point::new(10.0, 20.0),
+        ctrl2: Point::new(30.0, 20.0),
+        to: Point::new(40.0, 0.0),
+    };
+    let points: Vec<Point> = (0..101).map(|i| curve.sample(i as f32 / 100.0)).collect();
+
+    let fitted = fit_cubic_beziers(&points, 0.1);
+    assert!(!fitted.is_empty());
+    assert!(fitted.len() <= 2);
+    assert_eq!(fitted[0].from, points[0]);
+    assert_eq!(fitted[fitted.len() - 1].to, points[100]);
+}
+
+#[test]
+fn fit_polyline_with_corner() {
+    let mut points = Vec::new();
+    for i in 0..11 {
+        points.push(Point::new(i as f32, 0.0));
+    }
+    for i in 1..11 {
+        points.push(Point::new(10.0, i as f32));
+    }
+
+    let tolerance = 0.5;
+    let fitted = fit_cubic_beziers(&points, tolerance);
+    assert!(fitted.len() >= 2);
+    for pair in fitted.windows(2) {
+        assert_eq!(pair[0].to, pair[1].from);
+    }
+    // Every point is close to the fitted curves.
+    for p in &points {
+        let closest = fitted.iter()
+            .map(|c| (c.sample(c.closest_point_t(*p)) - *p).length())
+            .fold(::std::f32::MAX, |a, b| a.min(b));
+        assert!(closest <= tolerance * 1.01);
+    }
+
+    assert!(fit_cubic_beziers(&[Point::new(1.0, 1.0)], 0.1).is_empty());
+}
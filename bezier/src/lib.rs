@@ -77,6 +77,8 @@ mod triangle;
 mod line;
 mod arc;
 mod monotone;
+mod arc_length;
+mod fit;
 pub mod utils;
 
 pub use cubic_to_quadratic::cubic_to_quadratic;
@@ -104,5 +106,7 @@ pub use cubic_bezier::{CubicBezierSegment, CubicFlatteningIter};
 pub use triangle::{Triangle};
 pub use line::{LineSegment, Line};
 pub use arc::{Arc, SvgArc, ArcFlags};
+pub use arc_length::ArcLengthParametrization;
+pub use fit::fit_cubic_beziers;
 
 use euclid::{vec2, point2, rect};
@@ -90,6 +90,17 @@ impl LineSegment {
         self.to_vector().length()
     }
 
+    /// The parameter of the point of the segment that is the closest to a given point.
+    pub fn closest_point_t(&self, p: Point) -> f32 {
+        let v = self.to_vector();
+        let square_length = v.square_length();
+        if square_length == 0.0 {
+            return 0.0;
+        }
+
+        return ((p - self.from).dot(v) / square_length).max(0.0).min(1.0);
+    }
+
     #[inline]
     #[must_use]
     pub fn translate(&mut self, by: Vec2) -> Self {
@@ -1,8 +1,10 @@
 use {CubicBezierSegment};
-use {Point, Vec2, Rect, rect, Triangle, Line, LineSegment, Transform2D};
+use {Point, Vec2, Rect, rect, vec2, Triangle, Line, LineSegment, Transform2D};
 use std::mem::swap;
 use monotone::{XMonotoneParametricCurve, solve_t_for_x};
 use arrayvec::ArrayVec;
+use arc_length::ArcLengthParametrization;
+use utils::quadratic_polynomial_roots;
 
 /// A 2d curve segment defined by three points: the beginning of the segment, a control
 /// point and the end of the segment.
@@ -243,6 +245,19 @@ impl QuadraticBezierSegment {
         QuadraticFlatteningIter::new(*self, tolerance)
     }
 
+    /// The parameter of the point of the curve that is the closest to a given point.
+    ///
+    /// See `CubicBezierSegment::closest_point_t`.
+    pub fn closest_point_t(&self, p: Point) -> f32 {
+        self.to_cubic().closest_point_t(p)
+    }
+
+    /// Returns a mapping between distances along the curve and curve parameters, see
+    /// `ArcLengthParametrization`.
+    pub fn arc_length_parametrization(&self, tolerance: f32) -> ArcLengthParametrization {
+        ArcLengthParametrization::for_quadratic(self, tolerance)
+    }
+
     /// Compute the length of the segment using a flattened approximation.
     pub fn compute_length(&self, tolerance: f32) -> f32 {
         let mut start = self.from;
@@ -311,6 +326,61 @@ impl QuadraticBezierSegment {
         return result;
     }
 
+    /// Computes the intersections with a line, as parameters along the curve.
+    ///
+    /// Unlike `line_intersections`, intersections at the endpoints of the curve are included.
+    pub fn line_intersections_t(&self, line: &Line) -> ArrayVec<[f32; 2]> {
+        let mut result = ArrayVec::new();
+        if line.vector.square_length() < 1e-6 {
+            return result;
+        }
+
+        // The signed distance to the line (scaled by the length of its vector) along the
+        // curve is a polynomial of degree 2 in t.
+        let normal = vec2(line.vector.y, -line.vector.x);
+        let from = self.from.to_vector();
+        let ctrl = self.ctrl.to_vector();
+        let to = self.to.to_vector();
+        let a = normal.dot(from - ctrl * 2.0 + to);
+        let b = normal.dot(ctrl - from) * 2.0;
+        let c = normal.dot(self.from - line.point);
+
+        for t in quadratic_polynomial_roots(a, b, c) {
+            if t >= -1e-5 && t <= 1.0 + 1e-5 {
+                result.push(t.max(0.0).min(1.0));
+            }
+        }
+
+        return result;
+    }
+
+    /// Computes the intersections with a line segment, as pairs of parameters along the
+    /// curve and along the segment.
+    pub fn line_segment_intersections_t(&self, segment: &LineSegment) -> ArrayVec<[(f32, f32); 2]> {
+        let mut result = ArrayVec::new();
+        for intersection in self.to_cubic().line_segment_intersections_t(segment) {
+            result.push(intersection);
+        }
+
+        return result;
+    }
+
+    /// Computes the intersections with another quadratic bézier curve, as pairs of parameters
+    /// along both curves.
+    ///
+    /// See `CubicBezierSegment::cubic_intersections_t`.
+    pub fn quadratic_intersections_t(&self, other: &QuadraticBezierSegment, tolerance: f32) -> Vec<(f32, f32)> {
+        self.to_cubic().cubic_intersections_t(&other.to_cubic(), tolerance)
+    }
+
+    /// Computes the intersections with a cubic bézier curve, as pairs of parameters along
+    /// this curve and along the cubic one.
+    ///
+    /// See `CubicBezierSegment::cubic_intersections_t`.
+    pub fn cubic_intersections_t(&self, other: &CubicBezierSegment, tolerance: f32) -> Vec<(f32, f32)> {
+        self.to_cubic().cubic_intersections_t(other, tolerance)
+    }
+
     pub fn line_segment_intersections(&self, segment: &LineSegment) -> ArrayVec<[Point; 2]> {
         // TODO: a specific quadratic bézier vs line intersection function
         // would allow for better performance.
@@ -624,3 +694,28 @@ fn monotone_solve_t_for_x() {
         assert!(x_diff.abs() <= tolerance);
     }
 }
+
+#[test]
+fn line_intersections_t() {
+    let curve = QuadraticBezierSegment {
+        from: Point::new(0.0, 0.0),
+        ctrl: Point::new(1.0, 2.0),
+        to: Point::new(2.0, 0.0),
+    };
+
+    // The curve peaks at y = 1 for t = 0.5 and crosses y = 0.75 at t = 0.25 and t = 0.75.
+    let line = Line { point: Point::new(0.0, 0.75), vector: Vec2::new(1.0, 0.0) };
+    let intersections = curve.line_intersections_t(&line);
+    assert_eq!(intersections.len(), 2);
+    assert!((intersections[0] - 0.25).abs() < 1e-5);
+    assert!((intersections[1] - 0.75).abs() < 1e-5);
+    assert_eq!(curve.line_intersections(&line).len(), 2);
+
+    let vertical = Line { point: Point::new(1.0, -5.0), vector: Vec2::new(0.0, 1.0) };
+    let intersections = curve.line_intersections_t(&vertical);
+    assert_eq!(intersections.len(), 1);
+    assert!((intersections[0] - 0.5).abs() < 1e-5);
+
+    let above = Line { point: Point::new(0.0, 2.0), vector: Vec2::new(1.0, 0.0) };
+    assert!(curve.line_intersections_t(&above).is_empty());
+}
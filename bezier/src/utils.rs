@@ -62,18 +62,47 @@ pub fn fast_atan2(y: f32, x: f32) -> f32 {
     return r;
 }
 
+/// The real roots of `a * x² + b * x + c`, falling back to the linear equation when `a`
+/// is close to zero.
+///
+/// The coefficients are compared to the largest of them, so that scaling the equation
+/// doesn't change its roots.
+pub fn quadratic_polynomial_roots(a: f32, b: f32, c: f32) -> ArrayVec<[f32; 2]> {
+    let mut result = ArrayVec::new();
+
+    let epsilon = 1e-6;
+    let scale = a.abs().max(b.abs()).max(c.abs());
+
+    if a.abs() <= epsilon * scale {
+        // linear equation
+        if b.abs() > epsilon * scale {
+            result.push(-c / b);
+        }
+        return result;
+    }
+
+    // The rounding error of the discriminant is relative to the magnitude of its terms.
+    let delta = b * b - 4.0 * a * c;
+    if delta.abs() <= epsilon * (b * b + (4.0 * a * c).abs()) {
+        result.push(-b / (2.0 * a));
+    } else if delta > 0.0 {
+        let sqrt_delta = delta.sqrt();
+        result.push((-b - sqrt_delta) / (2.0 * a));
+        result.push((-b + sqrt_delta) / (2.0 * a));
+    }
+
+    return result;
+}
+
+/// The real roots of `a * x³ + b * x² + c * x + d`, falling back to the quadratic equation
+/// when `a` is close to zero relative to the other coefficients.
 pub fn cubic_polynomial_roots(a: f32, b: f32, c: f32, d: f32) -> ArrayVec<[f32; 3]> {
     let mut result = ArrayVec::new();
 
-    if a.abs() < 1e-6 {
-        // quadratic equation
-        let delta = b * b - 4.0 * a * c;
-        if delta > 0.0 {
-            let sqrt_delta = delta.sqrt();
-            result.push((-b - sqrt_delta) / (2.0 * a));
-            result.push((-b + sqrt_delta) / (2.0 * a));
-        } else if delta.abs() < 1e-6 {
-            result.push(-b / (2.0 * a));
+    let scale = a.abs().max(b.abs()).max(c.abs()).max(d.abs());
+    if a.abs() <= 1e-6 * scale {
+        for root in quadratic_polynomial_roots(b, c, d) {
+            result.push(root);
         }
         return result;
     }
@@ -128,4 +157,30 @@ fn cubic_polynomial() {
     assert_approx_eq(cubic_polynomial_roots(2.0, -4.0, 2.0, 0.0), &[0.0, 1.0], 0.0000001);
     assert_approx_eq(cubic_polynomial_roots(-1.0, 1.0, -1.0, 1.0), &[1.0], 0.000001);
     assert_approx_eq(cubic_polynomial_roots(-2.0, 2.0, -1.0, 10.0), &[2.0], 0.00005);
+
+    // Degenerate cubic equations.
+    assert_approx_eq(cubic_polynomial_roots(0.0, 1.0, -3.0, 2.0), &[1.0, 2.0], 0.000001);
+    assert_approx_eq(cubic_polynomial_roots(1e-8, 1.0, -3.0, 2.0), &[1.0, 2.0], 0.000001);
+    assert_approx_eq(cubic_polynomial_roots(0.0, 0.0, 2.0, -1.0), &[0.5], 0.000001);
+    assert_approx_eq(cubic_polynomial_roots(0.0, 0.0, 0.0, 1.0), &[], 0.0);
+}
+
+#[test]
+fn quadratic_polynomial_scale() {
+    // Scaling the coefficients doesn't change the roots.
+    for &scale in &[1e-6, 1.0, 1e6] {
+        let roots = quadratic_polynomial_roots(scale, -3.0 * scale, 2.0 * scale);
+        assert_eq!(roots.len(), 2);
+        assert!((roots[0] - 1.0).abs() < 1e-5);
+        assert!((roots[1] - 2.0).abs() < 1e-5);
+
+        // A double root.
+        let roots = quadratic_polynomial_roots(scale, -2.0 * scale, scale);
+        assert_eq!(roots.len(), 1);
+        assert!((roots[0] - 1.0).abs() < 1e-5);
+
+        let roots = quadratic_polynomial_roots(0.0, scale, -0.5 * scale);
+        assert_eq!(&roots[..], &[0.5]);
+    }
+    assert!(quadratic_polynomial_roots(0.0, 0.0, 0.0).is_empty());
 }
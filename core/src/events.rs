@@ -1,4 +1,4 @@
-use math::{Point, Vec2, Radians};
+use math::{Point, Vec2, Radians, Transform2D};
 use super::ArcFlags;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -68,6 +68,24 @@ impl PathEvent {
             PathEvent::Close => None,
         };
     }
+
+    /// Applies a transform to the points of the event.
+    pub fn transform(self, mat: &Transform2D) -> Self {
+        return match self {
+            PathEvent::MoveTo(to) => PathEvent::MoveTo(mat.transform_point(&to)),
+            PathEvent::LineTo(to) => PathEvent::LineTo(mat.transform_point(&to)),
+            PathEvent::QuadraticTo(ctrl, to) => PathEvent::QuadraticTo(
+                mat.transform_point(&ctrl),
+                mat.transform_point(&to),
+            ),
+            PathEvent::CubicTo(ctrl1, ctrl2, to) => PathEvent::CubicTo(
+                mat.transform_point(&ctrl1),
+                mat.transform_point(&ctrl2),
+                mat.transform_point(&to),
+            ),
+            PathEvent::Close => PathEvent::Close,
+        };
+    }
 }
 
 impl FlattenedEvent {
@@ -86,4 +104,13 @@ impl FlattenedEvent {
             FlattenedEvent::Close => PathEvent::Close,
         };
     }
+
+    /// Applies a transform to the points of the event.
+    pub fn transform(self, mat: &Transform2D) -> Self {
+        return match self {
+            FlattenedEvent::MoveTo(to) => FlattenedEvent::MoveTo(mat.transform_point(&to)),
+            FlattenedEvent::LineTo(to) => FlattenedEvent::LineTo(mat.transform_point(&to)),
+            FlattenedEvent::Close => FlattenedEvent::Close,
+        };
+    }
 }
@@ -25,3 +25,12 @@ impl Default for ArcFlags {
         }
     }
 }
+
+/// The fill rule defines how to determine what is inside and what is outside of the shape.
+///
+/// See the SVG specification.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FillRule {
+    EvenOdd,
+    NonZero,
+}
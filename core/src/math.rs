@@ -2,6 +2,7 @@ use euclid;
 use fixed;
 
 pub use euclid::{Point2D, Vector2D, TypedPoint2D, Radians};
+pub use euclid::{TypedVector2D, TypedSize2D, TypedRect, ScaleFactor};
 
 pub type Point = euclid::Point2D<f32>;
 pub type IntPoint = euclid::Point2D<i32>;
@@ -164,8 +164,12 @@ impl PathState {
 
     pub fn from_relative(&self, v: Vec2) -> Point { self.current + v }
 
-    pub fn svg_to_path_event(&self, event: SvgEvent) -> PathEvent {
-        match event {
+    /// Resolves the relative and shorthand SVG events with the current state.
+    ///
+    /// Arcs can't be represented with a single path event and return None. They are
+    /// approximated with several curves by `svg_to_path_events` in lyon_path_iterator.
+    pub fn svg_to_path_event(&self, event: SvgEvent) -> Option<PathEvent> {
+        let evt = match event {
             SvgEvent::MoveTo(to) => PathEvent::MoveTo(to),
             SvgEvent::LineTo(to) => PathEvent::LineTo(to),
             SvgEvent::QuadraticTo(ctrl, to) => PathEvent::QuadraticTo(ctrl, to),
@@ -209,8 +213,11 @@ impl PathState {
                     self.from_relative(to),
                 )
             }
-            // TODO arcs
-            _ => unimplemented!(),
-        }
+            SvgEvent::ArcTo(..) | SvgEvent::RelativeArcTo(..) => {
+                return None;
+            }
+        };
+
+        return Some(evt);
     }
 }
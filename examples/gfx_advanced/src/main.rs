@@ -21,7 +21,7 @@ use lyon_renderer::renderer::{
     GpuTransform, GpuFillVertex, GpuStrokeVertex, GpuFillPrimitive,
     GpuStrokePrimitive, opaque_fill_pipeline,
     opaque_stroke_pipeline, GpuGeometry,
-    GpuBufferStore, Globals, WithId
+    GpuBufferStore, Globals, GpuStyle, GpuGradientStopWords, GpuClip, WithId
 };
 // make  public so that the module in gfx_defines can see the types.
 pub use lyon_renderer::gfx_types::*;
@@ -248,6 +248,10 @@ fn main() {
         gfx_window_glutin::init::<ColorFormat, DepthFormat>(glutin_builder);
 
     let constants = factory.create_constant_buffer(1);
+    // The shared styles of Pattern::Shared and the clips of GpuFillPrimitive::set_clip, unused here.
+    let styles = factory.create_constant_buffer::<GpuStyle>(PRIM_BUFFER_LEN);
+    let gradient_stops = factory.create_constant_buffer::<GpuGradientStopWords>(GRADIENT_STOP_BUFFER_LEN);
+    let clips = factory.create_constant_buffer::<GpuClip>(PRIM_BUFFER_LEN);
 
     let mut gpu = Gpu {
         //fills: GpuGeometry::new(),
@@ -275,13 +279,13 @@ fn main() {
     );
 
     let fill_shader = factory.link_program(
-        FILL_VERTEX_SHADER.as_bytes(),
-        FILL_FRAGMENT_SHADER.as_bytes()
+        shader_source(FILL_VERTEX_SHADER).as_bytes(),
+        shader_source(FILL_FRAGMENT_SHADER).as_bytes()
     ).unwrap();
 
     let stroke_shader = factory.link_program(
-        STROKE_VERTEX_SHADER.as_bytes(),
-        STROKE_FRAGMENT_SHADER.as_bytes()
+        shader_source(STROKE_VERTEX_SHADER).as_bytes(),
+        shader_source(STROKE_FRAGMENT_SHADER).as_bytes()
     ).unwrap();
 
     let opaque_fill_pso = factory.create_pipeline_from_program(
@@ -419,6 +423,9 @@ fn main() {
                     primitives: gpu.fill_primitives[point_ids_1.buffer].clone(),
                     transforms: gpu.transforms[point_transforms.buffer].clone(),
                     constants: constants.clone(),
+                    styles: styles.clone(),
+                    gradient_stops: gradient_stops.clone(),
+                    clips: clips.clone(),
                     out_color: main_fbo.clone(),
                     out_depth: main_depth.clone(),
                 },
@@ -431,6 +438,9 @@ fn main() {
                     primitives: gpu.fill_primitives[point_ids_2.buffer].clone(),
                     transforms: gpu.transforms[point_transforms.buffer].clone(),
                     constants: constants.clone(),
+                    styles: styles.clone(),
+                    gradient_stops: gradient_stops.clone(),
+                    clips: clips.clone(),
                     out_color: main_fbo.clone(),
                     out_depth: main_depth.clone(),
                 },
@@ -451,6 +461,9 @@ fn main() {
                 primitives: gpu.fill_primitives[logo_fill_ids.buffer].clone(),
                 transforms: gpu.transforms[logo_transforms.buffer].clone(),
                 constants: constants.clone(),
+                styles: styles.clone(),
+                gradient_stops: gradient_stops.clone(),
+                clips: clips.clone(),
                 out_color: main_fbo.clone(),
                 out_depth: main_depth.clone(),
             },
@@ -464,6 +477,8 @@ fn main() {
                 primitives: gpu.stroke_primitives[logo_stroke_id.buffer].clone(),
                 transforms: gpu.transforms[logo_transforms.buffer].clone(),
                 constants: constants.clone(),
+                styles: styles.clone(),
+                gradient_stops: gradient_stops.clone(),
                 out_color: main_fbo.clone(),
                 out_depth: main_depth.clone(),
             },
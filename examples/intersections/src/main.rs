@@ -20,7 +20,8 @@ use lyon_renderer::renderer::{
     GpuTransform, GpuFillVertex, GpuStrokeVertex, GpuFillPrimitive,
     GpuStrokePrimitive, opaque_fill_pipeline,
     opaque_stroke_pipeline, GpuGeometry,
-    GpuBufferStore, Globals, WithId
+    GpuBufferStore, Globals, GpuStyle, GpuGradientStopWords, GpuClip, WithId,
+    StrokeBuffersBuilder,
 };
 use lyon::bezier::{CubicBezierSegment, Line};
 // make  public so that the module in gfx_defines can see the types.
@@ -153,12 +154,12 @@ fn main() {
     StrokeTessellator::new().tessellate_flattened_path(
         bezier_path.path_iter().flattened(0.01),
         &StrokeOptions::default().dont_apply_line_width(),
-        &mut BuffersBuilder::new(&mut cpu.strokes, WithId(bezier_id.element)),
+        &mut StrokeBuffersBuilder::new(&mut cpu.strokes, bezier_id.element),
     );
     StrokeTessellator::new().tessellate_flattened_path(
         line_path.path_iter().flattened(0.01),
         &StrokeOptions::default().dont_apply_line_width(),
-        &mut BuffersBuilder::new(&mut cpu.strokes, WithId(line_id.element)),
+        &mut StrokeBuffersBuilder::new(&mut cpu.strokes, line_id.element),
     );
 
     let num_points = intersections.len() as u16;
@@ -227,6 +228,10 @@ fn main() {
         gfx_window_glutin::init::<ColorFormat, DepthFormat>(glutin_builder);
 
     let constants = factory.create_constant_buffer(1);
+    // The shared styles of Pattern::Shared and the clips of GpuFillPrimitive::set_clip, unused here.
+    let styles = factory.create_constant_buffer::<GpuStyle>(PRIM_BUFFER_LEN);
+    let gradient_stops = factory.create_constant_buffer::<GpuGradientStopWords>(GRADIENT_STOP_BUFFER_LEN);
+    let clips = factory.create_constant_buffer::<GpuClip>(PRIM_BUFFER_LEN);
 
     let mut gpu = Gpu {
         //fills: GpuGeometry::new(),
@@ -254,13 +259,13 @@ fn main() {
     );
 
     let fill_shader = factory.link_program(
-        FILL_VERTEX_SHADER.as_bytes(),
-        FILL_FRAGMENT_SHADER.as_bytes()
+        shader_source(FILL_VERTEX_SHADER).as_bytes(),
+        shader_source(FILL_FRAGMENT_SHADER).as_bytes()
     ).unwrap();
 
     let stroke_shader = factory.link_program(
-        STROKE_VERTEX_SHADER.as_bytes(),
-        STROKE_FRAGMENT_SHADER.as_bytes()
+        shader_source(STROKE_VERTEX_SHADER).as_bytes(),
+        shader_source(STROKE_FRAGMENT_SHADER).as_bytes()
     ).unwrap();
 
     let opaque_fill_pso = factory.create_pipeline_from_program(
@@ -373,6 +378,9 @@ fn main() {
                 primitives: gpu.fill_primitives[point_ids_1.buffer].clone(),
                 transforms: gpu.transforms[point_transforms.buffer].clone(),
                 constants: constants.clone(),
+                styles: styles.clone(),
+                gradient_stops: gradient_stops.clone(),
+                clips: clips.clone(),
                 out_color: main_fbo.clone(),
                 out_depth: main_depth.clone(),
             },
@@ -385,6 +393,9 @@ fn main() {
                 primitives: gpu.fill_primitives[point_ids_2.buffer].clone(),
                 transforms: gpu.transforms[point_transforms.buffer].clone(),
                 constants: constants.clone(),
+                styles: styles.clone(),
+                gradient_stops: gradient_stops.clone(),
+                clips: clips.clone(),
                 out_color: main_fbo.clone(),
                 out_depth: main_depth.clone(),
             },
@@ -400,6 +411,8 @@ fn main() {
                 primitives: gpu.stroke_primitives[stroke_ids.buffer].clone(),
                 transforms: gpu.transforms[default_transform.buffer].clone(),
                 constants: constants.clone(),
+                styles: styles.clone(),
+                gradient_stops: gradient_stops.clone(),
                 out_color: main_fbo.clone(),
                 out_depth: main_depth.clone(),
             },
@@ -0,0 +1,277 @@
+#[macro_use]
+extern crate gfx;
+extern crate gfx_window_glutin;
+extern crate gfx_device_gl;
+extern crate glutin;
+extern crate lyon;
+
+use lyon::svg::parser::build_path;
+use lyon::path_iterator::{PathIterator, FromPolyline};
+use lyon::math::*;
+use lyon::events::FlattenedEvent;
+use lyon::tessellation::geometry_builder::{VertexConstructor, VertexBuffers, BuffersBuilder};
+use lyon::tessellation::{FillTessellator, FillOptions};
+use lyon::tessellation;
+use lyon::path::Path;
+
+use gfx::traits::{Device, FactoryExt};
+
+use std::time::Instant;
+
+type ColorFormat = gfx::format::Rgba8;
+type DepthFormat = gfx::format::DepthStencil;
+
+gfx_defines!{
+    vertex GpuVertex {
+        position: [f32; 2] = "a_position",
+    }
+
+    constant Globals {
+        color: [f32; 4] = "u_color",
+    }
+
+    pipeline morph_pipeline {
+        vbo: gfx::VertexBuffer<GpuVertex> = (),
+        globals: gfx::ConstantBuffer<Globals> = "Globals",
+        out_color: gfx::RenderTarget<ColorFormat> = "out_color",
+    }
+}
+
+// Two icons in a 100x100 viewport, as they would appear in the "d" attribute of
+// svg path elements.
+const STAR: &'static str = "M 50,5 L 61,39 L 97,39 L 68,60 L 79,95 L 50,74 L 21,95 L 32,60 L 3,39 L 39,39 Z";
+const HEART: &'static str = "M 50,30 C 50,20 35,5 20,15 C 5,25 10,45 20,55 L 50,85 L 80,55 \
+                             C 90,45 95,25 80,15 C 65,5 50,20 50,30 Z";
+
+const STAR_COLOR: [f32; 4] = [0.9, 0.7, 0.1, 1.0];
+const HEART_COLOR: [f32; 4] = [0.8, 0.1, 0.2, 1.0];
+
+// Both outlines are resampled with this many points so that they can be interpolated.
+const NUM_POINTS: usize = 256;
+// Generous upper bounds for the size of the tessellation of a polygon with NUM_POINTS points.
+const MAX_VERTICES: usize = NUM_POINTS * 4;
+const MAX_INDICES: usize = NUM_POINTS * 12;
+
+struct VertexCtor;
+impl VertexConstructor<tessellation::FillVertex, GpuVertex> for VertexCtor {
+    fn new_vertex(&mut self, vertex: tessellation::FillVertex) -> GpuVertex {
+        GpuVertex {
+            // Map the 100x100 viewport to clip space.
+            position: (vertex.position * 0.018 - vec2(0.9, 0.9)).to_array(),
+        }
+    }
+}
+
+fn main() {
+    let star = build_path(Path::builder().with_svg(), STAR).unwrap();
+    let heart = build_path(Path::builder().with_svg(), HEART).unwrap();
+
+    let star_points = resample(&star, 0.05, NUM_POINTS);
+    let heart_points = resample(&heart, 0.05, NUM_POINTS);
+
+    let glutin_builder = glutin::WindowBuilder::new()
+        .with_dimensions(700, 700)
+        .with_decorations(true)
+        .with_title("Morphing".to_string())
+        .with_vsync();
+
+    let (window, mut device, mut factory, mut main_fbo, mut main_depth) =
+        gfx_window_glutin::init::<ColorFormat, DepthFormat>(glutin_builder);
+
+    let shader = factory.link_program(
+        VERTEX_SHADER.as_bytes(),
+        FRAGMENT_SHADER.as_bytes()
+    ).unwrap();
+
+    let pso = factory.create_pipeline_from_program(
+        &shader,
+        gfx::Primitive::TriangleList,
+        gfx::state::Rasterizer::new_fill(),
+        morph_pipeline::new(),
+    ).unwrap();
+
+    // The geometry changes every frame, so the buffers are allocated once and only
+    // the part that is used is updated.
+    let vbo = factory.create_buffer(
+        MAX_VERTICES,
+        gfx::buffer::Role::Vertex,
+        gfx::memory::Usage::Dynamic,
+        gfx::memory::Bind::empty(),
+    ).unwrap();
+    let ibo = factory.create_buffer(
+        MAX_INDICES,
+        gfx::buffer::Role::Index,
+        gfx::memory::Usage::Dynamic,
+        gfx::memory::Bind::empty(),
+    ).unwrap();
+    let globals = factory.create_constant_buffer(1);
+
+    let mut cmd_queue: gfx::Encoder<_, _> = factory.create_command_buffer().into();
+
+    let mut tessellator = FillTessellator::new();
+    let mut mesh: VertexBuffers<GpuVertex> = VertexBuffers::with_capacity(MAX_VERTICES, MAX_INDICES);
+    let mut points = Vec::with_capacity(NUM_POINTS);
+
+    let start_time = Instant::now();
+
+    loop {
+        if !update_inputs(&window) {
+            break;
+        }
+
+        let elapsed = start_time.elapsed();
+        let time = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 * 1e-9;
+        let t = smoothstep((time * 1.5).sin() * 0.5 + 0.5);
+
+        points.clear();
+        for (a, b) in star_points.iter().zip(heart_points.iter()) {
+            points.push(a.lerp(*b, t));
+        }
+
+        mesh.vertices.clear();
+        mesh.indices.clear();
+        let tessellated = tessellator.tessellate_flattened_path(
+            FromPolyline::closed(points.iter().cloned()),
+            &FillOptions::tolerance(0.05),
+            &mut BuffersBuilder::new(&mut mesh, VertexCtor),
+        ).is_ok();
+
+        gfx_window_glutin::update_views(&window, &mut main_fbo, &mut main_depth);
+
+        cmd_queue.clear(&main_fbo.clone(), [0.95, 0.95, 0.95, 1.0]);
+
+        if tessellated && mesh.vertices.len() <= MAX_VERTICES && mesh.indices.len() <= MAX_INDICES {
+            cmd_queue.update_buffer(&vbo, &mesh.vertices[..], 0).unwrap();
+            cmd_queue.update_buffer(&ibo, &mesh.indices[..], 0).unwrap();
+            cmd_queue.update_constant_buffer(&globals, &Globals {
+                color: lerp_color(STAR_COLOR, HEART_COLOR, t),
+            });
+
+            let slice = gfx::Slice {
+                start: 0,
+                end: mesh.indices.len() as u32,
+                base_vertex: 0,
+                instances: None,
+                buffer: gfx::IndexBuffer::Index16(ibo.clone()),
+            };
+
+            cmd_queue.draw(
+                &slice,
+                &pso,
+                &morph_pipeline::Data {
+                    vbo: vbo.clone(),
+                    globals: globals.clone(),
+                    out_color: main_fbo.clone(),
+                },
+            );
+        }
+
+        cmd_queue.flush(&mut device);
+
+        window.swap_buffers().unwrap();
+
+        device.cleanup();
+    }
+}
+
+/// Approximates the first sub-path of a path with `count` points evenly spaced along
+/// its outline.
+fn resample(path: &Path, tolerance: f32, count: usize) -> Vec<Point> {
+    let mut polygon = Vec::new();
+    for evt in path.path_iter().flattened(tolerance) {
+        match evt {
+            FlattenedEvent::MoveTo(to) => {
+                if !polygon.is_empty() {
+                    break;
+                }
+                polygon.push(to);
+            }
+            FlattenedEvent::LineTo(to) => {
+                polygon.push(to);
+            }
+            FlattenedEvent::Close => {
+                break;
+            }
+        }
+    }
+    let first = polygon[0];
+    polygon.push(first);
+
+    let mut lengths = vec![0.0];
+    for i in 1..polygon.len() {
+        let length = lengths[i - 1] + (polygon[i] - polygon[i - 1]).length();
+        lengths.push(length);
+    }
+    let total_length = lengths[lengths.len() - 1];
+
+    let mut result = Vec::with_capacity(count);
+    let mut segment = 1;
+    for i in 0..count {
+        let d = total_length * i as f32 / count as f32;
+        while segment < polygon.len() - 1 && lengths[segment] < d {
+            segment += 1;
+        }
+        let segment_length = lengths[segment] - lengths[segment - 1];
+        let t = if segment_length > 0.0 { (d - lengths[segment - 1]) / segment_length } else { 0.0 };
+        result.push(polygon[segment - 1].lerp(polygon[segment], t));
+    }
+
+    return result;
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp_color(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+        a[3] + (b[3] - a[3]) * t,
+    ]
+}
+
+fn update_inputs(window: &glutin::Window) -> bool {
+    for event in window.poll_events() {
+        use glutin::Event::KeyboardInput;
+        use glutin::ElementState::Pressed;
+        use glutin::VirtualKeyCode;
+        match event {
+            glutin::Event::Closed => {
+                return false;
+            }
+            KeyboardInput(Pressed, _, Some(VirtualKeyCode::Escape)) => {
+                return false;
+            }
+            _evt => {}
+        };
+    }
+
+    return true;
+}
+
+pub static VERTEX_SHADER: &'static str = &"
+    #version 140
+
+    in vec2 a_position;
+
+    void main() {
+        gl_Position = vec4(a_position, 0.0, 1.0);
+        gl_Position.y *= -1.0;
+    }
+";
+
+pub static FRAGMENT_SHADER: &'static str = &"
+    #version 140
+
+    uniform Globals {
+        vec4 u_color;
+    };
+
+    out vec4 out_color;
+
+    void main() {
+        out_color = u_color;
+    }
+";
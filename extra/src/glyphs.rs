@@ -0,0 +1,246 @@
+//! Conversion of font glyph outlines into paths, using the
+//! [ttf-parser](https://crates.io/crates/ttf-parser) crate.
+//!
+//! Requires the `ttf-parser` feature.
+//!
+//! Font outlines are expressed in font units with the y axis pointing up. The paths produced
+//! here are scaled to the requested font size and use lyon's convention of the y axis pointing
+//! down, with the origin on the baseline.
+
+use ttf_parser::{self, Face, GlyphId, OutlineBuilder};
+
+use core::math::*;
+use path::Path;
+use path_builder::*;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Forwards glyph outlines to a path builder, applying a transform to the points.
+pub struct GlyphOutlineBuilder<'l, Builder: 'l> {
+    builder: &'l mut Builder,
+    transform: Transform2D,
+    // ttf-parser doesn't always close the last contour explicitly.
+    open: bool,
+}
+
+impl<'l, Builder: PathBuilder> GlyphOutlineBuilder<'l, Builder> {
+    /// Creates an outline builder transforming points with the provided transform.
+    pub fn new(builder: &'l mut Builder, transform: Transform2D) -> Self {
+        GlyphOutlineBuilder {
+            builder: builder,
+            transform: transform,
+            open: false,
+        }
+    }
+
+    /// Creates an outline builder that scales font units to the requested size and places
+    /// the origin of the glyph at the provided position.
+    pub fn with_size(builder: &'l mut Builder, units_per_em: f32, size: f32, origin: Point) -> Self {
+        let scale = size / units_per_em;
+        GlyphOutlineBuilder::new(
+            builder,
+            Transform2D::create_scale(scale, -scale).post_translate(origin.to_vector()),
+        )
+    }
+
+    /// Closes the current contour if the outline left it open.
+    pub fn finish(&mut self) {
+        if self.open {
+            self.builder.close();
+            self.open = false;
+        }
+    }
+
+    fn point(&self, x: f32, y: f32) -> Point {
+        self.transform.transform_point(&point(x, y))
+    }
+}
+
+impl<'l, Builder: PathBuilder> OutlineBuilder for GlyphOutlineBuilder<'l, Builder> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.finish();
+        let to = self.point(x, y);
+        self.builder.move_to(to);
+        self.open = true;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let to = self.point(x, y);
+        self.builder.line_to(to);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let ctrl = self.point(x1, y1);
+        let to = self.point(x, y);
+        self.builder.quadratic_bezier_to(ctrl, to);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let ctrl1 = self.point(x1, y1);
+        let ctrl2 = self.point(x2, y2);
+        let to = self.point(x, y);
+        self.builder.cubic_bezier_to(ctrl1, ctrl2, to);
+    }
+
+    fn close(&mut self) {
+        if self.open {
+            self.builder.close();
+            self.open = false;
+        }
+    }
+}
+
+/// A glyph laid out by `GlyphShapeProvider::layout`.
+#[derive(Clone, Debug)]
+pub struct PositionedGlyph {
+    /// The outline of the glyph, for a font size of 1.0 and the origin on the baseline.
+    pub path: Arc<Path>,
+    /// Position of the glyph origin.
+    pub position: Point,
+    /// The font size, which is the scale to apply to the path.
+    pub size: f32,
+}
+
+/// Provides the outlines of the glyphs of a font as paths.
+///
+/// Glyph paths are normalized to a font size of 1.0 and cached, so that they can be shared
+/// between all the instances of the same glyph and placed with a transform. The paths are
+/// reference counted to be directly usable with the renderer's shape store.
+pub struct GlyphShapeProvider<'l> {
+    face: Face<'l>,
+    glyphs: HashMap<u16, Option<Arc<Path>>>,
+}
+
+impl<'l> GlyphShapeProvider<'l> {
+    /// Parses a TrueType or OpenType font.
+    pub fn new(font_data: &'l [u8], index: u32) -> Result<Self, ttf_parser::FaceParsingError> {
+        Ok(GlyphShapeProvider {
+            face: Face::parse(font_data, index)?,
+            glyphs: HashMap::new(),
+        })
+    }
+
+    pub fn face(&self) -> &Face<'l> { &self.face }
+
+    /// Returns the glyph of a character, if the font has one.
+    pub fn glyph_index(&self, c: char) -> Option<u16> {
+        self.face.glyph_index(c).map(|id| id.0)
+    }
+
+    /// Returns the outline of a glyph for a font size of 1.0, or `None` if the glyph is
+    /// missing or has no outline (for example spaces).
+    pub fn glyph_path(&mut self, glyph: u16) -> Option<Arc<Path>> {
+        if let Some(path) = self.glyphs.get(&glyph) {
+            return path.clone();
+        }
+
+        let path = glyph_outline(&self.face, glyph, 1.0, point(0.0, 0.0)).map(Arc::new);
+        self.glyphs.insert(glyph, path.clone());
+        return path;
+    }
+
+    /// Horizontal advance of a glyph for a given font size.
+    pub fn advance(&self, glyph: u16, size: f32) -> f32 {
+        let advance = self.face.glyph_hor_advance(GlyphId(glyph)).unwrap_or(0);
+        return advance as f32 * size / self.units_per_em();
+    }
+
+    /// Lays out a single line of text starting at the provided baseline origin.
+    ///
+    /// Characters that are missing from the font are skipped. Kerning and shaping are
+    /// not supported.
+    pub fn layout(&mut self, text: &str, size: f32, origin: Point) -> Vec<PositionedGlyph> {
+        let mut glyphs = Vec::new();
+        let mut position = origin;
+        for c in text.chars() {
+            let glyph = match self.glyph_index(c) {
+                Some(glyph) => glyph,
+                None => { continue; }
+            };
+            if let Some(path) = self.glyph_path(glyph) {
+                glyphs.push(PositionedGlyph {
+                    path: path,
+                    position: position,
+                    size: size,
+                });
+            }
+            position.x += self.advance(glyph, size);
+        }
+
+        return glyphs;
+    }
+
+    /// Builds a single line of text into one path.
+    pub fn text_path(&mut self, text: &str, size: f32, origin: Point) -> Path {
+        let units_per_em = self.units_per_em();
+        let mut builder = Path::builder();
+        let mut position = origin;
+        for c in text.chars() {
+            let glyph = match self.glyph_index(c) {
+                Some(glyph) => glyph,
+                None => { continue; }
+            };
+            {
+                let mut outline = GlyphOutlineBuilder::with_size(&mut builder, units_per_em, size, position);
+                self.face.outline_glyph(GlyphId(glyph), &mut outline);
+                outline.finish();
+            }
+            position.x += self.advance(glyph, size);
+        }
+
+        return builder.build();
+    }
+
+    fn units_per_em(&self) -> f32 { self.face.units_per_em() as f32 }
+}
+
+/// Builds the outline of a glyph into a path, scaled to the provided font size and with its
+/// origin at the provided position.
+///
+/// Returns `None` if the glyph has no outline.
+pub fn glyph_outline(face: &Face, glyph: u16, size: f32, origin: Point) -> Option<Path> {
+    let mut builder = Path::builder();
+    let found = {
+        let units_per_em = face.units_per_em() as f32;
+        let mut outline = GlyphOutlineBuilder::with_size(&mut builder, units_per_em, size, origin);
+        let found = face.outline_glyph(GlyphId(glyph), &mut outline).is_some();
+        outline.finish();
+        found
+    };
+
+    if !found {
+        return None;
+    }
+
+    return Some(builder.build());
+}
+
+#[test]
+fn test_glyph_outline_builder() {
+    let mut builder = Path::builder();
+    {
+        // 1000 units per em, rendered at size 10.
+        let mut outline = GlyphOutlineBuilder::with_size(&mut builder, 1000.0, 10.0, point(5.0, 20.0));
+        outline.move_to(0.0, 0.0);
+        outline.line_to(500.0, 0.0);
+        outline.quad_to(500.0, 500.0, 0.0, 1000.0);
+        // The second contour is left open, finish closes it.
+        outline.move_to(100.0, 100.0);
+        outline.curve_to(200.0, 100.0, 200.0, 200.0, 100.0, 200.0);
+        outline.finish();
+    }
+    let path = builder.build();
+
+    assert_eq!(
+        path.points(),
+        &[
+            point(5.0, 20.0), point(10.0, 20.0),
+            point(10.0, 15.0), point(5.0, 10.0),
+            point(6.0, 19.0),
+            point(7.0, 19.0), point(7.0, 18.0), point(6.0, 18.0),
+        ]
+    );
+    let closes = path.iter().filter(|evt| *evt == ::core::PathEvent::Close).count();
+    assert_eq!(closes, 2);
+}
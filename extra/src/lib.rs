@@ -8,7 +8,12 @@ extern crate lyon_path as path;
 extern crate lyon_path_builder as path_builder;
 extern crate lyon_path_iterator as path_iterator;
 
+#[cfg(feature = "ttf-parser")]
+extern crate ttf_parser;
+
 pub mod rust_logo;
 //pub mod triangle_rasterizer;
 pub mod debugging;
 pub mod image;
+#[cfg(feature = "ttf-parser")]
+pub mod glyphs;
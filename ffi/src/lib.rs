@@ -0,0 +1,520 @@
+#![doc(html_logo_url = "https://nical.github.io/lyon-doc/lyon-logo.svg")]
+
+//! # Lyon FFI
+//!
+//! A C API to build paths, tessellate them and import SVG documents from non-Rust code.
+//!
+//! Paths and geometry are exposed as opaque pointers that must be released with the
+//! corresponding `lyon_*_delete` function. Vector images are not rendered by this crate:
+//! the host provides a `LyonVectorImageBuilder` vtable which receives the colors, gradients,
+//! transforms and shapes of imported documents, and tessellates the shapes it is interested
+//! in with `lyon_fill_path` and `lyon_stroke_path`, so any GPU backend can be plugged in.
+//!
+//! Panics must not unwind into the host, so every function catches them. Functions that
+//! return a `LyonStatus` report them with `LYON_PANIC` and the ones that return a pointer
+//! return null. The objects passed to a function that panicked should be deleted rather
+//! than used again.
+
+extern crate lyon_core as core;
+extern crate lyon_path as path;
+extern crate lyon_path_builder as path_builder;
+extern crate lyon_path_iterator as path_iterator;
+extern crate lyon_tessellation as tessellation;
+extern crate lyon_svg as svg;
+
+use core::math::*;
+use path::{Path, Builder};
+use path_builder::*;
+use tessellation::{FillTessellator, FillOptions, FillVertex, StrokeTessellator, StrokeOptions, StrokeVertex};
+use tessellation::geometry_builder::{VertexBuffers, BuffersBuilder};
+use svg::importer::{self, VectorImageBuilder, RgbaColor, GradientStop, PatternId};
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+pub type LyonPath = Path;
+pub type LyonPathBuilder = Builder;
+pub type LyonGeometry = VertexBuffers<LyonVertex>;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LyonPoint {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LyonVertex {
+    pub position: LyonPoint,
+    pub normal: LyonPoint,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LyonColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LyonGradientStop {
+    pub offset: f32,
+    pub color: LyonColor,
+}
+
+/// The result of the functions that can fail, one of the `LYON_*` status constants.
+pub type LyonStatus = u32;
+
+pub const LYON_OK: LyonStatus = 0;
+/// The path could not be tessellated, or its geometry doesn't fit in 16 bit indices.
+pub const LYON_TESSELLATION_ERROR: LyonStatus = 1;
+/// The SVG document could not be parsed.
+pub const LYON_PARSE_ERROR: LyonStatus = 2;
+/// The function panicked, see the crate documentation.
+pub const LYON_PANIC: LyonStatus = 3;
+
+pub const LYON_PATTERN_COLOR: u32 = 0;
+pub const LYON_PATTERN_GRADIENT: u32 = 1;
+
+/// A handle to a color or a gradient allocated by the host.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LyonPattern {
+    /// Either `LYON_PATTERN_COLOR` or `LYON_PATTERN_GRADIENT`.
+    pub kind: u32,
+    pub id: u32,
+}
+
+/// Transform handle passed to the host for shapes that don't have a transform.
+pub const LYON_NO_TRANSFORM: i32 = -1;
+
+/// The callbacks through which the host receives the content of vector images.
+///
+/// The handles returned by the host are passed back to it as is. Paths are only valid
+/// for the duration of the `fill` and `stroke` callbacks.
+#[repr(C)]
+pub struct LyonVectorImageBuilder {
+    pub user_data: *mut c_void,
+    pub add_color: extern "C" fn(user_data: *mut c_void, color: LyonColor) -> u32,
+    pub add_linear_gradient: extern "C" fn(
+        user_data: *mut c_void,
+        from: LyonPoint,
+        to: LyonPoint,
+        stops: *const LyonGradientStop,
+        num_stops: u32,
+    ) -> u32,
+    /// The transform is passed as the six components of a 2D matrix, in the same order
+    /// as the SVG `matrix(a b c d e f)` notation.
+    pub add_transform: extern "C" fn(user_data: *mut c_void, transform: *const f32) -> u32,
+    pub fill: extern "C" fn(
+        user_data: *mut c_void,
+        path: *const LyonPath,
+        pattern: LyonPattern,
+        transform: i32,
+    ),
+    pub stroke: extern "C" fn(
+        user_data: *mut c_void,
+        path: *const LyonPath,
+        pattern: LyonPattern,
+        width: f32,
+        transform: i32,
+    ),
+    /// Starts a group of shapes composited with an opacity. May be null if the host
+    /// doesn't support groups, in which case the opacity is applied to each shape.
+    pub push_group: Option<extern "C" fn(user_data: *mut c_void, opacity: f32)>,
+    /// Ends the last group. May only be null if `push_group` is null.
+    pub pop_group: Option<extern "C" fn(user_data: *mut c_void)>,
+}
+
+// Paths.
+
+#[no_mangle]
+pub extern "C" fn lyon_path_builder_new() -> *mut LyonPathBuilder {
+    catch_panic(ptr::null_mut(), || Box::into_raw(Box::new(Path::builder())))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lyon_path_builder_move_to(builder: *mut LyonPathBuilder, to: LyonPoint) -> LyonStatus {
+    catch_status(|| {
+        (*builder).move_to(to_point(to));
+        LYON_OK
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lyon_path_builder_line_to(builder: *mut LyonPathBuilder, to: LyonPoint) -> LyonStatus {
+    catch_status(|| {
+        (*builder).line_to(to_point(to));
+        LYON_OK
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lyon_path_builder_quadratic_bezier_to(
+    builder: *mut LyonPathBuilder,
+    ctrl: LyonPoint,
+    to: LyonPoint,
+) -> LyonStatus {
+    catch_status(|| {
+        (*builder).quadratic_bezier_to(to_point(ctrl), to_point(to));
+        LYON_OK
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lyon_path_builder_cubic_bezier_to(
+    builder: *mut LyonPathBuilder,
+    ctrl1: LyonPoint,
+    ctrl2: LyonPoint,
+    to: LyonPoint,
+) -> LyonStatus {
+    catch_status(|| {
+        (*builder).cubic_bezier_to(to_point(ctrl1), to_point(ctrl2), to_point(to));
+        LYON_OK
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lyon_path_builder_close(builder: *mut LyonPathBuilder) -> LyonStatus {
+    catch_status(|| {
+        (*builder).close();
+        LYON_OK
+    })
+}
+
+/// Builds the path and releases the builder.
+#[no_mangle]
+pub unsafe extern "C" fn lyon_path_builder_build(builder: *mut LyonPathBuilder) -> *mut LyonPath {
+    catch_panic(ptr::null_mut(), || {
+        let builder = Box::from_raw(builder);
+        Box::into_raw(Box::new(builder.build()))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lyon_path_builder_delete(builder: *mut LyonPathBuilder) -> LyonStatus {
+    catch_status(|| {
+        if !builder.is_null() {
+            Box::from_raw(builder);
+        }
+        LYON_OK
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lyon_path_delete(path: *mut LyonPath) -> LyonStatus {
+    catch_status(|| {
+        if !path.is_null() {
+            Box::from_raw(path);
+        }
+        LYON_OK
+    })
+}
+
+// Tessellation.
+
+#[no_mangle]
+pub extern "C" fn lyon_geometry_new() -> *mut LyonGeometry {
+    catch_panic(ptr::null_mut(), || Box::into_raw(Box::new(VertexBuffers::new())))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lyon_geometry_delete(geometry: *mut LyonGeometry) -> LyonStatus {
+    catch_status(|| {
+        if !geometry.is_null() {
+            Box::from_raw(geometry);
+        }
+        LYON_OK
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lyon_geometry_clear(geometry: *mut LyonGeometry) -> LyonStatus {
+    catch_status(|| {
+        (*geometry).vertices.clear();
+        (*geometry).indices.clear();
+        LYON_OK
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lyon_geometry_vertices(geometry: *const LyonGeometry, count: *mut u32) -> *const LyonVertex {
+    catch_panic(ptr::null(), || {
+        *count = (*geometry).vertices.len() as u32;
+        (*geometry).vertices.as_ptr()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn lyon_geometry_indices(geometry: *const LyonGeometry, count: *mut u32) -> *const u16 {
+    catch_panic(ptr::null(), || {
+        *count = (*geometry).indices.len() as u32;
+        (*geometry).indices.as_ptr()
+    })
+}
+
+/// Appends the fill tessellation of a path to the geometry.
+///
+/// Returns `LYON_TESSELLATION_ERROR` if the tessellation failed, in which case nothing is
+/// appended.
+#[no_mangle]
+pub unsafe extern "C" fn lyon_fill_path(path: *const LyonPath, tolerance: f32, geometry: *mut LyonGeometry) -> LyonStatus {
+    catch_status(|| {
+        let mut output = BuffersBuilder::new(&mut *geometry, |vertex: FillVertex| {
+            LyonVertex {
+                position: from_point(vertex.position),
+                normal: from_vector(vertex.normal),
+            }
+        });
+        let result = FillTessellator::new().tessellate_path(
+            (*path).path_iter(),
+            &FillOptions::tolerance(tolerance),
+            &mut output,
+        );
+        if result.is_err() || output.overflowed() {
+            return LYON_TESSELLATION_ERROR;
+        }
+
+        LYON_OK
+    })
+}
+
+/// Appends the stroke tessellation of a path to the geometry.
+///
+/// Returns `LYON_TESSELLATION_ERROR` if the geometry doesn't fit in 16 bit indices, in
+/// which case nothing is appended.
+#[no_mangle]
+pub unsafe extern "C" fn lyon_stroke_path(
+    path: *const LyonPath,
+    line_width: f32,
+    tolerance: f32,
+    geometry: *mut LyonGeometry,
+) -> LyonStatus {
+    catch_status(|| {
+        let mut output = BuffersBuilder::new(&mut *geometry, |vertex: StrokeVertex| {
+            LyonVertex {
+                position: from_point(vertex.position),
+                normal: from_vector(vertex.normal),
+            }
+        });
+        StrokeTessellator::new().tessellate_path(
+            (*path).path_iter(),
+            &StrokeOptions::tolerance(tolerance).with_line_width(line_width),
+            &mut output,
+        );
+        if output.overflowed() {
+            return LYON_TESSELLATION_ERROR;
+        }
+
+        LYON_OK
+    })
+}
+
+// Vector images.
+
+/// Parses an SVG document and forwards its content to the host's vector image builder.
+///
+/// Returns `LYON_PARSE_ERROR` if the document could not be parsed.
+#[no_mangle]
+pub unsafe extern "C" fn lyon_import_svg(src: *const c_char, builder: *const LyonVectorImageBuilder) -> LyonStatus {
+    catch_status(|| {
+        let src = match CStr::from_ptr(src).to_str() {
+            Ok(src) => src,
+            Err(_) => { return LYON_PARSE_ERROR; }
+        };
+
+        let mut host = HostImageBuilder { vtable: &*builder };
+        match importer::import_svg(src, &mut host) {
+            Ok(_) => LYON_OK,
+            Err(_) => LYON_PARSE_ERROR,
+        }
+    })
+}
+
+// Runs the body of a function, returning `on_panic` instead of unwinding into the host.
+fn catch_panic<T, F: FnOnce() -> T>(on_panic: T, f: F) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(on_panic)
+}
+
+fn catch_status<F: FnOnce() -> LyonStatus>(f: F) -> LyonStatus {
+    catch_panic(LYON_PANIC, f)
+}
+
+struct HostImageBuilder<'l> {
+    vtable: &'l LyonVectorImageBuilder,
+}
+
+impl<'l> VectorImageBuilder for HostImageBuilder<'l> {
+    type ColorId = u32;
+    type GradientId = u32;
+    type TransformId = u32;
+
+    fn add_color(&mut self, color: RgbaColor) -> u32 {
+        (self.vtable.add_color)(self.vtable.user_data, to_color(color))
+    }
+
+    fn add_linear_gradient(&mut self, from: Point, to: Point, stops: &[GradientStop]) -> u32 {
+        let stops: Vec<LyonGradientStop> = stops.iter().map(|stop| {
+            LyonGradientStop { offset: stop.offset, color: to_color(stop.color) }
+        }).collect();
+        (self.vtable.add_linear_gradient)(
+            self.vtable.user_data,
+            from_point(from),
+            from_point(to),
+            stops.as_ptr(),
+            stops.len() as u32,
+        )
+    }
+
+    fn add_transform(&mut self, transform: &Transform2D) -> u32 {
+        let m = [transform.m11, transform.m12, transform.m21, transform.m22, transform.m31, transform.m32];
+        (self.vtable.add_transform)(self.vtable.user_data, m.as_ptr())
+    }
+
+    fn fill(&mut self, path: Path, pattern: PatternId<u32, u32>, transform: Option<u32>) {
+        (self.vtable.fill)(self.vtable.user_data, &path, to_pattern(pattern), to_transform(transform));
+    }
+
+    fn stroke(&mut self, path: Path, pattern: PatternId<u32, u32>, width: f32, transform: Option<u32>) {
+        (self.vtable.stroke)(self.vtable.user_data, &path, to_pattern(pattern), width, to_transform(transform));
+    }
+
+    fn push_group(&mut self, opacity: f32) -> bool {
+        match (self.vtable.push_group, self.vtable.pop_group) {
+            (Some(push_group), Some(_)) => {
+                push_group(self.vtable.user_data, opacity);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn pop_group(&mut self) {
+        if let Some(pop_group) = self.vtable.pop_group {
+            pop_group(self.vtable.user_data);
+        }
+    }
+}
+
+fn to_point(p: LyonPoint) -> Point { point(p.x, p.y) }
+
+fn from_point(p: Point) -> LyonPoint { LyonPoint { x: p.x, y: p.y } }
+
+fn from_vector(v: Vec2) -> LyonPoint { LyonPoint { x: v.x, y: v.y } }
+
+fn to_color(c: RgbaColor) -> LyonColor { LyonColor { r: c.r, g: c.g, b: c.b, a: c.a } }
+
+fn to_pattern(pattern: PatternId<u32, u32>) -> LyonPattern {
+    match pattern {
+        PatternId::Color(id) => LyonPattern { kind: LYON_PATTERN_COLOR, id: id },
+        PatternId::Gradient(id) => LyonPattern { kind: LYON_PATTERN_GRADIENT, id: id },
+    }
+}
+
+fn to_transform(transform: Option<u32>) -> i32 {
+    transform.map(|id| id as i32).unwrap_or(LYON_NO_TRANSFORM)
+}
+
+#[cfg(test)]
+struct TestHost {
+    colors: Vec<LyonColor>,
+    fills: Vec<(LyonPattern, i32)>,
+    num_triangles: usize,
+}
+
+#[cfg(test)]
+extern "C" fn test_add_color(user_data: *mut c_void, color: LyonColor) -> u32 {
+    let host = unsafe { &mut *(user_data as *mut TestHost) };
+    host.colors.push(color);
+    return host.colors.len() as u32 - 1;
+}
+
+#[cfg(test)]
+extern "C" fn test_add_linear_gradient(_: *mut c_void, _: LyonPoint, _: LyonPoint, _: *const LyonGradientStop, _: u32) -> u32 { 0 }
+
+#[cfg(test)]
+extern "C" fn test_add_transform(_: *mut c_void, _: *const f32) -> u32 { 0 }
+
+#[cfg(test)]
+extern "C" fn test_fill(user_data: *mut c_void, path: *const LyonPath, pattern: LyonPattern, transform: i32) {
+    let host = unsafe { &mut *(user_data as *mut TestHost) };
+    host.fills.push((pattern, transform));
+    unsafe {
+        let geometry = lyon_geometry_new();
+        assert_eq!(lyon_fill_path(path, 0.1, geometry), LYON_OK);
+        let mut count = 0;
+        lyon_geometry_indices(geometry, &mut count);
+        host.num_triangles += count as usize / 3;
+        lyon_geometry_delete(geometry);
+    }
+}
+
+#[cfg(test)]
+extern "C" fn test_stroke(_: *mut c_void, _: *const LyonPath, _: LyonPattern, _: f32, _: i32) {}
+
+#[test]
+fn test_ffi_path_tessellation() {
+    unsafe {
+        let builder = lyon_path_builder_new();
+        lyon_path_builder_move_to(builder, LyonPoint { x: 0.0, y: 0.0 });
+        lyon_path_builder_line_to(builder, LyonPoint { x: 1.0, y: 0.0 });
+        lyon_path_builder_line_to(builder, LyonPoint { x: 1.0, y: 1.0 });
+        lyon_path_builder_line_to(builder, LyonPoint { x: 0.0, y: 1.0 });
+        lyon_path_builder_close(builder);
+        let path = lyon_path_builder_build(builder);
+
+        let geometry = lyon_geometry_new();
+        assert_eq!(lyon_fill_path(path, 0.1, geometry), LYON_OK);
+        let mut num_vertices = 0;
+        let mut num_indices = 0;
+        assert!(!lyon_geometry_vertices(geometry, &mut num_vertices).is_null());
+        lyon_geometry_indices(geometry, &mut num_indices);
+        assert_eq!(num_vertices, 4);
+        assert_eq!(num_indices, 6);
+
+        lyon_geometry_clear(geometry);
+        assert_eq!(lyon_stroke_path(path, 2.0, 0.1, geometry), LYON_OK);
+        lyon_geometry_indices(geometry, &mut num_indices);
+        assert!(num_indices > 0);
+
+        lyon_geometry_delete(geometry);
+        lyon_path_delete(path);
+        assert_eq!(lyon_path_builder_delete(::std::ptr::null_mut()), LYON_OK);
+    }
+}
+
+#[test]
+fn test_ffi_import_svg() {
+    let mut host = TestHost { colors: Vec::new(), fills: Vec::new(), num_triangles: 0 };
+    let builder = LyonVectorImageBuilder {
+        user_data: &mut host as *mut TestHost as *mut c_void,
+        add_color: test_add_color,
+        add_linear_gradient: test_add_linear_gradient,
+        add_transform: test_add_transform,
+        fill: test_fill,
+        stroke: test_stroke,
+        push_group: None,
+        pop_group: None,
+    };
+
+    let src = b"<svg xmlns=\"http://www.w3.org/2000/svg\"><rect width=\"10\" height=\"10\" fill=\"#ff0000\"/></svg>\0";
+    assert_eq!(unsafe { lyon_import_svg(src.as_ptr() as *const c_char, &builder) }, LYON_OK);
+
+    assert_eq!(host.colors, vec![LyonColor { r: 255, g: 0, b: 0, a: 255 }]);
+    assert_eq!(host.fills, vec![(LyonPattern { kind: LYON_PATTERN_COLOR, id: 0 }, LYON_NO_TRANSFORM)]);
+    assert_eq!(host.num_triangles, 2);
+}
+
+#[test]
+fn test_ffi_panic() {
+    // Panics don't unwind into the host.
+    assert_eq!(catch_status(|| panic!()), LYON_PANIC);
+    assert!(catch_panic(ptr::null::<LyonVertex>(), || panic!()).is_null());
+    assert_eq!(catch_status(|| LYON_OK), LYON_OK);
+}
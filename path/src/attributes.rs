@@ -0,0 +1,508 @@
+use path::{Path, Builder, Verb};
+use path_builder::{BaseBuilder, PathBuilder};
+use bezier::{QuadraticBezierSegment, CubicBezierSegment};
+
+use core::{PathEvent, FlattenedEvent};
+use core::math::*;
+
+use std::cmp::Ordering;
+
+/// A path with custom attributes (for example a width, a color index or an elevation)
+/// associated to each endpoint.
+///
+/// Each `MoveTo`, `LineTo`, `QuadraticTo` and `CubicTo` event has the same number of
+/// attributes. Control points don't have attributes.
+///
+/// The attributes are interpolated when flattening the path. The tessellators only deal
+/// with positions, so the attributes of the generated vertices are looked up with an
+/// [AttributeSampler](struct.AttributeSampler.html), using the `advancement` of stroke
+/// vertices or the `position` of fill vertices.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate lyon_core;
+/// # extern crate lyon_path;
+/// # fn main() {
+/// use lyon_path::{AttributedPath, AttributeSampler};
+/// use lyon_core::math::point;
+///
+/// let mut builder = AttributedPath::builder(1);
+/// builder.move_to(point(0.0, 0.0), &[1.0]);
+/// builder.line_to(point(10.0, 0.0), &[3.0]);
+/// let path = builder.build();
+///
+/// let sampler = AttributeSampler::new(&path, 0.1);
+/// let mut width = [0.0];
+/// sampler.at_distance(5.0, &mut width);
+/// assert_eq!(width[0], 2.0);
+/// # }
+/// ```
+///
+/// With the `serialization` feature, deserializing a path which doesn't have
+/// `num_attributes` attributes per endpoint fails.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialization", derive(Serialize))]
+pub struct AttributedPath {
+    path: Path,
+    num_attributes: usize,
+    attributes: Vec<f32>,
+}
+
+#[cfg(feature = "serialization")]
+impl<'de> ::serde::Deserialize<'de> for AttributedPath {
+    fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename = "AttributedPath")]
+        struct AttributedPathData {
+            path: Path,
+            num_attributes: usize,
+            attributes: Vec<f32>,
+        }
+
+        let data = AttributedPathData::deserialize(deserializer)?;
+        let endpoints = data.path.verbs().iter().filter(|&&verb| verb != Verb::Close).count();
+        let expected = endpoints.checked_mul(data.num_attributes);
+        if expected != Some(data.attributes.len()) {
+            return Err(::serde::de::Error::custom(format!(
+                "expected {} attributes for each of the {} endpoints of the path, found {} attributes",
+                data.num_attributes, endpoints, data.attributes.len(),
+            )));
+        }
+
+        return Ok(AttributedPath {
+            path: data.path,
+            num_attributes: data.num_attributes,
+            attributes: data.attributes,
+        });
+    }
+}
+
+impl AttributedPath {
+    /// Creates a builder for paths with `num_attributes` attributes per endpoint.
+    pub fn builder(num_attributes: usize) -> AttributedBuilder {
+        AttributedBuilder::new(num_attributes)
+    }
+
+    /// The path without its attributes.
+    pub fn path(&self) -> &Path { &self.path }
+
+    pub fn num_attributes(&self) -> usize { self.num_attributes }
+
+    /// Number of endpoints with attributes in the path.
+    pub fn num_endpoints(&self) -> usize {
+        if self.num_attributes == 0 {
+            return self.path.verbs().iter().filter(|&&verb| verb != Verb::Close).count();
+        }
+        self.attributes.len() / self.num_attributes
+    }
+
+    /// The attributes of the nth endpoint.
+    pub fn attributes(&self, endpoint: usize) -> &[f32] {
+        let start = endpoint * self.num_attributes;
+        &self.attributes[start..(start + self.num_attributes)]
+    }
+
+    /// Returns a copy of this path where the curves are approximated with line segments.
+    ///
+    /// The attributes of the points added along the curves are interpolated linearly with
+    /// the distance along the curves.
+    pub fn flattened(&self, tolerance: f32) -> AttributedPath {
+        let mut builder = AttributedBuilder::new(self.num_attributes);
+        self.for_each_flattened(tolerance, &mut |evt, attributes| {
+            match evt {
+                FlattenedEvent::MoveTo(to) => { builder.move_to(to, attributes); }
+                FlattenedEvent::LineTo(to) => { builder.line_to(to, attributes); }
+                FlattenedEvent::Close => { builder.close(); }
+            }
+        });
+        return builder.build();
+    }
+
+    /// Calls the callback with the flattened events of the path and their attributes.
+    ///
+    /// `Close` events are provided with the attributes of the first endpoint of the sub-path,
+    /// which is where the closing edge ends.
+    pub fn for_each_flattened<F>(&self, tolerance: f32, callback: &mut F)
+    where
+        F: FnMut(FlattenedEvent, &[f32]),
+    {
+        let n = self.num_attributes;
+        let mut first = point(0.0, 0.0);
+        let mut current = first;
+        let mut first_attributes = vec![0.0; n];
+        let mut current_attributes = vec![0.0; n];
+        let mut tmp = vec![0.0; n];
+        let mut points = Vec::new();
+        let mut endpoint = 0;
+
+        for evt in self.path.iter() {
+            match evt {
+                PathEvent::MoveTo(to) => {
+                    let attributes = self.attributes(endpoint);
+                    endpoint += 1;
+                    callback(FlattenedEvent::MoveTo(to), attributes);
+                    first = to;
+                    current = to;
+                    first_attributes.copy_from_slice(attributes);
+                    current_attributes.copy_from_slice(attributes);
+                }
+                PathEvent::LineTo(to) => {
+                    let attributes = self.attributes(endpoint);
+                    endpoint += 1;
+                    callback(FlattenedEvent::LineTo(to), attributes);
+                    current = to;
+                    current_attributes.copy_from_slice(attributes);
+                }
+                PathEvent::QuadraticTo(ctrl, to) => {
+                    points.clear();
+                    QuadraticBezierSegment {
+                        from: current,
+                        ctrl: ctrl,
+                        to: to,
+                    }.flattened_for_each(tolerance, &mut |p| { points.push(p); });
+
+                    let attributes = self.attributes(endpoint);
+                    endpoint += 1;
+                    interpolate_along(current, &points, &current_attributes, attributes, &mut tmp, callback);
+                    current = to;
+                    current_attributes.copy_from_slice(attributes);
+                }
+                PathEvent::CubicTo(ctrl1, ctrl2, to) => {
+                    points.clear();
+                    CubicBezierSegment {
+                        from: current,
+                        ctrl1: ctrl1,
+                        ctrl2: ctrl2,
+                        to: to,
+                    }.flattened_for_each(tolerance, &mut |p| { points.push(p); });
+
+                    let attributes = self.attributes(endpoint);
+                    endpoint += 1;
+                    interpolate_along(current, &points, &current_attributes, attributes, &mut tmp, callback);
+                    current = to;
+                    current_attributes.copy_from_slice(attributes);
+                }
+                PathEvent::Close => {
+                    callback(FlattenedEvent::Close, &first_attributes);
+                    current = first;
+                    current_attributes.copy_from_slice(&first_attributes);
+                }
+            }
+        }
+    }
+}
+
+// Emits line segments through the points, interpolating the attributes with the distance
+// from the start.
+fn interpolate_along<F>(
+    from: Point,
+    points: &[Point],
+    from_attributes: &[f32],
+    to_attributes: &[f32],
+    tmp: &mut [f32],
+    callback: &mut F,
+) where
+    F: FnMut(FlattenedEvent, &[f32]),
+{
+    let mut total_length = 0.0;
+    let mut prev = from;
+    for &p in points {
+        total_length += (p - prev).length();
+        prev = p;
+    }
+
+    let mut length = 0.0;
+    prev = from;
+    for (i, &p) in points.iter().enumerate() {
+        if i + 1 == points.len() {
+            callback(FlattenedEvent::LineTo(p), to_attributes);
+            break;
+        }
+        length += (p - prev).length();
+        prev = p;
+        let t = if total_length > 0.0 { length / total_length } else { 1.0 };
+        lerp_attributes(from_attributes, to_attributes, t, tmp);
+        callback(FlattenedEvent::LineTo(p), tmp);
+    }
+}
+
+fn lerp_attributes(a: &[f32], b: &[f32], t: f32, output: &mut [f32]) {
+    for i in 0..output.len() {
+        output[i] = a[i] + (b[i] - a[i]) * t;
+    }
+}
+
+/// Builds an `AttributedPath`.
+pub struct AttributedBuilder {
+    builder: Builder,
+    num_attributes: usize,
+    attributes: Vec<f32>,
+}
+
+impl AttributedBuilder {
+    pub fn new(num_attributes: usize) -> Self {
+        AttributedBuilder {
+            builder: Builder::new(),
+            num_attributes: num_attributes,
+            attributes: Vec::new(),
+        }
+    }
+
+    pub fn move_to(&mut self, to: Point, attributes: &[f32]) {
+        self.push_attributes(attributes);
+        self.builder.move_to(to);
+    }
+
+    pub fn line_to(&mut self, to: Point, attributes: &[f32]) {
+        self.push_attributes(attributes);
+        self.builder.line_to(to);
+    }
+
+    pub fn quadratic_bezier_to(&mut self, ctrl: Point, to: Point, attributes: &[f32]) {
+        self.push_attributes(attributes);
+        self.builder.quadratic_bezier_to(ctrl, to);
+    }
+
+    pub fn cubic_bezier_to(&mut self, ctrl1: Point, ctrl2: Point, to: Point, attributes: &[f32]) {
+        self.push_attributes(attributes);
+        self.builder.cubic_bezier_to(ctrl1, ctrl2, to);
+    }
+
+    pub fn close(&mut self) {
+        self.builder.close();
+    }
+
+    pub fn current_position(&self) -> Point { self.builder.current_position() }
+
+    pub fn build(self) -> AttributedPath {
+        AttributedPath {
+            path: self.builder.build(),
+            num_attributes: self.num_attributes,
+            attributes: self.attributes,
+        }
+    }
+
+    fn push_attributes(&mut self, attributes: &[f32]) {
+        assert_eq!(attributes.len(), self.num_attributes);
+        self.attributes.extend_from_slice(attributes);
+    }
+}
+
+/// Looks up the interpolated attributes of an `AttributedPath` at a given distance along
+/// the path or at the closest point on the path.
+///
+/// Distances are measured like the `advancement` of stroke vertices: they accumulate over
+/// all sub-paths, closing edges included.
+pub struct AttributeSampler {
+    num_attributes: usize,
+    segments: Vec<SampledSegment>,
+    // The attributes at the start and at the end of each segment.
+    attributes: Vec<f32>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct SampledSegment {
+    from: Point,
+    to: Point,
+    distance: f32,
+    length: f32,
+}
+
+impl AttributeSampler {
+    pub fn new(path: &AttributedPath, tolerance: f32) -> Self {
+        let n = path.num_attributes();
+        let mut segments = Vec::new();
+        let mut attributes = Vec::new();
+        let mut distance = 0.0;
+        let mut first = point(0.0, 0.0);
+        let mut current = first;
+        let mut current_attributes = vec![0.0; n];
+
+        path.for_each_flattened(tolerance, &mut |evt, evt_attributes| {
+            let to = match evt {
+                FlattenedEvent::MoveTo(to) => {
+                    first = to;
+                    current = to;
+                    current_attributes.copy_from_slice(evt_attributes);
+                    return;
+                }
+                FlattenedEvent::LineTo(to) => to,
+                FlattenedEvent::Close => first,
+            };
+
+            let length = (to - current).length();
+            if length > 0.0 {
+                segments.push(SampledSegment {
+                    from: current,
+                    to: to,
+                    distance: distance,
+                    length: length,
+                });
+                attributes.extend_from_slice(&current_attributes);
+                attributes.extend_from_slice(evt_attributes);
+                distance += length;
+            }
+            current = to;
+            current_attributes.copy_from_slice(evt_attributes);
+        });
+
+        AttributeSampler {
+            num_attributes: n,
+            segments: segments,
+            attributes: attributes,
+        }
+    }
+
+    /// Total length of the path.
+    pub fn length(&self) -> f32 {
+        match self.segments.last() {
+            Some(segment) => segment.distance + segment.length,
+            None => 0.0,
+        }
+    }
+
+    /// Writes the attributes at a given distance along the path into `output`.
+    ///
+    /// Typically used with the `advancement` of stroke vertices. The distance is clamped to
+    /// the length of the path, a NaN distance samples the start of the path. Returns false
+    /// if the path has no edge.
+    pub fn at_distance(&self, distance: f32, output: &mut [f32]) -> bool {
+        if self.segments.is_empty() {
+            return false;
+        }
+
+        let idx = match self.segments.binary_search_by(|segment| {
+            if segment.distance < distance {
+                Ordering::Less
+            } else if segment.distance == distance {
+                Ordering::Equal
+            } else {
+                Ordering::Greater
+            }
+        }) {
+            Ok(idx) => idx,
+            Err(idx) => if idx > 0 { idx - 1 } else { 0 },
+        };
+
+        let segment = &self.segments[idx];
+        let t = ((distance - segment.distance) / segment.length).max(0.0).min(1.0);
+        self.interpolate(idx, t, output);
+
+        return true;
+    }
+
+    /// Writes the attributes at the point of the path closest to `position` into `output`.
+    ///
+    /// Typically used with the `position` of fill vertices. The cost is linear with the number
+    /// of edges of the flattened path. Returns false if the path has no edge.
+    pub fn at_position(&self, position: Point, output: &mut [f32]) -> bool {
+        let mut best = None;
+        let mut best_distance = ::std::f32::MAX;
+        for (idx, segment) in self.segments.iter().enumerate() {
+            let v = segment.to - segment.from;
+            let t = ((position - segment.from).dot(v) / v.square_length()).max(0.0).min(1.0);
+            let d = (segment.from.lerp(segment.to, t) - position).square_length();
+            if d < best_distance {
+                best_distance = d;
+                best = Some((idx, t));
+            }
+        }
+
+        return match best {
+            Some((idx, t)) => {
+                self.interpolate(idx, t, output);
+                true
+            }
+            None => false,
+        };
+    }
+
+    fn interpolate(&self, segment: usize, t: f32, output: &mut [f32]) {
+        let n = self.num_attributes;
+        let start = segment * n * 2;
+        lerp_attributes(
+            &self.attributes[start..(start + n)],
+            &self.attributes[(start + n)..(start + 2 * n)],
+            t,
+            &mut output[..n],
+        );
+    }
+}
+
+#[test]
+fn test_attributed_path_flattening() {
+    let mut builder = AttributedPath::builder(2);
+    builder.move_to(point(0.0, 0.0), &[0.0, 1.0]);
+    builder.quadratic_bezier_to(point(5.0, 5.0), point(10.0, 0.0), &[1.0, 1.0]);
+    builder.close();
+    let path = builder.build();
+    assert_eq!(path.num_endpoints(), 2);
+
+    let flattened = path.flattened(0.01);
+    assert!(flattened.num_endpoints() > 3);
+    assert_eq!(flattened.path().points().len(), flattened.num_endpoints());
+
+    let mut prev = -1.0;
+    for i in 0..flattened.num_endpoints() {
+        let attributes = flattened.attributes(i);
+        assert!(attributes[0] > prev);
+        assert_eq!(attributes[1], 1.0);
+        prev = attributes[0];
+    }
+    assert_eq!(flattened.attributes(flattened.num_endpoints() - 1), &[1.0, 1.0]);
+}
+
+#[test]
+fn test_attribute_sampler() {
+    let mut builder = AttributedPath::builder(1);
+    builder.move_to(point(0.0, 0.0), &[0.0]);
+    builder.line_to(point(10.0, 0.0), &[10.0]);
+    builder.line_to(point(10.0, 10.0), &[20.0]);
+    builder.close();
+    let path = builder.build();
+
+    let sampler = AttributeSampler::new(&path, 0.1);
+    let mut output = [0.0];
+
+    assert!(sampler.at_distance(5.0, &mut output));
+    assert_eq!(output[0], 5.0);
+    assert!(sampler.at_distance(15.0, &mut output));
+    assert_eq!(output[0], 15.0);
+    // The closing edge interpolates back to the first endpoint.
+    assert!(sampler.at_distance(20.0 + 50.0f32.sqrt(), &mut output));
+    assert!((output[0] - 10.0).abs() < 0.001);
+    assert!(sampler.at_distance(::std::f32::NAN, &mut output));
+    assert_eq!(output[0], 0.0);
+
+    assert!(sampler.at_position(point(5.0, -1.0), &mut output));
+    assert_eq!(output[0], 5.0);
+    assert!(sampler.at_position(point(11.0, 5.0), &mut output));
+    assert_eq!(output[0], 15.0);
+
+    let empty = AttributedPath::builder(1).build();
+    assert!(!AttributeSampler::new(&empty, 0.1).at_distance(1.0, &mut output));
+}
+
+#[cfg(feature = "serialization")]
+#[test]
+fn test_attributed_path_serialization() {
+    let mut builder = AttributedPath::builder(2);
+    builder.move_to(point(0.0, 0.0), &[0.0, 1.0]);
+    builder.line_to(point(10.0, 0.0), &[1.0, 1.0]);
+    let path = builder.build();
+
+    let json = ::serde_json::to_string(&path).unwrap();
+    let deserialized: AttributedPath = ::serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized.num_endpoints(), 2);
+    assert_eq!(deserialized.attributes(1), &[1.0, 1.0]);
+
+    // The last endpoint is missing an attribute.
+    let mut malformed: ::serde_json::Value = ::serde_json::from_str(&json).unwrap();
+    malformed["attributes"].as_array_mut().unwrap().pop();
+    assert!(::serde_json::from_value::<AttributedPath>(malformed).is_err());
+
+    // Attributes without a path.
+    let malformed = r#"{"path":{"points":[],"verbs":[]},"num_attributes":1,"attributes":[1.0]}"#;
+    assert!(::serde_json::from_str::<AttributedPath>(malformed).is_err());
+}
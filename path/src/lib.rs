@@ -40,9 +40,28 @@
 //!
 
 extern crate lyon_core as core;
+extern crate lyon_bezier as bezier;
 extern crate lyon_path_builder as path_builder;
 extern crate lyon_path_iterator as path_iterator;
 
+#[cfg(feature = "serialization")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "serialization")]
+extern crate serde;
+#[cfg(all(test, feature = "serialization"))]
+extern crate serde_json;
+
 mod path;
+mod measure;
+mod attributes;
+mod rounded_corners;
+mod morph;
+mod polygon;
 
 pub use path::*;
+pub use measure::*;
+pub use attributes::*;
+pub use rounded_corners::*;
+pub use morph::*;
+pub use polygon::*;
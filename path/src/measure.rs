@@ -0,0 +1,230 @@
+use path::{Path, PathSlice};
+use path_builder::BaseBuilder;
+use path_iterator::PathIterator;
+
+use core::FlattenedEvent;
+use core::math::*;
+
+use std::cmp::Ordering;
+
+/// Measures lengths along a path.
+///
+/// The path is flattened with the provided tolerance, so the measurements are approximations
+/// of the exact lengths along the curves. Distances accumulate over all sub-paths, closing
+/// edges included.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate lyon_core;
+/// # extern crate lyon_path;
+/// # extern crate lyon_path_builder;
+/// # fn main() {
+/// use lyon_path::{Path, PathMeasure};
+/// use lyon_core::math::point;
+/// use lyon_path_builder::*;
+///
+/// let mut builder = Path::builder();
+/// builder.move_to(point(0.0, 0.0));
+/// builder.line_to(point(10.0, 0.0));
+/// builder.line_to(point(10.0, 10.0));
+/// let path = builder.build();
+///
+/// let measure = PathMeasure::new(path.as_slice(), 0.1);
+/// assert_eq!(measure.length(), 20.0);
+/// assert_eq!(measure.position(15.0), Some(point(10.0, 5.0)));
+/// # }
+/// ```
+pub struct PathMeasure {
+    segments: Vec<Segment>,
+    length: f32,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Segment {
+    from: Point,
+    to: Point,
+    // Distance along the path at the start of the segment.
+    distance: f32,
+    length: f32,
+    starts_sub_path: bool,
+}
+
+impl PathMeasure {
+    pub fn new(path: PathSlice, tolerance: f32) -> Self {
+        let mut segments = Vec::new();
+        let mut length = 0.0;
+        let mut first = point(0.0, 0.0);
+        let mut current = first;
+        let mut starts_sub_path = true;
+
+        {
+            let mut add_segment = |from: Point, to: Point, starts_sub_path: &mut bool| {
+                let segment_length = (to - from).length();
+                if segment_length > 0.0 {
+                    segments.push(Segment {
+                        from: from,
+                        to: to,
+                        distance: length,
+                        length: segment_length,
+                        starts_sub_path: *starts_sub_path,
+                    });
+                    length += segment_length;
+                    *starts_sub_path = false;
+                }
+            };
+
+            for evt in path.path_iter().flattened(tolerance) {
+                match evt {
+                    FlattenedEvent::MoveTo(to) => {
+                        first = to;
+                        current = to;
+                        starts_sub_path = true;
+                    }
+                    FlattenedEvent::LineTo(to) => {
+                        add_segment(current, to, &mut starts_sub_path);
+                        current = to;
+                    }
+                    FlattenedEvent::Close => {
+                        add_segment(current, first, &mut starts_sub_path);
+                        current = first;
+                    }
+                }
+            }
+        }
+
+        PathMeasure {
+            segments: segments,
+            length: length,
+        }
+    }
+
+    /// Total length of the path.
+    pub fn length(&self) -> f32 { self.length }
+
+    /// Position at a given distance along the path.
+    ///
+    /// The distance is clamped to the length of the path. Returns `None` if the path is empty.
+    pub fn position(&self, distance: f32) -> Option<Point> {
+        self.sample(distance).map(|(position, _)| position)
+    }
+
+    /// Position and normalized tangent at a given distance along the path.
+    ///
+    /// The distance is clamped to the length of the path. Returns `None` if the path is empty.
+    pub fn sample(&self, distance: f32) -> Option<(Point, Vec2)> {
+        let idx = match self.segment_at(distance) {
+            Some(idx) => idx,
+            None => { return None; }
+        };
+        let segment = &self.segments[idx];
+        let t = ((distance - segment.distance) / segment.length).max(0.0).min(1.0);
+        let tangent = (segment.to - segment.from) / segment.length;
+        return Some((segment.from.lerp(segment.to, t), tangent));
+    }
+
+    /// Builds the part of the path between two distances into a path builder.
+    ///
+    /// The result is made of line segments.
+    pub fn split_range_into<Builder: BaseBuilder>(&self, start: f32, end: f32, output: &mut Builder) {
+        let start = start.max(0.0);
+        let end = end.min(self.length);
+        if start >= end {
+            return;
+        }
+
+        let first_idx = match self.segment_at(start) {
+            Some(idx) => idx,
+            None => { return; }
+        };
+
+        let (start_position, _) = self.sample(start).unwrap();
+        output.move_to(start_position);
+        for segment in &self.segments[first_idx..] {
+            if segment.distance >= end {
+                break;
+            }
+            if segment.starts_sub_path && segment.distance > start {
+                output.move_to(segment.from);
+            }
+            if segment.distance + segment.length >= end {
+                let t = (end - segment.distance) / segment.length;
+                output.line_to(segment.from.lerp(segment.to, t));
+                break;
+            }
+            output.line_to(segment.to);
+        }
+    }
+
+    /// Returns the part of the path between two distances as a new path.
+    ///
+    /// The result is made of line segments.
+    pub fn split_range(&self, start: f32, end: f32) -> Path {
+        let mut builder = Path::builder();
+        self.split_range_into(start, end, &mut builder);
+        return builder.build();
+    }
+
+    // Index of the segment containing the provided distance, the first segment if the
+    // distance is NaN.
+    fn segment_at(&self, distance: f32) -> Option<usize> {
+        if self.segments.is_empty() {
+            return None;
+        }
+
+        let idx = match self.segments.binary_search_by(|segment| {
+            if segment.distance < distance {
+                Ordering::Less
+            } else if segment.distance == distance {
+                Ordering::Equal
+            } else {
+                Ordering::Greater
+            }
+        }) {
+            Ok(idx) => idx,
+            Err(idx) => if idx > 0 { idx - 1 } else { 0 },
+        };
+
+        return Some(idx);
+    }
+}
+
+#[cfg(test)]
+use path_builder::PathBuilder;
+
+#[test]
+fn test_path_measure() {
+    let mut builder = Path::builder();
+    builder.move_to(point(0.0, 0.0));
+    builder.line_to(point(10.0, 0.0));
+    builder.line_to(point(10.0, 10.0));
+    builder.line_to(point(0.0, 10.0));
+    builder.close();
+    builder.move_to(point(20.0, 0.0));
+    builder.quadratic_bezier_to(point(25.0, 0.0), point(30.0, 0.0));
+    let path = builder.build();
+
+    let measure = PathMeasure::new(path.as_slice(), 0.01);
+    assert!((measure.length() - 50.0).abs() < 0.001);
+
+    assert_eq!(measure.sample(15.0), Some((point(10.0, 5.0), vec2(0.0, 1.0))));
+    assert_eq!(measure.position(-1.0), Some(point(0.0, 0.0)));
+    assert_eq!(measure.position(35.0), Some(point(0.0, 5.0)));
+    let end = measure.position(100.0).unwrap();
+    assert!((end - point(30.0, 0.0)).length() < 0.001);
+
+    let sub_path = measure.split_range(5.0, 15.0);
+    assert_eq!(sub_path.points(), &[point(5.0, 0.0), point(10.0, 0.0), point(10.0, 5.0)]);
+
+    // Ranges spanning several sub-paths.
+    let sub_path = measure.split_range(35.0, 45.0);
+    assert_eq!(sub_path.points()[0], point(0.0, 5.0));
+    assert_eq!(sub_path.points()[1], point(0.0, 0.0));
+    assert_eq!(sub_path.points()[2], point(20.0, 0.0));
+    let last = *sub_path.points().last().unwrap();
+    assert!((last - point(25.0, 0.0)).length() < 0.001);
+
+    assert!(PathMeasure::new(Path::new().as_slice(), 0.1).position(1.0).is_none());
+
+    assert_eq!(measure.position(::std::f32::NAN), Some(point(0.0, 0.0)));
+}
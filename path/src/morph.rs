@@ -0,0 +1,192 @@
+use path::{Path, Builder};
+use path_builder::{BaseBuilder, PathBuilder};
+use bezier::{CubicBezierSegment, QuadraticBezierSegment};
+
+use core::PathEvent;
+use core::math::*;
+
+/// Returns versions of two paths that have the same structure, so that they can be
+/// interpolated with `Path::lerp`.
+///
+/// The shapes of the paths are preserved:
+///
+/// - All segments are converted to cubic bézier curves.
+/// - The path with fewer sub-paths is completed with sub-paths collapsed to a point, which
+///   grow into the sub-paths of the other path.
+/// - The sub-paths with fewer segments have their longest curves split until both have as
+///   many segments.
+/// - Closed sub-paths get an explicit closing segment, and if one of two matching sub-paths
+///   is closed, both are. This changes the way open sub-paths are stroked.
+pub fn compatible_paths(a: &Path, b: &Path) -> (Path, Path) {
+    let mut sub_paths_a = cubic_sub_paths(a);
+    let mut sub_paths_b = cubic_sub_paths(b);
+
+    while sub_paths_a.len() < sub_paths_b.len() {
+        let start = sub_paths_b[sub_paths_a.len()].start;
+        sub_paths_a.push(SubPath::collapsed(start));
+    }
+    while sub_paths_b.len() < sub_paths_a.len() {
+        let start = sub_paths_a[sub_paths_b.len()].start;
+        sub_paths_b.push(SubPath::collapsed(start));
+    }
+
+    let mut builder_a = Path::builder();
+    let mut builder_b = Path::builder();
+    for (sub_path_a, sub_path_b) in sub_paths_a.iter_mut().zip(sub_paths_b.iter_mut()) {
+        let count = ::std::cmp::max(sub_path_a.curves.len(), sub_path_b.curves.len());
+        sub_path_a.subdivide(count);
+        sub_path_b.subdivide(count);
+        let closed = sub_path_a.closed || sub_path_b.closed;
+        sub_path_a.build(closed, &mut builder_a);
+        sub_path_b.build(closed, &mut builder_b);
+    }
+
+    return (builder_a.build(), builder_b.build());
+}
+
+struct SubPath {
+    start: Point,
+    curves: Vec<CubicBezierSegment>,
+    closed: bool,
+}
+
+impl SubPath {
+    fn collapsed(position: Point) -> Self {
+        SubPath {
+            start: position,
+            curves: Vec::new(),
+            closed: false,
+        }
+    }
+
+    // Splits the longest curves until there are `count` of them.
+    fn subdivide(&mut self, count: usize) {
+        if self.curves.is_empty() && count > 0 {
+            let p = self.start;
+            self.curves.push(CubicBezierSegment { from: p, ctrl1: p, ctrl2: p, to: p });
+        }
+
+        while self.curves.len() < count {
+            let mut longest = 0;
+            let mut max_length = -1.0;
+            for (idx, curve) in self.curves.iter().enumerate() {
+                let length = approximate_length(curve);
+                if length > max_length {
+                    longest = idx;
+                    max_length = length;
+                }
+            }
+            let (first, second) = self.curves[longest].split(0.5);
+            self.curves[longest] = first;
+            self.curves.insert(longest + 1, second);
+        }
+    }
+
+    fn build(&self, closed: bool, output: &mut Builder) {
+        output.move_to(self.start);
+        for curve in &self.curves {
+            output.cubic_bezier_to(curve.ctrl1, curve.ctrl2, curve.to);
+        }
+        if closed {
+            output.close();
+        }
+    }
+}
+
+// Length of the control polygon, an upper bound of the length of the curve.
+fn approximate_length(curve: &CubicBezierSegment) -> f32 {
+    (curve.ctrl1 - curve.from).length()
+        + (curve.ctrl2 - curve.ctrl1).length()
+        + (curve.to - curve.ctrl2).length()
+}
+
+fn line_to_cubic(from: Point, to: Point) -> CubicBezierSegment {
+    CubicBezierSegment {
+        from: from,
+        ctrl1: from.lerp(to, 1.0 / 3.0),
+        ctrl2: from.lerp(to, 2.0 / 3.0),
+        to: to,
+    }
+}
+
+fn cubic_sub_paths(path: &Path) -> Vec<SubPath> {
+    let mut sub_paths: Vec<SubPath> = Vec::new();
+    let mut current = point(0.0, 0.0);
+    for evt in path.iter() {
+        // Paths that don't start with a MoveTo event start at the origin.
+        let starts_sub_path = match evt {
+            PathEvent::MoveTo(_) => true,
+            _ => false,
+        };
+        if sub_paths.is_empty() && !starts_sub_path {
+            sub_paths.push(SubPath::collapsed(current));
+        }
+
+        match evt {
+            PathEvent::MoveTo(to) => {
+                sub_paths.push(SubPath::collapsed(to));
+                current = to;
+            }
+            PathEvent::LineTo(to) => {
+                sub_paths.last_mut().unwrap().curves.push(line_to_cubic(current, to));
+                current = to;
+            }
+            PathEvent::QuadraticTo(ctrl, to) => {
+                let curve = QuadraticBezierSegment { from: current, ctrl: ctrl, to: to }.to_cubic();
+                sub_paths.last_mut().unwrap().curves.push(curve);
+                current = to;
+            }
+            PathEvent::CubicTo(ctrl1, ctrl2, to) => {
+                let curve = CubicBezierSegment { from: current, ctrl1: ctrl1, ctrl2: ctrl2, to: to };
+                sub_paths.last_mut().unwrap().curves.push(curve);
+                current = to;
+            }
+            PathEvent::Close => {
+                let sub_path = sub_paths.last_mut().unwrap();
+                let first = sub_path.start;
+                if current != first {
+                    sub_path.curves.push(line_to_cubic(current, first));
+                }
+                sub_path.closed = true;
+                current = first;
+            }
+        }
+    }
+
+    return sub_paths;
+}
+
+#[test]
+fn test_compatible_paths() {
+    let mut builder = Path::builder();
+    builder.move_to(point(0.0, 0.0));
+    builder.line_to(point(10.0, 0.0));
+    builder.line_to(point(10.0, 10.0));
+    builder.close();
+    let triangle = builder.build();
+
+    let mut builder = Path::builder();
+    builder.move_to(point(0.0, 0.0));
+    builder.quadratic_bezier_to(point(5.0, -5.0), point(10.0, 0.0));
+    builder.line_to(point(10.0, 10.0));
+    builder.line_to(point(0.0, 10.0));
+    builder.close();
+    builder.move_to(point(20.0, 20.0));
+    builder.line_to(point(30.0, 20.0));
+    let other = builder.build();
+
+    assert!(Path::lerp(&triangle, &other, 0.5).is_none());
+
+    let (a, b) = compatible_paths(&triangle, &other);
+    assert!(a.is_compatible(&b));
+    // Four curves in the first sub-path and one in the second one.
+    assert_eq!(a.verbs().len(), 8);
+
+    let mid = Path::lerp(&a, &b, 0.5).unwrap();
+    let start = Path::lerp(&a, &b, 0.0).unwrap();
+    assert_eq!(start.points(), a.points());
+    assert_eq!(mid.points()[0], point(0.0, 0.0));
+    // The collapsed sub-path grows from the start of the second sub-path.
+    assert_eq!(a.points()[a.points().len() - 1], point(20.0, 20.0));
+    assert_eq!(mid.points()[mid.points().len() - 1], point(25.0, 20.0));
+}
@@ -1,7 +1,8 @@
 use path_builder::{BaseBuilder, PathBuilder, SvgPathBuilder, FlatteningBuilder};
-use path_iterator::PathStateIter;
+use path_iterator::{PathIterator, PathStateIter, Segment};
 
-use core::PathEvent;
+use core::{PathEvent, FlattenedEvent, FillRule};
+use bezier::{QuadraticBezierSegment, CubicBezierSegment};
 use core::math::*;
 
 use std::iter::IntoIterator;
@@ -12,6 +13,7 @@ use std::iter::IntoIterator;
 /// This is used by the [Path](struct.Path.html) data structure to store path events a tad
 /// more efficiently.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
 pub enum Verb {
     MoveTo,
     LineTo,
@@ -20,15 +22,99 @@ pub enum Verb {
     Close,
 }
 
+/// The point of a path closest to another point, see `Path::closest_point`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ClosestPoint {
+    pub position: Point,
+    pub distance: f32,
+    /// Index in the path's events of the event of the segment containing the point.
+    pub event: usize,
+    /// Parameter of the point along the segment.
+    pub t: f32,
+}
+
+/// The direction in which a sub-path winds around the area it encloses.
+///
+/// Directions are given on screen, with the y axis pointing down.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Winding {
+    Clockwise,
+    CounterClockwise,
+}
+
+impl Winding {
+    /// The winding of a sub-path from its signed area, see `Path::sub_path_signed_areas`.
+    pub fn from_signed_area(area: f32) -> Option<Winding> {
+        if area > 0.0 {
+            Some(Winding::Clockwise)
+        } else if area < 0.0 {
+            Some(Winding::CounterClockwise)
+        } else {
+            None
+        }
+    }
+}
+
+// Contribution of an edge to the signed area of a polygon (shoelace formula).
+fn edge_area(from: Point, to: Point) -> f32 {
+    (from.x * to.y - to.x * from.y) * 0.5
+}
+
+fn add_segment(builder: &mut Builder, segment: &Segment) {
+    match *segment {
+        Segment::Line(_, to) => builder.line_to(to),
+        Segment::QuadraticBezier(_, ctrl, to) => builder.quadratic_bezier_to(ctrl, to),
+        Segment::CubicBezier(_, ctrl1, ctrl2, to) => builder.cubic_bezier_to(ctrl1, ctrl2, to),
+    }
+}
+
 /// A simple path data structure.
 ///
 /// It can be created using a [Builder](struct.Builder.html), and can be iterated over.
+///
+/// With the `serialization` feature, deserializing a path whose verbs don't match its
+/// number of points fails instead of producing a path that panics when iterated.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialization", derive(Serialize))]
 pub struct Path {
     points: Vec<Point>,
     verbs: Vec<Verb>,
 }
 
+#[cfg(feature = "serialization")]
+impl<'de> ::serde::Deserialize<'de> for Path {
+    fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename = "Path")]
+        struct PathData {
+            points: Vec<Point>,
+            verbs: Vec<Verb>,
+        }
+
+        let data = PathData::deserialize(deserializer)?;
+        let expected: usize = data.verbs.iter().map(|&verb| num_points(verb)).sum();
+        if expected != data.points.len() {
+            return Err(::serde::de::Error::custom(format!(
+                "the verbs of the path require {} points, found {}",
+                expected, data.points.len(),
+            )));
+        }
+
+        return Ok(Path { points: data.points, verbs: data.verbs });
+    }
+}
+
+// The number of points a verb consumes.
+#[cfg(feature = "serialization")]
+fn num_points(verb: Verb) -> usize {
+    match verb {
+        Verb::MoveTo | Verb::LineTo => 1,
+        Verb::QuadraticTo => 2,
+        Verb::CubicTo => 3,
+        Verb::Close => 0,
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct PathSlice<'l> {
     points: &'l [Point],
@@ -69,6 +155,497 @@ impl Path {
     pub fn mut_points(&mut self) -> &mut [Point] { &mut self.points[..] }
 
     pub fn verbs(&self) -> &[Verb] { &self.verbs[..] }
+
+    /// Removes all of the points and verbs of the path while keeping the allocations.
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.verbs.clear();
+    }
+
+    /// Creates a builder that appends to this path, reusing its allocations.
+    ///
+    /// This can be used to extend an existing path, or together with `clear` to reuse the
+    /// memory of a path that isn't needed anymore when building a new one.
+    pub fn into_builder(self) -> Builder {
+        let mut first = point(0.0, 0.0);
+        let mut current = first;
+        let mut p = 0;
+        for verb in &self.verbs {
+            match *verb {
+                Verb::MoveTo => {
+                    first = self.points[p];
+                    current = first;
+                    p += 1;
+                }
+                Verb::LineTo => {
+                    current = self.points[p];
+                    p += 1;
+                }
+                Verb::QuadraticTo => {
+                    current = self.points[p + 1];
+                    p += 2;
+                }
+                Verb::CubicTo => {
+                    current = self.points[p + 2];
+                    p += 3;
+                }
+                Verb::Close => {
+                    current = first;
+                }
+            }
+        }
+
+        let building = match self.verbs.last() {
+            Some(&Verb::Close) | None => false,
+            _ => true,
+        };
+
+        Builder {
+            path: self,
+            current_position: current,
+            first_position: first,
+            building: building,
+        }
+    }
+
+    /// Returns a path containing the sub-paths of this path followed by the ones of
+    /// another path.
+    pub fn merge(&self, other: &Path) -> Self {
+        let mut builder = Path::with_capacity(self.verbs.len() + other.verbs.len()).into_builder();
+        builder.extend_from_path(self.as_slice());
+        builder.extend_from_path(other.as_slice());
+        return builder.build();
+    }
+
+    /// Applies a transform to all of the points of the path, control points included.
+    pub fn apply_transform(&mut self, transform: &Transform2D) {
+        for p in &mut self.points {
+            *p = transform.transform_point(p);
+        }
+    }
+
+    /// Returns a copy of this path with a transform applied to all of its points.
+    #[must_use]
+    pub fn transformed(&self, transform: &Transform2D) -> Self {
+        Path {
+            points: self.points.iter().map(|p| transform.transform_point(p)).collect(),
+            verbs: self.verbs.clone(),
+        }
+    }
+
+    /// Returns the point of the path that is the closest to a given point, or `None` if the
+    /// path has no segment.
+    ///
+    /// Only the closing edges of the sub-paths that are explicitly closed are considered,
+    /// like when stroking the path.
+    pub fn closest_point(&self, p: Point) -> Option<ClosestPoint> {
+        let mut result: Option<ClosestPoint> = None;
+        let mut first = point(0.0, 0.0);
+        let mut current = first;
+        for (idx, evt) in self.iter().enumerate() {
+            let segment = match evt {
+                PathEvent::MoveTo(to) => {
+                    first = to;
+                    current = to;
+                    continue;
+                }
+                PathEvent::LineTo(to) => Segment::Line(current, to),
+                PathEvent::QuadraticTo(ctrl, to) => Segment::QuadraticBezier(current, ctrl, to),
+                PathEvent::CubicTo(ctrl1, ctrl2, to) => Segment::CubicBezier(current, ctrl1, ctrl2, to),
+                PathEvent::Close => Segment::Line(current, first),
+            };
+            current = segment.to();
+
+            let t = segment.closest_point_t(p);
+            let position = segment.sample(t);
+            let distance = (position - p).length();
+            if result.map(|r| distance < r.distance).unwrap_or(true) {
+                result = Some(ClosestPoint {
+                    position: position,
+                    distance: distance,
+                    event: idx,
+                    t: t,
+                });
+            }
+        }
+
+        return result;
+    }
+
+    /// Whether two paths have the same structure, which is required to interpolate
+    /// between them.
+    ///
+    /// See `lerp` and `compatible_paths`.
+    pub fn is_compatible(&self, other: &Path) -> bool {
+        self.verbs == other.verbs
+    }
+
+    /// Linearly interpolates the points of two compatible paths, or returns `None` if the
+    /// paths aren't compatible.
+    ///
+    /// Paths with different structures can be made compatible with `compatible_paths`.
+    pub fn lerp(a: &Path, b: &Path, t: f32) -> Option<Path> {
+        if !a.is_compatible(b) {
+            return None;
+        }
+
+        return Some(Path {
+            points: a.points.iter().zip(b.points.iter()).map(|(pa, pb)| pa.lerp(*pb, t)).collect(),
+            verbs: a.verbs.clone(),
+        });
+    }
+
+    /// Splits the path at a given distance along it, closing edges included.
+    ///
+    /// The segment containing the split point is split exactly, curves included. Lengths
+    /// along curves are approximated within the tolerance. The second path starts at the
+    /// split point, and if the split happens in a closed sub-path, the rest of that sub-path
+    /// ends with a line back to its first point instead of a close.
+    pub fn split_at_distance(&self, distance: f32, tolerance: f32) -> (Path, Path) {
+        if distance <= 0.0 {
+            return (Path::new(), self.clone());
+        }
+
+        let mut before = Path::builder();
+        let mut after = Path::builder();
+        let mut remaining = distance;
+        let mut split = false;
+        // Whether the current sub-path of the second path starts at the split point.
+        let mut cut_sub_path = false;
+        let mut first = point(0.0, 0.0);
+        let mut current = first;
+        for evt in self.iter() {
+            if split {
+                match evt {
+                    PathEvent::Close if cut_sub_path => {
+                        after.line_to(first);
+                        cut_sub_path = false;
+                    }
+                    PathEvent::MoveTo(_) => {
+                        cut_sub_path = false;
+                        after.path_event(evt);
+                    }
+                    _ => {
+                        after.path_event(evt);
+                    }
+                }
+            } else {
+                let segment = match evt {
+                    PathEvent::MoveTo(to) => {
+                        before.move_to(to);
+                        first = to;
+                        current = to;
+                        continue;
+                    }
+                    PathEvent::LineTo(to) => Segment::Line(current, to),
+                    PathEvent::QuadraticTo(ctrl, to) => Segment::QuadraticBezier(current, ctrl, to),
+                    PathEvent::CubicTo(ctrl1, ctrl2, to) => Segment::CubicBezier(current, ctrl1, ctrl2, to),
+                    PathEvent::Close => Segment::Line(current, first),
+                };
+
+                let length = segment.length(tolerance);
+                if length <= remaining {
+                    remaining -= length;
+                    before.path_event(evt);
+                } else {
+                    let t = segment.parameter_at_distance(remaining, tolerance);
+                    let (a, b) = segment.split(t);
+                    add_segment(&mut before, &a);
+                    after.move_to(b.from());
+                    add_segment(&mut after, &b);
+                    split = true;
+                    cut_sub_path = evt != PathEvent::Close;
+                }
+            }
+
+            match evt {
+                PathEvent::MoveTo(to) => {
+                    first = to;
+                    current = to;
+                }
+                PathEvent::Close => {
+                    current = first;
+                }
+                _ => {
+                    current = evt.destination().unwrap();
+                }
+            }
+        }
+
+        return (before.build(), after.build());
+    }
+
+    /// Returns the smallest rectangle containing the path.
+    ///
+    /// Unlike a rectangle computed from the points of the path, this takes the extrema of the
+    /// curves into account rather than their control points. Returns an empty rectangle at the
+    /// origin if the path is empty.
+    pub fn bounding_rect(&self) -> Rect {
+        let mut min = point(::std::f32::MAX, ::std::f32::MAX);
+        let mut max = point(::std::f32::MIN, ::std::f32::MIN);
+        {
+            let mut add_point = |p: Point| {
+                min.x = min.x.min(p.x);
+                min.y = min.y.min(p.y);
+                max.x = max.x.max(p.x);
+                max.y = max.y.max(p.y);
+            };
+
+            let mut first = point(0.0, 0.0);
+            let mut current = first;
+            for evt in self.iter() {
+                match evt {
+                    PathEvent::MoveTo(to) => {
+                        add_point(to);
+                        first = to;
+                        current = to;
+                    }
+                    PathEvent::LineTo(to) => {
+                        add_point(current);
+                        add_point(to);
+                        current = to;
+                    }
+                    PathEvent::QuadraticTo(ctrl, to) => {
+                        let r = QuadraticBezierSegment {
+                            from: current,
+                            ctrl: ctrl,
+                            to: to,
+                        }.minimum_bounding_rect();
+                        add_point(r.origin);
+                        add_point(r.bottom_right());
+                        current = to;
+                    }
+                    PathEvent::CubicTo(ctrl1, ctrl2, to) => {
+                        let r = CubicBezierSegment {
+                            from: current,
+                            ctrl1: ctrl1,
+                            ctrl2: ctrl2,
+                            to: to,
+                        }.minimum_bounding_rect();
+                        add_point(r.origin);
+                        add_point(r.bottom_right());
+                        current = to;
+                    }
+                    PathEvent::Close => {
+                        current = first;
+                    }
+                }
+            }
+        }
+
+        if min.x > max.x {
+            return Rect::zero();
+        }
+
+        return rect(min.x, min.y, max.x - min.x, max.y - min.y);
+    }
+
+    /// Returns a rectangle containing the path stroked with the provided line width.
+    ///
+    /// The rectangle is conservative: it is inflated by the largest distance that joins and caps
+    /// can extend away from the path. Miter joins extend up to `miter_limit` times half of the
+    /// line width, so a miter limit of 1.0 can be passed when using round or bevel joins.
+    pub fn stroke_bounding_rect(&self, line_width: f32, miter_limit: f32) -> Rect {
+        if self.verbs.is_empty() {
+            return Rect::zero();
+        }
+        // Square caps extend up to sqrt(2) times half of the line width at the corners.
+        let d = line_width * 0.5 * miter_limit.max(::std::f32::consts::SQRT_2);
+        return self.bounding_rect().inflate(d, d);
+    }
+
+    /// Returns whether a point is inside of the path according to a fill rule.
+    ///
+    /// All sub-paths are considered closed, like when filling the path. The curves are
+    /// approximated with line segments using the provided tolerance.
+    pub fn contains_point(&self, p: Point, fill_rule: FillRule, tolerance: f32) -> bool {
+        let mut winding = 0;
+        let mut first = point(0.0, 0.0);
+        let mut current = first;
+        for evt in self.path_iter().flattened(tolerance) {
+            match evt {
+                FlattenedEvent::MoveTo(to) => {
+                    winding += edge_winding(current, first, p);
+                    first = to;
+                    current = to;
+                }
+                FlattenedEvent::LineTo(to) => {
+                    winding += edge_winding(current, to, p);
+                    current = to;
+                }
+                FlattenedEvent::Close => {
+                    winding += edge_winding(current, first, p);
+                    current = first;
+                }
+            }
+        }
+        winding += edge_winding(current, first, p);
+
+        return match fill_rule {
+            FillRule::EvenOdd => winding % 2 != 0,
+            FillRule::NonZero => winding != 0,
+        };
+    }
+
+    /// Returns the signed area of the path, the sum of the signed areas of its sub-paths.
+    ///
+    /// See `sub_path_signed_areas`.
+    pub fn signed_area(&self, tolerance: f32) -> f32 {
+        self.sub_path_signed_areas(tolerance).iter().fold(0.0, |sum, area| sum + area)
+    }
+
+    /// Returns the signed area of each sub-path.
+    ///
+    /// All sub-paths are considered closed. The area is positive when the sub-path winds
+    /// clockwise on screen (with the y axis pointing down), see `Winding`. The curves are
+    /// approximated with line segments using the provided tolerance.
+    pub fn sub_path_signed_areas(&self, tolerance: f32) -> Vec<f32> {
+        let mut areas = Vec::new();
+        let mut area = 0.0;
+        let mut in_sub_path = false;
+        let mut first = point(0.0, 0.0);
+        let mut current = first;
+        for evt in self.path_iter().flattened(tolerance) {
+            match evt {
+                FlattenedEvent::MoveTo(to) => {
+                    if in_sub_path {
+                        areas.push(area + edge_area(current, first));
+                    }
+                    in_sub_path = true;
+                    area = 0.0;
+                    first = to;
+                    current = to;
+                }
+                FlattenedEvent::LineTo(to) => {
+                    in_sub_path = true;
+                    area += edge_area(current, to);
+                    current = to;
+                }
+                FlattenedEvent::Close => {
+                    area += edge_area(current, first);
+                    current = first;
+                }
+            }
+        }
+        if in_sub_path {
+            areas.push(area + edge_area(current, first));
+        }
+
+        return areas;
+    }
+
+    /// Returns the winding direction of each sub-path, or `None` for the sub-paths that
+    /// don't enclose any area.
+    pub fn sub_path_windings(&self, tolerance: f32) -> Vec<Option<Winding>> {
+        self.sub_path_signed_areas(tolerance).iter().map(|&area| Winding::from_signed_area(area)).collect()
+    }
+
+    /// Returns a copy of this path where the direction of each sub-path is reversed.
+    ///
+    /// The sub-paths keep their order and their shape, curves included. The reversed sub-paths
+    /// always start with a `MoveTo` event.
+    pub fn reversed(&self) -> Self {
+        let mut builder = Builder::with_capacity(self.points.len());
+        // The segments of the current sub-path, as (verb, index of the first point).
+        let mut segments = Vec::new();
+        let mut first = point(0.0, 0.0);
+        let mut has_move_to = false;
+        let mut p = 0;
+        for &verb in &self.verbs {
+            match verb {
+                Verb::MoveTo => {
+                    self.reverse_sub_path(first, &segments, has_move_to, false, &mut builder);
+                    segments.clear();
+                    first = self.points[p];
+                    has_move_to = true;
+                    p += 1;
+                }
+                Verb::Close => {
+                    self.reverse_sub_path(first, &segments, has_move_to, true, &mut builder);
+                    segments.clear();
+                    has_move_to = false;
+                }
+                Verb::LineTo => {
+                    segments.push((verb, p));
+                    p += 1;
+                }
+                Verb::QuadraticTo => {
+                    segments.push((verb, p));
+                    p += 2;
+                }
+                Verb::CubicTo => {
+                    segments.push((verb, p));
+                    p += 3;
+                }
+            }
+        }
+        self.reverse_sub_path(first, &segments, has_move_to, false, &mut builder);
+
+        return builder.build();
+    }
+
+    fn reverse_sub_path(
+        &self,
+        first: Point,
+        segments: &[(Verb, usize)],
+        has_move_to: bool,
+        closed: bool,
+        builder: &mut Builder,
+    ) {
+        let last = match segments.last() {
+            Some(&segment) => segment_end(&self.points, segment),
+            None => {
+                // Preserve empty sub-paths.
+                if has_move_to || closed {
+                    builder.move_to(first);
+                }
+                if closed {
+                    builder.close();
+                }
+                return;
+            }
+        };
+
+        builder.move_to(last);
+        for (i, &(verb, p)) in segments.iter().enumerate().rev() {
+            // The start of the segment is the end of the previous one.
+            let from = if i == 0 { first } else { segment_end(&self.points, segments[i - 1]) };
+            match verb {
+                Verb::LineTo => { builder.line_to(from); }
+                Verb::QuadraticTo => { builder.quadratic_bezier_to(self.points[p], from); }
+                Verb::CubicTo => {
+                    builder.cubic_bezier_to(self.points[p + 1], self.points[p], from);
+                }
+                Verb::MoveTo | Verb::Close => { unreachable!(); }
+            }
+        }
+        if closed {
+            builder.close();
+        }
+    }
+}
+
+// Contribution of an edge to the winding number of a point, counting the crossings of a
+// ray going from the point towards positive x.
+fn edge_winding(from: Point, to: Point, p: Point) -> i32 {
+    let side = (to - from).cross(p - from);
+    if from.y <= p.y {
+        if to.y > p.y && side > 0.0 {
+            return 1;
+        }
+    } else if to.y <= p.y && side < 0.0 {
+        return -1;
+    }
+    return 0;
+}
+
+// Returns the last point of a segment given its verb and the index of its first point.
+fn segment_end(points: &[Point], segment: (Verb, usize)) -> Point {
+    return match segment {
+        (Verb::QuadraticTo, p) => points[p + 1],
+        (Verb::CubicTo, p) => points[p + 2],
+        (_, p) => points[p],
+    };
 }
 
 impl<'l> IntoIterator for &'l Path {
@@ -127,6 +704,20 @@ impl Builder {
 
     pub fn with_svg(self) -> SvgPathBuilder<Self> { SvgPathBuilder::new(self) }
 
+    /// Adds the sub-paths of a path to the builder.
+    ///
+    /// The appended sub-paths keep their shape: if the path doesn't begin with a `MoveTo`,
+    /// its first sub-path starts at the origin like it would on its own.
+    pub fn extend_from_path(&mut self, path: PathSlice) {
+        match path.verbs().first() {
+            Some(&Verb::MoveTo) | None => {}
+            Some(_) => { self.move_to(point(0.0, 0.0)); }
+        }
+        for evt in path.iter() {
+            self.path_event(evt);
+        }
+    }
+
     pub fn flattened(self, tolerance: f32) -> FlatteningBuilder<Self> {
         FlatteningBuilder::new(self, tolerance)
     }
@@ -354,6 +945,189 @@ fn test_path_builder_move_to_after_close() {
     assert_eq!(it.next(), None);
 }
 
+#[test]
+fn test_path_transformed() {
+    let mut p = Path::builder();
+    p.move_to(point(1.0, 0.0));
+    p.quadratic_bezier_to(point(2.0, 0.0), point(2.0, 1.0));
+    p.cubic_bezier_to(point(3.0, 0.0), point(3.0, 1.0), point(3.0, 2.0));
+    p.close();
+    let path = p.build();
+
+    let transform = Transform2D::create_translation(1.0, 2.0).post_scale(2.0, 2.0);
+    let transformed = path.transformed(&transform);
+    let mut it = transformed.iter();
+    assert_eq!(it.next(), Some(PathEvent::MoveTo(point(4.0, 4.0))));
+    assert_eq!(it.next(), Some(PathEvent::QuadraticTo(point(6.0, 4.0), point(6.0, 6.0))));
+    assert_eq!(
+        it.next(),
+        Some(PathEvent::CubicTo(point(8.0, 4.0), point(8.0, 6.0), point(8.0, 8.0)))
+    );
+    assert_eq!(it.next(), Some(PathEvent::Close));
+    assert_eq!(it.next(), None);
+
+    let mut in_place = path.clone();
+    in_place.apply_transform(&transform);
+    assert_eq!(in_place.points(), transformed.points());
+    assert_eq!(in_place.verbs(), transformed.verbs());
+}
+
+#[test]
+fn test_path_bounding_rect() {
+    assert_eq!(Path::new().bounding_rect(), Rect::zero());
+
+    let mut p = Path::builder();
+    p.move_to(point(0.0, 0.0));
+    p.quadratic_bezier_to(point(1.0, 2.0), point(2.0, 0.0));
+    p.cubic_bezier_to(point(2.0, -1.0), point(3.0, -1.0), point(3.0, 0.0));
+    p.close();
+    let path = p.build();
+
+    let r = path.bounding_rect();
+    assert!((r.origin.x - 0.0).abs() < 0.0001);
+    assert!((r.origin.y + 0.75).abs() < 0.0001);
+    assert!((r.size.width - 3.0).abs() < 0.0001);
+    assert!((r.size.height - 1.75).abs() < 0.0001);
+
+    let stroke_rect = path.stroke_bounding_rect(2.0, 4.0);
+    assert!((stroke_rect.origin.x + 4.0).abs() < 0.0001);
+    assert!((stroke_rect.size.width - 11.0).abs() < 0.0001);
+}
+
+#[test]
+fn test_path_contains_point() {
+    let mut p = Path::builder();
+    // Outer square.
+    p.move_to(point(0.0, 0.0));
+    p.line_to(point(10.0, 0.0));
+    p.line_to(point(10.0, 10.0));
+    p.line_to(point(0.0, 10.0));
+    p.close();
+    // Inner square with the same orientation.
+    p.move_to(point(2.0, 2.0));
+    p.line_to(point(8.0, 2.0));
+    p.line_to(point(8.0, 8.0));
+    p.line_to(point(2.0, 8.0));
+    p.close();
+    // A curved sub-path which isn't explicitly closed.
+    p.move_to(point(20.0, 0.0));
+    p.quadratic_bezier_to(point(25.0, 10.0), point(30.0, 0.0));
+    let path = p.build();
+
+    assert!(path.contains_point(point(1.0, 5.0), FillRule::EvenOdd, 0.1));
+    assert!(path.contains_point(point(1.0, 5.0), FillRule::NonZero, 0.1));
+    assert!(!path.contains_point(point(5.0, 5.0), FillRule::EvenOdd, 0.1));
+    assert!(path.contains_point(point(5.0, 5.0), FillRule::NonZero, 0.1));
+    assert!(!path.contains_point(point(15.0, 5.0), FillRule::NonZero, 0.1));
+
+    assert!(path.contains_point(point(25.0, 4.0), FillRule::NonZero, 0.01));
+    assert!(!path.contains_point(point(25.0, 6.0), FillRule::NonZero, 0.01));
+    assert!(!path.contains_point(point(25.0, -1.0), FillRule::NonZero, 0.01));
+
+    assert!(!Path::new().contains_point(point(0.0, 0.0), FillRule::NonZero, 0.1));
+}
+
+#[test]
+fn test_path_merge() {
+    let mut a = Path::builder();
+    a.move_to(point(0.0, 0.0));
+    a.line_to(point(1.0, 0.0));
+    a.close();
+    let a = a.build();
+
+    // Doesn't start with a MoveTo.
+    let mut b = Path::builder();
+    b.line_to(point(2.0, 0.0));
+    b.quadratic_bezier_to(point(3.0, 0.0), point(3.0, 1.0));
+    let b = b.build();
+
+    let merged = a.merge(&b);
+    let mut it = merged.iter();
+    assert_eq!(it.next(), Some(PathEvent::MoveTo(point(0.0, 0.0))));
+    assert_eq!(it.next(), Some(PathEvent::LineTo(point(1.0, 0.0))));
+    assert_eq!(it.next(), Some(PathEvent::Close));
+    assert_eq!(it.next(), Some(PathEvent::MoveTo(point(0.0, 0.0))));
+    assert_eq!(it.next(), Some(PathEvent::LineTo(point(2.0, 0.0))));
+    assert_eq!(it.next(), Some(PathEvent::QuadraticTo(point(3.0, 0.0), point(3.0, 1.0))));
+    assert_eq!(it.next(), None);
+
+    // Continue building after the existing content.
+    let mut builder = merged.into_builder();
+    assert_eq!(builder.current_position(), point(3.0, 1.0));
+    builder.line_to(point(4.0, 1.0));
+    builder.close();
+    assert_eq!(builder.current_position(), point(0.0, 0.0));
+    let path = builder.build();
+    assert_eq!(path.verbs().len(), 8);
+
+    // Reuse the allocations of a path.
+    let mut path = path;
+    path.clear();
+    let mut builder = path.into_builder();
+    builder.move_to(point(5.0, 5.0));
+    let path = builder.build();
+    assert_eq!(path.points(), &[point(5.0, 5.0)]);
+}
+
+#[cfg(feature = "serialization")]
+#[test]
+fn test_path_serialization() {
+    let mut p = Path::builder();
+    p.move_to(point(1.0, 0.0));
+    p.quadratic_bezier_to(point(2.0, 0.0), point(2.0, 1.0));
+    p.close();
+    let path = p.build();
+
+    let json = ::serde_json::to_string(&path).unwrap();
+    let deserialized: Path = ::serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized.points(), path.points());
+    assert_eq!(deserialized.verbs(), path.verbs());
+
+    // The quadratic curve is missing its endpoint.
+    let mut malformed: ::serde_json::Value = ::serde_json::from_str(&json).unwrap();
+    malformed["points"].as_array_mut().unwrap().pop();
+    assert!(::serde_json::from_value::<Path>(malformed).is_err());
+
+    // A point without a verb.
+    let mut malformed: ::serde_json::Value = ::serde_json::from_str(&json).unwrap();
+    malformed["verbs"].as_array_mut().unwrap().remove(1);
+    assert!(::serde_json::from_value::<Path>(malformed).is_err());
+}
+
+#[test]
+fn test_path_reversed() {
+    let mut p = Path::builder();
+    p.move_to(point(0.0, 0.0));
+    p.line_to(point(1.0, 0.0));
+    p.quadratic_bezier_to(point(2.0, 0.0), point(2.0, 1.0));
+    p.cubic_bezier_to(point(3.0, 0.0), point(3.0, 1.0), point(3.0, 2.0));
+    p.close();
+    p.move_to(point(10.0, 0.0));
+    p.line_to(point(11.0, 0.0));
+    p.line_to(point(12.0, 1.0));
+    let path = p.build();
+
+    let reversed = path.reversed();
+    let mut it = reversed.iter();
+    assert_eq!(it.next(), Some(PathEvent::MoveTo(point(3.0, 2.0))));
+    assert_eq!(
+        it.next(),
+        Some(PathEvent::CubicTo(point(3.0, 1.0), point(3.0, 0.0), point(2.0, 1.0)))
+    );
+    assert_eq!(it.next(), Some(PathEvent::QuadraticTo(point(2.0, 0.0), point(1.0, 0.0))));
+    assert_eq!(it.next(), Some(PathEvent::LineTo(point(0.0, 0.0))));
+    assert_eq!(it.next(), Some(PathEvent::Close));
+    assert_eq!(it.next(), Some(PathEvent::MoveTo(point(12.0, 1.0))));
+    assert_eq!(it.next(), Some(PathEvent::LineTo(point(11.0, 0.0))));
+    assert_eq!(it.next(), Some(PathEvent::LineTo(point(10.0, 0.0))));
+    assert_eq!(it.next(), None);
+
+    // Reversing twice gives back the original path.
+    let twice = reversed.reversed();
+    assert_eq!(twice.points(), path.points());
+    assert_eq!(twice.verbs(), path.verbs());
+}
+
 /// Builder for flattened paths
 pub type FlattenedPathBuilder = SvgPathBuilder<FlatteningBuilder<Builder>>;
 /// FlattenedPathBuilder constructor.
@@ -524,3 +1298,116 @@ fn test_path_builder_close_empty() {
     let _ = builder.build();
 }
 */
+
+#[test]
+fn test_split_at_distance() {
+    let mut builder = Path::builder();
+    builder.move_to(point(0.0, 0.0));
+    builder.line_to(point(10.0, 0.0));
+    builder.quadratic_bezier_to(point(20.0, 0.0), point(30.0, 0.0));
+    builder.line_to(point(30.0, 10.0));
+    builder.close();
+    builder.move_to(point(100.0, 0.0));
+    builder.line_to(point(110.0, 0.0));
+    let path = builder.build();
+
+    let (a, b) = path.split_at_distance(20.0, 0.01);
+    let mut it = a.iter();
+    assert_eq!(it.next(), Some(PathEvent::MoveTo(point(0.0, 0.0))));
+    assert_eq!(it.next(), Some(PathEvent::LineTo(point(10.0, 0.0))));
+    match it.next() {
+        Some(PathEvent::QuadraticTo(_, to)) => { assert!((to - point(20.0, 0.0)).length() < 0.01); }
+        evt => { panic!("unexpected event {:?}", evt); }
+    }
+    assert_eq!(it.next(), None);
+
+    // The rest of the closed sub-path ends with a line back to its start.
+    let events: Vec<PathEvent> = b.iter().collect();
+    assert_eq!(events.len(), 6);
+    assert_eq!(events[2], PathEvent::LineTo(point(30.0, 10.0)));
+    assert_eq!(events[3], PathEvent::LineTo(point(0.0, 0.0)));
+    assert_eq!(events[4], PathEvent::MoveTo(point(100.0, 0.0)));
+
+    let (a, b) = path.split_at_distance(1000.0, 0.01);
+    assert_eq!(a.verbs().len(), path.verbs().len());
+    assert!(b.verbs().is_empty());
+}
+
+#[test]
+fn test_signed_area() {
+    let mut builder = Path::builder();
+    // Clockwise on screen.
+    builder.move_to(point(0.0, 0.0));
+    builder.line_to(point(10.0, 0.0));
+    builder.line_to(point(10.0, 10.0));
+    builder.line_to(point(0.0, 10.0));
+    builder.close();
+    // A counter-clockwise hole, not explicitly closed.
+    builder.move_to(point(2.0, 2.0));
+    builder.line_to(point(2.0, 4.0));
+    builder.line_to(point(4.0, 4.0));
+    builder.line_to(point(4.0, 2.0));
+    // Degenerate.
+    builder.move_to(point(20.0, 0.0));
+    builder.line_to(point(30.0, 0.0));
+    let path = builder.build();
+
+    assert_eq!(path.sub_path_signed_areas(0.1), vec![100.0, -4.0, 0.0]);
+    assert_eq!(path.signed_area(0.1), 96.0);
+    assert_eq!(
+        path.sub_path_windings(0.1),
+        vec![Some(Winding::Clockwise), Some(Winding::CounterClockwise), None]
+    );
+    assert_eq!(path.reversed().signed_area(0.1), -96.0);
+}
+
+#[test]
+fn test_closest_point() {
+    let mut builder = Path::builder();
+    builder.move_to(point(0.0, 0.0));
+    builder.line_to(point(10.0, 0.0));
+    builder.quadratic_bezier_to(point(20.0, 0.0), point(20.0, 10.0));
+    builder.close();
+    let path = builder.build();
+
+    let closest = path.closest_point(point(5.0, 1.0)).unwrap();
+    assert_eq!(closest.position, point(5.0, 0.0));
+    assert_eq!(closest.distance, 1.0);
+    assert_eq!(closest.event, 1);
+    assert_eq!(closest.t, 0.5);
+
+    // On the closing edge.
+    let closest = path.closest_point(point(10.0, 6.0)).unwrap();
+    assert_eq!(closest.event, 3);
+    assert!((closest.position - point(10.4, 5.2)).length() < 1e-4);
+
+    assert!(Path::new().closest_point(point(0.0, 0.0)).is_none());
+}
+
+#[test]
+fn test_svg_builder_smooth_curves() {
+    use path_builder::SvgBuilder;
+
+    let mut p = Path::builder().with_svg();
+    p.move_to(point(0.0, 0.0));
+    p.cubic_bezier_to(point(0.0, 1.0), point(1.0, 2.0), point(2.0, 2.0));
+    // Reflects the previous control point.
+    p.smooth_relative_cubic_bezier_to(vec2(2.0, -1.0), vec2(2.0, 0.0));
+    // The previous curve is cubic, so the control point is the current position.
+    p.smooth_quadratic_bezier_to(point(6.0, 2.0));
+    p.smooth_relative_quadratic_bezier_to(vec2(2.0, 0.0));
+    p.relative_line_to(vec2(0.0, 1.0));
+    // No reflection after a line.
+    p.smooth_cubic_bezier_to(point(9.0, 3.0), point(10.0, 3.0));
+
+    let path = p.build();
+    let mut it = path.iter();
+    assert_eq!(it.next(), Some(PathEvent::MoveTo(point(0.0, 0.0))));
+    assert_eq!(it.next(), Some(PathEvent::CubicTo(point(0.0, 1.0), point(1.0, 2.0), point(2.0, 2.0))));
+    assert_eq!(it.next(), Some(PathEvent::CubicTo(point(3.0, 2.0), point(4.0, 1.0), point(4.0, 2.0))));
+    assert_eq!(it.next(), Some(PathEvent::QuadraticTo(point(4.0, 2.0), point(6.0, 2.0))));
+    assert_eq!(it.next(), Some(PathEvent::QuadraticTo(point(8.0, 2.0), point(8.0, 2.0))));
+    assert_eq!(it.next(), Some(PathEvent::LineTo(point(8.0, 3.0))));
+    assert_eq!(it.next(), Some(PathEvent::CubicTo(point(8.0, 3.0), point(9.0, 3.0), point(10.0, 3.0))));
+    assert_eq!(it.next(), None);
+}
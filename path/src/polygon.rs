@@ -0,0 +1,116 @@
+use path::{Path, Winding};
+use path_builder::BaseBuilder;
+
+use core::FillRule;
+use core::math::*;
+
+/// Returns a path containing polygons with holes, for example the polygons of a GeoJSON
+/// `MultiPolygon`.
+///
+/// See `build_polygons`.
+pub fn polygons_to_path<Polygon, Ring>(polygons: &[Polygon], fill_rule: FillRule) -> Path
+where
+    Polygon: AsRef<[Ring]>,
+    Ring: AsRef<[Point]>,
+{
+    let mut builder = Path::builder();
+    build_polygons(polygons, fill_rule, &mut builder);
+    return builder.build();
+}
+
+/// Builds polygons with holes into a path builder, so that they can be tessellated with a
+/// single fill.
+///
+/// The first ring of each polygon is its exterior and the following ones are its holes.
+/// Imported data rarely agrees on the direction of the rings, so with `FillRule::NonZero`
+/// the rings are reversed where needed for the exteriors to wind clockwise and the holes
+/// counter-clockwise (on screen, with the y axis pointing down), which makes the holes
+/// cancel out the exterior they are in. With `FillRule::EvenOdd` the direction of the rings
+/// doesn't matter and is kept.
+///
+/// A ring can repeat its first point at the end, as in GeoJSON. Rings that don't enclose
+/// any area are skipped.
+pub fn build_polygons<Polygon, Ring, Builder>(polygons: &[Polygon], fill_rule: FillRule, output: &mut Builder)
+where
+    Polygon: AsRef<[Ring]>,
+    Ring: AsRef<[Point]>,
+    Builder: BaseBuilder,
+{
+    for polygon in polygons {
+        for (i, ring) in polygon.as_ref().iter().enumerate() {
+            let mut points = ring.as_ref();
+            if points.len() > 1 && points.first() == points.last() {
+                points = &points[..points.len() - 1];
+            }
+
+            let winding = match Winding::from_signed_area(ring_signed_area(points)) {
+                Some(winding) => winding,
+                None => { continue; }
+            };
+            let expected = if i == 0 { Winding::Clockwise } else { Winding::CounterClockwise };
+            let reverse = fill_rule == FillRule::NonZero && winding != expected;
+
+            if reverse {
+                add_ring(points.iter().rev(), output);
+            } else {
+                add_ring(points.iter(), output);
+            }
+        }
+    }
+}
+
+fn add_ring<'l, Iter, Builder>(mut points: Iter, output: &mut Builder)
+where
+    Iter: Iterator<Item = &'l Point>,
+    Builder: BaseBuilder,
+{
+    if let Some(&first) = points.next() {
+        output.move_to(first);
+        for &p in points {
+            output.line_to(p);
+        }
+        output.close();
+    }
+}
+
+fn ring_signed_area(points: &[Point]) -> f32 {
+    let mut area = 0.0;
+    for i in 0..points.len() {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        area += (a.x * b.y - b.x * a.y) * 0.5;
+    }
+
+    return area;
+}
+
+#[test]
+fn test_polygons_with_holes() {
+    // The exterior winds counter-clockwise and the hole clockwise, the opposite of what is
+    // expected, and the rings repeat their first point.
+    let exterior = vec![point(0.0, 0.0), point(0.0, 10.0), point(10.0, 10.0), point(10.0, 0.0), point(0.0, 0.0)];
+    let hole = vec![point(2.0, 2.0), point(8.0, 2.0), point(8.0, 8.0), point(2.0, 8.0), point(2.0, 2.0)];
+    let degenerate = vec![point(20.0, 0.0), point(30.0, 0.0)];
+    let island = vec![point(4.0, 4.0), point(6.0, 4.0), point(6.0, 6.0), point(4.0, 6.0)];
+    let polygons = vec![vec![exterior, hole, degenerate], vec![island]];
+
+    let path = polygons_to_path(&polygons, FillRule::NonZero);
+    assert_eq!(path.points().len(), 12);
+    assert_eq!(
+        path.sub_path_windings(0.1),
+        vec![Some(Winding::Clockwise), Some(Winding::CounterClockwise), Some(Winding::Clockwise)]
+    );
+    assert!(path.contains_point(point(1.0, 1.0), FillRule::NonZero, 0.1));
+    assert!(!path.contains_point(point(3.0, 3.0), FillRule::NonZero, 0.1));
+    assert!(path.contains_point(point(5.0, 5.0), FillRule::NonZero, 0.1));
+    assert!(!path.contains_point(point(25.0, 0.0), FillRule::NonZero, 0.1));
+
+    // The rings keep their direction with the even-odd rule.
+    let path = polygons_to_path(&polygons, FillRule::EvenOdd);
+    assert_eq!(
+        path.sub_path_windings(0.1),
+        vec![Some(Winding::CounterClockwise), Some(Winding::Clockwise), Some(Winding::Clockwise)]
+    );
+    assert!(!path.contains_point(point(3.0, 3.0), FillRule::EvenOdd, 0.1));
+    assert!(path.contains_point(point(5.0, 5.0), FillRule::EvenOdd, 0.1));
+}
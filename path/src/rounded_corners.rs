@@ -0,0 +1,194 @@
+use path::Path;
+use path_builder::{BaseBuilder, PathBuilder};
+
+use core::math::*;
+
+use std::f32::consts::PI;
+
+/// The shape of the curves replacing the corners, see `rounded_polygon`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Fillet {
+    /// Circular arcs, approximated with cubic bézier curves.
+    Arc,
+    /// Quadratic bézier curves using the corner as control point.
+    Quadratic,
+}
+
+/// Returns a path following a polygon or polyline, with its corners rounded.
+///
+/// See `build_rounded_polygon`.
+pub fn rounded_polygon(points: &[Point], closed: bool, radius: f32, fillet: Fillet) -> Path {
+    let mut builder = Path::builder();
+    build_rounded_polygon(points, closed, fillet, &|_| radius, &mut builder);
+    return builder.build();
+}
+
+/// Like `rounded_polygon` with a radius per corner.
+///
+/// `radii` must have as many elements as `points`. The radius of the end points of open
+/// polylines is ignored.
+pub fn rounded_polygon_with_radii(points: &[Point], closed: bool, radii: &[f32], fillet: Fillet) -> Path {
+    assert_eq!(points.len(), radii.len());
+    let mut builder = Path::builder();
+    build_rounded_polygon(points, closed, fillet, &|idx| radii[idx], &mut builder);
+    return builder.build();
+}
+
+/// Builds a polygon or polyline with its corners rounded into a path builder.
+///
+/// The radius of each corner is given by its index in `points`. The rounded part of a
+/// corner never extends past the middle of its edges, so that neighbouring corners don't
+/// overlap: at acute angles and along short edges the radius is reduced to fit.
+pub fn build_rounded_polygon<Builder: PathBuilder>(
+    points: &[Point],
+    closed: bool,
+    fillet: Fillet,
+    radius: &Fn(usize) -> f32,
+    output: &mut Builder,
+) {
+    let n = points.len();
+    if n == 0 {
+        return;
+    }
+    if n < 3 {
+        output.move_to(points[0]);
+        for p in &points[1..] {
+            output.line_to(*p);
+        }
+        if closed {
+            output.close();
+        }
+        return;
+    }
+
+    let corner = |idx: usize| {
+        let prev = points[(idx + n - 1) % n];
+        let next = points[(idx + 1) % n];
+        Corner::new(prev, points[idx], next, radius(idx))
+    };
+
+    if closed {
+        let first = corner(0);
+        output.move_to(first.to);
+        for idx in 1..n {
+            corner(idx).build(fillet, output);
+        }
+        first.build(fillet, output);
+        output.close();
+    } else {
+        output.move_to(points[0]);
+        for idx in 1..(n - 1) {
+            corner(idx).build(fillet, output);
+        }
+        output.line_to(points[n - 1]);
+    }
+}
+
+// A rounded corner, from a point on the incoming edge to a point on the outgoing one.
+struct Corner {
+    from: Point,
+    position: Point,
+    to: Point,
+    // Length of the handles of the cubic approximating the arc.
+    handle: f32,
+    dir_in: Vec2,
+    dir_out: Vec2,
+}
+
+impl Corner {
+    fn new(prev: Point, position: Point, next: Point, radius: f32) -> Self {
+        let no_rounding = Corner {
+            from: position,
+            position: position,
+            to: position,
+            handle: 0.0,
+            dir_in: vec2(0.0, 0.0),
+            dir_out: vec2(0.0, 0.0),
+        };
+
+        let len_in = (position - prev).length();
+        let len_out = (next - position).length();
+        if radius <= 0.0 || len_in == 0.0 || len_out == 0.0 {
+            return no_rounding;
+        }
+
+        let dir_in = (position - prev) / len_in;
+        let dir_out = (next - position) / len_out;
+        // The angle the path turns by at the corner.
+        let turn = dir_in.dot(dir_out).max(-1.0).min(1.0).acos();
+        if turn < 1e-4 || turn > PI - 1e-4 {
+            return no_rounding;
+        }
+
+        let half_tan = (turn * 0.5).tan();
+        let distance = (radius * half_tan).min(len_in * 0.5).min(len_out * 0.5);
+        let radius = distance / half_tan;
+
+        return Corner {
+            from: position - dir_in * distance,
+            position: position,
+            to: position + dir_out * distance,
+            handle: radius * 4.0 / 3.0 * (turn * 0.25).tan(),
+            dir_in: dir_in,
+            dir_out: dir_out,
+        };
+    }
+
+    fn build<Builder: PathBuilder>(&self, fillet: Fillet, output: &mut Builder) {
+        output.line_to(self.from);
+        if self.from == self.to {
+            return;
+        }
+
+        match fillet {
+            Fillet::Arc => {
+                output.cubic_bezier_to(
+                    self.from + self.dir_in * self.handle,
+                    self.to - self.dir_out * self.handle,
+                    self.to,
+                );
+            }
+            Fillet::Quadratic => {
+                output.quadratic_bezier_to(self.position, self.to);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+use core::PathEvent;
+
+#[test]
+fn test_rounded_square() {
+    let square = [point(0.0, 0.0), point(10.0, 0.0), point(10.0, 10.0), point(0.0, 10.0)];
+    let path = rounded_polygon(&square, true, 2.0, Fillet::Quadratic);
+    let events: Vec<PathEvent> = path.iter().collect();
+    let near = |a: Point, b: Point| (a - b).length() < 1e-5;
+
+    // Four lines and four curves.
+    assert_eq!(events.len(), 10);
+    match (events[0], events[1], events[2]) {
+        (PathEvent::MoveTo(a), PathEvent::LineTo(b), PathEvent::QuadraticTo(ctrl, c)) => {
+            assert!(near(a, point(2.0, 0.0)));
+            assert!(near(b, point(8.0, 0.0)));
+            assert_eq!(ctrl, point(10.0, 0.0));
+            assert!(near(c, point(10.0, 2.0)));
+        }
+        evts => { panic!("unexpected events {:?}", evts); }
+    }
+    assert_eq!(events.last(), Some(&PathEvent::Close));
+
+    // The arcs end on the edges of the square.
+    let path = rounded_polygon(&square, true, 2.0, Fillet::Arc);
+    for evt in path.iter() {
+        if let PathEvent::CubicTo(_, _, to) = evt {
+            assert!(to.x == 0.0 || to.x == 10.0 || to.y == 0.0 || to.y == 10.0);
+        }
+    }
+
+    // Radii larger than what fits are reduced to half of the edges.
+    let path = rounded_polygon_with_radii(&square, false, &[0.0, 100.0, 0.0, 0.0], Fillet::Quadratic);
+    let events: Vec<PathEvent> = path.iter().collect();
+    assert_eq!(events[1], PathEvent::LineTo(point(5.0, 0.0)));
+    assert_eq!(events[2], PathEvent::QuadraticTo(point(10.0, 0.0), point(10.0, 5.0)));
+}
@@ -177,16 +177,47 @@ pub trait PolygonBuilder {
 }
 
 /// Implements the Svg building interface on top of a PathBuilder.
+///
+/// Relative commands are resolved against the current position, and the shorthand curves
+/// reflect the previous control point with SVG's semantics: `S` only reflects the second
+/// control point of a preceding cubic curve and `T` the control point of a preceding
+/// quadratic curve, otherwise the current position is used as the control point.
 pub struct SvgPathBuilder<Builder: PathBuilder> {
     builder: Builder,
-    last_ctrl: Point,
+    last_ctrl: LastCtrl,
+}
+
+// The control point the smooth curves reflect.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum LastCtrl {
+    None,
+    Quadratic(Point),
+    Cubic(Point),
 }
 
 impl<Builder: PathBuilder> SvgPathBuilder<Builder> {
     pub fn new(builder: Builder) -> SvgPathBuilder<Builder> {
         SvgPathBuilder {
             builder: builder,
-            last_ctrl: point(0.0, 0.0),
+            last_ctrl: LastCtrl::None,
+        }
+    }
+
+    // The first control point of a smooth cubic curve.
+    fn smooth_cubic_ctrl(&self) -> Point {
+        let current = self.builder.current_position();
+        match self.last_ctrl {
+            LastCtrl::Cubic(ctrl) => current + (current - ctrl),
+            _ => current,
+        }
+    }
+
+    // The control point of a smooth quadratic curve.
+    fn smooth_quadratic_ctrl(&self) -> Point {
+        let current = self.builder.current_position();
+        match self.last_ctrl {
+            LastCtrl::Quadratic(ctrl) => current + (current - ctrl),
+            _ => current,
         }
     }
 }
@@ -195,17 +226,17 @@ impl<Builder: PathBuilder> BaseBuilder for SvgPathBuilder<Builder> {
     type PathType = Builder::PathType;
 
     fn move_to(&mut self, to: Point) {
-        self.last_ctrl = to;
+        self.last_ctrl = LastCtrl::None;
         self.builder.move_to(to);
     }
 
     fn line_to(&mut self, to: Point) {
-        self.last_ctrl = self.current_position();
+        self.last_ctrl = LastCtrl::None;
         self.builder.line_to(to);
     }
 
     fn close(&mut self) {
-        self.last_ctrl = point(0.0, 0.0);
+        self.last_ctrl = LastCtrl::None;
         self.builder.close()
     }
 
@@ -218,12 +249,12 @@ impl<Builder: PathBuilder> BaseBuilder for SvgPathBuilder<Builder> {
 
 impl<Builder: PathBuilder> PathBuilder for SvgPathBuilder<Builder> {
     fn quadratic_bezier_to(&mut self, ctrl: Point, to: Point) {
-        self.last_ctrl = ctrl;
+        self.last_ctrl = LastCtrl::Quadratic(ctrl);
         self.builder.quadratic_bezier_to(ctrl, to);
     }
 
     fn cubic_bezier_to(&mut self, ctrl1: Point, ctrl2: Point, to: Point) {
-        self.last_ctrl = ctrl2;
+        self.last_ctrl = LastCtrl::Cubic(ctrl2);
         self.builder.cubic_bezier_to(ctrl1, ctrl2, to);
     }
 }
@@ -250,24 +281,22 @@ impl<Builder: PathBuilder> SvgBuilder for SvgPathBuilder<Builder> {
     }
 
     fn smooth_cubic_bezier_to(&mut self, ctrl2: Point, to: Point) {
-        let ctrl = self.builder.current_position() +
-            (self.builder.current_position() - self.last_ctrl);
+        let ctrl = self.smooth_cubic_ctrl();
         self.cubic_bezier_to(ctrl, ctrl2, to);
     }
 
     fn smooth_relative_cubic_bezier_to(&mut self, ctrl2: Vec2, to: Vec2) {
-        let ctrl = self.builder.current_position() - self.last_ctrl;
+        let ctrl = self.smooth_cubic_ctrl() - self.builder.current_position();
         self.relative_cubic_bezier_to(ctrl, ctrl2, to);
     }
 
     fn smooth_quadratic_bezier_to(&mut self, to: Point) {
-        let ctrl = self.builder.current_position() +
-            (self.builder.current_position() - self.last_ctrl);
+        let ctrl = self.smooth_quadratic_ctrl();
         self.quadratic_bezier_to(ctrl, to);
     }
 
     fn smooth_relative_quadratic_bezier_to(&mut self, to: Vec2) {
-        let ctrl = self.builder.current_position() - self.last_ctrl;
+        let ctrl = self.smooth_quadratic_ctrl() - self.builder.current_position();
         self.relative_quadratic_bezier_to(ctrl, to);
     }
 
@@ -292,7 +321,7 @@ impl<Builder: PathBuilder> SvgBuilder for SvgPathBuilder<Builder> {
     }
 
     fn arc_to(&mut self, radii: Vec2, x_rotation: Radians<f32>, flags: ArcFlags, to: Point) {
-        SvgArc {
+        let arc = SvgArc {
             from: self.current_position(),
             to: to,
             radii: radii,
@@ -301,9 +330,22 @@ impl<Builder: PathBuilder> SvgBuilder for SvgPathBuilder<Builder> {
                 large_arc: flags.large_arc,
                 sweep: flags.sweep,
             },
-        }.to_quadratic_beziers(&mut|ctrl, to|{
-            self.quadratic_bezier_to(ctrl, to);
-        })
+        };
+
+        // See https://www.w3.org/TR/SVG/implnote.html#ArcOutOfRangeParameters
+        if arc.from == arc.to {
+            return;
+        }
+        if arc.is_straight_line() {
+            self.line_to(to);
+            return;
+        }
+
+        arc.to_cubic_beziers(&mut|ctrl1, ctrl2, to|{
+            self.cubic_bezier_to(ctrl1, ctrl2, to);
+        });
+        // Arcs aren't curves the shorthand commands can continue.
+        self.last_ctrl = LastCtrl::None;
     }
 
     fn relative_arc_to(
@@ -1,9 +1,10 @@
 use std::iter;
 
 use core::math::*;
-use core::{PathEvent, SvgEvent, FlattenedEvent, PathState};
+use core::{PathEvent, SvgEvent, FlattenedEvent, PathState, ArcFlags};
 use bezier::{QuadraticBezierSegment, QuadraticFlatteningIter};
-use bezier::{CubicBezierSegment, CubicFlatteningIter};
+use bezier::{CubicBezierSegment, CubicFlatteningIter, SvgArc, LineSegment};
+use bezier;
 
 /// Convenience for algorithms which prefer to iterate over segments directly rather than
 /// path events.
@@ -14,6 +15,112 @@ pub enum Segment {
     CubicBezier(Point, Point, Point, Point),
 }
 
+impl Segment {
+    pub fn from(&self) -> Point {
+        match *self {
+            Segment::Line(from, _) => from,
+            Segment::QuadraticBezier(from, _, _) => from,
+            Segment::CubicBezier(from, _, _, _) => from,
+        }
+    }
+
+    pub fn to(&self) -> Point {
+        match *self {
+            Segment::Line(_, to) => to,
+            Segment::QuadraticBezier(_, _, to) => to,
+            Segment::CubicBezier(_, _, _, to) => to,
+        }
+    }
+
+    /// Splits the segment at t (expecting t between 0 and 1).
+    pub fn split(&self, t: f32) -> (Segment, Segment) {
+        match *self {
+            Segment::Line(from, to) => {
+                let (a, b) = LineSegment { from: from, to: to }.split(t);
+                (Segment::Line(a.from, a.to), Segment::Line(b.from, b.to))
+            }
+            Segment::QuadraticBezier(from, ctrl, to) => {
+                let (a, b) = QuadraticBezierSegment { from: from, ctrl: ctrl, to: to }.split(t);
+                (
+                    Segment::QuadraticBezier(a.from, a.ctrl, a.to),
+                    Segment::QuadraticBezier(b.from, b.ctrl, b.to),
+                )
+            }
+            Segment::CubicBezier(from, ctrl1, ctrl2, to) => {
+                let (a, b) = CubicBezierSegment { from: from, ctrl1: ctrl1, ctrl2: ctrl2, to: to }.split(t);
+                (
+                    Segment::CubicBezier(a.from, a.ctrl1, a.ctrl2, a.to),
+                    Segment::CubicBezier(b.from, b.ctrl1, b.ctrl2, b.to),
+                )
+            }
+        }
+    }
+
+    /// The parameter of the point of the segment that is the closest to a given point.
+    pub fn closest_point_t(&self, p: Point) -> f32 {
+        match *self {
+            Segment::Line(from, to) => LineSegment { from: from, to: to }.closest_point_t(p),
+            Segment::QuadraticBezier(from, ctrl, to) => {
+                QuadraticBezierSegment { from: from, ctrl: ctrl, to: to }.closest_point_t(p)
+            }
+            Segment::CubicBezier(from, ctrl1, ctrl2, to) => {
+                CubicBezierSegment { from: from, ctrl1: ctrl1, ctrl2: ctrl2, to: to }.closest_point_t(p)
+            }
+        }
+    }
+
+    /// Position at t (expecting t between 0 and 1).
+    pub fn sample(&self, t: f32) -> Point {
+        match *self {
+            Segment::Line(from, to) => from.lerp(to, t),
+            Segment::QuadraticBezier(from, ctrl, to) => {
+                QuadraticBezierSegment { from: from, ctrl: ctrl, to: to }.sample(t)
+            }
+            Segment::CubicBezier(from, ctrl1, ctrl2, to) => {
+                CubicBezierSegment { from: from, ctrl1: ctrl1, ctrl2: ctrl2, to: to }.sample(t)
+            }
+        }
+    }
+
+    /// Length of the segment, approximated within the tolerance for curves.
+    pub fn length(&self, tolerance: f32) -> f32 {
+        match *self {
+            Segment::Line(from, to) => (to - from).length(),
+            Segment::QuadraticBezier(from, ctrl, to) => {
+                QuadraticBezierSegment { from: from, ctrl: ctrl, to: to }.compute_length(tolerance)
+            }
+            Segment::CubicBezier(from, ctrl1, ctrl2, to) => {
+                CubicBezierSegment { from: from, ctrl1: ctrl1, ctrl2: ctrl2, to: to }.compute_length(tolerance)
+            }
+        }
+    }
+
+    /// The parameter at a given distance along the segment.
+    ///
+    /// For curves, see `ArcLengthParametrization`.
+    pub fn parameter_at_distance(&self, distance: f32, tolerance: f32) -> f32 {
+        match *self {
+            Segment::Line(from, to) => {
+                let length = (to - from).length();
+                if length <= 0.0 {
+                    return 0.0;
+                }
+                (distance / length).max(0.0).min(1.0)
+            }
+            Segment::QuadraticBezier(from, ctrl, to) => {
+                QuadraticBezierSegment { from: from, ctrl: ctrl, to: to }
+                    .arc_length_parametrization(tolerance)
+                    .parameter_at_length(distance)
+            }
+            Segment::CubicBezier(from, ctrl1, ctrl2, to) => {
+                CubicBezierSegment { from: from, ctrl1: ctrl1, ctrl2: ctrl2, to: to }
+                    .arc_length_parametrization(tolerance)
+                    .parameter_at_length(distance)
+            }
+        }
+    }
+}
+
 /// An extension to the common Iterator interface, that adds information which is useful when
 /// chaining path-specific iterators.
 pub trait PathIterator: Iterator<Item = PathEvent> + Sized {
@@ -28,6 +135,14 @@ pub trait PathIterator: Iterator<Item = PathEvent> + Sized {
 
     /// Returns an iterator of SVG events.
     fn svg_iter(self) -> iter::Map<Self, fn(PathEvent) -> SvgEvent> { self.map(path_to_svg_event) }
+
+    /// Returns an iterator applying a transform to the events.
+    fn transformed(self, mat: &Transform2D) -> TransformedPathIter<Self> {
+        TransformedPathIter::new(mat, self)
+    }
+
+    /// Returns an iterator of segments.
+    fn segments(self) -> SegmentIterator<Self> { SegmentIterator::new(self) }
 }
 
 /// An extension to the common Iterator interface, that adds information which is useful when
@@ -62,6 +177,15 @@ pub trait FlattenedIterator: Iterator<Item = FlattenedEvent> + Sized {
     fn svg_iter(self) -> iter::Map<Self, fn(FlattenedEvent) -> SvgEvent> {
         self.map(flattened_to_svg_event)
     }
+
+    /// Returns an iterator applying a transform to the events.
+    fn transformed(self, mat: &Transform2D) -> TransformedFlattenedIter<Self> {
+        TransformedFlattenedIter::new(mat, self)
+    }
+
+    /// Returns an iterator of the line segments of the path, including the ones closing
+    /// the sub-paths.
+    fn with_line_segments(self) -> LineSegmentIter<Self> { LineSegmentIter::new(self) }
 }
 
 /// Consumes an iterator of path events and yields segments.
@@ -131,19 +255,146 @@ impl<'l, PathIt: 'l + Iterator<Item = PathEvent>> Iterator for SegmentIterator<P
     }
 }
 
+/// An iterator applying a transform to path events.
+pub struct TransformedPathIter<Iter> {
+    it: Iter,
+    transform: Transform2D,
+    state: PathState,
+}
+
+impl<Iter: Iterator<Item = PathEvent>> TransformedPathIter<Iter> {
+    pub fn new(transform: &Transform2D, it: Iter) -> Self {
+        TransformedPathIter {
+            it: it,
+            transform: *transform,
+            state: PathState::new(),
+        }
+    }
+}
+
+impl<Iter> PathIterator for TransformedPathIter<Iter>
+where
+    Iter: Iterator<Item = PathEvent>,
+{
+    fn get_state(&self) -> &PathState { &self.state }
+}
+
+impl<Iter: Iterator<Item = PathEvent>> Iterator for TransformedPathIter<Iter> {
+    type Item = PathEvent;
+    fn next(&mut self) -> Option<PathEvent> {
+        let next = self.it.next().map(|evt| evt.transform(&self.transform));
+        if let Some(evt) = next {
+            self.state.path_event(evt);
+        }
+        return next;
+    }
+}
+
+/// An iterator applying a transform to flattened events.
+pub struct TransformedFlattenedIter<Iter> {
+    it: Iter,
+    transform: Transform2D,
+    state: PathState,
+}
+
+impl<Iter: Iterator<Item = FlattenedEvent>> TransformedFlattenedIter<Iter> {
+    pub fn new(transform: &Transform2D, it: Iter) -> Self {
+        TransformedFlattenedIter {
+            it: it,
+            transform: *transform,
+            state: PathState::new(),
+        }
+    }
+}
+
+impl<Iter> FlattenedIterator for TransformedFlattenedIter<Iter>
+where
+    Iter: Iterator<Item = FlattenedEvent>,
+{
+    fn get_state(&self) -> &PathState { &self.state }
+}
+
+impl<Iter: Iterator<Item = FlattenedEvent>> Iterator for TransformedFlattenedIter<Iter> {
+    type Item = FlattenedEvent;
+    fn next(&mut self) -> Option<FlattenedEvent> {
+        let next = self.it.next().map(|evt| evt.transform(&self.transform));
+        if let Some(evt) = next {
+            self.state.flattened_event(evt);
+        }
+        return next;
+    }
+}
+
+/// Consumes an iterator of flattened events and yields line segments.
+///
+/// Closing a sub-path yields the segment back to its first point, unless it is empty.
+pub struct LineSegmentIter<Iter> {
+    it: Iter,
+    state: PathState,
+}
+
+impl<Iter: Iterator<Item = FlattenedEvent>> LineSegmentIter<Iter> {
+    pub fn new(it: Iter) -> Self {
+        LineSegmentIter {
+            it: it,
+            state: PathState::new(),
+        }
+    }
+}
+
+impl<Iter: Iterator<Item = FlattenedEvent>> Iterator for LineSegmentIter<Iter> {
+    type Item = LineSegment;
+    fn next(&mut self) -> Option<LineSegment> {
+        loop {
+            let from = self.state.current;
+            match self.it.next() {
+                Some(FlattenedEvent::MoveTo(to)) => {
+                    self.state.move_to(to);
+                }
+                Some(FlattenedEvent::LineTo(to)) => {
+                    self.state.line_to(to);
+                    return Some(LineSegment { from: from, to: to });
+                }
+                Some(FlattenedEvent::Close) => {
+                    self.state.close();
+                    if from != self.state.current {
+                        return Some(LineSegment { from: from, to: self.state.current });
+                    }
+                }
+                None => {
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// An iterator that consumes an SvgIterator and yields path events.
+///
+/// Relative and shorthand events are resolved, and arcs are approximated with sequences
+/// of cubic bézier curves.
 pub struct SvgToPathIter<SvgIter> {
     it: SvgIter,
+    state: PathState,
+    // Remaining curves of the arc being approximated, in reverse order.
+    pending: Vec<PathEvent>,
 }
 
 impl<SvgIter> SvgToPathIter<SvgIter> {
-    pub fn new(it: SvgIter) -> Self { SvgToPathIter { it: it } }
+    pub fn new(it: SvgIter) -> Self {
+        SvgToPathIter {
+            it: it,
+            state: PathState::new(),
+            pending: Vec::new(),
+        }
+    }
 }
 
 impl<SvgIter> PathIterator for SvgToPathIter<SvgIter>
 where
     SvgIter: SvgIterator,
 {
-    fn get_state(&self) -> &PathState { self.it.get_state() }
+    fn get_state(&self) -> &PathState { &self.state }
 }
 
 impl<SvgIter> Iterator for SvgToPathIter<SvgIter>
@@ -152,11 +403,71 @@ where
 {
     type Item = PathEvent;
     fn next(&mut self) -> Option<PathEvent> {
-        return match self.it.next() {
-                   Some(svg_evt) => Some(self.get_state().svg_to_path_event(svg_evt)),
-                   None => None,
-               };
+        if self.pending.is_empty() {
+            let svg_evt = match self.it.next() {
+                Some(svg_evt) => svg_evt,
+                None => { return None; }
+            };
+            {
+                let pending = &mut self.pending;
+                svg_to_path_events(&self.state, svg_evt, &mut |evt| { pending.push(evt); });
+                pending.reverse();
+            }
+            // Arcs with identical endpoints are omitted.
+            if self.pending.is_empty() {
+                return self.next();
+            }
+        }
+
+        let evt = self.pending.pop();
+        if let Some(evt) = evt {
+            self.state.path_event(evt);
+        }
+        return evt;
+    }
+}
+
+/// Converts an SVG event into path events, approximating arcs with cubic bézier curves.
+///
+/// Relative and shorthand events are resolved with `state`, the state of the path before
+/// the event. Arcs with identical endpoints produce no event and, as specified by SVG, arcs
+/// with a radius equal to zero produce a line segment.
+pub fn svg_to_path_events<F: FnMut(PathEvent)>(state: &PathState, event: SvgEvent, callback: &mut F) {
+    let (radii, x_rotation, flags, to) = match event {
+        SvgEvent::ArcTo(radii, x_rotation, flags, to) => (radii, x_rotation, flags, to),
+        SvgEvent::RelativeArcTo(radii, x_rotation, flags, to) => {
+            (radii, x_rotation, flags, state.from_relative(to))
+        }
+        _ => {
+            if let Some(evt) = state.svg_to_path_event(event) {
+                callback(evt);
+            }
+            return;
+        }
+    };
+
+    let arc = SvgArc {
+        from: state.current,
+        to: to,
+        radii: radii,
+        x_rotation: x_rotation,
+        flags: bezier::ArcFlags {
+            large_arc: flags.large_arc,
+            sweep: flags.sweep,
+        },
+    };
+
+    if arc.from == arc.to {
+        return;
     }
+    if arc.is_straight_line() {
+        callback(PathEvent::LineTo(to));
+        return;
+    }
+
+    arc.to_cubic_beziers(&mut |ctrl1, ctrl2, to| {
+        callback(PathEvent::CubicTo(ctrl1, ctrl2, to));
+    });
 }
 
 /// An iterator that consumes an PathIterator and yields FlattenedEvents.
@@ -464,3 +775,77 @@ fn test_svg_to_flattened_iter() {
     }
 }
 */
+
+#[test]
+fn test_svg_to_path_iter() {
+    let events = vec![
+        SvgEvent::MoveTo(point(0.0, 0.0)),
+        SvgEvent::RelativeLineTo(vec2(10.0, 0.0)),
+        SvgEvent::ArcTo(
+            vec2(5.0, 5.0),
+            Radians::new(0.0),
+            ArcFlags { large_arc: false, sweep: true },
+            point(20.0, 0.0),
+        ),
+        SvgEvent::RelativeLineTo(vec2(0.0, 10.0)),
+        SvgEvent::Close,
+    ];
+
+    let mut it = PathStateSvgIter::new(events.into_iter()).path_iter();
+    assert_eq!(it.next(), Some(PathEvent::MoveTo(point(0.0, 0.0))));
+    assert_eq!(it.next(), Some(PathEvent::LineTo(point(10.0, 0.0))));
+    match it.next() {
+        Some(PathEvent::CubicTo(_, _, to)) => {
+            assert!((to - point(15.0, -5.0)).length() < 0.001);
+        }
+        evt => { panic!("unexpected event {:?}", evt); }
+    }
+    match it.next() {
+        Some(PathEvent::CubicTo(_, _, to)) => {
+            assert_eq!(to, point(20.0, 0.0));
+        }
+        evt => { panic!("unexpected event {:?}", evt); }
+    }
+    assert_eq!(it.get_state().current, point(20.0, 0.0));
+    assert_eq!(it.next(), Some(PathEvent::LineTo(point(20.0, 10.0))));
+    assert_eq!(it.next(), Some(PathEvent::Close));
+    assert_eq!(it.next(), None);
+}
+
+#[test]
+fn test_svg_to_path_events_arcs() {
+    let mut state = PathState::new();
+    state.move_to(point(0.0, 0.0));
+    let flags = ArcFlags { large_arc: false, sweep: true };
+
+    assert_eq!(state.svg_to_path_event(SvgEvent::ArcTo(vec2(5.0, 5.0), Radians::new(0.0), flags, point(10.0, 0.0))), None);
+
+    let mut events = Vec::new();
+    svg_to_path_events(&state, SvgEvent::RelativeArcTo(vec2(0.0, 5.0), Radians::new(0.0), flags, vec2(10.0, 0.0)), &mut |evt| { events.push(evt); });
+    assert_eq!(events, vec![PathEvent::LineTo(point(10.0, 0.0))]);
+
+    events.clear();
+    svg_to_path_events(&state, SvgEvent::ArcTo(vec2(5.0, 5.0), Radians::new(0.0), flags, point(0.0, 0.0)), &mut |evt| { events.push(evt); });
+    assert!(events.is_empty());
+}
+
+#[test]
+fn test_transformed_line_segments() {
+    let points = &[
+        point(0.0, 0.0),
+        point(1.0, 0.0),
+        point(1.0, 1.0),
+    ];
+
+    let transform = Transform2D::create_translation(10.0, 0.0);
+    let segments: Vec<LineSegment> = TransformedFlattenedIter::new(
+        &transform,
+        FromPolyline::closed(points.iter().cloned()),
+    ).with_line_segments().collect();
+
+    assert_eq!(segments, vec![
+        LineSegment { from: point(10.0, 0.0), to: point(11.0, 0.0) },
+        LineSegment { from: point(11.0, 0.0), to: point(11.0, 1.0) },
+        LineSegment { from: point(11.0, 1.0), to: point(10.0, 0.0) },
+    ]);
+}
@@ -0,0 +1,84 @@
+//! `#[derive(GpuBlock)]` for the custom primitives of lyon_renderer.
+//!
+//! ```ignore
+//! #[macro_use]
+//! extern crate lyon_renderer_derive;
+//! extern crate lyon_renderer;
+//!
+//! #[repr(C)]
+//! #[derive(Copy, Clone, GpuBlock)]
+//! struct MyPrimitive {
+//!     color: [f32; 4],
+//!     offset: Point,
+//!     z_index: f32,
+//!     transform: i32,
+//! }
+//! ```
+//!
+//! The fields must implement `lyon_renderer::gpu_block::GpuField`, which is the case of
+//! `f32`, `i32`, `u32`, `[f32; 2]`, `Point`, `Vec2`, `[f32; 4]`, `[[f32; 4]; 4]` and
+//! `Transform3D`.
+//!
+//! `GpuBlock` is an unsafe trait since its words are read straight from the memory of the
+//! struct, so the derive rejects structs that are not `#[repr(C)]`, and the generated code
+//! fails to compile if the size of the struct doesn't match the sum of the sizes of its
+//! fields.
+
+extern crate proc_macro;
+extern crate syn;
+#[macro_use]
+extern crate quote;
+
+use proc_macro::TokenStream;
+
+#[proc_macro_derive(GpuBlock)]
+pub fn derive_gpu_block(input: TokenStream) -> TokenStream {
+    let source = input.to_string();
+    let ast = syn::parse_derive_input(&source).unwrap();
+    let expanded = impl_gpu_block(&ast);
+    return expanded.parse().unwrap();
+}
+
+fn impl_gpu_block(ast: &syn::DeriveInput) -> quote::Tokens {
+    let name = &ast.ident;
+    let fields = match ast.body {
+        syn::Body::Struct(syn::VariantData::Struct(ref fields)) => fields,
+        _ => panic!("#[derive(GpuBlock)] only supports structs with named fields"),
+    };
+    if !is_repr_c(ast) {
+        panic!("#[derive(GpuBlock)] requires #[repr(C)] on {}", name);
+    }
+
+    let members: Vec<quote::Tokens> = fields.iter().map(|field| {
+        let field_name = field.ident.as_ref().unwrap().to_string();
+        let ty = &field.ty;
+        quote! { (#field_name, <#ty as ::lyon_renderer::gpu_block::GpuField>::data_type()) }
+    }).collect();
+    let words: Vec<quote::Tokens> = fields.iter().map(|field| {
+        let ty = &field.ty;
+        quote! { <#ty as ::lyon_renderer::gpu_block::GpuField>::WORDS }
+    }).collect();
+
+    // The array length underflows or doesn't match if the struct has padding.
+    quote! {
+        unsafe impl ::lyon_renderer::gpu_block::GpuBlock for #name {
+            fn layout() -> ::lyon_renderer::gpu_block::MemoryLayout {
+                let _: [(); 0] = [(); ::std::mem::size_of::<#name>() - 4 * (0 #(+ #words)*)];
+                ::lyon_renderer::gpu_block::MemoryLayout::new(&[#(#members),*])
+            }
+        }
+    }
+}
+
+// Whether the struct has a #[repr(C)] attribute, possibly among other representation hints.
+fn is_repr_c(ast: &syn::DeriveInput) -> bool {
+    ast.attrs.iter().any(|attr| match attr.value {
+        syn::MetaItem::List(ref ident, ref items) if ident == "repr" => {
+            items.iter().any(|item| match *item {
+                syn::NestedMetaItem::MetaItem(syn::MetaItem::Word(ref word)) => word == "C",
+                _ => false,
+            })
+        }
+        _ => false,
+    })
+}
@@ -0,0 +1,46 @@
+#[macro_use]
+extern crate lyon_renderer_derive;
+extern crate lyon_renderer;
+
+use lyon_renderer::gpu_block::{GpuBlock, DataType};
+
+#[repr(C)]
+#[derive(Copy, Clone, GpuBlock)]
+struct DerivedPrimitive {
+    color: [f32; 4],
+    offset: [f32; 2],
+    z_index: f32,
+    transform: i32,
+}
+
+#[test]
+fn derived_layout() {
+    let prim = DerivedPrimitive {
+        color: [1.0, 0.0, 0.0, 1.0],
+        offset: [2.0, 3.0],
+        z_index: 0.5,
+        transform: 7,
+    };
+
+    let layout = DerivedPrimitive::layout();
+    assert_eq!(layout.check_std140(), Ok(()));
+    assert_eq!(DerivedPrimitive::size_in_words(), 8);
+    let members: Vec<(&str, DataType, usize)> = layout.members().iter().map(|member| {
+        (member.name, member.data_type, member.offset)
+    }).collect();
+    assert_eq!(members, vec![
+        ("color", DataType::Vec4, 0),
+        ("offset", DataType::Vec2, 4),
+        ("z_index", DataType::Float, 6),
+        ("transform", DataType::Int, 7),
+    ]);
+
+    // The words are the fields at the offsets of the layout.
+    let words = prim.as_words();
+    assert_eq!(words.len(), 8);
+    assert_eq!(words[0], 1.0f32.to_bits());
+    assert_eq!(words[4], 2.0f32.to_bits());
+    assert_eq!(words[5], 3.0f32.to_bits());
+    assert_eq!(words[6], 0.5f32.to_bits());
+    assert_eq!(words[7], 7);
+}
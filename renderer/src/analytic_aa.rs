@@ -0,0 +1,227 @@
+//! Analytic anti-aliasing of fills.
+//!
+//! MSAA is expensive on tiled GPUs and the vertex fringes of `FillStyle::aa` break down on
+//! shapes thinner than a pixel. This pass instead renders the tessellated geometry of a
+//! shape slightly inflated, and each fragment computes its coverage from its distance in
+//! pixels to the boundary edges of its triangle. Triangles are independent, so a hairline
+//! gets exactly the coverage of its width, and the result is as crisp as the geometry.
+//!
+//! The geometry is de-indexed: each vertex carries the barycentric coordinates of its
+//! (inflated) position relative to the original triangle, and a mask of the triangle's edges
+//! that are on the boundary of the shape. Edges shared by two triangles are inside the shape
+//! and don't attenuate the coverage. Since coverage is blended, the pass must be drawn with
+//! the transparent primitives, back to front.
+
+use std::collections::HashMap;
+
+use gfx;
+use gfx::traits::FactoryExt;
+
+pub use gfx_types::*;
+use core::math::*;
+use api::RenderOptions;
+use renderer::{Globals, GpuTransform, GpuFillPrimitive, GpuFillVertex, GpuStyle, GpuGradientStopWords, GpuClip, blend_state};
+use glsl::{AA_FILL_VERTEX_SHADER, AA_FILL_FRAGMENT_SHADER, shader_with_options};
+#[cfg(test)]
+use tessellation::basic_shapes;
+#[cfg(test)]
+use tessellation::geometry_builder::{BuffersBuilder, VertexBuffers};
+#[cfg(test)]
+use renderer::{WithId, FillPrimitiveId};
+
+gfx_defines!{
+    vertex GpuAaFillVertex {
+        position: [f32; 2] = "a_position",
+        // The barycentric coordinates of the position in the original triangle.
+        barycentric: [f32; 3] = "a_barycentric",
+        // Bit i is set if the edge opposite to vertex i is on the boundary of the shape.
+        edge_mask: i32 = "a_edge_mask",
+        prim_id: i32 = "a_prim_id",
+    }
+
+    pipeline aa_fill_pipeline {
+        vbo: gfx::VertexBuffer<GpuAaFillVertex> = (),
+        out_color: gfx::BlendTarget<ColorFormat> = ("out_color", gfx::state::MASK_ALL, gfx::preset::blend::ALPHA),
+        out_depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_TEST,
+        constants: gfx::ConstantBuffer<Globals> = "Globals",
+        transforms: gfx::ConstantBuffer<GpuTransform> = "u_transforms",
+        primitives: gfx::ConstantBuffer<GpuFillPrimitive> = "u_primitives",
+        styles: gfx::ConstantBuffer<GpuStyle> = "u_styles",
+        gradient_stops: gfx::ConstantBuffer<GpuGradientStopWords> = "u_gradient_stops",
+        clips: gfx::ConstantBuffer<GpuClip> = "u_clips",
+    }
+}
+
+pub type AaFillPso = Pso<aa_fill_pipeline::Meta>;
+
+pub fn create_aa_fill_pso(factory: &mut GlFactory, options: &RenderOptions) -> AaFillPso {
+    let program = factory.link_program(
+        shader_with_options(AA_FILL_VERTEX_SHADER, options).as_bytes(),
+        shader_with_options(AA_FILL_FRAGMENT_SHADER, options).as_bytes(),
+    ).unwrap();
+
+    return factory.create_pipeline_from_program(
+        &program,
+        gfx::Primitive::TriangleList,
+        gfx::state::Rasterizer::new_fill(),
+        aa_fill_pipeline::Init {
+            out_color: ("out_color", gfx::state::MASK_ALL, blend_state(options.alpha_mode)),
+            .. aa_fill_pipeline::new()
+        },
+    ).unwrap();
+}
+
+/// Builds the analytic anti-aliasing geometry of a tessellated fill.
+///
+/// `indices` are the triangles of a single shape, for example the range of a shape in the
+/// `GeometryStore`, and refer to `vertices`. The boundary of the shape is pushed outwards by
+/// `inflate` local units, which must be at least one device pixel in the local space of the
+/// shape for the edges to fade out completely.
+pub fn build_aa_fill_geometry(
+    vertices: &[GpuFillVertex],
+    indices: &[u16],
+    inflate: f32,
+    output: &mut Vec<GpuAaFillVertex>,
+) {
+    let position = |idx: u16| {
+        let p = vertices[idx as usize].position;
+        point(p[0], p[1])
+    };
+    let edge_key = |a: u16, b: u16| if a < b { (a, b) } else { (b, a) };
+
+    let mut edge_counts: HashMap<(u16, u16), u32> = HashMap::new();
+    for tri in indices.chunks(3) {
+        for i in 0..3 {
+            *edge_counts.entry(edge_key(tri[(i + 1) % 3], tri[(i + 2) % 3])).or_insert(0) += 1;
+        }
+    }
+
+    // Accumulate the outward normals of the boundary edges at their endpoints.
+    let mut normals: HashMap<u16, (Vec2, Vec2)> = HashMap::new();
+    for tri in indices.chunks(3) {
+        for i in 0..3 {
+            let (a, b) = (tri[(i + 1) % 3], tri[(i + 2) % 3]);
+            if edge_counts[&edge_key(a, b)] != 1 {
+                continue;
+            }
+            let edge = position(b) - position(a);
+            if edge.square_length() == 0.0 {
+                continue;
+            }
+            let mut n = vec2(-edge.y, edge.x).normalize();
+            if n.dot(position(tri[i]) - position(a)) > 0.0 {
+                n = -n;
+            }
+            for &v in &[a, b] {
+                let entry = normals.entry(v).or_insert((vec2(0.0, 0.0), n));
+                entry.0 = entry.0 + n;
+            }
+        }
+    }
+
+    // Miter the normals so that the boundary edges move by `inflate`, with a limit to
+    // avoid long spikes at sharp corners.
+    let offset = |idx: u16| -> Vec2 {
+        match normals.get(&idx) {
+            Some(&(sum, edge_normal)) if sum.square_length() > 0.0 => {
+                let n = sum.normalize();
+                n * (inflate / n.dot(edge_normal).max(0.25))
+            }
+            _ => vec2(0.0, 0.0),
+        }
+    };
+
+    for tri in indices.chunks(3) {
+        let p = [position(tri[0]), position(tri[1]), position(tri[2])];
+        let area = (p[1] - p[0]).cross(p[2] - p[0]);
+        if area == 0.0 {
+            continue;
+        }
+
+        let mut edge_mask = 0;
+        for i in 0..3 {
+            if edge_counts[&edge_key(tri[(i + 1) % 3], tri[(i + 2) % 3])] == 1 {
+                edge_mask |= 1 << i;
+            }
+        }
+
+        for i in 0..3 {
+            let q = p[i] + offset(tri[i]);
+            // The barycentric coordinates extend linearly outside of the triangle, and are
+            // negative past the edges.
+            let b0 = (p[1] - q).cross(p[2] - q) / area;
+            let b1 = (p[2] - q).cross(p[0] - q) / area;
+            output.push(GpuAaFillVertex {
+                position: q.to_array(),
+                barycentric: [b0, b1, 1.0 - b0 - b1],
+                edge_mask: edge_mask,
+                prim_id: vertices[tri[i] as usize].prim_id,
+            });
+        }
+    }
+}
+
+/// The coverage of a pixel at a signed distance (in pixels, positive inside) from a
+/// straight edge, as computed by the shader.
+pub fn edge_coverage(distance: f32) -> f32 {
+    (distance + 0.5).max(0.0).min(1.0)
+}
+
+#[test]
+fn aa_fill_geometry() {
+    let mut geom: VertexBuffers<GpuFillVertex> = VertexBuffers::new();
+    basic_shapes::fill_rectangle(
+        &rect(0.0, 0.0, 10.0, 1.0),
+        &mut BuffersBuilder::new(&mut geom, WithId(FillPrimitiveId::new(3))),
+    );
+
+    let mut output = Vec::new();
+    build_aa_fill_geometry(&geom.vertices, &geom.indices, 1.0, &mut output);
+    assert_eq!(output.len(), 6);
+
+    for vertex in &output {
+        assert_eq!(vertex.prim_id, 3);
+        // Each triangle has two sides of the rectangle, but not the diagonal.
+        assert_eq!(vertex.edge_mask.count_ones(), 2);
+        // The corners moved outwards by one unit on both axes.
+        let p = point(vertex.position[0], vertex.position[1]);
+        assert!((p.x + 1.0).abs() < 1e-4 || (p.x - 11.0).abs() < 1e-4);
+        assert!((p.y + 1.0).abs() < 1e-4 || (p.y - 2.0).abs() < 1e-4);
+        let sum: f32 = vertex.barycentric.iter().sum();
+        assert!((sum - 1.0).abs() < 1e-5);
+    }
+
+    // The barycentric coordinate of a vertex is its signed distance to the opposite edge
+    // divided by the height of the triangle over that edge. The first triangle is
+    // (0, 0), (10, 0), (10, 1), with corners moved to (-1, -1), (11, -1) and (11, 2).
+    let expected = [[1.1, 0.9, -1.0], [-0.1, 2.1, -1.0], [-0.1, -0.9, 2.0]];
+    for (vertex, expected) in output[0..3].iter().zip(expected.iter()) {
+        for i in 0..3 {
+            assert!((vertex.barycentric[i] - expected[i]).abs() < 1e-4);
+        }
+    }
+
+    // Each inflated corner is `inflate` outside of the boundary edges that meet at it.
+    for (tri, vertices) in geom.indices.chunks(3).zip(output.chunks(3)) {
+        let p: Vec<Point> = tri.iter().map(|&idx| {
+            let p = geom.vertices[idx as usize].position;
+            point(p[0], p[1])
+        }).collect();
+        let area = (p[1] - p[0]).cross(p[2] - p[0]).abs();
+        for (i, vertex) in vertices.iter().enumerate() {
+            for edge in 0..3 {
+                if edge == i || vertex.edge_mask & (1 << edge) == 0 {
+                    continue;
+                }
+                let height = area / (p[(edge + 2) % 3] - p[(edge + 1) % 3]).length();
+                let distance = vertex.barycentric[edge] * height;
+                assert!((distance + 1.0).abs() < 1e-4);
+                assert_eq!(edge_coverage(distance), 0.0);
+            }
+        }
+    }
+
+    assert_eq!(edge_coverage(-1.0), 0.0);
+    assert_eq!(edge_coverage(0.0), 0.5);
+    assert_eq!(edge_coverage(2.0), 1.0);
+}
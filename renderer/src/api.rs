@@ -1,6 +1,11 @@
 use core::math::*;
 use path::Path;
 use buffer::*;
+use renderer::ClipId;
+use tessellation::{StrokeOptions, LineCap, LineJoin};
+
+use std::collections::HashSet;
+use std::f32::consts::PI;
 
 #[derive(Copy, Clone, Debug)]
 pub struct Image;
@@ -8,10 +13,26 @@ pub struct Image;
 pub struct Transform;
 #[derive(Copy, Clone, Debug)]
 pub struct Mesh;
-#[derive(Copy, Clone, Debug)]
-pub struct Ellipse;
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ellipse {
+    pub center: Point,
+    pub radii: Vec2,
+}
+/// A rectangle with a radius for each corner, see `ShapeStore::add_rounded_rect`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RoundedRect {
+    pub rect: Rect,
+    pub top_left: f32,
+    pub top_right: f32,
+    pub bottom_left: f32,
+    pub bottom_right: f32,
+}
 #[derive(Copy, Clone, Debug)]
 pub struct Effect;
+#[derive(Copy, Clone, Debug)]
+pub struct VectorImage;
+#[derive(Copy, Clone, Debug)]
+pub struct ShapeGroup;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Epoch(u64);
@@ -23,14 +44,33 @@ pub type RenderNodeId = Id<RenderNode>;
 pub type PathId = Id<Path>;
 pub type RectId = Id<Rect>;
 pub type EllipseId = Id<Ellipse>;
+pub type RoundedRectId = Id<RoundedRect>;
 pub type MeshId = Id<Mesh>;
 pub type ColorId = Id<Color>;
 pub type GradientId = Id<LinearGradient>;
+pub type ConicGradientId = Id<ConicGradient>;
 pub type EffectId = Id<Effect>;
+pub type VectorImageId = Id<VectorImage>;
+pub type GroupId = Id<ShapeGroup>;
 
+/// A color or gradient registered in a `StyleSheet`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum PatternId {
     Color(ColorId),
-    Gradient(GradientId)
+    Gradient(GradientId),
+    ConicGradient(ConicGradientId),
+}
+
+impl PatternId {
+    /// The index of the entry in the style sheet. Colors and gradients share the same
+    /// index space.
+    pub fn index(&self) -> usize {
+        match *self {
+            PatternId::Color(id) => id.index(),
+            PatternId::Gradient(id) => id.index(),
+            PatternId::ConicGradient(id) => id.index(),
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -51,16 +91,168 @@ impl Color {
         self.b as f32 / 255.0,
         self.a as f32 / 255.0,
     ]}
+
+    /// The color with its components multiplied by alpha.
+    pub fn premultiplied_f32_array(self) -> [f32; 4] { premultiply(self.f32_array()) }
+}
+
+/// Multiplies the color components of a straight alpha color by its alpha.
+pub fn premultiply(color: [f32; 4]) -> [f32; 4] {
+    let a = color[3];
+    [color[0] * a, color[1] * a, color[2] * a, a]
+}
+
+/// Converts a premultiplied color back to straight alpha.
+///
+/// Fully transparent colors have no recoverable color and become transparent black.
+pub fn unpremultiply(color: [f32; 4]) -> [f32; 4] {
+    let a = color[3];
+    if a == 0.0 {
+        return [0.0; 4];
+    }
+    [color[0] / a, color[1] / a, color[2] / a, a]
+}
+
+/// How the renderer represents transparent colors, which also selects the blend
+/// factors of the passes.
+///
+/// Premultiplied alpha must be used when the output is composited by something that
+/// expects premultiplied content (most UI toolkits and window compositors), otherwise
+/// the transparent edges get dark fringes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AlphaMode {
+    /// Colors are blended with `src * src_alpha + dst * (1 - src_alpha)`.
+    Straight,
+    /// Colors are premultiplied and blended with `src + dst * (1 - src_alpha)`.
+    Premultiplied,
+}
+
+impl AlphaMode {
+    /// Converts a color into the representation expected by the shaders.
+    pub fn color(self, color: Color) -> [f32; 4] {
+        match self {
+            AlphaMode::Straight => color.f32_array(),
+            AlphaMode::Premultiplied => color.premultiplied_f32_array(),
+        }
+    }
+}
+
+impl Default for AlphaMode {
+    fn default() -> Self { AlphaMode::Straight }
+}
+
+/// Renderer-wide options that the shaders and pipelines are set up with.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RenderOptions {
+    /// How transparent colors are represented and blended.
+    ///
+    /// Default value: `AlphaMode::Straight`.
+    pub alpha_mode: AlphaMode,
+
+    /// Blend in linear space rather than directly with the sRGB values.
+    ///
+    /// The shaders output linear colors, so the color targets must encode their content
+    /// to sRGB for the result to be displayed correctly.
+    ///
+    /// Default value: `false`.
+    pub linear_blending: bool,
+
+    /// Add a small amount of noise to the output to hide the banding of large soft gradients.
+    ///
+    /// Default value: `false`.
+    pub dithering: bool,
+
+    /// The noise added when `dithering` is enabled.
+    ///
+    /// Default value: `DitherPattern::InterleavedNoise`.
+    pub dither_pattern: DitherPattern,
+
+    // To be able to add fields without making it a breaking change, add an empty private field
+    // which makes it impossible to create a RenderOptions without the calling constructor.
+    _private: (),
 }
 
+impl RenderOptions {
+    pub fn default() -> RenderOptions {
+        RenderOptions {
+            alpha_mode: AlphaMode::Straight,
+            linear_blending: false,
+            dithering: false,
+            dither_pattern: DitherPattern::InterleavedNoise,
+            _private: (),
+        }
+    }
+
+    pub fn with_alpha_mode(mut self, alpha_mode: AlphaMode) -> RenderOptions {
+        self.alpha_mode = alpha_mode;
+        return self;
+    }
+
+    pub fn with_linear_blending(mut self, linear_blending: bool) -> RenderOptions {
+        self.linear_blending = linear_blending;
+        return self;
+    }
+
+    pub fn with_dithering(mut self, dithering: bool) -> RenderOptions {
+        self.dithering = dithering;
+        return self;
+    }
+
+    pub fn with_dither_pattern(mut self, dither_pattern: DitherPattern) -> RenderOptions {
+        self.dither_pattern = dither_pattern;
+        return self;
+    }
+}
+
+/// The noise used to dither the output, see `RenderOptions::dithering`.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DitherPattern {
+    /// Noise without visible structure, which looks like fine grain.
+    InterleavedNoise,
+    /// A 4x4 Bayer matrix, which gives the smoothest result on very slow gradients but can
+    /// show a regular cross-hatch pattern.
+    Ordered,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum ShapeId {
     Path(PathId),
     Ellipse(EllipseId),
     Rect(RectId),
+    RoundedRect(RoundedRectId),
+    Glyph(GlyphKey),
+    /// Several shapes tessellated together and drawn with a single primitive, see
+    /// `ShapeStore::add_group`.
+    Group(GroupId),
     None, // meh
 }
 
+/// Identifies the geometry of a glyph, shared by all the render nodes that draw it.
+///
+/// Glyph outlines are usually normalized to a font size of 1.0 and scaled by the transform
+/// of each render node, so the tolerance is expressed in the space of the outline: the
+/// same glyph drawn at very different sizes should use different tolerances.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GlyphKey {
+    /// An identifier chosen by the caller for the font the glyph comes from.
+    pub font: u32,
+    /// The index of the glyph in the font.
+    pub glyph: u16,
+    tolerance: u32,
+}
+
+impl GlyphKey {
+    pub fn new(font: u32, glyph: u16, tolerance: f32) -> Self {
+        GlyphKey {
+            font: font,
+            glyph: glyph,
+            tolerance: tolerance.to_bits(),
+        }
+    }
+
+    pub fn tolerance(&self) -> f32 { f32::from_bits(self.tolerance) }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct GradientStop {
     pub color: Color,
@@ -72,6 +264,12 @@ pub enum Pattern {
     Color(Color),
     Image(ImagePattern),
     LinearGradient(LinearGradient),
+    ConicGradient(ConicGradient),
+    StrokeGradient(StrokeGradient),
+    VectorImage(VectorImagePattern),
+    /// A color or gradient of the `StyleSheet`, which can be changed for all the
+    /// primitives that use it at once.
+    Shared(PatternId),
 }
 
 impl Pattern {
@@ -79,7 +277,12 @@ impl Pattern {
         match self {
             &Pattern::Color(color) => { color.a == 255 }
             &Pattern::LinearGradient(ref gradient) => { gradient.is_opaque }
+            &Pattern::ConicGradient(ref gradient) => { gradient.is_opaque }
             &Pattern::Image(ref img) => { img.is_opaque }
+            &Pattern::StrokeGradient(ref gradient) => { gradient.start.a == 255 && gradient.end.a == 255 }
+            &Pattern::VectorImage(ref pattern) => { pattern.is_opaque }
+            // The entry can become transparent after the primitive is built.
+            &Pattern::Shared(_) => { false }
         }
     }
 }
@@ -108,6 +311,94 @@ impl LinearGradient {
     pub fn stops(&self) -> &[GradientStop] { &self.stops }
 }
 
+/// A gradient sweeping around a center, for pie charts, color wheels and spinners.
+///
+/// The offsets of the stops go from 0.0 at `start_angle` to 1.0 after a full turn,
+/// clockwise on screen since the y axis points down. For a seamless color wheel, the last
+/// stop must have the color of the first one.
+///
+/// Like linear gradients, the shaders interpolate the stops of conic gradients registered
+/// in a `StyleSheet` (see `StyleSheet::add_conic_gradient`), and primitives that embed the
+/// gradient are drawn with its first stop.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConicGradient {
+    pub center: Point,
+    pub start_angle: Radians<f32>,
+    pub stops: Vec<GradientStop>,
+    pub is_opaque: bool,
+}
+
+impl ConicGradient {
+    pub fn new(center: Point, start_angle: Radians<f32>, stops: Vec<GradientStop>) -> Self {
+        let is_opaque = stops.iter().all(|stop| stop.color.a == 255);
+        ConicGradient {
+            center: center,
+            start_angle: start_angle,
+            stops: stops,
+            is_opaque: is_opaque,
+        }
+    }
+
+    pub fn stops(&self) -> &[GradientStop] { &self.stops }
+
+    /// The offset in the stops of a point, in [0, 1).
+    pub fn offset_at(&self, position: Point) -> f32 {
+        let v = position - self.center;
+        if v.x == 0.0 && v.y == 0.0 {
+            return 0.0;
+        }
+        let turns = (v.y.atan2(v.x) - self.start_angle.get()) / (2.0 * PI);
+
+        return turns - turns.floor();
+    }
+
+    /// The color of a point, as a straight alpha color.
+    pub fn color_at(&self, position: Point) -> [f32; 4] {
+        sample_gradient(&self.stops, self.offset_at(position))
+    }
+}
+
+/// Interpolates the colors of stops sorted by offset, as a straight alpha color.
+///
+/// Offsets before the first stop and after the last one take their color.
+pub fn sample_gradient(stops: &[GradientStop], offset: f32) -> [f32; 4] {
+    if stops.is_empty() {
+        return [0.0; 4];
+    }
+
+    let mut prev = stops[0];
+    for &stop in stops {
+        if offset < stop.d {
+            if offset <= prev.d {
+                return stop.color.f32_array();
+            }
+            let t = (offset - prev.d) / (stop.d - prev.d);
+            let a = prev.color.f32_array();
+            let b = stop.color.f32_array();
+            return [
+                a[0] + (b[0] - a[0]) * t,
+                a[1] + (b[1] - a[1]) * t,
+                a[2] + (b[2] - a[2]) * t,
+                a[3] + (b[3] - a[3]) * t,
+            ];
+        }
+        prev = stop;
+    }
+
+    return prev.color.f32_array();
+}
+
+/// A gradient following a stroked path, from its start to its end.
+///
+/// Only applies to strokes: `GpuFillPrimitive::set_pattern` returns an error and the
+/// batcher rejects fills using it with `BatchError::UnsupportedPattern`. Fading the end
+/// color to transparent makes "draw-on" and progress effects.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StrokeGradient {
+    pub start: Color,
+    pub end: Color,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct ImagePattern {
     pub image_id: ImageId,
@@ -115,17 +406,200 @@ pub struct ImagePattern {
     pub is_opaque: bool,
 }
 
+/// Fills a shape with a vector image repeated over a grid of tiles.
+///
+/// `tile` is the area of the image that is repeated, in the local space of the filled
+/// shape. The shaders don't draw vector images yet, so the batchers reject nodes with
+/// this pattern (see `BatchError::UnsupportedPattern`). Applications can instance the
+/// image once per tile instead, see `tile_offsets`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VectorImagePattern {
+    pub image: VectorImageId,
+    pub tile: Rect,
+    pub is_opaque: bool,
+}
+
+impl VectorImagePattern {
+    /// Returns the translations to apply to the image to cover `bounds` with tiles.
+    pub fn tile_offsets(&self, bounds: &Rect) -> Vec<Vec2> {
+        let mut offsets = Vec::new();
+        let size = self.tile.size;
+        if size.width <= 0.0 || size.height <= 0.0 || bounds.size.width <= 0.0 || bounds.size.height <= 0.0 {
+            return offsets;
+        }
+
+        let origin = self.tile.origin;
+        let first_x = ((bounds.min_x() - origin.x) / size.width).floor() as i32;
+        let first_y = ((bounds.min_y() - origin.y) / size.height).floor() as i32;
+        let last_x = ((bounds.max_x() - origin.x) / size.width).ceil() as i32;
+        let last_y = ((bounds.max_y() - origin.y) / size.height).ceil() as i32;
+        for y in first_y..last_y {
+            for x in first_x..last_x {
+                offsets.push(vec2(x as f32 * size.width, y as f32 * size.height));
+            }
+        }
+
+        return offsets;
+    }
+}
+
+/// The appearance of a stroke, including the parameters of its tessellation.
+///
+/// The caps and joins are part of the style rather than of separate `StrokeOptions` so
+/// that the geometry of a stroke can't get out of sync with the rest of its style, and so
+/// that they can be read back from the `RenderNode` that uses the style.
 #[derive(Clone, Debug, PartialEq)]
 pub struct StrokeStyle {
     pub pattern: Pattern,
     pub width: f32,
     pub aa: bool,
+    pub start_cap: LineCap,
+    pub end_cap: LineCap,
+    pub line_join: LineJoin,
+    pub miter_limit: f32,
+    pub glow: Option<Glow>,
+    pub mask: Option<Mask>,
+    /// See `FillStyle::pattern_transform`.
+    pub pattern_transform: Option<TransformId>,
+}
+
+impl StrokeStyle {
+    /// A stroke with butt caps and miter joins, the defaults of `StrokeOptions`.
+    pub fn new(pattern: Pattern, width: f32) -> Self {
+        let defaults = StrokeOptions::default();
+        StrokeStyle {
+            pattern: pattern,
+            width: width,
+            aa: false,
+            start_cap: defaults.start_cap,
+            end_cap: defaults.end_cap,
+            line_join: defaults.line_join,
+            miter_limit: defaults.miter_limit,
+            glow: None,
+            mask: None,
+            pattern_transform: None,
+        }
+    }
+
+    pub fn with_line_cap(mut self, cap: LineCap) -> Self {
+        self.start_cap = cap;
+        self.end_cap = cap;
+        return self;
+    }
+
+    pub fn with_start_cap(mut self, cap: LineCap) -> Self {
+        self.start_cap = cap;
+        return self;
+    }
+
+    pub fn with_end_cap(mut self, cap: LineCap) -> Self {
+        self.end_cap = cap;
+        return self;
+    }
+
+    pub fn with_line_join(mut self, join: LineJoin) -> Self {
+        self.line_join = join;
+        return self;
+    }
+
+    pub fn with_miter_limit(mut self, limit: f32) -> Self {
+        self.miter_limit = limit;
+        return self;
+    }
+
+    pub fn with_aa(mut self, aa: bool) -> Self {
+        self.aa = aa;
+        return self;
+    }
+
+    pub fn with_glow(mut self, glow: Glow) -> Self {
+        self.glow = Some(glow);
+        return self;
+    }
+
+    pub fn with_mask(mut self, mask: Mask) -> Self {
+        self.mask = Some(mask);
+        return self;
+    }
+
+    pub fn with_pattern_transform(mut self, transform: TransformId) -> Self {
+        self.pattern_transform = Some(transform);
+        return self;
+    }
+
+    /// The options to tessellate the stroke with.
+    ///
+    /// The width is applied by the vertex shader from the primitive, so the tessellated
+    /// vertices stay on the path and the same geometry can be reused for other widths.
+    pub fn tessellation_options(&self, tolerance: f32) -> StrokeOptions {
+        StrokeOptions::tolerance(tolerance)
+            .with_start_cap(self.start_cap)
+            .with_end_cap(self.end_cap)
+            .with_line_join(self.line_join)
+            .with_miter_limit(self.miter_limit)
+            .with_line_width(self.width)
+            .dont_apply_line_width()
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct FillStyle {
     pub pattern: Pattern,
     pub aa: bool,
+    pub shadow: Option<DropShadow>,
+    pub mask: Option<Mask>,
+    /// A rounded rectangle the fill is clipped to, see `GpuFillPrimitive::set_clip`.
+    pub clip: Option<ClipId>,
+    /// The transform from the local space of the shape to the space of the pattern, so that
+    /// gradients and images can move independently of the geometry. The pattern is in the
+    /// local space of the shape if None.
+    pub pattern_transform: Option<TransformId>,
+}
+
+impl FillStyle {
+    /// A fill without anti-aliasing, shadow, mask or clip.
+    pub fn new(pattern: Pattern) -> Self {
+        FillStyle {
+            pattern: pattern,
+            aa: false,
+            shadow: None,
+            mask: None,
+            clip: None,
+            pattern_transform: None,
+        }
+    }
+}
+
+/// Modulates the coverage of a fill or stroke with the alpha channel of an image
+/// or of another shape.
+///
+/// Masked primitives are not opaque: the batcher emits them as `MaskedCmd`s, which are
+/// rendered into an offscreen target along with their mask and composited with
+/// `PostProcessor::composite_masked`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mask {
+    Image(ImageId),
+    Shape(ShapeId),
+}
+
+/// A glow drawn around a stroke, for example to highlight a selected edge of a diagram.
+///
+/// The glow reuses the geometry of the stroke, widened by `radius` on each side, and fades
+/// out towards its edge in the fragment shader, so it doesn't need an offscreen blur.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Glow {
+    pub color: Color,
+    pub radius: f32,
+    /// The opacity of the glow next to the stroke, between 0.0 and 1.0.
+    pub intensity: f32,
+}
+
+/// A shadow drawn behind a fill, with the same shape.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DropShadow {
+    pub offset: Vec2,
+    pub blur_radius: f32,
+    pub color: Color,
 }
 
 #[derive(Clone, Debug)]
@@ -136,6 +610,200 @@ pub struct RenderNode {
     pub fill: Option<FillStyle>,
 }
 
+/// A full-screen effect applied to the composited output of a layer.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PostEffect {
+    /// Darkens the pixels further than `radius` from the center (in normalized coordinates),
+    /// over a distance of `softness`.
+    Vignette { radius: f32, softness: f32 },
+    /// Transforms the (r, g, b, a) color of each pixel with a matrix.
+    ColorGrading { matrix: [[f32; 4]; 4] },
+    /// Replaces the image with blocks of `block_size` pixels.
+    Pixelate { block_size: f32 },
+    /// Blurs the image over approximately `radius` pixels.
+    Blur { radius: f32 },
+}
+
+/// Stores the post-processing effects so that layers can refer to chains of effects by id.
+pub struct EffectRegistry {
+    effects: Vec<PostEffect>,
+}
+
+impl EffectRegistry {
+    pub fn new() -> Self {
+        EffectRegistry {
+            effects: Vec::new(),
+        }
+    }
+
+    pub fn add_effect(&mut self, effect: PostEffect) -> EffectId {
+        self.effects.push(effect);
+        return Id::new(self.effects.len() as u16 - 1);
+    }
+
+    /// Replaces the parameters of an effect, for example to animate it.
+    pub fn set_effect(&mut self, id: EffectId, effect: PostEffect) {
+        self.effects[id.index()] = effect;
+    }
+
+    pub fn get_effect(&self, id: EffectId) -> &PostEffect {
+        &self.effects[id.index()]
+    }
+}
+
+/// How the targets of a pass are initialized before drawing into them.
+///
+/// `None` keeps the previous content.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ClearOps {
+    pub color: Option<Color>,
+    /// The depth the depth buffer is cleared to. The opaque passes draw front to back
+    /// with depths between 0.0 (front) and 1.0 (back), so this is usually 1.0.
+    pub depth: Option<f32>,
+}
+
+impl ClearOps {
+    /// Keeps the content of the targets.
+    pub fn load() -> Self { ClearOps { color: None, depth: None } }
+
+    /// Clears the color target to a background color and the depth to the back.
+    pub fn clear(color: Color) -> Self { ClearOps { color: Some(color), depth: Some(1.0) } }
+
+    pub fn is_load(&self) -> bool { self.color.is_none() && self.depth.is_none() }
+}
+
+impl Default for ClearOps {
+    fn default() -> Self { ClearOps::load() }
+}
+
+/// Properties applied to a group of primitives as a whole.
+///
+/// Layers with an opacity below 1.0 or with effects must be rendered into an offscreen
+/// target and composited with `PostProcessor::composite_layer`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    opacity: f32,
+    effects: Vec<EffectId>,
+    clear: ClearOps,
+    cached: bool,
+    depth_pre_pass: bool,
+}
+
+impl Layer {
+    pub fn new() -> Self {
+        Layer {
+            opacity: 1.0,
+            effects: Vec::new(),
+            clear: ClearOps::load(),
+            cached: false,
+            depth_pre_pass: false,
+        }
+    }
+
+    /// Renders the layer into a retained texture that is composited on the following
+    /// frames until the content changes, see `LayerCache`.
+    ///
+    /// This is worth it for layers that rarely change, like the background of a document,
+    /// and wasteful for the ones that change every frame.
+    pub fn set_cached(&mut self, cached: bool) {
+        self.cached = cached;
+    }
+
+    pub fn is_cached(&self) -> bool { self.cached }
+
+    /// Renders the depth of the opaque fills before shading them, so that each pixel is
+    /// shaded once by the front-most fill.
+    ///
+    /// This pays off for layers with a lot of stacked opaque shapes, at the cost of drawing
+    /// their geometry twice. See `depth_pre_pass_pipeline`.
+    pub fn set_depth_pre_pass(&mut self, enabled: bool) {
+        self.depth_pre_pass = enabled;
+    }
+
+    pub fn has_depth_pre_pass(&self) -> bool { self.depth_pre_pass }
+
+    /// Sets how the target of the layer is initialized at the beginning of the frame,
+    /// for example with a background color. See `RenderTarget::clear`.
+    pub fn set_clear(&mut self, clear: ClearOps) {
+        self.clear = clear;
+    }
+
+    pub fn clear(&self) -> ClearOps { self.clear }
+
+    /// Sets the opacity of the layer, clamped between 0.0 and 1.0.
+    ///
+    /// Unlike fading each primitive, overlapping primitives don't show through each other.
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity.max(0.0).min(1.0);
+    }
+
+    pub fn opacity(&self) -> f32 { self.opacity }
+
+    /// Appends an effect to the chain of post-processing effects of the layer.
+    pub fn add_effect(&mut self, effect: EffectId) {
+        self.effects.push(effect);
+    }
+
+    pub fn effects(&self) -> &[EffectId] { &self.effects }
+
+    /// Returns true if the layer can't be rendered directly into its destination.
+    pub fn needs_offscreen_target(&self) -> bool {
+        self.opacity < 1.0 || !self.effects.is_empty() || self.cached
+    }
+}
+
+/// Tracks whether the retained texture of a cached layer is up to date.
+///
+/// The render nodes of the layer (their index in the batcher) are registered with
+/// `add_node`, and every change to a render node is reported with `mark_dirty`. The layer
+/// only needs to be rendered again when one of its own nodes changed.
+#[derive(Clone, Debug)]
+pub struct LayerCache {
+    nodes: HashSet<usize>,
+    valid: bool,
+}
+
+impl LayerCache {
+    /// Creates an invalid cache, so that the layer is rendered the first time.
+    pub fn new() -> Self {
+        LayerCache {
+            nodes: HashSet::new(),
+            valid: false,
+        }
+    }
+
+    pub fn add_node(&mut self, node_index: usize) {
+        self.nodes.insert(node_index);
+        self.valid = false;
+    }
+
+    pub fn remove_node(&mut self, node_index: usize) {
+        if self.nodes.remove(&node_index) {
+            self.valid = false;
+        }
+    }
+
+    pub fn contains(&self, node_index: usize) -> bool { self.nodes.contains(&node_index) }
+
+    /// Reports a change to a render node. Returns true if this invalidated the cache.
+    pub fn mark_dirty(&mut self, node_index: usize) -> bool {
+        if self.valid && self.nodes.contains(&node_index) {
+            self.valid = false;
+            return true;
+        }
+
+        return false;
+    }
+
+    /// Forces the layer to be rendered again, for example after the texture was resized.
+    pub fn invalidate(&mut self) { self.valid = false; }
+
+    pub fn needs_render(&self) -> bool { !self.valid }
+
+    /// Records that the texture was rendered with the current content of the layer.
+    pub fn mark_rendered(&mut self) { self.valid = true; }
+}
+
 pub struct Api {
     // TODO!
 }
@@ -150,3 +818,124 @@ impl PropertyFlags {
     pub fn animated() -> Self { PropertyFlags { flags: 1 } }
 }
 
+
+#[test]
+fn alpha_modes() {
+    let color = Color { r: 255, g: 0, b: 255, a: 51 };
+    assert_eq!(AlphaMode::Straight.color(color), [1.0, 0.0, 1.0, 0.2]);
+    assert_eq!(AlphaMode::Premultiplied.color(color), [0.2, 0.0, 0.2, 0.2]);
+    assert_eq!(unpremultiply(premultiply([1.0, 0.5, 0.0, 0.5])), [1.0, 0.5, 0.0, 0.5]);
+    assert_eq!(unpremultiply([0.0; 4]), [0.0; 4]);
+    assert_eq!(AlphaMode::default(), AlphaMode::Straight);
+}
+
+#[test]
+fn layer_clear_ops() {
+    let mut layer = Layer::new();
+    assert!(layer.clear().is_load());
+    assert_eq!(ClearOps::default(), ClearOps::load());
+
+    layer.set_clear(ClearOps::clear(Color::white()));
+    assert_eq!(layer.clear().color, Some(Color::white()));
+    assert_eq!(layer.clear().depth, Some(1.0));
+    assert!(!layer.needs_offscreen_target());
+
+    // The pre-pass only changes how the opaque fills are drawn into the target.
+    assert!(!layer.has_depth_pre_pass());
+    layer.set_depth_pre_pass(true);
+    assert!(layer.has_depth_pre_pass());
+    assert!(!layer.needs_offscreen_target());
+}
+
+#[test]
+fn conic_gradient() {
+    let red = Color { r: 255, g: 0, b: 0, a: 255 };
+    let blue = Color { r: 0, g: 0, b: 255, a: 255 };
+    let gradient = ConicGradient::new(
+        point(10.0, 10.0),
+        Radians::new(-PI * 0.5),
+        vec![GradientStop { color: red, d: 0.0 }, GradientStop { color: blue, d: 1.0 }],
+    );
+    assert!(Pattern::ConicGradient(gradient.clone()).is_opaque());
+
+    // The gradient starts at the top and turns clockwise.
+    let near = |a: f32, b: f32| (a - b).abs() < 1e-5;
+    assert!(near(gradient.offset_at(point(10.0, 0.0)), 0.0));
+    assert!(near(gradient.offset_at(point(20.0, 10.0)), 0.25));
+    assert!(near(gradient.offset_at(point(10.0, 20.0)), 0.5));
+    assert!(near(gradient.offset_at(point(0.0, 10.0)), 0.75));
+    assert_eq!(gradient.offset_at(point(10.0, 10.0)), 0.0);
+
+    let color = gradient.color_at(point(10.0, 20.0));
+    assert!(near(color[0], 0.5) && near(color[2], 0.5) && near(color[3], 1.0));
+
+    let stops = [GradientStop { color: red, d: 0.25 }, GradientStop { color: blue, d: 0.75 }];
+    assert_eq!(sample_gradient(&stops, 0.0), red.f32_array());
+    assert_eq!(sample_gradient(&stops, 1.0), blue.f32_array());
+}
+
+#[test]
+fn layer_cache_invalidation() {
+    let mut layer = Layer::new();
+    layer.set_cached(true);
+    assert!(layer.needs_offscreen_target());
+
+    let mut cache = LayerCache::new();
+    cache.add_node(1);
+    cache.add_node(2);
+    assert!(cache.needs_render());
+    cache.mark_rendered();
+
+    // Changes to nodes of other layers don't invalidate the cache.
+    assert!(!cache.mark_dirty(5));
+    assert!(!cache.needs_render());
+
+    assert!(cache.mark_dirty(2));
+    assert!(!cache.mark_dirty(1));
+    assert!(cache.needs_render());
+    cache.mark_rendered();
+
+    cache.remove_node(7);
+    assert!(!cache.needs_render());
+    cache.remove_node(1);
+    assert!(cache.needs_render());
+}
+
+#[test]
+fn vector_image_tiles() {
+    let pattern = VectorImagePattern {
+        image: VectorImageId::new(0),
+        tile: rect(0.0, 0.0, 10.0, 20.0),
+        is_opaque: false,
+    };
+
+    assert_eq!(
+        pattern.tile_offsets(&rect(5.0, 0.0, 10.0, 20.0)),
+        vec![vec2(0.0, 0.0), vec2(10.0, 0.0)]
+    );
+    assert_eq!(
+        pattern.tile_offsets(&rect(-5.0, -5.0, 10.0, 10.0)),
+        vec![vec2(-10.0, -20.0), vec2(0.0, -20.0), vec2(-10.0, 0.0), vec2(0.0, 0.0)]
+    );
+    assert!(pattern.tile_offsets(&rect(0.0, 0.0, 0.0, 10.0)).is_empty());
+}
+
+#[test]
+fn stroke_style_options() {
+    let style = StrokeStyle::new(Pattern::Color(Color::black()), 3.0)
+        .with_line_cap(LineCap::Round)
+        .with_end_cap(LineCap::Square)
+        .with_line_join(LineJoin::Bevel);
+
+    let options = style.tessellation_options(0.25);
+    assert_eq!(options.start_cap, LineCap::Round);
+    assert_eq!(options.end_cap, LineCap::Square);
+    assert_eq!(options.line_join, LineJoin::Bevel);
+    assert_eq!(options.line_width, 3.0);
+    assert_eq!(options.tolerance, 0.25);
+    assert!(!options.apply_line_width);
+
+    let default = StrokeStyle::new(Pattern::Color(Color::black()), 1.0);
+    assert_eq!(default.start_cap, LineCap::Butt);
+    assert_eq!(default.line_join, LineJoin::Miter);
+}
@@ -1,18 +1,34 @@
 use std::sync::Arc;
+use std::fmt;
 use std::default::Default;
-use std::collections::HashMap;
-use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::cmp::Reverse;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+use std::sync::mpsc;
+#[cfg(not(feature = "parallel"))]
+use std::sync::Mutex;
+#[cfg(not(feature = "parallel"))]
+use std::thread;
+#[cfg(not(feature = "parallel"))]
+use std::panic;
 
 use api::*;
 use buffer::*;
 use path::Path;
 use path_iterator::*;
 use glsl::PRIM_BUFFER_LEN;
-use renderer::{ GpuFillVertex, GpuStrokeVertex };
-use renderer::{ GpuFillPrimitive, GpuStrokePrimitive };
-use renderer::{ FillPrimitiveId, StrokePrimitiveId, WithId };
+use renderer::{ GpuFillVertex, GpuStrokeVertex, GpuPositionVertex };
+use renderer::{ GpuFillPrimitive, GpuStrokePrimitive, GpuInstance, GpuTransform };
+use renderer::{ FillPrimitiveId, FillPrimitiveElement, StrokePrimitiveId, WithId, ClipId };
+use quantize::Dequantization;
+use caps::DeviceCapabilities;
+#[cfg(test)]
+use renderer::{ PATTERN_KIND_COLOR, PATTERN_KIND_IMAGE };
 use frame::{
-    FillVertexBufferRange, IndexBufferRange,
+    IndexBufferRange,
     //StrokeVertexBufferRange,
 };
 
@@ -20,14 +36,60 @@ use core::math::*;
 use tessellation::basic_shapes;
 use tessellation::*;
 //use tessellation::path_stroke::*;
-use tessellation::geometry_builder::{ VertexBuffers, BuffersBuilder };
+use tessellation::geometry_builder::{ VertexBuffers, BuffersBuilder, VertexConstructor };
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 pub type Geometry<VertexType> = VertexBuffers<VertexType>;
 
 /// Contains a vbo ibo pair and a map of thier allocations.
+///
+/// Geometry is added by `OpaqueBatcher::build` the first time a shape is drawn and stays in
+/// the store until it is removed. Removing geometry only forgets its ranges: the space is
+/// reclaimed by `compact`, which moves the remaining geometry, so the commands built before
+/// compacting must be rebuilt. Applications that stream content can bound the size of the
+/// store with `evict_to_budget`, which removes the least recently drawn shapes; shapes that
+/// are drawn again after being evicted are tessellated again.
+///
+/// Tessellating many shapes at once (new content, level of detail changes, edited shapes)
+/// can take several frames worth of time. `set_tessellation_budget` bounds the time `build`
+/// spends tessellating in a frame: the shapes that don't fit are deferred to the next frames
+/// and their render nodes are not drawn until then. Edited shapes are marked with
+/// `invalidate` and keep drawing their current geometry until the new one is complete, so
+/// the geometry of a shape is always replaced at once.
+///
+/// Shapes can also be tessellated on another thread with `tessellate_async`. The geometry
+/// is added to the store by `poll_tessellation` once it is complete, until then `build`
+/// treats the shape like a deferred one.
+///
+/// Different shapes often have the same content, for example the same rounded rectangle
+/// or marker added several times to the `ShapeStore`. With `set_shape_cache`, shapes are
+/// looked up by their content, tolerance and the options of the vertex builder (see
+/// `VertexBuilder::options_key`) before being tessellated and share the geometry of an
+/// identical shape if there is one. Their commands then refer to the same
+/// ranges and can be instanced together.
 pub struct GeometryStore<Vertex> {
     geom: Geometry<Vertex>,
     ranges: HashMap<ShapeId, GeometryRanges<Vertex>>,
+    // The frame in which each shape was last drawn.
+    last_used: HashMap<ShapeId, u64>,
+    frame: u64,
+    dequantization: HashMap<ShapeId, Dequantization>,
+    // Shapes which geometry is outdated and must be tessellated again.
+    stale: HashSet<ShapeId>,
+    // Shapes that could not be tessellated within the budget.
+    deferred: HashSet<ShapeId>,
+    // Shapes being tessellated on another thread.
+    in_flight: HashSet<ShapeId>,
+    // The shapes that were tessellated and the options key of their vertex builder, by the
+    // hash of their content and options.
+    shape_cache: Option<HashMap<u64, (ShapeId, u64)>>,
+    budget: Option<Duration>,
+    // The time spent tessellating in the current frame.
+    spent: Duration,
+    #[cfg(not(feature = "parallel"))]
+    pool: TessellationPool,
 }
 
 impl<Vertex> GeometryStore<Vertex> {
@@ -35,6 +97,17 @@ impl<Vertex> GeometryStore<Vertex> {
         Self {
             geom: Geometry::new(),
             ranges: HashMap::new(),
+            last_used: HashMap::new(),
+            frame: 0,
+            dequantization: HashMap::new(),
+            stale: HashSet::new(),
+            deferred: HashSet::new(),
+            in_flight: HashSet::new(),
+            shape_cache: None,
+            budget: None,
+            spent: Duration::new(0, 0),
+            #[cfg(not(feature = "parallel"))]
+            pool: TessellationPool::new(),
         }
     }
 
@@ -42,282 +115,3734 @@ impl<Vertex> GeometryStore<Vertex> {
         self.ranges.get(&id)
     }
 
+    /// The constants to read the geometry of a shape, if it was built with quantized
+    /// vertices (see `QuantizedVertexBuilder`).
+    pub fn dequantization(&self, id: ShapeId) -> Option<Dequantization> {
+        self.dequantization.get(&id).cloned()
+    }
+
+    /// The vertices and indices of all the shapes in the store.
+    pub fn geometry(&self) -> &Geometry<Vertex> { &self.geom }
+
+    /// The number of shapes that have been tessellated into the store.
+    pub fn len(&self) -> usize { self.ranges.len() }
+
     pub fn clear(&mut self) {
         self.geom.vertices.clear();
         self.geom.indices.clear();
         self.ranges.clear();
+        self.last_used.clear();
+        self.dequantization.clear();
+        self.stale.clear();
+        self.deferred.clear();
+        self.in_flight.clear();
+        if let Some(ref mut cache) = self.shape_cache {
+            cache.clear();
+        }
     }
-}
 
-pub struct ShapeStore {
-    paths: Vec<Arc<Path>>,
-}
+    /// Enables or disables sharing the geometry of shapes with the same content.
+    ///
+    /// Disabled by default, since hashing the content of every new shape is wasted work
+    /// for content without duplicates.
+    pub fn set_shape_cache(&mut self, enabled: bool) {
+        if enabled != self.shape_cache.is_some() {
+            self.shape_cache = if enabled { Some(HashMap::new()) } else { None };
+        }
+    }
 
-impl ShapeStore {
-    pub fn new() -> Self { Self { paths: Vec::new() } }
+    /// Forgets the geometry of a shape. Returns false if the shape had no geometry.
+    pub fn remove(&mut self, id: ShapeId) -> bool {
+        self.last_used.remove(&id);
+        self.dequantization.remove(&id);
+        self.stale.remove(&id);
+        self.deferred.remove(&id);
+        self.in_flight.remove(&id);
+        return self.ranges.remove(&id).is_some();
+    }
 
-    pub fn get_path(&self, id: PathId) -> &Arc<Path> {
-        &self.paths[id.index()]
+    /// Marks the geometry of a shape as outdated, for example after its path was edited or
+    /// when it needs a different level of detail.
+    ///
+    /// The current geometry is drawn until the shape is tessellated again by `build`.
+    pub fn invalidate(&mut self, id: ShapeId) {
+        if self.ranges.contains_key(&id) {
+            self.stale.insert(id);
+        }
     }
-}
 
-#[derive(Copy, Clone, Debug)]
-pub struct PrimitiveParams<Style> {
-    pub z_index: u32,
-    pub shape: ShapeId,
-    pub transforms: Transforms,
-    pub style: Style,
-}
+    /// Limits the time spent tessellating shapes in each frame, or removes the limit with
+    /// `None` (the default).
+    ///
+    /// The budget is checked before tessellating each shape, so it can be exceeded by the
+    /// time it takes to tessellate one shape.
+    pub fn set_tessellation_budget(&mut self, budget: Option<Duration>) {
+        self.budget = budget;
+    }
 
-#[derive(Copy, Clone, Debug)]
-pub struct Transforms {
-    pub local: Option<TransformId>,
-    pub view: Option<TransformId>,
-}
+    /// The time spent tessellating shapes since the last call to `end_frame`.
+    pub fn tessellation_time(&self) -> Duration { self.spent }
 
-pub trait VertexBuilder<PrimitiveId, Vertex> {
+    /// Whether some shapes are waiting to be tessellated, in which case the render nodes
+    /// must be built again in the next frames to draw the complete content.
+    pub fn has_pending_tessellation(&self) -> bool {
+        !self.stale.is_empty() || !self.deferred.is_empty() || !self.in_flight.is_empty()
+    }
 
-    fn add_path(
+    // Tessellates a shape, or reuses the geometry of a shape with the same content.
+    fn tessellate_or_share<PrimitiveId, VtxBuilder>(
         &mut self,
-        path: &Path,
+        shapes: &ShapeStore,
+        id: ShapeId,
         prim_id: PrimitiveId,
-        tolerance: f32,
-        geom: &mut Geometry<Vertex>
-    ) -> GeometryRanges<Vertex>;
+        geom_builder: &mut VtxBuilder,
+    ) -> Result<GeometryRanges<Vertex>, GeometryOverflow>
+    where
+        PrimitiveId: PrimitiveIndex,
+        VtxBuilder: VertexBuilder<PrimitiveId, Vertex>,
+    {
+        let source = ShapeSource::new(shapes, id);
+        let options = geom_builder.options_key();
+        let key = self.shape_cache.as_ref().map(|_| source.content_hash(options));
+        let cached = match (key, self.shape_cache.as_ref()) {
+            (Some(key), Some(cache)) => cache.get(&key).cloned(),
+            _ => None,
+        };
+        if let Some((cached, cached_options)) = cached {
+            let shared = if cached != id && cached_options == options && !self.stale.contains(&cached) {
+                self.ranges.get(&cached).cloned()
+            } else {
+                None
+            };
+            // Guard against hash collisions.
+            if let Some(geom) = shared {
+                if source.same_content(&ShapeSource::new(shapes, cached)) {
+                    let dequantization = self.dequantization.get(&cached).cloned();
+                    self.publish(id, geom, dequantization);
+                    return Ok(geom);
+                }
+            }
+        }
 
-    fn add_circle(
-        &mut self,
-        center: Point,
-        radius: f32,
-        prim_id: FillPrimitiveId,
-        tolerance: f32,
-        geom: &mut Geometry<Vertex>
-    ) -> GeometryRanges<Vertex>;
-}
+        // The geometry is shared by all the primitives drawing the shape, the vertices
+        // don't refer to the primitive of the first one.
+        let geom = source.tessellate(prim_id.with_index(0), geom_builder, &mut self.geom);
+        if geom_builder.overflowed() {
+            return Err(self.discarded_geometry(id));
+        }
+        self.publish(id, geom, geom_builder.dequantization());
+        if let (Some(key), Some(cache)) = (key, self.shape_cache.as_mut()) {
+            cache.insert(key, (id, options));
+        }
 
-pub trait PrimitiveBuilder<PrimitiveId, Params> {
-    fn alloc_id(&mut self) -> PrimitiveId;
-    fn build_primtive(&mut self, id: PrimitiveId, params: &Params);
-}
+        return Ok(geom);
+    }
 
-#[derive(Clone)]
-pub struct Cmd<Vertex> {
-    pub geometry: GeometryRanges<Vertex>,
-    pub instances: u32,
-}
+    // The error for a shape which geometry was discarded by the vertex builder, because it
+    // didn't fit in the 16 bit indices along with the geometry of the store.
+    fn discarded_geometry(&self, id: ShapeId) -> GeometryOverflow {
+        GeometryOverflow {
+            shape: id,
+            vertices: (self.geom.vertices.len() + 1).max(MAX_STORE_VERTICES + 1),
+        }
+    }
 
-pub struct OpaqueBatcher<PrimitiveId, Params> {
-    render_nodes: Vec<PrimitiveParams<Params>>,
-    allocated_primitives: Vec<Option<PrimitiveId>>,
-}
+    // The geometry to draw a shape with in `OpaqueBatcher::build`, tessellating the shape
+    // if it is new or outdated and the budget allows it. Returns None if the shape has no
+    // geometry yet.
+    fn geometry_for_build<PrimitiveId, VtxBuilder>(
+        &mut self,
+        shapes: &ShapeStore,
+        shape: ShapeId,
+        prim_id: PrimitiveId,
+        geom_builder: &mut VtxBuilder,
+    ) -> Result<Option<GeometryRanges<Vertex>>, GeometryOverflow>
+    where
+        PrimitiveId: PrimitiveIndex,
+        VtxBuilder: VertexBuilder<PrimitiveId, Vertex>,
+    {
+        let current = self.ranges.get(&shape).cloned();
+        let outdated = (current.is_none() || self.stale.contains(&shape))
+            && !self.in_flight.contains(&shape);
+        let geometry = if outdated && self.can_tessellate() {
+            let start = Instant::now();
+            let geom = self.tessellate_or_share(shapes, shape, prim_id, geom_builder);
+            self.spent += start.elapsed();
 
-impl<PrimitiveId: Copy, Params> OpaqueBatcher<PrimitiveId, Params> {
-    pub fn new() -> Self {
-        Self {
-            render_nodes: Vec::new(),
-            allocated_primitives: Vec::new(),
-        }
+            geom?
+        } else {
+            if outdated {
+                self.deferred.insert(shape);
+            }
+            match current {
+                Some(geom) => geom,
+                None => { return Ok(None); }
+            }
+        };
+        self.mark_used(shape);
+
+        return Ok(Some(geometry));
     }
 
-    pub fn push_item(&mut self, params: PrimitiveParams<Params>){
-        self.render_nodes.push(params);
-        self.allocated_primitives.push(None);
+    fn can_tessellate(&self) -> bool {
+        self.budget.map_or(true, |budget| self.spent < budget)
     }
 
-    pub fn clear(&mut self) {
-        self.render_nodes.clear();
-        self.allocated_primitives.clear();
+    // Replaces the geometry of a shape with newly tessellated geometry.
+    fn publish(&mut self, id: ShapeId, ranges: GeometryRanges<Vertex>, dequantization: Option<Dequantization>) {
+        self.ranges.insert(id, ranges);
+        match dequantization {
+            Some(dequantization) => { self.dequantization.insert(id, dequantization); }
+            None => { self.dequantization.remove(&id); }
+        }
+        self.stale.remove(&id);
+        self.deferred.remove(&id);
     }
 
-    pub fn build<VtxBuilder, PrimBuilder, Vertex>(
+    /// Tessellates a shape on another thread, on rayon's thread pool with the `parallel`
+    /// feature. Otherwise the store starts `TESSELLATION_THREADS` threads with its first
+    /// asynchronous tessellation and the shapes wait for one of them to be available.
+    ///
+    /// The shape keeps its current geometry, if any, until the returned handle is completed
+    /// with `poll_tessellation` or `wait_for_tessellation`. Removing the shape or clearing
+    /// the store in the meantime discards the result.
+    ///
+    /// Like the geometry tessellated by `build`, the vertices are shared by all the render
+    /// nodes drawing the shape and don't refer to their primitives: `prim_id` only selects
+    /// the primitive page.
+    pub fn tessellate_async<PrimitiveId, VtxBuilder>(
         &mut self,
         shapes: &ShapeStore,
-        geom_store: &mut GeometryStore<Vertex>,
-        geom_builder: &mut VtxBuilder,
-        prim_builder: &mut PrimBuilder,
-    ) -> Vec<Cmd<Vertex>>
+        shape: ShapeId,
+        prim_id: PrimitiveId,
+        mut geom_builder: VtxBuilder,
+    ) -> TessellationHandle<Vertex>
     where
-        VtxBuilder: VertexBuilder<PrimitiveId, Vertex>,
-        PrimBuilder: PrimitiveBuilder<PrimitiveId, PrimitiveParams<Params>>
+        PrimitiveId: PrimitiveIndex + Send + 'static,
+        VtxBuilder: VertexBuilder<PrimitiveId, Vertex> + Send + 'static,
+        Vertex: Send + 'static,
     {
-        // This is a gross overestimate if commands get merged through batching or instancing.
-        let mut cmds = Vec::with_capacity(self.render_nodes.len());
+        let source = ShapeSource::new(shapes, shape);
+        let (sender, receiver) = mpsc::channel();
+        self.spawn_tessellation(move || {
+            let mut geom = Geometry::new();
+            source.tessellate(prim_id.with_index(0), &mut geom_builder, &mut geom);
+            let result = if geom_builder.overflowed() {
+                None
+            } else {
+                Some((geom, geom_builder.dequantization()))
+            };
+            // The handle may have been dropped.
+            let _ = sender.send(result);
+        });
 
+        self.in_flight.insert(shape);
+        self.deferred.remove(&shape);
 
-        // Go through render nodes in reverse order to make it more likely that
-        // primitives are rendered front to back.
-        for index in (0..self.render_nodes.len()).rev() {
-            let node = &mut self.render_nodes[index];
-            let allocated_primitive = &mut self.allocated_primitives[index];
+        return TessellationHandle { shape: shape, receiver: receiver };
+    }
 
-            let prim_id = allocated_primitive.unwrap_or_else(&mut||{
-                let prim_id = prim_builder.alloc_id();
-                *allocated_primitive = Some(prim_id);
-                prim_id
-            });
+    /// Adds the geometry of the completed handles to the store and removes them from
+    /// `handles`, without blocking. Returns the number of shapes that were added.
+    ///
+    /// Handles which tessellation failed are removed as well, their shapes are tessellated
+    /// by `build` like new shapes. If the geometry of a shape doesn't fit in the store, its
+    /// handle is removed and the error returned, the other handles can be polled again.
+    pub fn poll_tessellation(
+        &mut self,
+        handles: &mut Vec<TessellationHandle<Vertex>>,
+    ) -> Result<usize, GeometryOverflow> {
+        let mut completed = 0;
+        let mut i = 0;
+        while i < handles.len() {
+            match handles[i].receiver.try_recv() {
+                Ok(result) => {
+                    let shape = handles.swap_remove(i).shape;
+                    if self.publish_async(shape, result)? {
+                        completed += 1;
+                    }
+                }
+                Err(mpsc::TryRecvError::Empty) => {
+                    i += 1;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    let shape = handles.swap_remove(i).shape;
+                    self.in_flight.remove(&shape);
+                }
+            }
+        }
 
-            prim_builder.build_primtive(prim_id, node);
+        return Ok(completed);
+    }
 
-            let draw_cmd = Cmd {
-                geometry: match geom_store.ranges.entry(node.shape) {
-                    Entry::Occupied(entry) => {
-                        *entry.get()
-                    }
-                    Entry::Vacant(entry) => {
-                        match node.shape {
-                            ShapeId::Path(path_id) => {
-                                // TODO: move this to a worker thread?
-                                let tolerance = 0.5;
-                                let geom = geom_builder.add_path(
-                                    &*shapes.get_path(path_id),
-                                    prim_id,
-                                    tolerance,
-                                    &mut geom_store.geom,
-                                );
-                                entry.insert(geom);
-
-                                geom
-                            }
-                            _ => { unimplemented!(); }
-                        }
-                    },
-                },
-                instances: 1,
-            };
+    /// Blocks until a shape is tessellated and adds its geometry to the store. Returns
+    /// false if the tessellation failed or the shape was removed in the meantime, and an
+    /// error if the geometry doesn't fit in the store.
+    pub fn wait_for_tessellation(&mut self, handle: TessellationHandle<Vertex>) -> Result<bool, GeometryOverflow> {
+        match handle.receiver.recv() {
+            Ok(result) => self.publish_async(handle.shape, result),
+            Err(_) => {
+                self.in_flight.remove(&handle.shape);
+                Ok(false)
+            }
+        }
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn spawn_tessellation<F: FnOnce() + Send + 'static>(&mut self, job: F) {
+        self.pool.spawn(job);
+    }
+
+    #[cfg(feature = "parallel")]
+    fn spawn_tessellation<F: FnOnce() + Send + 'static>(&mut self, job: F) {
+        ::rayon::spawn(job);
+    }
+
+    fn publish_async(
+        &mut self,
+        id: ShapeId,
+        result: AsyncGeometry<Vertex>,
+    ) -> Result<bool, GeometryOverflow> {
+        if !self.in_flight.remove(&id) {
+            return Ok(false);
+        }
+
+        let (geom, dequantization) = match result {
+            Some(result) => result,
+            None => { return Err(self.discarded_geometry(id)); }
+        };
+        let vtx_offset = self.geom.vertices.len();
+        // The indices are offset by the vertices already in the store.
+        let vertices = vtx_offset + geom.vertices.len();
+        if vertices > MAX_STORE_VERTICES {
+            return Err(GeometryOverflow { shape: id, vertices: vertices });
+        }
+
+        let idx_offset = self.geom.indices.len();
+        let count = Count {
+            vertices: geom.vertices.len() as u32,
+            indices: geom.indices.len() as u32,
+        };
+        self.geom.vertices.extend(geom.vertices);
+        for index in geom.indices {
+            self.geom.indices.push(index + vtx_offset as u16);
+        }
+        self.publish(id, geometry_ranges(vtx_offset, idx_offset, count), dequantization);
+
+        return Ok(true);
+    }
+
+    /// Records that a shape is drawn in the current frame.
+    pub fn mark_used(&mut self, id: ShapeId) {
+        self.last_used.insert(id, self.frame);
+    }
+
+    /// Starts a new frame, for the purpose of finding the least recently used shapes.
+    pub fn end_frame(&mut self) {
+        self.frame += 1;
+        self.spent = Duration::new(0, 0);
+    }
 
-            // TODO: if current geom == previous geom && prim_id = previous id + 1
-            // just increment the previous command's instance count.
-            // or do it as a later pass ?
+    /// The number of vertices of the shapes in the store.
+    pub fn live_vertices(&self) -> usize {
+        // Shapes sharing their geometry are counted once.
+        let mut starts = HashSet::new();
+        self.ranges.values()
+            .filter(|range| starts.insert(range.vertices.range.start_index()))
+            .map(|range| range.vertices.range.len())
+            .sum()
+    }
+
+    /// The number of vertices of removed shapes, which `compact` would free.
+    pub fn wasted_vertices(&self) -> usize {
+        self.geom.vertices.len() - self.live_vertices()
+    }
+
+    /// Removes the least recently drawn shapes until the store has at most `max_vertices`
+    /// vertices, and compacts it if anything was removed.
+    ///
+    /// The shapes drawn in the current frame are never removed, so the budget can be
+    /// exceeded. Returns the removed shapes, in the order they were removed.
+    pub fn evict_to_budget(&mut self, max_vertices: usize) -> Vec<ShapeId>
+    where Vertex: Clone {
+        let mut evicted = Vec::new();
+        let mut live_vertices = self.live_vertices();
+        if live_vertices > max_vertices {
+            let mut candidates: Vec<(u64, ShapeId, usize)> = Vec::new();
+            for (&id, range) in &self.ranges {
+                let last_used = self.last_used.get(&id).cloned().unwrap_or(0);
+                if last_used < self.frame {
+                    candidates.push((last_used, id, range.vertices.range.len()));
+                }
+            }
+            // Oldest first, the largest first among shapes of the same age. The remaining
+            // ties are broken by id rather than by the order of the hash map, so that the
+            // same scene evicts the same shapes and compacts to the same layout every run.
+            candidates.sort_by(|a, b| a.0.cmp(&b.0).then(b.2.cmp(&a.2)).then(a.1.cmp(&b.1)));
+
+            for (_, id, vertices) in candidates {
+                if live_vertices <= max_vertices {
+                    break;
+                }
+                let start = self.ranges[&id].vertices.range.start_index();
+                self.remove(id);
+                let shared = self.shape_cache.is_some()
+                    && self.ranges.values().any(|range| range.vertices.range.start_index() == start);
+                if !shared {
+                    live_vertices -= vertices;
+                }
+                evicted.push(id);
+            }
+        }
+
+        if self.wasted_vertices() > 0 {
+            self.compact();
+        }
 
-            cmds.push(draw_cmd);
+        return evicted;
+    }
+
+    /// Moves the geometry of the shapes in the store next to each other, freeing the space
+    /// of the removed shapes. This invalidates the ranges of previously built commands.
+    pub fn compact(&mut self) where Vertex: Clone {
+        let mut shapes: Vec<(ShapeId, GeometryRanges<Vertex>)> = self.ranges.iter()
+            .map(|(&id, &range)| (id, range))
+            .collect();
+        shapes.sort_by_key(|&(_, range)| range.vertices.range.start_index());
+
+        let mut geom = Geometry::new();
+        // The new ranges of the geometry shared by several shapes, by their old start.
+        let mut moved: HashMap<usize, GeometryRanges<Vertex>> = HashMap::new();
+        for (id, range) in shapes {
+            if let Some(&new_range) = moved.get(&range.vertices.range.start_index()) {
+                self.ranges.insert(id, new_range);
+                continue;
+            }
+
+            let vtx_offset = geom.vertices.len();
+            let idx_offset = geom.indices.len();
+            let old_vtx_offset = range.vertices.range.start_index();
+            geom.vertices.extend_from_slice(&self.geom.vertices[range.vertices.range.usize_range()]);
+            for &index in &self.geom.indices[range.indices.range.usize_range()] {
+                geom.indices.push((index as usize - old_vtx_offset + vtx_offset) as u16);
+            }
+
+            let count = Count {
+                vertices: range.vertices.range.count() as u32,
+                indices: range.indices.range.count() as u32,
+            };
+            let new_range = geometry_ranges(vtx_offset, idx_offset, count);
+            moved.insert(old_vtx_offset, new_range);
+            self.ranges.insert(id, new_range);
         }
 
-        return cmds;
+        self.geom = geom;
     }
 }
 
-pub struct FillPrimitiveBuilder<'l> {
-    // TODO: move this to a more generic primitive store where data is just put into
-    // a texture like webrender.
-    pub primitives: &'l mut CpuBuffer<GpuFillPrimitive>,
+/// The geometry of a shape being tessellated on another thread, see
+/// `GeometryStore::tessellate_async`.
+pub struct TessellationHandle<Vertex> {
+    shape: ShapeId,
+    receiver: mpsc::Receiver<AsyncGeometry<Vertex>>,
 }
 
-impl<'l> PrimitiveBuilder<FillPrimitiveId, PrimitiveParams<FillStyle>> for FillPrimitiveBuilder<'l> {
-    fn alloc_id(&mut self) -> FillPrimitiveId {
-        self.primitives.alloc()
+// The result of tessellating a shape on another thread, None if the geometry was discarded
+// because 16 bit indices can't address it.
+type AsyncGeometry<Vertex> = Option<(Geometry<Vertex>, Option<Dequantization>)>;
+
+// The number of vertices 16 bit indices can address.
+const MAX_STORE_VERTICES: usize = 1 << 16;
+
+impl<Vertex> TessellationHandle<Vertex> {
+    pub fn shape(&self) -> ShapeId { self.shape }
+}
+
+/// The geometry of a shape would take the store past the vertices that 16 bit indices can
+/// address. `vertices` is the amount of vertices the store would have with the shape.
+///
+/// When the vertex builder discards the geometry while tessellating it (see
+/// `VertexBuilder::overflowed`), its size is unknown and `vertices` is a lower bound.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GeometryOverflow {
+    pub shape: ShapeId,
+    pub vertices: usize,
+}
+
+/// The number of threads tessellating the shapes of `GeometryStore::tessellate_async`
+/// without the `parallel` feature.
+#[cfg(not(feature = "parallel"))]
+pub const TESSELLATION_THREADS: usize = 4;
+
+#[cfg(not(feature = "parallel"))]
+type TessellationJob = Box<FnMut() + Send>;
+
+// The threads tessellating the shapes of a store, started with the first job. They stop
+// once the store is dropped and the queued jobs are done.
+#[cfg(not(feature = "parallel"))]
+struct TessellationPool {
+    sender: Option<mpsc::Sender<TessellationJob>>,
+}
+
+#[cfg(not(feature = "parallel"))]
+impl TessellationPool {
+    fn new() -> Self {
+        TessellationPool { sender: None }
     }
 
-    fn build_primtive(&mut self, id: FillPrimitiveId, params: &PrimitiveParams<FillStyle>) {
-        let default_transform = TransformId { buffer: BufferId::new(0), element: Id::new(0) };
-        self.primitives[id] = GpuFillPrimitive {
-            color: match params.style.pattern {
-                Pattern::Color(color) => { color.f32_array() }
-                _ => { unimplemented!(); }
-            },
-            z_index: params.z_index as f32 / 10000.0,
-            local_transform: params.transforms.local.unwrap_or(default_transform).element.to_i32(),
-            view_transform: params.transforms.view.unwrap_or(default_transform).element.to_i32(),
-            width: 0.0,
-            .. Default::default()
-        };
+    fn spawn<F: FnOnce() + Send + 'static>(&mut self, job: F) {
+        if self.sender.is_none() {
+            let (sender, receiver) = mpsc::channel::<TessellationJob>();
+            let receiver = Arc::new(Mutex::new(receiver));
+            for _ in 0..TESSELLATION_THREADS {
+                let receiver = Arc::clone(&receiver);
+                thread::spawn(move || {
+                    loop {
+                        // The lock is released before running the job.
+                        let job = receiver.lock().unwrap().recv();
+                        match job {
+                            // A job that panics drops its sender, its handle is then
+                            // completed like a failed tessellation.
+                            Ok(mut job) => {
+                                let _ = panic::catch_unwind(panic::AssertUnwindSafe(|| job()));
+                            }
+                            Err(_) => { return; }
+                        }
+                    }
+                });
+            }
+            self.sender = Some(sender);
+        }
+
+        let mut job = Some(job);
+        let job: TessellationJob = Box::new(move || {
+            if let Some(job) = job.take() {
+                job();
+            }
+        });
+        let _ = self.sender.as_ref().unwrap().send(job);
     }
 }
 
-pub struct FillVertexBuilder {
-    tessellator: FillTessellator,
+pub struct ShapeStore {
+    paths: Vec<Arc<Path>>,
+    ellipses: Vec<Ellipse>,
+    rects: Vec<Rect>,
+    rounded_rects: Vec<RoundedRect>,
+    glyphs: HashMap<GlyphKey, Arc<Path>>,
+    groups: Vec<Vec<ShapeId>>,
+    tolerance: f32,
 }
 
-impl FillVertexBuilder {
+impl ShapeStore {
     pub fn new() -> Self {
         Self {
-            tessellator: FillTessellator::new(),
+            paths: Vec::new(),
+            ellipses: Vec::new(),
+            rects: Vec::new(),
+            rounded_rects: Vec::new(),
+            glyphs: HashMap::new(),
+            groups: Vec::new(),
+            tolerance: 0.5,
         }
     }
-}
 
-impl VertexBuilder<FillPrimitiveId, GpuFillVertex> for FillVertexBuilder {
+    /// Sets the tolerance used to flatten the curves of paths, ellipses and rounded
+    /// rectangles (0.5 by default). Glyphs use the tolerance of their key instead.
+    ///
+    /// The geometry already in the `GeometryStore` keeps its level of detail until the
+    /// shapes are invalidated.
+    pub fn set_tolerance(&mut self, tolerance: f32) {
+        assert!(tolerance > 0.0);
+        self.tolerance = tolerance;
+    }
+
+    pub fn tolerance(&self) -> f32 { self.tolerance }
 
-    fn add_path(
-        &mut self,
-        path: &Path,
-        prim_id: FillPrimitiveId,
-        tolerance: f32,
-        geom: &mut Geometry<GpuFillVertex>
-    ) -> GeometryRanges<GpuFillVertex> {
-        let vtx_offset = geom.vertices.len();
-        let idx_offset = geom.indices.len();
+    pub fn add_path(&mut self, path: Arc<Path>) -> PathId {
+        self.paths.push(path);
+        return Id::new(self.paths.len() as u16 - 1);
+    }
 
-        let count = self.tessellator.tessellate_flattened_path(
-            path.path_iter().flattened(tolerance),
-            &FillOptions::default(),
-            &mut BuffersBuilder::new(geom, WithId(prim_id))
-        ).unwrap();
+    pub fn add_ellipse(&mut self, ellipse: Ellipse) -> EllipseId {
+        self.ellipses.push(ellipse);
+        return Id::new(self.ellipses.len() as u16 - 1);
+    }
 
-        return FillGeometryRanges {
-            vertices: FillVertexBufferRange {
-                buffer: BufferId::new(0),
-                range: IdRange::from_start_count(vtx_offset as u16, count.vertices as u16),
-            },
-            indices: IndexBufferRange {
-                buffer: BufferId::new(0),
-                range: IdRange::from_start_count(idx_offset as u16, count.indices as u16),
-            },
-        };
+    pub fn add_rect(&mut self, rect: Rect) -> RectId {
+        self.rects.push(rect);
+        return Id::new(self.rects.len() as u16 - 1);
     }
 
-    fn add_circle(
-        &mut self,
-        center: Point,
-        radius: f32,
-        prim_id: FillPrimitiveId,
-        tolerance: f32,
-        geom: &mut Geometry<GpuFillVertex>
-    ) -> GeometryRanges<GpuFillVertex> {
-        let vtx_offset = geom.vertices.len();
-        let idx_offset = geom.indices.len();
+    /// Registers a rectangle with rounded corners, which is tessellated directly rather
+    /// than through a path.
+    pub fn add_rounded_rect(&mut self, rect: RoundedRect) -> RoundedRectId {
+        self.rounded_rects.push(rect);
+        return Id::new(self.rounded_rects.len() as u16 - 1);
+    }
 
-        let count = basic_shapes::fill_circle(
-            center, radius, tolerance,
-            &mut BuffersBuilder::new(geom, WithId(prim_id))
-        );
+    /// Registers the outline of a glyph.
+    ///
+    /// Unlike paths, glyphs are identified by their key rather than by the order in which
+    /// they are added: render nodes drawing the same glyph share its geometry, which is
+    /// tessellated once into the `GeometryStore` and then drawn with the primitive of each
+    /// node. Adding a glyph that is already in the store keeps the existing outline.
+    pub fn add_glyph(&mut self, key: GlyphKey, path: Arc<Path>) -> ShapeId {
+        self.glyphs.entry(key).or_insert(path);
+        return ShapeId::Glyph(key);
+    }
 
-        return FillGeometryRanges {
-            vertices: FillVertexBufferRange {
-                buffer: BufferId::new(0),
-                range: IdRange::from_start_count(vtx_offset as u16, count.vertices as u16),
-            },
-            indices: IndexBufferRange {
-                buffer: BufferId::new(0),
-                range: IdRange::from_start_count(idx_offset as u16, count.indices as u16),
-            },
-        };
+    /// Registers shapes that are always drawn together with the same style and transforms.
+    ///
+    /// The shapes of a group are tessellated into a single range of the `GeometryStore`
+    /// and drawn with a single primitive, which is much cheaper than a render node per
+    /// shape for things like the markers of a scatter plot. Groups can't contain other
+    /// groups and, like other shapes, their geometry must fit in the 16 bits indices.
+    pub fn add_group<I: IntoIterator<Item = ShapeId>>(&mut self, shapes: I) -> GroupId {
+        let shapes: Vec<ShapeId> = shapes.into_iter().collect();
+        debug_assert!(shapes.iter().all(|shape| match *shape {
+            ShapeId::Group(_) | ShapeId::None => false,
+            _ => true,
+        }));
+        self.groups.push(shapes);
+        return Id::new(self.groups.len() as u16 - 1);
+    }
+
+    /// Returns whether the store has a shape for this id.
+    pub fn contains(&self, id: ShapeId) -> bool {
+        match id {
+            ShapeId::Path(id) => id.index() < self.paths.len(),
+            ShapeId::Ellipse(id) => id.index() < self.ellipses.len(),
+            ShapeId::Rect(id) => id.index() < self.rects.len(),
+            ShapeId::RoundedRect(id) => id.index() < self.rounded_rects.len(),
+            ShapeId::Glyph(key) => self.glyphs.contains_key(&key),
+            ShapeId::Group(id) => {
+                id.index() < self.groups.len()
+                    && self.groups[id.index()].iter().all(|&shape| self.contains(shape))
+            }
+            ShapeId::None => false,
+        }
     }
-}
 
+    pub fn get_path(&self, id: PathId) -> &Arc<Path> {
+        &self.paths[id.index()]
+    }
 
-impl<T> Copy for GeometryRanges<T> {}
-impl<T> Clone for GeometryRanges<T> { fn clone(&self) -> Self { *self } }
-#[derive(Debug)]
-pub struct GeometryRanges<Vertex> {
-    pub vertices: BufferRange<Vertex>,
-    pub indices: IndexBufferRange,
-}
+    pub fn get_ellipse(&self, id: EllipseId) -> &Ellipse {
+        &self.ellipses[id.index()]
+    }
 
-pub type FillGeometryRanges = GeometryRanges<GpuFillVertex>;
-pub type StrokeGeometryRanges = GeometryRanges<GpuStrokeVertex>;
+    pub fn get_rect(&self, id: RectId) -> &Rect {
+        &self.rects[id.index()]
+    }
 
+    pub fn get_rounded_rect(&self, id: RoundedRectId) -> &RoundedRect {
+        &self.rounded_rects[id.index()]
+    }
 
-#[test]
-fn simple_opaque_builder() {
-    let mut batcher = OpaqueBatcher::new();
-    let shapes = ShapeStore::new();
-    let mut geom = GeometryStore::new();
-    let mut primitives = CpuBuffer::new(1024);
+    pub fn get_glyph(&self, key: GlyphKey) -> &Arc<Path> {
+        &self.glyphs[&key]
+    }
 
-    let _cmds = batcher.build(
-        &shapes,
-        &mut geom,
-        &mut FillVertexBuilder::new(),
-        &mut FillPrimitiveBuilder { primitives: &mut primitives },
+    pub fn get_group(&self, id: GroupId) -> &[ShapeId] {
+        &self.groups[id.index()]
+    }
+
+    /// The bounding rectangle of a shape in its local space, or None if the shape is not
+    /// in the store or is empty.
+    pub fn bounds(&self, id: ShapeId) -> Option<Rect> {
+        if !self.contains(id) {
+            return None;
+        }
+
+        let bounds = match id {
+            ShapeId::Path(id) => path_bounds(self.get_path(id)),
+            ShapeId::Glyph(key) => path_bounds(self.get_glyph(key)),
+            ShapeId::Ellipse(id) => {
+                let ellipse = self.get_ellipse(id);
+                let radii = vec2(ellipse.radii.x.abs(), ellipse.radii.y.abs());
+                Some(Rect::new(ellipse.center - radii, size(radii.x * 2.0, radii.y * 2.0)))
+            }
+            ShapeId::Rect(id) => Some(*self.get_rect(id)),
+            ShapeId::RoundedRect(id) => Some(self.get_rounded_rect(id).rect),
+            ShapeId::Group(id) => {
+                self.get_group(id).iter().fold(None, |result: Option<Rect>, &shape| {
+                    match (result, self.bounds(shape)) {
+                        (Some(a), Some(b)) => Some(a.union(&b)),
+                        (a, b) => a.or(b),
+                    }
+                })
+            }
+            ShapeId::None => None,
+        };
+
+        return bounds;
+    }
+}
+
+fn path_bounds(path: &Path) -> Option<Rect> {
+    if path.points().is_empty() { None } else { Some(path.bounding_rect()) }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PrimitiveParams<Style> {
+    pub z_index: u32,
+    pub shape: ShapeId,
+    pub transforms: Transforms,
+    pub style: Style,
+}
+
+/// The parts of the style of a render node that decide which pass it can be drawn in.
+pub trait NodeStyle {
+    /// The rounded rectangle the node is clipped to.
+    ///
+    /// The fragments outside of a clip are discarded and its edges blend, so clipped
+    /// nodes are drawn in the transparent pass. The depth pre-pass would otherwise write
+    /// the depth of the clipped out fragments and hide what is behind them.
+    fn clip(&self) -> Option<ClipId> { None }
+
+    /// Whether the primitives of the node can be drawn with its pattern.
+    fn supports_pattern(&self) -> bool { true }
+
+    /// The mask of the node. Masked nodes are drawn offscreen, see `MaskedCmd`.
+    fn mask(&self) -> Option<Mask> { None }
+}
+
+impl NodeStyle for FillStyle {
+    fn clip(&self) -> Option<ClipId> { self.clip }
+
+    fn supports_pattern(&self) -> bool { GpuFillPrimitive::supports_pattern(&self.pattern) }
+
+    fn mask(&self) -> Option<Mask> { self.mask }
+}
+
+impl NodeStyle for StrokeStyle {
+    fn supports_pattern(&self) -> bool { GpuStrokePrimitive::supports_pattern(&self.pattern) }
+
+    fn mask(&self) -> Option<Mask> { self.mask }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Transforms {
+    pub local: Option<TransformId>,
+    pub view: Option<TransformId>,
+    /// The placement of the render node, applied between the local and view transforms.
+    ///
+    /// This lets the same content (with its own local transforms) be stamped at several
+    /// positions, see `OpaqueBatcher::push_with_transform`.
+    pub root: Option<TransformId>,
+}
+
+/// Hands out the z-indices of primitives.
+///
+/// By default z-indices increase in call order, which matches the paint order of
+/// immediate-mode drawing. Callers converting a retained scene graph where the paint
+/// order is known up front can jump to an explicit z-index with `set_z_index`, or
+/// reserve a range of z-indices to interleave primitives (for example the fills and
+/// strokes of a sub-tree) in it later.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZIndexAllocator {
+    next: u32,
+}
+
+impl ZIndexAllocator {
+    pub fn new() -> Self {
+        ZIndexAllocator { next: 0 }
+    }
+
+    /// Returns the next z-index.
+    pub fn next_z_index(&mut self) -> u32 {
+        let z_index = self.next;
+        self.next += 1;
+        return z_index;
+    }
+
+    /// Sets the z-index returned by the next call to `next_z_index`.
+    pub fn set_z_index(&mut self, z_index: u32) {
+        self.next = z_index;
+    }
+
+    /// Reserves `count` consecutive z-indices that the allocator will skip.
+    pub fn reserve_z_range(&mut self, count: u32) -> Range<u32> {
+        let start = self.next;
+        self.next += count;
+        return start..self.next;
+    }
+
+    /// The z-index that the next call to `next_z_index` will return.
+    pub fn current(&self) -> u32 { self.next }
+}
+
+pub trait VertexBuilder<PrimitiveId, Vertex> {
+
+    fn add_path(
+        &mut self,
+        path: &Path,
+        prim_id: PrimitiveId,
+        tolerance: f32,
+        geom: &mut Geometry<Vertex>
+    ) -> GeometryRanges<Vertex>;
+
+    fn add_circle(
+        &mut self,
+        center: Point,
+        radius: f32,
+        prim_id: FillPrimitiveId,
+        tolerance: f32,
+        geom: &mut Geometry<Vertex>
+    ) -> GeometryRanges<Vertex>;
+
+    fn add_ellipse(
+        &mut self,
+        ellipse: &Ellipse,
+        prim_id: PrimitiveId,
+        tolerance: f32,
+        geom: &mut Geometry<Vertex>
+    ) -> GeometryRanges<Vertex>;
+
+    fn add_rect(
+        &mut self,
+        rect: &Rect,
+        prim_id: PrimitiveId,
+        geom: &mut Geometry<Vertex>
+    ) -> GeometryRanges<Vertex>;
+
+    fn add_rounded_rect(
+        &mut self,
+        rect: &RoundedRect,
+        prim_id: PrimitiveId,
+        tolerance: f32,
+        geom: &mut Geometry<Vertex>
+    ) -> GeometryRanges<Vertex>;
+
+    /// The constants to read the geometry added last, for builders producing quantized
+    /// vertices.
+    fn dequantization(&self) -> Option<Dequantization> { None }
+
+    /// Whether the geometry added last was discarded because its vertices could not be
+    /// addressed with the 16 bit indices of the geometry.
+    fn overflowed(&self) -> bool { false }
+
+    /// Identifies the options that change the geometry produced by the builder, besides
+    /// the shape and its tolerance.
+    ///
+    /// The shape cache of the `GeometryStore` only shares geometry between shapes that were
+    /// tessellated with the same key, so builders with different options must return
+    /// different keys.
+    fn options_key(&self) -> u64 { 0 }
+}
+
+pub trait PrimitiveBuilder<PrimitiveId, Params> {
+    fn alloc_id(&mut self) -> PrimitiveId;
+    fn build_primtive(&mut self, id: PrimitiveId, params: &Params);
+
+    /// Returns the blur radius of the shadow of a render node, or `None` if it has no shadow.
+    fn shadow_blur_radius(&self, _params: &Params) -> Option<f32> { None }
+
+    /// Builds the primitive drawing the shadow of a render node.
+    ///
+    /// Only called for render nodes that have a shadow.
+    fn build_shadow(&mut self, _id: PrimitiveId, _params: &Params) {}
+
+    /// Shows or hides a primitive that was built with `build_primtive` or `build_shadow`.
+    fn set_visible(&mut self, _id: PrimitiveId, _visible: bool) {}
+}
+
+/// Primitive ids that can be compared to detect consecutive instances.
+pub trait PrimitiveIndex: Copy {
+    /// The index of the primitive within its page.
+    fn primitive_index(&self) -> u32;
+
+    /// The page (buffer) containing the primitive, for primitives allocated in a
+    /// `BufferStore`. Primitives allocated in a single buffer are all in page 0.
+    fn primitive_page(&self) -> u32 { 0 }
+
+    /// The primitive at another index of the same page.
+    fn with_index(&self, index: u32) -> Self;
+}
+
+impl<T> PrimitiveIndex for Id<T> {
+    fn primitive_index(&self) -> u32 { self.to_u16() as u32 }
+    fn with_index(&self, index: u32) -> Self { Id::new(index as u16) }
+}
+
+impl<T: Copy> PrimitiveIndex for BufferElement<T> {
+    fn primitive_index(&self) -> u32 { self.element.to_u16() as u32 }
+    fn primitive_page(&self) -> u32 { self.buffer.to_u32() }
+    fn with_index(&self, index: u32) -> Self {
+        BufferElement { buffer: self.buffer, element: Id::new(index as u16) }
+    }
+}
+
+/// An instanced draw call.
+///
+/// The primitive of each instance is `first_primitive` plus the instance index, in the
+/// primitive page `primitive_page` which must be bound for the draw. The geometry of a
+/// shape is shared by all the render nodes that draw it, so its vertices don't refer to a
+/// primitive: the shaders read the primitive of each instance from the per-instance
+/// attributes (see `INSTANCED_FILL_VERTEX_SHADER`), which start at `first_instance` in the
+/// batcher's instance stream.
+#[derive(Clone)]
+pub struct Cmd<Vertex> {
+    pub geometry: GeometryRanges<Vertex>,
+    pub first_primitive: u32,
+    pub primitive_page: u32,
+    pub instances: u32,
+    pub first_instance: u32,
+    /// The z-index of the first instance.
+    pub z_index: u32,
+}
+
+impl<Vertex> Cmd<Vertex> {
+    // Adds an instance to this command if it can be drawn along with the existing ones.
+    fn try_merge<PrimitiveId: PrimitiveIndex>(
+        &mut self,
+        geometry: &GeometryRanges<Vertex>,
+        prim_id: PrimitiveId,
+        max_instances: u32,
+    ) -> bool {
+        if self.instances < max_instances
+            && self.geometry == *geometry
+            && self.primitive_page == prim_id.primitive_page()
+            && self.first_primitive + self.instances == prim_id.primitive_index() {
+            self.instances += 1;
+            return true;
+        }
+
+        return false;
+    }
+}
+
+/// A draw call rendering the shadows of render nodes, reusing their geometry.
+///
+/// Shadows are usually translucent and blurred: they are meant to be drawn into an
+/// offscreen target which is composited with `PostEffect::Blur` before the main primitives
+/// are rendered. Their primitives are placed slightly behind the ones they belong to.
+#[derive(Clone)]
+pub struct ShadowCmd<Vertex> {
+    pub cmd: Cmd<Vertex>,
+    pub blur_radius: f32,
+}
+
+/// A draw call rendering masked render nodes.
+///
+/// The coverage of masked nodes depends on their mask, so they can't be drawn in the
+/// opaque pass. The commands are meant to be drawn into an offscreen target, their mask
+/// into another one, and the result composited with `PostProcessor::composite_masked`.
+/// Consecutive commands with the same mask can share these targets.
+///
+/// For `Mask::Shape`, `mask_geometry` is the geometry of the mask shape. It is drawn
+/// with the same instances as `cmd`, so the mask follows the transforms of each node.
+#[derive(Clone)]
+pub struct MaskedCmd<Vertex> {
+    pub cmd: Cmd<Vertex>,
+    pub mask: Mask,
+    pub mask_geometry: Option<GeometryRanges<Vertex>>,
+}
+
+/// Sorts the commands of an opaque pass to minimize state changes.
+///
+/// Commands are grouped by vertex and index buffers and by primitive page so that
+/// consecutive draws share their bindings, and sorted front to back (by decreasing
+/// z-index, since higher z-indices are closer to the viewer) within each group so that the
+/// depth test rejects the hidden fragments before they are shaded. The sort is stable, so
+/// the result only depends on the order of the input.
+///
+/// With the `parallel` feature the commands are sorted on rayon's thread pool, with the
+/// same stable sort, so the result is the same as the serial sort.
+pub fn sort_opaque_cmds<Vertex: MaybeSend>(cmds: &mut [Cmd<Vertex>]) {
+    sort_cmds_by_key(cmds);
+}
+
+/// The bound on the vertex types of the commands built by the batchers.
+///
+/// With the `parallel` feature the commands are sorted on other threads and their vertex
+/// type must be `Send`. Without it, any vertex type can be used.
+#[cfg(feature = "parallel")]
+pub trait MaybeSend: Send {}
+#[cfg(feature = "parallel")]
+impl<T: Send> MaybeSend for T {}
+
+/// The bound on the vertex types of the commands built by the batchers.
+///
+/// With the `parallel` feature the commands are sorted on other threads and their vertex
+/// type must be `Send`. Without it, any vertex type can be used.
+#[cfg(not(feature = "parallel"))]
+pub trait MaybeSend {}
+#[cfg(not(feature = "parallel"))]
+impl<T> MaybeSend for T {}
+
+#[cfg(not(feature = "parallel"))]
+fn sort_cmds_by_key<Vertex>(cmds: &mut [Cmd<Vertex>]) {
+    cmds.sort_by_key(opaque_sort_key);
+}
+
+#[cfg(feature = "parallel")]
+fn sort_cmds_by_key<Vertex: MaybeSend>(cmds: &mut [Cmd<Vertex>]) {
+    cmds.par_sort_by_key(opaque_sort_key);
+}
+
+fn opaque_sort_key<Vertex>(cmd: &Cmd<Vertex>) -> (u32, u32, u32, Reverse<u32>) {
+    (
+        cmd.geometry.vertices.buffer.to_u32(),
+        cmd.geometry.indices.buffer.to_u32(),
+        cmd.primitive_page,
+        Reverse(cmd.z_index),
+    )
+}
+
+/// Two render nodes of a batcher that overlap at the same z-index, see
+/// `OpaqueBatcher::find_depth_conflicts`.
+///
+/// The depth of opaque primitives is derived from their z-index, so where such nodes
+/// overlap they have the same depth and which one is visible depends on the order of the
+/// draw calls rather than on the scene. This shows up as z-fighting once the commands are
+/// merged or sorted differently.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DepthConflict {
+    pub z_index: u32,
+    /// The indices of the render nodes, in push order.
+    pub nodes: (usize, usize),
+    pub shapes: (ShapeId, ShapeId),
+    /// The area where the bounds of the nodes overlap.
+    pub overlap: Rect,
+}
+
+impl fmt::Display for DepthConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Render nodes {} ({:?}) and {} ({:?}) overlap at z-index {} in {:?}. \
+             Give them distinct z-indices, for example with a ZIndexAllocator or set_z_index.",
+            self.nodes.0, self.shapes.0, self.nodes.1, self.shapes.1, self.z_index, self.overlap,
+        )
+    }
+}
+
+/// Errors reported when pushing render nodes to a batcher.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BatchError {
+    /// The render node at `node_index` uses a local transform that was not added to the batcher.
+    UnknownLocalTransform { node_index: usize, transform: TransformId },
+    /// The render node at `node_index` uses a view transform that was not added to the batcher.
+    UnknownViewTransform { node_index: usize, transform: TransformId },
+    /// The render node at `node_index` uses a root transform that was not added to the batcher.
+    UnknownRootTransform { node_index: usize, transform: TransformId },
+    /// The render node at `node_index` uses a transform that is past the end of the
+    /// transform arrays declared in the shaders.
+    TransformOutOfBounds { node_index: usize, transform: TransformId },
+    /// The render node at `node_index` uses a shape that is not in the shape store.
+    UnknownShape { node_index: usize, shape: ShapeId },
+    /// `push_with_transform` could not allocate the root transform of the render node at
+    /// `node_index`, because the transforms that the shaders can read are all in use.
+    TransformBufferFull { node_index: usize },
+    /// The render node at `node_index` passed to `push_with_transform` already has a root
+    /// transform.
+    RootTransformAlreadySet { node_index: usize, transform: TransformId },
+    /// The render node at `node_index` is clipped, and must be drawn in the transparent
+    /// pass. See `NodeStyle::clip`.
+    ClippedNode { node_index: usize, clip: ClipId },
+    /// The render node at `node_index` has a pattern its primitives can't be drawn with.
+    UnsupportedPattern { node_index: usize },
+    /// The geometry of the render node at `node_index` (or of its mask) doesn't fit in the
+    /// geometry store.
+    GeometryOverflow { node_index: usize, overflow: GeometryOverflow },
+}
+
+/// Batches render nodes into draw commands.
+///
+/// The render nodes are stored in the order they are pushed and `build` only depends on
+/// that order and on the content of the nodes, so the same scene always produces the same
+/// commands. The order in which the nodes appear on screen is defined by their z-index:
+/// nodes with a greater z-index are in front. Nodes with the same z-index are drawn in
+/// reverse push order, so where they overlap the depth test keeps the node pushed first.
+/// Applications that need the painter's order of the nodes give them increasing
+/// z-indices, for example with a `ZIndexAllocator`, and reorder them with `set_z_index`.
+pub struct OpaqueBatcher<PrimitiveId, Params> {
+    render_nodes: Vec<PrimitiveParams<Params>>,
+    allocated_primitives: Vec<Option<PrimitiveId>>,
+    allocated_shadows: Vec<Option<PrimitiveId>>,
+    visible: Vec<bool>,
+    transforms: HashSet<TransformId>,
+    instances: Vec<GpuInstance>,
+    max_instances: u32,
+}
+
+impl<PrimitiveId: Copy, Params: NodeStyle> OpaqueBatcher<PrimitiveId, Params> {
+    pub fn new() -> Self {
+        Self {
+            render_nodes: Vec::new(),
+            allocated_primitives: Vec::new(),
+            allocated_shadows: Vec::new(),
+            visible: Vec::new(),
+            transforms: HashSet::new(),
+            instances: Vec::new(),
+            max_instances: u32::max_value(),
+        }
+    }
+
+    /// Limits the number of instances merged into a command, 1 disabling instancing.
+    pub fn set_max_instances(&mut self, max_instances: u32) {
+        self.max_instances = max_instances.max(1);
+    }
+
+    /// Adapts the batching to what the device supports.
+    pub fn configure(&mut self, caps: &DeviceCapabilities) {
+        self.set_max_instances(caps.batch_max_instances());
+    }
+
+    /// Declares a transform that render nodes pushed to this batcher may reference.
+    pub fn add_transform(&mut self, id: TransformId) {
+        self.transforms.insert(id);
+    }
+
+    /// Declares a range of transforms that render nodes pushed to this batcher may reference.
+    pub fn add_transforms(&mut self, range: TransformIdRange) {
+        for id in range.iter() {
+            self.transforms.insert(id);
+        }
+    }
+
+    /// Adds a render node to the batch.
+    ///
+    /// Returns an error without adding the node if it references a transform that was not
+    /// added with `add_transform` or `add_transforms`, rather than silently reading some
+    /// other transform's data on the GPU, if it is clipped or if its pattern is not supported.
+    pub fn push_item(&mut self, params: PrimitiveParams<Params>) -> Result<(), BatchError> {
+        let node_index = self.render_nodes.len();
+        self.check_transforms(node_index, &params.transforms)?;
+        Self::check_style(node_index, &params.style)?;
+
+        self.render_nodes.push(params);
+        self.allocated_primitives.push(None);
+        self.allocated_shadows.push(None);
+        self.visible.push(true);
+
+        return Ok(());
+    }
+
+    /// Adds a render node placed by its own root transform.
+    ///
+    /// The transform is allocated in `transforms`, which must be uploaded with the other
+    /// transforms, and added to the batcher. Stamping the same content at many positions
+    /// only requires one call per position: the local and view transforms of `params` are
+    /// shared, and so is the geometry. Returns the id of the root transform, to move the
+    /// node later by rewriting it.
+    ///
+    /// The node is checked like in `push_item` before allocating the transform, and must
+    /// not have a root transform already.
+    pub fn push_with_transform(
+        &mut self,
+        mut params: PrimitiveParams<Params>,
+        transform: &GpuTransform,
+        transforms: &mut CpuBuffer<GpuTransform>,
+    ) -> Result<TransformId, BatchError> {
+        let node_index = self.render_nodes.len();
+        if let Some(root) = params.transforms.root {
+            return Err(BatchError::RootTransformAlreadySet { node_index: node_index, transform: root });
+        }
+        self.check_transforms(node_index, &params.transforms)?;
+        Self::check_style(node_index, &params.style)?;
+
+        let id = match transforms.try_alloc() {
+            Some(id) if id.index() < PRIM_BUFFER_LEN => id,
+            // The transforms past the arrays declared in the shaders can't be read.
+            _ => { return Err(BatchError::TransformBufferFull { node_index: node_index }); }
+        };
+        transforms[id] = *transform;
+
+        let root = TransformId { buffer: BufferId::new(0), element: Id::new(id.to_u16()) };
+        self.add_transform(root);
+        params.transforms.root = Some(root);
+        self.push_item(params)?;
+
+        return Ok(root);
+    }
+
+    /// Adds a render node drawing many shapes with the same style and transforms.
+    ///
+    /// The shapes are registered as a group (see `ShapeStore::add_group`), so that they
+    /// are tessellated as one batch and drawn with a single primitive.
+    pub fn push_group<I>(
+        &mut self,
+        shapes: &mut ShapeStore,
+        items: I,
+        z_index: u32,
+        transforms: Transforms,
+        style: Params,
+    ) -> Result<GroupId, BatchError>
+    where I: IntoIterator<Item = ShapeId> {
+        self.check_transforms(self.render_nodes.len(), &transforms)?;
+        let group = shapes.add_group(items);
+        self.push_item(PrimitiveParams {
+            z_index: z_index,
+            shape: ShapeId::Group(group),
+            transforms: transforms,
+            style: style,
+        })?;
+
+        return Ok(group);
+    }
+
+    fn check_style(node_index: usize, style: &Params) -> Result<(), BatchError> {
+        if let Some(clip) = style.clip() {
+            return Err(BatchError::ClippedNode { node_index: node_index, clip: clip });
+        }
+        if !style.supports_pattern() {
+            return Err(BatchError::UnsupportedPattern { node_index: node_index });
+        }
+
+        return Ok(());
+    }
+
+    fn check_transforms(&self, node_index: usize, transforms: &Transforms) -> Result<(), BatchError> {
+        for transform in transforms.local.iter().chain(transforms.view.iter()).chain(transforms.root.iter()) {
+            if transform.element.index() >= PRIM_BUFFER_LEN {
+                return Err(BatchError::TransformOutOfBounds {
+                    node_index: node_index,
+                    transform: *transform,
+                });
+            }
+        }
+        if let Some(transform) = transforms.local {
+            if !self.transforms.contains(&transform) {
+                return Err(BatchError::UnknownLocalTransform {
+                    node_index: node_index,
+                    transform: transform,
+                });
+            }
+        }
+        if let Some(transform) = transforms.view {
+            if !self.transforms.contains(&transform) {
+                return Err(BatchError::UnknownViewTransform {
+                    node_index: node_index,
+                    transform: transform,
+                });
+            }
+        }
+        if let Some(transform) = transforms.root {
+            if !self.transforms.contains(&transform) {
+                return Err(BatchError::UnknownRootTransform {
+                    node_index: node_index,
+                    transform: transform,
+                });
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Checks that all the render nodes reference valid shapes and transforms, and returns
+    /// the list of the problems otherwise.
+    ///
+    /// Nodes are already checked against the transforms of the batcher when they are pushed,
+    /// but not against the shape store. This is done in debug builds at the beginning of
+    /// `build`, which returns the first error, and can be called explicitly to report the
+    /// errors in release builds.
+    pub fn validate(&self, shapes: &ShapeStore) -> Result<(), Vec<BatchError>> {
+        let mut errors = Vec::new();
+        for (node_index, node) in self.render_nodes.iter().enumerate() {
+            if !shapes.contains(node.shape) {
+                errors.push(BatchError::UnknownShape {
+                    node_index: node_index,
+                    shape: node.shape,
+                });
+            }
+            if let Some(Mask::Shape(shape)) = node.style.mask() {
+                if !shapes.contains(shape) {
+                    errors.push(BatchError::UnknownShape {
+                        node_index: node_index,
+                        shape: shape,
+                    });
+                }
+            }
+            if let Err(error) = self.check_transforms(node_index, &node.transforms) {
+                errors.push(error);
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        return Ok(());
+    }
+
+    /// Finds the visible render nodes that have the same z-index and overlapping bounds.
+    ///
+    /// The batcher only knows the ids of the transforms of the nodes, so `node_bounds`
+    /// maps the local bounds of the shape of a node (see `ShapeStore::bounds`) to a space
+    /// shared by all the nodes, typically by applying their transforms. If the nodes share
+    /// their transforms it can return the bounds unchanged. Nodes which shape has no bounds
+    /// are ignored.
+    ///
+    /// This compares every pair of nodes with the same z-index, which is meant for
+    /// debugging rather than for every frame.
+    pub fn find_depth_conflicts<F>(&self, shapes: &ShapeStore, mut node_bounds: F) -> Vec<DepthConflict>
+    where F: FnMut(&PrimitiveParams<Params>, &Rect) -> Rect {
+        let mut nodes: Vec<(u32, usize, Rect)> = Vec::new();
+        for (index, node) in self.render_nodes.iter().enumerate() {
+            if !self.visible[index] {
+                continue;
+            }
+            if let Some(bounds) = shapes.bounds(node.shape) {
+                nodes.push((node.z_index, index, node_bounds(node, &bounds)));
+            }
+        }
+        nodes.sort_by_key(|&(z_index, index, _)| (z_index, index));
+
+        let mut conflicts = Vec::new();
+        let mut start = 0;
+        while start < nodes.len() {
+            let z_index = nodes[start].0;
+            let end = nodes[start..].iter().position(|node| node.0 != z_index).map_or(nodes.len(), |n| start + n);
+            for i in start..end {
+                for j in (i + 1)..end {
+                    if let Some(overlap) = nodes[i].2.intersection(&nodes[j].2) {
+                        if overlap.size.width <= 0.0 || overlap.size.height <= 0.0 {
+                            continue;
+                        }
+                        let (a, b) = (nodes[i].1, nodes[j].1);
+                        conflicts.push(DepthConflict {
+                            z_index: z_index,
+                            nodes: (a, b),
+                            shapes: (self.render_nodes[a].shape, self.render_nodes[b].shape),
+                            overlap: overlap,
+                        });
+                    }
+                }
+            }
+            start = end;
+        }
+
+        return conflicts;
+    }
+
+    /// Removes the render nodes. Transforms added to the batcher are kept.
+    pub fn clear(&mut self) {
+        self.render_nodes.clear();
+        self.allocated_primitives.clear();
+        self.allocated_shadows.clear();
+        self.visible.clear();
+        self.instances.clear();
+    }
+
+    pub fn is_empty(&self) -> bool { self.render_nodes.is_empty() }
+
+    /// Changes the z-index of a render node.
+    ///
+    /// If the node was already built, its primitive (and its shadow's) is rewritten in place
+    /// and returned so that the caller can upload it: bringing a primitive to the front
+    /// doesn't require rebuilding the commands or the geometry. The depth test takes care
+    /// of the ordering, only the `z_index` of the existing commands is outdated until the
+    /// next build.
+    pub fn set_z_index<PrimBuilder>(
+        &mut self,
+        node_index: usize,
+        z_index: u32,
+        prim_builder: &mut PrimBuilder,
+    ) -> Option<PrimitiveId>
+    where
+        PrimBuilder: PrimitiveBuilder<PrimitiveId, PrimitiveParams<Params>>
+    {
+        self.render_nodes[node_index].z_index = z_index;
+        return self.rebuild_primitive(node_index, prim_builder);
+    }
+
+    /// Changes the style of a render node, for example to switch between a color and a
+    /// gradient or image pattern.
+    ///
+    /// Like `set_z_index`, the primitive of a node that was already built is rewritten in
+    /// place and returned, without rebuilding the commands or the geometry. Styles that
+    /// `push_item` would reject, because they are clipped or have an unsupported pattern,
+    /// are rejected the same way and the node is left unchanged.
+    pub fn set_style<PrimBuilder>(
+        &mut self,
+        node_index: usize,
+        style: Params,
+        prim_builder: &mut PrimBuilder,
+    ) -> Result<Option<PrimitiveId>, BatchError>
+    where
+        PrimBuilder: PrimitiveBuilder<PrimitiveId, PrimitiveParams<Params>>
+    {
+        Self::check_style(node_index, &style)?;
+        self.render_nodes[node_index].style = style;
+        return Ok(self.rebuild_primitive(node_index, prim_builder));
+    }
+
+    /// Changes the transforms of a render node.
+    ///
+    /// This lets nodes that were pushed with the same parameters be moved independently
+    /// after the batch was built. Like `set_z_index`, the primitive of a node that was
+    /// already built is rewritten in place and returned. Transforms that were not added to
+    /// the batcher are rejected the same way as in `push_item`, and the node is left
+    /// unchanged.
+    pub fn set_transforms<PrimBuilder>(
+        &mut self,
+        node_index: usize,
+        transforms: Transforms,
+        prim_builder: &mut PrimBuilder,
+    ) -> Result<Option<PrimitiveId>, BatchError>
+    where
+        PrimBuilder: PrimitiveBuilder<PrimitiveId, PrimitiveParams<Params>>
+    {
+        self.check_transforms(node_index, &transforms)?;
+        self.render_nodes[node_index].transforms = transforms;
+        return Ok(self.rebuild_primitive(node_index, prim_builder));
+    }
+
+    /// Shows or hides a render node.
+    ///
+    /// Hidden nodes stay in the commands and keep their primitive, which the vertex
+    /// shaders collapse, so toggling elements on and off only requires uploading the
+    /// returned primitive rather than rebuilding the commands.
+    pub fn set_visible<PrimBuilder>(
+        &mut self,
+        node_index: usize,
+        visible: bool,
+        prim_builder: &mut PrimBuilder,
+    ) -> Option<PrimitiveId>
+    where
+        PrimBuilder: PrimitiveBuilder<PrimitiveId, PrimitiveParams<Params>>
+    {
+        self.visible[node_index] = visible;
+        return self.rebuild_primitive(node_index, prim_builder);
+    }
+
+    pub fn is_visible(&self, node_index: usize) -> bool { self.visible[node_index] }
+
+    fn rebuild_primitive<PrimBuilder>(
+        &mut self,
+        node_index: usize,
+        prim_builder: &mut PrimBuilder,
+    ) -> Option<PrimitiveId>
+    where
+        PrimBuilder: PrimitiveBuilder<PrimitiveId, PrimitiveParams<Params>>
+    {
+        let node = &self.render_nodes[node_index];
+        let visible = self.visible[node_index];
+
+        if let Some(shadow_id) = self.allocated_shadows[node_index] {
+            prim_builder.build_shadow(shadow_id, node);
+            prim_builder.set_visible(shadow_id, visible);
+        }
+
+        let prim_id = self.allocated_primitives[node_index];
+        if let Some(prim_id) = prim_id {
+            prim_builder.build_primtive(prim_id, node);
+            prim_builder.set_visible(prim_id, visible);
+        }
+
+        return prim_id;
+    }
+
+    /// The per-instance attributes of the commands produced by the last call to `build`,
+    /// to be uploaded into an instance buffer.
+    pub fn instances(&self) -> &[GpuInstance] { &self.instances }
+
+    /// Produces the draw commands for the pushed render nodes.
+    ///
+    /// Consecutive render nodes that share the same geometry and have consecutive
+    /// primitive ids are merged into a single instanced command, and the commands are
+    /// sorted with `sort_opaque_cmds`. The per-instance attributes of the commands are
+    /// regenerated in the order of the sorted commands and can be retrieved with `instances`.
+    ///
+    /// Shadows are not produced, see `build_with_shadows`, and masked render nodes are not
+    /// drawn, see `build_with_masks`.
+    ///
+    /// Building an empty batcher is a no-op: it does not allocate primitives or
+    /// geometry and returns an empty (non-allocated) command list.
+    ///
+    /// Returns an error if the geometry of a render node doesn't fit in the geometry store.
+    /// The nodes visited before are built, and the geometry store stays usable. In debug
+    /// builds, the first error reported by `validate` is returned before building anything.
+    pub fn build<VtxBuilder, PrimBuilder, Vertex>(
+        &mut self,
+        shapes: &ShapeStore,
+        geom_store: &mut GeometryStore<Vertex>,
+        geom_builder: &mut VtxBuilder,
+        prim_builder: &mut PrimBuilder,
+    ) -> Result<Vec<Cmd<Vertex>>, BatchError>
+    where
+        PrimitiveId: PrimitiveIndex,
+        VtxBuilder: VertexBuilder<PrimitiveId, Vertex>,
+        PrimBuilder: PrimitiveBuilder<PrimitiveId, PrimitiveParams<Params>>,
+        Vertex: MaybeSend,
+    {
+        self.build_impl(shapes, geom_store, geom_builder, prim_builder, None, None)
+    }
+
+    /// Same as `build`, also producing the commands that draw the shadows of the render
+    /// nodes into `shadows`.
+    ///
+    /// The shadow commands reuse the geometry of the render nodes. Their per-instance
+    /// attributes follow the ones of the main commands in `instances`.
+    pub fn build_with_shadows<VtxBuilder, PrimBuilder, Vertex>(
+        &mut self,
+        shapes: &ShapeStore,
+        geom_store: &mut GeometryStore<Vertex>,
+        geom_builder: &mut VtxBuilder,
+        prim_builder: &mut PrimBuilder,
+        shadows: &mut Vec<ShadowCmd<Vertex>>,
+    ) -> Result<Vec<Cmd<Vertex>>, BatchError>
+    where
+        PrimitiveId: PrimitiveIndex,
+        VtxBuilder: VertexBuilder<PrimitiveId, Vertex>,
+        PrimBuilder: PrimitiveBuilder<PrimitiveId, PrimitiveParams<Params>>,
+        Vertex: MaybeSend,
+    {
+        self.build_impl(shapes, geom_store, geom_builder, prim_builder, Some(shadows), None)
+    }
+
+    /// Same as `build_with_shadows`, also producing the commands that draw the masked
+    /// render nodes into `masked`.
+    ///
+    /// Masked nodes are left out of the returned commands. The geometry of mask shapes is
+    /// tessellated into the geometry store like the shapes of the nodes. The per-instance
+    /// attributes of the masked commands follow the ones of the shadows in `instances`.
+    pub fn build_with_masks<VtxBuilder, PrimBuilder, Vertex>(
+        &mut self,
+        shapes: &ShapeStore,
+        geom_store: &mut GeometryStore<Vertex>,
+        geom_builder: &mut VtxBuilder,
+        prim_builder: &mut PrimBuilder,
+        shadows: &mut Vec<ShadowCmd<Vertex>>,
+        masked: &mut Vec<MaskedCmd<Vertex>>,
+    ) -> Result<Vec<Cmd<Vertex>>, BatchError>
+    where
+        PrimitiveId: PrimitiveIndex,
+        VtxBuilder: VertexBuilder<PrimitiveId, Vertex>,
+        PrimBuilder: PrimitiveBuilder<PrimitiveId, PrimitiveParams<Params>>,
+        Vertex: MaybeSend,
+    {
+        self.build_impl(shapes, geom_store, geom_builder, prim_builder, Some(shadows), Some(masked))
+    }
+
+    fn build_impl<VtxBuilder, PrimBuilder, Vertex>(
+        &mut self,
+        shapes: &ShapeStore,
+        geom_store: &mut GeometryStore<Vertex>,
+        geom_builder: &mut VtxBuilder,
+        prim_builder: &mut PrimBuilder,
+        mut shadows: Option<&mut Vec<ShadowCmd<Vertex>>>,
+        mut masked: Option<&mut Vec<MaskedCmd<Vertex>>>,
+    ) -> Result<Vec<Cmd<Vertex>>, BatchError>
+    where
+        PrimitiveId: PrimitiveIndex,
+        VtxBuilder: VertexBuilder<PrimitiveId, Vertex>,
+        PrimBuilder: PrimitiveBuilder<PrimitiveId, PrimitiveParams<Params>>,
+        Vertex: MaybeSend,
+    {
+        self.instances.clear();
+
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        if cfg!(debug_assertions) {
+            if let Err(errors) = self.validate(shapes) {
+                return Err(errors[0]);
+            }
+        }
+
+        // This is a gross overestimate if commands get merged through batching or instancing.
+        let mut cmds: Vec<Cmd<Vertex>> = Vec::with_capacity(self.render_nodes.len());
+        let first_shadow = shadows.as_ref().map_or(0, |shadows| shadows.len());
+        let first_masked = masked.as_ref().map_or(0, |masked| masked.len());
+        let max_instances = self.max_instances;
+
+        // Go through render nodes in reverse order to make it more likely that
+        // primitives are rendered front to back.
+        for index in (0..self.render_nodes.len()).rev() {
+            let node = &mut self.render_nodes[index];
+            let mask = node.style.mask();
+            if mask.is_some() && masked.is_none() {
+                continue;
+            }
+
+            let allocated_primitive = &mut self.allocated_primitives[index];
+            let visible = self.visible[index];
+
+            let prim_id = allocated_primitive.unwrap_or_else(&mut||{
+                let prim_id = prim_builder.alloc_id();
+                *allocated_primitive = Some(prim_id);
+                prim_id
+            });
+
+            prim_builder.build_primtive(prim_id, node);
+            if !visible {
+                prim_builder.set_visible(prim_id, false);
+            }
+
+            let overflow = |overflow| BatchError::GeometryOverflow { node_index: index, overflow: overflow };
+            let geometry = match geom_store.geometry_for_build(shapes, node.shape, prim_id, geom_builder).map_err(overflow)? {
+                Some(geom) => geom,
+                // Not drawn until the shape is tessellated in a later frame.
+                None => { continue; }
+            };
+
+            let mask_geometry = match mask {
+                Some(Mask::Shape(shape)) => {
+                    match geom_store.geometry_for_build(shapes, shape, prim_id, geom_builder).map_err(overflow)? {
+                        Some(geom) => Some(geom),
+                        // Not drawn until the mask is tessellated in a later frame.
+                        None => { continue; }
+                    }
+                }
+                _ => None,
+            };
+
+            if let Some(ref mut shadows) = shadows {
+                if let Some(blur_radius) = prim_builder.shadow_blur_radius(node) {
+                    let allocated_shadow = &mut self.allocated_shadows[index];
+                    let shadow_id = allocated_shadow.unwrap_or_else(&mut||{
+                        let shadow_id = prim_builder.alloc_id();
+                        *allocated_shadow = Some(shadow_id);
+                        shadow_id
+                    });
+
+                    prim_builder.build_shadow(shadow_id, node);
+                    if !visible {
+                        prim_builder.set_visible(shadow_id, false);
+                    }
+
+                    let merged = match shadows.last_mut() {
+                        Some(last) => {
+                            last.blur_radius == blur_radius && last.cmd.try_merge(&geometry, shadow_id, max_instances)
+                        }
+                        None => false,
+                    };
+                    if !merged {
+                        shadows.push(ShadowCmd {
+                            cmd: Cmd {
+                                geometry: geometry,
+                                first_primitive: shadow_id.primitive_index(),
+                                primitive_page: shadow_id.primitive_page(),
+                                instances: 1,
+                                first_instance: 0,
+                                z_index: node.z_index,
+                            },
+                            blur_radius: blur_radius,
+                        });
+                    }
+                }
+            }
+
+            if let (Some(mask), Some(masked)) = (mask, masked.as_mut()) {
+                let merged = match masked.last_mut() {
+                    Some(last) => {
+                        last.mask == mask
+                            && last.mask_geometry == mask_geometry
+                            && last.cmd.try_merge(&geometry, prim_id, max_instances)
+                    }
+                    None => false,
+                };
+                if !merged {
+                    masked.push(MaskedCmd {
+                        cmd: Cmd {
+                            geometry: geometry,
+                            first_primitive: prim_id.primitive_index(),
+                            primitive_page: prim_id.primitive_page(),
+                            instances: 1,
+                            first_instance: 0,
+                            z_index: node.z_index,
+                        },
+                        mask: mask,
+                        mask_geometry: mask_geometry,
+                    });
+                }
+                continue;
+            }
+
+            if let Some(last) = cmds.last_mut() {
+                if last.try_merge(&geometry, prim_id, max_instances) {
+                    continue;
+                }
+            }
+
+            cmds.push(Cmd {
+                geometry: geometry,
+                first_primitive: prim_id.primitive_index(),
+                primitive_page: prim_id.primitive_page(),
+                instances: 1,
+                first_instance: 0,
+                z_index: node.z_index,
+            });
+        }
+
+        sort_opaque_cmds(&mut cmds);
+
+        push_instances(&mut self.instances, cmds.iter_mut());
+        if let Some(shadows) = shadows {
+            push_instances(
+                &mut self.instances,
+                shadows[first_shadow..].iter_mut().map(|shadow| &mut shadow.cmd),
+            );
+        }
+        if let Some(masked) = masked {
+            push_instances(
+                &mut self.instances,
+                masked[first_masked..].iter_mut().map(|masked| &mut masked.cmd),
+            );
+        }
+
+        return Ok(cmds);
+    }
+}
+
+impl<PrimitiveId: Copy> OpaqueBatcher<PrimitiveId, FillStyle> {
+    /// Fills a render node with a plain color. See `set_style`.
+    pub fn set_color<PrimBuilder>(
+        &mut self,
+        node_index: usize,
+        color: Color,
+        prim_builder: &mut PrimBuilder,
+    ) -> Option<PrimitiveId>
+    where
+        PrimBuilder: PrimitiveBuilder<PrimitiveId, PrimitiveParams<FillStyle>>
+    {
+        self.render_nodes[node_index].style.pattern = Pattern::Color(color);
+        return self.rebuild_primitive(node_index, prim_builder);
+    }
+}
+
+// Appends the per-instance attributes of the commands to the instance stream, in the
+// order of the commands.
+fn push_instances<'l, Vertex: 'l, Cmds>(instances: &mut Vec<GpuInstance>, cmds: Cmds)
+where
+    Cmds: Iterator<Item = &'l mut Cmd<Vertex>>
+{
+    // The offsets are assigned serially (it's cheap), only filling the stream may
+    // happen in parallel.
+    let mut spans = Vec::new();
+    let mut first_instance = instances.len() as u32;
+    for cmd in cmds {
+        cmd.first_instance = first_instance;
+        first_instance += cmd.instances;
+        spans.push((cmd.first_primitive, cmd.instances));
+    }
+
+    extend_instances(instances, &spans);
+}
+
+#[cfg(not(feature = "parallel"))]
+fn extend_instances(instances: &mut Vec<GpuInstance>, spans: &[(u32, u32)]) {
+    for &(first_primitive, count) in spans {
+        for i in 0..count {
+            instances.push(GpuInstance::new(first_primitive + i, 0));
+        }
+    }
+}
+
+#[cfg(feature = "parallel")]
+fn extend_instances(instances: &mut Vec<GpuInstance>, spans: &[(u32, u32)]) {
+    // par_extend preserves the order of the spans.
+    instances.par_extend(spans.par_iter().flat_map(|&(first_primitive, count)| {
+        (0..count).into_par_iter().map(move |i| GpuInstance::new(first_primitive + i, 0))
+    }));
+}
+
+pub struct FillPrimitiveBuilder<'l> {
+    // TODO: move this to a more generic primitive store where data is just put into
+    // a texture like webrender.
+    pub primitives: &'l mut CpuBuffer<GpuFillPrimitive>,
+    pub alpha_mode: AlphaMode,
+}
+
+impl<'l> PrimitiveBuilder<FillPrimitiveId, PrimitiveParams<FillStyle>> for FillPrimitiveBuilder<'l> {
+    fn alloc_id(&mut self) -> FillPrimitiveId {
+        self.primitives.alloc()
+    }
+
+    fn build_primtive(&mut self, id: FillPrimitiveId, params: &PrimitiveParams<FillStyle>) {
+        self.primitives[id] = fill_primitive(params, self.alpha_mode);
+    }
+
+    fn shadow_blur_radius(&self, params: &PrimitiveParams<FillStyle>) -> Option<f32> {
+        params.style.shadow.map(|shadow| shadow.blur_radius)
+    }
+
+    fn build_shadow(&mut self, id: FillPrimitiveId, params: &PrimitiveParams<FillStyle>) {
+        self.primitives[id] = fill_shadow_primitive(params, self.alpha_mode);
+    }
+
+    fn set_visible(&mut self, id: FillPrimitiveId, visible: bool) {
+        self.primitives[id].set_visible(visible);
+    }
+}
+
+/// Allocates fill primitives in a `BufferStore`, adding pages as needed, so that the
+/// amount of primitives isn't limited by the size of a single GPU buffer.
+pub struct PagedFillPrimitiveBuilder<'l> {
+    pub primitives: &'l mut BufferStore<GpuFillPrimitive>,
+    pub alpha_mode: AlphaMode,
+}
+
+impl<'l> PrimitiveBuilder<FillPrimitiveElement, PrimitiveParams<FillStyle>> for PagedFillPrimitiveBuilder<'l> {
+    fn alloc_id(&mut self) -> FillPrimitiveElement {
+        self.primitives.alloc()
+    }
+
+    fn build_primtive(&mut self, id: FillPrimitiveElement, params: &PrimitiveParams<FillStyle>) {
+        self.primitives[id] = fill_primitive(params, self.alpha_mode);
+    }
+
+    fn shadow_blur_radius(&self, params: &PrimitiveParams<FillStyle>) -> Option<f32> {
+        params.style.shadow.map(|shadow| shadow.blur_radius)
+    }
+
+    fn build_shadow(&mut self, id: FillPrimitiveElement, params: &PrimitiveParams<FillStyle>) {
+        self.primitives[id] = fill_shadow_primitive(params, self.alpha_mode);
+    }
+
+    fn set_visible(&mut self, id: FillPrimitiveElement, visible: bool) {
+        self.primitives[id].set_visible(visible);
+    }
+}
+
+// Each z-index has two depth slots: the primitives of the render nodes use the upper one
+// and their shadows and glows the lower one, so that they are behind the primitive they belong
+// to without leaving the depth range at z-index 0.
+fn primitive_depth(z_index: u32) -> f32 { (z_index as f32 + 0.5) / 10000.0 }
+
+fn shadow_depth(z_index: u32) -> f32 { z_index as f32 / 10000.0 }
+
+fn fill_primitive(params: &PrimitiveParams<FillStyle>, alpha_mode: AlphaMode) -> GpuFillPrimitive {
+    let default_transform = TransformId { buffer: BufferId::new(0), element: Id::new(0) };
+    let mut primitive = GpuFillPrimitive {
+        z_index: primitive_depth(params.z_index),
+        local_transform: params.transforms.local.unwrap_or(default_transform).element.to_i32(),
+        view_transform: params.transforms.view.unwrap_or(default_transform).element.to_i32(),
+        root_transform: params.transforms.root.unwrap_or(default_transform).element.to_i32(),
+        pattern_transform: params.style.pattern_transform.unwrap_or(default_transform).element.to_i32(),
+        width: 0.0,
+        .. Default::default()
+    };
+    // Unsupported patterns are rejected by push_item.
+    primitive.set_pattern(&params.style.pattern, alpha_mode).ok();
+    primitive.set_clip(params.style.clip);
+    return primitive;
+}
+
+// The shadow is offset and drawn in the lower depth slot of the z-index of the primitive.
+fn fill_shadow_primitive(params: &PrimitiveParams<FillStyle>, alpha_mode: AlphaMode) -> GpuFillPrimitive {
+    let shadow = params.style.shadow.unwrap();
+    let mut primitive = fill_primitive(params, alpha_mode);
+    primitive.color = alpha_mode.color(shadow.color);
+    primitive.z_index = shadow_depth(params.z_index);
+    primitive.offset = shadow.offset.to_array();
+    return primitive;
+}
+
+/// Builds the stroke primitives of render nodes.
+///
+/// The glow of a stroke (see `StrokeStyle::glow`) is built as its shadow, with a blur
+/// radius of 0.0: `build_with_shadows` produces the commands of the glows, which reuse the
+/// geometry of the strokes and are drawn before them in the transparent pass, without
+/// blurring.
+pub struct StrokePrimitiveBuilder<'l> {
+    pub primitives: &'l mut CpuBuffer<GpuStrokePrimitive>,
+    pub alpha_mode: AlphaMode,
+}
+
+impl<'l> PrimitiveBuilder<StrokePrimitiveId, PrimitiveParams<StrokeStyle>> for StrokePrimitiveBuilder<'l> {
+    fn alloc_id(&mut self) -> StrokePrimitiveId {
+        self.primitives.alloc()
+    }
+
+    fn build_primtive(&mut self, id: StrokePrimitiveId, params: &PrimitiveParams<StrokeStyle>) {
+        self.primitives[id] = stroke_primitive(params, self.alpha_mode);
+    }
+
+    fn shadow_blur_radius(&self, params: &PrimitiveParams<StrokeStyle>) -> Option<f32> {
+        params.style.glow.map(|_| 0.0)
+    }
+
+    fn build_shadow(&mut self, id: StrokePrimitiveId, params: &PrimitiveParams<StrokeStyle>) {
+        self.primitives[id] = stroke_glow_primitive(params, self.alpha_mode);
+    }
+
+    fn set_visible(&mut self, id: StrokePrimitiveId, visible: bool) {
+        self.primitives[id].set_visible(visible);
+    }
+}
+
+fn stroke_primitive(params: &PrimitiveParams<StrokeStyle>, alpha_mode: AlphaMode) -> GpuStrokePrimitive {
+    let default_transform = TransformId { buffer: BufferId::new(0), element: Id::new(0) };
+    let mut primitive = GpuStrokePrimitive {
+        z_index: primitive_depth(params.z_index),
+        local_transform: params.transforms.local.unwrap_or(default_transform).element.to_i32(),
+        view_transform: params.transforms.view.unwrap_or(default_transform).element.to_i32(),
+        root_transform: params.transforms.root.unwrap_or(default_transform).element.to_i32(),
+        pattern_transform: params.style.pattern_transform.unwrap_or(default_transform).element.to_i32(),
+        // The normals of the stroke tessellator are offsets for a line width of 2.0.
+        width: params.style.width * 0.5,
+        .. Default::default()
+    };
+    primitive.set_pattern(&params.style.pattern, alpha_mode);
+    return primitive;
+}
+
+// The glow is drawn half a z-index step behind the stroke, like the shadows of fills.
+fn stroke_glow_primitive(params: &PrimitiveParams<StrokeStyle>, alpha_mode: AlphaMode) -> GpuStrokePrimitive {
+    let glow = params.style.glow.unwrap();
+    let mut primitive = stroke_primitive(params, alpha_mode);
+    let width = primitive.width;
+    primitive.set_glow(&glow, width, alpha_mode);
+    primitive.z_index = shadow_depth(params.z_index);
+    return primitive;
+}
+
+/// Creates the vertex constructors that the fill tessellator uses to produce vertices.
+///
+/// This lets backends choose their vertex format, for example to use compact vertices or to
+/// add attributes, while reusing `FillVertexBuilder`.
+pub trait FillVertexConstructor<Vertex> {
+    type Ctor: VertexConstructor<FillVertex, Vertex>;
+
+    /// Returns a vertex constructor for the geometry of a primitive (its index within
+    /// its page).
+    fn vertex_constructor(&mut self, prim_id: FillPrimitiveId) -> Self::Ctor;
+}
+
+/// Produces `GpuFillVertex` vertices.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GpuFillVertexConstructor;
+
+impl FillVertexConstructor<GpuFillVertex> for GpuFillVertexConstructor {
+    type Ctor = WithId<GpuFillPrimitive>;
+    fn vertex_constructor(&mut self, prim_id: FillPrimitiveId) -> WithId<GpuFillPrimitive> {
+        WithId(prim_id)
+    }
+}
+
+/// Produces `GpuPositionVertex` vertices, without normals.
+///
+/// This is what the clip and picking passes need: they only rasterize the coverage of the
+/// shapes, so they can use vertices half the size of `GpuFillVertex`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PositionVertexConstructor;
+
+impl FillVertexConstructor<GpuPositionVertex> for PositionVertexConstructor {
+    type Ctor = WithId<GpuFillPrimitive>;
+    fn vertex_constructor(&mut self, prim_id: FillPrimitiveId) -> WithId<GpuFillPrimitive> {
+        WithId(prim_id)
+    }
+}
+
+pub struct FillVertexBuilder<Ctor = GpuFillVertexConstructor> {
+    tessellator: FillTessellator,
+    options: FillOptions,
+    ctor: Ctor,
+    overflowed: bool,
+}
+
+impl FillVertexBuilder {
+    pub fn new() -> Self {
+        FillVertexBuilder::with_constructor(GpuFillVertexConstructor)
+    }
+}
+
+impl<Ctor> FillVertexBuilder<Ctor> {
+    /// Creates a builder producing the vertices of a custom vertex constructor.
+    pub fn with_constructor(ctor: Ctor) -> Self {
+        FillVertexBuilder {
+            tessellator: FillTessellator::new(),
+            options: FillOptions::default(),
+            ctor: ctor,
+            overflowed: false,
+        }
+    }
+
+    /// Sets the options used to tessellate paths. Their tolerance is ignored: the tolerance
+    /// of each shape is provided by the `ShapeStore`.
+    pub fn with_options(mut self, options: FillOptions) -> Self {
+        self.options = options;
+        return self;
+    }
+}
+
+// The vertices store the index of the primitive within its page.
+impl<PrimitiveId, Vertex, Ctor> VertexBuilder<PrimitiveId, Vertex> for FillVertexBuilder<Ctor>
+where
+    PrimitiveId: PrimitiveIndex,
+    Vertex: Clone,
+    Ctor: FillVertexConstructor<Vertex>,
+{
+    fn add_path(
+        &mut self,
+        path: &Path,
+        prim_id: PrimitiveId,
+        tolerance: f32,
+        geom: &mut Geometry<Vertex>
+    ) -> GeometryRanges<Vertex> {
+        let vtx_offset = geom.vertices.len();
+        let idx_offset = geom.indices.len();
+
+        let mut builder = BuffersBuilder::new(geom, self.ctor.vertex_constructor(fill_id_in_page(prim_id)));
+        let count = self.tessellator.tessellate_flattened_path(
+            path.path_iter().flattened(tolerance),
+            &self.options,
+            &mut builder
+        ).unwrap();
+        self.overflowed = builder.overflowed();
+
+        return geometry_ranges(vtx_offset, idx_offset, count);
+    }
+
+    fn add_circle(
+        &mut self,
+        center: Point,
+        radius: f32,
+        prim_id: FillPrimitiveId,
+        tolerance: f32,
+        geom: &mut Geometry<Vertex>
+    ) -> GeometryRanges<Vertex> {
+        let vtx_offset = geom.vertices.len();
+        let idx_offset = geom.indices.len();
+
+        let mut builder = BuffersBuilder::new(geom, self.ctor.vertex_constructor(prim_id));
+        let count = basic_shapes::fill_circle(center, radius, tolerance, &mut builder);
+        self.overflowed = builder.overflowed();
+
+        return geometry_ranges(vtx_offset, idx_offset, count);
+    }
+
+    fn add_ellipse(
+        &mut self,
+        ellipse: &Ellipse,
+        prim_id: PrimitiveId,
+        tolerance: f32,
+        geom: &mut Geometry<Vertex>
+    ) -> GeometryRanges<Vertex> {
+        let vtx_offset = geom.vertices.len();
+        let idx_offset = geom.indices.len();
+
+        let mut builder = BuffersBuilder::new(geom, self.ctor.vertex_constructor(fill_id_in_page(prim_id)));
+        let count = basic_shapes::fill_ellipse(
+            ellipse.center, ellipse.radii, Radians::new(0.0), tolerance,
+            &mut builder
+        );
+        self.overflowed = builder.overflowed();
+
+        return geometry_ranges(vtx_offset, idx_offset, count);
+    }
+
+    fn add_rect(
+        &mut self,
+        rect: &Rect,
+        prim_id: PrimitiveId,
+        geom: &mut Geometry<Vertex>
+    ) -> GeometryRanges<Vertex> {
+        let vtx_offset = geom.vertices.len();
+        let idx_offset = geom.indices.len();
+
+        let mut builder = BuffersBuilder::new(geom, self.ctor.vertex_constructor(fill_id_in_page(prim_id)));
+        let count = basic_shapes::fill_rectangle(rect, &mut builder);
+        self.overflowed = builder.overflowed();
+
+        return geometry_ranges(vtx_offset, idx_offset, count);
+    }
+
+    fn add_rounded_rect(
+        &mut self,
+        rect: &RoundedRect,
+        prim_id: PrimitiveId,
+        tolerance: f32,
+        geom: &mut Geometry<Vertex>
+    ) -> GeometryRanges<Vertex> {
+        let vtx_offset = geom.vertices.len();
+        let idx_offset = geom.indices.len();
+
+        let radii = basic_shapes::BorderRadii::new(
+            rect.top_left, rect.top_right, rect.bottom_left, rect.bottom_right,
+        );
+        let mut builder = BuffersBuilder::new(geom, self.ctor.vertex_constructor(fill_id_in_page(prim_id)));
+        let count = basic_shapes::fill_rounded_rectangle(&rect.rect, &radii, tolerance, &mut builder);
+        self.overflowed = builder.overflowed();
+
+        return geometry_ranges(vtx_offset, idx_offset, count);
+    }
+
+    fn overflowed(&self) -> bool { self.overflowed }
+
+    fn options_key(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        match self.options.fill_rule {
+            FillRule::EvenOdd => 0u8,
+            FillRule::NonZero => 1u8,
+        }.hash(&mut hasher);
+        self.options.error_policy.hash(&mut hasher);
+        return hasher.finish();
+    }
+}
+
+// The data needed to tessellate a shape, independently of the `ShapeStore`, so that it
+// can be sent to another thread.
+enum ShapeSource {
+    Path(Arc<Path>, f32),
+    Ellipse(Ellipse, f32),
+    Rect(Rect),
+    RoundedRect(RoundedRect, f32),
+    Group(Vec<ShapeSource>),
+}
+
+impl ShapeSource {
+    fn new(shapes: &ShapeStore, shape: ShapeId) -> Self {
+        let tolerance = shapes.tolerance();
+        match shape {
+            ShapeId::Path(path_id) => ShapeSource::Path(shapes.get_path(path_id).clone(), tolerance),
+            ShapeId::Ellipse(ellipse_id) => ShapeSource::Ellipse(*shapes.get_ellipse(ellipse_id), tolerance),
+            ShapeId::Rect(rect_id) => ShapeSource::Rect(*shapes.get_rect(rect_id)),
+            ShapeId::RoundedRect(rect_id) => ShapeSource::RoundedRect(*shapes.get_rounded_rect(rect_id), tolerance),
+            ShapeId::Glyph(key) => ShapeSource::Path(shapes.get_glyph(key).clone(), key.tolerance()),
+            ShapeId::Group(group_id) => ShapeSource::Group(
+                shapes.get_group(group_id).iter().map(|&member| ShapeSource::new(shapes, member)).collect()
+            ),
+            // Like an empty group, there is nothing to tessellate.
+            ShapeId::None => ShapeSource::Group(Vec::new()),
+        }
+    }
+
+    // The hash of the content and of the options key of the vertex builder.
+    fn content_hash(&self, options: u64) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash_content(&mut hasher);
+        options.hash(&mut hasher);
+        return hasher.finish();
+    }
+
+    fn hash_content<H: Hasher>(&self, state: &mut H) {
+        match *self {
+            ShapeSource::Path(ref path, tolerance) => {
+                0u8.hash(state);
+                tolerance.to_bits().hash(state);
+                for p in path.points() {
+                    p.x.to_bits().hash(state);
+                    p.y.to_bits().hash(state);
+                }
+                for &verb in path.verbs() {
+                    (verb as u8).hash(state);
+                }
+            }
+            ShapeSource::Ellipse(ref ellipse, tolerance) => {
+                1u8.hash(state);
+                tolerance.to_bits().hash(state);
+                for &v in &[ellipse.center.x, ellipse.center.y, ellipse.radii.x, ellipse.radii.y] {
+                    v.to_bits().hash(state);
+                }
+            }
+            ShapeSource::Rect(ref rect) => {
+                2u8.hash(state);
+                for &v in &[rect.origin.x, rect.origin.y, rect.size.width, rect.size.height] {
+                    v.to_bits().hash(state);
+                }
+            }
+            ShapeSource::RoundedRect(ref rounded, tolerance) => {
+                4u8.hash(state);
+                tolerance.to_bits().hash(state);
+                let rect = rounded.rect;
+                for &v in &[
+                    rect.origin.x, rect.origin.y, rect.size.width, rect.size.height,
+                    rounded.top_left, rounded.top_right, rounded.bottom_left, rounded.bottom_right,
+                ] {
+                    v.to_bits().hash(state);
+                }
+            }
+            ShapeSource::Group(ref members) => {
+                3u8.hash(state);
+                members.len().hash(state);
+                for member in members {
+                    member.hash_content(state);
+                }
+            }
+        }
+    }
+
+    fn same_content(&self, other: &ShapeSource) -> bool {
+        match (self, other) {
+            (&ShapeSource::Path(ref a, ta), &ShapeSource::Path(ref b, tb)) => {
+                ta == tb && (Arc::ptr_eq(a, b) || (a.points() == b.points() && a.verbs() == b.verbs()))
+            }
+            (&ShapeSource::Ellipse(ref a, ta), &ShapeSource::Ellipse(ref b, tb)) => ta == tb && a == b,
+            (&ShapeSource::Rect(ref a), &ShapeSource::Rect(ref b)) => a == b,
+            (&ShapeSource::RoundedRect(ref a, ta), &ShapeSource::RoundedRect(ref b, tb)) => ta == tb && a == b,
+            (&ShapeSource::Group(ref a), &ShapeSource::Group(ref b)) => {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.same_content(b))
+            }
+            _ => false,
+        }
+    }
+
+    fn tessellate<PrimitiveId, VtxBuilder, Vertex>(
+        &self,
+        prim_id: PrimitiveId,
+        geom_builder: &mut VtxBuilder,
+        geom: &mut Geometry<Vertex>,
+    ) -> GeometryRanges<Vertex>
+    where
+        PrimitiveId: PrimitiveIndex,
+        VtxBuilder: VertexBuilder<PrimitiveId, Vertex>,
+    {
+        match *self {
+            ShapeSource::Path(ref path, tolerance) => {
+                geom_builder.add_path(path, prim_id, tolerance, geom)
+            }
+            ShapeSource::Ellipse(ref ellipse, tolerance) => {
+                geom_builder.add_ellipse(ellipse, prim_id, tolerance, geom)
+            }
+            ShapeSource::Rect(ref rect) => {
+                geom_builder.add_rect(rect, prim_id, geom)
+            }
+            ShapeSource::RoundedRect(ref rect, tolerance) => {
+                geom_builder.add_rounded_rect(rect, prim_id, tolerance, geom)
+            }
+            ShapeSource::Group(ref members) => {
+                // The shapes are appended one after the other, so their ranges are contiguous.
+                let vtx_offset = geom.vertices.len();
+                let idx_offset = geom.indices.len();
+                for member in members {
+                    member.tessellate(prim_id, geom_builder, geom);
+                    if geom_builder.overflowed() {
+                        // The whole group is discarded, the overflow is reported by the
+                        // vertex builder.
+                        geom.vertices.truncate(vtx_offset);
+                        geom.indices.truncate(idx_offset);
+                        break;
+                    }
+                }
+                let count = Count {
+                    vertices: (geom.vertices.len() - vtx_offset) as u32,
+                    indices: (geom.indices.len() - idx_offset) as u32,
+                };
+
+                geometry_ranges(vtx_offset, idx_offset, count)
+            }
+        }
+    }
+}
+
+fn fill_id_in_page<PrimitiveId: PrimitiveIndex>(id: PrimitiveId) -> FillPrimitiveId {
+    Id::new(id.primitive_index() as u16)
+}
+
+pub(crate) fn geometry_ranges<Vertex>(vtx_offset: usize, idx_offset: usize, count: Count) -> GeometryRanges<Vertex> {
+    return GeometryRanges {
+        vertices: BufferRange {
+            buffer: BufferId::new(0),
+            range: IdRange::from_start_count(vtx_offset as u16, count.vertices as u16),
+        },
+        indices: IndexBufferRange {
+            buffer: BufferId::new(0),
+            range: IdRange::from_start_count(idx_offset as u16, count.indices as u16),
+        },
+    };
+}
+
+
+impl<T> Copy for GeometryRanges<T> {}
+impl<T> Clone for GeometryRanges<T> { fn clone(&self) -> Self { *self } }
+impl<T> ::std::cmp::PartialEq for GeometryRanges<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.vertices.buffer == other.vertices.buffer
+            && self.vertices.range == other.vertices.range
+            && self.indices.buffer == other.indices.buffer
+            && self.indices.range == other.indices.range
+    }
+}
+#[derive(Debug)]
+pub struct GeometryRanges<Vertex> {
+    pub vertices: BufferRange<Vertex>,
+    pub indices: IndexBufferRange,
+}
+
+pub type FillGeometryRanges = GeometryRanges<GpuFillVertex>;
+pub type StrokeGeometryRanges = GeometryRanges<GpuStrokeVertex>;
+
+
+#[test]
+fn simple_opaque_builder() {
+    let mut batcher = OpaqueBatcher::new();
+    let shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let _cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+}
+
+#[test]
+fn empty_opaque_builder() {
+    let mut batcher: OpaqueBatcher<FillPrimitiveId, FillStyle> = OpaqueBatcher::new();
+    let shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    assert!(batcher.is_empty());
+
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+
+    assert!(cmds.is_empty());
+    assert_eq!(cmds.capacity(), 0);
+    assert!(geom.geom.vertices.is_empty());
+    assert_eq!(primitives.try_alloc(), Some(Id::new(0)));
+}
+
+#[test]
+fn basic_shapes_opaque_builder() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let ellipse = shapes.add_ellipse(Ellipse { center: point(0.0, 0.0), radii: vec2(10.0, 5.0) });
+    let rect_id = shapes.add_rect(rect(0.0, 0.0, 10.0, 10.0));
+
+    for &shape in &[ShapeId::Ellipse(ellipse), ShapeId::Rect(rect_id)] {
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: shape,
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }).unwrap();
+    }
+
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+
+    assert_eq!(cmds.len(), 2);
+    // Commands are produced in reverse order.
+    assert_eq!(cmds[0].geometry.indices.range.count(), 6);
+    assert!(cmds[1].geometry.indices.range.count() > 6);
+}
+
+#[test]
+fn instanced_opaque_builder() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let rect_id = shapes.add_rect(rect(0.0, 0.0, 10.0, 10.0));
+    let ellipse = shapes.add_ellipse(Ellipse { center: point(0.0, 0.0), radii: vec2(10.0, 5.0) });
+
+    // An ellipse between two groups of rectangles.
+    let items = [
+        ShapeId::Rect(rect_id), ShapeId::Rect(rect_id),
+        ShapeId::Ellipse(ellipse),
+        ShapeId::Rect(rect_id), ShapeId::Rect(rect_id), ShapeId::Rect(rect_id),
+    ];
+    for &shape in &items {
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: shape,
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }).unwrap();
+    }
+
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+
+    // Each group of consecutive rectangles is merged into one command.
+    assert_eq!(cmds.len(), 3);
+    assert_eq!(cmds[0].instances, 3);
+    assert_eq!(cmds[0].first_primitive, 0);
+    assert_eq!(cmds[1].instances, 1);
+    assert!(cmds[2].geometry == cmds[0].geometry);
+    assert_eq!(cmds[2].first_primitive, 4);
+    assert_eq!(cmds[2].instances, 2);
+
+    // The rectangle is drawn by both groups, so its vertices can't carry the primitive of
+    // either: each instance reads its own from the instance stream.
+    assert!(geom.geometry().vertices.iter().all(|vertex| vertex.prim_id == 0));
+    let instances: Vec<i32> = batcher.instances().iter().map(|instance| instance.prim_id).collect();
+    assert_eq!(instances, vec![0, 1, 2, 3, 4, 5]);
+    assert_eq!(cmds[2].first_instance, 4);
+
+    // Devices that can't draw instances get one command per render node.
+    batcher.configure(&DeviceCapabilities {
+        instance_attributes_supported: false,
+        .. DeviceCapabilities::minimal()
+    });
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+    assert_eq!(cmds.len(), 6);
+    assert!(cmds.iter().all(|cmd| cmd.instances == 1));
+}
+
+#[cfg(test)]
+use path_builder::*;
+
+#[test]
+fn shared_glyph_geometry() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let mut builder = Path::builder();
+    builder.move_to(point(0.0, 0.0));
+    builder.line_to(point(0.5, 0.0));
+    builder.line_to(point(0.5, -0.7));
+    builder.close();
+    let outline = Arc::new(builder.build());
+
+    let fine = shapes.add_glyph(GlyphKey::new(0, 42, 0.01), outline.clone());
+    let coarse = shapes.add_glyph(GlyphKey::new(0, 42, 0.1), outline.clone());
+    assert!(fine != coarse);
+    assert_eq!(shapes.add_glyph(GlyphKey::new(0, 42, 0.01), outline.clone()), fine);
+
+    // The same glyph three times at one size and once at another.
+    for &shape in &[fine, fine, fine, coarse] {
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: shape,
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }).unwrap();
+    }
+
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+
+    // The glyph is tessellated once per tolerance and the uses of the same geometry are
+    // drawn by a single instanced command with a primitive per use.
+    assert_eq!(geom.len(), 2);
+    assert_eq!(cmds.len(), 2);
+    assert_eq!(cmds[0].instances, 1);
+    assert_eq!(cmds[1].instances, 3);
+    assert_eq!(cmds[1].first_primitive, 1);
+    assert!(geom.get(fine).is_some());
+
+    // The cached vertices don't carry the primitive of the first use of the glyph, every
+    // use reads its own from the instance stream.
+    assert!(geom.geometry().vertices.iter().all(|vertex| vertex.prim_id == 0));
+    let mut instances: Vec<i32> = batcher.instances().iter().map(|instance| instance.prim_id).collect();
+    instances.sort();
+    assert_eq!(instances, vec![0, 1, 2, 3]);
+}
+
+#[test]
+fn geometry_eviction() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let rects: Vec<ShapeId> = (0..3).map(|i| {
+        ShapeId::Rect(shapes.add_rect(rect(i as f32 * 20.0, 0.0, 10.0, 10.0)))
+    }).collect();
+
+    let mut draw = |batcher: &mut OpaqueBatcher<FillPrimitiveId, FillStyle>, geom: &mut GeometryStore<GpuFillVertex>, items: &[ShapeId]| {
+        batcher.clear();
+        for &shape in items {
+            batcher.push_item(PrimitiveParams {
+                z_index: 0,
+                shape: shape,
+                transforms: Transforms { local: None, view: None, root: None },
+                style: FillStyle::new(Pattern::Color(Color::black())),
+            }).unwrap();
+        }
+        batcher.build(
+            &shapes,
+            geom,
+            &mut FillVertexBuilder::new(),
+            &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+        ).unwrap()
+    };
+
+    draw(&mut batcher, &mut geom, &rects);
+    assert_eq!(geom.live_vertices(), 12);
+    geom.end_frame();
+
+    // Only the last rectangle is drawn in the second frame.
+    draw(&mut batcher, &mut geom, &rects[2..]);
+    let evicted = geom.evict_to_budget(4);
+    assert_eq!(evicted.len(), 2);
+    assert!(!evicted.contains(&rects[2]));
+    // The shapes have the same age and size, ties are broken by id.
+    assert_eq!(evicted, vec![rects[0], rects[1]]);
+    assert_eq!(geom.len(), 1);
+    assert_eq!(geom.geometry().vertices.len(), 4);
+    assert_eq!(geom.wasted_vertices(), 0);
+
+    // The remaining geometry was moved to the start of the buffers.
+    let range = *geom.get(rects[2]).unwrap();
+    assert_eq!(range.vertices.range.start_index(), 0);
+    assert!(geom.geometry().indices.iter().all(|&idx| idx < 4));
+
+    // Evicted shapes are tessellated again when they are drawn.
+    let cmds = draw(&mut batcher, &mut geom, &rects[..1]);
+    assert_eq!(cmds.len(), 1);
+    assert_eq!(geom.len(), 2);
+
+    assert!(geom.remove(rects[0]));
+    assert!(!geom.remove(rects[0]));
+    assert_eq!(geom.wasted_vertices(), 4);
+}
+
+#[test]
+fn tessellation_budget() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    for i in 0..3 {
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: ShapeId::Rect(shapes.add_rect(rect(i as f32 * 20.0, 0.0, 10.0, 10.0))),
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }).unwrap();
+    }
+    let first = batcher.render_nodes[0].shape;
+
+    let mut build = |batcher: &mut OpaqueBatcher<FillPrimitiveId, FillStyle>, geom: &mut GeometryStore<GpuFillVertex>| {
+        let cmds = batcher.build(
+            &shapes,
+            geom,
+            &mut FillVertexBuilder::new(),
+            &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+        ).unwrap();
+        geom.end_frame();
+        cmds
+    };
+
+    // Without any budget left, the new shapes are deferred and not drawn.
+    geom.set_tessellation_budget(Some(Duration::new(0, 0)));
+    assert!(build(&mut batcher, &mut geom).is_empty());
+    assert_eq!(geom.len(), 0);
+    assert!(geom.has_pending_tessellation());
+
+    geom.set_tessellation_budget(None);
+    assert_eq!(build(&mut batcher, &mut geom).len(), 3);
+    assert!(!geom.has_pending_tessellation());
+    let old_range = *geom.get(first).unwrap();
+
+    // An edited shape is drawn with its previous geometry until it is tessellated again.
+    geom.invalidate(first);
+    geom.set_tessellation_budget(Some(Duration::new(0, 0)));
+    assert_eq!(build(&mut batcher, &mut geom).len(), 3);
+    assert_eq!(*geom.get(first).unwrap(), old_range);
+    assert!(geom.has_pending_tessellation());
+
+    geom.set_tessellation_budget(None);
+    assert_eq!(build(&mut batcher, &mut geom).len(), 3);
+    assert!(*geom.get(first).unwrap() != old_range);
+    assert_eq!(geom.wasted_vertices(), 4);
+    assert!(!geom.has_pending_tessellation());
+}
+
+#[test]
+fn background_tessellation() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let ellipse = ShapeId::Ellipse(shapes.add_ellipse(Ellipse { center: point(0.0, 0.0), radii: vec2(10.0, 5.0) }));
+    let rect_id = ShapeId::Rect(shapes.add_rect(rect(0.0, 0.0, 10.0, 10.0)));
+    for &shape in &[ellipse, rect_id] {
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: shape,
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }).unwrap();
+    }
+
+    let mut build = |batcher: &mut OpaqueBatcher<FillPrimitiveId, FillStyle>, geom: &mut GeometryStore<GpuFillVertex>| {
+        batcher.build(
+            &shapes,
+            geom,
+            &mut FillVertexBuilder::new(),
+            &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+        ).unwrap()
+    };
+
+    // The ellipse is not drawn until its geometry is added to the store.
+    let handle = geom.tessellate_async(&shapes, ellipse, FillPrimitiveId::new(0), FillVertexBuilder::new());
+    assert_eq!(handle.shape(), ellipse);
+    assert_eq!(build(&mut batcher, &mut geom).len(), 1);
+    assert_eq!(geom.len(), 1);
+    assert!(geom.has_pending_tessellation());
+
+    assert_eq!(geom.wait_for_tessellation(handle), Ok(true));
+    assert!(geom.get(ellipse).unwrap().indices.range.count() > 0);
+    // The geometry is shared by the nodes drawing the ellipse, whatever their primitive.
+    let range = geom.get(ellipse).unwrap().vertices.range.usize_range();
+    assert!(geom.geometry().vertices[range].iter().all(|vertex| vertex.prim_id == 0));
+    assert_eq!(build(&mut batcher, &mut geom).len(), 2);
+    assert_eq!(geom.len(), 2);
+    assert!(!geom.has_pending_tessellation());
+
+    // Removing the shape discards the pending geometry.
+    let mut handles = vec![
+        geom.tessellate_async(&shapes, ellipse, FillPrimitiveId::new(0), FillVertexBuilder::new()),
+    ];
+    assert!(geom.remove(ellipse));
+    let mut completed = 0;
+    while !handles.is_empty() {
+        completed += geom.poll_tessellation(&mut handles).unwrap();
+    }
+    assert_eq!(completed, 0);
+    assert!(geom.get(ellipse).is_none());
+
+    // Shapes without geometry produce empty ranges.
+    let handle = geom.tessellate_async(&shapes, ShapeId::None, FillPrimitiveId::new(1), FillVertexBuilder::new());
+    assert_eq!(geom.wait_for_tessellation(handle), Ok(true));
+    assert_eq!(geom.get(ShapeId::None).unwrap().vertices.range.count(), 0);
+
+    // The jobs queue up when there are more of them than tessellation threads, they all
+    // complete but only the first one to complete is added to the store.
+    let mut handles: Vec<_> = (0..8).map(|_| {
+        geom.tessellate_async(&shapes, ShapeId::None, FillPrimitiveId::new(1), FillVertexBuilder::new())
+    }).collect();
+    let mut completed = 0;
+    while !handles.is_empty() {
+        completed += geom.poll_tessellation(&mut handles).unwrap();
+    }
+    assert_eq!(completed, 1);
+
+    // Geometry that 16 bit indices can't address is rejected.
+    let filler = GpuFillVertex { position: [0.0, 0.0], normal: [0.0, 0.0], alpha: 1.0, prim_id: 0 };
+    let free = u16::max_value() as usize + 1 - geom.geom.vertices.len();
+    geom.geom.vertices.extend(::std::iter::repeat(filler).take(free - 2));
+    let handle = geom.tessellate_async(&shapes, ellipse, FillPrimitiveId::new(1), FillVertexBuilder::new());
+    match geom.wait_for_tessellation(handle) {
+        Err(GeometryOverflow { shape, vertices }) => {
+            assert_eq!(shape, ellipse);
+            assert!(vertices > u16::max_value() as usize + 1);
+        }
+        result => panic!("{:?}", result),
+    }
+    assert!(geom.get(ellipse).is_none());
+    assert!(!geom.has_pending_tessellation());
+}
+
+#[test]
+fn geometry_overflow() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let ellipse = ShapeId::Ellipse(shapes.add_ellipse(Ellipse { center: point(0.0, 0.0), radii: vec2(10.0, 5.0) }));
+    batcher.push_item(PrimitiveParams {
+        z_index: 0,
+        shape: ellipse,
+        transforms: Transforms { local: None, view: None, root: None },
+        style: FillStyle::new(Pattern::Color(Color::black())),
+    }).unwrap();
+
+    // Geometry that 16 bit indices can't address is discarded rather than wrapping around.
+    let filler = GpuFillVertex { position: [0.0, 0.0], normal: [0.0, 0.0], alpha: 1.0, prim_id: 0 };
+    geom.geom.vertices.extend(::std::iter::repeat(filler).take(u16::max_value() as usize - 2));
+    let result = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    );
+    match result {
+        Err(BatchError::GeometryOverflow { node_index, overflow }) => {
+            assert_eq!(node_index, 0);
+            assert_eq!(overflow.shape, ellipse);
+            assert!(overflow.vertices > u16::max_value() as usize + 1);
+        }
+        result => panic!("{:?}", result.map(|cmds| cmds.len())),
+    }
+    assert!(geom.get(ellipse).is_none());
+    assert_eq!(geom.geometry().vertices.len(), u16::max_value() as usize - 2);
+    assert!(geom.geometry().indices.is_empty());
+}
+
+#[test]
+fn shape_cache() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+    geom.set_shape_cache(true);
+
+    let triangle = || {
+        let mut builder = Path::builder();
+        builder.move_to(point(0.0, 0.0));
+        builder.line_to(point(5.0, 0.0));
+        builder.line_to(point(5.0, 7.0));
+        builder.close();
+        Arc::new(builder.build())
+    };
+    // Different shapes with the same content.
+    let path_a = ShapeId::Path(shapes.add_path(triangle()));
+    let path_b = ShapeId::Path(shapes.add_path(triangle()));
+    let rect_a = ShapeId::Rect(shapes.add_rect(rect(0.0, 0.0, 10.0, 10.0)));
+    let rect_b = ShapeId::Rect(shapes.add_rect(rect(0.0, 0.0, 10.0, 10.0)));
+    let other = ShapeId::Rect(shapes.add_rect(rect(0.0, 0.0, 20.0, 10.0)));
+
+    for &shape in &[path_a, path_b, rect_a, rect_b, other] {
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: shape,
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }).unwrap();
+    }
+
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+
+    // Identical shapes share their geometry and are drawn with instancing.
+    assert_eq!(geom.len(), 5);
+    assert_eq!(geom.get(path_a), geom.get(path_b));
+    assert_eq!(geom.get(rect_a), geom.get(rect_b));
+    assert!(geom.get(rect_a) != geom.get(other));
+    assert_eq!(cmds.len(), 3);
+    assert_eq!(geom.wasted_vertices(), 0);
+
+    // The shared vertices don't refer to the primitive of the node that was tessellated
+    // first, each node gets its own primitive from the instance stream.
+    assert!(geom.geometry().vertices.iter().all(|vertex| vertex.prim_id == 0));
+    let mut instances: Vec<i32> = batcher.instances().iter().map(|instance| instance.prim_id).collect();
+    instances.sort();
+    assert_eq!(instances, vec![0, 1, 2, 3, 4]);
+
+    // Compacting keeps the geometry shared.
+    assert!(geom.remove(other));
+    assert_eq!(geom.wasted_vertices(), 4);
+    geom.compact();
+    assert_eq!(geom.get(rect_a), geom.get(rect_b));
+    assert_eq!(geom.get(path_a), geom.get(path_b));
+    assert_eq!(geom.geometry().vertices.len(), geom.live_vertices());
+
+    // Shapes tessellated with different options don't share their geometry.
+    let path_c = ShapeId::Path(shapes.add_path(triangle()));
+    let path_d = ShapeId::Path(shapes.add_path(triangle()));
+    batcher.clear();
+    batcher.push_item(PrimitiveParams {
+        z_index: 0,
+        shape: path_c,
+        transforms: Transforms { local: None, view: None, root: None },
+        style: FillStyle::new(Pattern::Color(Color::black())),
+    }).unwrap();
+    batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new().with_options(FillOptions::non_zero()),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+    assert!(geom.get(path_c).is_some());
+    assert!(geom.get(path_c) != geom.get(path_a));
+
+    batcher.clear();
+    batcher.push_item(PrimitiveParams {
+        z_index: 0,
+        shape: path_d,
+        transforms: Transforms { local: None, view: None, root: None },
+        style: FillStyle::new(Pattern::Color(Color::black())),
+    }).unwrap();
+    batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+    assert_eq!(geom.get(path_d), geom.get(path_a));
+}
+
+#[test]
+fn rounded_rect_tolerance() {
+    let rounded = RoundedRect {
+        rect: rect(0.0, 0.0, 100.0, 50.0),
+        top_left: 20.0,
+        top_right: 0.0,
+        bottom_left: 5.0,
+        bottom_right: 20.0,
+    };
+
+    let tessellate = |tolerance| {
+        let mut batcher = OpaqueBatcher::new();
+        let mut shapes = ShapeStore::new();
+        let mut geom = GeometryStore::new();
+        let mut primitives = CpuBuffer::new(1024);
+        shapes.set_tolerance(tolerance);
+        let shape = ShapeId::RoundedRect(shapes.add_rounded_rect(rounded));
+        assert_eq!(shapes.bounds(shape), Some(rect(0.0, 0.0, 100.0, 50.0)));
+
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: shape,
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }).unwrap();
+        batcher.build(
+            &shapes,
+            &mut geom,
+            &mut FillVertexBuilder::new(),
+            &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+        ).unwrap();
+
+        geom.get(shape).unwrap().vertices.range.count()
+    };
+
+    // The corners are flattened with the tolerance of the store.
+    let coarse = tessellate(0.5);
+    let fine = tessellate(0.01);
+    assert!(coarse > 4);
+    assert!(fine > coarse);
+}
+
+#[test]
+fn sorted_opaque_cmds() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let mut z_indices = Vec::new();
+    for &z_index in &[3, 1, 2] {
+        let rect_id = shapes.add_rect(rect(0.0, 0.0, 10.0, z_index as f32));
+        batcher.push_item(PrimitiveParams {
+            z_index: z_index,
+            shape: ShapeId::Rect(rect_id),
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }).unwrap();
+    }
+
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+
+    for cmd in &cmds {
+        z_indices.push(cmd.z_index);
+    }
+    // Front to back.
+    assert_eq!(z_indices, vec![3, 2, 1]);
+}
+
+#[test]
+fn sort_ties_keep_build_order() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    // Distinct shapes with the same z-index can't be merged and tie in the sort.
+    for i in 0..8 {
+        let rect_id = shapes.add_rect(rect(0.0, 0.0, 10.0, i as f32));
+        batcher.push_item(PrimitiveParams {
+            z_index: 1,
+            shape: ShapeId::Rect(rect_id),
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }).unwrap();
+    }
+
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+
+    assert_eq!(cmds.len(), 8);
+    for (i, cmd) in cmds.iter().enumerate() {
+        assert_eq!(cmd.first_primitive, i as u32);
+        assert_eq!(cmd.first_instance, i as u32);
+        assert_eq!(batcher.instances()[i].prim_id, i as i32);
+    }
+}
+
+#[cfg(test)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct CompactVertex {
+    position: [i16; 2],
+    prim_id: u16,
+}
+
+#[cfg(test)]
+struct CompactVertexCtor(u16);
+
+#[cfg(test)]
+impl VertexConstructor<FillVertex, CompactVertex> for CompactVertexCtor {
+    fn new_vertex(&mut self, vertex: FillVertex) -> CompactVertex {
+        CompactVertex {
+            position: [vertex.position.x as i16, vertex.position.y as i16],
+            prim_id: self.0,
+        }
+    }
+}
+
+#[cfg(test)]
+impl FillVertexConstructor<CompactVertex> for () {
+    type Ctor = CompactVertexCtor;
+    fn vertex_constructor(&mut self, prim_id: FillPrimitiveId) -> CompactVertexCtor {
+        CompactVertexCtor(prim_id.to_u16())
+    }
+}
+
+#[test]
+fn custom_vertex_format() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom: GeometryStore<CompactVertex> = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let rect_id = shapes.add_rect(rect(0.0, 0.0, 10.0, 20.0));
+    batcher.push_item(PrimitiveParams {
+        z_index: 0,
+        shape: ShapeId::Rect(rect_id),
+        transforms: Transforms { local: None, view: None, root: None },
+        style: FillStyle::new(Pattern::Color(Color::black())),
+    }).unwrap();
+
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::with_constructor(()),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+
+    assert_eq!(cmds.len(), 1);
+    assert_eq!(geom.geom.vertices.len(), 4);
+    assert!(geom.geom.vertices.contains(&CompactVertex { position: [10, 20], prim_id: 0 }));
+}
+
+#[test]
+fn position_only_vertices() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom: GeometryStore<GpuPositionVertex> = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let rect_id = shapes.add_rect(rect(0.0, 0.0, 10.0, 20.0));
+    batcher.push_item(PrimitiveParams {
+        z_index: 0,
+        shape: ShapeId::Rect(rect_id),
+        transforms: Transforms { local: None, view: None, root: None },
+        style: FillStyle::new(Pattern::Color(Color::black())),
+    }).unwrap();
+
+    batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::with_constructor(PositionVertexConstructor),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+
+    assert_eq!(geom.geom.vertices.len(), 4);
+    assert!(geom.geom.vertices.iter().any(|v| v.position == [10.0, 20.0]));
+}
+
+#[test]
+fn instance_attributes() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let rect_id = shapes.add_rect(rect(0.0, 0.0, 10.0, 10.0));
+    let ellipse = shapes.add_ellipse(Ellipse { center: point(0.0, 0.0), radii: vec2(10.0, 5.0) });
+
+    for &shape in &[ShapeId::Ellipse(ellipse), ShapeId::Rect(rect_id), ShapeId::Rect(rect_id)] {
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: shape,
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }).unwrap();
+    }
+
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+
+    assert_eq!(cmds.len(), 2);
+    assert_eq!(batcher.instances().len(), 3);
+    for cmd in &cmds {
+        for i in 0..cmd.instances {
+            let instance = &batcher.instances()[(cmd.first_instance + i) as usize];
+            assert_eq!(instance.prim_id, (cmd.first_primitive + i) as i32);
+            assert_eq!(instance.flags, 0);
+        }
+    }
+
+    batcher.clear();
+    assert!(batcher.instances().is_empty());
+}
+
+#[test]
+fn paged_opaque_builder() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    // Pages of two primitives.
+    let mut primitives = BufferStore::new(1, 2);
+
+    let rect_id = shapes.add_rect(rect(0.0, 0.0, 10.0, 10.0));
+    for _ in 0..3 {
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: ShapeId::Rect(rect_id),
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }).unwrap();
+    }
+
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut PagedFillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+
+    // Instances can't be merged across pages.
+    assert_eq!(primitives.num_buffers(), 2);
+    assert_eq!(cmds.len(), 2);
+    assert_eq!(cmds[0].primitive_page, 0);
+    assert_eq!(cmds[0].first_primitive, 0);
+    assert_eq!(cmds[0].instances, 2);
+    assert_eq!(cmds[1].primitive_page, 1);
+    assert_eq!(cmds[1].first_primitive, 0);
+    assert_eq!(cmds[1].instances, 1);
+}
+
+#[test]
+fn shadows_opaque_builder() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let rect_id = shapes.add_rect(rect(0.0, 0.0, 10.0, 10.0));
+    let shadow = DropShadow {
+        offset: vec2(2.0, 3.0),
+        blur_radius: 4.0,
+        color: Color::black(),
+    };
+    for &shadow in &[Some(shadow), None] {
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: ShapeId::Rect(rect_id),
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle { shadow: shadow, .. FillStyle::new(Pattern::Color(Color::white())) },
+        }).unwrap();
+    }
+
+    let mut shadows = Vec::new();
+    let cmds = batcher.build_with_shadows(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+        &mut shadows,
+    ).unwrap();
+
+    // The shadow reuses the geometry of the main primitives.
+    assert_eq!(cmds.len(), 1);
+    assert_eq!(cmds[0].instances, 2);
+    assert_eq!(shadows.len(), 1);
+    assert_eq!(shadows[0].blur_radius, 4.0);
+    assert!(shadows[0].cmd.geometry == cmds[0].geometry);
+    assert_eq!(shadows[0].cmd.first_instance, 2);
+    assert_eq!(batcher.instances().len(), 3);
+
+    let shadow_prim = primitives[Id::new(shadows[0].cmd.first_primitive as u16)];
+    let main_prim = primitives[Id::new(1)];
+    assert_eq!(shadow_prim.color, [0.0, 0.0, 0.0, 1.0]);
+    assert_eq!(shadow_prim.offset, [2.0, 3.0]);
+    assert!(shadow_prim.z_index < main_prim.z_index);
+    // The shader uses 1.0 - z_index as the depth, which must not be clipped at z-index 0.
+    assert!(shadow_prim.z_index >= 0.0);
+}
+
+#[test]
+fn masked_opaque_builder() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let rect_id = shapes.add_rect(rect(0.0, 0.0, 10.0, 10.0));
+    let mask_shape = ShapeId::Ellipse(shapes.add_ellipse(Ellipse {
+        center: point(5.0, 5.0),
+        radii: vec2(5.0, 5.0),
+    }));
+    let masks = [
+        None,
+        Some(Mask::Shape(mask_shape)),
+        Some(Mask::Shape(mask_shape)),
+        Some(Mask::Image(ImageId::new(1))),
+    ];
+    for &mask in &masks {
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: ShapeId::Rect(rect_id),
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle { mask: mask, .. FillStyle::new(Pattern::Color(Color::white())) },
+        }).unwrap();
+    }
+
+    // Without an output for them, masked nodes are not drawn.
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+    assert_eq!(cmds.len(), 1);
+    assert_eq!(cmds[0].instances, 1);
+
+    batcher.clear();
+    for &mask in &masks {
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: ShapeId::Rect(rect_id),
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle { mask: mask, .. FillStyle::new(Pattern::Color(Color::white())) },
+        }).unwrap();
+    }
+
+    let mut shadows = Vec::new();
+    let mut masked = Vec::new();
+    let cmds = batcher.build_with_masks(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+        &mut shadows,
+        &mut masked,
+    ).unwrap();
+
+    assert_eq!(cmds.len(), 1);
+    assert_eq!(cmds[0].instances, 1);
+    assert!(shadows.is_empty());
+    // The nodes are visited in reverse order.
+    assert_eq!(masked.len(), 2);
+    assert_eq!(masked[0].mask, Mask::Image(ImageId::new(1)));
+    assert!(masked[0].mask_geometry.is_none());
+    assert_eq!(masked[1].mask, Mask::Shape(mask_shape));
+    assert_eq!(masked[1].cmd.instances, 2);
+    assert!(masked[1].mask_geometry == geom.get(mask_shape).cloned());
+    assert!(masked[1].cmd.geometry == cmds[0].geometry);
+    assert_eq!(masked[0].cmd.first_instance, 1);
+    assert_eq!(masked[1].cmd.first_instance, 2);
+    assert_eq!(batcher.instances().len(), 4);
+}
+
+#[test]
+fn stroke_glow() {
+    let mut primitives = CpuBuffer::new(1024);
+    let mut builder = StrokePrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight };
+    let glow = Glow { color: Color::white(), radius: 3.0, intensity: 0.5 };
+    let params = PrimitiveParams {
+        z_index: 0,
+        shape: ShapeId::None,
+        transforms: Transforms { local: None, view: None, root: None },
+        style: StrokeStyle::new(Pattern::Color(Color::black()), 2.0).with_glow(glow),
+    };
+
+    assert_eq!(builder.shadow_blur_radius(&params), Some(0.0));
+    let stroke_id = builder.alloc_id();
+    let glow_id = builder.alloc_id();
+    builder.build_primtive(stroke_id, &params);
+    builder.build_shadow(glow_id, &params);
+
+    let stroke = builder.primitives[stroke_id];
+    let glow = builder.primitives[glow_id];
+    assert_eq!(stroke.width, 1.0);
+    assert!(!stroke.is_glow());
+    assert_eq!(glow.width, 4.0);
+    assert_eq!(glow.glow, [0.25, 0.5]);
+    assert_eq!(glow.color, [1.0, 1.0, 1.0, 1.0]);
+    assert!(glow.z_index < stroke.z_index);
+    assert!(glow.z_index >= 0.0);
+
+    let no_glow = PrimitiveParams { style: StrokeStyle::new(Pattern::Color(Color::black()), 2.0), .. params };
+    assert_eq!(builder.shadow_blur_radius(&no_glow), None);
+}
+
+#[test]
+fn pattern_transforms() {
+    let transform = TransformId { buffer: BufferId::new(0), element: Id::new(7) };
+    let mut fills = CpuBuffer::new(1024);
+    let mut builder = FillPrimitiveBuilder { primitives: &mut fills, alpha_mode: AlphaMode::Straight };
+    let mut params = PrimitiveParams {
+        z_index: 0,
+        shape: ShapeId::None,
+        transforms: Transforms { local: None, view: None, root: None },
+        style: FillStyle::new(Pattern::Color(Color::black())),
+    };
+    let id = builder.alloc_id();
+    builder.build_primtive(id, &params);
+    assert_eq!(builder.primitives[id].pattern_transform, 0);
+
+    // Only the pattern moves, the geometry keeps its transforms.
+    params.style.pattern_transform = Some(transform);
+    builder.build_primtive(id, &params);
+    assert_eq!(builder.primitives[id].pattern_transform, 7);
+    assert_eq!(builder.primitives[id].local_transform, 0);
+
+    let mut strokes = CpuBuffer::new(1024);
+    let mut builder = StrokePrimitiveBuilder { primitives: &mut strokes, alpha_mode: AlphaMode::Straight };
+    let params = PrimitiveParams {
+        z_index: 0,
+        shape: ShapeId::None,
+        transforms: Transforms { local: None, view: None, root: None },
+        style: StrokeStyle::new(Pattern::Color(Color::black()), 1.0).with_pattern_transform(transform),
+    };
+    let id = builder.alloc_id();
+    builder.build_primtive(id, &params);
+    assert_eq!(builder.primitives[id].pattern_transform, 7);
+}
+
+#[test]
+fn depth_conflicts() {
+    let mut batcher: OpaqueBatcher<FillPrimitiveId, FillStyle> = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+
+    let items = [
+        (1, rect(0.0, 0.0, 10.0, 10.0)),
+        (1, rect(5.0, 5.0, 10.0, 10.0)),
+        // Only touches the first one.
+        (1, rect(10.0, 0.0, 10.0, 5.0)),
+        // Overlaps at another z-index.
+        (2, rect(0.0, 0.0, 10.0, 10.0)),
+        (1, rect(-5.0, -5.0, 10.0, 10.0)),
+    ];
+    for &(z_index, r) in &items {
+        batcher.push_item(PrimitiveParams {
+            z_index: z_index,
+            shape: ShapeId::Rect(shapes.add_rect(r)),
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }).unwrap();
+    }
+
+    let conflicts = batcher.find_depth_conflicts(&shapes, |_, bounds| *bounds);
+    assert_eq!(conflicts.len(), 2);
+    assert_eq!(conflicts[0].nodes, (0, 1));
+    assert_eq!(conflicts[0].z_index, 1);
+    assert_eq!(conflicts[0].overlap, rect(5.0, 5.0, 5.0, 5.0));
+    assert_eq!(conflicts[1].nodes, (0, 4));
+    assert!(format!("{}", conflicts[0]).contains("z-index 1"));
+
+    // Hidden nodes don't conflict, and nodes are compared in the space given by the caller.
+    batcher.visible[4] = false;
+    let conflicts = batcher.find_depth_conflicts(&shapes, |node, bounds| {
+        bounds.translate(&vec2(node.z_index as f32 * 100.0, 0.0))
+    });
+    assert_eq!(conflicts.len(), 1);
+
+    assert_eq!(shapes.bounds(ShapeId::None), None);
+    let ellipse = shapes.add_ellipse(Ellipse { center: point(1.0, 2.0), radii: vec2(3.0, 4.0) });
+    assert_eq!(shapes.bounds(ShapeId::Ellipse(ellipse)), Some(rect(-2.0, -2.0, 6.0, 8.0)));
+}
+
+#[test]
+fn z_index_allocator() {
+    let mut z = ZIndexAllocator::new();
+    assert_eq!(z.next_z_index(), 0);
+    assert_eq!(z.next_z_index(), 1);
+
+    let reserved = z.reserve_z_range(3);
+    assert_eq!(reserved, 2..5);
+    assert_eq!(z.next_z_index(), 5);
+
+    z.set_z_index(100);
+    assert_eq!(z.current(), 100);
+    assert_eq!(z.next_z_index(), 100);
+    assert_eq!(z.next_z_index(), 101);
+}
+
+#[test]
+fn reorder_after_build() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let rect_id = shapes.add_rect(rect(0.0, 0.0, 10.0, 10.0));
+    for z_index in 0..2 {
+        batcher.push_item(PrimitiveParams {
+            z_index: z_index,
+            shape: ShapeId::Rect(rect_id),
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }).unwrap();
+    }
+
+    batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+    let num_vertices = geom.geom.vertices.len();
+
+    let prim_id = batcher.set_z_index(
+        0, 5,
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+
+    // The first node was built last.
+    assert_eq!(prim_id, Id::new(1));
+    assert_eq!(primitives[prim_id].z_index, 5.5 / 10000.0);
+    assert_eq!(geom.geom.vertices.len(), num_vertices);
+}
+
+#[test]
+fn rebind_pattern_after_build() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let rect_id = shapes.add_rect(rect(0.0, 0.0, 10.0, 10.0));
+    batcher.push_item(PrimitiveParams {
+        z_index: 0,
+        shape: ShapeId::Rect(rect_id),
+        transforms: Transforms { local: None, view: None, root: None },
+        style: FillStyle::new(Pattern::Color(Color::black())),
+    }).unwrap();
+
+    // Not built yet.
+    let style = FillStyle::new(Pattern::Color(Color::white()));
+    assert_eq!(batcher.set_style(0, style, &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight }), Ok(None));
+
+    batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+    assert_eq!(primitives[Id::new(0)].pattern_kind, PATTERN_KIND_COLOR);
+    assert_eq!(primitives[Id::new(0)].color, [1.0, 1.0, 1.0, 1.0]);
+
+    let image = ImagePattern { image_id: ImageId::new(3), rect: rect(0.0, 0.0, 1.0, 1.0), is_opaque: true };
+    let style = FillStyle::new(Pattern::Image(image));
+    let prim_id = batcher.set_style(
+        0, style,
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap().unwrap();
+
+    assert_eq!(primitives[prim_id].pattern_kind, PATTERN_KIND_IMAGE);
+    assert_eq!(primitives[prim_id].pattern_id, 3);
+
+    // Styles that push_item rejects are rejected here too, and the primitive is untouched.
+    let style = FillStyle { clip: Some(ClipId::new(2)), .. FillStyle::new(Pattern::Color(Color::black())) };
+    assert_eq!(
+        batcher.set_style(
+            0, style,
+            &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+        ),
+        Err(BatchError::ClippedNode { node_index: 0, clip: ClipId::new(2) })
+    );
+    assert_eq!(primitives[prim_id].pattern_kind, PATTERN_KIND_IMAGE);
+}
+
+#[test]
+fn animate_nodes_independently() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let transforms = TransformIdRange { buffer: BufferId::new(0), range: IdRange::new(0..2) };
+    batcher.add_transforms(transforms);
+
+    let rect_id = shapes.add_rect(rect(0.0, 0.0, 10.0, 10.0));
+    for _ in 0..2 {
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: ShapeId::Rect(rect_id),
+            transforms: Transforms { local: Some(transforms.get(0)), view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }).unwrap();
+    }
+
+    batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+
+    let moved = batcher.set_transforms(
+        0, Transforms { local: Some(transforms.get(1)), view: None, root: None },
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap().unwrap();
+    let recolored = batcher.set_color(
+        1, Color::white(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+
+    assert!(moved != recolored);
+    assert_eq!(primitives[moved].local_transform, 1);
+    assert_eq!(primitives[moved].color, [0.0, 0.0, 0.0, 1.0]);
+    assert_eq!(primitives[recolored].local_transform, 0);
+    assert_eq!(primitives[recolored].color, [1.0, 1.0, 1.0, 1.0]);
+
+    // Unknown transforms are rejected and leave the node unchanged.
+    let unknown = TransformId { buffer: BufferId::new(1), element: Id::new(0) };
+    assert_eq!(
+        batcher.set_transforms(
+            0, Transforms { local: None, view: Some(unknown), root: None },
+            &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+        ),
+        Err(BatchError::UnknownViewTransform { node_index: 0, transform: unknown })
+    );
+    assert_eq!(primitives[moved].local_transform, 1);
+}
+
+#[test]
+fn stamp_with_root_transforms() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+    let mut transforms: CpuBuffer<GpuTransform> = CpuBuffer::new(1024);
+    // The first transform is the identity used by default.
+    transforms.alloc();
+
+    let rect_id = shapes.add_rect(rect(0.0, 0.0, 10.0, 10.0));
+    let mut roots = Vec::new();
+    for i in 0..3 {
+        let placement = GpuTransform::new(Transform3D::create_translation(i as f32 * 20.0, 0.0, 0.0));
+        roots.push(batcher.push_with_transform(
+            PrimitiveParams {
+                z_index: 0,
+                shape: ShapeId::Rect(rect_id),
+                transforms: Transforms { local: None, view: None, root: None },
+                style: FillStyle::new(Pattern::Color(Color::black())),
+            },
+            &placement,
+            &mut transforms,
+        ).unwrap());
+    }
+
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+
+    // The copies share the geometry and are drawn with a single instanced command.
+    assert_eq!(cmds.len(), 1);
+    assert_eq!(cmds[0].instances, 3);
+    assert_eq!(geom.geom.vertices.len(), 4);
+    for i in 0..3 {
+        let prim = primitives[Id::new(cmds[0].first_primitive as u16 + i)];
+        // Render nodes are built in reverse order.
+        let root = roots[2 - i as usize];
+        assert_eq!(prim.root_transform, root.element.to_i32());
+        assert_eq!(prim.local_transform, 0);
+        assert_eq!(transforms[Id::new(root.element.to_u16())].transform[3][0], (2 - i) as f32 * 20.0);
+    }
+
+    // Root transforms are checked like the others.
+    let unknown = TransformId { buffer: BufferId::new(0), element: Id::new(10) };
+    assert_eq!(
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: ShapeId::Rect(rect_id),
+            transforms: Transforms { local: None, view: None, root: Some(unknown) },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }),
+        Err(BatchError::UnknownRootTransform { node_index: 3, transform: unknown })
+    );
+
+    // Rejected nodes don't allocate transforms.
+    let mut transforms: CpuBuffer<GpuTransform> = CpuBuffer::new(2);
+    transforms.alloc();
+    let placement = GpuTransform::new(Transform3D::create_translation(5.0, 0.0, 0.0));
+    let params = |root, clip| PrimitiveParams {
+        z_index: 0,
+        shape: ShapeId::Rect(rect_id),
+        transforms: Transforms { local: None, view: None, root: root },
+        style: FillStyle { clip: clip, .. FillStyle::new(Pattern::Color(Color::black())) },
+    };
+    assert_eq!(
+        batcher.push_with_transform(params(None, Some(ClipId::new(1))), &placement, &mut transforms),
+        Err(BatchError::ClippedNode { node_index: 3, clip: ClipId::new(1) })
+    );
+    assert_eq!(
+        batcher.push_with_transform(params(Some(roots[0]), None), &placement, &mut transforms),
+        Err(BatchError::RootTransformAlreadySet { node_index: 3, transform: roots[0] })
+    );
+    let root = batcher.push_with_transform(params(None, None), &placement, &mut transforms).unwrap();
+    assert_eq!(root.element, Id::new(1));
+    assert_eq!(
+        batcher.push_with_transform(params(None, None), &placement, &mut transforms),
+        Err(BatchError::TransformBufferFull { node_index: 4 })
+    );
+}
+
+#[test]
+fn toggle_visibility() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let rect_id = shapes.add_rect(rect(0.0, 0.0, 10.0, 10.0));
+    for _ in 0..2 {
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: ShapeId::Rect(rect_id),
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }).unwrap();
+    }
+
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+    assert_eq!(cmds.len(), 1);
+
+    let hidden = batcher.set_visible(
+        0, false,
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+    assert!(!batcher.is_visible(0));
+    assert!(!primitives[hidden].is_visible());
+    assert!(primitives[Id::new(0)].is_visible());
+
+    // Rebuilding keeps the node hidden, and in the same instanced command.
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+    assert_eq!(cmds.len(), 1);
+    assert!(!primitives[hidden].is_visible());
+
+    batcher.set_visible(
+        0, true,
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    );
+    assert!(primitives[hidden].is_visible());
+}
+
+#[test]
+fn grouped_shapes() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let ellipse = shapes.add_ellipse(Ellipse { center: point(0.0, 0.0), radii: vec2(10.0, 5.0) });
+    let mut markers: Vec<ShapeId> = (0..100).map(|i| {
+        ShapeId::Rect(shapes.add_rect(rect(i as f32 * 20.0, 0.0, 10.0, 10.0)))
+    }).collect();
+    markers.push(ShapeId::Ellipse(ellipse));
+
+    let group = batcher.push_group(
+        &mut shapes,
+        markers,
+        0,
+        Transforms { local: None, view: None, root: None },
+        FillStyle::new(Pattern::Color(Color::black())),
+    ).unwrap();
+    assert_eq!(shapes.get_group(group).len(), 101);
+    assert!(batcher.validate(&shapes).is_ok());
+
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+
+    // A single primitive and draw call for all the shapes.
+    assert_eq!(cmds.len(), 1);
+    assert_eq!(cmds[0].instances, 1);
+    assert!(cmds[0].geometry.indices.range.count() > 600);
+    assert_eq!(geom.geometry().indices.len(), cmds[0].geometry.indices.range.count() as usize);
+    assert_eq!(primitives.try_alloc(), Some(Id::new(1)));
+}
+
+#[test]
+fn validate_render_nodes() {
+    let mut batcher = OpaqueBatcher::<FillPrimitiveId, FillStyle>::new();
+    let mut shapes = ShapeStore::new();
+    let rect_id = shapes.add_rect(rect(0.0, 0.0, 10.0, 10.0));
+
+    let out_of_bounds = TransformId { buffer: BufferId::new(0), element: Id::new(PRIM_BUFFER_LEN as u16) };
+    batcher.add_transform(out_of_bounds);
+    assert_eq!(
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: ShapeId::Rect(rect_id),
+            transforms: Transforms { local: Some(out_of_bounds), view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }),
+        Err(BatchError::TransformOutOfBounds { node_index: 0, transform: out_of_bounds })
+    );
+
+    // Clipped fills belong to the transparent pass.
+    assert_eq!(
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: ShapeId::Rect(rect_id),
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle { clip: Some(ClipId::new(2)), .. FillStyle::new(Pattern::Color(Color::black())) },
+        }),
+        Err(BatchError::ClippedNode { node_index: 0, clip: ClipId::new(2) })
+    );
+    let stroke_gradient = Pattern::StrokeGradient(StrokeGradient { start: Color::black(), end: Color::white() });
+    assert_eq!(
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: ShapeId::Rect(rect_id),
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(stroke_gradient),
+        }),
+        Err(BatchError::UnsupportedPattern { node_index: 0 })
+    );
+    let vector_image = Pattern::VectorImage(VectorImagePattern {
+        image: Id::new(0),
+        tile: rect(0.0, 0.0, 10.0, 10.0),
+        is_opaque: true,
+    });
+    assert_eq!(
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: ShapeId::Rect(rect_id),
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(vector_image),
+        }),
+        Err(BatchError::UnsupportedPattern { node_index: 0 })
+    );
+
+    for &shape in &[ShapeId::Rect(rect_id), ShapeId::Rect(Id::new(1)), ShapeId::Path(Id::new(0))] {
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: shape,
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }).unwrap();
+    }
+
+    assert_eq!(
+        batcher.validate(&shapes),
+        Err(vec![
+            BatchError::UnknownShape { node_index: 1, shape: ShapeId::Rect(Id::new(1)) },
+            BatchError::UnknownShape { node_index: 2, shape: ShapeId::Path(Id::new(0)) },
+        ])
+    );
+
+    shapes.add_rect(rect(0.0, 0.0, 1.0, 1.0));
+    batcher.clear();
+    batcher.push_item(PrimitiveParams {
+        z_index: 0,
+        shape: ShapeId::Rect(Id::new(1)),
+        transforms: Transforms { local: None, view: None, root: None },
+        style: FillStyle::new(Pattern::Color(Color::black())),
+    }).unwrap();
+    assert_eq!(batcher.validate(&shapes), Ok(()));
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn build_invalid_render_nodes() {
+    let mut batcher = OpaqueBatcher::<FillPrimitiveId, FillStyle>::new();
+    let shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    batcher.push_item(PrimitiveParams {
+        z_index: 0,
+        shape: ShapeId::Rect(Id::new(0)),
+        transforms: Transforms { local: None, view: None, root: None },
+        style: FillStyle::new(Pattern::Color(Color::black())),
+    }).unwrap();
+
+    let result = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    );
+    match result {
+        Err(error) => {
+            assert_eq!(error, BatchError::UnknownShape { node_index: 0, shape: ShapeId::Rect(Id::new(0)) });
+        }
+        Ok(_) => panic!("expected an error"),
+    }
+    assert_eq!(geom.len(), 0);
+}
+
+#[test]
+fn unknown_transforms() {
+    let mut batcher = OpaqueBatcher::<FillPrimitiveId, FillStyle>::new();
+    let mut shapes = ShapeStore::new();
+    let rect_id = shapes.add_rect(rect(0.0, 0.0, 10.0, 10.0));
+
+    let known = TransformId { buffer: BufferId::new(0), element: Id::new(0) };
+    let unknown = TransformId { buffer: BufferId::new(1), element: Id::new(0) };
+    batcher.add_transform(known);
+
+    let params = |local, view| {
+        PrimitiveParams {
+            z_index: 0,
+            shape: ShapeId::Rect(rect_id),
+            transforms: Transforms { local: local, view: view, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }
+    };
+
+    assert_eq!(batcher.push_item(params(Some(known), None)), Ok(()));
+    assert_eq!(
+        batcher.push_item(params(Some(known), Some(unknown))),
+        Err(BatchError::UnknownViewTransform { node_index: 1, transform: unknown })
+    );
+    assert_eq!(
+        batcher.push_item(params(Some(unknown), Some(known))),
+        Err(BatchError::UnknownLocalTransform { node_index: 1, transform: unknown })
     );
+    assert_eq!(batcher.render_nodes.len(), 1);
 }
@@ -5,6 +5,9 @@ use std::hash;
 use std::marker::PhantomData;
 use std::ops;
 
+#[cfg(feature = "serialization")]
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
 pub struct Id<T> {
     handle: u16,
     _marker: PhantomData<T>,
@@ -28,6 +31,13 @@ impl<T> ::std::cmp::PartialEq for Id<T> {
     fn ne(&self, other: &Self) -> bool { self.handle != other.handle }
 }
 
+impl<T> ::std::cmp::PartialOrd for Id<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> { Some(self.cmp(other)) }
+}
+impl<T> ::std::cmp::Ord for Id<T> {
+    fn cmp(&self, other: &Self) -> cmp::Ordering { self.handle.cmp(&other.handle) }
+}
+
 impl<T> fmt::Debug for Id<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "#{}", self.handle) }
 }
@@ -36,6 +46,20 @@ impl<T> hash::Hash for Id<T> {
     fn hash<H: hash::Hasher>(&self, state: &mut H) { self.handle.hash(state); }
 }
 
+#[cfg(feature = "serialization")]
+impl<T> Serialize for Id<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.handle.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<'de, T> Deserialize<'de> for Id<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u16::deserialize(deserializer).map(Id::new)
+    }
+}
+
 pub struct IdRange<T> {
     start: u16,
     end: u16,
@@ -79,6 +103,31 @@ impl<T> IdRange<T> {
     #[inline]
     pub fn count(&self) -> u16 { self.end - self.start }
 
+    #[inline]
+    pub fn len(&self) -> usize { self.count() as usize }
+
+    /// Splits the range into the first `n` ids and the remaining ones.
+    #[inline]
+    pub fn split_at(&self, n: u16) -> (Self, Self) {
+        assert!(n <= self.count(), "Split index out of range.");
+        let mid = self.start + n;
+        return (IdRange::new(self.start..mid), IdRange::new(mid..self.end));
+    }
+
+    /// Returns the `count` ids starting at the `offset`-th id of the range.
+    #[inline]
+    pub fn sub_range(&self, offset: u16, count: u16) -> Self {
+        assert!(offset + count <= self.count(), "Sub-range out of range.");
+        let start = self.start + offset;
+        return IdRange::new(start..(start + count));
+    }
+
+    /// Iterates over the ids of the range.
+    #[inline]
+    pub fn iter(&self) -> IdRangeIter<T> {
+        IdRangeIter { range: *self }
+    }
+
     #[inline]
     pub fn get(&self, n: u16) -> Id<T> {
         assert!(n < (self.end - self.start), "Shape id out of range.");
@@ -127,19 +176,70 @@ impl<T> ::std::cmp::PartialEq for IdRange<T> {
     fn ne(&self, other: &Self) -> bool { self.start != other.start || self.end != other.end }
 }
 
+impl<T> IntoIterator for IdRange<T> {
+    type Item = Id<T>;
+    type IntoIter = IdRangeIter<T>;
+    fn into_iter(self) -> IdRangeIter<T> { self.iter() }
+}
+
+/// Iterator over the ids of an `IdRange`.
+pub struct IdRangeIter<T> {
+    range: IdRange<T>,
+}
+
+impl<T> Iterator for IdRangeIter<T> {
+    type Item = Id<T>;
+    fn next(&mut self) -> Option<Id<T>> {
+        if self.range.is_empty() {
+            return None;
+        }
+        let id = self.range.start();
+        self.range.start += 1;
+        return Some(id);
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.range.len();
+        (len, Some(len))
+    }
+}
+
 impl<T> fmt::Debug for IdRange<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "IdRange({}..{})", self.start, self.end) }
 }
 
+#[cfg(feature = "serialization")]
+impl<T> Serialize for IdRange<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.start, self.end).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<'de, T> Deserialize<'de> for IdRange<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <(u16, u16)>::deserialize(deserializer).map(|(start, end)| IdRange::new(start..end))
+    }
+}
+
+/// Identifies a page of a `BufferStore`.
+///
+/// Ids also contain the generation of the store they were allocated from, which lets the
+/// store detect ids that outlived a `clear`.
 pub struct BufferId<T> {
     handle: u32,
+    generation: u32,
     _marker: PhantomData<T>,
 }
 impl<T> BufferId<T> {
-    pub fn new(handle: u32) -> Self { BufferId { handle: handle, _marker: PhantomData  } }
+    pub fn new(handle: u32) -> Self { BufferId::with_generation(handle, 0) }
+    pub fn with_generation(handle: u32, generation: u32) -> Self {
+        BufferId { handle: handle, generation: generation, _marker: PhantomData  }
+    }
     pub fn index(&self) -> usize { self.handle as usize }
     pub fn to_i32(&self) -> i32 { self.handle as i32 }
     pub fn to_u32(&self) -> u32 { self.handle }
+    pub fn generation(&self) -> u32 { self.generation }
 }
 
 impl<T> Copy for BufferId<T> {}
@@ -148,21 +248,44 @@ impl<T> Clone for BufferId<T> { fn clone(&self) -> Self { *self } }
 
 impl<T> ::std::cmp::Eq for BufferId<T> {}
 impl<T> ::std::cmp::PartialEq for BufferId<T> {
-    fn eq(&self, other: &Self) -> bool { self.handle == other.handle }
-    fn ne(&self, other: &Self) -> bool { self.handle != other.handle }
+    fn eq(&self, other: &Self) -> bool { self.handle == other.handle && self.generation == other.generation }
+    fn ne(&self, other: &Self) -> bool { !self.eq(other) }
 }
 
 impl<T> fmt::Debug for BufferId<T> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "Buffer#{}", self.handle) }
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Buffer#{}(gen {})", self.handle, self.generation)
+    }
 }
 
 impl<T> hash::Hash for BufferId<T> {
-    fn hash<H: hash::Hasher>(&self, state: &mut H) { self.handle.hash(state); }
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.handle.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<T> Serialize for BufferId<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.handle, self.generation).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl<'de, T> Deserialize<'de> for BufferId<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <(u32, u32)>::deserialize(deserializer).map(|(handle, generation)| {
+            BufferId::with_generation(handle, generation)
+        })
+    }
 }
 
 impl<T> Copy for BufferRange<T> {}
 impl<T> Clone for BufferRange<T> { fn clone(&self) -> Self { *self } }
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialization", serde(bound = ""))]
 pub struct BufferRange<T> {
     pub buffer: BufferId<T>,
     pub range: IdRange<T>,
@@ -177,14 +300,80 @@ impl<T> BufferRange<T> {
     }
 
     pub fn first(&self) -> BufferElement<T> { self.get(0) }
+
+    pub fn len(&self) -> usize { self.range.len() }
+
+    pub fn is_empty(&self) -> bool { self.range.is_empty() }
+
+    /// Splits the range into the first `n` elements and the remaining ones.
+    pub fn split_at(&self, n: u16) -> (Self, Self) {
+        let (first, second) = self.range.split_at(n);
+        return (
+            BufferRange { buffer: self.buffer, range: first },
+            BufferRange { buffer: self.buffer, range: second },
+        );
+    }
+
+    /// Returns the `count` elements starting at the `offset`-th element of the range.
+    pub fn sub_range(&self, offset: u16, count: u16) -> Self {
+        BufferRange {
+            buffer: self.buffer,
+            range: self.range.sub_range(offset, count),
+        }
+    }
+
+    /// Iterates over the elements of the range, for example to write per-instance data.
+    pub fn iter(&self) -> BufferRangeIter<T> {
+        BufferRangeIter {
+            buffer: self.buffer,
+            ids: self.range.iter(),
+        }
+    }
+}
+
+impl<T> IntoIterator for BufferRange<T> {
+    type Item = BufferElement<T>;
+    type IntoIter = BufferRangeIter<T>;
+    fn into_iter(self) -> BufferRangeIter<T> { self.iter() }
+}
+
+/// Iterator over the elements of a `BufferRange`.
+pub struct BufferRangeIter<T> {
+    buffer: BufferId<T>,
+    ids: IdRangeIter<T>,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+impl<T> Iterator for BufferRangeIter<T> {
+    type Item = BufferElement<T>;
+    fn next(&mut self) -> Option<BufferElement<T>> {
+        let buffer = self.buffer;
+        self.ids.next().map(|id| BufferElement { buffer: buffer, element: id })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { self.ids.size_hint() }
+}
+
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialization", serde(bound = ""))]
 pub struct BufferElement<T> {
     pub buffer: BufferId<T>,
     pub element: Id<T>,
 }
 
+impl<T> ::std::cmp::Eq for BufferElement<T> {}
+impl<T> ::std::cmp::PartialEq for BufferElement<T> {
+    fn eq(&self, other: &Self) -> bool { self.buffer == other.buffer && self.element == other.element }
+}
+
+impl<T> hash::Hash for BufferElement<T> {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.buffer.hash(state);
+        self.element.hash(state);
+    }
+}
+
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
 pub struct CpuBuffer<T> {
     data: Vec<T>,
     allocator: SimpleBufferAllocator,
@@ -253,6 +442,12 @@ impl<T: Default+Copy> CpuBuffer<T> {
         return &self.data[range.start_index()..(range.end as usize)]
     }
 
+    /// Discards all allocations.
+    pub fn clear(&mut self) {
+        self.allocator = SimpleBufferAllocator::new(self.allocator.len());
+        self.dirty = true;
+    }
+
     pub fn flush_dirty_range(&mut self) -> IdRange<T> {
         if self.dirty {
             self.dirty = false;
@@ -288,6 +483,7 @@ impl<T: Copy+Default> std::ops::IndexMut<IdRange<T>> for CpuBuffer<T> {
     }
 }
 
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
 pub struct SimpleBufferAllocator {
     back_index: u16,
     front_index: u16,
@@ -374,10 +570,25 @@ impl<T> TypedSimpleBufferAllocator<T> {
 }
 
 
+/// A collection of fixed-size buffers (pages).
+///
+/// Allocations never span several pages, and growing the store adds pages instead
+/// of reallocating, so existing data is never moved or copied around. Elements are
+/// addressed with `BufferElement`/`BufferRange` which contain the page id and the
+/// offset within the page.
+///
+/// The store has a generation counter that is incremented every time it is cleared and
+/// baked into the ids it hands out. In debug builds, accessing the store with an id from
+/// a previous generation panics instead of silently reading or overwriting unrelated data.
+///
+/// With the `serialization` feature, stores can be serialized with serde along with the
+/// allocation state of their pages.
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
 pub struct BufferStore<Primitive> {
     pub buffers: Vec<CpuBuffer<Primitive>>,
     current: BufferId<Primitive>,
     buffer_len: u16,
+    generation: u32,
 }
 
 impl<Primitive: Copy+Default> BufferStore<Primitive> {
@@ -386,6 +597,7 @@ impl<Primitive: Copy+Default> BufferStore<Primitive> {
             buffers: Vec::new(),
             current: BufferId::new(0),
             buffer_len: size,
+            generation: 0,
         };
         for _ in 0..count {
             store.alloc_buffer();
@@ -393,7 +605,7 @@ impl<Primitive: Copy+Default> BufferStore<Primitive> {
         return store;
     }
 
-    pub fn first_buffer_id(&self) -> BufferId<Primitive> { BufferId::new(0) }
+    pub fn first_buffer_id(&self) -> BufferId<Primitive> { BufferId::with_generation(0, self.generation) }
 
     pub fn current_buffer_id(&self) -> BufferId<Primitive> { self.current }
 
@@ -405,7 +617,7 @@ impl<Primitive: Copy+Default> BufferStore<Primitive> {
     }
 
     pub fn bump_current_buffer(&mut self) {
-        self.current = BufferId::new(self.current.to_u32() + 1);
+        self.current = BufferId::with_generation(self.current.to_u32() + 1, self.generation);
         while self.current.index() >= self.buffers.len() {
             self.alloc_buffer();
         }
@@ -416,6 +628,45 @@ impl<Primitive: Copy+Default> BufferStore<Primitive> {
         self.buffers.push(CpuBuffer::new(len));
     }
 
+    /// Discards all allocations so that the content of the store can be rebuilt.
+    ///
+    /// The pages are kept, but the ids allocated so far become stale.
+    pub fn clear(&mut self) {
+        let generation = self.generation + 1;
+        self.reset(generation);
+    }
+
+    fn reset(&mut self, generation: u32) {
+        for buffer in &mut self.buffers {
+            buffer.clear();
+        }
+        self.generation = generation;
+        self.current = BufferId::with_generation(0, self.generation);
+    }
+
+    /// Number of times the store was cleared.
+    pub fn generation(&self) -> u32 { self.generation }
+
+    /// Returns true if the id was allocated since the store was last cleared.
+    pub fn is_valid(&self, id: BufferId<Primitive>) -> bool {
+        id.generation == self.generation && id.index() < self.buffers.len()
+    }
+
+    /// Number of pages.
+    pub fn num_buffers(&self) -> usize { self.buffers.len() }
+
+    /// Size of each page.
+    pub fn buffer_len(&self) -> u16 { self.buffer_len }
+
+    /// Iterates over the contiguous content of each page, typically to upload them.
+    pub fn pages(&self) -> Pages<Primitive> {
+        Pages {
+            buffers: &self.buffers,
+            idx: 0,
+            generation: self.generation,
+        }
+    }
+
     pub fn alloc_range(&mut self, count: u16) -> BufferRange<Primitive> {
         assert!(count <= self.buffer_len);
         loop {
@@ -479,15 +730,127 @@ impl<Primitive: Copy+Default> BufferStore<Primitive> {
     }
 }
 
+/// A ring of resources, one per frame in flight.
+///
+/// The resource of the current frame can be written to while the resources of the previous
+/// frames may still be in use by the GPU. Advancing to the next frame recycles the resource
+/// that was used `len` frames ago, so the caller must make sure that the GPU is never more
+/// than `len - 1` frames behind (for example by waiting on the swap chain or on a fence)
+/// before advancing.
+pub struct FrameRing<T> {
+    items: Vec<T>,
+    current: usize,
+    frame: u64,
+}
+
+impl<T> FrameRing<T> {
+    pub fn new(items: Vec<T>) -> Self {
+        assert!(!items.is_empty());
+        FrameRing {
+            items: items,
+            current: 0,
+            frame: 0,
+        }
+    }
+
+    pub fn from_fn<F: FnMut() -> T>(len: usize, mut f: F) -> Self {
+        FrameRing::new((0..len).map(|_| f()).collect())
+    }
+
+    /// Number of frames in flight.
+    pub fn len(&self) -> usize { self.items.len() }
+
+    /// Index of the current frame, starting at zero.
+    pub fn frame(&self) -> u64 { self.frame }
+
+    pub fn current(&self) -> &T { &self.items[self.current] }
+
+    pub fn current_mut(&mut self) -> &mut T { &mut self.items[self.current] }
+
+    /// Moves to the next frame and returns the recycled resource.
+    pub fn advance(&mut self) -> &mut T {
+        self.frame += 1;
+        self.current = (self.current + 1) % self.items.len();
+        return &mut self.items[self.current];
+    }
+}
+
+/// Per-frame arenas for dynamic data.
+///
+/// Each frame allocates from its own `BufferStore`, which is cleared when it is recycled
+/// instead of growing forever. The stores use the frame index as their generation, so ids
+/// allocated during a previous frame are detected as stale in debug builds.
+pub struct PerFrameBufferStore<Primitive> {
+    stores: FrameRing<BufferStore<Primitive>>,
+}
+
+impl<Primitive: Copy+Default> PerFrameBufferStore<Primitive> {
+    pub fn new(frames_in_flight: usize, count: u16, size: u16) -> Self {
+        PerFrameBufferStore {
+            stores: FrameRing::from_fn(frames_in_flight, || BufferStore::new(count, size)),
+        }
+    }
+
+    /// Moves to the next frame and returns its recycled, empty store.
+    pub fn begin_frame(&mut self) -> &mut BufferStore<Primitive> {
+        let generation = self.stores.frame() as u32 + 1;
+        let store = self.stores.advance();
+        store.reset(generation);
+        return store;
+    }
+
+    /// Index of the current frame.
+    pub fn frame(&self) -> u64 { self.stores.frame() }
+
+    /// Number of frames in flight.
+    pub fn frames_in_flight(&self) -> usize { self.stores.len() }
+
+    pub fn current(&self) -> &BufferStore<Primitive> { self.stores.current() }
+
+    pub fn current_mut(&mut self) -> &mut BufferStore<Primitive> { self.stores.current_mut() }
+}
+
+/// Iterator over the pages of a `BufferStore`.
+pub struct Pages<'l, T: 'l> {
+    buffers: &'l [CpuBuffer<T>],
+    idx: usize,
+    generation: u32,
+}
+
+impl<'l, T: 'l + Copy + Default> Iterator for Pages<'l, T> {
+    type Item = (BufferId<T>, &'l [T]);
+    fn next(&mut self) -> Option<(BufferId<T>, &'l [T])> {
+        if self.idx >= self.buffers.len() {
+            return None;
+        }
+        let idx = self.idx;
+        self.idx += 1;
+        return Some((BufferId::with_generation(idx as u32, self.generation), self.buffers[idx].as_slice()));
+    }
+}
+
+impl<T> BufferStore<T> {
+    #[inline]
+    fn check_generation(&self, id: BufferId<T>) {
+        debug_assert!(
+            id.generation == self.generation,
+            "Stale {:?} used after the store was cleared (current generation: {}).",
+            id, self.generation,
+        );
+    }
+}
+
 impl<T> ops::Index<BufferId<T>> for BufferStore<T> {
     type Output = CpuBuffer<T>;
     fn index(&self, id: BufferId<T>) -> &CpuBuffer<T> {
+        self.check_generation(id);
         &self.buffers[id.index()]
     }
 }
 
 impl<T> ops::IndexMut<BufferId<T>> for BufferStore<T> {
     fn index_mut(&mut self, id: BufferId<T>) -> &mut CpuBuffer<T> {
+        self.check_generation(id);
         &mut self.buffers[id.index()]
     }
 }
@@ -495,12 +858,14 @@ impl<T> ops::IndexMut<BufferId<T>> for BufferStore<T> {
 impl<T: Copy+Default> ops::Index<BufferRange<T>> for BufferStore<T> {
     type Output = [T];
     fn index(&self, id: BufferRange<T>) -> &[T] {
+        self.check_generation(id.buffer);
         &self.buffers[id.buffer.index()][id.range]
     }
 }
 
 impl<T: Copy+Default> ops::IndexMut<BufferRange<T>> for BufferStore<T> {
     fn index_mut(&mut self, id: BufferRange<T>) -> &mut [T] {
+        self.check_generation(id.buffer);
         &mut self.buffers[id.buffer.index()][id.range]
     }
 }
@@ -508,12 +873,140 @@ impl<T: Copy+Default> ops::IndexMut<BufferRange<T>> for BufferStore<T> {
 impl<T: Copy+Default> ops::Index<BufferElement<T>> for BufferStore<T> {
     type Output = T;
     fn index(&self, id: BufferElement<T>) -> &T {
+        self.check_generation(id.buffer);
         &self.buffers[id.buffer.index()][id.element]
     }
 }
 
 impl<T: Copy+Default> ops::IndexMut<BufferElement<T>> for BufferStore<T> {
     fn index_mut(&mut self, id: BufferElement<T>) -> &mut T {
+        self.check_generation(id.buffer);
         &mut self.buffers[id.buffer.index()][id.element]
     }
 }
+
+#[test]
+fn buffer_store_pages() {
+    let mut store: BufferStore<u32> = BufferStore::new(1, 4);
+
+    let mut elements = Vec::new();
+    for i in 0..10 {
+        elements.push(store.push(i));
+    }
+
+    // Ten elements in pages of four.
+    assert_eq!(store.num_buffers(), 3);
+    for (i, &e) in elements.iter().enumerate() {
+        assert_eq!(store[e], i as u32);
+        assert_eq!(e.buffer, BufferId::new(i as u32 / 4));
+    }
+
+    let pages: Vec<(BufferId<u32>, &[u32])> = store.pages().collect();
+    assert_eq!(pages.len(), 3);
+    assert_eq!(pages[0].1, &[0, 1, 2, 3]);
+    assert_eq!(pages[2].0, BufferId::new(2));
+    assert_eq!(pages[2].1, &[8, 9, 0, 0]);
+}
+
+#[test]
+fn buffer_range_sub_allocation() {
+    let mut store: BufferStore<u32> = BufferStore::new(1, 16);
+    store.push(0);
+    let range = store.alloc_range(6);
+    assert_eq!(range.len(), 6);
+
+    let (head, tail) = range.split_at(2);
+    assert_eq!(head.len(), 2);
+    assert_eq!(tail.len(), 4);
+    assert_eq!(tail.first(), range.get(2));
+
+    let sub = range.sub_range(1, 3);
+    assert_eq!(sub.first(), range.get(1));
+    assert_eq!(sub.len(), 3);
+
+    for (i, element) in range.iter().enumerate() {
+        store[element] = i as u32 * 10;
+    }
+    assert_eq!(&store[range], &[0, 10, 20, 30, 40, 50]);
+    assert_eq!(range.iter().size_hint(), (6, Some(6)));
+    assert_eq!(range.split_at(6).1.iter().next(), None);
+}
+
+#[test]
+fn buffer_store_generations() {
+    let mut store: BufferStore<u32> = BufferStore::new(1, 4);
+    let a = store.push(1);
+    assert!(store.is_valid(a.buffer));
+    assert_eq!(store.generation(), 0);
+
+    store.clear();
+    assert_eq!(store.generation(), 1);
+    assert!(!store.is_valid(a.buffer));
+
+    // Allocations restart from the first page, with the new generation.
+    let b = store.push(2);
+    assert_eq!(b.element, a.element);
+    assert!(b != a);
+    assert_eq!(store[b], 2);
+    assert_eq!(store.pages().next().unwrap().0, b.buffer);
+}
+
+#[test]
+fn per_frame_buffer_store() {
+    let mut stores: PerFrameBufferStore<u32> = PerFrameBufferStore::new(2, 1, 4);
+    let a = stores.current_mut().push(1);
+
+    // The next frame writes into another store while frame 0 may still be in flight.
+    let b = stores.begin_frame().push(2);
+    assert_eq!(stores.frame(), 1);
+    assert_eq!(stores.current()[b], 2);
+    assert!(!stores.current().is_valid(a.buffer));
+
+    // Frame 2 recycles the store of frame 0.
+    let c = stores.begin_frame().push(3);
+    assert_eq!(c.element, a.element);
+    assert!(!stores.current().is_valid(a.buffer));
+    assert!(!stores.current().is_valid(b.buffer));
+    assert_eq!(stores.current()[c], 3);
+
+    let mut ring = FrameRing::new(vec![0, 1, 2]);
+    assert_eq!(*ring.current(), 0);
+    assert_eq!(*ring.advance(), 1);
+    ring.advance();
+    assert_eq!(*ring.advance(), 0);
+    assert_eq!(ring.frame(), 3);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic]
+fn buffer_store_stale_id() {
+    let mut store: BufferStore<u32> = BufferStore::new(1, 4);
+    let a = store.push(1);
+    store.clear();
+    store[a] = 3;
+}
+
+#[cfg(feature = "serialization")]
+#[test]
+fn buffer_store_serialization() {
+    let mut store: BufferStore<u32> = BufferStore::new(1, 4);
+    let mut elements = Vec::new();
+    for i in 0..6 {
+        elements.push(store.push(i));
+    }
+
+    let json = ::serde_json::to_string(&store).unwrap();
+    let mut deserialized: BufferStore<u32> = ::serde_json::from_str(&json).unwrap();
+
+    assert_eq!(deserialized.num_buffers(), 2);
+    for (i, &e) in elements.iter().enumerate() {
+        assert_eq!(deserialized[e], i as u32);
+    }
+    // The allocation state is preserved.
+    assert_eq!(deserialized.push(6), store.push(6));
+
+    let json = ::serde_json::to_string(&elements[5]).unwrap();
+    let element: BufferElement<u32> = ::serde_json::from_str(&json).unwrap();
+    assert_eq!(element, elements[5]);
+}
@@ -0,0 +1,154 @@
+//! What the device supports, and the rendering strategies that follow from it.
+//!
+//! The renderer used to assume a desktop GL 3 class device. WebGL and GLES class devices
+//! lack some of the features it relies on, so the batcher and the passes are configured
+//! from a `DeviceCapabilities` instead, either queried from the device or built by hand
+//! (see `DeviceCapabilities::minimal`) to test the fallbacks.
+
+use gfx;
+use gfx::Device;
+
+use gfx_types::GlDevice;
+use core::math::IntSize;
+
+/// The capabilities of a device that affect how the renderer draws.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DeviceCapabilities {
+    /// The maximum width and height of a texture or render target.
+    pub max_texture_size: u32,
+    /// The maximum number of instances of an instanced draw call.
+    pub max_instances_per_draw: u32,
+    /// Instanced draw calls are supported and the shaders can read gl_InstanceID.
+    pub instance_id_supported: bool,
+    /// Per-instance vertex attributes are supported.
+    pub instance_attributes_supported: bool,
+    /// The primitives can be stored in constant (uniform) buffers.
+    pub constant_buffers_supported: bool,
+    /// The primitives can be stored in storage buffers, which can be much larger.
+    pub storage_buffers_supported: bool,
+    /// Render targets can encode their content to sRGB, see `RenderOptions::linear_blending`.
+    pub srgb_targets_supported: bool,
+    /// The maximum number of samples of multisampled render targets, 1 without MSAA.
+    pub max_msaa_samples: u8,
+}
+
+/// How the primitive of each instance of a draw call is found.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Instancing {
+    /// The shaders offset the primitive of the vertices with gl_InstanceID.
+    ///
+    /// Only geometry that stores its primitive (built with `WithId`) can be drawn this way.
+    /// The commands of the batcher share the geometry of a shape between primitives and
+    /// always read the primitive from the per-instance attributes.
+    InstanceId,
+    /// The primitive is read from the per-instance `GpuInstance` attributes.
+    InstanceAttributes,
+    /// Each instance is drawn with its own draw call.
+    Disabled,
+}
+
+impl DeviceCapabilities {
+    /// Queries the capabilities of a device.
+    ///
+    /// gfx doesn't report the number of samples, so this assumes 4, the minimum that GL 3
+    /// guarantees.
+    pub fn query(device: &GlDevice) -> Self {
+        DeviceCapabilities::from_gfx(device.get_capabilities(), 4)
+    }
+
+    pub fn from_gfx(caps: &gfx::Capabilities, max_msaa_samples: u8) -> Self {
+        DeviceCapabilities {
+            max_texture_size: caps.max_texture_size as u32,
+            max_instances_per_draw: if caps.instance_call_supported { u32::max_value() } else { 1 },
+            instance_id_supported: caps.instance_call_supported,
+            instance_attributes_supported: caps.instance_rate_supported,
+            constant_buffers_supported: caps.constant_buffer_supported,
+            storage_buffers_supported: caps.unordered_access_view_supported,
+            srgb_targets_supported: caps.srgb_color_supported,
+            max_msaa_samples: max_msaa_samples.max(1),
+        }
+    }
+
+    /// The capabilities that all the devices the renderer runs on have, which correspond
+    /// to WebGL 1 with the instancing extension.
+    pub fn minimal() -> Self {
+        DeviceCapabilities {
+            max_texture_size: 2048,
+            max_instances_per_draw: u32::max_value(),
+            instance_id_supported: false,
+            instance_attributes_supported: true,
+            constant_buffers_supported: false,
+            storage_buffers_supported: false,
+            srgb_targets_supported: false,
+            max_msaa_samples: 1,
+        }
+    }
+
+    /// The way instanced draw calls are emitted.
+    pub fn instancing(&self) -> Instancing {
+        if self.max_instances_per_draw <= 1 {
+            return Instancing::Disabled;
+        }
+        if self.instance_id_supported {
+            return Instancing::InstanceId;
+        }
+        if self.instance_attributes_supported {
+            return Instancing::InstanceAttributes;
+        }
+
+        return Instancing::Disabled;
+    }
+
+    /// The maximum number of instances the batcher may merge into a draw call.
+    pub fn batch_max_instances(&self) -> u32 {
+        match self.instancing() {
+            Instancing::Disabled => 1,
+            _ => self.max_instances_per_draw,
+        }
+    }
+
+    /// The number of samples to use for a requested number of MSAA samples.
+    ///
+    /// Returns 1 when MSAA isn't supported, in which case anti-aliasing must rely on the
+    /// `FillStyle::aa` fringes instead.
+    pub fn msaa_samples(&self, requested: u8) -> u8 {
+        let mut samples: u32 = 1;
+        while samples * 2 <= requested as u32 && samples * 2 <= self.max_msaa_samples as u32 {
+            samples *= 2;
+        }
+
+        return samples as u8;
+    }
+
+    /// Whether a texture or render target of this size can be created.
+    pub fn supports_texture_size(&self, size: IntSize) -> bool {
+        size.width >= 0 && size.height >= 0
+            && size.width as u32 <= self.max_texture_size
+            && size.height as u32 <= self.max_texture_size
+    }
+}
+
+#[test]
+fn device_capabilities() {
+    let minimal = DeviceCapabilities::minimal();
+    assert_eq!(minimal.instancing(), Instancing::InstanceAttributes);
+    assert_eq!(minimal.msaa_samples(4), 1);
+    assert!(!minimal.supports_texture_size(IntSize::new(4096, 16)));
+
+    let caps = DeviceCapabilities {
+        instance_id_supported: true,
+        max_msaa_samples: 8,
+        .. minimal
+    };
+    assert_eq!(caps.instancing(), Instancing::InstanceId);
+    assert_eq!(caps.msaa_samples(4), 4);
+    assert_eq!(caps.msaa_samples(6), 4);
+    assert_eq!(caps.msaa_samples(16), 8);
+
+    let no_instancing = DeviceCapabilities {
+        instance_attributes_supported: false,
+        .. minimal
+    };
+    assert_eq!(no_instancing.instancing(), Instancing::Disabled);
+    assert_eq!(no_instancing.batch_max_instances(), 1);
+}
@@ -0,0 +1,294 @@
+//! A low-level, serializable stream of the uploads and draw calls of a frame.
+//!
+//! Instead of (or in addition to) submitting the output of the batchers to the device, it
+//! can be recorded into a `CommandStream` that contains everything needed to render the
+//! frame: the geometry, the primitives and transforms, the per-instance data, the clears
+//! and the draw calls with the pass and target they belong to. With the `serialization`
+//! feature, the stream can be saved to reproduce backend bugs from a capture file, or sent
+//! to another process that replays it against its own device with a `CommandReplayer`.
+
+use api::ClearOps;
+use buffer::BufferStore;
+use batch_builder::{ Cmd, Geometry };
+use frame::RenderTargetId;
+use stats::{RenderPass, PassTimer, NoPassTimer};
+use renderer::{ GpuFillVertex, GpuStrokeVertex, GpuFillPrimitive, GpuStrokePrimitive };
+use renderer::{ GpuTransform, GpuInstance, Globals };
+#[cfg(test)]
+use batch_builder::*;
+#[cfg(test)]
+use buffer::CpuBuffer;
+#[cfg(test)]
+use api::*;
+#[cfg(test)]
+use core::math::*;
+#[cfg(test)]
+use stats::{CpuPassTimer, RenderStats};
+
+/// An operation of a recorded frame.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub enum RenderCommand {
+    /// Replaces the content of the fill vertex and index buffers.
+    SetFillGeometry { vertices: Vec<GpuFillVertex>, indices: Vec<u16> },
+    /// Replaces the content of the stroke vertex and index buffers.
+    SetStrokeGeometry { vertices: Vec<GpuStrokeVertex>, indices: Vec<u16> },
+    /// Writes fill primitives at an offset of a primitive page.
+    UploadFillPrimitives { page: u32, offset: u32, primitives: Vec<GpuFillPrimitive> },
+    /// Writes stroke primitives at an offset of a primitive page.
+    UploadStrokePrimitives { page: u32, offset: u32, primitives: Vec<GpuStrokePrimitive> },
+    /// Writes transforms at an offset of a transform page.
+    UploadTransforms { page: u32, offset: u32, transforms: Vec<GpuTransform> },
+    /// Replaces the content of the instance buffer.
+    SetInstances { instances: Vec<GpuInstance> },
+    /// Sets the globals of the following draw calls.
+    SetGlobals { resolution: [f32; 2], scroll_offset: [f32; 2], zoom: f32 },
+    /// Clears a target, see `ClearOps`.
+    Clear { target: u32, color: Option<[u8; 4]>, depth: Option<f32> },
+    Draw(DrawCommand),
+}
+
+/// A recorded draw call, see `Cmd`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct DrawCommand {
+    /// The pass, which selects the pipeline and the geometry buffers.
+    pub pass: RenderPass,
+    pub target: u32,
+    pub first_index: u32,
+    pub index_count: u32,
+    pub primitive_page: u32,
+    pub first_primitive: u32,
+    pub instances: u32,
+    pub first_instance: u32,
+    pub z_index: u32,
+}
+
+/// Executes recorded commands, typically by submitting them to a device.
+pub trait CommandReplayer {
+    fn execute(&mut self, command: &RenderCommand);
+}
+
+/// The commands of one or several frames, in the order they must be executed.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub struct CommandStream {
+    commands: Vec<RenderCommand>,
+}
+
+impl CommandStream {
+    pub fn new() -> Self {
+        CommandStream { commands: Vec::new() }
+    }
+
+    pub fn commands(&self) -> &[RenderCommand] { &self.commands }
+
+    pub fn len(&self) -> usize { self.commands.len() }
+
+    pub fn is_empty(&self) -> bool { self.commands.is_empty() }
+
+    pub fn clear(&mut self) { self.commands.clear(); }
+
+    pub fn push(&mut self, command: RenderCommand) {
+        self.commands.push(command);
+    }
+
+    pub fn record_fill_geometry(&mut self, geom: &Geometry<GpuFillVertex>) {
+        self.commands.push(RenderCommand::SetFillGeometry {
+            vertices: geom.vertices.clone(),
+            indices: geom.indices.clone(),
+        });
+    }
+
+    pub fn record_stroke_geometry(&mut self, geom: &Geometry<GpuStrokeVertex>) {
+        self.commands.push(RenderCommand::SetStrokeGeometry {
+            vertices: geom.vertices.clone(),
+            indices: geom.indices.clone(),
+        });
+    }
+
+    /// Records the upload of all the pages of a fill primitive store.
+    pub fn record_fill_primitives(&mut self, store: &BufferStore<GpuFillPrimitive>) {
+        for (page, primitives) in store.pages() {
+            self.commands.push(RenderCommand::UploadFillPrimitives {
+                page: page.to_u32(),
+                offset: 0,
+                primitives: primitives.to_vec(),
+            });
+        }
+    }
+
+    /// Records the upload of all the pages of a stroke primitive store.
+    pub fn record_stroke_primitives(&mut self, store: &BufferStore<GpuStrokePrimitive>) {
+        for (page, primitives) in store.pages() {
+            self.commands.push(RenderCommand::UploadStrokePrimitives {
+                page: page.to_u32(),
+                offset: 0,
+                primitives: primitives.to_vec(),
+            });
+        }
+    }
+
+    /// Records the upload of all the pages of a transform store.
+    pub fn record_transforms(&mut self, store: &BufferStore<GpuTransform>) {
+        for (page, transforms) in store.pages() {
+            self.commands.push(RenderCommand::UploadTransforms {
+                page: page.to_u32(),
+                offset: 0,
+                transforms: transforms.to_vec(),
+            });
+        }
+    }
+
+    /// Records the per-instance data of the commands of a batcher, see
+    /// `OpaqueBatcher::instances`.
+    pub fn record_instances(&mut self, instances: &[GpuInstance]) {
+        self.commands.push(RenderCommand::SetInstances { instances: instances.to_vec() });
+    }
+
+    pub fn record_globals(&mut self, globals: &Globals) {
+        self.commands.push(RenderCommand::SetGlobals {
+            resolution: globals.resolution,
+            scroll_offset: globals.scroll_offset,
+            zoom: globals.zoom,
+        });
+    }
+
+    pub fn record_clear(&mut self, target: RenderTargetId, ops: ClearOps) {
+        self.commands.push(RenderCommand::Clear {
+            target: target.0,
+            color: ops.color.map(|color| color.array()),
+            depth: ops.depth,
+        });
+    }
+
+    /// Records the draw calls produced by a batcher for a pass.
+    pub fn record_draws<Vertex>(&mut self, pass: RenderPass, target: RenderTargetId, cmds: &[Cmd<Vertex>]) {
+        for cmd in cmds {
+            self.commands.push(RenderCommand::Draw(DrawCommand {
+                pass: pass,
+                target: target.0,
+                first_index: cmd.geometry.indices.range.start_index() as u32,
+                index_count: cmd.geometry.indices.range.count() as u32,
+                primitive_page: cmd.primitive_page,
+                first_primitive: cmd.first_primitive,
+                instances: cmd.instances,
+                first_instance: cmd.first_instance,
+                z_index: cmd.z_index,
+            }));
+        }
+    }
+
+    /// Executes the commands in order.
+    pub fn replay<R: CommandReplayer>(&self, replayer: &mut R) {
+        self.replay_timed(replayer, &mut NoPassTimer);
+    }
+
+    /// Same as `replay`, timing each run of consecutive draw calls of the same pass as that
+    /// pass, and each run of geometry, primitive and instance uploads as
+    /// `RenderPass::Uploads`.
+    ///
+    /// Only the CPU time spent in the replayer is measured, see the `stats` module.
+    pub fn replay_timed<R: CommandReplayer, T: PassTimer>(&self, replayer: &mut R, timer: &mut T) {
+        let mut current_pass = None;
+        for command in &self.commands {
+            let pass = command.pass();
+            if pass != current_pass {
+                if let Some(pass) = current_pass {
+                    timer.end_pass_timer(pass);
+                }
+                if let Some(pass) = pass {
+                    timer.begin_pass_timer(pass);
+                }
+                current_pass = pass;
+            }
+            replayer.execute(command);
+        }
+
+        if let Some(pass) = current_pass {
+            timer.end_pass_timer(pass);
+        }
+    }
+}
+
+impl RenderCommand {
+    /// The pass this command is timed in by `CommandStream::replay_timed`, if any.
+    pub fn pass(&self) -> Option<RenderPass> {
+        match *self {
+            RenderCommand::SetFillGeometry { .. }
+            | RenderCommand::SetStrokeGeometry { .. }
+            | RenderCommand::UploadFillPrimitives { .. }
+            | RenderCommand::UploadStrokePrimitives { .. }
+            | RenderCommand::UploadTransforms { .. }
+            | RenderCommand::SetInstances { .. } => Some(RenderPass::Uploads),
+            RenderCommand::Draw(ref draw) => Some(draw.pass),
+            RenderCommand::SetGlobals { .. } | RenderCommand::Clear { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+struct DrawCounter {
+    draws: Vec<DrawCommand>,
+    clears: u32,
+}
+
+#[cfg(test)]
+impl CommandReplayer for DrawCounter {
+    fn execute(&mut self, command: &RenderCommand) {
+        match *command {
+            RenderCommand::Draw(draw) => { self.draws.push(draw); }
+            RenderCommand::Clear { .. } => { self.clears += 1; }
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn record_and_replay() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let rect_id = shapes.add_rect(rect(0.0, 0.0, 10.0, 10.0));
+    for _ in 0..3 {
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: ShapeId::Rect(rect_id),
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }).unwrap();
+    }
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+
+    let target = RenderTargetId(0);
+    let mut stream = CommandStream::new();
+    stream.record_fill_geometry(geom.geometry());
+    stream.record_instances(batcher.instances());
+    stream.record_clear(target, ClearOps::clear(Color::white()));
+    stream.record_draws(RenderPass::OpaqueFill, target, &cmds);
+    assert_eq!(stream.len(), 4);
+
+    let mut counter = DrawCounter { draws: Vec::new(), clears: 0 };
+    stream.replay(&mut counter);
+    assert_eq!(counter.clears, 1);
+    assert_eq!(counter.draws.len(), 1);
+    assert_eq!(counter.draws[0].pass, RenderPass::OpaqueFill);
+    assert_eq!(counter.draws[0].instances, 3);
+    assert_eq!(counter.draws[0].index_count, 6);
+
+    let mut counter = DrawCounter { draws: Vec::new(), clears: 0 };
+    let mut timer = CpuPassTimer::new();
+    stream.replay_timed(&mut counter, &mut timer);
+    assert_eq!(counter.draws.len(), 1);
+    let mut stats = RenderStats::new();
+    timer.collect(&mut stats);
+    let passes: Vec<RenderPass> = stats.pass_times_ns.iter().map(|&(pass, _)| pass).collect();
+    assert_eq!(passes, vec![RenderPass::Uploads, RenderPass::OpaqueFill]);
+}
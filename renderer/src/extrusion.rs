@@ -0,0 +1,162 @@
+//! Strokes extruded into 3D ribbons.
+//!
+//! Maps and graphs often draw polylines at an elevation that varies along the line, for
+//! example a route over terrain or a flight path. The stroke of such a polyline is
+//! tessellated like other strokes, and the elevation is read from an attribute of an
+//! `AttributedPath` at the advancement of each vertex and stored as the z component of its
+//! position. Drawn with 3D transforms, the stroke becomes a ribbon following the elevation,
+//! without needing a separate mesh.
+
+use gfx;
+use gfx::traits::FactoryExt;
+
+pub use gfx_types::*;
+use path::{AttributedPath, AttributeSampler};
+use tessellation::{StrokeTessellator, StrokeOptions, StrokeVertex, Count};
+use tessellation::geometry_builder::{VertexBuffers, BuffersBuilder, VertexConstructor};
+use api::RenderOptions;
+use renderer::{Globals, GpuTransform, GpuStrokePrimitive, GpuStyle, GpuGradientStopWords, StrokePrimitiveId, PackedPrimId};
+use glsl::{EXTRUDED_STROKE_VERTEX_SHADER, STROKE_FRAGMENT_SHADER, shader_with_options};
+#[cfg(test)]
+use core::math::*;
+
+gfx_defines!{
+    vertex GpuExtrudedStrokeVertex {
+        // The position on the path and its elevation.
+        position: [f32; 3] = "a_position",
+        normal: [f32; 2] = "a_normal",
+        advancement: f32 = "a_advancement",
+        path_length: f32 = "a_path_length",
+        prim_id: i32 = "a_prim_id",
+    }
+
+    pipeline extruded_stroke_pipeline {
+        vbo: gfx::VertexBuffer<GpuExtrudedStrokeVertex> = (),
+        out_color: gfx::RenderTarget<ColorFormat> = "out_color",
+        out_depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
+        constants: gfx::ConstantBuffer<Globals> = "Globals",
+        transforms: gfx::ConstantBuffer<GpuTransform> = "u_transforms",
+        primitives: gfx::ConstantBuffer<GpuStrokePrimitive> = "u_primitives",
+        styles: gfx::ConstantBuffer<GpuStyle> = "u_styles",
+        gradient_stops: gfx::ConstantBuffer<GpuGradientStopWords> = "u_gradient_stops",
+    }
+}
+
+pub type ExtrudedStrokePso = Pso<extruded_stroke_pipeline::Meta>;
+
+pub fn create_extruded_stroke_pso(factory: &mut GlFactory, options: &RenderOptions) -> ExtrudedStrokePso {
+    let program = factory.link_program(
+        shader_with_options(EXTRUDED_STROKE_VERTEX_SHADER, options).as_bytes(),
+        shader_with_options(STROKE_FRAGMENT_SHADER, options).as_bytes(),
+    ).unwrap();
+
+    return factory.create_pipeline_from_program(
+        &program,
+        gfx::Primitive::TriangleList,
+        gfx::state::Rasterizer::new_fill(),
+        extruded_stroke_pipeline::new(),
+    ).unwrap();
+}
+
+/// Builds extruded stroke vertices, reading the elevation of each vertex from an attribute
+/// of the stroked path.
+pub struct ExtrudedStrokeVertexConstructor<'l> {
+    sampler: &'l AttributeSampler,
+    elevation: usize,
+    prim_id: StrokePrimitiveId,
+    attributes: Vec<f32>,
+}
+
+impl<'l> ExtrudedStrokeVertexConstructor<'l> {
+    /// `elevation` is the index of the attribute containing the elevation, out of the
+    /// `num_attributes` attributes of the path.
+    pub fn new(
+        sampler: &'l AttributeSampler,
+        num_attributes: usize,
+        elevation: usize,
+        prim_id: StrokePrimitiveId,
+    ) -> Self {
+        assert!(elevation < num_attributes);
+        ExtrudedStrokeVertexConstructor {
+            sampler: sampler,
+            elevation: elevation,
+            prim_id: prim_id,
+            attributes: vec![0.0; num_attributes],
+        }
+    }
+}
+
+impl<'l> VertexConstructor<StrokeVertex, GpuExtrudedStrokeVertex> for ExtrudedStrokeVertexConstructor<'l> {
+    fn new_vertex(&mut self, vertex: StrokeVertex) -> GpuExtrudedStrokeVertex {
+        debug_assert!(!vertex.position.x.is_nan());
+        debug_assert!(!vertex.position.y.is_nan());
+        let z = if self.sampler.at_distance(vertex.advancement, &mut self.attributes) {
+            self.attributes[self.elevation]
+        } else {
+            0.0
+        };
+
+        GpuExtrudedStrokeVertex {
+            position: [vertex.position.x, vertex.position.y, z],
+            normal: vertex.normal.to_array(),
+            advancement: vertex.advancement,
+            path_length: self.sampler.length(),
+            prim_id: PackedPrimId::from_id(self.prim_id).to_i32(),
+        }
+    }
+}
+
+/// Tessellates the stroke of a path into a ribbon at the elevation given by one of its
+/// attributes.
+///
+/// The width is applied by the shader like for other strokes, so `options` should not
+/// apply it. The elevation is interpolated linearly along the flattened path.
+pub fn tessellate_extruded_stroke(
+    path: &AttributedPath,
+    elevation: usize,
+    options: &StrokeOptions,
+    prim_id: StrokePrimitiveId,
+    output: &mut VertexBuffers<GpuExtrudedStrokeVertex>,
+) -> Count {
+    let sampler = AttributeSampler::new(path, options.tolerance);
+    let ctor = ExtrudedStrokeVertexConstructor::new(&sampler, path.num_attributes(), elevation, prim_id);
+
+    return StrokeTessellator::new().tessellate_path(
+        path.path().path_iter(),
+        options,
+        &mut BuffersBuilder::new(output, ctor),
+    );
+}
+
+#[test]
+fn extruded_route() {
+    let mut builder = AttributedPath::builder(2);
+    builder.move_to(point(0.0, 0.0), &[1.0, 0.0]);
+    builder.line_to(point(10.0, 0.0), &[1.0, 5.0]);
+    builder.line_to(point(10.0, 10.0), &[1.0, 15.0]);
+    let path = builder.build();
+
+    let mut geom = VertexBuffers::new();
+    let count = tessellate_extruded_stroke(
+        &path,
+        1,
+        &StrokeOptions::default().dont_apply_line_width(),
+        StrokePrimitiveId::new(2),
+        &mut geom,
+    );
+    assert!(count.indices > 0);
+    assert_eq!(geom.vertices.len(), count.vertices as usize);
+
+    for vertex in &geom.vertices {
+        assert_eq!(vertex.prim_id, 2);
+        assert_eq!(vertex.path_length, 20.0);
+        // The elevation follows the distance along the path.
+        let expected = if vertex.advancement <= 10.0 {
+            vertex.advancement * 0.5
+        } else {
+            5.0 + (vertex.advancement - 10.0)
+        };
+        assert!((vertex.position[2] - expected).abs() < 1e-4);
+    }
+    assert!(geom.vertices.iter().any(|vertex| (vertex.position[2] - 15.0).abs() < 1e-4));
+}
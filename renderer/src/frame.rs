@@ -1,6 +1,7 @@
-use renderer::{ GpuFillVertex, GpuStrokeVertex, GpuStrokePrimitive, GpuFillPrimitive, GpuTransform };
+use renderer::{ GpuFillVertex, GpuStrokeVertex, GpuStrokePrimitive, GpuFillPrimitive, GpuTransform, Globals };
 use api::Color;
 use buffer::*;
+use core::math::IntSize;
 
 pub type Index = u16;
 pub type IndexBufferId = BufferId<Index>;
@@ -18,3 +19,382 @@ pub type StrokePrimBufferRange = BufferRange<GpuStrokePrimitive>;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct RenderTargetId(pub u32);
+
+/// The render targets that passes can draw into, for example the swapchains of several
+/// windows and offscreen surfaces.
+///
+/// The geometry and primitive buffers don't depend on the target, so a single set of them
+/// can be shared by all the windows of an application: only the per-target globals (see
+/// `globals`) differ between the passes drawing the same content into different targets.
+/// Ids of removed targets are not reused.
+pub struct RenderTargetStore<Target> {
+    targets: Vec<Option<(Target, IntSize)>>,
+}
+
+impl<Target> RenderTargetStore<Target> {
+    pub fn new() -> Self {
+        RenderTargetStore { targets: Vec::new() }
+    }
+
+    pub fn add_target(&mut self, target: Target, size: IntSize) -> RenderTargetId {
+        self.targets.push(Some((target, size)));
+        return RenderTargetId(self.targets.len() as u32 - 1);
+    }
+
+    /// Replaces a target, for example with the new swapchain of a resized window.
+    pub fn set_target(&mut self, id: RenderTargetId, target: Target, size: IntSize) {
+        self.targets[id.0 as usize] = Some((target, size));
+    }
+
+    pub fn remove_target(&mut self, id: RenderTargetId) -> Option<Target> {
+        self.targets.get_mut(id.0 as usize)
+            .and_then(|target| target.take())
+            .map(|(target, _)| target)
+    }
+
+    pub fn get(&self, id: RenderTargetId) -> Option<&Target> {
+        self.targets.get(id.0 as usize)
+            .and_then(|target| target.as_ref())
+            .map(|&(ref target, _)| target)
+    }
+
+    pub fn size(&self, id: RenderTargetId) -> Option<IntSize> {
+        self.targets.get(id.0 as usize)
+            .and_then(|target| target.as_ref())
+            .map(|&(_, size)| size)
+    }
+
+    /// The globals to render into a target, which give its resolution to the shaders.
+    pub fn globals(&self, id: RenderTargetId) -> Option<Globals> {
+        self.size(id).map(|size| Globals {
+            resolution: [size.width as f32, size.height as f32],
+            scroll_offset: [0.0, 0.0],
+            zoom: 1.0,
+        })
+    }
+
+    /// The ids of the targets in the store.
+    pub fn ids(&self) -> Vec<RenderTargetId> {
+        (0..self.targets.len())
+            .filter(|&i| self.targets[i].is_some())
+            .map(|i| RenderTargetId(i as u32))
+            .collect()
+    }
+}
+
+/// Index of a pass in a `FrameGraph`.
+pub type PassId = usize;
+
+/// Errors reported when scheduling the passes of a frame graph.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrameGraphError {
+    /// The passes depend on each other through the targets they read and write.
+    Cycle { passes: Vec<PassId> },
+}
+
+struct PassNode<Pass> {
+    pass: Pass,
+    reads: Vec<RenderTargetId>,
+    writes: Vec<RenderTargetId>,
+}
+
+/// Schedules the passes of a frame from the render targets they read and write.
+///
+/// Passes writing the same target run in the order they were added, so that for example
+/// strokes are drawn on top of fills. A pass that only reads a target runs after all the
+/// passes that write it, while a pass that reads and writes a target (to blend with it) is
+/// ordered like the other writers. Independent passes keep the order they were added in. The schedule is therefore
+/// deterministic and only differs from the order of `add_pass` where a dependency requires it.
+pub struct FrameGraph<Pass> {
+    passes: Vec<PassNode<Pass>>,
+}
+
+impl<Pass> FrameGraph<Pass> {
+    pub fn new() -> Self {
+        FrameGraph { passes: Vec::new() }
+    }
+
+    /// Adds a pass reading and writing the provided targets.
+    ///
+    /// A pass may read the target it writes into (for example to blend with it).
+    pub fn add_pass(&mut self, pass: Pass, reads: &[RenderTargetId], writes: &[RenderTargetId]) -> PassId {
+        self.passes.push(PassNode {
+            pass: pass,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+        });
+        return self.passes.len() - 1;
+    }
+
+    pub fn len(&self) -> usize { self.passes.len() }
+
+    pub fn is_empty(&self) -> bool { self.passes.is_empty() }
+
+    pub fn pass(&self, id: PassId) -> &Pass { &self.passes[id].pass }
+
+    pub fn clear(&mut self) { self.passes.clear(); }
+
+    /// Returns the order in which the passes must run.
+    pub fn schedule(&self) -> Result<Vec<PassId>, FrameGraphError> {
+        let n = self.passes.len();
+        let mut dependencies: Vec<Vec<PassId>> = vec![Vec::new(); n];
+        for (i, pass) in self.passes.iter().enumerate() {
+            for (j, other) in self.passes.iter().enumerate() {
+                if i == j {
+                    continue;
+                }
+                // A pass that reads and writes a target is ordered like the other writers.
+                let reads_output = pass.reads.iter().any(|target| {
+                    other.writes.contains(target) && (j < i || !pass.writes.contains(target))
+                });
+                let writes_after = j < i && pass.writes.iter().any(|target| other.writes.contains(target));
+                if reads_output || writes_after {
+                    dependencies[i].push(j);
+                }
+            }
+        }
+
+        // Kahn's algorithm, always picking the first ready pass in insertion order.
+        let mut scheduled = vec![false; n];
+        let mut order = Vec::with_capacity(n);
+        while order.len() < n {
+            let next = (0..n).find(|&i| {
+                !scheduled[i] && dependencies[i].iter().all(|&dep| scheduled[dep])
+            });
+            match next {
+                Some(i) => {
+                    scheduled[i] = true;
+                    order.push(i);
+                }
+                None => {
+                    return Err(FrameGraphError::Cycle {
+                        passes: (0..n).filter(|&i| !scheduled[i]).collect(),
+                    });
+                }
+            }
+        }
+
+        return Ok(order);
+    }
+
+    /// Runs the passes in the order of `schedule`.
+    pub fn execute<F>(&mut self, mut run: F) -> Result<(), FrameGraphError>
+    where F: FnMut(PassId, &mut Pass) {
+        for id in self.schedule()? {
+            run(id, &mut self.passes[id].pass);
+        }
+
+        return Ok(());
+    }
+}
+
+/// The steps that must happen once per frame, in this order, see `FramePacer::end_frame`.
+///
+/// Typically implemented by the application's renderer around its `GpuBufferStore`s,
+/// `PerFrameBufferStore`s, render passes and swap chain.
+pub trait FrameSteps {
+    /// Writes the data built during the frame into the GPU buffers, for example with
+    /// `GpuBufferStore::flush_uploads`. Rendering before this reads stale data.
+    fn flush_uploads(&mut self);
+
+    /// Runs the passes of the frame, for example with `FrameGraph::execute`.
+    fn render(&mut self);
+
+    /// Presents the frame, swapping the buffers of the window.
+    fn present(&mut self);
+
+    /// Moves the upload buffers and the per-frame arenas to the next frame, for example
+    /// with `GpuBufferStore::end_frame` and `PerFrameBufferStore::begin_frame`. Doing so
+    /// before presenting would overwrite data the GPU may still read, causing flickering.
+    fn rotate_frame(&mut self);
+}
+
+/// Errors reported when the frames are not started and ended in order.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FrameError {
+    /// `begin_frame` was called twice without ending the frame.
+    AlreadyStarted { frame: u64 },
+    /// `end_frame` was called without starting a frame.
+    NotStarted,
+    /// The GPU hasn't completed enough frames: starting another one would recycle
+    /// resources of a frame it may still be reading.
+    TooManyFramesInFlight { completed: Option<u64>, frames_in_flight: usize },
+}
+
+/// Makes the order of the steps of a frame explicit.
+///
+/// The scene, the batches and the geometry are updated between `begin_frame` and
+/// `end_frame`, which then runs the `FrameSteps` in the only valid order. The pacer also
+/// tracks the frames completed by the GPU (as reported by fences or by a swap chain that
+/// blocks on present) so that no more than `frames_in_flight` frames are pending, which is
+/// the number of resources in the `FrameRing`s of the renderer.
+pub struct FramePacer {
+    frames_in_flight: usize,
+    // The next frame to start.
+    next_frame: u64,
+    current: Option<u64>,
+    completed: Option<u64>,
+}
+
+impl FramePacer {
+    pub fn new(frames_in_flight: usize) -> Self {
+        assert!(frames_in_flight > 0);
+        FramePacer {
+            frames_in_flight: frames_in_flight,
+            next_frame: 0,
+            current: None,
+            completed: None,
+        }
+    }
+
+    pub fn frames_in_flight(&self) -> usize { self.frames_in_flight }
+
+    /// The frame being recorded, if any.
+    pub fn current_frame(&self) -> Option<u64> { self.current }
+
+    /// The number of frames ended and not completed by the GPU yet.
+    pub fn pending_frames(&self) -> usize {
+        let completed = self.completed.map_or(0, |frame| frame + 1);
+        let ended = match self.current {
+            Some(frame) => frame,
+            None => self.next_frame,
+        };
+        return (ended - completed) as usize;
+    }
+
+    /// Whether a frame can start without waiting for the GPU.
+    pub fn can_begin_frame(&self) -> bool {
+        self.current.is_none() && self.pending_frames() < self.frames_in_flight
+    }
+
+    /// Starts recording a frame and returns its index.
+    pub fn begin_frame(&mut self) -> Result<u64, FrameError> {
+        if let Some(frame) = self.current {
+            return Err(FrameError::AlreadyStarted { frame: frame });
+        }
+        if self.pending_frames() >= self.frames_in_flight {
+            return Err(FrameError::TooManyFramesInFlight {
+                completed: self.completed,
+                frames_in_flight: self.frames_in_flight,
+            });
+        }
+
+        let frame = self.next_frame;
+        self.current = Some(frame);
+        self.next_frame += 1;
+
+        return Ok(frame);
+    }
+
+    /// Flushes the uploads, renders, presents and rotates the per-frame resources.
+    pub fn end_frame<Steps: FrameSteps>(&mut self, steps: &mut Steps) -> Result<u64, FrameError> {
+        let frame = match self.current.take() {
+            Some(frame) => frame,
+            None => { return Err(FrameError::NotStarted); }
+        };
+
+        steps.flush_uploads();
+        steps.render();
+        steps.present();
+        steps.rotate_frame();
+
+        return Ok(frame);
+    }
+
+    /// Records that the GPU finished a frame and all the frames before it.
+    pub fn complete_frame(&mut self, frame: u64) {
+        debug_assert!(frame < self.next_frame && self.current != Some(frame));
+        if self.completed.map_or(true, |completed| frame > completed) {
+            self.completed = Some(frame);
+        }
+    }
+}
+
+#[test]
+fn render_target_store() {
+    let mut targets = RenderTargetStore::new();
+    let main_window = targets.add_target("main", IntSize::new(800, 600));
+    let inspector = targets.add_target("inspector", IntSize::new(300, 600));
+
+    assert_eq!(targets.get(inspector), Some(&"inspector"));
+    assert_eq!(targets.globals(main_window).unwrap().resolution, [800.0, 600.0]);
+
+    targets.set_target(main_window, "main (resized)", IntSize::new(1024, 768));
+    assert_eq!(targets.globals(main_window).unwrap().resolution, [1024.0, 768.0]);
+
+    assert_eq!(targets.remove_target(inspector), Some("inspector"));
+    assert_eq!(targets.remove_target(inspector), None);
+    assert!(targets.globals(inspector).is_none());
+    assert_eq!(targets.ids(), vec![main_window]);
+    assert_eq!(targets.add_target("preview", IntSize::new(64, 64)), RenderTargetId(2));
+}
+
+#[test]
+fn frame_graph_schedule() {
+    let main = RenderTargetId(0);
+    let offscreen = RenderTargetId(1);
+    let blurred = RenderTargetId(2);
+
+    let mut graph = FrameGraph::new();
+    let fill = graph.add_pass("fill", &[], &[main]);
+    let composite = graph.add_pass("composite", &[blurred, main], &[main]);
+    let stroke = graph.add_pass("stroke", &[], &[main]);
+    let blur = graph.add_pass("blur", &[offscreen], &[blurred]);
+    let content = graph.add_pass("content", &[], &[offscreen]);
+
+    // The composite pass is moved after the passes producing its input, the passes
+    // writing into the main target keep their order.
+    assert_eq!(graph.schedule(), Ok(vec![fill, content, blur, composite, stroke]));
+
+    let mut names = Vec::new();
+    graph.execute(|_, pass| names.push(*pass)).unwrap();
+    assert_eq!(names, vec!["fill", "content", "blur", "composite", "stroke"]);
+
+    let a = RenderTargetId(3);
+    let b = RenderTargetId(4);
+    let mut cyclic = FrameGraph::new();
+    cyclic.add_pass((), &[], &[main]);
+    cyclic.add_pass((), &[a], &[b]);
+    cyclic.add_pass((), &[b], &[a]);
+    assert_eq!(cyclic.schedule(), Err(FrameGraphError::Cycle { passes: vec![1, 2] }));
+}
+
+#[test]
+fn frame_pacing() {
+    struct Recorder(Vec<&'static str>);
+    impl FrameSteps for Recorder {
+        fn flush_uploads(&mut self) { self.0.push("flush"); }
+        fn render(&mut self) { self.0.push("render"); }
+        fn present(&mut self) { self.0.push("present"); }
+        fn rotate_frame(&mut self) { self.0.push("rotate"); }
+    }
+
+    let mut steps = Recorder(Vec::new());
+    let mut pacer = FramePacer::new(2);
+    assert_eq!(pacer.end_frame(&mut steps), Err(FrameError::NotStarted));
+    assert!(steps.0.is_empty());
+
+    assert_eq!(pacer.begin_frame(), Ok(0));
+    assert_eq!(pacer.begin_frame(), Err(FrameError::AlreadyStarted { frame: 0 }));
+    assert_eq!(pacer.end_frame(&mut steps), Ok(0));
+    assert_eq!(steps.0, vec!["flush", "render", "present", "rotate"]);
+
+    assert_eq!(pacer.begin_frame(), Ok(1));
+    assert_eq!(pacer.end_frame(&mut steps), Ok(1));
+    assert_eq!(pacer.pending_frames(), 2);
+
+    // The GPU is two frames behind.
+    assert!(!pacer.can_begin_frame());
+    assert_eq!(
+        pacer.begin_frame(),
+        Err(FrameError::TooManyFramesInFlight { completed: None, frames_in_flight: 2 })
+    );
+
+    pacer.complete_frame(0);
+    assert_eq!(pacer.pending_frames(), 1);
+    assert_eq!(pacer.begin_frame(), Ok(2));
+    assert_eq!(pacer.current_frame(), Some(2));
+    assert_eq!(pacer.end_frame(&mut steps), Ok(2));
+    pacer.complete_frame(2);
+    assert_eq!(pacer.pending_frames(), 0);
+}
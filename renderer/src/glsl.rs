@@ -1,4 +1,91 @@
-pub static PRIM_BUFFER_LEN: usize = 1024;
+use api::{RenderOptions, AlphaMode, DitherPattern};
+
+/// The length of the arrays of the uniform buffers (primitives, transforms, styles, clips).
+///
+/// This is the value of the `PRIM_BUFFER_LEN` define of the shaders, which is inserted by
+/// `shader_source` and `shader_with_options`.
+pub static PRIM_BUFFER_LEN: usize = 64;
+
+/// The bits of the `a_prim_id` vertex attribute holding the index of the primitive, see
+/// `PackedPrimId`. The vertex shaders declare the same value as `PRIM_ID_MASK`.
+pub static PRIM_ID_MASK: u32 = 0x7fffffff;
+
+/// The length in vec4s of the `u_gradient_stops` buffer, which holds the stop tables of the
+/// `StyleSheet`. This is the value of the `GRADIENT_STOP_BUFFER_LEN` define of the shaders.
+pub static GRADIENT_STOP_BUFFER_LEN: usize = 1024;
+
+// The entries of the StyleSheet, read by the fragment shaders of the primitives drawn with
+// Pattern::Shared. Gradients interpolate the stops of their table like api::sample_gradient.
+// The offset is computed by fill_gradient_offset for fills and is the position along the
+// path for strokes.
+macro_rules! shared_style_glsl { () => { "
+    #define STYLE_KIND_CONIC_GRADIENT 2
+
+    struct Style { vec4 color; int stops; int stop_count; int kind; float start_angle; vec2 center; };
+    uniform u_styles { Style styles[PRIM_BUFFER_LEN]; };
+
+    struct GpuGradientStopWords { vec4 words; };
+    uniform u_gradient_stops { GpuGradientStopWords stop_words[GRADIENT_STOP_BUFFER_LEN]; };
+
+    float stop_word(int address) {
+        return stop_words[address / 4].words[address % 4];
+    }
+
+    // A stop is its straight alpha color followed by its offset, see GpuGradientStop.
+    vec4 stop_color(int address) {
+        return vec4(
+            stop_word(address),
+            stop_word(address + 1),
+            stop_word(address + 2),
+            stop_word(address + 3)
+        );
+    }
+
+    vec4 sample_gradient(int stops, int stop_count, float offset) {
+        vec4 color = stop_color(stops);
+        float prev_offset = stop_word(stops + 4);
+        for (int i = 0; i < stop_count; i++) {
+            int stop = stops + i * 5;
+            float d = stop_word(stop + 4);
+            if (offset < d) {
+                if (offset > prev_offset) {
+                    color = mix(color, stop_color(stop), (offset - prev_offset) / (d - prev_offset));
+                }
+                break;
+            }
+            color = stop_color(stop);
+            prev_offset = d;
+        }
+    #ifdef PREMULTIPLIED_ALPHA
+        color.rgb *= color.a;
+    #endif
+        return color;
+    }
+
+    // The offset of a point of the local space of a fill in the stops of a style: the turns
+    // around the center of conic gradients, like api::ConicGradient::offset_at, and the x
+    // coordinate for linear gradients.
+    float fill_gradient_offset(int id, vec2 local_pos) {
+        Style style = styles[id];
+        if (style.kind != STYLE_KIND_CONIC_GRADIENT) {
+            return local_pos.x;
+        }
+        vec2 v = local_pos - style.center;
+        if (v.x == 0.0 && v.y == 0.0) {
+            return 0.0;
+        }
+        float turns = (atan(v.y, v.x) - style.start_angle) / 6.28318530718;
+        return turns - floor(turns);
+    }
+
+    vec4 shared_style_color(int id, float offset) {
+        Style style = styles[id];
+        if (style.stop_count == 0) {
+            return style.color;
+        }
+        return sample_gradient(style.stops, style.stop_count, offset);
+    }
+" } }
 
 // The vertex shader for the tessellated geometry.
 // The transform, color and stroke width are applied instead of during tessellation. This makes
@@ -8,7 +95,9 @@ pub static FILL_VERTEX_SHADER: &'static str = &"
     #version 140
     #line 266
 
-    #define PRIM_BUFFER_LEN 64
+    #define PRIM_ID_MASK 0x7fffffff
+    #define PRIMITIVE_FLAG_HIDDEN 1
+    #define PATTERN_KIND_SHARED 5
 
     uniform Globals {
         vec2 u_resolution;
@@ -23,28 +112,60 @@ pub static FILL_VERTEX_SHADER: &'static str = &"
         int local_transform;
         int view_transform;
         float width;
+        vec2 offset;
+        int pattern_kind;
+        int pattern_id;
+        int flags;
+        int root_transform;
+        int clip_id;
     };
     uniform u_primitives { Primitive primitives[PRIM_BUFFER_LEN]; };
 
+    // Rounded rectangles clipping the primitives, see GpuClip.
+    struct Clip { vec4 rect; vec4 radii; };
+    uniform u_clips { Clip clips[PRIM_BUFFER_LEN]; };
+
     in vec2 a_position;
     in vec2 a_normal;
+    in float a_alpha;
     in int a_prim_id;
 
     out vec4 v_color;
+    out float v_alpha;
+    flat out int v_style;
+    out vec2 v_local_pos;
+    out vec2 v_clip_pos;
+    flat out int v_clip_id;
+    flat out vec4 v_clip_rect;
+    flat out vec4 v_clip_radii;
 
     void main() {
-        int id = a_prim_id + gl_InstanceID;
+        int id = (a_prim_id & PRIM_ID_MASK) + gl_InstanceID;
         Primitive prim = primitives[id];
 
         vec4 local_pos = vec4(a_position + a_normal * prim.width, 0.0, 1.0);
+        vec4 clip_pos = transforms[prim.local_transform].transform * local_pos + vec4(prim.offset, 0.0, 0.0);
         vec4 world_pos = transforms[prim.view_transform].transform
-            * transforms[prim.local_transform].transform
-            * local_pos;
+            * transforms[prim.root_transform].transform
+            * clip_pos;
 
         vec2 transformed_pos = world_pos.xy / (vec2(0.5, -0.5) * u_resolution * world_pos.w);
 
         gl_Position = vec4(transformed_pos, 1.0 - prim.z_index, 1.0);
+        if ((prim.flags & PRIMITIVE_FLAG_HIDDEN) != 0) {
+            // Collapse the triangles of hidden primitives so that they produce no fragments.
+            gl_Position = vec4(0.0, 0.0, 0.0, 1.0);
+        }
         v_color = prim.color;
+        v_alpha = a_alpha;
+        v_style = prim.pattern_kind == PATTERN_KIND_SHARED ? prim.pattern_id : -1;
+        v_local_pos = local_pos.xy;
+        v_clip_pos = clip_pos.xy;
+        v_clip_id = prim.clip_id;
+        if (prim.clip_id >= 0) {
+            v_clip_rect = clips[prim.clip_id].rect;
+            v_clip_radii = clips[prim.clip_id].radii;
+        }
     }
 ";
 
@@ -52,7 +173,9 @@ pub static STROKE_VERTEX_SHADER: &'static str = &"
     #version 140
     #line 53
 
-    #define PRIM_BUFFER_LEN 64
+    #define PRIM_ID_MASK 0x7fffffff
+    #define PRIMITIVE_FLAG_HIDDEN 1
+    #define PATTERN_KIND_SHARED 5
 
     uniform Globals {
         vec2 u_resolution;
@@ -67,56 +190,933 @@ pub static STROKE_VERTEX_SHADER: &'static str = &"
         int local_transform;
         int view_transform;
         float width;
+        vec4 end_color;
+        int flags;
+        int root_transform;
+        vec2 glow;
+        int pattern_transform;
+        int pattern_kind;
+        int pattern_id;
     };
     uniform u_primitives { Primitive primitives[PRIM_BUFFER_LEN]; };
 
     in vec2 a_position;
     in vec2 a_normal;
     in float a_advancement;
+    in float a_path_length;
+    in float a_alpha;
     in int a_prim_id;
 
     out vec4 v_color;
+    out float v_alpha;
+    flat out int v_style;
+    out float v_gradient_offset;
     out float v_advancement;
+    out vec2 v_normal;
+    flat out vec2 v_glow;
 
     void main() {
-        int id = a_prim_id + gl_InstanceID;
+        int id = (a_prim_id & PRIM_ID_MASK) + gl_InstanceID;
         Primitive prim = primitives[id];
 
         vec4 local_pos = vec4(a_position + a_normal * prim.width, 0.0, 1.0);
         vec4 world_pos = transforms[prim.view_transform].transform
+            * transforms[prim.root_transform].transform
             * transforms[prim.local_transform].transform
             * local_pos;
 
         vec2 transformed_pos = world_pos.xy / (vec2(0.5, -0.5) * u_resolution * world_pos.w);
 
         gl_Position = vec4(transformed_pos, 1.0 - prim.z_index, 1.0);
+        if ((prim.flags & PRIMITIVE_FLAG_HIDDEN) != 0) {
+            // Collapse the triangles of hidden primitives so that they produce no fragments.
+            gl_Position = vec4(0.0, 0.0, 0.0, 1.0);
+        }
+        float t = a_path_length > 0.0 ? a_advancement / a_path_length : 0.0;
+        v_color = mix(prim.color, prim.end_color, t);
+        v_alpha = a_alpha;
+        v_style = prim.pattern_kind == PATTERN_KIND_SHARED ? prim.pattern_id : -1;
+        v_gradient_offset = t;
+        v_advancement = a_advancement;
+        v_normal = a_normal;
+        v_glow = prim.glow;
+    }
+";
+
+// Variants of the vertex shaders that read the primitive from a per-instance attribute
+// instead of offsetting the per-vertex one with gl_InstanceID. The per-vertex prim_id is
+// ignored: these are the shaders that draw the commands of the batcher, which share the
+// geometry of a shape between all the primitives that draw it.
+pub static INSTANCED_FILL_VERTEX_SHADER: &'static str = &"
+    #version 140
+
+    #define PRIM_ID_MASK 0x7fffffff
+    #define PRIMITIVE_FLAG_HIDDEN 1
+    #define PATTERN_KIND_SHARED 5
+
+    uniform Globals {
+        vec2 u_resolution;
+    };
+
+    struct GpuTransform { mat4 transform; };
+    uniform u_transforms { GpuTransform transforms[PRIM_BUFFER_LEN]; };
+
+    struct Primitive {
+        vec4 color;
+        float z_index;
+        int local_transform;
+        int view_transform;
+        float width;
+        vec2 offset;
+        int pattern_kind;
+        int pattern_id;
+        int flags;
+        int root_transform;
+        int clip_id;
+    };
+    uniform u_primitives { Primitive primitives[PRIM_BUFFER_LEN]; };
+
+    // Rounded rectangles clipping the primitives, see GpuClip.
+    struct Clip { vec4 rect; vec4 radii; };
+    uniform u_clips { Clip clips[PRIM_BUFFER_LEN]; };
+
+    in vec2 a_position;
+    in vec2 a_normal;
+    in float a_alpha;
+    in int a_instance_prim_id;
+    in int a_instance_flags;
+
+    out vec4 v_color;
+    out float v_alpha;
+    flat out int v_style;
+    out vec2 v_local_pos;
+    out vec2 v_clip_pos;
+    flat out int v_clip_id;
+    flat out vec4 v_clip_rect;
+    flat out vec4 v_clip_radii;
+
+    void main() {
+        Primitive prim = primitives[a_instance_prim_id & PRIM_ID_MASK];
+
+        vec4 local_pos = vec4(a_position + a_normal * prim.width, 0.0, 1.0);
+        vec4 clip_pos = transforms[prim.local_transform].transform * local_pos + vec4(prim.offset, 0.0, 0.0);
+        vec4 world_pos = transforms[prim.view_transform].transform
+            * transforms[prim.root_transform].transform
+            * clip_pos;
+
+        vec2 transformed_pos = world_pos.xy / (vec2(0.5, -0.5) * u_resolution * world_pos.w);
+
+        gl_Position = vec4(transformed_pos, 1.0 - prim.z_index, 1.0);
+        if ((prim.flags & PRIMITIVE_FLAG_HIDDEN) != 0) {
+            // Collapse the triangles of hidden primitives so that they produce no fragments.
+            gl_Position = vec4(0.0, 0.0, 0.0, 1.0);
+        }
         v_color = prim.color;
+        v_alpha = a_alpha;
+        v_style = prim.pattern_kind == PATTERN_KIND_SHARED ? prim.pattern_id : -1;
+        v_local_pos = local_pos.xy;
+        v_clip_pos = clip_pos.xy;
+        v_clip_id = prim.clip_id;
+        if (prim.clip_id >= 0) {
+            v_clip_rect = clips[prim.clip_id].rect;
+            v_clip_radii = clips[prim.clip_id].radii;
+        }
+    }
+";
+
+pub static INSTANCED_STROKE_VERTEX_SHADER: &'static str = &"
+    #version 140
+
+    #define PRIM_ID_MASK 0x7fffffff
+    #define PRIMITIVE_FLAG_HIDDEN 1
+    #define PATTERN_KIND_SHARED 5
+
+    uniform Globals {
+        vec2 u_resolution;
+    };
+
+    struct GpuTransform { mat4 transform; };
+    uniform u_transforms { GpuTransform transforms[PRIM_BUFFER_LEN]; };
+
+    struct Primitive {
+        vec4 color;
+        float z_index;
+        int local_transform;
+        int view_transform;
+        float width;
+        vec4 end_color;
+        int flags;
+        int root_transform;
+        vec2 glow;
+        int pattern_transform;
+        int pattern_kind;
+        int pattern_id;
+    };
+    uniform u_primitives { Primitive primitives[PRIM_BUFFER_LEN]; };
+
+    in vec2 a_position;
+    in vec2 a_normal;
+    in float a_advancement;
+    in float a_path_length;
+    in float a_alpha;
+    in int a_instance_prim_id;
+    in int a_instance_flags;
+
+    out vec4 v_color;
+    out float v_alpha;
+    flat out int v_style;
+    out float v_gradient_offset;
+    out float v_advancement;
+    out vec2 v_normal;
+    flat out vec2 v_glow;
+
+    void main() {
+        Primitive prim = primitives[a_instance_prim_id & PRIM_ID_MASK];
+
+        vec4 local_pos = vec4(a_position + a_normal * prim.width, 0.0, 1.0);
+        vec4 world_pos = transforms[prim.view_transform].transform
+            * transforms[prim.root_transform].transform
+            * transforms[prim.local_transform].transform
+            * local_pos;
+
+        vec2 transformed_pos = world_pos.xy / (vec2(0.5, -0.5) * u_resolution * world_pos.w);
+
+        gl_Position = vec4(transformed_pos, 1.0 - prim.z_index, 1.0);
+        if ((prim.flags & PRIMITIVE_FLAG_HIDDEN) != 0) {
+            // Collapse the triangles of hidden primitives so that they produce no fragments.
+            gl_Position = vec4(0.0, 0.0, 0.0, 1.0);
+        }
+        float t = a_path_length > 0.0 ? a_advancement / a_path_length : 0.0;
+        v_color = mix(prim.color, prim.end_color, t);
+        v_alpha = a_alpha;
+        v_style = prim.pattern_kind == PATTERN_KIND_SHARED ? prim.pattern_id : -1;
+        v_gradient_offset = t;
         v_advancement = a_advancement;
+        v_normal = a_normal;
+        v_glow = prim.glow;
     }
 ";
 
-// The fragment shader is dead simple. It just applies the color computed in the vertex shader.
+// The fragment shader is dead simple. It just applies the color computed in the vertex shader,
+// or the one of the style sheet for Pattern::Shared.
 // A more advanced renderer would probably compute texture coordinates in the vertex shader and
 // sample the color from a texture here.
-pub static FILL_FRAGMENT_SHADER: &'static str = &"
+//
+// The LINEAR_BLENDING, DITHERING and DITHER_ORDERED defines are set by `shader_with_options`.
+pub static FILL_FRAGMENT_SHADER: &'static str = concat!("
     #version 140
     in vec4 v_color;
+    in float v_alpha;
+    flat in int v_style;
+    in vec2 v_local_pos;
+    in vec2 v_clip_pos;
+    flat in int v_clip_id;
+    flat in vec4 v_clip_rect;
+    flat in vec4 v_clip_radii;
     out vec4 out_color;
+", shared_style_glsl!(), "
+
+    // The signed distance in local units from p to the edge of a rounded rectangle,
+    // negative inside. Same as GpuClip::distance.
+    float rounded_rect_distance(vec2 p, vec4 rect, vec4 radii) {
+        p -= rect.xy;
+        vec2 side = p.x > 0.0 ? radii.yz : radii.xw;
+        float r = p.y > 0.0 ? side.y : side.x;
+        vec2 q = abs(p) - rect.zw + r;
+        return min(max(q.x, q.y), 0.0) + length(max(q, 0.0)) - r;
+    }
+
+    // The coverage of the fragment by its clip, 1.0 if it isn't clipped.
+    float clip_coverage() {
+        if (v_clip_id < 0) {
+            return 1.0;
+        }
+        float d = rounded_rect_distance(v_clip_pos, v_clip_rect, v_clip_radii);
+        return clamp(0.5 - d / max(fwidth(d), 0.0001), 0.0, 1.0);
+    }
+
+    #ifdef DITHERING
+    // Shifts the color by up to half a step of the 8 bit output, following a 4x4 ordered
+    // (Bayer) pattern or interleaved gradient noise.
+    vec3 dither() {
+    #ifdef DITHER_ORDERED
+        const float bayer[16] = float[16](
+            0.0, 8.0, 2.0, 10.0,
+            12.0, 4.0, 14.0, 6.0,
+            3.0, 11.0, 1.0, 9.0,
+            15.0, 7.0, 13.0, 5.0
+        );
+        ivec2 p = ivec2(gl_FragCoord.xy) % 4;
+        float noise = (bayer[p.y * 4 + p.x] + 0.5) / 16.0;
+    #else
+        float noise = fract(52.9829189 * fract(dot(gl_FragCoord.xy, vec2(0.06711056, 0.00583715))));
+    #endif
+        return vec3((noise - 0.5) / 255.0);
+    }
+    #endif
+
+    vec4 output_color(vec4 color) {
+    #ifdef LINEAR_BLENDING
+        // The target encodes the output to sRGB, so that blending happens in linear space.
+        color.rgb = pow(color.rgb, vec3(2.2));
+    #endif
+    #ifdef DITHERING
+        color.rgb += dither();
+    #endif
+        return color;
+    }
 
     void main() {
-        out_color = v_color;
+        float coverage = clip_coverage();
+        if (coverage <= 0.0) {
+            discard;
+        }
+
+        // The vertex alpha fades out the anti-aliasing skirts of AaSkirtBuilder.
+        coverage *= v_alpha;
+        vec4 color = v_style >= 0 ? shared_style_color(v_style, fill_gradient_offset(v_style, v_local_pos)) : v_color;
+    #ifdef PREMULTIPLIED_ALPHA
+        color *= coverage;
+    #else
+        color.a *= coverage;
+    #endif
+        out_color = output_color(color);
     }
-";
+");
 
-pub static STROKE_FRAGMENT_SHADER: &'static str = &"
+pub static STROKE_FRAGMENT_SHADER: &'static str = concat!("
     #version 140
     in vec4 v_color;
+    in float v_alpha;
+    flat in int v_style;
+    in float v_gradient_offset;
     in float v_advancement;
+    in vec2 v_normal;
+    flat in vec2 v_glow;
     out vec4 out_color;
+", shared_style_glsl!(), "
+
+    #ifdef DITHERING
+    // Shifts the color by up to half a step of the 8 bit output, following a 4x4 ordered
+    // (Bayer) pattern or interleaved gradient noise.
+    vec3 dither() {
+    #ifdef DITHER_ORDERED
+        const float bayer[16] = float[16](
+            0.0, 8.0, 2.0, 10.0,
+            12.0, 4.0, 14.0, 6.0,
+            3.0, 11.0, 1.0, 9.0,
+            15.0, 7.0, 13.0, 5.0
+        );
+        ivec2 p = ivec2(gl_FragCoord.xy) % 4;
+        float noise = (bayer[p.y * 4 + p.x] + 0.5) / 16.0;
+    #else
+        float noise = fract(52.9829189 * fract(dot(gl_FragCoord.xy, vec2(0.06711056, 0.00583715))));
+    #endif
+        return vec3((noise - 0.5) / 255.0);
+    }
+    #endif
+
+    vec4 output_color(vec4 color) {
+    #ifdef LINEAR_BLENDING
+        // The target encodes the output to sRGB, so that blending happens in linear space.
+        color.rgb = pow(color.rgb, vec3(2.2));
+    #endif
+    #ifdef DITHERING
+        color.rgb += dither();
+    #endif
+        return color;
+    }
 
     void main() {
         //float a = mod(v_advancement * 1.0, 1.0);
         //out_color = vec4(a, a, a, 1.0);
-        out_color = v_color;
+        vec4 color = v_style >= 0 ? shared_style_color(v_style, v_gradient_offset) : v_color;
+        // The vertex alpha fades out the anti-aliasing skirts of AaSkirtBuilder.
+    #ifdef PREMULTIPLIED_ALPHA
+        color *= v_alpha;
+    #else
+        color.a *= v_alpha;
+    #endif
+        if (v_glow.y > 0.0) {
+            // The normals of the two sides of the stroke are opposite, so the length of the
+            // interpolated normal is the distance to the center of the stroke relative to
+            // its width. The glow fades out from the edge of the stroke to its own edge.
+            float falloff = v_glow.y * (1.0 - smoothstep(v_glow.x, 1.0, length(v_normal)));
+    #ifdef PREMULTIPLIED_ALPHA
+            color *= falloff;
+    #else
+            color.a *= falloff;
+    #endif
+        }
+        out_color = output_color(color);
+    }
+");
+
+// The analytic anti-aliasing pass, see the analytic_aa module. The geometry is inflated on
+// the CPU and each fragment computes its distance in pixels to the boundary edges of its
+// triangle from the barycentric coordinates, which gives the exact coverage of straight
+// edges regardless of how thin the shape is.
+pub static AA_FILL_VERTEX_SHADER: &'static str = &"
+    #version 140
+
+    #define PRIM_ID_MASK 0x7fffffff
+    #define PRIMITIVE_FLAG_HIDDEN 1
+    #define PATTERN_KIND_SHARED 5
+
+    uniform Globals {
+        vec2 u_resolution;
+    };
+
+    struct GpuTransform { mat4 transform; };
+    uniform u_transforms { GpuTransform transforms[PRIM_BUFFER_LEN]; };
+
+    struct Primitive {
+        vec4 color;
+        float z_index;
+        int local_transform;
+        int view_transform;
+        float width;
+        vec2 offset;
+        int pattern_kind;
+        int pattern_id;
+        int flags;
+        int root_transform;
+        int clip_id;
+    };
+    uniform u_primitives { Primitive primitives[PRIM_BUFFER_LEN]; };
+
+    // Rounded rectangles clipping the primitives, see GpuClip.
+    struct Clip { vec4 rect; vec4 radii; };
+    uniform u_clips { Clip clips[PRIM_BUFFER_LEN]; };
+
+    in vec2 a_position;
+    in vec3 a_barycentric;
+    in int a_edge_mask;
+    in int a_prim_id;
+
+    out vec4 v_color;
+    flat out int v_style;
+    out vec2 v_local_pos;
+    out vec2 v_clip_pos;
+    flat out int v_clip_id;
+    flat out vec4 v_clip_rect;
+    flat out vec4 v_clip_radii;
+    out vec3 v_barycentric;
+    flat out int v_edge_mask;
+
+    void main() {
+        int id = (a_prim_id & PRIM_ID_MASK) + gl_InstanceID;
+        Primitive prim = primitives[id];
+
+        vec4 local_pos = vec4(a_position, 0.0, 1.0);
+        vec4 clip_pos = transforms[prim.local_transform].transform * local_pos + vec4(prim.offset, 0.0, 0.0);
+        vec4 world_pos = transforms[prim.view_transform].transform
+            * transforms[prim.root_transform].transform
+            * clip_pos;
+
+        vec2 transformed_pos = world_pos.xy / (vec2(0.5, -0.5) * u_resolution * world_pos.w);
+
+        gl_Position = vec4(transformed_pos, 1.0 - prim.z_index, 1.0);
+        if ((prim.flags & PRIMITIVE_FLAG_HIDDEN) != 0) {
+            // Collapse the triangles of hidden primitives so that they produce no fragments.
+            gl_Position = vec4(0.0, 0.0, 0.0, 1.0);
+        }
+        v_color = prim.color;
+        v_style = prim.pattern_kind == PATTERN_KIND_SHARED ? prim.pattern_id : -1;
+        v_local_pos = local_pos.xy;
+        v_barycentric = a_barycentric;
+        v_edge_mask = a_edge_mask;
+        v_clip_pos = clip_pos.xy;
+        v_clip_id = prim.clip_id;
+        if (prim.clip_id >= 0) {
+            v_clip_rect = clips[prim.clip_id].rect;
+            v_clip_radii = clips[prim.clip_id].radii;
+        }
+    }
+";
+
+// The LINEAR_BLENDING and PREMULTIPLIED_ALPHA defines are set by `shader_with_options`.
+pub static AA_FILL_FRAGMENT_SHADER: &'static str = concat!("
+    #version 140
+    in vec4 v_color;
+    flat in int v_style;
+    in vec2 v_local_pos;
+    in vec3 v_barycentric;
+    flat in int v_edge_mask;
+    in vec2 v_clip_pos;
+    flat in int v_clip_id;
+    flat in vec4 v_clip_rect;
+    flat in vec4 v_clip_radii;
+    out vec4 out_color;
+", shared_style_glsl!(), "
+
+    // The signed distance in local units from p to the edge of a rounded rectangle,
+    // negative inside. Same as GpuClip::distance.
+    float rounded_rect_distance(vec2 p, vec4 rect, vec4 radii) {
+        p -= rect.xy;
+        vec2 side = p.x > 0.0 ? radii.yz : radii.xw;
+        float r = p.y > 0.0 ? side.y : side.x;
+        vec2 q = abs(p) - rect.zw + r;
+        return min(max(q.x, q.y), 0.0) + length(max(q, 0.0)) - r;
+    }
+
+    // The coverage of the fragment by its clip, 1.0 if it isn't clipped.
+    float clip_coverage() {
+        if (v_clip_id < 0) {
+            return 1.0;
+        }
+        float d = rounded_rect_distance(v_clip_pos, v_clip_rect, v_clip_radii);
+        return clamp(0.5 - d / max(fwidth(d), 0.0001), 0.0, 1.0);
+    }
+
+    void main() {
+        // The barycentric coordinate of a vertex is 0 on the opposite edge and changes by
+        // fwidth per pixel, so their ratio is the signed distance to the edge in pixels.
+        float coverage = clip_coverage();
+        for (int i = 0; i < 3; i++) {
+            if ((v_edge_mask & (1 << i)) != 0) {
+                float d = v_barycentric[i] / fwidth(v_barycentric[i]);
+                coverage *= clamp(d + 0.5, 0.0, 1.0);
+            }
+        }
+
+        vec4 color = v_style >= 0 ? shared_style_color(v_style, fill_gradient_offset(v_style, v_local_pos)) : v_color;
+    #ifdef LINEAR_BLENDING
+        color.rgb = pow(color.rgb, vec3(2.2));
+    #endif
+    #ifdef PREMULTIPLIED_ALPHA
+        color *= coverage;
+    #else
+        color.a *= coverage;
+    #endif
+        out_color = color;
+    }
+");
+
+// The depth pre-pass only needs the positions. The depth of a primitive is constant, so it
+// is exactly the same as the one computed by the fill pass, which tests it for equality.
+// Like the instanced fill shader, it draws the commands of the batcher, which geometry is
+// shared by several primitives: the primitive is read from the per-instance attribute.
+pub static DEPTH_PRE_PASS_VERTEX_SHADER: &'static str = &"
+    #version 140
+
+    #define PRIM_ID_MASK 0x7fffffff
+    #define PRIMITIVE_FLAG_HIDDEN 1
+
+    uniform Globals {
+        vec2 u_resolution;
+    };
+
+    struct GpuTransform { mat4 transform; };
+    uniform u_transforms { GpuTransform transforms[PRIM_BUFFER_LEN]; };
+
+    struct Primitive {
+        vec4 color;
+        float z_index;
+        int local_transform;
+        int view_transform;
+        float width;
+        vec2 offset;
+        int pattern_kind;
+        int pattern_id;
+        int flags;
+        int root_transform;
+    };
+    uniform u_primitives { Primitive primitives[PRIM_BUFFER_LEN]; };
+
+    in vec2 a_position;
+    in int a_instance_prim_id;
+
+    void main() {
+        Primitive prim = primitives[a_instance_prim_id & PRIM_ID_MASK];
+
+        vec4 local_pos = vec4(a_position, 0.0, 1.0);
+        vec4 world_pos = transforms[prim.view_transform].transform
+            * transforms[prim.root_transform].transform
+            * (transforms[prim.local_transform].transform * local_pos + vec4(prim.offset, 0.0, 0.0));
+
+        vec2 transformed_pos = world_pos.xy / (vec2(0.5, -0.5) * u_resolution * world_pos.w);
+
+        gl_Position = vec4(transformed_pos, 1.0 - prim.z_index, 1.0);
+        if ((prim.flags & PRIMITIVE_FLAG_HIDDEN) != 0) {
+            // Collapse the triangles of hidden primitives so that they produce no fragments.
+            gl_Position = vec4(0.0, 0.0, 0.0, 1.0);
+        }
+    }
+";
+
+pub static DEPTH_PRE_PASS_FRAGMENT_SHADER: &'static str = &"
+    #version 140
+
+    void main() {}
+";
+
+// The picking pass writes the page and index of the primitive covering each pixel, encoded
+// like `encode_picking_id` (0 means no primitive). It draws the commands of the batcher, so
+// the primitive is read from the per-instance attribute like in the depth pre-pass.
+pub static PICKING_VERTEX_SHADER: &'static str = &"
+    #version 140
+
+    #define PRIM_ID_MASK 0x7fffffff
+    #define PRIMITIVE_FLAG_HIDDEN 1
+
+    uniform Globals {
+        vec2 u_resolution;
+    };
+
+    struct GpuTransform { mat4 transform; };
+    uniform u_transforms { GpuTransform transforms[PRIM_BUFFER_LEN]; };
+
+    struct Primitive {
+        vec4 color;
+        float z_index;
+        int local_transform;
+        int view_transform;
+        float width;
+        vec2 offset;
+        int pattern_kind;
+        int pattern_id;
+        int flags;
+        int root_transform;
+    };
+    uniform u_primitives { Primitive primitives[PRIM_BUFFER_LEN]; };
+
+    in vec2 a_position;
+    in int a_instance_prim_id;
+
+    flat out int v_prim_id;
+
+    void main() {
+        int id = a_instance_prim_id & PRIM_ID_MASK;
+        Primitive prim = primitives[id];
+
+        vec4 local_pos = vec4(a_position, 0.0, 1.0);
+        vec4 world_pos = transforms[prim.view_transform].transform
+            * transforms[prim.root_transform].transform
+            * (transforms[prim.local_transform].transform * local_pos + vec4(prim.offset, 0.0, 0.0));
+
+        vec2 transformed_pos = world_pos.xy / (vec2(0.5, -0.5) * u_resolution * world_pos.w);
+
+        gl_Position = vec4(transformed_pos, 1.0 - prim.z_index, 1.0);
+        if ((prim.flags & PRIMITIVE_FLAG_HIDDEN) != 0) {
+            // Collapse the triangles of hidden primitives so that they produce no fragments.
+            gl_Position = vec4(0.0, 0.0, 0.0, 1.0);
+        }
+        v_prim_id = id;
+    }
+";
+
+pub static PICKING_FRAGMENT_SHADER: &'static str = &"
+    #version 140
+
+    uniform Picking {
+        int u_page;
+    };
+
+    flat in int v_prim_id;
+    out uint out_id;
+
+    void main() {
+        out_id = ((uint(u_page) << 16u) | uint(v_prim_id)) + 1u;
+    }
+";
+
+// Full-screen passes applying a post-processing effect to a texture.
+// The vertex shader is used with a single triangle covering the whole viewport.
+pub static POST_PROCESS_VERTEX_SHADER: &'static str = &"
+    #version 140
+
+    in vec2 a_position;
+    out vec2 v_uv;
+
+    void main() {
+        v_uv = a_position * 0.5 + vec2(0.5);
+        gl_Position = vec4(a_position, 0.0, 1.0);
+    }
+";
+
+// kind: 0 = passthrough, 1 = vignette, 2 = color grading, 3 = pixelate, 4 = blur.
+pub static POST_PROCESS_FRAGMENT_SHADER: &'static str = &"
+    #version 140
+
+    uniform Effect {
+        mat4 color_matrix;
+        vec4 params;
+        vec2 resolution;
+        int kind;
+        float opacity;
+    };
+
+    uniform sampler2D t_source;
+    // A white texture when there is no mask.
+    uniform sampler2D t_mask;
+
+    in vec2 v_uv;
+    out vec4 out_color;
+
+    #ifdef DITHERING
+    // Shifts the color by up to half a step of the 8 bit output, following a 4x4 ordered
+    // (Bayer) pattern or interleaved gradient noise.
+    vec3 dither() {
+    #ifdef DITHER_ORDERED
+        const float bayer[16] = float[16](
+            0.0, 8.0, 2.0, 10.0,
+            12.0, 4.0, 14.0, 6.0,
+            3.0, 11.0, 1.0, 9.0,
+            15.0, 7.0, 13.0, 5.0
+        );
+        ivec2 p = ivec2(gl_FragCoord.xy) % 4;
+        float noise = (bayer[p.y * 4 + p.x] + 0.5) / 16.0;
+    #else
+        float noise = fract(52.9829189 * fract(dot(gl_FragCoord.xy, vec2(0.06711056, 0.00583715))));
+    #endif
+        return vec3((noise - 0.5) / 255.0);
+    }
+    #endif
+
+    void main() {
+        vec2 uv = v_uv;
+        if (kind == 3) {
+            vec2 block = vec2(params.x) / resolution;
+            uv = (floor(uv / block) + vec2(0.5)) * block;
+        }
+
+        vec4 color = texture(t_source, uv);
+
+        if (kind == 4) {
+            // 5x5 box filter spanning the blur radius.
+            vec2 texel_step = vec2(params.x * 0.5) / resolution;
+            color = vec4(0.0);
+            for (int x = -2; x <= 2; x++) {
+                for (int y = -2; y <= 2; y++) {
+                    color += texture(t_source, uv + vec2(x, y) * texel_step);
+                }
+            }
+            color /= 25.0;
+        }
+
+        if (kind == 1) {
+            float d = distance(v_uv, vec2(0.5));
+            color.rgb *= 1.0 - smoothstep(params.x, params.x + params.y, d);
+        } else if (kind == 2) {
+            color = color_matrix * color;
+        }
+
+        float alpha = opacity * texture(t_mask, v_uv).a;
+    #ifdef PREMULTIPLIED_ALPHA
+        color *= alpha;
+    #else
+        color.a *= alpha;
+    #endif
+
+    #ifdef DITHERING
+        color.rgb += dither();
+    #endif
+
+        out_color = color;
+    }
+";
+
+/// Inserts the defines shared with the Rust side, like `PRIM_BUFFER_LEN`, after the
+/// `#version` directive of a shader.
+pub fn shader_source(source: &str) -> String {
+    return insert_defines(source, &shared_defines());
+}
+
+/// Same as `shader_source`, also inserting the defines corresponding to the render options.
+pub fn shader_with_options(source: &str, options: &RenderOptions) -> String {
+    let mut defines = shared_defines();
+    if options.linear_blending {
+        defines.push_str("#define LINEAR_BLENDING\n");
+    }
+    if options.dithering {
+        defines.push_str("#define DITHERING\n");
+        if options.dither_pattern == DitherPattern::Ordered {
+            defines.push_str("#define DITHER_ORDERED\n");
+        }
+    }
+    if options.alpha_mode == AlphaMode::Premultiplied {
+        defines.push_str("#define PREMULTIPLIED_ALPHA\n");
+    }
+
+    return insert_defines(source, &defines);
+}
+
+fn shared_defines() -> String {
+    format!(
+        "#define PRIM_BUFFER_LEN {}\n#define GRADIENT_STOP_BUFFER_LEN {}\n",
+        PRIM_BUFFER_LEN,
+        GRADIENT_STOP_BUFFER_LEN,
+    )
+}
+
+fn insert_defines(source: &str, defines: &str) -> String {
+    let insert_at = match source.find("#version") {
+        Some(version) => {
+            source[version..].find('\n').map(|eol| version + eol + 1).unwrap_or(source.len())
+        }
+        None => 0,
+    };
+
+    let mut result = String::with_capacity(source.len() + defines.len());
+    result.push_str(&source[..insert_at]);
+    result.push_str(defines);
+    result.push_str(&source[insert_at..]);
+    return result;
+}
+
+#[test]
+fn shader_options() {
+    let options = RenderOptions::default().with_dithering(true).with_linear_blending(true);
+    let src = shader_with_options(FILL_FRAGMENT_SHADER, &options);
+    assert!(src.starts_with(
+        "\n    #version 140\n#define PRIM_BUFFER_LEN 64\n#define GRADIENT_STOP_BUFFER_LEN 1024\n\
+         #define LINEAR_BLENDING\n#define DITHERING\n    in vec4 v_color;"
+    ));
+
+    let options = options.with_linear_blending(false).with_dither_pattern(DitherPattern::Ordered);
+    let src = shader_with_options(FILL_FRAGMENT_SHADER, &options);
+    assert!(src.starts_with(
+        "\n    #version 140\n#define PRIM_BUFFER_LEN 64\n#define GRADIENT_STOP_BUFFER_LEN 1024\n\
+         #define DITHERING\n#define DITHER_ORDERED\n"
+    ));
+
+    let src = shader_with_options(FILL_FRAGMENT_SHADER, &RenderOptions::default());
+    assert_eq!(src, shader_source(FILL_FRAGMENT_SHADER));
+}
+
+#[test]
+fn prim_buffer_len_define() {
+    // The length of the arrays comes from the Rust constant only.
+    let define = format!("#define PRIM_BUFFER_LEN {}\n", PRIM_BUFFER_LEN);
+    for shader in &[
+        FILL_VERTEX_SHADER,
+        STROKE_VERTEX_SHADER,
+        INSTANCED_FILL_VERTEX_SHADER,
+        INSTANCED_STROKE_VERTEX_SHADER,
+        AA_FILL_VERTEX_SHADER,
+        DEPTH_PRE_PASS_VERTEX_SHADER,
+        PICKING_VERTEX_SHADER,
+        EXTRUDED_STROKE_VERTEX_SHADER,
+        FILL_FRAGMENT_SHADER,
+        STROKE_FRAGMENT_SHADER,
+        AA_FILL_FRAGMENT_SHADER,
+    ] {
+        assert!(shader.contains("[PRIM_BUFFER_LEN]"));
+        assert!(!shader.contains("#define PRIM_BUFFER_LEN"));
+        assert_eq!(shader_source(shader).matches(&define[..]).count(), 1);
+    }
+
+    // The stop tables of the style sheet are only read by the fragment shaders.
+    for shader in &[FILL_FRAGMENT_SHADER, STROKE_FRAGMENT_SHADER, AA_FILL_FRAGMENT_SHADER] {
+        assert!(shader.contains("[GRADIENT_STOP_BUFFER_LEN]"));
+        assert!(shader_source(shader).contains(
+            &format!("#define GRADIENT_STOP_BUFFER_LEN {}\n", GRADIENT_STOP_BUFFER_LEN)[..]
+        ));
+    }
+}
+
+#[test]
+fn prim_id_decoding() {
+    let define = format!("#define PRIM_ID_MASK {:#x}", PRIM_ID_MASK);
+    // Geometry built with WithId, drawn with gl_InstanceID.
+    let per_vertex = [
+        FILL_VERTEX_SHADER,
+        STROKE_VERTEX_SHADER,
+        AA_FILL_VERTEX_SHADER,
+        EXTRUDED_STROKE_VERTEX_SHADER,
+    ];
+    for shader in &per_vertex {
+        assert!(shader.contains(&define));
+        assert!(shader.contains("(a_prim_id & PRIM_ID_MASK) + gl_InstanceID"));
+        assert!(!shader.contains("a_prim_id + gl_InstanceID"));
+    }
+
+    // The commands of the batcher, which read the primitive of each instance.
+    let per_instance = [
+        INSTANCED_FILL_VERTEX_SHADER,
+        INSTANCED_STROKE_VERTEX_SHADER,
+        DEPTH_PRE_PASS_VERTEX_SHADER,
+        PICKING_VERTEX_SHADER,
+    ];
+    for shader in &per_instance {
+        assert!(shader.contains(&define));
+        assert!(shader.contains("a_instance_prim_id & PRIM_ID_MASK"));
+        assert!(!shader.contains("gl_InstanceID"));
+    }
+}
+
+// The stroke vertex shader for ribbons extruded in 3D, see the extrusion module.
+pub static EXTRUDED_STROKE_VERTEX_SHADER: &'static str = &"
+    #version 140
+
+    #define PRIM_ID_MASK 0x7fffffff
+    #define PRIMITIVE_FLAG_HIDDEN 1
+    #define PATTERN_KIND_SHARED 5
+
+    uniform Globals {
+        vec2 u_resolution;
+    };
+
+    struct GpuTransform { mat4 transform; };
+    uniform u_transforms { GpuTransform transforms[PRIM_BUFFER_LEN]; };
+
+    struct Primitive {
+        vec4 color;
+        float z_index;
+        int local_transform;
+        int view_transform;
+        float width;
+        vec4 end_color;
+        int flags;
+        int root_transform;
+        vec2 glow;
+        int pattern_transform;
+        int pattern_kind;
+        int pattern_id;
+    };
+    uniform u_primitives { Primitive primitives[PRIM_BUFFER_LEN]; };
+
+    in vec3 a_position;
+    in vec2 a_normal;
+    in float a_advancement;
+    in float a_path_length;
+    in int a_prim_id;
+
+    out vec4 v_color;
+    out float v_alpha;
+    flat out int v_style;
+    out float v_gradient_offset;
+    out float v_advancement;
+    out vec2 v_normal;
+    flat out vec2 v_glow;
+
+    void main() {
+        int id = (a_prim_id & PRIM_ID_MASK) + gl_InstanceID;
+        Primitive prim = primitives[id];
+
+        // The elevation is applied before the transforms, which can be 3D.
+        vec4 local_pos = vec4(a_position.xy + a_normal * prim.width, a_position.z, 1.0);
+        vec4 world_pos = transforms[prim.view_transform].transform
+            * transforms[prim.root_transform].transform
+            * transforms[prim.local_transform].transform
+            * local_pos;
+
+        vec2 transformed_pos = world_pos.xy / (vec2(0.5, -0.5) * u_resolution * world_pos.w);
+
+        gl_Position = vec4(transformed_pos, 1.0 - prim.z_index, 1.0);
+        if ((prim.flags & PRIMITIVE_FLAG_HIDDEN) != 0) {
+            // Collapse the triangles of hidden primitives so that they produce no fragments.
+            gl_Position = vec4(0.0, 0.0, 0.0, 1.0);
+        }
+        float t = a_path_length > 0.0 ? a_advancement / a_path_length : 0.0;
+        v_color = mix(prim.color, prim.end_color, t);
+        // Extruded ribbons have no anti-aliasing skirt.
+        v_alpha = 1.0;
+        v_style = prim.pattern_kind == PATTERN_KIND_SHARED ? prim.pattern_id : -1;
+        v_gradient_offset = t;
+        v_advancement = a_advancement;
+        v_normal = a_normal;
+        v_glow = prim.glow;
     }
 ";
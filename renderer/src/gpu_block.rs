@@ -0,0 +1,624 @@
+//! Description of the memory layout of the blocks of data read by the shaders.
+//!
+//! Custom primitives implement `GpuBlock`, usually with `#[derive(GpuBlock)]` from the
+//! `lyon_renderer_derive` crate, to describe their members. The layout is used to check that
+//! the Rust struct matches the std140 rules and to generate the GLSL declaration of the block,
+//! rather than maintaining both by hand.
+
+use std::mem;
+use std::slice;
+use std::fmt::Write;
+
+use core::math::{Point, Vec2, Transform3D};
+
+/// The type of a member of a `GpuBlock`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DataType {
+    /// `f32`.
+    Float,
+    /// `i32`.
+    Int,
+    /// `u32`.
+    Uint,
+    /// `[f32; 2]`, `Point` or `Vec2`.
+    Vec2,
+    /// `[f32; 4]`.
+    Vec4,
+    /// `[[f32; 4]; 4]` or `Transform3D`.
+    ///
+    /// `Transform2D` has no std140 equivalent and is not supported.
+    Mat4,
+}
+
+impl DataType {
+    /// Size in 32 bits words.
+    pub fn size_in_words(self) -> usize {
+        match self {
+            DataType::Float | DataType::Int | DataType::Uint => 1,
+            DataType::Vec2 => 2,
+            DataType::Vec4 => 4,
+            DataType::Mat4 => 16,
+        }
+    }
+
+    /// Base alignment in 32 bits words, following the std140 rules.
+    pub fn alignment_in_words(self) -> usize {
+        self.alignment(LayoutMode::Std140)
+    }
+
+    /// Alignment in 32 bits words in a given layout mode.
+    ///
+    /// std140 and std430 only differ for arrays and nested structs, which blocks don't
+    /// contain, so their member alignments are the same.
+    pub fn alignment(self, mode: LayoutMode) -> usize {
+        if mode == LayoutMode::Packed {
+            return 1;
+        }
+
+        match self {
+            DataType::Float | DataType::Int | DataType::Uint => 1,
+            DataType::Vec2 => 2,
+            DataType::Vec4 | DataType::Mat4 => 4,
+        }
+    }
+
+    pub fn glsl_name(self) -> &'static str {
+        match self {
+            DataType::Float => "float",
+            DataType::Int => "int",
+            DataType::Uint => "uint",
+            DataType::Vec2 => "vec2",
+            DataType::Vec4 => "vec4",
+            DataType::Mat4 => "mat4",
+        }
+    }
+}
+
+/// A member of a `MemoryLayout`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Member {
+    pub name: &'static str,
+    pub data_type: DataType,
+    /// Offset from the start of the block in 32 bits words.
+    pub offset: usize,
+}
+
+/// Errors reported by `MemoryLayout::check_std140`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LayoutError {
+    /// A member is not placed at a multiple of its base alignment.
+    Misaligned { member: &'static str, offset: usize, alignment: usize },
+    /// The size of the block is not a multiple of a vec4, so the elements of an array of
+    /// blocks would not be placed where the shaders expect them.
+    UnpaddedSize { size: usize },
+}
+
+/// How the members of a `MemoryLayout` are placed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LayoutMode {
+    /// Members are tightly packed in declaration order like the fields of a `#[repr(C)]`
+    /// struct of 32 bits values. This is what data textures use.
+    Packed,
+    /// The rules of uniform buffers: members are aligned to their base alignment and the
+    /// size of the block is rounded up to a vec4, so that arrays of blocks are tightly packed.
+    Std140,
+    /// The rules of storage buffers: like std140, except that the size of the block is
+    /// only rounded up to the largest alignment of its members.
+    Std430,
+}
+
+impl LayoutMode {
+    /// The qualifier to use in the GLSL declaration of the buffer, if any.
+    pub fn glsl_qualifier(self) -> Option<&'static str> {
+        match self {
+            LayoutMode::Packed => None,
+            LayoutMode::Std140 => Some("std140"),
+            LayoutMode::Std430 => Some("std430"),
+        }
+    }
+}
+
+/// The members of a block, placed in declaration order following the rules of a
+/// `LayoutMode`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryLayout {
+    members: Vec<Member>,
+    size: usize,
+    mode: LayoutMode,
+}
+
+impl MemoryLayout {
+    /// Creates a packed layout.
+    pub fn new(members: &[(&'static str, DataType)]) -> Self {
+        MemoryLayout::with_mode(LayoutMode::Packed, members)
+    }
+
+    pub fn with_mode(mode: LayoutMode, members: &[(&'static str, DataType)]) -> Self {
+        let mut layout = MemoryLayout::empty(mode);
+        for &(name, data_type) in members {
+            layout.alloc_named(name, data_type);
+        }
+
+        return layout;
+    }
+
+    pub fn empty(mode: LayoutMode) -> Self {
+        MemoryLayout { members: Vec::new(), size: 0, mode: mode }
+    }
+
+    pub fn mode(&self) -> LayoutMode { self.mode }
+
+    /// Appends a member and returns its offset in 32 bits words.
+    ///
+    /// Names must be unique within a layout.
+    pub fn alloc_named(&mut self, name: &'static str, data_type: DataType) -> usize {
+        debug_assert!(self.member(name).is_none(), "Duplicate member {}", name);
+        let offset = align(self.size, data_type.alignment(self.mode));
+        self.members.push(Member {
+            name: name,
+            data_type: data_type,
+            offset: offset,
+        });
+        self.size = offset + data_type.size_in_words();
+
+        return offset;
+    }
+
+    pub fn members(&self) -> &[Member] { &self.members }
+
+    /// Looks a member up by name.
+    pub fn member(&self, name: &str) -> Option<&Member> {
+        self.members.iter().find(|member| member.name == name)
+    }
+
+    /// The offset in 32 bits words of the member with this name, if any.
+    ///
+    /// This lets effects and tools find the parameters of a block without tracking
+    /// the offsets returned by `alloc_named`.
+    pub fn address_of(&self, name: &str) -> Option<usize> {
+        self.member(name).map(|member| member.offset)
+    }
+
+    /// Size in 32 bits words, including the padding at the end of the block.
+    pub fn size_in_words(&self) -> usize {
+        let alignment = match self.mode {
+            LayoutMode::Packed => 1,
+            LayoutMode::Std140 => 4,
+            LayoutMode::Std430 => {
+                self.members.iter().map(|m| m.data_type.alignment(self.mode)).max().unwrap_or(1)
+            }
+        };
+
+        return align(self.size, alignment);
+    }
+
+    /// Checks that the layout is also a valid std140 layout, which means that the struct
+    /// can be uploaded as is into a uniform buffer.
+    pub fn check_std140(&self) -> Result<(), LayoutError> {
+        for member in &self.members {
+            let alignment = member.data_type.alignment_in_words();
+            if member.offset % alignment != 0 {
+                return Err(LayoutError::Misaligned {
+                    member: member.name,
+                    offset: member.offset,
+                    alignment: alignment,
+                });
+            }
+        }
+
+        let size = self.size_in_words();
+        if size % 4 != 0 {
+            return Err(LayoutError::UnpaddedSize { size: size });
+        }
+
+        return Ok(());
+    }
+
+    /// Generates the GLSL declaration of the block as a struct.
+    ///
+    /// The offsets match the ones of the GLSL struct in std140 and std430 buffers, and in
+    /// packed layouts that pass `check_std140`.
+    pub fn glsl_struct(&self, name: &str) -> String {
+        let mut glsl = String::new();
+        write!(glsl, "struct {} {{\n", name).unwrap();
+        for member in &self.members {
+            write!(glsl, "    {} {};\n", member.data_type.glsl_name(), member.name).unwrap();
+        }
+        glsl.push_str("};\n");
+
+        return glsl;
+    }
+}
+
+fn align(offset: usize, alignment: usize) -> usize {
+    (offset + alignment - 1) / alignment * alignment
+}
+
+/// A type that can be a member of a `GpuBlock`.
+///
+/// `#[derive(GpuBlock)]` requires all the fields of the struct to implement this trait and
+/// takes their `DataType` from it.
+///
+/// # Safety
+///
+/// Implementations must be made of `WORDS` 32 bits values without padding, laid out like
+/// `data_type()` describes.
+pub unsafe trait GpuField: Copy {
+    /// Size in 32 bits words.
+    const WORDS: usize;
+
+    fn data_type() -> DataType;
+}
+
+macro_rules! impl_gpu_field {
+    ($ty:ty, $data_type:ident, $words:expr) => {
+        unsafe impl GpuField for $ty {
+            const WORDS: usize = $words;
+            fn data_type() -> DataType { DataType::$data_type }
+        }
+    }
+}
+
+impl_gpu_field!(f32, Float, 1);
+impl_gpu_field!(i32, Int, 1);
+impl_gpu_field!(u32, Uint, 1);
+impl_gpu_field!([f32; 2], Vec2, 2);
+impl_gpu_field!(Point, Vec2, 2);
+impl_gpu_field!(Vec2, Vec2, 2);
+impl_gpu_field!([f32; 4], Vec4, 4);
+impl_gpu_field!([[f32; 4]; 4], Mat4, 16);
+impl_gpu_field!(Transform3D, Mat4, 16);
+
+/// A struct that can be copied as is into the buffers read by the shaders.
+///
+/// # Safety
+///
+/// `as_words` reinterprets the struct as 32 bits words, so implementations must be
+/// `#[repr(C)]` structs of 32 bits values without padding, laid out like `layout()`
+/// describes. `#[derive(GpuBlock)]` rejects structs that are not `#[repr(C)]` or that have
+/// fields which don't implement `GpuField`, and checks the size of the struct at compile
+/// time.
+pub unsafe trait GpuBlock: Copy {
+    fn layout() -> MemoryLayout;
+
+    /// Size in 32 bits words.
+    fn size_in_words() -> usize { Self::layout().size_in_words() }
+
+    /// The content of the block as 32 bits words, ready to be uploaded.
+    fn as_words(&self) -> &[u32] {
+        let len = mem::size_of::<Self>() / 4;
+        debug_assert_eq!(len, Self::size_in_words());
+        unsafe { slice::from_raw_parts(self as *const Self as *const u32, len) }
+    }
+}
+
+/// The address of a block in a `GpuMemory`, in 32 bits words.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GpuAddress(pub u32);
+
+/// A contiguous region of a `GpuMemory`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GpuAddressRange {
+    pub start: GpuAddress,
+    /// Size in 32 bits words.
+    pub words: u32,
+}
+
+impl GpuAddressRange {
+    /// The address of the `index`-th element of an array of `Block`s stored in this range.
+    pub fn element<Block: GpuBlock>(&self, index: usize) -> GpuAddress {
+        let offset = (index * Block::size_in_words()) as u32;
+        assert!(offset + Block::size_in_words() as u32 <= self.words);
+        GpuAddress(self.start.0 + offset)
+    }
+}
+
+/// CPU-side storage of the blocks read by the shaders, as 32 bits words to be uploaded.
+///
+/// Blocks are always appended, and freed blocks leave holes until `compact` is called. After
+/// many allocations and frees the memory can grow much larger than the live blocks;
+/// compacting moves the live blocks next to each other and returns the `GpuRelocations`
+/// that the owners of addresses must apply before the next upload.
+#[derive(Clone, Debug)]
+pub struct GpuMemory {
+    words: Vec<u32>,
+    freed: Vec<GpuAddressRange>,
+}
+
+impl GpuMemory {
+    pub fn new() -> Self {
+        GpuMemory { words: Vec::new(), freed: Vec::new() }
+    }
+
+    /// Appends a block.
+    pub fn push<Block: GpuBlock>(&mut self, block: &Block) -> GpuAddress {
+        let address = GpuAddress(self.words.len() as u32);
+        self.words.extend_from_slice(block.as_words());
+        return address;
+    }
+
+    /// Reserves a contiguous region, for example to store an array of blocks (gradient
+    /// stops, glyph positions, etc.) that are then written individually with `set_at`.
+    ///
+    /// The region is zero-initialized.
+    pub fn reserve(&mut self, words: u32) -> GpuAddressRange {
+        let start = GpuAddress(self.words.len() as u32);
+        let len = self.words.len() + words as usize;
+        self.words.resize(len, 0);
+        return GpuAddressRange { start: start, words: words };
+    }
+
+    /// Overwrites the block at an address.
+    pub fn set<Block: GpuBlock>(&mut self, address: GpuAddress, block: &Block) {
+        let start = address.0 as usize;
+        let src = block.as_words();
+        self.words[start..(start + src.len())].copy_from_slice(src);
+    }
+
+    /// Overwrites the `index`-th block of an array stored in a reserved range.
+    pub fn set_at<Block: GpuBlock>(&mut self, range: GpuAddressRange, index: usize, block: &Block) {
+        self.set(range.element::<Block>(index), block);
+    }
+
+    /// Marks a region as unused. Its space is reclaimed by `compact`.
+    pub fn free(&mut self, range: GpuAddressRange) {
+        debug_assert!((range.start.0 + range.words) as usize <= self.words.len());
+        self.freed.push(range);
+    }
+
+    /// Marks a block added with `push` as unused.
+    pub fn free_block<Block: GpuBlock>(&mut self, address: GpuAddress) {
+        self.free(GpuAddressRange { start: address, words: Block::size_in_words() as u32 });
+    }
+
+    /// The number of words that `compact` would reclaim.
+    pub fn freed_words(&self) -> usize {
+        self.freed.iter().map(|range| range.words as usize).sum()
+    }
+
+    /// Moves the live blocks next to each other, removing the freed regions.
+    ///
+    /// The addresses of the blocks after a freed region change: every address or range kept
+    /// outside of the memory (in primitives, draw commands, instances, etc.) must be updated
+    /// with the returned relocations.
+    pub fn compact(&mut self) -> GpuRelocations {
+        let mut freed = mem::replace(&mut self.freed, Vec::new());
+        freed.sort_by_key(|range| range.start.0);
+
+        let mut segments = Vec::new();
+        let mut words = Vec::with_capacity(self.words.len());
+        {
+            let old_words = &self.words;
+            let mut move_segment = |start: u32, end: u32, words: &mut Vec<u32>| {
+                if end > start {
+                    segments.push(RelocatedSegment { old_start: start, old_end: end, new_start: words.len() as u32 });
+                    words.extend_from_slice(&old_words[start as usize..end as usize]);
+                }
+            };
+            let mut live_start = 0;
+            for range in &freed {
+                move_segment(live_start, range.start.0, &mut words);
+                // Freed regions may overlap if a range was freed twice.
+                live_start = live_start.max(range.start.0 + range.words);
+            }
+            move_segment(live_start, old_words.len() as u32, &mut words);
+        }
+
+        self.words = words;
+
+        return GpuRelocations { segments: segments };
+    }
+
+    pub fn words(&self) -> &[u32] { &self.words }
+
+    pub fn len(&self) -> usize { self.words.len() }
+
+    pub fn clear(&mut self) {
+        self.words.clear();
+        self.freed.clear();
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct RelocatedSegment {
+    old_start: u32,
+    old_end: u32,
+    new_start: u32,
+}
+
+/// The new addresses of the blocks moved by `GpuMemory::compact`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GpuRelocations {
+    // The live regions before compaction, sorted by address.
+    segments: Vec<RelocatedSegment>,
+}
+
+impl GpuRelocations {
+    /// The new location of an address, or `None` if it was freed.
+    pub fn relocate(&self, address: GpuAddress) -> Option<GpuAddress> {
+        let idx = match self.segments.binary_search_by_key(&address.0, |segment| segment.old_start) {
+            Ok(idx) => idx,
+            Err(0) => { return None; }
+            Err(idx) => idx - 1,
+        };
+
+        let segment = &self.segments[idx];
+        if address.0 >= segment.old_end {
+            return None;
+        }
+
+        return Some(GpuAddress(address.0 - segment.old_start + segment.new_start));
+    }
+
+    /// The new location of a range, or `None` if it was freed.
+    ///
+    /// Live ranges are never split, so relocating the start is enough.
+    pub fn relocate_range(&self, range: GpuAddressRange) -> Option<GpuAddressRange> {
+        self.relocate(range.start).map(|start| GpuAddressRange { start: start, words: range.words })
+    }
+
+    /// Whether no address changed.
+    pub fn is_identity(&self) -> bool {
+        self.segments.iter().all(|segment| segment.old_start == segment.new_start)
+    }
+}
+
+#[cfg(test)]
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct TestPrimitive {
+    color: [f32; 4],
+    offset: [f32; 2],
+    z_index: f32,
+    transform: i32,
+}
+
+#[cfg(test)]
+unsafe impl GpuBlock for TestPrimitive {
+    // What #[derive(GpuBlock)] generates.
+    fn layout() -> MemoryLayout {
+        let _: [(); 0] = [(); mem::size_of::<TestPrimitive>()
+            - 4 * (0 + <[f32; 4] as GpuField>::WORDS + <[f32; 2] as GpuField>::WORDS
+                + <f32 as GpuField>::WORDS + <i32 as GpuField>::WORDS)];
+        MemoryLayout::new(&[
+            ("color", <[f32; 4] as GpuField>::data_type()),
+            ("offset", <[f32; 2] as GpuField>::data_type()),
+            ("z_index", <f32 as GpuField>::data_type()),
+            ("transform", <i32 as GpuField>::data_type()),
+        ])
+    }
+}
+
+#[test]
+fn gpu_block_layout() {
+    let prim = TestPrimitive {
+        color: [1.0, 0.0, 0.0, 1.0],
+        offset: [2.0, 3.0],
+        z_index: 0.5,
+        transform: 7,
+    };
+
+    assert_eq!(TestPrimitive::size_in_words(), 8);
+    assert_eq!(<Point as GpuField>::data_type(), DataType::Vec2);
+    assert_eq!(<Transform3D as GpuField>::WORDS * 4, mem::size_of::<Transform3D>());
+    assert_eq!(prim.as_words()[5], 3.0f32.to_bits());
+    assert_eq!(prim.as_words()[7], 7);
+
+    let layout = TestPrimitive::layout();
+    assert_eq!(layout.members()[2].offset, 6);
+    assert_eq!(layout.check_std140(), Ok(()));
+    assert_eq!(
+        layout.glsl_struct("Primitive"),
+        "struct Primitive {\n    vec4 color;\n    vec2 offset;\n    float z_index;\n    int transform;\n};\n"
+    );
+
+    let mut glow = MemoryLayout::empty(LayoutMode::Packed);
+    assert_eq!(glow.alloc_named("glow_radius", DataType::Float), 0);
+    assert_eq!(glow.alloc_named("glow_color", DataType::Vec4), 1);
+    assert_eq!(glow.address_of("glow_color"), Some(1));
+    assert_eq!(glow.member("glow_radius").map(|m| m.data_type), Some(DataType::Float));
+    assert_eq!(glow.address_of("shadow_color"), None);
+
+    let misaligned = MemoryLayout::new(&[("width", DataType::Float), ("offset", DataType::Vec2)]);
+    assert_eq!(
+        misaligned.check_std140(),
+        Err(LayoutError::Misaligned { member: "offset", offset: 1, alignment: 2 })
+    );
+
+    let unpadded = MemoryLayout::new(&[("color", DataType::Vec4), ("width", DataType::Float)]);
+    assert_eq!(unpadded.check_std140(), Err(LayoutError::UnpaddedSize { size: 5 }));
+}
+
+#[test]
+fn layout_modes() {
+    let members = [
+        ("width", DataType::Float),
+        ("offset", DataType::Vec2),
+        ("color", DataType::Vec4),
+        ("z_index", DataType::Float),
+    ];
+
+    let packed = MemoryLayout::with_mode(LayoutMode::Packed, &members);
+    let offsets: Vec<usize> = packed.members().iter().map(|m| m.offset).collect();
+    assert_eq!(offsets, vec![0, 1, 3, 7]);
+    assert_eq!(packed.size_in_words(), 8);
+
+    let std140 = MemoryLayout::with_mode(LayoutMode::Std140, &members);
+    let offsets: Vec<usize> = std140.members().iter().map(|m| m.offset).collect();
+    assert_eq!(offsets, vec![0, 2, 4, 8]);
+    assert_eq!(std140.size_in_words(), 12);
+    assert_eq!(std140.check_std140(), Ok(()));
+
+    let std430 = MemoryLayout::with_mode(
+        LayoutMode::Std430,
+        &[("offset", DataType::Vec2), ("z_index", DataType::Float)],
+    );
+    assert_eq!(std430.size_in_words(), 4);
+    let std430 = MemoryLayout::with_mode(
+        LayoutMode::Std430,
+        &[("width", DataType::Float), ("z_index", DataType::Float), ("id", DataType::Int)],
+    );
+    assert_eq!(std430.size_in_words(), 3);
+    assert_eq!(LayoutMode::Std430.glsl_qualifier(), Some("std430"));
+}
+
+#[test]
+fn gpu_memory_arrays() {
+    let prim = TestPrimitive {
+        color: [1.0, 1.0, 1.0, 1.0],
+        offset: [0.0, 0.0],
+        z_index: 0.0,
+        transform: 1,
+    };
+
+    let mut memory = GpuMemory::new();
+    let first = memory.push(&prim);
+    let stops = memory.reserve(3 * TestPrimitive::size_in_words() as u32);
+    let last = memory.push(&prim);
+
+    assert_eq!(first, GpuAddress(0));
+    assert_eq!(stops.start, GpuAddress(8));
+    assert_eq!(last, GpuAddress(32));
+    assert_eq!(stops.element::<TestPrimitive>(2), GpuAddress(24));
+
+    memory.set_at(stops, 1, &TestPrimitive { transform: 5, .. prim });
+    assert_eq!(memory.words()[16 + 7], 5);
+    assert_eq!(memory.words()[8 + 7], 0);
+    assert_eq!(memory.words()[32 + 7], 1);
+}
+
+#[test]
+fn gpu_memory_compaction() {
+    let prim = TestPrimitive {
+        color: [1.0, 1.0, 1.0, 1.0],
+        offset: [0.0, 0.0],
+        z_index: 0.0,
+        transform: 1,
+    };
+
+    let mut memory = GpuMemory::new();
+    let first = memory.push(&prim);
+    let stops = memory.reserve(3 * TestPrimitive::size_in_words() as u32);
+    let second = memory.push(&prim);
+    let last = memory.push(&TestPrimitive { transform: 9, .. prim });
+    assert_eq!(memory.len(), 48);
+
+    memory.free(stops);
+    memory.free_block::<TestPrimitive>(second);
+    assert_eq!(memory.freed_words(), 32);
+
+    let relocations = memory.compact();
+    assert_eq!(memory.len(), 16);
+    assert_eq!(memory.freed_words(), 0);
+    assert!(!relocations.is_identity());
+
+    assert_eq!(relocations.relocate(first), Some(first));
+    assert_eq!(relocations.relocate_range(stops), None);
+    assert_eq!(relocations.relocate(second), None);
+    let moved = relocations.relocate(last).unwrap();
+    assert_eq!(moved, GpuAddress(8));
+    assert_eq!(memory.words()[moved.0 as usize + 7], 9);
+
+    // Nothing moves without freed blocks.
+    assert!(memory.compact().is_identity());
+}
@@ -15,10 +15,37 @@ extern crate lyon_bezier as bezier;
 extern crate lyon_path_iterator as path_iterator;
 extern crate lyon_tessellation as tessellation;
 
+#[cfg(feature = "serialization")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "serialization")]
+extern crate serde;
+#[cfg(all(test, feature = "serialization"))]
+extern crate serde_json;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+
 pub mod api;
 pub mod frame;
 pub mod batch_builder;
 pub mod buffer;
 pub mod renderer;
+pub mod post_process;
+pub mod readback;
+pub mod picking;
+pub mod snapshot;
+pub mod stats;
+pub mod viewport;
+pub mod units;
+pub mod quantize;
+pub mod caps;
+pub mod capture;
+pub mod analytic_aa;
+pub mod style_sheet;
+pub mod extrusion;
+pub mod storage;
+pub mod streaming;
+pub mod text;
+pub mod gpu_block;
 pub mod gfx_types;
 pub mod glsl;
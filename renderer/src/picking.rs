@@ -0,0 +1,175 @@
+//! Identifying the primitive under a given pixel.
+//!
+//! The picking pass renders the geometry of the render nodes into an integer target,
+//! writing the id of the primitive that covers each pixel instead of its color. The depth
+//! test keeps the front-most primitive, so reading a pixel back gives exactly what the
+//! user sees at that position, which CPU-side hit testing against the shapes can't do
+//! for transformed, instanced or overlapping primitives.
+//!
+//! The pass uses the position-only vertices produced by `PositionVertexConstructor`, the
+//! same primitive buffers as the fill pass and the instance stream of the batcher.
+
+use gfx;
+use gfx::Factory;
+use gfx::traits::FactoryExt;
+use gfx::format::Formatted;
+use gfx_device_gl;
+
+pub use gfx_types::*;
+use renderer::{Globals, GpuTransform, GpuFillPrimitive, GpuPositionVertex, GpuInstance};
+use glsl::{PICKING_VERTEX_SHADER, PICKING_FRAGMENT_SHADER, shader_source};
+
+/// The format of the picking target: one unsigned integer id per pixel.
+pub type PickingFormat = (gfx::format::R32, gfx::format::Uint);
+pub type PickingTexture = gfx::handle::Texture<gfx_device_gl::Resources, gfx::format::R32>;
+pub type PickingView = gfx::handle::RenderTargetView<gfx_device_gl::Resources, PickingFormat>;
+
+gfx_defines!{
+    constant GpuPickingParams {
+        // The primitive page bound for the draw call.
+        page: i32 = "u_page",
+    }
+
+    pipeline picking_pipeline {
+        vbo: gfx::VertexBuffer<GpuPositionVertex> = (),
+        instances: gfx::InstanceBuffer<GpuInstance> = (),
+        out_id: gfx::RenderTarget<PickingFormat> = "out_id",
+        out_depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
+        constants: gfx::ConstantBuffer<Globals> = "Globals",
+        picking: gfx::ConstantBuffer<GpuPickingParams> = "Picking",
+        transforms: gfx::ConstantBuffer<GpuTransform> = "u_transforms",
+        primitives: gfx::ConstantBuffer<GpuFillPrimitive> = "u_primitives",
+    }
+}
+
+pub type PickingPso = Pso<picking_pipeline::Meta>;
+
+/// A primitive found by the picking pass.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PickedPrimitive {
+    /// The page (buffer) of the primitive, see `PrimitiveIndex::primitive_page`.
+    pub page: u32,
+    /// The index of the primitive in its page, including the instance offset.
+    pub index: u32,
+}
+
+/// Encodes a primitive the way the picking shader does.
+///
+/// Zero is reserved for the pixels that aren't covered by any primitive.
+pub fn encode_picking_id(primitive: PickedPrimitive) -> u32 {
+    debug_assert!(primitive.index <= 0xFFFF);
+    return ((primitive.page << 16) | primitive.index) + 1;
+}
+
+/// Decodes a value read back from the picking target.
+pub fn decode_picking_id(value: u32) -> Option<PickedPrimitive> {
+    if value == 0 {
+        return None;
+    }
+    let value = value - 1;
+
+    return Some(PickedPrimitive {
+        page: value >> 16,
+        index: value & 0xFFFF,
+    });
+}
+
+pub fn create_picking_pso(factory: &mut GlFactory) -> PickingPso {
+    let program = factory.link_program(
+        shader_source(PICKING_VERTEX_SHADER).as_bytes(),
+        shader_source(PICKING_FRAGMENT_SHADER).as_bytes(),
+    ).unwrap();
+
+    return factory.create_pipeline_from_program(
+        &program,
+        gfx::Primitive::TriangleList,
+        gfx::state::Rasterizer::new_fill(),
+        picking_pipeline::new(),
+    ).unwrap();
+}
+
+/// The integer target of the picking pass.
+///
+/// It is typically much smaller than the window (or only rendered around the cursor
+/// with a scissor rect), since only a few pixels are read back.
+pub struct PickingTarget {
+    pub view: PickingView,
+    pub surface: PickingTexture,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl PickingTarget {
+    pub fn new(factory: &mut GlFactory, width: u16, height: u16) -> Self {
+        let kind = gfx::texture::Kind::D2(width, height, gfx::texture::AaMode::Single);
+        let surface = factory.create_texture(
+            kind, 1,
+            gfx::memory::RENDER_TARGET | gfx::memory::TRANSFER_SRC,
+            gfx::memory::Usage::Data,
+            Some(gfx::format::ChannelType::Uint),
+        ).unwrap();
+        let view = factory.view_texture_as_render_target(&surface, 0, None).unwrap();
+
+        PickingTarget {
+            view: view,
+            surface: surface,
+            width: width,
+            height: height,
+        }
+    }
+
+    /// Clears the target so that pixels that aren't covered by any primitive are picked
+    /// as `None`.
+    pub fn clear(&self, encoder: &mut CmdEncoder) {
+        encoder.clear(&self.view, [0, 0, 0, 0]);
+    }
+
+    /// Reads back the primitive at a position of the target, with the origin at the top
+    /// left corner.
+    ///
+    /// Like `read_pixels` this stalls until the GPU has finished rendering the picking pass.
+    pub fn pick(
+        &self,
+        x: u16,
+        y: u16,
+        factory: &mut GlFactory,
+        encoder: &mut CmdEncoder,
+        device: &mut GlDevice,
+    ) -> Option<PickedPrimitive> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        let download = factory.create_download_buffer::<u32>(1).unwrap();
+
+        // GL textures have their origin at the bottom-left corner.
+        let image = gfx::texture::RawImageInfo {
+            xoffset: x,
+            yoffset: self.height - y - 1,
+            zoffset: 0,
+            width: 1,
+            height: 1,
+            depth: 0,
+            format: PickingFormat::get_format(),
+            mipmap: 0,
+        };
+        encoder.copy_texture_to_buffer_raw(self.surface.raw(), None, image, download.raw(), 0).unwrap();
+        encoder.flush(device);
+
+        let value = factory.read_mapping(&download).unwrap()[0];
+
+        return decode_picking_id(value);
+    }
+}
+
+#[test]
+fn picking_ids() {
+    assert_eq!(decode_picking_id(0), None);
+
+    for &(page, index) in &[(0, 0), (0, 1023), (3, 17), (200, 0xFFFF)] {
+        let primitive = PickedPrimitive { page: page, index: index };
+        let id = encode_picking_id(primitive);
+        assert!(id != 0);
+        assert_eq!(decode_picking_id(id), Some(primitive));
+    }
+}
@@ -0,0 +1,450 @@
+use gfx;
+use gfx::Factory;
+use gfx::traits::FactoryExt;
+use gfx_device_gl;
+
+pub use gfx_types::*;
+use api::{PostEffect, EffectId, EffectRegistry, AlphaMode, RenderOptions, Layer, LayerCache};
+use renderer::blend_state;
+use stats::{PassTimer, RenderPass, RenderStats};
+use glsl::{POST_PROCESS_VERTEX_SHADER, POST_PROCESS_FRAGMENT_SHADER, shader_with_options};
+
+gfx_defines!{
+    vertex GpuBlitVertex {
+        position: [f32; 2] = "a_position",
+    }
+
+    constant GpuEffectParams {
+        color_matrix: [[f32; 4]; 4] = "color_matrix",
+        params: [f32; 4] = "params",
+        resolution: [f32; 2] = "resolution",
+        kind: i32 = "kind",
+        opacity: f32 = "opacity",
+    }
+
+    // Used for the intermediate passes which overwrite their target.
+    pipeline post_process_pipeline {
+        vbo: gfx::VertexBuffer<GpuBlitVertex> = (),
+        source: gfx::TextureSampler<[f32; 4]> = "t_source",
+        mask: gfx::TextureSampler<[f32; 4]> = "t_mask",
+        effect: gfx::ConstantBuffer<GpuEffectParams> = "Effect",
+        out_color: gfx::RenderTarget<ColorFormat> = "out_color",
+    }
+
+    // Used for the last pass which blends the layer over the destination.
+    pipeline composite_pipeline {
+        vbo: gfx::VertexBuffer<GpuBlitVertex> = (),
+        source: gfx::TextureSampler<[f32; 4]> = "t_source",
+        mask: gfx::TextureSampler<[f32; 4]> = "t_mask",
+        effect: gfx::ConstantBuffer<GpuEffectParams> = "Effect",
+        out_color: gfx::BlendTarget<ColorFormat> = ("out_color", gfx::state::MASK_ALL, gfx::preset::blend::ALPHA),
+    }
+}
+
+pub type TextureView = gfx::handle::ShaderResourceView<gfx_device_gl::Resources, [f32; 4]>;
+pub type Sampler = gfx::handle::Sampler<gfx_device_gl::Resources>;
+pub type ColorTexture = gfx::handle::Texture<gfx_device_gl::Resources, gfx::format::R8_G8_B8_A8>;
+
+/// A texture that layers can be rendered into before being post-processed.
+pub struct OffscreenTarget {
+    pub color: ColorTarget,
+    pub texture: TextureView,
+    pub surface: ColorTexture,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl OffscreenTarget {
+    pub fn new(factory: &mut GlFactory, width: u16, height: u16) -> Self {
+        let (surface, texture, color) = factory.create_render_target::<ColorFormat>(width, height).unwrap();
+        OffscreenTarget {
+            color: color,
+            texture: texture,
+            surface: surface,
+            width: width,
+            height: height,
+        }
+    }
+}
+
+/// The retained texture of a cached layer, see `Layer::set_cached`.
+///
+/// The layer is rendered into `target` by `update` when its cache was invalidated, and
+/// composited from it like any other offscreen layer on every frame.
+pub struct CachedLayerTarget {
+    pub target: OffscreenTarget,
+    pub cache: LayerCache,
+}
+
+impl CachedLayerTarget {
+    pub fn new(factory: &mut GlFactory, width: u16, height: u16) -> Self {
+        CachedLayerTarget {
+            target: OffscreenTarget::new(factory, width, height),
+            cache: LayerCache::new(),
+        }
+    }
+
+    /// Re-creates the texture with a new size, which invalidates it.
+    pub fn resize(&mut self, factory: &mut GlFactory, width: u16, height: u16) {
+        if width == self.target.width && height == self.target.height {
+            return;
+        }
+        self.target = OffscreenTarget::new(factory, width, height);
+        self.cache.invalidate();
+    }
+
+    /// Calls `render` to draw the layer into the texture if the cache is invalid.
+    ///
+    /// Returns whether the layer was rendered.
+    pub fn update<F>(&mut self, render: F) -> bool
+    where F: FnOnce(&OffscreenTarget) {
+        if !self.cache.needs_render() {
+            return false;
+        }
+        render(&self.target);
+        self.cache.mark_rendered();
+        return true;
+    }
+}
+
+/// Applies chains of post-processing effects to layers rendered in offscreen targets and
+/// composites the result into a destination target.
+///
+/// Each effect is a full-screen pass. Intermediate results ping-pong between two offscreen
+/// targets owned by the post-processor and the last pass is alpha-blended over the destination,
+/// with the blend factors of the alpha mode the layers were rendered with.
+pub struct PostProcessor {
+    post_process_pso: Pso<post_process_pipeline::Meta>,
+    composite_pso: Pso<composite_pipeline::Meta>,
+    vbo: Vbo<GpuBlitVertex>,
+    slice: IndexSlice,
+    effect: BufferObject<GpuEffectParams>,
+    sampler: Sampler,
+    targets: [OffscreenTarget; 2],
+    // A single white pixel, used when compositing without a mask.
+    no_mask: TextureView,
+    options: RenderOptions,
+    // Times the compositions as the `RenderPass::PostProcess` pass, see `set_pass_timer`.
+    pass_timer: Option<Box<PassTimer>>,
+}
+
+/// Errors reported when building the pipelines of a shader.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShaderError {
+    /// The shader failed to compile or link, with the log of the driver.
+    Program(String),
+    /// The pipeline didn't match the shader's inputs and outputs.
+    Pipeline(String),
+}
+
+impl PostProcessor {
+    pub fn new(factory: &mut GlFactory, width: u16, height: u16) -> Self {
+        PostProcessor::with_alpha_mode(factory, width, height, AlphaMode::Straight)
+    }
+
+    pub fn with_alpha_mode(factory: &mut GlFactory, width: u16, height: u16, alpha_mode: AlphaMode) -> Self {
+        let options = RenderOptions::default().with_alpha_mode(alpha_mode);
+        PostProcessor::with_options(factory, width, height, &options)
+    }
+
+    /// Creates a post-processor compositing with the alpha mode of the options, and
+    /// dithering its output if requested.
+    pub fn with_options(factory: &mut GlFactory, width: u16, height: u16, options: &RenderOptions) -> Self {
+        let (post_process_pso, composite_pso) = create_psos(
+            factory,
+            POST_PROCESS_FRAGMENT_SHADER,
+            options,
+        ).unwrap();
+
+        // A single triangle covering the whole viewport.
+        let vertices = [
+            GpuBlitVertex { position: [-1.0, -1.0] },
+            GpuBlitVertex { position: [3.0, -1.0] },
+            GpuBlitVertex { position: [-1.0, 3.0] },
+        ];
+        let (vbo, slice) = factory.create_vertex_buffer_with_slice(&vertices[..], ());
+
+        let (_, no_mask) = factory.create_texture_immutable_u8::<ColorFormat>(
+            gfx::texture::Kind::D2(1, 1, gfx::texture::AaMode::Single),
+            &[&[255, 255, 255, 255]],
+        ).unwrap();
+
+        PostProcessor {
+            post_process_pso: post_process_pso,
+            composite_pso: composite_pso,
+            vbo: vbo,
+            slice: slice,
+            effect: factory.create_constant_buffer(1),
+            sampler: factory.create_sampler_linear(),
+            targets: [
+                OffscreenTarget::new(factory, width, height),
+                OffscreenTarget::new(factory, width, height),
+            ],
+            no_mask: no_mask,
+            options: *options,
+            pass_timer: None,
+        }
+    }
+
+    /// Recompiles the effects with a new fragment shader, to iterate on effects without
+    /// restarting the application.
+    ///
+    /// All the effects are implemented by the same shader, selected by the `kind` parameter,
+    /// so the new source must handle all of them. It receives the same defines as the
+    /// built-in shader. On failure the previous shader is kept and the error is returned.
+    pub fn reload_shader(&mut self, factory: &mut GlFactory, fragment_source: &str) -> Result<(), ShaderError> {
+        let (post_process_pso, composite_pso) = create_psos(factory, fragment_source, &self.options)?;
+        self.post_process_pso = post_process_pso;
+        self.composite_pso = composite_pso;
+
+        return Ok(());
+    }
+
+    /// Times each of the following compositions as the `RenderPass::PostProcess` pass, or
+    /// stops timing them with `None`.
+    ///
+    /// Only the CPU time spent recording the draw calls is measured, see the `stats` module.
+    pub fn set_pass_timer(&mut self, timer: Option<Box<PassTimer>>) {
+        self.pass_timer = timer;
+    }
+
+    /// Moves the timings of the compositions since the last call into `stats`.
+    pub fn collect_pass_times(&mut self, stats: &mut RenderStats) {
+        if let Some(ref mut timer) = self.pass_timer {
+            timer.collect(stats);
+        }
+    }
+
+    /// Applies the effects registered under the provided ids, in order.
+    pub fn apply_chain(
+        &mut self,
+        registry: &EffectRegistry,
+        chain: &[EffectId],
+        source: &OffscreenTarget,
+        destination: &ColorTarget,
+        encoder: &mut CmdEncoder,
+    ) {
+        let effects: Vec<PostEffect> = chain.iter().map(|&id| *registry.get_effect(id)).collect();
+        self.apply(&effects, source, destination, encoder);
+    }
+
+    /// Composites a layer that was rendered into `source`, applying its effects and opacity.
+    pub fn composite_layer(
+        &mut self,
+        registry: &EffectRegistry,
+        layer: &Layer,
+        source: &OffscreenTarget,
+        destination: &ColorTarget,
+        encoder: &mut CmdEncoder,
+    ) {
+        let effects: Vec<PostEffect> = layer.effects().iter().map(|&id| *registry.get_effect(id)).collect();
+        self.composite(&effects, layer.opacity(), source, destination, encoder);
+    }
+
+    /// Applies the effects to the content of `source`, in order, and blends the result
+    /// over `destination`. With no effect the source is composited as is.
+    pub fn apply(
+        &mut self,
+        effects: &[PostEffect],
+        source: &OffscreenTarget,
+        destination: &ColorTarget,
+        encoder: &mut CmdEncoder,
+    ) {
+        self.composite(effects, 1.0, source, destination, encoder);
+    }
+
+    /// Same as `apply`, fading the result with `opacity` when blending it over the
+    /// destination.
+    ///
+    /// Since the content is flattened in `source` first, overlapping primitives don't
+    /// show through each other as they would if each of them was faded individually.
+    pub fn composite(
+        &mut self,
+        effects: &[PostEffect],
+        opacity: f32,
+        source: &OffscreenTarget,
+        destination: &ColorTarget,
+        encoder: &mut CmdEncoder,
+    ) {
+        let no_mask = self.no_mask.clone();
+        self.composite_impl(effects, opacity, source, no_mask, destination, encoder);
+    }
+
+    /// Same as `composite`, also multiplying the result by the alpha channel of `mask`.
+    ///
+    /// The mask is typically an image or shapes rendered in an offscreen target of the
+    /// same size as the source. This is how the `MaskedCmd`s produced by the batcher are
+    /// rendered: the commands are drawn into `source`, their mask into `mask`, and the
+    /// result is composited through the mask.
+    pub fn composite_masked(
+        &mut self,
+        effects: &[PostEffect],
+        opacity: f32,
+        source: &OffscreenTarget,
+        mask: &OffscreenTarget,
+        destination: &ColorTarget,
+        encoder: &mut CmdEncoder,
+    ) {
+        self.composite_impl(effects, opacity, source, mask.texture.clone(), destination, encoder);
+    }
+
+    fn composite_impl(
+        &mut self,
+        effects: &[PostEffect],
+        opacity: f32,
+        source: &OffscreenTarget,
+        mask: TextureView,
+        destination: &ColorTarget,
+        encoder: &mut CmdEncoder,
+    ) {
+        if let Some(ref mut timer) = self.pass_timer {
+            timer.begin_pass_timer(RenderPass::PostProcess);
+        }
+
+        let resolution = [source.width as f32, source.height as f32];
+
+        // All effects but the last one are applied in intermediate passes.
+        let num_intermediate_passes = effects.len().saturating_sub(1);
+        let mut input = source.texture.clone();
+        for i in 0..num_intermediate_passes {
+            encoder.update_constant_buffer(&self.effect, &GpuEffectParams::new(Some(&effects[i]), resolution));
+            let target = &self.targets[i % 2];
+            encoder.draw(
+                &self.slice,
+                &self.post_process_pso,
+                &post_process_pipeline::Data {
+                    vbo: self.vbo.clone(),
+                    source: (input.clone(), self.sampler.clone()),
+                    mask: (self.no_mask.clone(), self.sampler.clone()),
+                    effect: self.effect.clone(),
+                    out_color: target.color.clone(),
+                },
+            );
+            input = target.texture.clone();
+        }
+
+        let last_effect = effects.last();
+        encoder.update_constant_buffer(
+            &self.effect,
+            &GpuEffectParams::new(last_effect, resolution).with_opacity(opacity),
+        );
+
+        encoder.draw(
+            &self.slice,
+            &self.composite_pso,
+            &composite_pipeline::Data {
+                vbo: self.vbo.clone(),
+                source: (input, self.sampler.clone()),
+                mask: (mask, self.sampler.clone()),
+                effect: self.effect.clone(),
+                out_color: destination.clone(),
+            },
+        );
+
+        if let Some(ref mut timer) = self.pass_timer {
+            timer.end_pass_timer(RenderPass::PostProcess);
+        }
+    }
+}
+
+fn create_psos(
+    factory: &mut GlFactory,
+    fragment_source: &str,
+    options: &RenderOptions,
+) -> Result<(Pso<post_process_pipeline::Meta>, Pso<composite_pipeline::Meta>), ShaderError> {
+    let program = factory.link_program(
+        POST_PROCESS_VERTEX_SHADER.as_bytes(),
+        shader_with_options(fragment_source, options).as_bytes(),
+    ).map_err(|e| ShaderError::Program(format!("{:?}", e)))?;
+
+    let post_process_pso = factory.create_pipeline_from_program(
+        &program,
+        gfx::Primitive::TriangleList,
+        gfx::state::Rasterizer::new_fill(),
+        post_process_pipeline::new(),
+    ).map_err(|e| ShaderError::Pipeline(format!("{:?}", e)))?;
+
+    let composite_pso = factory.create_pipeline_from_program(
+        &program,
+        gfx::Primitive::TriangleList,
+        gfx::state::Rasterizer::new_fill(),
+        composite_pipeline::Init {
+            out_color: ("out_color", gfx::state::MASK_ALL, blend_state(options.alpha_mode)),
+            .. composite_pipeline::new()
+        },
+    ).map_err(|e| ShaderError::Pipeline(format!("{:?}", e)))?;
+
+    return Ok((post_process_pso, composite_pso));
+}
+
+impl GpuEffectParams {
+    pub fn new(effect: Option<&PostEffect>, resolution: [f32; 2]) -> Self {
+        let identity = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        let (kind, params, matrix) = match effect {
+            None => (0, [0.0; 4], identity),
+            Some(&PostEffect::Vignette { radius, softness }) => {
+                (1, [radius, softness, 0.0, 0.0], identity)
+            }
+            Some(&PostEffect::ColorGrading { matrix }) => (2, [0.0; 4], matrix),
+            Some(&PostEffect::Pixelate { block_size }) => {
+                (3, [block_size.max(1.0), 0.0, 0.0, 0.0], identity)
+            }
+            Some(&PostEffect::Blur { radius }) => {
+                (4, [radius.max(0.0), 0.0, 0.0, 0.0], identity)
+            }
+        };
+
+        GpuEffectParams {
+            color_matrix: matrix,
+            params: params,
+            resolution: resolution,
+            kind: kind,
+            opacity: 1.0,
+        }
+    }
+
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity;
+        return self;
+    }
+}
+
+#[test]
+fn effect_registry() {
+    let mut registry = EffectRegistry::new();
+    let vignette = registry.add_effect(PostEffect::Vignette { radius: 0.5, softness: 0.2 });
+    let pixelate = registry.add_effect(PostEffect::Pixelate { block_size: 4.0 });
+
+    assert_eq!(*registry.get_effect(pixelate), PostEffect::Pixelate { block_size: 4.0 });
+
+    registry.set_effect(vignette, PostEffect::Vignette { radius: 0.3, softness: 0.2 });
+    let params = GpuEffectParams::new(Some(registry.get_effect(vignette)), [800.0, 600.0]);
+    assert_eq!(params.kind, 1);
+    assert_eq!(params.params, [0.3, 0.2, 0.0, 0.0]);
+    assert_eq!(GpuEffectParams::new(None, [800.0, 600.0]).kind, 0);
+}
+
+#[test]
+fn layer_opacity() {
+    let mut registry = EffectRegistry::new();
+    let mut layer = Layer::new();
+    assert!(!layer.needs_offscreen_target());
+
+    layer.set_opacity(0.5);
+    assert!(layer.needs_offscreen_target());
+    layer.set_opacity(2.0);
+    assert_eq!(layer.opacity(), 1.0);
+    assert!(!layer.needs_offscreen_target());
+
+    layer.add_effect(registry.add_effect(PostEffect::Pixelate { block_size: 4.0 }));
+    assert!(layer.needs_offscreen_target());
+
+    let params = GpuEffectParams::new(None, [800.0, 600.0]);
+    assert_eq!(params.opacity, 1.0);
+    assert_eq!(params.with_opacity(0.25).opacity, 0.25);
+}
@@ -0,0 +1,343 @@
+//! Compact vertex formats storing positions and normals as normalized 16 bits integers.
+//!
+//! Large scenes are limited by the vertex bandwidth rather than by the fragment work, and
+//! the f32 positions and normals take most of the size of the vertices. Quantized vertices
+//! store them relative to the bounding rectangle of the geometry they belong to, which the
+//! shaders map back to world units with the `Dequantization` of the geometry. This shrinks
+//! `GpuFillVertex` from 20 to 12 bytes and `GpuStrokeVertex` from 28 to 20 bytes.
+//!
+//! The precision is 1/32767th of the half size of the geometry, which is plenty for
+//! anything that fits on screen but not for very large paths viewed at a high zoom level.
+//!
+//! Wrap a vertex builder in a `QuantizedVertexBuilder` to produce quantized vertices, the
+//! constants of each shape are then available through `GeometryStore::dequantization`.
+
+use gfx::format::I16Norm;
+
+use api::*;
+use path::Path;
+use core::math::*;
+use batch_builder::{ Geometry, GeometryRanges, VertexBuilder, geometry_ranges };
+use tessellation::Count;
+use renderer::{ GpuFillVertex, GpuStrokeVertex, GpuDequantization };
+use renderer::{ GpuQuantizedFillVertex, GpuQuantizedStrokeVertex, FillPrimitiveId };
+#[cfg(test)]
+use batch_builder::*;
+#[cfg(test)]
+use buffer::CpuBuffer;
+
+const QUANTIZATION_RANGE: f32 = 32767.0;
+
+/// Maps the normalized positions and normals of quantized vertices back to world units.
+///
+/// A position is `offset + p * scale` and a normal is `n * normal_scale`, where `p` and
+/// `n` are the normalized values in [-1, 1].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Dequantization {
+    pub offset: Vec2,
+    pub scale: Vec2,
+    pub normal_scale: f32,
+}
+
+impl Dequantization {
+    /// The constants covering a rectangle and normals up to `max_normal` on each axis.
+    pub fn new(bounds: &Rect, max_normal: f32) -> Self {
+        let scale = vec2(bounds.size.width * 0.5, bounds.size.height * 0.5);
+        Dequantization {
+            offset: bounds.origin.to_vector() + scale,
+            scale: scale,
+            normal_scale: max_normal.max(1.0),
+        }
+    }
+
+    pub fn quantize_position(&self, position: Point) -> [I16Norm; 2] {
+        let p = position - self.offset;
+        [quantize(p.x, self.scale.x), quantize(p.y, self.scale.y)]
+    }
+
+    pub fn dequantize_position(&self, position: [I16Norm; 2]) -> Point {
+        point(
+            self.offset.x + dequantize(position[0], self.scale.x),
+            self.offset.y + dequantize(position[1], self.scale.y),
+        )
+    }
+
+    pub fn quantize_normal(&self, normal: Vec2) -> [I16Norm; 2] {
+        [quantize(normal.x, self.normal_scale), quantize(normal.y, self.normal_scale)]
+    }
+
+    pub fn dequantize_normal(&self, normal: [I16Norm; 2]) -> Vec2 {
+        vec2(dequantize(normal[0], self.normal_scale), dequantize(normal[1], self.normal_scale))
+    }
+
+    pub fn to_gpu(&self) -> GpuDequantization {
+        GpuDequantization {
+            offset: self.offset.to_array(),
+            scale: self.scale.to_array(),
+            normal_scale: self.normal_scale,
+        }
+    }
+}
+
+fn quantize(value: f32, scale: f32) -> I16Norm {
+    if scale == 0.0 {
+        return I16Norm(0);
+    }
+    let normalized = (value / scale).max(-1.0).min(1.0);
+    return I16Norm((normalized * QUANTIZATION_RANGE).round() as i16);
+}
+
+fn dequantize(value: I16Norm, scale: f32) -> f32 {
+    value.0 as f32 / QUANTIZATION_RANGE * scale
+}
+
+/// Vertices that have a quantized version.
+pub trait Quantize {
+    type Quantized;
+
+    fn position(&self) -> Point;
+
+    fn normal(&self) -> Vec2;
+
+    fn quantize(&self, dequantization: &Dequantization) -> Self::Quantized;
+}
+
+impl Quantize for GpuFillVertex {
+    type Quantized = GpuQuantizedFillVertex;
+
+    fn position(&self) -> Point { point(self.position[0], self.position[1]) }
+
+    fn normal(&self) -> Vec2 { vec2(self.normal[0], self.normal[1]) }
+
+    fn quantize(&self, dequantization: &Dequantization) -> GpuQuantizedFillVertex {
+        GpuQuantizedFillVertex {
+            position: dequantization.quantize_position(self.position()),
+            normal: dequantization.quantize_normal(self.normal()),
+            alpha: self.alpha,
+            prim_id: self.prim_id,
+        }
+    }
+}
+
+impl Quantize for GpuStrokeVertex {
+    type Quantized = GpuQuantizedStrokeVertex;
+
+    fn position(&self) -> Point { point(self.position[0], self.position[1]) }
+
+    fn normal(&self) -> Vec2 { vec2(self.normal[0], self.normal[1]) }
+
+    fn quantize(&self, dequantization: &Dequantization) -> GpuQuantizedStrokeVertex {
+        GpuQuantizedStrokeVertex {
+            position: dequantization.quantize_position(self.position()),
+            normal: dequantization.quantize_normal(self.normal()),
+            advancement: self.advancement,
+            path_length: self.path_length,
+            alpha: self.alpha,
+            prim_id: self.prim_id,
+        }
+    }
+}
+
+/// Quantizes vertices with constants covering all of them.
+pub fn quantize_vertices<V: Quantize>(vertices: &[V]) -> (Vec<V::Quantized>, Dequantization) {
+    let dequantization = compute_dequantization(vertices);
+    let quantized = vertices.iter().map(|vertex| vertex.quantize(&dequantization)).collect();
+
+    return (quantized, dequantization);
+}
+
+fn compute_dequantization<V: Quantize>(vertices: &[V]) -> Dequantization {
+    if vertices.is_empty() {
+        return Dequantization::new(&Rect::zero(), 1.0);
+    }
+
+    let first = vertices[0].position();
+    let mut min = first;
+    let mut max = first;
+    let mut max_normal: f32 = 0.0;
+    for vertex in vertices {
+        let p = vertex.position();
+        let n = vertex.normal();
+        min = point(min.x.min(p.x), min.y.min(p.y));
+        max = point(max.x.max(p.x), max.y.max(p.y));
+        max_normal = max_normal.max(n.x.abs()).max(n.y.abs());
+    }
+
+    return Dequantization::new(&Rect::new(min, size(max.x - min.x, max.y - min.y)), max_normal);
+}
+
+/// Wraps a vertex builder to produce the quantized version of its vertices.
+///
+/// Each shape is quantized relative to its own bounds, so `OpaqueBatcher::build` records
+/// the `Dequantization` of each shape in the `GeometryStore`. Shape groups are not
+/// supported since their members would each have their own constants.
+pub struct QuantizedVertexBuilder<Builder, Vertex> {
+    builder: Builder,
+    scratch: Geometry<Vertex>,
+    last: Option<Dequantization>,
+    overflowed: bool,
+}
+
+impl<Builder, Vertex: Quantize> QuantizedVertexBuilder<Builder, Vertex> {
+    pub fn new(builder: Builder) -> Self {
+        QuantizedVertexBuilder {
+            builder: builder,
+            scratch: Geometry::new(),
+            last: None,
+            overflowed: false,
+        }
+    }
+
+    // Moves the geometry tessellated into the scratch buffers to the output, unless the
+    // wrapped builder discarded it.
+    fn quantize_scratch(&mut self, discarded: bool, geom: &mut Geometry<Vertex::Quantized>) -> GeometryRanges<Vertex::Quantized> {
+        let vtx_offset = geom.vertices.len();
+        let idx_offset = geom.indices.len();
+
+        // The indices are offset by the vertices already in the output.
+        self.overflowed = discarded
+            || vtx_offset + self.scratch.vertices.len() > u16::max_value() as usize + 1;
+        if self.overflowed {
+            self.scratch.vertices.clear();
+            self.scratch.indices.clear();
+            return geometry_ranges(vtx_offset, idx_offset, Count { vertices: 0, indices: 0 });
+        }
+
+        let (vertices, dequantization) = quantize_vertices(&self.scratch.vertices);
+        geom.vertices.extend(vertices);
+        for &index in &self.scratch.indices {
+            geom.indices.push(index + vtx_offset as u16);
+        }
+        self.last = Some(dequantization);
+
+        let count = Count {
+            vertices: self.scratch.vertices.len() as u32,
+            indices: self.scratch.indices.len() as u32,
+        };
+        self.scratch.vertices.clear();
+        self.scratch.indices.clear();
+
+        return geometry_ranges(vtx_offset, idx_offset, count);
+    }
+}
+
+impl<PrimitiveId, Builder, Vertex> VertexBuilder<PrimitiveId, Vertex::Quantized> for QuantizedVertexBuilder<Builder, Vertex>
+where
+    Builder: VertexBuilder<PrimitiveId, Vertex>,
+    Vertex: Quantize,
+{
+    fn add_path(
+        &mut self,
+        path: &Path,
+        prim_id: PrimitiveId,
+        tolerance: f32,
+        geom: &mut Geometry<Vertex::Quantized>
+    ) -> GeometryRanges<Vertex::Quantized> {
+        self.builder.add_path(path, prim_id, tolerance, &mut self.scratch);
+        let discarded = self.builder.overflowed();
+        return self.quantize_scratch(discarded, geom);
+    }
+
+    fn add_circle(
+        &mut self,
+        center: Point,
+        radius: f32,
+        prim_id: FillPrimitiveId,
+        tolerance: f32,
+        geom: &mut Geometry<Vertex::Quantized>
+    ) -> GeometryRanges<Vertex::Quantized> {
+        self.builder.add_circle(center, radius, prim_id, tolerance, &mut self.scratch);
+        let discarded = self.builder.overflowed();
+        return self.quantize_scratch(discarded, geom);
+    }
+
+    fn add_ellipse(
+        &mut self,
+        ellipse: &Ellipse,
+        prim_id: PrimitiveId,
+        tolerance: f32,
+        geom: &mut Geometry<Vertex::Quantized>
+    ) -> GeometryRanges<Vertex::Quantized> {
+        self.builder.add_ellipse(ellipse, prim_id, tolerance, &mut self.scratch);
+        let discarded = self.builder.overflowed();
+        return self.quantize_scratch(discarded, geom);
+    }
+
+    fn add_rect(
+        &mut self,
+        rect: &Rect,
+        prim_id: PrimitiveId,
+        geom: &mut Geometry<Vertex::Quantized>
+    ) -> GeometryRanges<Vertex::Quantized> {
+        self.builder.add_rect(rect, prim_id, &mut self.scratch);
+        let discarded = self.builder.overflowed();
+        return self.quantize_scratch(discarded, geom);
+    }
+
+    fn add_rounded_rect(
+        &mut self,
+        rect: &RoundedRect,
+        prim_id: PrimitiveId,
+        tolerance: f32,
+        geom: &mut Geometry<Vertex::Quantized>
+    ) -> GeometryRanges<Vertex::Quantized> {
+        self.builder.add_rounded_rect(rect, prim_id, tolerance, &mut self.scratch);
+        let discarded = self.builder.overflowed();
+        return self.quantize_scratch(discarded, geom);
+    }
+
+    fn dequantization(&self) -> Option<Dequantization> { self.last }
+
+    fn overflowed(&self) -> bool { self.overflowed }
+
+    fn options_key(&self) -> u64 { self.builder.options_key() }
+}
+
+#[test]
+fn quantized_geometry() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(1024);
+
+    let ellipse = shapes.add_ellipse(Ellipse { center: point(100.0, 50.0), radii: vec2(10.0, 5.0) });
+    let rect_id = shapes.add_rect(rect(-1000.0, 0.0, 2000.0, 10.0));
+
+    for &shape in &[ShapeId::Ellipse(ellipse), ShapeId::Rect(rect_id)] {
+        batcher.push_item(PrimitiveParams {
+            z_index: 0,
+            shape: shape,
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(Pattern::Color(Color::black())),
+        }).unwrap();
+    }
+
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut QuantizedVertexBuilder::new(FillVertexBuilder::new()),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+    assert_eq!(cmds.len(), 2);
+
+    // Each shape is quantized relative to its own bounds.
+    let rect_dq = geom.dequantization(ShapeId::Rect(rect_id)).unwrap();
+    assert_eq!(rect_dq.offset, vec2(0.0, 5.0));
+    let ellipse_dq = geom.dequantization(ShapeId::Ellipse(ellipse)).unwrap();
+    let ellipse_range = geom.get(ShapeId::Ellipse(ellipse)).unwrap().vertices.range;
+    for vertex in &geom.geometry().vertices[ellipse_range.usize_range()] {
+        let p = ellipse_dq.dequantize_position(vertex.position);
+        let d = vec2((p.x - 100.0) / 10.0, (p.y - 50.0) / 5.0);
+        assert!((d.length() - 1.0).abs() < 0.01);
+    }
+
+    // The normal range grows to fit the longest normal.
+    let (quantized, dq) = quantize_vertices(&[
+        GpuStrokeVertex { position: [1.0, 2.0], normal: [0.0, -3.0], advancement: 0.0, path_length: 1.0, alpha: 1.0, prim_id: 0 },
+        GpuStrokeVertex { position: [3.0, 2.5], normal: [0.5, 0.5], advancement: 1.0, path_length: 1.0, alpha: 1.0, prim_id: 0 },
+    ]);
+    assert_eq!(dq.normal_scale, 3.0);
+    let p = dq.dequantize_position(quantized[1].position);
+    assert!((p - point(3.0, 2.5)).length() < 1e-4);
+    assert!((dq.dequantize_normal(quantized[0].normal) - vec2(0.0, -3.0)).length() < 1e-4);
+}
@@ -0,0 +1,107 @@
+use gfx;
+use gfx::Factory;
+use gfx::format::Formatted;
+
+pub use gfx_types::*;
+use post_process::{OffscreenTarget, ColorTexture};
+use core::math::IntRect;
+
+/// Copies the pixels of a rectangle of a color texture back to the CPU.
+///
+/// The copy goes through a download buffer and the encoder is flushed, so this stalls
+/// until the GPU has finished rendering into the texture. The result contains tightly
+/// packed RGBA8 rows, starting with the top row of the rectangle.
+pub fn read_pixels(
+    texture: &ColorTexture,
+    rect: IntRect,
+    factory: &mut GlFactory,
+    encoder: &mut CmdEncoder,
+    device: &mut GlDevice,
+) -> Vec<u8> {
+    let info = texture.get_info().to_image_info(0);
+    let x = rect.origin.x.max(0);
+    let y = rect.origin.y.max(0);
+    let width = rect.size.width.min(info.width as i32 - x).max(0);
+    let height = rect.size.height.min(info.height as i32 - y).max(0);
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+
+    let download = factory.create_download_buffer::<[u8; 4]>((width * height) as usize).unwrap();
+
+    // GL textures have their origin at the bottom-left corner.
+    let image = gfx::texture::RawImageInfo {
+        xoffset: x as u16,
+        yoffset: (info.height as i32 - y - height) as u16,
+        zoffset: 0,
+        width: width as u16,
+        height: height as u16,
+        depth: 0,
+        format: ColorFormat::get_format(),
+        mipmap: 0,
+    };
+    encoder.copy_texture_to_buffer_raw(texture.raw(), None, image, download.raw(), 0).unwrap();
+    encoder.flush(device);
+
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    {
+        let reader = factory.read_mapping(&download).unwrap();
+        for pixel in reader.iter() {
+            pixels.extend_from_slice(&pixel[..]);
+        }
+    }
+
+    flip_rows(&mut pixels, width as usize * 4);
+
+    return pixels;
+}
+
+impl OffscreenTarget {
+    /// Reads back a rectangle of the target, see `read_pixels`.
+    pub fn read_pixels(
+        &self,
+        rect: IntRect,
+        factory: &mut GlFactory,
+        encoder: &mut CmdEncoder,
+        device: &mut GlDevice,
+    ) -> Vec<u8> {
+        read_pixels(&self.surface, rect, factory, encoder, device)
+    }
+
+    /// Reads back the whole target, for example to save a screenshot or a thumbnail.
+    pub fn screenshot(
+        &self,
+        factory: &mut GlFactory,
+        encoder: &mut CmdEncoder,
+        device: &mut GlDevice,
+    ) -> Vec<u8> {
+        let rect = IntRect::new(
+            ::core::math::Point2D::new(0, 0),
+            ::core::math::IntSize::new(self.width as i32, self.height as i32),
+        );
+        self.read_pixels(rect, factory, encoder, device)
+    }
+}
+
+/// Reverses the order of the rows of an image.
+fn flip_rows(pixels: &mut [u8], row_len: usize) {
+    let num_rows = pixels.len() / row_len;
+    for row in 0..(num_rows / 2) {
+        let top = row * row_len;
+        let bottom = (num_rows - row - 1) * row_len;
+        for i in 0..row_len {
+            pixels.swap(top + i, bottom + i);
+        }
+    }
+}
+
+#[test]
+fn flip_pixel_rows() {
+    let mut pixels = vec![1, 1, 2, 2, 3, 3];
+    flip_rows(&mut pixels, 2);
+    assert_eq!(pixels, vec![3, 3, 2, 2, 1, 1]);
+
+    let mut pixels = vec![1, 2, 3, 4, 5, 6, 7, 8];
+    flip_rows(&mut pixels, 4);
+    assert_eq!(pixels, vec![5, 6, 7, 8, 1, 2, 3, 4]);
+}
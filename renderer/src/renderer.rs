@@ -1,12 +1,18 @@
 use gfx;
 use gfx::Factory;
+use gfx::traits::FactoryExt;
 
 use tessellation;
-use tessellation::geometry_builder::VertexConstructor;
+use tessellation::geometry_builder::{VertexConstructor, GeometryReceiver, GeometryBuilder, BuffersBuilder, VertexBuffers, VertexId, Count};
+use tessellation::geometry_builder::AntiAliasedVertex;
+use tessellation::basic_shapes::BorderRadii;
 use core::math::*;
 use buffer::*;
+use api::{AlphaMode, Pattern, ClearOps, Glow};
+use gpu_block::GpuAddress;
+use stats::{PassTimer, NoPassTimer, RenderPass};
 pub use gfx_types::*;
-use glsl::PRIM_BUFFER_LEN;
+use glsl::{PRIM_BUFFER_LEN, PRIM_ID_MASK, DEPTH_PRE_PASS_VERTEX_SHADER, DEPTH_PRE_PASS_FRAGMENT_SHADER, shader_source};
 
 use std;
 use std::mem;
@@ -14,6 +20,7 @@ use std::ops;
 
 pub type OpaquePso = Pso<opaque_fill_pipeline::Meta>;
 pub type TransparentPso = Pso<transparent_fill_pipeline::Meta>;
+pub type DepthPrePassPso = Pso<depth_pre_pass_pipeline::Meta>;
 
 gfx_defines!{
     constant Globals {
@@ -30,9 +37,16 @@ gfx_defines!{
     vertex GpuFillVertex {
         position: [f32; 2] = "a_position",
         normal: [f32; 2] = "a_normal",
+        alpha: f32 = "a_alpha", // Multiplied into the color, below 1.0 in the skirts of AaSkirtBuilder.
         prim_id: i32 = "a_prim_id", // An id pointing to the PrimData struct above.
     }
 
+    // Per-vertex data for the passes that only need positions, see PositionVertexConstructor.
+    vertex GpuPositionVertex {
+        position: [f32; 2] = "a_position",
+        prim_id: i32 = "a_prim_id",
+    }
+
     // Per fill primitive data.
     constant GpuFillPrimitive {
         color: [f32; 4] = "color",
@@ -40,6 +54,13 @@ gfx_defines!{
         local_transform: i32 = "local_transform",
         view_transform: i32 = "view_transform",
         width: f32 = "width",
+        offset: [f32; 2] = "offset", // Translation applied after the local transform.
+        pattern_kind: i32 = "pattern_kind", // One of the PATTERN_KIND_* constants.
+        pattern_id: i32 = "pattern_id", // The image of image patterns, -1 otherwise.
+        flags: i32 = "flags", // PRIMITIVE_FLAG_* bits.
+        root_transform: i32 = "root_transform", // Placement of the instance, applied before the view transform.
+        clip_id: i32 = "clip_id", // The GpuClip of the primitive, -1 if it isn't clipped.
+        pattern_transform: i32 = "pattern_transform", // From the local space to the space of the pattern.
     }
 
     // Per-vertex data.
@@ -47,9 +68,64 @@ gfx_defines!{
         position: [f32; 2] = "a_position",
         normal: [f32; 2] = "a_normal",
         advancement: f32 = "a_advancement",
+        path_length: f32 = "a_path_length", // Advancement at the end of the path, see set_path_length.
+        alpha: f32 = "a_alpha", // Multiplied into the color, below 1.0 in the skirts of AaSkirtBuilder.
         prim_id: i32 = "a_prim_id", // An id pointing to the PrimData struct above.
     }
 
+    // Compact version of GpuFillVertex, see the quantize module.
+    vertex GpuQuantizedFillVertex {
+        position: [gfx::format::I16Norm; 2] = "a_position",
+        normal: [gfx::format::I16Norm; 2] = "a_normal",
+        alpha: f32 = "a_alpha",
+        prim_id: i32 = "a_prim_id",
+    }
+
+    // Compact version of GpuStrokeVertex, see the quantize module.
+    vertex GpuQuantizedStrokeVertex {
+        position: [gfx::format::I16Norm; 2] = "a_position",
+        normal: [gfx::format::I16Norm; 2] = "a_normal",
+        advancement: f32 = "a_advancement",
+        path_length: f32 = "a_path_length",
+        alpha: f32 = "a_alpha",
+        prim_id: i32 = "a_prim_id",
+    }
+
+    // Maps the normalized positions and normals of quantized vertices back to world units.
+    constant GpuDequantization {
+        offset: [f32; 2] = "offset",
+        scale: [f32; 2] = "scale",
+        normal_scale: f32 = "normal_scale",
+    }
+
+    // An entry of the StyleSheet shared by the primitives, see PATTERN_KIND_SHARED.
+    constant GpuStyle {
+        color: [f32; 4] = "color",
+        stops: i32 = "stops", // Address of the stop table of gradients in u_gradient_stops, -1 for colors.
+        stop_count: i32 = "stop_count", // 0 for colors.
+        kind: i32 = "kind", // One of the STYLE_KIND_* constants.
+        start_angle: f32 = "start_angle", // Angle of the offset 0 of conic gradients.
+        center: [f32; 2] = "center", // Center of conic gradients in the local space of fills.
+        _padding: [f32; 2] = "_padding", // Rounds the size to the std140 array stride.
+    }
+
+    // Four words of the stop tables of the StyleSheet, see StyleSheet::take_gradient_stops.
+    constant GpuGradientStopWords {
+        words: [f32; 4] = "words",
+    }
+
+    // A rounded rectangle clipping the fill primitives that reference it, see GpuClip::rounded_rect.
+    constant GpuClip {
+        rect: [f32; 4] = "rect", // Center in xy, half size in zw.
+        radii: [f32; 4] = "radii", // Top-left, top-right, bottom-right and bottom-left.
+    }
+
+    // Per-instance data, for backends that can't derive the primitive from gl_InstanceID.
+    vertex GpuInstance {
+        prim_id: i32 = "a_instance_prim_id", // The primitive of the instance.
+        flags: i32 = "a_instance_flags", // Reserved, currently always 0.
+    }
+
     // Per stroke primitive data.
     constant GpuStrokePrimitive {
         color: [f32; 4] = "color",
@@ -57,6 +133,14 @@ gfx_defines!{
         local_transform: i32 = "local_transform",
         view_transform: i32 = "view_transform",
         width: f32 = "width",
+        end_color: [f32; 4] = "end_color", // Color at the end of the path.
+        flags: i32 = "flags", // PRIMITIVE_FLAG_* bits.
+        root_transform: i32 = "root_transform", // Placement of the instance, applied before the view transform.
+        glow: [f32; 2] = "glow", // Start of the falloff relative to the width and intensity, see set_glow.
+        pattern_transform: i32 = "pattern_transform", // From the local space to the space of the pattern.
+        pattern_kind: i32 = "pattern_kind", // One of the PATTERN_KIND_* constants.
+        pattern_id: i32 = "pattern_id", // The image of image patterns, -1 otherwise.
+        _padding: i32 = "_padding", // Rounds the size to the std140 array stride.
     }
 
     pipeline opaque_fill_pipeline {
@@ -66,15 +150,32 @@ gfx_defines!{
         constants: gfx::ConstantBuffer<Globals> = "Globals",
         transforms: gfx::ConstantBuffer<GpuTransform> = "u_transforms",
         primitives: gfx::ConstantBuffer<GpuFillPrimitive> = "u_primitives",
+        styles: gfx::ConstantBuffer<GpuStyle> = "u_styles",
+        gradient_stops: gfx::ConstantBuffer<GpuGradientStopWords> = "u_gradient_stops",
+        clips: gfx::ConstantBuffer<GpuClip> = "u_clips",
+    }
+
+    // Writes the depth of the opaque fills without shading them, see Layer::set_depth_pre_pass.
+    // The geometry is produced by PositionVertexConstructor, like for the picking pass.
+    pipeline depth_pre_pass_pipeline {
+        vbo: gfx::VertexBuffer<GpuPositionVertex> = (),
+        instances: gfx::InstanceBuffer<GpuInstance> = (),
+        out_depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
+        constants: gfx::ConstantBuffer<Globals> = "Globals",
+        transforms: gfx::ConstantBuffer<GpuTransform> = "u_transforms",
+        primitives: gfx::ConstantBuffer<GpuFillPrimitive> = "u_primitives",
     }
 
     pipeline transparent_fill_pipeline {
         vbo: gfx::VertexBuffer<GpuFillVertex> = (),
-        out_color: gfx::RenderTarget<ColorFormat> = "out_color",
+        out_color: gfx::BlendTarget<ColorFormat> = ("out_color", gfx::state::MASK_ALL, gfx::preset::blend::ALPHA),
         out_depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_TEST,
         constants: gfx::ConstantBuffer<Globals> = "Globals",
         transforms: gfx::ConstantBuffer<GpuTransform> = "u_transforms",
         primitives: gfx::ConstantBuffer<GpuFillPrimitive> = "u_primitives",
+        styles: gfx::ConstantBuffer<GpuStyle> = "u_styles",
+        gradient_stops: gfx::ConstantBuffer<GpuGradientStopWords> = "u_gradient_stops",
+        clips: gfx::ConstantBuffer<GpuClip> = "u_clips",
     }
 
     pipeline opaque_stroke_pipeline {
@@ -84,18 +185,127 @@ gfx_defines!{
         constants: gfx::ConstantBuffer<Globals> = "Globals",
         transforms: gfx::ConstantBuffer<GpuTransform> = "u_transforms",
         primitives: gfx::ConstantBuffer<GpuStrokePrimitive> = "u_primitives",
+        styles: gfx::ConstantBuffer<GpuStyle> = "u_styles",
+        gradient_stops: gfx::ConstantBuffer<GpuGradientStopWords> = "u_gradient_stops",
     }
 
-    pipeline transparent_stroke_pipeline {
+    pipeline instanced_opaque_fill_pipeline {
+        vbo: gfx::VertexBuffer<GpuFillVertex> = (),
+        instances: gfx::InstanceBuffer<GpuInstance> = (),
+        out_color: gfx::RenderTarget<ColorFormat> = "out_color",
+        out_depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
+        constants: gfx::ConstantBuffer<Globals> = "Globals",
+        transforms: gfx::ConstantBuffer<GpuTransform> = "u_transforms",
+        primitives: gfx::ConstantBuffer<GpuFillPrimitive> = "u_primitives",
+        styles: gfx::ConstantBuffer<GpuStyle> = "u_styles",
+        gradient_stops: gfx::ConstantBuffer<GpuGradientStopWords> = "u_gradient_stops",
+        clips: gfx::ConstantBuffer<GpuClip> = "u_clips",
+    }
+
+    pipeline instanced_opaque_stroke_pipeline {
         vbo: gfx::VertexBuffer<GpuStrokeVertex> = (),
+        instances: gfx::InstanceBuffer<GpuInstance> = (),
         out_color: gfx::RenderTarget<ColorFormat> = "out_color",
+        out_depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_WRITE,
+        constants: gfx::ConstantBuffer<Globals> = "Globals",
+        transforms: gfx::ConstantBuffer<GpuTransform> = "u_transforms",
+        primitives: gfx::ConstantBuffer<GpuStrokePrimitive> = "u_primitives",
+        styles: gfx::ConstantBuffer<GpuStyle> = "u_styles",
+        gradient_stops: gfx::ConstantBuffer<GpuGradientStopWords> = "u_gradient_stops",
+    }
+
+    pipeline transparent_stroke_pipeline {
+        vbo: gfx::VertexBuffer<GpuStrokeVertex> = (),
+        out_color: gfx::BlendTarget<ColorFormat> = ("out_color", gfx::state::MASK_ALL, gfx::preset::blend::ALPHA),
         out_depth: gfx::DepthTarget<DepthFormat> = gfx::preset::depth::LESS_EQUAL_TEST,
         constants: gfx::ConstantBuffer<Globals> = "Globals",
         transforms: gfx::ConstantBuffer<GpuTransform> = "u_transforms",
         primitives: gfx::ConstantBuffer<GpuStrokePrimitive> = "u_primitives",
+        styles: gfx::ConstantBuffer<GpuStyle> = "u_styles",
+        gradient_stops: gfx::ConstantBuffer<GpuGradientStopWords> = "u_gradient_stops",
+    }
+}
+
+/// The blend state of the transparent passes for an alpha mode.
+pub fn blend_state(mode: AlphaMode) -> gfx::state::Blend {
+    use gfx::state::{Blend, BlendValue, Equation, Factor};
+    let source = match mode {
+        AlphaMode::Straight => Factor::ZeroPlus(BlendValue::SourceAlpha),
+        AlphaMode::Premultiplied => Factor::One,
+    };
+    return Blend::new(Equation::Add, source, Factor::OneMinus(BlendValue::SourceAlpha));
+}
+
+/// Pipeline initializer for the opaque fill pass of layers that have a depth pre-pass.
+///
+/// The depth buffer already contains the depth of the front-most fill of each pixel, so
+/// only the fragments of that fill pass the test and nothing is shaded twice.
+pub fn opaque_fill_after_depth_pre_pass_init() -> opaque_fill_pipeline::Init<'static> {
+    opaque_fill_pipeline::Init {
+        out_depth: gfx::state::Depth {
+            fun: gfx::state::Comparison::Equal,
+            write: false,
+        },
+        .. opaque_fill_pipeline::new()
+    }
+}
+
+pub fn create_depth_pre_pass_pso(factory: &mut GlFactory) -> DepthPrePassPso {
+    let program = factory.link_program(
+        shader_source(DEPTH_PRE_PASS_VERTEX_SHADER).as_bytes(),
+        shader_source(DEPTH_PRE_PASS_FRAGMENT_SHADER).as_bytes(),
+    ).unwrap();
+
+    return factory.create_pipeline_from_program(
+        &program,
+        gfx::Primitive::TriangleList,
+        gfx::state::Rasterizer::new_fill(),
+        depth_pre_pass_pipeline::new(),
+    ).unwrap();
+}
+
+/// Pipeline initializer for the transparent fill pass using the blend factors of an alpha mode.
+pub fn transparent_fill_init(mode: AlphaMode) -> transparent_fill_pipeline::Init<'static> {
+    transparent_fill_pipeline::Init {
+        out_color: ("out_color", gfx::state::MASK_ALL, blend_state(mode)),
+        .. transparent_fill_pipeline::new()
     }
 }
 
+/// Pipeline initializer for the transparent stroke pass using the blend factors of an alpha mode.
+pub fn transparent_stroke_init(mode: AlphaMode) -> transparent_stroke_pipeline::Init<'static> {
+    transparent_stroke_pipeline::Init {
+        out_color: ("out_color", gfx::state::MASK_ALL, blend_state(mode)),
+        .. transparent_stroke_pipeline::new()
+    }
+}
+
+/// Values of the `pattern_kind` of `GpuFillPrimitive` and `GpuStrokePrimitive`.
+pub const PATTERN_KIND_COLOR: i32 = 0;
+pub const PATTERN_KIND_LINEAR_GRADIENT: i32 = 1;
+pub const PATTERN_KIND_IMAGE: i32 = 2;
+pub const PATTERN_KIND_VECTOR_IMAGE: i32 = 3;
+pub const PATTERN_KIND_CONIC_GRADIENT: i32 = 4;
+pub const PATTERN_KIND_SHARED: i32 = 5;
+
+/// Values of the `kind` of `GpuStyle`, which selects how the fill shaders compute the
+/// offset of a fragment in the stops of the style.
+pub const STYLE_KIND_COLOR: i32 = 0;
+pub const STYLE_KIND_LINEAR_GRADIENT: i32 = 1;
+pub const STYLE_KIND_CONIC_GRADIENT: i32 = 2;
+
+/// Bits of the `flags` of `GpuFillPrimitive` and `GpuStrokePrimitive`.
+pub const PRIMITIVE_FLAG_HIDDEN: i32 = 1;
+
+fn set_flag(flags: i32, flag: i32, value: bool) -> i32 {
+    if value { flags | flag } else { flags & !flag }
+}
+
+/// The error returned when setting a pattern that a kind of primitive can't be drawn with,
+/// for example a `Pattern::StrokeGradient` on a fill.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UnsupportedPattern;
+
 impl GpuFillPrimitive {
     pub fn new(
         color: [f32; 4],
@@ -109,6 +319,75 @@ impl GpuFillPrimitive {
             local_transform: local_transform.to_i32(),
             view_transform: view_transform.to_i32(),
             width: 0.0,
+            offset: [0.0, 0.0],
+            pattern_kind: PATTERN_KIND_COLOR,
+            pattern_id: -1,
+            flags: 0,
+            root_transform: 0,
+            clip_id: -1,
+            pattern_transform: 0,
+        }
+    }
+
+    /// Hidden primitives are collapsed by the vertex shaders, which is cheaper than
+    /// removing them from the batches and lets them be shown again by rewriting them.
+    pub fn set_visible(&mut self, visible: bool) {
+        self.flags = set_flag(self.flags, PRIMITIVE_FLAG_HIDDEN, !visible);
+    }
+
+    pub fn is_visible(&self) -> bool { self.flags & PRIMITIVE_FLAG_HIDDEN == 0 }
+
+    /// Clips the primitive to a rounded rectangle of the `u_clips` buffer, or removes its clip.
+    ///
+    /// The clip is evaluated by the fill fragment shader and its edges are anti-aliased by
+    /// reducing the alpha, so clipped primitives must be drawn in the transparent pass (see
+    /// `NodeStyle::clip`).
+    pub fn set_clip(&mut self, clip: Option<ClipId>) {
+        self.clip_id = clip.map_or(-1, |id| id.to_i32());
+    }
+
+    pub fn clip(&self) -> Option<ClipId> {
+        if self.clip_id < 0 { None } else { Some(ClipId::new(self.clip_id as u16)) }
+    }
+
+    /// Sets the pattern fields of the primitive.
+    ///
+    /// The color of gradients is their first stop and the one of images is white, until
+    /// the shaders support them. `Pattern::StrokeGradient` only applies to strokes and no
+    /// shader draws `Pattern::VectorImage` yet: the primitive is left unchanged and an
+    /// error returned.
+    pub fn set_pattern(&mut self, pattern: &Pattern, alpha_mode: AlphaMode) -> Result<(), UnsupportedPattern> {
+        if !GpuFillPrimitive::supports_pattern(pattern) {
+            return Err(UnsupportedPattern);
+        }
+
+        let white = [1.0, 1.0, 1.0, 1.0];
+        let (kind, id, color) = match *pattern {
+            Pattern::Color(color) => (PATTERN_KIND_COLOR, -1, alpha_mode.color(color)),
+            Pattern::LinearGradient(ref gradient) => {
+                let color = gradient.stops().first().map_or(white, |stop| alpha_mode.color(stop.color));
+                (PATTERN_KIND_LINEAR_GRADIENT, -1, color)
+            }
+            Pattern::ConicGradient(ref gradient) => {
+                let color = gradient.stops().first().map_or(white, |stop| alpha_mode.color(stop.color));
+                (PATTERN_KIND_CONIC_GRADIENT, -1, color)
+            }
+            Pattern::Image(ref image) => (PATTERN_KIND_IMAGE, image.image_id.to_i32(), white),
+            Pattern::Shared(id) => (PATTERN_KIND_SHARED, id.index() as i32, white),
+            Pattern::StrokeGradient(_) | Pattern::VectorImage(_) => { return Err(UnsupportedPattern); }
+        };
+        self.pattern_kind = kind;
+        self.pattern_id = id;
+        self.color = color;
+
+        return Ok(());
+    }
+
+    /// Whether fills can be drawn with a pattern, see `set_pattern`.
+    pub fn supports_pattern(pattern: &Pattern) -> bool {
+        match *pattern {
+            Pattern::StrokeGradient(_) | Pattern::VectorImage(_) => false,
+            _ => true,
         }
     }
 }
@@ -119,6 +398,15 @@ impl std::default::Default for GpuFillPrimitive {
     }
 }
 
+impl GpuInstance {
+    pub fn new(prim_id: u32, flags: i32) -> GpuInstance {
+        GpuInstance {
+            prim_id: prim_id as i32,
+            flags: flags,
+        }
+    }
+}
+
 impl GpuStrokePrimitive {
     pub fn new(
         color: [f32; 4],
@@ -132,8 +420,147 @@ impl GpuStrokePrimitive {
             local_transform: local_transform.to_i32(),
             view_transform: view_transform.to_i32(),
             width: 1.0,
+            end_color: color,
+            flags: 0,
+            root_transform: 0,
+            glow: [0.0, 0.0],
+            pattern_transform: 0,
+            pattern_kind: PATTERN_KIND_COLOR,
+            pattern_id: -1,
+            _padding: 0,
+        }
+    }
+
+    /// See `GpuFillPrimitive::set_visible`.
+    pub fn set_visible(&mut self, visible: bool) {
+        self.flags = set_flag(self.flags, PRIMITIVE_FLAG_HIDDEN, !visible);
+    }
+
+    pub fn is_visible(&self) -> bool { self.flags & PRIMITIVE_FLAG_HIDDEN == 0 }
+
+    /// Turns the primitive into the glow of a stroke which primitive has a width of
+    /// `stroke_width`.
+    ///
+    /// The glow is as wide as the stroke plus the radius of the glow, and its opacity
+    /// decreases from the edge of the stroke to its own edge.
+    pub fn set_glow(&mut self, glow: &Glow, stroke_width: f32, alpha_mode: AlphaMode) {
+        self.width = stroke_width + glow.radius;
+        self.color = alpha_mode.color(glow.color);
+        self.end_color = self.color;
+        self.pattern_kind = PATTERN_KIND_COLOR;
+        self.pattern_id = -1;
+        let inner = if self.width > 0.0 { stroke_width / self.width } else { 0.0 };
+        self.glow = [inner, glow.intensity.max(0.0).min(1.0)];
+    }
+
+    pub fn is_glow(&self) -> bool { self.glow[1] > 0.0 }
+
+    /// Sets the pattern fields and the colors of the primitive.
+    ///
+    /// `Pattern::StrokeGradient` interpolates between its colors along the path, and so do
+    /// the gradients of the style sheet. Like for fills, the color of the other gradients is
+    /// their first stop and the one of images is white, until the shaders support them.
+    pub fn set_pattern(&mut self, pattern: &Pattern, alpha_mode: AlphaMode) {
+        let white = [1.0, 1.0, 1.0, 1.0];
+        let (kind, id, color) = match *pattern {
+            Pattern::Color(color) => (PATTERN_KIND_COLOR, -1, alpha_mode.color(color)),
+            Pattern::StrokeGradient(ref gradient) => (PATTERN_KIND_COLOR, -1, alpha_mode.color(gradient.start)),
+            Pattern::LinearGradient(ref gradient) => {
+                let color = gradient.stops().first().map_or(white, |stop| alpha_mode.color(stop.color));
+                (PATTERN_KIND_LINEAR_GRADIENT, -1, color)
+            }
+            Pattern::ConicGradient(ref gradient) => {
+                let color = gradient.stops().first().map_or(white, |stop| alpha_mode.color(stop.color));
+                (PATTERN_KIND_CONIC_GRADIENT, -1, color)
+            }
+            Pattern::Image(ref image) => (PATTERN_KIND_IMAGE, image.image_id.to_i32(), white),
+            Pattern::VectorImage(ref image) => (PATTERN_KIND_VECTOR_IMAGE, image.image.to_i32(), white),
+            Pattern::Shared(id) => (PATTERN_KIND_SHARED, id.index() as i32, white),
+        };
+        self.pattern_kind = kind;
+        self.pattern_id = id;
+        self.color = color;
+        self.end_color = match *pattern {
+            Pattern::StrokeGradient(ref gradient) => alpha_mode.color(gradient.end),
+            _ => color,
+        };
+    }
+
+    /// Whether strokes can be drawn with a pattern.
+    ///
+    /// No shader draws `Pattern::VectorImage` yet, `set_pattern` only records its kind.
+    pub fn supports_pattern(pattern: &Pattern) -> bool {
+        match *pattern {
+            Pattern::VectorImage(_) => false,
+            _ => true,
+        }
+    }
+}
+
+/// Stores the length of the stroked path in its vertices so that the shaders can
+/// normalize the advancement.
+///
+/// `vertices` must contain the vertices of a single path, typically the ones produced
+/// by one call to the stroke tessellator.
+pub fn set_path_length(vertices: &mut [GpuStrokeVertex]) {
+    let mut length: f32 = 0.0;
+    for vertex in vertices.iter() {
+        length = length.max(vertex.advancement);
+    }
+    for vertex in vertices.iter_mut() {
+        vertex.path_length = length;
+    }
+}
+
+/// A geometry builder for strokes that writes the length of each tessellated path into
+/// its vertices (see `set_path_length`).
+///
+/// Without it the vertices have a `path_length` of zero and the stroke gradients don't
+/// interpolate along the path.
+pub struct StrokeBuffersBuilder<'l> {
+    builder: BuffersBuilder<'l, GpuStrokeVertex, tessellation::StrokeVertex, WithId<GpuStrokePrimitive>>,
+    vertex_offset: usize,
+}
+
+impl<'l> StrokeBuffersBuilder<'l> {
+    pub fn new(buffers: &'l mut VertexBuffers<GpuStrokeVertex>, prim_id: Id<GpuStrokePrimitive>) -> Self {
+        let vertex_offset = buffers.vertices.len();
+        StrokeBuffersBuilder {
+            builder: BuffersBuilder::new(buffers, WithId(prim_id)),
+            vertex_offset: vertex_offset,
         }
     }
+
+    pub fn overflowed(&self) -> bool { self.builder.overflowed() }
+}
+
+impl<'l> GeometryBuilder<tessellation::StrokeVertex> for StrokeBuffersBuilder<'l> {
+    fn begin_geometry(&mut self) {
+        self.vertex_offset = self.builder.buffers_mut().vertices.len();
+        self.builder.begin_geometry();
+    }
+
+    fn end_geometry(&mut self) -> Count {
+        let count = self.builder.end_geometry();
+        // Discarded geometries have been truncated, in which case the range is empty.
+        let vertices = &mut self.builder.buffers_mut().vertices;
+        let start = self.vertex_offset.min(vertices.len());
+        set_path_length(&mut vertices[start..]);
+
+        return count;
+    }
+
+    fn add_vertex(&mut self, vertex: tessellation::StrokeVertex) -> VertexId {
+        return self.builder.add_vertex(vertex);
+    }
+
+    fn add_triangle(&mut self, a: VertexId, b: VertexId, c: VertexId) {
+        self.builder.add_triangle(a, b, c);
+    }
+
+    fn abort_geometry(&mut self) {
+        self.builder.abort_geometry();
+    }
 }
 
 impl std::default::Default for GpuStrokePrimitive {
@@ -142,8 +569,58 @@ impl std::default::Default for GpuStrokePrimitive {
     }
 }
 
-
 pub type TransformId = Id<GpuTransform>;
+pub type ClipId = Id<GpuClip>;
+
+impl GpuClip {
+    /// A clip in the space the local transform and offset of the primitives map to, before
+    /// their root and view transforms. Clipping to a card or an avatar doesn't need a
+    /// stencil pass this way.
+    ///
+    /// The radii are clamped to half of the size of the rectangle.
+    pub fn rounded_rect(rect: &Rect, radii: &BorderRadii) -> GpuClip {
+        let max = rect.size.width.min(rect.size.height).abs() * 0.5;
+        let half_size = size(rect.size.width * 0.5, rect.size.height * 0.5);
+        GpuClip {
+            rect: [
+                rect.origin.x + half_size.width,
+                rect.origin.y + half_size.height,
+                half_size.width.abs(),
+                half_size.height.abs(),
+            ],
+            radii: [
+                radii.top_left.min(max),
+                radii.top_right.min(max),
+                radii.bottom_right.min(max),
+                radii.bottom_left.min(max),
+            ],
+        }
+    }
+
+    /// The signed distance from a point to the edge of the clip, negative inside.
+    ///
+    /// This is the function evaluated by the fill fragment shader.
+    pub fn distance(&self, p: Point) -> f32 {
+        let px = p.x - self.rect[0];
+        let py = p.y - self.rect[1];
+        // The radius of the corner of the quadrant the point is in, with y pointing down.
+        let r = match (px > 0.0, py > 0.0) {
+            (false, false) => self.radii[0],
+            (true, false) => self.radii[1],
+            (true, true) => self.radii[2],
+            (false, true) => self.radii[3],
+        };
+        let qx = px.abs() - self.rect[2] + r;
+        let qy = py.abs() - self.rect[3] + r;
+        let outside = vec2(qx.max(0.0), qy.max(0.0)).length();
+
+        return qx.max(qy).min(0.0) + outside - r;
+    }
+
+    pub fn contains_point(&self, p: Point) -> bool {
+        self.distance(p) <= 0.0
+    }
+}
 
 impl std::default::Default for GpuTransform {
     fn default() -> Self {
@@ -170,8 +647,158 @@ impl GpuTransform {
     }
 }
 
+// The structs generated by gfx_defines can't be annotated with derives, so their serde
+// implementations go through remote definitions.
+
+#[cfg(feature = "serialization")]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "GpuTransform")]
+struct GpuTransformDef {
+    transform: [[f32; 4]; 4],
+}
+
+#[cfg(feature = "serialization")]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "GpuFillVertex")]
+struct GpuFillVertexDef {
+    position: [f32; 2],
+    normal: [f32; 2],
+    alpha: f32,
+    prim_id: i32,
+}
+
+#[cfg(feature = "serialization")]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "GpuFillPrimitive")]
+struct GpuFillPrimitiveDef {
+    color: [f32; 4],
+    z_index: f32,
+    local_transform: i32,
+    view_transform: i32,
+    width: f32,
+    offset: [f32; 2],
+    pattern_kind: i32,
+    pattern_id: i32,
+    flags: i32,
+    root_transform: i32,
+    clip_id: i32,
+    pattern_transform: i32,
+}
+
+#[cfg(feature = "serialization")]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "GpuStrokeVertex")]
+struct GpuStrokeVertexDef {
+    position: [f32; 2],
+    normal: [f32; 2],
+    advancement: f32,
+    path_length: f32,
+    alpha: f32,
+    prim_id: i32,
+}
+
+#[cfg(feature = "serialization")]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "GpuInstance")]
+struct GpuInstanceDef {
+    prim_id: i32,
+    flags: i32,
+}
+
+#[cfg(feature = "serialization")]
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "GpuStrokePrimitive")]
+struct GpuStrokePrimitiveDef {
+    color: [f32; 4],
+    z_index: f32,
+    local_transform: i32,
+    view_transform: i32,
+    width: f32,
+    end_color: [f32; 4],
+    flags: i32,
+    root_transform: i32,
+    glow: [f32; 2],
+    pattern_transform: i32,
+    pattern_kind: i32,
+    pattern_id: i32,
+    _padding: i32,
+}
+
+#[cfg(feature = "serialization")]
+macro_rules! impl_serde_with_remote {
+    ($ty:ident, $def:ident) => {
+        impl ::serde::Serialize for $ty {
+            fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                $def::serialize(self, serializer)
+            }
+        }
+
+        impl<'de> ::serde::Deserialize<'de> for $ty {
+            fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                $def::deserialize(deserializer)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serialization")]
+impl_serde_with_remote!(GpuTransform, GpuTransformDef);
+#[cfg(feature = "serialization")]
+impl_serde_with_remote!(GpuFillVertex, GpuFillVertexDef);
+#[cfg(feature = "serialization")]
+impl_serde_with_remote!(GpuFillPrimitive, GpuFillPrimitiveDef);
+#[cfg(feature = "serialization")]
+impl_serde_with_remote!(GpuStrokeVertex, GpuStrokeVertexDef);
+#[cfg(feature = "serialization")]
+impl_serde_with_remote!(GpuStrokePrimitive, GpuStrokePrimitiveDef);
+#[cfg(feature = "serialization")]
+impl_serde_with_remote!(GpuInstance, GpuInstanceDef);
+
 pub type FillPrimitiveId = Id<GpuFillPrimitive>;
 pub type StrokePrimitiveId = Id<GpuStrokePrimitive>;
+/// Fill primitives allocated in a paged `BufferStore`.
+pub type FillPrimitiveElement = BufferElement<GpuFillPrimitive>;
+
+/// The value of the `prim_id` attribute of the vertices.
+///
+/// The attribute is a signed integer that the vertex shaders offset with `gl_InstanceID`,
+/// so indices and addresses are packed with the sign bit clear and decoded with
+/// `PRIM_ID_MASK` on both sides, instead of being cast to `i32` and read back as negative
+/// offsets when they are too large.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PackedPrimId(i32);
+
+impl PackedPrimId {
+    /// Panics if the index doesn't fit in the bits of `PRIM_ID_MASK`.
+    pub fn encode(index: u32) -> Self {
+        assert!(index & !PRIM_ID_MASK == 0, "Primitive index {} out of range", index);
+        let packed = PackedPrimId(index as i32);
+        debug_assert_eq!(packed.decode(), index);
+        return packed;
+    }
+
+    pub fn from_id<T>(id: Id<T>) -> Self { PackedPrimId::encode(id.to_u16() as u32) }
+
+    /// Packs the address of a primitive stored in a `GpuMemory`.
+    pub fn from_address(address: GpuAddress) -> Self { PackedPrimId::encode(address.0) }
+
+    /// Reads back the value of a vertex attribute.
+    pub fn from_i32(value: i32) -> Self {
+        debug_assert!(value >= 0, "Invalid packed primitive id {}", value);
+        PackedPrimId(value)
+    }
+
+    pub fn decode(&self) -> u32 { self.0 as u32 & PRIM_ID_MASK }
+
+    /// Panics if the index isn't the one of an `Id`.
+    pub fn to_id<T>(&self) -> Id<T> {
+        let index = self.decode();
+        assert!(index <= u16::max_value() as u32);
+        return Id::new(index as u16);
+    }
+
+    pub fn to_i32(&self) -> i32 { self.0 }
+}
 
 /// This vertex constructor forwards the positions and normals provided by the
 /// tessellators and add a shape id.
@@ -188,7 +815,28 @@ impl VertexConstructor<tessellation::FillVertex, GpuFillVertex> for WithId<GpuFi
         GpuFillVertex {
             position: vertex.position.to_array(),
             normal: vertex.normal.to_array(),
-            prim_id: self.0.to_i32(),
+            alpha: 1.0,
+            prim_id: PackedPrimId::from_id(self.0).to_i32(),
+        }
+    }
+}
+
+// The vertices of AaSkirtBuilder, which fade out on the outer side of the skirt.
+impl VertexConstructor<AntiAliasedVertex<tessellation::FillVertex>, GpuFillVertex> for WithId<GpuFillPrimitive> {
+    fn new_vertex(&mut self, vertex: AntiAliasedVertex<tessellation::FillVertex>) -> GpuFillVertex {
+        assert!(!vertex.alpha.is_nan());
+        let gpu_vertex = <Self as VertexConstructor<tessellation::FillVertex, GpuFillVertex>>::new_vertex(self, vertex.vertex);
+        return GpuFillVertex { alpha: vertex.alpha, .. gpu_vertex };
+    }
+}
+
+impl VertexConstructor<tessellation::FillVertex, GpuPositionVertex> for WithId<GpuFillPrimitive> {
+    fn new_vertex(&mut self, vertex: tessellation::FillVertex) -> GpuPositionVertex {
+        assert!(!vertex.position.x.is_nan());
+        assert!(!vertex.position.y.is_nan());
+        GpuPositionVertex {
+            position: vertex.position.to_array(),
+            prim_id: PackedPrimId::from_id(self.0).to_i32(),
         }
     }
 }
@@ -204,11 +852,22 @@ impl VertexConstructor<tessellation::StrokeVertex, GpuStrokeVertex> for WithId<G
             position: vertex.position.to_array(),
             normal: vertex.normal.to_array(),
             advancement: vertex.advancement,
-            prim_id: self.0.to_i32(),
+            // Written once the path is tessellated, see StrokeBuffersBuilder.
+            path_length: 0.0,
+            alpha: 1.0,
+            prim_id: PackedPrimId::from_id(self.0).to_i32(),
         }
     }
 }
 
+impl VertexConstructor<AntiAliasedVertex<tessellation::StrokeVertex>, GpuStrokeVertex> for WithId<GpuStrokePrimitive> {
+    fn new_vertex(&mut self, vertex: AntiAliasedVertex<tessellation::StrokeVertex>) -> GpuStrokeVertex {
+        assert!(!vertex.alpha.is_nan());
+        let gpu_vertex = <Self as VertexConstructor<tessellation::StrokeVertex, GpuStrokeVertex>>::new_vertex(self, vertex.vertex);
+        return GpuStrokeVertex { alpha: vertex.alpha, .. gpu_vertex };
+    }
+}
+
 pub enum SurfaceFormat {
     RgbaU8,
     AlphaU8,
@@ -220,28 +879,87 @@ pub struct RenderTarget {
     pub depth: DepthTarget,
 }
 
+impl RenderTarget {
+    /// Initializes the target before a pass, see `Layer::set_clear`.
+    ///
+    /// The clear color is converted with the alpha mode that the passes blend with.
+    pub fn clear(&self, ops: ClearOps, alpha_mode: AlphaMode, encoder: &mut CmdEncoder) {
+        if let Some(color) = ops.color {
+            encoder.clear(&self.color, alpha_mode.color(color));
+        }
+        if let Some(depth) = ops.depth {
+            encoder.clear_depth(&self.depth, depth);
+        }
+    }
+}
+
 pub struct GpuGeometry<T> {
     pub vbo: Vbo<T>,
     pub ibo: IndexSlice,
 }
 
 
+/// The GPU side of a `BufferStore`.
+///
+/// Uploads are staged: `begin_upload` starts recording, `queue_upload` and `queue_store`
+/// copy the data into a contiguous staging area and `flush_uploads` writes it into a single
+/// upload buffer and records one buffer-to-buffer copy per queued range, instead of many
+/// small buffer updates.
+///
+/// The upload buffers are N-buffered (see `with_frames_in_flight` and `end_frame`), so that
+/// the data of the next frame can be written while the GPU is still copying the data of the
+/// previous ones.
 pub struct GpuBufferStore<Primitive> {
     buffers: Vec<BufferObject<Primitive>>,
     role: gfx::buffer::Role,
     usage: gfx::memory::Usage,
+    staging: Vec<Primitive>,
+    pending_uploads: Vec<PendingUpload>,
+    upload_buffers: FrameRing<Option<BufferObject<Primitive>>>,
+    // Where the next flush writes in the upload buffer of the current frame. Each flush
+    // gets its own region, the copies recorded by the previous ones read theirs later.
+    upload_offset: usize,
+}
+
+// A range of the staging data to copy into one of the buffers.
+struct PendingUpload {
+    buffer: usize,
+    dst_offset: usize,
+    src_offset: usize,
+    len: usize,
 }
 
 impl<Primitive> GpuBufferStore<Primitive>
 where  Primitive: Copy + Default + gfx::traits::Pod {
     pub fn new(role: gfx::buffer::Role, usage: gfx::memory::Usage) -> Self {
+        GpuBufferStore::with_frames_in_flight(role, usage, 1)
+    }
+
+    pub fn with_frames_in_flight(
+        role: gfx::buffer::Role,
+        usage: gfx::memory::Usage,
+        frames_in_flight: usize,
+    ) -> Self {
         GpuBufferStore {
             buffers: Vec::new(),
             role: role,
             usage: usage,
+            staging: Vec::new(),
+            pending_uploads: Vec::new(),
+            upload_buffers: FrameRing::from_fn(frames_in_flight, || None),
+            upload_offset: 0,
         }
     }
 
+    /// Moves on to the upload buffer of the next frame.
+    ///
+    /// The upload buffer of the current frame is not written to again before `frames_in_flight`
+    /// frames have passed.
+    pub fn end_frame(&mut self) {
+        self.upload_buffers.advance();
+        self.upload_offset = 0;
+    }
+
     pub fn new_uniforms() -> Self {
         GpuBufferStore::new(gfx::buffer::Role::Constant, gfx::memory::Usage::Dynamic)
     }
@@ -250,23 +968,145 @@ where  Primitive: Copy + Default + gfx::traits::Pod {
         GpuBufferStore::new(gfx::buffer::Role::Vertex, gfx::memory::Usage::Dynamic)
     }
 
+    /// Uploads all the pages of the cpu store.
     pub fn update(
         &mut self,
         cpu: &mut BufferStore<Primitive>,
         factory: &mut GlFactory,
         queue: &mut CmdEncoder
     ) {
-        for i in 0..cpu.buffers.len() {
-            if i >= self.buffers.len() {
+        self.begin_upload();
+        self.queue_store(cpu);
+        self.flush_uploads(factory, queue);
+    }
+
+    /// Discards the uploads that were queued and not flushed.
+    pub fn begin_upload(&mut self) {
+        self.staging.clear();
+        self.pending_uploads.clear();
+    }
+
+    /// Queues the upload of some data at a given offset in one of the buffers.
+    pub fn queue_upload(&mut self, buffer: BufferId<Primitive>, offset: usize, data: &[Primitive]) {
+        if data.is_empty() {
+            return;
+        }
+        assert!(offset + data.len() <= PRIM_BUFFER_LEN);
+
+        // Merge with the previous upload if it ends where this one starts.
+        let src_offset = self.staging.len();
+        self.staging.extend_from_slice(data);
+        if let Some(last) = self.pending_uploads.last_mut() {
+            if last.buffer == buffer.index() && last.dst_offset + last.len == offset {
+                last.len += data.len();
+                return;
+            }
+        }
+
+        self.pending_uploads.push(PendingUpload {
+            buffer: buffer.index(),
+            dst_offset: offset,
+            src_offset: src_offset,
+            len: data.len(),
+        });
+    }
+
+    /// Queues the upload of a range of elements of the cpu store.
+    pub fn queue_range(&mut self, cpu: &BufferStore<Primitive>, range: BufferRange<Primitive>) {
+        self.queue_upload(range.buffer, range.range.start_index(), &cpu[range]);
+    }
+
+    /// Queues the upload of all the pages of the cpu store.
+    pub fn queue_store(&mut self, cpu: &BufferStore<Primitive>) {
+        for (id, data) in cpu.pages() {
+            self.queue_upload(id, 0, data);
+        }
+    }
+
+    /// Writes the queued data into the upload buffer and records the copies into the
+    /// destination buffers, creating the latter as needed.
+    ///
+    /// The copies are executed when the encoder is flushed, so flushing the uploads several
+    /// times in a frame writes each batch to a separate region of the frame's upload buffer.
+    pub fn flush_uploads(&mut self, factory: &mut GlFactory, queue: &mut CmdEncoder) {
+        self.flush_uploads_timed(factory, queue, &mut NoPassTimer);
+    }
+
+    /// Same as `flush_uploads`, timing the flush as the `RenderPass::Uploads` pass.
+    ///
+    /// Only the CPU time spent writing the upload buffer and recording the copies is
+    /// measured, see the `stats` module.
+    pub fn flush_uploads_timed<Timer: PassTimer>(
+        &mut self,
+        factory: &mut GlFactory,
+        queue: &mut CmdEncoder,
+        timer: &mut Timer,
+    ) {
+        if self.pending_uploads.is_empty() {
+            return;
+        }
+
+        timer.begin_pass_timer(RenderPass::Uploads);
+
+        for upload in &self.pending_uploads {
+            while upload.buffer >= self.buffers.len() {
                 let buffer = factory.create_buffer(
                     PRIM_BUFFER_LEN,
                     self.role,
                     self.usage,
-                    gfx::memory::Bind::empty(),
+                    gfx::memory::TRANSFER_DST,
                 ).unwrap();
                 self.buffers.push(buffer);
             }
-            queue.update_buffer(&self.buffers[i], cpu.buffers[i].as_slice(), 0).unwrap();
+        }
+
+        let capacity = self.upload_buffers.current().as_ref().map(|buffer| buffer.len());
+        let (new_buffer_len, offset) = upload_region(capacity, self.upload_offset, self.staging.len());
+        if let Some(len) = new_buffer_len {
+            // The copies recorded earlier in the frame keep the previous buffer alive.
+            *self.upload_buffers.current_mut() = Some(factory.create_upload_buffer(len).unwrap());
+        }
+        self.upload_offset = offset + self.staging.len();
+
+        let upload_buffer = self.upload_buffers.current().as_ref().unwrap();
+        {
+            let mut writer = factory.write_mapping(upload_buffer).unwrap();
+            for (dst, src) in writer[offset..].iter_mut().zip(self.staging.iter()) {
+                *dst = *src;
+            }
+        }
+
+        for upload in self.pending_uploads.drain(..) {
+            queue.copy_buffer(
+                upload_buffer,
+                &self.buffers[upload.buffer],
+                offset + upload.src_offset,
+                upload.dst_offset,
+                upload.len,
+            ).unwrap();
+        }
+        self.staging.clear();
+
+        timer.end_pass_timer(RenderPass::Uploads);
+    }
+
+    /// Number of elements waiting to be flushed.
+    pub fn pending_upload_len(&self) -> usize { self.staging.len() }
+
+    /// Number of copies that the next flush will record.
+    pub fn pending_upload_count(&self) -> usize { self.pending_uploads.len() }
+}
+
+// Finds where to write `len` elements in an upload buffer of `capacity` elements (None if
+// there is no buffer yet), which is used up to `offset`. Returns the length of the buffer to
+// allocate if it doesn't fit, and the offset of the region.
+fn upload_region(capacity: Option<usize>, offset: usize, len: usize) -> (Option<usize>, usize) {
+    match capacity {
+        Some(capacity) if offset + len <= capacity => (None, offset),
+        _ => {
+            // Grow geometrically to avoid reallocating every time a few elements are added.
+            let len = ::std::cmp::max(len, PRIM_BUFFER_LEN).max(capacity.unwrap_or(0) * 2).next_power_of_two();
+            (Some(len), 0)
         }
     }
 }
@@ -284,7 +1124,257 @@ impl<T> ops::IndexMut<BufferId<T>> for GpuBufferStore<T> {
     }
 }
 
+/// Uploads the chunks produced by a `StreamingBuilder` directly into GPU buffers so that
+/// large meshes don't have to be held in memory before being submitted.
+///
+/// The buffers must be large enough to contain the whole geometry, see
+/// `create_streaming_buffers`.
+pub struct GpuGeometryUploader<'l, Vertex: 'l> {
+    pub vbo: &'l BufferObject<Vertex>,
+    pub ibo: &'l BufferObject<u16>,
+    pub queue: &'l mut CmdEncoder,
+}
+
+impl<'l, Vertex> GeometryReceiver<Vertex, u16> for GpuGeometryUploader<'l, Vertex>
+where Vertex: 'l + gfx::traits::Pod {
+    fn receive_vertices(&mut self, first_vertex: u32, vertices: &[Vertex]) {
+        self.queue.update_buffer(self.vbo, vertices, first_vertex as usize).unwrap();
+    }
+
+    fn receive_indices(&mut self, first_index: u32, indices: &[u16]) {
+        self.queue.update_buffer(self.ibo, indices, first_index as usize).unwrap();
+    }
+}
+
+/// Creates a vertex and an index buffer to be filled with a `GpuGeometryUploader`.
+pub fn create_streaming_buffers<Vertex>(
+    factory: &mut GlFactory,
+    max_vertices: usize,
+    max_indices: usize,
+) -> (BufferObject<Vertex>, BufferObject<u16>)
+where Vertex: gfx::traits::Pod {
+    let vbo = factory.create_buffer(
+        max_vertices,
+        gfx::buffer::Role::Vertex,
+        gfx::memory::Usage::Dynamic,
+        gfx::memory::Bind::empty(),
+    ).unwrap();
+    let ibo = factory.create_buffer(
+        max_indices,
+        gfx::buffer::Role::Index,
+        gfx::memory::Usage::Dynamic,
+        gfx::memory::Bind::empty(),
+    ).unwrap();
+    return (vbo, ibo);
+}
+
 pub fn create_index_buffer(factory: &mut GlFactory, data: &[u16]) -> Ibo {
     use gfx::IntoIndexBuffer;
     return data.into_index_buffer(factory);
 }
+
+#[test]
+fn stroke_gradient() {
+    use api::{Color, ColorId, PatternId, StrokeGradient};
+
+    use path::Path;
+    use path_builder::*;
+    use tessellation::{StrokeTessellator, StrokeOptions};
+
+    let mut builder = Path::builder();
+    builder.move_to(point(0.0, 0.0));
+    builder.line_to(point(10.0, 0.0));
+    builder.line_to(point(10.0, 5.0));
+    let first = builder.build();
+    let mut builder = Path::builder();
+    builder.move_to(point(0.0, 0.0));
+    builder.line_to(point(0.0, 3.0));
+    let second = builder.build();
+
+    // Each path gets its own length, written at tessellation time.
+    let mut geom = VertexBuffers::new();
+    let mut tessellator = StrokeTessellator::new();
+    let first_count = tessellator.tessellate_path(
+        first.path_iter(),
+        &StrokeOptions::default(),
+        &mut StrokeBuffersBuilder::new(&mut geom, Id::new(1)),
+    );
+    tessellator.tessellate_path(
+        second.path_iter(),
+        &StrokeOptions::default(),
+        &mut StrokeBuffersBuilder::new(&mut geom, Id::new(2)),
+    );
+    let (first_vertices, second_vertices) = geom.vertices.split_at(first_count.vertices as usize);
+    assert!(!first_vertices.is_empty());
+    assert!(!second_vertices.is_empty());
+    assert!(first_vertices.iter().all(|v| v.path_length == 15.0));
+    assert!(second_vertices.iter().all(|v| v.path_length == 3.0));
+
+    let mut prim = GpuStrokePrimitive::default();
+    prim.set_pattern(
+        &Pattern::StrokeGradient(StrokeGradient { start: Color::black(), end: Color::white() }),
+        AlphaMode::Straight,
+    );
+    assert_eq!(prim.color, [0.0, 0.0, 0.0, 1.0]);
+    assert_eq!(prim.end_color, [1.0, 1.0, 1.0, 1.0]);
+
+    prim.set_pattern(&Pattern::Color(Color::black()), AlphaMode::Straight);
+    assert_eq!(prim.end_color, prim.color);
+
+    // Strokes read shared styles from the style sheet, like fills.
+    prim.set_pattern(&Pattern::Shared(PatternId::Color(ColorId::new(3))), AlphaMode::Straight);
+    assert_eq!(prim.pattern_kind, PATTERN_KIND_SHARED);
+    assert_eq!(prim.pattern_id, 3);
+    prim.set_glow(&Glow { radius: 2.0, color: Color::white(), intensity: 1.0 }, 1.0, AlphaMode::Straight);
+    assert_eq!(prim.pattern_kind, PATTERN_KIND_COLOR);
+
+    // Stroke gradients don't apply to fills.
+    let gradient = Pattern::StrokeGradient(StrokeGradient { start: Color::black(), end: Color::white() });
+    let mut fill = GpuFillPrimitive::default();
+    assert_eq!(fill.set_pattern(&gradient, AlphaMode::Straight), Err(UnsupportedPattern));
+    assert_eq!(fill.color, GpuFillPrimitive::default().color);
+    assert_eq!(fill.set_pattern(&Pattern::Color(Color::black()), AlphaMode::Straight), Ok(()));
+}
+
+#[test]
+fn aa_skirt_alpha() {
+    use tessellation::basic_shapes::fill_rectangle;
+    use tessellation::geometry_builder::AaSkirtBuilder;
+
+    let mut geom: VertexBuffers<GpuFillVertex> = VertexBuffers::new();
+    {
+        let mut builder = BuffersBuilder::new(&mut geom, WithId(FillPrimitiveId::new(2)));
+        fill_rectangle(&rect(0.0, 0.0, 10.0, 10.0), &mut AaSkirtBuilder::new(1.0, &mut builder));
+    }
+
+    // The rectangle is opaque and the outer side of its skirt transparent.
+    let alphas: Vec<f32> = geom.vertices.iter().map(|v| v.alpha).collect();
+    assert_eq!(alphas, vec![1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0]);
+    assert!(geom.vertices.iter().all(|v| v.prim_id == 2));
+
+    // Vertices that don't come from a skirt are opaque.
+    let mut geom: VertexBuffers<GpuFillVertex> = VertexBuffers::new();
+    fill_rectangle(&rect(0.0, 0.0, 10.0, 10.0), &mut BuffersBuilder::new(&mut geom, WithId(FillPrimitiveId::new(2))));
+    assert!(geom.vertices.iter().all(|v| v.alpha == 1.0));
+}
+
+#[test]
+fn upload_regions() {
+    // Successive flushes of a frame write to separate regions.
+    let (new_buffer, first) = upload_region(None, 0, 10);
+    assert_eq!((new_buffer, first), (Some(PRIM_BUFFER_LEN), 0));
+    let capacity = new_buffer.unwrap();
+    assert_eq!(upload_region(Some(capacity), 10, 20), (None, 10));
+    // A batch that doesn't fit in the rest of the buffer gets a new one.
+    assert_eq!(upload_region(Some(capacity), 30, capacity), (Some(capacity * 2), 0));
+}
+
+#[test]
+fn every_pattern() {
+    use api::{Color, ColorId, PatternId, GradientStop, LinearGradient, ConicGradient, StrokeGradient};
+    use api::{ImagePattern, VectorImagePattern};
+
+    let stops = vec![
+        GradientStop { color: Color::black(), d: 0.0 },
+        GradientStop { color: Color::white(), d: 1.0 },
+    ];
+    let patterns = [
+        Pattern::Color(Color::white()),
+        Pattern::Image(ImagePattern { image_id: Id::new(1), rect: rect(0.0, 0.0, 1.0, 1.0), is_opaque: true }),
+        Pattern::LinearGradient(LinearGradient::new(stops.clone())),
+        Pattern::ConicGradient(ConicGradient::new(point(0.0, 0.0), Radians::new(0.0), stops.clone())),
+        Pattern::StrokeGradient(StrokeGradient { start: Color::black(), end: Color::white() }),
+        Pattern::VectorImage(VectorImagePattern { image: Id::new(2), tile: rect(0.0, 0.0, 1.0, 1.0), is_opaque: true }),
+        Pattern::Shared(PatternId::Color(ColorId::new(0))),
+    ];
+
+    let white = [1.0, 1.0, 1.0, 1.0];
+    let black = [0.0, 0.0, 0.0, 1.0];
+    // What the shaders draw each pattern with: the pattern kind and id, the color and for
+    // strokes the end color. None for the patterns that are rejected.
+    let expected = [
+        (Some((PATTERN_KIND_COLOR, -1, white)), Some((PATTERN_KIND_COLOR, -1, white, white))),
+        (Some((PATTERN_KIND_IMAGE, 1, white)), Some((PATTERN_KIND_IMAGE, 1, white, white))),
+        (Some((PATTERN_KIND_LINEAR_GRADIENT, -1, black)), Some((PATTERN_KIND_LINEAR_GRADIENT, -1, black, black))),
+        (Some((PATTERN_KIND_CONIC_GRADIENT, -1, black)), Some((PATTERN_KIND_CONIC_GRADIENT, -1, black, black))),
+        (None, Some((PATTERN_KIND_COLOR, -1, black, white))),
+        // Vector images have no shader path, the batchers reject them.
+        (None, None),
+        (Some((PATTERN_KIND_SHARED, 0, white)), Some((PATTERN_KIND_SHARED, 0, white, white))),
+    ];
+
+    for (pattern, &(fill_expected, stroke_expected)) in patterns.iter().zip(expected.iter()) {
+        let mut fill = GpuFillPrimitive::default();
+        let result = fill.set_pattern(pattern, AlphaMode::Straight);
+        assert_eq!(GpuFillPrimitive::supports_pattern(pattern), fill_expected.is_some());
+        match fill_expected {
+            Some((kind, id, color)) => {
+                assert_eq!(result, Ok(()));
+                assert_eq!((fill.pattern_kind, fill.pattern_id, fill.color), (kind, id, color));
+            }
+            None => {
+                assert_eq!(result, Err(UnsupportedPattern));
+                assert_eq!(fill.pattern_kind, PATTERN_KIND_COLOR);
+            }
+        }
+
+        let mut stroke = GpuStrokePrimitive::default();
+        stroke.set_pattern(pattern, AlphaMode::Straight);
+        assert_eq!(GpuStrokePrimitive::supports_pattern(pattern), stroke_expected.is_some());
+        if let Some((kind, id, color, end_color)) = stroke_expected {
+            assert_eq!(
+                (stroke.pattern_kind, stroke.pattern_id, stroke.color, stroke.end_color),
+                (kind, id, color, end_color)
+            );
+        }
+    }
+}
+
+#[test]
+fn rounded_rect_clip() {
+    let clip = GpuClip::rounded_rect(
+        &rect(0.0, 0.0, 100.0, 50.0),
+        &BorderRadii::new(10.0, 0.0, 40.0, 10.0),
+    );
+    assert_eq!(clip.rect, [50.0, 25.0, 50.0, 25.0]);
+    // The radii are in clockwise order from the top-left corner, and the bottom-left one
+    // is clamped to half of the height.
+    assert_eq!(clip.radii, [10.0, 0.0, 10.0, 25.0]);
+
+    assert!(clip.contains_point(point(50.0, 25.0)));
+    assert!((clip.distance(point(50.0, 0.0)) - 0.0).abs() < 1e-5);
+    assert!((clip.distance(point(50.0, -5.0)) - 5.0).abs() < 1e-5);
+    // The corners are rounded, except the square top-right one.
+    assert!(!clip.contains_point(point(1.0, 1.0)));
+    assert!(clip.contains_point(point(99.5, 0.5)));
+    assert!(!clip.contains_point(point(98.0, 48.0)));
+    assert!(!clip.contains_point(point(3.0, 47.0)));
+    assert!(clip.contains_point(point(10.0, 10.0)));
+
+    let mut prim = GpuFillPrimitive::default();
+    assert_eq!(prim.clip(), None);
+    prim.set_clip(Some(ClipId::new(3)));
+    assert_eq!(prim.clip_id, 3);
+    assert_eq!(prim.clip(), Some(ClipId::new(3)));
+    prim.set_clip(None);
+    assert_eq!(prim.clip_id, -1);
+}
+
+#[test]
+fn packed_prim_id() {
+    let id: FillPrimitiveId = Id::new(u16::max_value());
+    let packed = PackedPrimId::from_id(id);
+    assert_eq!(packed.decode(), 65535);
+    assert_eq!(packed.to_id::<GpuFillPrimitive>(), id);
+
+    // Large addresses stay positive once packed.
+    let packed = PackedPrimId::from_address(GpuAddress(0x7fff0000));
+    assert!(packed.to_i32() > 0);
+    assert_eq!(PackedPrimId::from_i32(packed.to_i32()).decode(), 0x7fff0000);
+}
+
+#[test]
+#[should_panic]
+fn packed_prim_id_out_of_range() {
+    PackedPrimId::encode(0x80000000);
+}
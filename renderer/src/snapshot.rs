@@ -0,0 +1,423 @@
+//! Reference-image testing of the output of the batcher.
+//!
+//! `Snapshot` is a small software rasterizer for the commands produced by
+//! `OpaqueBatcher::build`. It runs the same vertex transformations as the fill shaders
+//! (primitive transforms, offset, z-index and depth test) on the CPU, so that rendering
+//! changes can be covered by tests without a GPU. Patterns are drawn with the color of
+//! their primitive, anti-aliasing and post-processing effects are not emulated.
+//!
+//! `assert_snapshot` compares a rendering with a reference image stored next to the
+//! tests, with a perceptual threshold to tolerate small rasterization differences. When
+//! the images differ, the rendering and a diff image are written next to the reference.
+//! Setting the `LYON_UPDATE_SNAPSHOTS` environment variable (re)writes the references
+//! instead.
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use batch_builder::{Cmd, Geometry};
+use renderer::{GpuFillVertex, GpuFillPrimitive, GpuTransform};
+
+/// An RGBA8 image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[u8; 4]>,
+    // Depth of the front-most primitive of each pixel, as in the depth buffer of the
+    // opaque passes.
+    depth: Vec<f32>,
+}
+
+/// The result of a failed comparison, see `Snapshot::compare`.
+#[derive(Clone, Debug)]
+pub struct SnapshotDiff {
+    /// The number of pixels that differ by more than the threshold.
+    pub different_pixels: usize,
+    /// The largest perceptual difference, between 0.0 and 1.0.
+    pub max_difference: f32,
+    /// The reference faded to gray with the differing pixels in red.
+    pub image: Snapshot,
+}
+
+impl Snapshot {
+    /// Creates an image cleared to opaque white.
+    pub fn new(width: u32, height: u32) -> Self {
+        Snapshot::from_pixels(width, height, vec![[255, 255, 255, 255]; (width * height) as usize])
+    }
+
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<[u8; 4]>) -> Self {
+        assert_eq!(pixels.len(), (width * height) as usize);
+        Snapshot {
+            width: width,
+            height: height,
+            pixels: pixels,
+            depth: vec![1.0; (width * height) as usize],
+        }
+    }
+
+    /// Creates an image from tightly packed RGBA8 rows, for example the output of
+    /// `read_pixels`.
+    pub fn from_rgba_bytes(width: u32, height: u32, bytes: &[u8]) -> Self {
+        let pixels = bytes.chunks(4).map(|p| [p[0], p[1], p[2], p[3]]).collect();
+        Snapshot::from_pixels(width, height, pixels)
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// Rasterizes the commands of an opaque fill pass.
+    ///
+    /// Like the instanced shaders, the primitive of each instance is the first primitive of
+    /// its command plus the instance index. The world origin is at the center of the image.
+    pub fn draw_fill_cmds(
+        &mut self,
+        geometry: &Geometry<GpuFillVertex>,
+        cmds: &[Cmd<GpuFillVertex>],
+        primitives: &[GpuFillPrimitive],
+        transforms: &[GpuTransform],
+    ) {
+        for cmd in cmds {
+            let indices = &geometry.indices[cmd.geometry.indices.range.usize_range()];
+            for instance in 0..cmd.instances {
+                let prim = &primitives[(cmd.first_primitive + instance) as usize];
+                let color = to_u8_color(prim.color);
+                let depth = 1.0 - prim.z_index;
+                for triangle in indices.chunks(3) {
+                    if triangle.len() < 3 {
+                        break;
+                    }
+                    let a = self.vertex_position(&geometry.vertices[triangle[0] as usize], prim, transforms);
+                    let b = self.vertex_position(&geometry.vertices[triangle[1] as usize], prim, transforms);
+                    let c = self.vertex_position(&geometry.vertices[triangle[2] as usize], prim, transforms);
+                    self.fill_triangle(a, b, c, depth, color);
+                }
+            }
+        }
+    }
+
+    // Same as the fill vertex shader, in pixels.
+    fn vertex_position(
+        &self,
+        vertex: &GpuFillVertex,
+        prim: &GpuFillPrimitive,
+        transforms: &[GpuTransform],
+    ) -> [f32; 2] {
+        let local = [
+            vertex.position[0] + vertex.normal[0] * prim.width,
+            vertex.position[1] + vertex.normal[1] * prim.width,
+            0.0,
+            1.0,
+        ];
+        let mut p = transform(&transforms[prim.local_transform as usize], local);
+        p[0] += prim.offset[0];
+        p[1] += prim.offset[1];
+        let world = transform(&transforms[prim.view_transform as usize], p);
+
+        return [
+            world[0] / world[3] + self.width as f32 * 0.5,
+            world[1] / world[3] + self.height as f32 * 0.5,
+        ];
+    }
+
+    // Fills the pixels whose center is inside of the triangle.
+    fn fill_triangle(&mut self, a: [f32; 2], b: [f32; 2], c: [f32; 2], depth: f32, color: [u8; 4]) {
+        let area = edge(a, b, c);
+        if area == 0.0 {
+            return;
+        }
+
+        let min_x = a[0].min(b[0]).min(c[0]).floor().max(0.0) as u32;
+        let min_y = a[1].min(b[1]).min(c[1]).floor().max(0.0) as u32;
+        let max_x = (a[0].max(b[0]).max(c[0]).ceil().max(0.0) as u32).min(self.width);
+        let max_y = (a[1].max(b[1]).max(c[1]).ceil().max(0.0) as u32).min(self.height);
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let p = [x as f32 + 0.5, y as f32 + 0.5];
+                let w0 = edge(b, c, p) * area.signum();
+                let w1 = edge(c, a, p) * area.signum();
+                let w2 = edge(a, b, p) * area.signum();
+                if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                    continue;
+                }
+
+                let idx = (y * self.width + x) as usize;
+                if depth <= self.depth[idx] {
+                    self.depth[idx] = depth;
+                    self.pixels[idx] = color;
+                }
+            }
+        }
+    }
+
+    /// Compares the image with a reference.
+    ///
+    /// The difference between two pixels is measured in the YIQ color space, which
+    /// weights the brightness more than the hue like the human eye does, and is normalized
+    /// between 0.0 and 1.0. Pixels that differ by more than `threshold` are counted and
+    /// the comparison fails if there are more than `max_different_pixels` of them.
+    pub fn compare(
+        &self,
+        reference: &Snapshot,
+        threshold: f32,
+        max_different_pixels: usize,
+    ) -> Result<(), SnapshotDiff> {
+        if self.width != reference.width || self.height != reference.height {
+            return Err(SnapshotDiff {
+                different_pixels: ::std::cmp::max(self.pixels.len(), reference.pixels.len()),
+                max_difference: 1.0,
+                image: self.clone(),
+            });
+        }
+
+        let mut different_pixels = 0;
+        let mut max_difference: f32 = 0.0;
+        let mut diff_pixels = Vec::with_capacity(self.pixels.len());
+        for (&actual, &expected) in self.pixels.iter().zip(reference.pixels.iter()) {
+            let difference = perceptual_difference(actual, expected);
+            max_difference = max_difference.max(difference);
+            if difference > threshold {
+                different_pixels += 1;
+                diff_pixels.push([255, 0, 0, 255]);
+            } else {
+                let gray = 255 - ((255 - luma(expected) as u32) / 4) as u8;
+                diff_pixels.push([gray, gray, gray, 255]);
+            }
+        }
+
+        if different_pixels <= max_different_pixels {
+            return Ok(());
+        }
+
+        return Err(SnapshotDiff {
+            different_pixels: different_pixels,
+            max_difference: max_difference,
+            image: Snapshot::from_pixels(self.width, self.height, diff_pixels),
+        });
+    }
+
+    /// Encodes the image in the PAM format, a trivial uncompressed format with an
+    /// alpha channel.
+    pub fn to_pam(&self) -> Vec<u8> {
+        let mut bytes = format!(
+            "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+            self.width, self.height,
+        ).into_bytes();
+        for pixel in &self.pixels {
+            bytes.extend_from_slice(&pixel[..]);
+        }
+
+        return bytes;
+    }
+
+    /// Decodes an image written by `to_pam`.
+    pub fn from_pam(bytes: &[u8]) -> Option<Snapshot> {
+        let mut width = None;
+        let mut height = None;
+        let mut offset = 0;
+        loop {
+            let end = match bytes[offset..].iter().position(|&b| b == b'\n') {
+                Some(len) => offset + len,
+                None => { return None; }
+            };
+            let line = match ::std::str::from_utf8(&bytes[offset..end]) {
+                Ok(line) => line.trim(),
+                Err(_) => { return None; }
+            };
+            offset = end + 1;
+
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("ENDHDR"), _) => { break; }
+                (Some("WIDTH"), Some(value)) => { width = value.parse::<u32>().ok(); }
+                (Some("HEIGHT"), Some(value)) => { height = value.parse::<u32>().ok(); }
+                (Some("DEPTH"), Some(value)) => {
+                    if value != "4" {
+                        return None;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let (width, height) = match (width, height) {
+            (Some(w), Some(h)) => (w, h),
+            _ => { return None; }
+        };
+        let data = &bytes[offset..];
+        if data.len() != (width * height * 4) as usize {
+            return None;
+        }
+
+        return Some(Snapshot::from_rgba_bytes(width, height, data));
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        File::create(path)?.write_all(&self.to_pam())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Snapshot> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        return Snapshot::from_pam(&bytes).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "Invalid PAM image")
+        });
+    }
+}
+
+/// Compares an image with the reference stored at `reference`, see `Snapshot::compare`.
+///
+/// Missing references are written and the assertion passes, so that new tests only need
+/// to be run once. On failure, the image and the diff are written next to the reference
+/// with the `actual.pam` and `diff.pam` extensions before panicking.
+pub fn assert_snapshot(
+    snapshot: &Snapshot,
+    reference: &Path,
+    threshold: f32,
+    max_different_pixels: usize,
+) {
+    if env::var_os("LYON_UPDATE_SNAPSHOTS").is_some() || !reference.exists() {
+        snapshot.save(reference).unwrap();
+        return;
+    }
+
+    let expected = Snapshot::load(reference).unwrap();
+    if let Err(diff) = snapshot.compare(&expected, threshold, max_different_pixels) {
+        let actual_path = with_extension(reference, "actual.pam");
+        let diff_path = with_extension(reference, "diff.pam");
+        snapshot.save(&actual_path).unwrap();
+        diff.image.save(&diff_path).unwrap();
+        panic!(
+            "{} pixels differ from {:?} (max difference {}), see {:?}",
+            diff.different_pixels, reference, diff.max_difference, diff_path,
+        );
+    }
+}
+
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.to_path_buf();
+    path.set_extension(extension);
+    return path;
+}
+
+// Transforms a vector the way the shaders do (the matrices are uploaded column by column).
+fn transform(transform: &GpuTransform, v: [f32; 4]) -> [f32; 4] {
+    let m = &transform.transform;
+    let mut result = [0.0; 4];
+    for row in 0..4 {
+        for col in 0..4 {
+            result[row] += m[col][row] * v[col];
+        }
+    }
+
+    return result;
+}
+
+fn edge(a: [f32; 2], b: [f32; 2], p: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
+
+fn to_u8_color(color: [f32; 4]) -> [u8; 4] {
+    let c = |v: f32| (v.max(0.0).min(1.0) * 255.0 + 0.5) as u8;
+    [c(color[0]), c(color[1]), c(color[2]), c(color[3])]
+}
+
+fn luma(pixel: [u8; 4]) -> u8 {
+    (pixel[0] as f32 * 0.299 + pixel[1] as f32 * 0.587 + pixel[2] as f32 * 0.114) as u8
+}
+
+// Blends the pixel over white and converts it to YIQ.
+fn yiq(pixel: [u8; 4]) -> (f32, f32, f32) {
+    let a = pixel[3] as f32 / 255.0;
+    let blend = |c: u8| 255.0 + (c as f32 - 255.0) * a;
+    let (r, g, b) = (blend(pixel[0]), blend(pixel[1]), blend(pixel[2]));
+
+    return (
+        r * 0.29889531 + g * 0.58662247 + b * 0.11448223,
+        r * 0.59597799 - g * 0.27417610 - b * 0.32180189,
+        r * 0.21147017 - g * 0.52261711 + b * 0.31114694,
+    );
+}
+
+fn perceptual_difference(a: [u8; 4], b: [u8; 4]) -> f32 {
+    if a == b {
+        return 0.0;
+    }
+    let (y1, i1, q1) = yiq(a);
+    let (y2, i2, q2) = yiq(b);
+    let (dy, di, dq) = (y1 - y2, i1 - i2, q1 - q2);
+    // 35215 is the difference between black and white.
+    return (0.5053 * dy * dy + 0.299 * di * di + 0.1957 * dq * dq) / 35215.0;
+}
+
+#[cfg(test)]
+use api::*;
+#[cfg(test)]
+use buffer::*;
+#[cfg(test)]
+use batch_builder::*;
+#[cfg(test)]
+use core::math::*;
+
+#[test]
+fn snapshot_of_opaque_rects() {
+    let mut batcher = OpaqueBatcher::new();
+    let mut shapes = ShapeStore::new();
+    let mut geom = GeometryStore::new();
+    let mut primitives = CpuBuffer::new(16);
+
+    // The origin is at the center of the 20x20 image: the black rect covers the whole
+    // image and the white one its center, from (5, 5) to (15, 15) in pixels.
+    let back = shapes.add_rect(rect(-10.0, -10.0, 20.0, 20.0));
+    let front = shapes.add_rect(rect(-5.0, -5.0, 10.0, 10.0));
+    for &(shape, z_index, color) in &[(back, 1, Color::black()), (front, 2, Color::white())] {
+        batcher.push_item(PrimitiveParams {
+            z_index: z_index,
+            shape: ShapeId::Rect(shape),
+            transforms: Transforms { local: None, view: None, root: None },
+            style: FillStyle::new(Pattern::Color(color)),
+        }).unwrap();
+    }
+
+    let cmds = batcher.build(
+        &shapes,
+        &mut geom,
+        &mut FillVertexBuilder::new(),
+        &mut FillPrimitiveBuilder { primitives: &mut primitives, alpha_mode: AlphaMode::Straight },
+    ).unwrap();
+
+    let mut snapshot = Snapshot::new(20, 20);
+    snapshot.draw_fill_cmds(
+        geom.geometry(),
+        &cmds,
+        &primitives[IdRange::new(0..16)],
+        &[GpuTransform::default()],
+    );
+
+    assert_eq!(snapshot.pixel(1, 1), [0, 0, 0, 255]);
+    assert_eq!(snapshot.pixel(7, 7), [255, 255, 255, 255]);
+    assert_eq!(snapshot.pixel(14, 14), [255, 255, 255, 255]);
+    assert_eq!(snapshot.pixel(12, 2), [0, 0, 0, 255]);
+    assert_eq!(snapshot.pixel(15, 15), [0, 0, 0, 255]);
+
+    let reference = Snapshot::from_pam(&snapshot.to_pam()).unwrap();
+    assert_eq!(reference.pixels, snapshot.pixels);
+    assert!(snapshot.compare(&reference, 0.01, 0).is_ok());
+
+    // A slightly different color is tolerated, a different shape is not.
+    let mut close = snapshot.clone();
+    close.pixels[0] = [2, 2, 2, 255];
+    assert!(close.compare(&reference, 0.01, 0).is_ok());
+
+    let mut different = snapshot.clone();
+    different.pixels[0] = [255, 255, 255, 255];
+    different.pixels[1] = [255, 255, 255, 255];
+    let diff = different.compare(&reference, 0.01, 1).unwrap_err();
+    assert_eq!(diff.different_pixels, 2);
+    assert_eq!(diff.image.pixel(0, 0), [255, 0, 0, 255]);
+    assert!(different.compare(&reference, 0.01, 2).is_ok());
+}
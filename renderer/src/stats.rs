@@ -0,0 +1,161 @@
+//! Per-pass timings, to find out which part of a frame is the bottleneck.
+//!
+//! Passes are bracketed with `PassTimer::begin_pass_timer` and `end_pass_timer` and the
+//! measurements are collected into `RenderStats` at the end of the frame. Timing is
+//! optional: `NoPassTimer` does nothing. The renderer times its own submissions with
+//! `GpuBufferStore::flush_uploads_timed` (uploads), `CommandStream::replay_timed` (uploads
+//! and draw passes) and `PostProcessor::set_pass_timer` (post-processing); passes submitted
+//! directly by the application are bracketed by the application.
+//!
+//! Only CPU time is measured: the gfx version used by the renderer doesn't expose timestamp
+//! queries, so `CpuPassTimer` measures the time spent recording and submitting each pass,
+//! not the time the GPU spends executing it. To approximate the GPU time, flush the encoder
+//! to the device at the end of each timed pass, which serializes the frame and should only
+//! be done while profiling.
+
+use std::time::{Duration, Instant};
+
+/// The passes of a frame that can be timed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+pub enum RenderPass {
+    Uploads,
+    DepthPrePass,
+    OpaqueFill,
+    OpaqueStroke,
+    TransparentFill,
+    TransparentStroke,
+    PostProcess,
+}
+
+/// Statistics about a rendered frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderStats {
+    /// The time spent in each timed pass, in nanoseconds, in the order the passes ended.
+    pub pass_times_ns: Vec<(RenderPass, u64)>,
+}
+
+impl RenderStats {
+    pub fn new() -> Self { RenderStats::default() }
+
+    /// The total time spent in a pass, if it was timed.
+    pub fn pass_time_ns(&self, pass: RenderPass) -> Option<u64> {
+        let mut result = None;
+        for &(p, time) in &self.pass_times_ns {
+            if p == pass {
+                result = Some(result.unwrap_or(0) + time);
+            }
+        }
+
+        return result;
+    }
+
+    /// The pass that took the longest.
+    pub fn slowest_pass(&self) -> Option<RenderPass> {
+        let mut slowest: Option<(RenderPass, u64)> = None;
+        for &(pass, _) in &self.pass_times_ns {
+            let time = self.pass_time_ns(pass).unwrap();
+            if slowest.map_or(true, |(_, max)| time > max) {
+                slowest = Some((pass, time));
+            }
+        }
+
+        return slowest.map(|(pass, _)| pass);
+    }
+
+    pub fn total_time_ns(&self) -> u64 {
+        self.pass_times_ns.iter().map(|&(_, time)| time).sum()
+    }
+
+    pub fn clear(&mut self) { self.pass_times_ns.clear(); }
+}
+
+/// Measures the duration of the passes of a frame.
+pub trait PassTimer {
+    fn begin_pass_timer(&mut self, pass: RenderPass);
+
+    fn end_pass_timer(&mut self, pass: RenderPass);
+
+    /// Moves the measurements of the ended passes into `stats`.
+    fn collect(&mut self, stats: &mut RenderStats);
+}
+
+/// A `PassTimer` that doesn't measure anything.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoPassTimer;
+
+impl PassTimer for NoPassTimer {
+    fn begin_pass_timer(&mut self, _pass: RenderPass) {}
+    fn end_pass_timer(&mut self, _pass: RenderPass) {}
+    fn collect(&mut self, _stats: &mut RenderStats) {}
+}
+
+/// Measures the CPU time between the beginning and the end of each pass.
+///
+/// The GPU work of the pass is not included unless the encoder is flushed and waited on
+/// before the pass ends.
+pub struct CpuPassTimer {
+    running: Vec<(RenderPass, Instant)>,
+    ended: Vec<(RenderPass, u64)>,
+}
+
+impl CpuPassTimer {
+    pub fn new() -> Self {
+        CpuPassTimer {
+            running: Vec::new(),
+            ended: Vec::new(),
+        }
+    }
+}
+
+impl PassTimer for CpuPassTimer {
+    fn begin_pass_timer(&mut self, pass: RenderPass) {
+        self.running.push((pass, Instant::now()));
+    }
+
+    /// Ends the most recent timer of `pass`. Ending a pass that wasn't begun is ignored.
+    fn end_pass_timer(&mut self, pass: RenderPass) {
+        if let Some(idx) = self.running.iter().rposition(|&(p, _)| p == pass) {
+            let (_, start) = self.running.remove(idx);
+            self.ended.push((pass, duration_ns(start.elapsed())));
+        }
+    }
+
+    fn collect(&mut self, stats: &mut RenderStats) {
+        stats.pass_times_ns.extend(self.ended.drain(..));
+    }
+}
+
+fn duration_ns(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64
+}
+
+#[test]
+fn pass_timers() {
+    let mut timer = CpuPassTimer::new();
+    timer.begin_pass_timer(RenderPass::Uploads);
+    timer.end_pass_timer(RenderPass::Uploads);
+    timer.begin_pass_timer(RenderPass::OpaqueFill);
+    // Nested passes are timed independently.
+    timer.begin_pass_timer(RenderPass::OpaqueStroke);
+    timer.end_pass_timer(RenderPass::OpaqueStroke);
+    timer.end_pass_timer(RenderPass::OpaqueFill);
+    timer.end_pass_timer(RenderPass::PostProcess);
+
+    let mut stats = RenderStats::new();
+    timer.collect(&mut stats);
+    let passes: Vec<RenderPass> = stats.pass_times_ns.iter().map(|&(pass, _)| pass).collect();
+    assert_eq!(passes, vec![RenderPass::Uploads, RenderPass::OpaqueStroke, RenderPass::OpaqueFill]);
+    assert!(stats.pass_time_ns(RenderPass::PostProcess).is_none());
+
+    let stats = RenderStats {
+        pass_times_ns: vec![
+            (RenderPass::OpaqueFill, 300),
+            (RenderPass::Uploads, 500),
+            (RenderPass::OpaqueFill, 400),
+        ],
+    };
+    assert_eq!(stats.pass_time_ns(RenderPass::OpaqueFill), Some(700));
+    assert_eq!(stats.slowest_pass(), Some(RenderPass::OpaqueFill));
+    assert_eq!(stats.total_time_ns(), 1200);
+}
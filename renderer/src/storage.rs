@@ -0,0 +1,161 @@
+//! Selection of the way primitive data is stored on the GPU.
+//!
+//! The primitive and transform blocks are currently read from uniform buffers, which are
+//! small (see `PRIM_BUFFER_LEN`) and therefore split into pages. Data textures are available
+//! on all GL-era hardware and can hold a lot more data, while storage buffers are the
+//! natural fit where the device supports them. The address of an element depends on the
+//! storage: uniform and storage buffers are indexed directly, while data textures are
+//! addressed with texel coordinates.
+
+use gfx;
+use glsl::PRIM_BUFFER_LEN;
+
+/// The kind of GPU resource the primitive data is stored in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StorageKind {
+    /// Uniform buffers of `PRIM_BUFFER_LEN` elements, bound one page at a time.
+    UniformBuffer,
+    /// An RGBA32F texture read with texelFetch, each element spanning a few texels of a row.
+    DataTexture,
+    /// A single storage buffer indexed directly.
+    StorageBuffer,
+}
+
+/// The storage kinds a device supports.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StorageSupport {
+    pub uniform_buffers: bool,
+    pub data_textures: bool,
+    pub storage_buffers: bool,
+    pub max_texture_size: u32,
+}
+
+impl StorageSupport {
+    /// Queries the supported storage kinds from the device's capabilities.
+    pub fn from_capabilities(caps: &gfx::Capabilities) -> Self {
+        StorageSupport {
+            uniform_buffers: caps.constant_buffer_supported,
+            // Float textures and texelFetch are part of the GL versions we require.
+            data_textures: true,
+            storage_buffers: caps.unordered_access_view_supported,
+            max_texture_size: caps.max_texture_size as u32,
+        }
+    }
+
+    pub fn supports(&self, kind: StorageKind) -> bool {
+        match kind {
+            StorageKind::UniformBuffer => self.uniform_buffers,
+            StorageKind::DataTexture => self.data_textures,
+            StorageKind::StorageBuffer => self.storage_buffers,
+        }
+    }
+
+    /// Picks the storage to use for a given number of elements.
+    ///
+    /// The shaders only read primitive data from uniform buffers for now, so this always
+    /// returns `StorageKind::UniformBuffer`, paging the elements that don't fit. Storage
+    /// buffers and data textures will be picked here once shaders can read from them.
+    pub fn preferred_kind(&self, _num_elements: usize) -> StorageKind {
+        return StorageKind::UniformBuffer;
+    }
+}
+
+/// Describes how the elements of a given type are laid out in the selected storage, and
+/// computes their addresses.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StorageLayout {
+    pub kind: StorageKind,
+    /// The size of an element in vec4s (or RGBA texels).
+    pub element_size: u32,
+    /// The width in texels of the data texture. Unused by the other kinds.
+    pub texture_width: u32,
+}
+
+impl StorageLayout {
+    /// Creates the layout of elements of `element_size` vec4s.
+    ///
+    /// Data textures use rows of up to 1024 texels, within the limits of the device.
+    pub fn new(kind: StorageKind, element_size: u32, support: &StorageSupport) -> Self {
+        assert!(element_size > 0);
+        let texture_width = ::std::cmp::min(support.max_texture_size, 1024);
+        assert!(kind != StorageKind::DataTexture || texture_width >= element_size);
+        StorageLayout {
+            kind: kind,
+            element_size: element_size,
+            texture_width: texture_width,
+        }
+    }
+
+    /// Number of elements in a row of the data texture. Elements don't straddle rows.
+    pub fn elements_per_row(&self) -> u32 {
+        self.texture_width / self.element_size
+    }
+
+    /// The address the shaders use to read the element at `index`.
+    ///
+    /// For buffers this is the index of the element (within its page for uniform buffers).
+    /// For data textures the address packs the texel coordinates of the first texel of the
+    /// element, x in the low 16 bits and y in the high ones, so that the shader doesn't have
+    /// to divide by the row size.
+    pub fn address(&self, index: u32) -> i32 {
+        match self.kind {
+            StorageKind::UniformBuffer => (index % PRIM_BUFFER_LEN as u32) as i32,
+            StorageKind::StorageBuffer => index as i32,
+            StorageKind::DataTexture => {
+                let per_row = self.elements_per_row();
+                let x = (index % per_row) * self.element_size;
+                let y = index / per_row;
+                (x | (y << 16)) as i32
+            }
+        }
+    }
+
+    /// The page to bind to read the element at `index`. Only uniform buffers are paged.
+    pub fn page(&self, index: u32) -> u32 {
+        match self.kind {
+            StorageKind::UniformBuffer => index / PRIM_BUFFER_LEN as u32,
+            _ => 0,
+        }
+    }
+
+    /// The size in texels of a data texture holding `num_elements` elements.
+    pub fn texture_size(&self, num_elements: u32) -> (u32, u32) {
+        let per_row = self.elements_per_row();
+        let rows = (num_elements + per_row - 1) / per_row;
+        return (self.texture_width, ::std::cmp::max(rows, 1));
+    }
+}
+
+#[test]
+fn storage_addresses() {
+    let support = StorageSupport {
+        uniform_buffers: true,
+        data_textures: true,
+        storage_buffers: false,
+        max_texture_size: 4096,
+    };
+    assert_eq!(support.preferred_kind(10), StorageKind::UniformBuffer);
+    // No shader reads from the other kinds yet.
+    assert_eq!(support.preferred_kind(PRIM_BUFFER_LEN + 1), StorageKind::UniformBuffer);
+    assert_eq!(
+        StorageSupport { storage_buffers: true, .. support }.preferred_kind(10),
+        StorageKind::UniformBuffer
+    );
+
+    let ubo = StorageLayout::new(StorageKind::UniformBuffer, 3, &support);
+    assert_eq!(ubo.address(5), 5);
+    assert_eq!(ubo.page(PRIM_BUFFER_LEN as u32 + 5), 1);
+    assert_eq!(ubo.address(PRIM_BUFFER_LEN as u32 + 5), 5);
+
+    let ssbo = StorageLayout::new(StorageKind::StorageBuffer, 3, &support);
+    assert_eq!(ssbo.address(PRIM_BUFFER_LEN as u32 + 5), PRIM_BUFFER_LEN as i32 + 5);
+    assert_eq!(ssbo.page(PRIM_BUFFER_LEN as u32 + 5), 0);
+
+    // 1024 texels per row hold 341 elements of 3 texels.
+    let texture = StorageLayout::new(StorageKind::DataTexture, 3, &support);
+    assert_eq!(texture.elements_per_row(), 341);
+    assert_eq!(texture.address(2), 6);
+    assert_eq!(texture.address(341), 1 << 16);
+    assert_eq!(texture.address(343), 6 | (1 << 16));
+    assert_eq!(texture.texture_size(342), (1024, 2));
+}
@@ -0,0 +1,262 @@
+//! Streaming the content of scenes that don't fit in memory.
+//!
+//! Document viewers and maps display scenes much larger than what can be tessellated and
+//! uploaded at once. A `ChunkedScene` divides the layer space into chunks, which are
+//! loaded when they come close to the viewport and unloaded when they go far from it.
+//! The scene doesn't know what a chunk contains: the application provides the content of
+//! a chunk when loading it, for example the render nodes and the batcher of a vector image,
+//! along with the shapes it draws. Unloading a chunk removes the geometry of its shapes
+//! from the `GeometryStore` and gives the content back so that its GPU data can be freed.
+//!
+//! Layers only draw the resident chunks returned by `visible_chunks`.
+
+use std::collections::HashMap;
+
+use api::ShapeId;
+use buffer::Id;
+use batch_builder::GeometryStore;
+use units::*;
+#[cfg(test)]
+use api::PathId;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Chunk;
+
+pub type ChunkId = Id<Chunk>;
+
+struct ChunkData<Content> {
+    bounds: LayerRect,
+    shapes: Vec<ShapeId>,
+    content: Option<Content>,
+}
+
+/// The chunks to load and unload, see `ChunkedScene::requests`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChunkRequests {
+    /// Chunks that intersect the prefetched area and are not resident, closest first.
+    pub load: Vec<ChunkId>,
+    /// Resident chunks that are out of the area kept in memory.
+    pub unload: Vec<ChunkId>,
+}
+
+/// A scene divided into spatial chunks that are loaded and unloaded independently.
+pub struct ChunkedScene<Content> {
+    chunks: Vec<ChunkData<Content>>,
+    // The number of resident chunks using each shape. Shapes can be shared by several chunks,
+    // for example the glyphs of a document, and their geometry is kept until none uses them.
+    shape_users: HashMap<ShapeId, u32>,
+}
+
+impl<Content> ChunkedScene<Content> {
+    pub fn new() -> Self {
+        ChunkedScene {
+            chunks: Vec::new(),
+            shape_users: HashMap::new(),
+        }
+    }
+
+    /// Adds an unloaded chunk covering an area of the layer space.
+    ///
+    /// The bounds of a chunk must contain everything it draws, chunks can overlap.
+    pub fn add_chunk(&mut self, bounds: LayerRect) -> ChunkId {
+        assert!(self.chunks.len() < u16::max_value() as usize, "Too many chunks");
+        self.chunks.push(ChunkData {
+            bounds: bounds,
+            shapes: Vec::new(),
+            content: None,
+        });
+
+        return Id::new((self.chunks.len() - 1) as u16);
+    }
+
+    /// Adds a grid of unloaded chunks of `chunk_size` covering `area`, row by row.
+    pub fn add_grid(&mut self, area: &LayerRect, chunk_size: LayerSize) -> Vec<ChunkId> {
+        assert!(chunk_size.width > 0.0 && chunk_size.height > 0.0);
+        let columns = (area.size.width / chunk_size.width).ceil().max(1.0) as u32;
+        let rows = (area.size.height / chunk_size.height).ceil().max(1.0) as u32;
+
+        let mut ids = Vec::with_capacity((columns * rows) as usize);
+        for row in 0..rows {
+            for column in 0..columns {
+                let origin = LayerPoint::new(
+                    area.origin.x + column as f32 * chunk_size.width,
+                    area.origin.y + row as f32 * chunk_size.height,
+                );
+                ids.push(self.add_chunk(LayerRect::new(origin, chunk_size)));
+            }
+        }
+
+        return ids;
+    }
+
+    pub fn len(&self) -> usize { self.chunks.len() }
+
+    pub fn bounds(&self, id: ChunkId) -> LayerRect { self.chunks[id.index()].bounds }
+
+    pub fn is_resident(&self, id: ChunkId) -> bool { self.chunks[id.index()].content.is_some() }
+
+    /// The content of a resident chunk.
+    pub fn get(&self, id: ChunkId) -> Option<&Content> {
+        self.chunks[id.index()].content.as_ref()
+    }
+
+    pub fn get_mut(&mut self, id: ChunkId) -> Option<&mut Content> {
+        self.chunks[id.index()].content.as_mut()
+    }
+
+    /// The shapes drawn by a resident chunk.
+    pub fn shapes(&self, id: ChunkId) -> &[ShapeId] { &self.chunks[id.index()].shapes }
+
+    /// Makes a chunk resident with its content and the shapes it draws.
+    ///
+    /// The shapes are tessellated when the content is built, like other shapes. Loading a
+    /// resident chunk replaces its content and returns the previous one.
+    pub fn load_chunk(&mut self, id: ChunkId, content: Content, shapes: Vec<ShapeId>) -> Option<Content> {
+        for shape in &shapes {
+            *self.shape_users.entry(*shape).or_insert(0) += 1;
+        }
+
+        let chunk = &mut self.chunks[id.index()];
+        let previous = chunk.content.take();
+        if previous.is_some() {
+            for shape in &chunk.shapes {
+                release_shape(&mut self.shape_users, *shape);
+            }
+        }
+        chunk.content = Some(content);
+        chunk.shapes = shapes;
+
+        return previous;
+    }
+
+    /// Unloads a chunk and returns its content, or None if it wasn't resident.
+    ///
+    /// The geometry of the shapes that no other resident chunk draws is removed from the
+    /// store. Like for other removals, the space is reclaimed by `GeometryStore::compact`.
+    pub fn unload_chunk<Vertex>(&mut self, id: ChunkId, geometry: &mut GeometryStore<Vertex>) -> Option<Content> {
+        let chunk = &mut self.chunks[id.index()];
+        let content = chunk.content.take();
+        if content.is_none() {
+            return None;
+        }
+
+        for shape in chunk.shapes.drain(..) {
+            if release_shape(&mut self.shape_users, shape) {
+                geometry.remove(shape);
+            }
+        }
+
+        return content;
+    }
+
+    /// The resident chunks intersecting an area, typically `Viewport::visible_rect`.
+    pub fn visible_chunks(&self, area: &LayerRect) -> Vec<ChunkId> {
+        let mut result = Vec::new();
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            if chunk.content.is_some() && chunk.bounds.intersects(area) {
+                result.push(Id::new(i as u16));
+            }
+        }
+
+        return result;
+    }
+
+    /// The chunks to load and unload for an area of the layer space.
+    ///
+    /// Chunks within `prefetch` units of the area should be loaded, so that they are
+    /// resident when scrolled into view. Resident chunks further than `keep` units are to
+    /// be unloaded. `keep` must not be smaller than `prefetch`, the difference avoids
+    /// loading and unloading the same chunks when scrolling back and forth.
+    pub fn requests(&self, area: &LayerRect, prefetch: f32, keep: f32) -> ChunkRequests {
+        debug_assert!(keep >= prefetch);
+        let prefetched = area.inflate(prefetch, prefetch);
+        let kept = area.inflate(keep, keep);
+        let center = LayerPoint::new(
+            area.origin.x + area.size.width * 0.5,
+            area.origin.y + area.size.height * 0.5,
+        );
+
+        let mut load = Vec::new();
+        let mut unload = Vec::new();
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            let id = Id::new(i as u16);
+            if chunk.content.is_none() {
+                if chunk.bounds.intersects(&prefetched) {
+                    load.push(id);
+                }
+            } else if !chunk.bounds.intersects(&kept) {
+                unload.push(id);
+            }
+        }
+
+        let distance = |id: &ChunkId| {
+            let bounds = self.chunks[id.index()].bounds;
+            let dx = (bounds.origin.x - center.x).max(center.x - bounds.max_x()).max(0.0);
+            let dy = (bounds.origin.y - center.y).max(center.y - bounds.max_y()).max(0.0);
+            dx * dx + dy * dy
+        };
+        load.sort_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap());
+
+        return ChunkRequests { load: load, unload: unload };
+    }
+}
+
+// Returns true if no resident chunk uses the shape anymore.
+fn release_shape(users: &mut HashMap<ShapeId, u32>, shape: ShapeId) -> bool {
+    let unused = match users.get_mut(&shape) {
+        Some(count) => {
+            *count -= 1;
+            *count == 0
+        }
+        None => { return false; }
+    };
+    if unused {
+        users.remove(&shape);
+    }
+
+    return unused;
+}
+
+#[test]
+fn chunk_streaming() {
+    use renderer::GpuFillVertex;
+
+    let mut scene: ChunkedScene<&'static str> = ChunkedScene::new();
+    let chunks = scene.add_grid(
+        &LayerRect::new(LayerPoint::new(0.0, 0.0), LayerSize::new(300.0, 200.0)),
+        LayerSize::new(100.0, 100.0),
+    );
+    assert_eq!(chunks.len(), 6);
+    assert_eq!(scene.bounds(chunks[4]), LayerRect::new(LayerPoint::new(100.0, 100.0), LayerSize::new(100.0, 100.0)));
+
+    // The viewport covers the first chunk, the prefetched area reaches the second row.
+    let viewport = LayerRect::new(LayerPoint::new(10.0, 10.0), LayerSize::new(50.0, 50.0));
+    let requests = scene.requests(&viewport, 50.0, 100.0);
+    assert_eq!(requests.load, vec![chunks[0], chunks[1], chunks[3], chunks[4]]);
+    assert!(requests.unload.is_empty());
+
+    let shared = ShapeId::Path(PathId::new(0));
+    let own = ShapeId::Path(PathId::new(1));
+    scene.load_chunk(chunks[0], "a", vec![shared, own]);
+    scene.load_chunk(chunks[1], "b", vec![shared]);
+    assert!(scene.is_resident(chunks[0]));
+    assert_eq!(scene.get(chunks[1]), Some(&"b"));
+    assert_eq!(scene.visible_chunks(&viewport), vec![chunks[0]]);
+
+    let mut geometry: GeometryStore<GpuFillVertex> = GeometryStore::new();
+
+    // Far away, both chunks are unloaded.
+    let viewport = LayerRect::new(LayerPoint::new(250.0, 150.0), LayerSize::new(10.0, 10.0));
+    let requests = scene.requests(&viewport, 0.0, 10.0);
+    assert_eq!(requests.load, vec![chunks[5]]);
+    assert_eq!(requests.unload, vec![chunks[0], chunks[1]]);
+
+    assert_eq!(scene.unload_chunk(chunks[0], &mut geometry), Some("a"));
+    // The shared shape is still used by the second chunk.
+    assert_eq!(scene.shape_users.get(&shared), Some(&1));
+    assert_eq!(scene.shape_users.get(&own), None);
+    assert_eq!(scene.unload_chunk(chunks[0], &mut geometry), None);
+    assert_eq!(scene.unload_chunk(chunks[1], &mut geometry), Some("b"));
+    assert!(scene.shape_users.is_empty());
+    assert!(scene.visible_chunks(&viewport).is_empty());
+}
@@ -0,0 +1,531 @@
+//! Colors and gradients shared by the primitives of many render nodes.
+//!
+//! A primitive normally embeds its color, so changing the theme of an application means
+//! rewriting and uploading every primitive that uses the color. Instead, the colors and
+//! gradients of a theme can be registered once in a `StyleSheet` and referenced with
+//! `Pattern::Shared`: the fill and stroke shaders read them from the `u_styles` buffer, and
+//! switching to a dark mode is a single upload of the sheet.
+//!
+//! The stops of each gradient are stored in a `GpuMemory`, in a table reserved when the
+//! gradient is added, which is uploaded to the `u_gradient_stops` buffer. The fragment
+//! shaders interpolate the stops from the offset 0 to 1 along the x axis of the local space
+//! of fills, or around the center of conic gradients, and from the start to the end of the
+//! path of strokes. Editors can reserve room
+//! for more stops than the gradient has and change them at runtime with
+//! `set_gradient_stops`, which rewrites the table in place.
+
+use api::{AlphaMode, Color, ColorId, GradientId, GradientStop, LinearGradient, PatternId};
+use api::{ConicGradient, ConicGradientId};
+use buffer::Id;
+use gpu_block::{GpuBlock, GpuMemory, GpuAddressRange, MemoryLayout, DataType};
+use glsl::{PRIM_BUFFER_LEN, GRADIENT_STOP_BUFFER_LEN};
+use renderer::{GpuStyle, GpuGradientStopWords};
+use renderer::{STYLE_KIND_COLOR, STYLE_KIND_LINEAR_GRADIENT, STYLE_KIND_CONIC_GRADIENT};
+#[cfg(test)]
+use api::Pattern;
+#[cfg(test)]
+use renderer::{GpuFillPrimitive, PATTERN_KIND_SHARED};
+
+/// An entry of a `StyleSheet`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SharedStyle {
+    Color(Color),
+    LinearGradient(LinearGradient),
+    ConicGradient(ConicGradient),
+}
+
+impl SharedStyle {
+    pub fn is_opaque(&self) -> bool {
+        match *self {
+            SharedStyle::Color(color) => color.a == 255,
+            SharedStyle::LinearGradient(ref gradient) => gradient.is_opaque,
+            SharedStyle::ConicGradient(ref gradient) => gradient.is_opaque,
+        }
+    }
+
+    /// The stops of gradients, None for colors.
+    pub fn stops(&self) -> Option<&[GradientStop]> {
+        match *self {
+            SharedStyle::Color(_) => None,
+            SharedStyle::LinearGradient(ref gradient) => Some(gradient.stops()),
+            SharedStyle::ConicGradient(ref gradient) => Some(gradient.stops()),
+        }
+    }
+
+    fn to_gpu(&self, stop_table: Option<GradientStopTable>, alpha_mode: AlphaMode) -> GpuStyle {
+        let color = match *self {
+            SharedStyle::Color(color) => alpha_mode.color(color),
+            _ => {
+                let first = self.stops().and_then(|stops| stops.first());
+                first.map_or([1.0, 1.0, 1.0, 1.0], |stop| alpha_mode.color(stop.color))
+            }
+        };
+        let (kind, center, start_angle) = match *self {
+            SharedStyle::Color(_) => (STYLE_KIND_COLOR, [0.0; 2], 0.0),
+            SharedStyle::LinearGradient(_) => (STYLE_KIND_LINEAR_GRADIENT, [0.0; 2], 0.0),
+            SharedStyle::ConicGradient(ref gradient) => {
+                (STYLE_KIND_CONIC_GRADIENT, gradient.center.to_array(), gradient.start_angle.get())
+            }
+        };
+        let (stops, stop_count) = stop_table.map_or((-1, 0), |table| (table.range.start.0 as i32, table.len as i32));
+
+        return GpuStyle {
+            color: color,
+            stops: stops,
+            stop_count: stop_count,
+            kind: kind,
+            start_angle: start_angle,
+            center: center,
+            _padding: [0.0; 2],
+        };
+    }
+}
+
+/// A gradient stop as stored in the stop tables, with a straight alpha color.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GpuGradientStop {
+    pub color: [f32; 4],
+    pub offset: f32,
+}
+
+impl GpuGradientStop {
+    pub fn new(stop: &GradientStop) -> Self {
+        GpuGradientStop {
+            color: stop.color.f32_array(),
+            offset: stop.d,
+        }
+    }
+}
+
+unsafe impl GpuBlock for GpuGradientStop {
+    fn layout() -> MemoryLayout {
+        MemoryLayout::new(&[
+            ("color", DataType::Vec4),
+            ("offset", DataType::Float),
+        ])
+    }
+}
+
+/// The region of the stop memory holding the stops of a gradient.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GradientStopTable {
+    pub range: GpuAddressRange,
+    /// The number of stops in use, the rest of the region is zeroed.
+    pub len: usize,
+}
+
+impl GradientStopTable {
+    /// The maximum number of stops the region can hold.
+    pub fn capacity(&self) -> usize {
+        self.range.words as usize / GpuGradientStop::size_in_words()
+    }
+}
+
+/// The error returned when changing the stops of a gradient to more stops than its table
+/// can hold.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TooManyStops {
+    pub stops: usize,
+    pub capacity: usize,
+}
+
+/// The error returned when an entry doesn't fit in the buffers read by the shaders.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StyleSheetFull {
+    /// The sheet already has `PRIM_BUFFER_LEN` entries, the length of `u_styles`.
+    Styles,
+    /// The stop tables would not fit in the `GRADIENT_STOP_BUFFER_LEN` vec4s of
+    /// `u_gradient_stops`, even after compacting them.
+    GradientStops,
+}
+
+/// Colors and gradients referenced by the primitives through `Pattern::Shared`.
+///
+/// Colors and gradients share the same index space, which is the index of their entry in
+/// the `u_styles` buffer.
+#[derive(Clone, Debug)]
+pub struct StyleSheet {
+    entries: Vec<SharedStyle>,
+    // The stop table of each entry, None for colors.
+    stop_tables: Vec<Option<GradientStopTable>>,
+    stops: GpuMemory,
+    dirty: bool,
+    stops_dirty: bool,
+}
+
+impl StyleSheet {
+    pub fn new() -> Self {
+        StyleSheet {
+            entries: Vec::new(),
+            stop_tables: Vec::new(),
+            stops: GpuMemory::new(),
+            dirty: true,
+            stops_dirty: true,
+        }
+    }
+
+    pub fn add_color(&mut self, color: Color) -> Result<ColorId, StyleSheetFull> {
+        if self.entries.len() >= PRIM_BUFFER_LEN {
+            return Err(StyleSheetFull::Styles);
+        }
+
+        return Ok(Id::new(self.add(SharedStyle::Color(color), None)));
+    }
+
+    pub fn add_linear_gradient(&mut self, gradient: LinearGradient) -> Result<GradientId, StyleSheetFull> {
+        let capacity = gradient.stops().len();
+        return self.add_linear_gradient_with_capacity(gradient, capacity);
+    }
+
+    /// Adds a gradient with a stop table that can hold up to `max_stops` stops, or the stops
+    /// of the gradient if it has more, so that `set_gradient_stops` can add stops later
+    /// without moving the table.
+    pub fn add_linear_gradient_with_capacity(
+        &mut self,
+        gradient: LinearGradient,
+        max_stops: usize,
+    ) -> Result<GradientId, StyleSheetFull> {
+        let max_stops = max_stops.max(gradient.stops().len());
+        if self.entries.len() >= PRIM_BUFFER_LEN {
+            return Err(StyleSheetFull::Styles);
+        }
+        let range = self.reserve_stops(max_stops)?;
+        let table = GradientStopTable { range: range, len: 0 };
+        let id = Id::new(self.add(SharedStyle::LinearGradient(gradient), Some(table)));
+        self.write_stops(id.index());
+
+        return Ok(id);
+    }
+
+    /// Adds a conic gradient, which the fill shaders evaluate around its center in the
+    /// local space of the primitives. Strokes interpolate its stops along the path like
+    /// for linear gradients.
+    pub fn add_conic_gradient(&mut self, gradient: ConicGradient) -> Result<ConicGradientId, StyleSheetFull> {
+        if self.entries.len() >= PRIM_BUFFER_LEN {
+            return Err(StyleSheetFull::Styles);
+        }
+        let range = self.reserve_stops(gradient.stops().len())?;
+        let table = GradientStopTable { range: range, len: 0 };
+        let index = self.add(SharedStyle::ConicGradient(gradient), Some(table));
+        self.write_stops(index as usize);
+
+        return Ok(Id::new(index));
+    }
+
+    fn add(&mut self, style: SharedStyle, stop_table: Option<GradientStopTable>) -> u16 {
+        debug_assert!(self.entries.len() < PRIM_BUFFER_LEN);
+        self.entries.push(style);
+        self.stop_tables.push(stop_table);
+        self.dirty = true;
+
+        return (self.entries.len() - 1) as u16;
+    }
+
+    // Reserves a stop table, compacting the stop memory first if the table would not fit
+    // in the u_gradient_stops buffer.
+    fn reserve_stops(&mut self, max_stops: usize) -> Result<GpuAddressRange, StyleSheetFull> {
+        let words = max_stops * GpuGradientStop::size_in_words();
+        let max_words = GRADIENT_STOP_BUFFER_LEN * 4;
+        if self.stops.len() + words > max_words {
+            self.compact_gradient_stops();
+        }
+        if self.stops.len() + words > max_words {
+            return Err(StyleSheetFull::GradientStops);
+        }
+
+        return Ok(self.stops.reserve(words as u32));
+    }
+
+    // Copies the stops of a gradient entry into its table.
+    fn write_stops(&mut self, index: usize) {
+        let stops = match self.entries[index].stops() {
+            Some(stops) => stops,
+            None => { return; }
+        };
+        let table = self.stop_tables[index].as_mut().unwrap();
+        debug_assert!(stops.len() <= table.capacity());
+
+        let zero = GpuGradientStop { color: [0.0; 4], offset: 0.0 };
+        for i in 0..table.capacity() {
+            let stop = stops.get(i).map_or(zero, GpuGradientStop::new);
+            self.stops.set_at(table.range, i, &stop);
+        }
+        table.len = stops.len();
+        self.stops_dirty = true;
+    }
+
+    /// Changes a color for all the primitives that use it.
+    pub fn set_color(&mut self, id: ColorId, color: Color) {
+        debug_assert!(match self.entries[id.index()] { SharedStyle::Color(_) => true, _ => false });
+        self.entries[id.index()] = SharedStyle::Color(color);
+        self.dirty = true;
+    }
+
+    /// Changes a gradient for all the primitives that use it.
+    ///
+    /// If the gradient has more stops than its table can hold, the table is moved to a new
+    /// region of the stop memory, see `stop_table`. The gradient is left unchanged if the new
+    /// table doesn't fit.
+    pub fn set_linear_gradient(&mut self, id: GradientId, gradient: LinearGradient) -> Result<(), StyleSheetFull> {
+        debug_assert!(match self.entries[id.index()] { SharedStyle::LinearGradient(_) => true, _ => false });
+        if gradient.stops().len() > self.stop_tables[id.index()].unwrap().capacity() {
+            let range = self.reserve_stops(gradient.stops().len())?;
+            // Reserving can compact the memory, so the old table is read afterwards.
+            self.stops.free(self.stop_tables[id.index()].unwrap().range);
+            self.stop_tables[id.index()] = Some(GradientStopTable { range: range, len: 0 });
+        }
+        self.entries[id.index()] = SharedStyle::LinearGradient(gradient);
+        self.write_stops(id.index());
+        self.dirty = true;
+
+        return Ok(());
+    }
+
+    /// Changes the stops of a gradient in place, within the capacity of its stop table.
+    ///
+    /// Unlike `set_linear_gradient`, the table never moves, so the addresses kept by the
+    /// primitives and draw commands stay valid.
+    pub fn set_gradient_stops(&mut self, id: GradientId, stops: &[GradientStop]) -> Result<(), TooManyStops> {
+        debug_assert!(match self.entries[id.index()] { SharedStyle::LinearGradient(_) => true, _ => false });
+        let capacity = self.stop_tables[id.index()].unwrap().capacity();
+        if stops.len() > capacity {
+            return Err(TooManyStops { stops: stops.len(), capacity: capacity });
+        }
+
+        self.entries[id.index()] = SharedStyle::LinearGradient(LinearGradient::new(stops.to_vec()));
+        self.write_stops(id.index());
+        self.dirty = true;
+
+        return Ok(());
+    }
+
+    /// Where the stops of a gradient are stored in the stop memory.
+    pub fn stop_table(&self, id: GradientId) -> GradientStopTable {
+        self.stop_tables[id.index()].unwrap()
+    }
+
+    /// The memory holding the stop tables of the gradients.
+    pub fn gradient_stops(&self) -> &GpuMemory { &self.stops }
+
+    /// Removes the regions left by the tables that `set_linear_gradient` moved.
+    ///
+    /// The `u_styles` buffer holds the addresses of the tables, so it is marked as changed
+    /// along with the stops.
+    pub fn compact_gradient_stops(&mut self) {
+        if self.stops.freed_words() == 0 {
+            return;
+        }
+        let relocations = self.stops.compact();
+        for table in self.stop_tables.iter_mut().filter_map(|table| table.as_mut()) {
+            table.range = relocations.relocate_range(table.range).unwrap();
+        }
+        self.stops_dirty = true;
+        self.dirty = true;
+    }
+
+    /// The content of the `u_gradient_stops` buffer: the words of the stop memory, four
+    /// per element.
+    pub fn gpu_gradient_stops(&self) -> Vec<GpuGradientStopWords> {
+        self.stops.words().chunks(4).map(|chunk| {
+            let mut words = [0.0; 4];
+            for (dst, src) in words.iter_mut().zip(chunk) {
+                *dst = f32::from_bits(*src);
+            }
+            GpuGradientStopWords { words: words }
+        }).collect()
+    }
+
+    /// Returns the content of the `u_gradient_stops` buffer if the stops changed since the
+    /// last call, and marks them as uploaded.
+    pub fn take_gradient_stops(&mut self) -> Option<Vec<GpuGradientStopWords>> {
+        if !self.stops_dirty {
+            return None;
+        }
+        self.stops_dirty = false;
+
+        return Some(self.gpu_gradient_stops());
+    }
+
+    pub fn get(&self, id: PatternId) -> &SharedStyle {
+        &self.entries[id.index()]
+    }
+
+    /// Whether the primitives using an entry can be drawn in the opaque passes.
+    ///
+    /// `Pattern::Shared` is never considered opaque since the entry can change after the
+    /// primitives are built. Callers that don't make opaque entries transparent can use
+    /// this to batch them with the opaque primitives.
+    pub fn is_opaque(&self, id: PatternId) -> bool {
+        self.get(id).is_opaque()
+    }
+
+    pub fn len(&self) -> usize { self.entries.len() }
+
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+
+    /// Whether the sheet changed since the last call to `take_gpu_styles`.
+    pub fn is_dirty(&self) -> bool { self.dirty }
+
+    /// The content of the `u_styles` buffer.
+    pub fn gpu_styles(&self, alpha_mode: AlphaMode) -> Vec<GpuStyle> {
+        self.entries.iter().zip(&self.stop_tables).map(|(style, &table)| style.to_gpu(table, alpha_mode)).collect()
+    }
+
+    /// Returns the content of the `u_styles` buffer if the sheet changed since the last
+    /// call, and marks it as uploaded.
+    pub fn take_gpu_styles(&mut self, alpha_mode: AlphaMode) -> Option<Vec<GpuStyle>> {
+        if !self.dirty {
+            return None;
+        }
+        self.dirty = false;
+
+        return Some(self.gpu_styles(alpha_mode));
+    }
+}
+
+#[test]
+fn shared_styles() {
+    let mut sheet = StyleSheet::new();
+    let background = sheet.add_color(Color::white()).unwrap();
+    let gradient = sheet.add_linear_gradient(LinearGradient::new(vec![
+        GradientStop { color: Color::black(), d: 0.0 },
+        GradientStop { color: Color::white(), d: 1.0 },
+    ])).unwrap();
+    assert_eq!(PatternId::Color(background).index(), 0);
+    assert_eq!(PatternId::Gradient(gradient).index(), 1);
+
+    let mut primitive = GpuFillPrimitive::default();
+    primitive.set_pattern(&Pattern::Shared(PatternId::Gradient(gradient)), AlphaMode::Straight).unwrap();
+    assert_eq!(primitive.pattern_kind, PATTERN_KIND_SHARED);
+    assert_eq!(primitive.pattern_id, 1);
+    assert!(!Pattern::Shared(PatternId::Color(background)).is_opaque());
+    assert!(sheet.is_opaque(PatternId::Color(background)));
+
+    let styles = sheet.take_gpu_styles(AlphaMode::Straight).unwrap();
+    assert_eq!(styles.len(), 2);
+    assert_eq!(styles[0].color, [1.0, 1.0, 1.0, 1.0]);
+    assert_eq!(styles[1].color, [0.0, 0.0, 0.0, 1.0]);
+    // The shaders interpolate the stop table of the gradient.
+    assert_eq!((styles[0].stops, styles[0].stop_count), (-1, 0));
+    assert_eq!(styles[1].stops, sheet.stop_table(gradient).range.start.0 as i32);
+    assert_eq!(styles[1].stop_count, 2);
+    assert!(sheet.take_gpu_styles(AlphaMode::Straight).is_none());
+
+    // Switching to a dark theme only changes the sheet.
+    sheet.set_color(background, Color::black());
+    let styles = sheet.take_gpu_styles(AlphaMode::Straight).unwrap();
+    assert_eq!(styles[0].color, [0.0, 0.0, 0.0, 1.0]);
+    assert_eq!(primitive.pattern_id, 1);
+}
+
+#[test]
+fn conic_gradient_style() {
+    use core::math::{point, Radians};
+
+    let mut sheet = StyleSheet::new();
+    sheet.add_color(Color::white()).unwrap();
+    let wheel = sheet.add_conic_gradient(ConicGradient::new(
+        point(10.0, 20.0),
+        Radians::new(1.0),
+        vec![
+            GradientStop { color: Color::black(), d: 0.0 },
+            GradientStop { color: Color::white(), d: 1.0 },
+        ],
+    )).unwrap();
+    assert_eq!(PatternId::ConicGradient(wheel).index(), 1);
+
+    let mut primitive = GpuFillPrimitive::default();
+    primitive.set_pattern(&Pattern::Shared(PatternId::ConicGradient(wheel)), AlphaMode::Straight).unwrap();
+    assert_eq!(primitive.pattern_id, 1);
+
+    // The fill shaders compute the offset around the center from the style.
+    let styles = sheet.take_gpu_styles(AlphaMode::Straight).unwrap();
+    assert_eq!(styles[0].kind, STYLE_KIND_COLOR);
+    assert_eq!(styles[1].kind, STYLE_KIND_CONIC_GRADIENT);
+    assert_eq!(styles[1].center, [10.0, 20.0]);
+    assert_eq!(styles[1].start_angle, 1.0);
+    assert_eq!(styles[1].color, [0.0, 0.0, 0.0, 1.0]);
+    assert_eq!(styles[1].stop_count, 2);
+    let stops = sheet.gradient_stops().words();
+    let address = styles[1].stops as usize + GpuGradientStop::size_in_words() + 4;
+    assert_eq!(stops[address], 1.0f32.to_bits());
+}
+
+#[test]
+fn gradient_stop_editing() {
+    let clear = Color::transparent_black();
+    let mut sheet = StyleSheet::new();
+    let background = sheet.add_color(Color::white()).unwrap();
+    let gradient = sheet.add_linear_gradient_with_capacity(LinearGradient::new(vec![
+        GradientStop { color: Color::black(), d: 0.0 },
+        GradientStop { color: Color::white(), d: 1.0 },
+    ]), 3).unwrap();
+    assert_eq!(GpuGradientStop::size_in_words(), 5);
+    assert!(sheet.take_gradient_stops().is_some());
+    assert!(sheet.take_gradient_stops().is_none());
+
+    let table = sheet.stop_table(gradient);
+    assert_eq!(table.len, 2);
+    assert_eq!(table.capacity(), 3);
+    let address = table.range.element::<GpuGradientStop>(1).0 as usize;
+    assert_eq!(sheet.gradient_stops().words()[address + 4], 1.0f32.to_bits());
+    let words = sheet.gpu_gradient_stops();
+    assert_eq!(words.len(), 4);
+    assert_eq!(words[(address + 4) / 4].words[(address + 4) % 4], 1.0);
+
+    sheet.take_gpu_styles(AlphaMode::Straight);
+    sheet.set_gradient_stops(gradient, &[
+        GradientStop { color: clear, d: 0.0 },
+        GradientStop { color: Color::black(), d: 0.5 },
+        GradientStop { color: Color::white(), d: 1.0 },
+    ]).unwrap();
+    assert_eq!(sheet.stop_table(gradient), GradientStopTable { range: table.range, len: 3 });
+    assert_eq!(sheet.gradient_stops().words()[address + 4], 0.5f32.to_bits());
+    assert_eq!(sheet.take_gpu_styles(AlphaMode::Straight).unwrap()[1].color, [0.0, 0.0, 0.0, 0.0]);
+    assert!(sheet.take_gradient_stops().is_some());
+
+    let too_many = [GradientStop { color: clear, d: 0.0 }; 4];
+    assert_eq!(
+        sheet.set_gradient_stops(gradient, &too_many),
+        Err(TooManyStops { stops: 4, capacity: 3 })
+    );
+    assert_eq!(sheet.stop_table(gradient).len, 3);
+
+    // Rebuilding the gradient with more stops moves its table.
+    sheet.set_linear_gradient(gradient, LinearGradient::new(too_many.to_vec())).unwrap();
+    let moved = sheet.stop_table(gradient);
+    assert_eq!(moved.capacity(), 4);
+    assert!(moved.range.start != table.range.start);
+    sheet.take_gpu_styles(AlphaMode::Straight);
+    sheet.compact_gradient_stops();
+    assert_eq!(sheet.stop_table(gradient).range.start, table.range.start);
+    // The styles point to the moved table.
+    assert_eq!(sheet.take_gpu_styles(AlphaMode::Straight).unwrap()[1].stops, table.range.start.0 as i32);
+    assert_eq!(sheet.gradient_stops().len(), 20);
+    assert!(sheet.is_opaque(PatternId::Color(background)));
+}
+
+#[test]
+fn style_sheet_capacity() {
+    let mut sheet = StyleSheet::new();
+    let stops = vec![GradientStop { color: Color::black(), d: 0.0 }; 4];
+    let max_stops = GRADIENT_STOP_BUFFER_LEN * 4 / GpuGradientStop::size_in_words();
+    let gradient = sheet.add_linear_gradient_with_capacity(LinearGradient::new(stops.clone()), max_stops - 4).unwrap();
+    assert_eq!(
+        sheet.add_linear_gradient_with_capacity(LinearGradient::new(stops.clone()), 5),
+        Err(StyleSheetFull::GradientStops)
+    );
+    assert_eq!(sheet.len(), 1);
+
+    // Moving the table of a gradient doesn't fit either, the gradient is left unchanged.
+    let more_stops = LinearGradient::new(vec![GradientStop { color: Color::white(), d: 0.0 }; max_stops - 3]);
+    assert_eq!(sheet.set_linear_gradient(gradient, more_stops), Err(StyleSheetFull::GradientStops));
+    assert_eq!(sheet.stop_table(gradient).capacity(), max_stops - 4);
+    assert!(sheet.add_linear_gradient(LinearGradient::new(stops)).is_ok());
+
+    while sheet.len() < PRIM_BUFFER_LEN {
+        sheet.add_color(Color::white()).unwrap();
+    }
+    assert_eq!(sheet.add_color(Color::white()), Err(StyleSheetFull::Styles));
+}
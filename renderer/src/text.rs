@@ -0,0 +1,197 @@
+//! Decorations of glyph runs.
+//!
+//! Glyphs are drawn as shapes (see `ShapeStore::add_glyph`), but underlines, overlines
+//! and strikethroughs are not part of the outlines: they are lines placed from the metrics
+//! of the font, along the whole run. A `GlyphRun` describes a line of positioned glyphs
+//! with the metrics of its font, answers where its baseline and ascent are, and produces
+//! the decorations as strokes to push with the other stroke render nodes.
+//!
+//! Decorations cross the descenders of the glyphs, like most text renderers do when they
+//! don't skip ink.
+
+use std::sync::Arc;
+
+use core::math::*;
+use path::Path;
+use path_builder::*;
+use api::{Pattern, ShapeId, StrokeStyle};
+use batch_builder::{OpaqueBatcher, PrimitiveParams, ShapeStore, Transforms, BatchError};
+#[cfg(test)]
+use api::Color;
+#[cfg(test)]
+use renderer::StrokePrimitiveId;
+
+/// The metrics of a font, in font units with the y axis pointing up as in font files.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FontMetrics {
+    pub units_per_em: f32,
+    /// The distance from the baseline to the top of the tallest glyphs.
+    pub ascent: f32,
+    /// The distance from the baseline to the bottom of the lowest glyphs, positive below it.
+    pub descent: f32,
+    /// The position of the center of the underline, negative below the baseline.
+    pub underline_position: f32,
+    pub underline_thickness: f32,
+    /// The position of the center of the strikethrough, above the baseline.
+    pub strikeout_position: f32,
+    pub strikeout_thickness: f32,
+}
+
+/// A glyph of a run, positioned relatively to the origin of the run.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PositionedGlyph {
+    pub glyph: u16,
+    pub position: Vec2,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextDecoration {
+    Underline,
+    Overline,
+    Strikethrough,
+}
+
+/// A line segment decorating a run, and the width of its stroke.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DecorationLine {
+    pub from: Point,
+    pub to: Point,
+    pub thickness: f32,
+}
+
+/// A line of glyphs of the same font and size.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlyphRun {
+    /// The identifier of the font, see `GlyphKey::font`.
+    pub font: u32,
+    pub font_size: f32,
+    pub metrics: FontMetrics,
+    /// The start of the baseline, in the space of the render nodes (y pointing down).
+    pub origin: Point,
+    pub glyphs: Vec<PositionedGlyph>,
+    /// The length of the run along the baseline, which the decorations span.
+    pub advance: f32,
+}
+
+impl GlyphRun {
+    /// The number of units of the render nodes per font unit.
+    pub fn scale(&self) -> f32 { self.font_size / self.metrics.units_per_em }
+
+    /// The y coordinate of the baseline.
+    pub fn baseline(&self) -> f32 { self.origin.y }
+
+    /// The y coordinate of the top of the tallest glyphs.
+    pub fn ascent(&self) -> f32 { self.origin.y - self.metrics.ascent * self.scale() }
+
+    /// The y coordinate of the bottom of the lowest glyphs.
+    pub fn descent(&self) -> f32 { self.origin.y + self.metrics.descent * self.scale() }
+
+    /// The area between the ascent and the descent along the run, for example to lay out
+    /// a caret or a selection.
+    pub fn bounds(&self) -> Rect {
+        let top = self.ascent();
+        return rect(self.origin.x, top, self.advance, self.descent() - top);
+    }
+
+    /// Where a decoration of the run is drawn.
+    ///
+    /// The overline is drawn at the ascent, with the thickness of the underline.
+    pub fn decoration(&self, decoration: TextDecoration) -> DecorationLine {
+        let scale = self.scale();
+        let (position, thickness) = match decoration {
+            TextDecoration::Underline => (self.metrics.underline_position, self.metrics.underline_thickness),
+            TextDecoration::Overline => (self.metrics.ascent, self.metrics.underline_thickness),
+            TextDecoration::Strikethrough => (self.metrics.strikeout_position, self.metrics.strikeout_thickness),
+        };
+        let y = self.origin.y - position * scale;
+
+        return DecorationLine {
+            from: point(self.origin.x, y),
+            to: point(self.origin.x + self.advance, y),
+            thickness: thickness * scale,
+        };
+    }
+
+    /// Adds the decorations of the run to a batcher of strokes, in the order given.
+    ///
+    /// The line of each decoration is added to `shapes` and drawn with the pattern, at the
+    /// z-index and with the transforms of the glyphs so that it stays aligned with them.
+    /// Returns the shapes of the decorations.
+    pub fn push_decorations<PrimitiveId: Copy>(
+        &self,
+        decorations: &[TextDecoration],
+        pattern: &Pattern,
+        z_index: u32,
+        transforms: Transforms,
+        shapes: &mut ShapeStore,
+        batcher: &mut OpaqueBatcher<PrimitiveId, StrokeStyle>,
+    ) -> Result<Vec<ShapeId>, BatchError> {
+        let mut result = Vec::with_capacity(decorations.len());
+        for &decoration in decorations {
+            let line = self.decoration(decoration);
+            let mut builder = Path::builder();
+            builder.move_to(line.from);
+            builder.line_to(line.to);
+            let shape = ShapeId::Path(shapes.add_path(Arc::new(builder.build())));
+
+            batcher.push_item(PrimitiveParams {
+                z_index: z_index,
+                shape: shape,
+                transforms: transforms,
+                style: StrokeStyle::new(pattern.clone(), line.thickness),
+            })?;
+            result.push(shape);
+        }
+
+        return Ok(result);
+    }
+}
+
+#[test]
+fn text_decorations() {
+    let run = GlyphRun {
+        font: 0,
+        font_size: 20.0,
+        metrics: FontMetrics {
+            units_per_em: 1000.0,
+            ascent: 800.0,
+            descent: 200.0,
+            underline_position: -100.0,
+            underline_thickness: 50.0,
+            strikeout_position: 300.0,
+            strikeout_thickness: 40.0,
+        },
+        origin: point(10.0, 100.0),
+        glyphs: vec![
+            PositionedGlyph { glyph: 3, position: vec2(0.0, 0.0) },
+            PositionedGlyph { glyph: 4, position: vec2(12.0, 0.0) },
+        ],
+        advance: 24.0,
+    };
+
+    assert_eq!(run.baseline(), 100.0);
+    assert_eq!(run.ascent(), 84.0);
+    assert_eq!(run.descent(), 104.0);
+    assert_eq!(run.bounds(), rect(10.0, 84.0, 24.0, 20.0));
+
+    let underline = run.decoration(TextDecoration::Underline);
+    assert_eq!(underline.from, point(10.0, 102.0));
+    assert_eq!(underline.to, point(34.0, 102.0));
+    assert_eq!(underline.thickness, 1.0);
+    assert_eq!(run.decoration(TextDecoration::Strikethrough).from.y, 94.0);
+    assert_eq!(run.decoration(TextDecoration::Overline).from.y, 84.0);
+
+    let mut shapes = ShapeStore::new();
+    let mut batcher: OpaqueBatcher<StrokePrimitiveId, StrokeStyle> = OpaqueBatcher::new();
+    let decorations = run.push_decorations(
+        &[TextDecoration::Underline, TextDecoration::Strikethrough],
+        &Pattern::Color(Color::black()),
+        3,
+        Transforms { local: None, view: None, root: None },
+        &mut shapes,
+        &mut batcher,
+    ).unwrap();
+    assert_eq!(decorations.len(), 2);
+    assert!(decorations.iter().all(|&shape| shapes.contains(shape)));
+    assert!(batcher.validate(&shapes).is_ok());
+}
@@ -0,0 +1,48 @@
+//! Typed units for the coordinate spaces of the renderer.
+//!
+//! Mixing up the spaces is an easy mistake to make, in particular logical and device
+//! pixels, which only differ on high-DPI displays. The public APIs dealing with several
+//! spaces (for example `Viewport`) use these types, and converting from one space to
+//! another requires an explicit scale or transform.
+//!
+//! - The local space is the space of the shapes, before their local transform.
+//! - The layer space is the space in which layers are laid out. One layer unit is one
+//!   logical pixel at zoom 1.0.
+//! - Device pixels are the pixels of the render targets.
+//!
+//! Untyped values can be converted with `from_untyped` and `to_untyped`.
+
+use core::math::*;
+
+/// The space of the shapes, before their local transform.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LocalSpace;
+
+/// The space in which layers are laid out, in logical pixels.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LayerSpace;
+
+/// The pixels of the render targets.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DevicePixel;
+
+pub type LocalPoint = TypedPoint2D<f32, LocalSpace>;
+pub type LocalVector = TypedVector2D<f32, LocalSpace>;
+pub type LocalSize = TypedSize2D<f32, LocalSpace>;
+pub type LocalRect = TypedRect<f32, LocalSpace>;
+
+pub type LayerPoint = TypedPoint2D<f32, LayerSpace>;
+pub type LayerVector = TypedVector2D<f32, LayerSpace>;
+pub type LayerSize = TypedSize2D<f32, LayerSpace>;
+pub type LayerRect = TypedRect<f32, LayerSpace>;
+
+pub type DevicePoint = TypedPoint2D<f32, DevicePixel>;
+pub type DeviceSize = TypedSize2D<f32, DevicePixel>;
+pub type DeviceRect = TypedRect<f32, DevicePixel>;
+pub type DeviceIntPoint = TypedPoint2D<i32, DevicePixel>;
+pub type DeviceIntSize = TypedSize2D<i32, DevicePixel>;
+pub type DeviceIntRect = TypedRect<i32, DevicePixel>;
+
+/// The number of device pixels per layer unit, which combines the scale factor of the
+/// display and the zoom.
+pub type DevicePixelScale = ScaleFactor<f32, LayerSpace, DevicePixel>;
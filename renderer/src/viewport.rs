@@ -0,0 +1,204 @@
+//! The mapping between the world space of the primitives and the window.
+
+use core::math::*;
+use renderer::Globals;
+use units::*;
+
+/// Keeps track of the size, scale factor, scroll offset and zoom of a window and derives
+/// what the passes need from them.
+///
+/// Layer units are logical pixels at zoom 1.0: the scale factor of high-DPI displays and
+/// the zoom both make layer units cover more device pixels (see `device_pixel_scale`).
+/// Geometry is tessellated in layer space, so the tolerance must shrink as they grow, otherwise curves look faceted
+/// when zooming in or moving the window to a high-DPI screen. Listeners registered with
+/// `on_tolerance_change` are notified when this happens so that they can re-tessellate.
+pub struct Viewport {
+    size: LayerSize,
+    scale_factor: f32,
+    scroll_offset: LayerVector,
+    zoom: f32,
+    base_tolerance: f32,
+    listeners: Vec<Box<FnMut(f32)>>,
+}
+
+impl Viewport {
+    /// Creates a viewport of the given logical size.
+    ///
+    /// `scale_factor` is the number of device pixels per logical pixel of the display and
+    /// `base_tolerance` is the tolerance in device pixels, typically around 0.1 to 0.5.
+    pub fn new(size: LayerSize, scale_factor: f32, base_tolerance: f32) -> Self {
+        Viewport {
+            size: size,
+            scale_factor: scale_factor,
+            scroll_offset: LayerVector::new(0.0, 0.0),
+            zoom: 1.0,
+            base_tolerance: base_tolerance,
+            listeners: Vec::new(),
+        }
+    }
+
+    /// Handles a resize of the window or a change of display.
+    pub fn set_viewport(&mut self, size: LayerSize, scale_factor: f32) {
+        let tolerance = self.tolerance();
+        self.size = size;
+        self.scale_factor = scale_factor;
+        self.notify_if_changed(tolerance);
+    }
+
+    pub fn set_zoom(&mut self, zoom: f32) {
+        let tolerance = self.tolerance();
+        self.zoom = zoom;
+        self.notify_if_changed(tolerance);
+    }
+
+    /// Sets the layer position that is displayed at the center of the window.
+    pub fn set_scroll_offset(&mut self, offset: LayerVector) {
+        self.scroll_offset = offset;
+    }
+
+    pub fn size(&self) -> LayerSize { self.size }
+
+    pub fn scale_factor(&self) -> f32 { self.scale_factor }
+
+    pub fn zoom(&self) -> f32 { self.zoom }
+
+    pub fn scroll_offset(&self) -> LayerVector { self.scroll_offset }
+
+    /// The size of the window in device pixels, which is the size of its render targets.
+    pub fn device_size(&self) -> DeviceIntSize {
+        DeviceIntSize::new(
+            (self.size.width * self.scale_factor).round() as i32,
+            (self.size.height * self.scale_factor).round() as i32,
+        )
+    }
+
+    /// The number of device pixels covered by a layer unit.
+    pub fn device_pixel_scale(&self) -> DevicePixelScale {
+        DevicePixelScale::new(self.scale_factor * self.zoom)
+    }
+
+    /// The tessellation tolerance in layer units that gives `base_tolerance` on screen.
+    pub fn tolerance(&self) -> f32 {
+        self.base_tolerance / self.device_pixel_scale().get()
+    }
+
+    /// The area of the layer space that is visible in the window, to cull what is outside.
+    pub fn visible_rect(&self) -> LayerRect {
+        let size = LayerSize::new(self.size.width / self.zoom, self.size.height / self.zoom);
+        let origin = LayerPoint::new(
+            self.scroll_offset.x - size.width * 0.5,
+            self.scroll_offset.y - size.height * 0.5,
+        );
+
+        return LayerRect::new(origin, size);
+    }
+
+    /// The position in device pixels of a layer point, from the top-left corner of the window.
+    pub fn layer_to_device(&self, point: LayerPoint) -> DevicePoint {
+        let scale = self.device_pixel_scale().get();
+        DevicePoint::new(
+            (point.x - self.scroll_offset.x) * scale + self.size.width * self.scale_factor * 0.5,
+            (point.y - self.scroll_offset.y) * scale + self.size.height * self.scale_factor * 0.5,
+        )
+    }
+
+    /// The layer point displayed at a position in device pixels, for example under the mouse.
+    pub fn device_to_layer(&self, point: DevicePoint) -> LayerPoint {
+        let scale = self.device_pixel_scale().get();
+        LayerPoint::new(
+            (point.x - self.size.width * self.scale_factor * 0.5) / scale + self.scroll_offset.x,
+            (point.y - self.size.height * self.scale_factor * 0.5) / scale + self.scroll_offset.y,
+        )
+    }
+
+    /// The transform from layer space to clip space, with the scroll offset at the center.
+    pub fn world_to_clip(&self) -> Transform3D {
+        let sx = 2.0 * self.zoom / self.size.width;
+        let sy = -2.0 * self.zoom / self.size.height;
+        Transform3D::row_major(
+            sx, 0.0, 0.0, 0.0,
+            0.0, sy, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            -self.scroll_offset.x * sx, -self.scroll_offset.y * sy, 0.0, 1.0,
+        )
+    }
+
+    /// The globals of the passes.
+    ///
+    /// The shaders map layer positions to clip space by dividing them by half of the
+    /// resolution, so the zoom is applied by shrinking the resolution. The scroll offset is
+    /// not applied by the shaders: it is meant to be part of the view transform.
+    pub fn globals(&self) -> Globals {
+        Globals {
+            resolution: [self.size.width / self.zoom, self.size.height / self.zoom],
+            scroll_offset: [self.scroll_offset.x, self.scroll_offset.y],
+            zoom: self.zoom,
+        }
+    }
+
+    /// Registers a function called with the new tolerance when it changes.
+    pub fn on_tolerance_change(&mut self, listener: Box<FnMut(f32)>) {
+        self.listeners.push(listener);
+    }
+
+    fn notify_if_changed(&mut self, previous_tolerance: f32) {
+        let tolerance = self.tolerance();
+        if tolerance == previous_tolerance {
+            return;
+        }
+        for listener in &mut self.listeners {
+            listener(tolerance);
+        }
+    }
+}
+
+#[cfg(test)]
+use std::rc::Rc;
+#[cfg(test)]
+use std::cell::Cell;
+
+#[test]
+fn viewport_transforms() {
+    let mut viewport = Viewport::new(LayerSize::new(800.0, 600.0), 2.0, 0.5);
+    assert_eq!(viewport.device_size(), DeviceIntSize::new(1600, 1200));
+    assert_eq!(viewport.tolerance(), 0.25);
+
+    let changes = Rc::new(Cell::new(0));
+    let counter = changes.clone();
+    viewport.on_tolerance_change(Box::new(move |_| { counter.set(counter.get() + 1); }));
+
+    // Resizing without changing the scale factor keeps the tolerance.
+    viewport.set_viewport(LayerSize::new(1000.0, 600.0), 2.0);
+    assert_eq!(changes.get(), 0);
+    viewport.set_zoom(4.0);
+    assert_eq!(changes.get(), 1);
+    assert_eq!(viewport.tolerance(), 0.0625);
+    viewport.set_viewport(LayerSize::new(1000.0, 600.0), 1.0);
+    assert_eq!(changes.get(), 2);
+
+    viewport.set_zoom(1.0);
+    viewport.set_scroll_offset(LayerVector::new(100.0, 50.0));
+    let transform = viewport.world_to_clip();
+    let near = |a: Point, b: Point| (a - b).length() < 1e-5;
+    assert!(near(transform.transform_point(&point(100.0, 50.0)), point(0.0, 0.0)));
+    assert!(near(transform.transform_point(&point(600.0, 350.0)), point(1.0, -1.0)));
+
+    assert_eq!(viewport.globals().resolution, [1000.0, 600.0]);
+}
+
+#[test]
+fn viewport_units() {
+    let mut viewport = Viewport::new(LayerSize::new(400.0, 300.0), 2.0, 0.5);
+    viewport.set_zoom(2.0);
+    viewport.set_scroll_offset(LayerVector::new(100.0, 0.0));
+    assert_eq!(viewport.device_pixel_scale().get(), 4.0);
+
+    // The window shows a quarter of its logical size at zoom 2.0.
+    assert_eq!(viewport.visible_rect(), LayerRect::new(LayerPoint::new(0.0, -75.0), LayerSize::new(200.0, 150.0)));
+
+    // The scroll offset is at the center of the 800x600 device pixels.
+    assert_eq!(viewport.layer_to_device(LayerPoint::new(100.0, 0.0)), DevicePoint::new(400.0, 300.0));
+    assert_eq!(viewport.layer_to_device(LayerPoint::new(0.0, -75.0)), DevicePoint::new(0.0, 0.0));
+    let p = LayerPoint::new(123.0, -45.0);
+    assert_eq!(viewport.device_to_layer(viewport.layer_to_device(p)), p);
+}
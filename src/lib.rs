@@ -184,6 +184,40 @@
 //! These crates are not very big, it's usually fine for most use-case to simply import the `lyon`
 //! meta-crate, unless you are only interested in the bezier tools.
 //!
+//! ## The prelude
+//!
+//! The most commonly used types and traits of the various lyon crates are reexported
+//! in the `prelude` module, so that most programs can simply start with:
+//!
+//! ```
+//! use lyon::prelude::*;
+//! ```
+//!
+//! Code that only goes through the prelude and the reexported crates (`lyon::tessellation`,
+//! `lyon::path`, etc.) does not depend on how the items are organized within each crate.
+//!
+//! ## Cargo features
+//!
+//! The optional crates are gated behind cargo features so that CPU-only users (for example
+//! tessellating paths on a server) don't have to pull in unneeded dependencies:
+//!
+//! - `extra` (enabled by default) reexports `lyon_extra` as `lyon::extra`.
+//! - `svg` (enabled by default) reexports `lyon_svg` as `lyon::svg` and depends on `svgparser`.
+//! - `renderer` (disabled by default) reexports the experimental `lyon_renderer` crate as
+//!   `lyon::renderer` and depends on gfx-rs.
+//! - `serialization` (disabled by default) implements serde's `Serialize` and `Deserialize`
+//!   traits for `lyon::path::Path`.
+//! - `glyphs` (disabled by default) enables the `lyon::extra::glyphs` module which converts
+//!   font glyph outlines into paths, and depends on `ttf-parser`.
+//!
+//! The path, path builder/iterator, bezier and tessellation crates are always available.
+//! For the smallest dependency footprint, use `default-features = false`:
+//!
+//! ```toml
+//! [dependencies]
+//! lyon = { version = "0.7", default-features = false }
+//! ```
+//!
 
 
 
@@ -193,9 +227,12 @@ pub extern crate lyon_path_builder;
 pub extern crate lyon_path_iterator;
 pub extern crate lyon_tessellation;
 pub extern crate lyon_bezier;
+#[cfg(feature = "extra")]
 pub extern crate lyon_extra;
+#[cfg(feature = "svg")]
 pub extern crate lyon_svg;
-//pub extern crate lyon_renderer;
+#[cfg(feature = "renderer")]
+pub extern crate lyon_renderer;
 
 pub use lyon_core::*;
 
@@ -204,6 +241,11 @@ pub use lyon_path as path;
 pub use lyon_path_builder as path_builder;
 pub use lyon_path_iterator as path_iterator;
 pub use lyon_bezier as bezier;
+#[cfg(feature = "extra")]
 pub use lyon_extra as extra;
+#[cfg(feature = "svg")]
 pub use lyon_svg as svg;
-//pub use lyon_renderer as renderer;
+#[cfg(feature = "renderer")]
+pub use lyon_renderer as renderer;
+
+pub mod prelude;
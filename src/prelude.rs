@@ -0,0 +1,39 @@
+//! Reexports the most commonly used items of the lyon crates.
+//!
+//! ```
+//! use lyon::prelude::*;
+//!
+//! let mut builder = Path::builder();
+//! builder.move_to(point(0.0, 0.0));
+//! builder.line_to(point(1.0, 0.0));
+//! builder.line_to(point(1.0, 1.0));
+//! builder.close();
+//! let path = builder.build();
+//!
+//! let mut geometry: VertexBuffers<FillVertex> = VertexBuffers::new();
+//! FillTessellator::new().tessellate_path(
+//!     path.path_iter(),
+//!     &FillOptions::default(),
+//!     &mut simple_builder(&mut geometry),
+//! ).unwrap();
+//! ```
+
+pub use lyon_core::math::{
+    Point, Vec2, Size, Rect, Transform2D, Radians,
+    point, vec2, size, rect,
+};
+pub use lyon_core::{PathEvent, SvgEvent, FlattenedEvent, ArcFlags};
+
+pub use lyon_path::{Path, PathSlice};
+pub use lyon_path_builder::{BaseBuilder, PathBuilder, SvgBuilder, PolygonBuilder};
+pub use lyon_path_iterator::{PathIterator, SvgIterator, FlattenedIterator};
+
+pub use lyon_bezier::{QuadraticBezierSegment, CubicBezierSegment, LineSegment, Arc};
+
+pub use lyon_tessellation::{
+    FillTessellator, FillOptions, FillVertex, FillRule,
+    StrokeTessellator, StrokeOptions, StrokeVertex, LineCap, LineJoin,
+    GeometryBuilder, VertexBuffers, BuffersBuilder, VertexConstructor, Count,
+};
+pub use lyon_tessellation::geometry_builder::simple_builder;
+pub use lyon_tessellation::basic_shapes;
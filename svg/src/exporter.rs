@@ -0,0 +1,250 @@
+//! Export of vector images into SVG documents.
+//!
+//! `SvgExporter` is a `VectorImageBuilder` that records the shapes, styles and transforms it
+//! receives and writes them out as an SVG document. Since the content is exported before
+//! tessellation, rendering the document with a reference rasterizer helps telling apart
+//! tessellation bugs from rendering bugs.
+
+use importer::{VectorImageBuilder, RgbaColor, GradientStop, PatternId};
+use serializer::write_path_data;
+use core::math::*;
+use path::Path;
+
+use std::fmt::Write;
+
+/// Records the content of a vector image and serializes it into an SVG document.
+pub struct SvgExporter {
+    colors: Vec<RgbaColor>,
+    gradients: Vec<(Point, Point, Vec<GradientStop>)>,
+    transforms: Vec<Transform2D>,
+    items: Vec<Item>,
+}
+
+enum Item {
+    Shape(Shape),
+    PushGroup(f32),
+    PopGroup,
+}
+
+struct Shape {
+    path: Path,
+    pattern: PatternId<usize, usize>,
+    // None for fills.
+    stroke_width: Option<f32>,
+    transform: Option<usize>,
+}
+
+impl SvgExporter {
+    pub fn new() -> Self {
+        SvgExporter {
+            colors: Vec::new(),
+            gradients: Vec::new(),
+            transforms: Vec::new(),
+            items: Vec::new(),
+        }
+    }
+
+    /// Writes the recorded content as an SVG document.
+    pub fn write_svg(&self, output: &mut String) {
+        output.push_str("<svg xmlns=\"http://www.w3.org/2000/svg\">\n");
+
+        if !self.gradients.is_empty() {
+            output.push_str("  <defs>\n");
+            for (i, &(from, to, ref stops)) in self.gradients.iter().enumerate() {
+                write!(
+                    output,
+                    "    <linearGradient id=\"gradient{}\" gradientUnits=\"userSpaceOnUse\" x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\">\n",
+                    i, from.x, from.y, to.x, to.y,
+                ).unwrap();
+                for stop in stops {
+                    write!(
+                        output,
+                        "      <stop offset=\"{}\" stop-color=\"{}\" stop-opacity=\"{}\"/>\n",
+                        stop.offset, rgb(stop.color), opacity(stop.color),
+                    ).unwrap();
+                }
+                output.push_str("    </linearGradient>\n");
+            }
+            output.push_str("  </defs>\n");
+        }
+
+        let mut indent = String::from("  ");
+        for item in &self.items {
+            let shape = match *item {
+                Item::Shape(ref shape) => shape,
+                Item::PushGroup(opacity) => {
+                    write!(output, "{}<g opacity=\"{}\">\n", indent, opacity).unwrap();
+                    indent.push_str("  ");
+                    continue;
+                }
+                Item::PopGroup => {
+                    // A pop without a matching push would close the svg element.
+                    if indent.len() <= 2 {
+                        continue;
+                    }
+                    let len = indent.len().saturating_sub(2);
+                    indent.truncate(len);
+                    write!(output, "{}</g>\n", indent).unwrap();
+                    continue;
+                }
+            };
+
+            output.push_str(&indent);
+            output.push_str("<path d=\"");
+            let mut data = String::new();
+            write_path_data(shape.path.iter(), &mut data);
+            output.push_str(&data);
+            output.push('"');
+
+            let paint = match shape.stroke_width {
+                Some(width) => {
+                    write!(output, " fill=\"none\" stroke-width=\"{}\"", width).unwrap();
+                    "stroke"
+                }
+                None => "fill",
+            };
+
+            match shape.pattern {
+                PatternId::Color(id) => {
+                    let color = self.colors[id];
+                    write!(output, " {}=\"{}\"", paint, rgb(color)).unwrap();
+                    if color.a != 255 {
+                        write!(output, " {}-opacity=\"{}\"", paint, opacity(color)).unwrap();
+                    }
+                }
+                PatternId::Gradient(id) => {
+                    write!(output, " {}=\"url(#gradient{})\"", paint, id).unwrap();
+                }
+            }
+
+            if let Some(id) = shape.transform {
+                let m = &self.transforms[id];
+                write!(
+                    output,
+                    " transform=\"matrix({} {} {} {} {} {})\"",
+                    m.m11, m.m12, m.m21, m.m22, m.m31, m.m32,
+                ).unwrap();
+            }
+
+            output.push_str("/>\n");
+        }
+
+        output.push_str("</svg>\n");
+    }
+
+    /// Returns the recorded content as an SVG document.
+    pub fn to_svg_string(&self) -> String {
+        let mut output = String::new();
+        self.write_svg(&mut output);
+        return output;
+    }
+}
+
+impl VectorImageBuilder for SvgExporter {
+    type ColorId = usize;
+    type GradientId = usize;
+    type TransformId = usize;
+
+    fn add_color(&mut self, color: RgbaColor) -> usize {
+        self.colors.push(color);
+        return self.colors.len() - 1;
+    }
+
+    fn add_linear_gradient(&mut self, from: Point, to: Point, stops: &[GradientStop]) -> usize {
+        self.gradients.push((from, to, stops.to_vec()));
+        return self.gradients.len() - 1;
+    }
+
+    fn add_transform(&mut self, transform: &Transform2D) -> usize {
+        self.transforms.push(*transform);
+        return self.transforms.len() - 1;
+    }
+
+    fn fill(&mut self, path: Path, pattern: PatternId<usize, usize>, transform: Option<usize>) {
+        self.items.push(Item::Shape(Shape {
+            path: path,
+            pattern: pattern,
+            stroke_width: None,
+            transform: transform,
+        }));
+    }
+
+    fn stroke(&mut self, path: Path, pattern: PatternId<usize, usize>, width: f32, transform: Option<usize>) {
+        self.items.push(Item::Shape(Shape {
+            path: path,
+            pattern: pattern,
+            stroke_width: Some(width),
+            transform: transform,
+        }));
+    }
+
+    fn push_group(&mut self, opacity: f32) -> bool {
+        self.items.push(Item::PushGroup(opacity));
+        return true;
+    }
+
+    fn pop_group(&mut self) {
+        self.items.push(Item::PopGroup);
+    }
+}
+
+fn rgb(color: RgbaColor) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+fn opacity(color: RgbaColor) -> f32 {
+    color.a as f32 / 255.0
+}
+
+#[test]
+fn test_export_round_trip() {
+    use importer::import_svg;
+    use path_builder::*;
+
+    let mut exporter = SvgExporter::new();
+    let red = exporter.add_color(RgbaColor { r: 255, g: 0, b: 0, a: 128 });
+    let gradient = exporter.add_linear_gradient(
+        point(0.0, 0.0),
+        point(10.0, 0.0),
+        &[
+            GradientStop { offset: 0.0, color: RgbaColor { r: 0, g: 0, b: 0, a: 255 } },
+            GradientStop { offset: 1.0, color: RgbaColor { r: 255, g: 255, b: 255, a: 255 } },
+        ],
+    );
+    let transform = exporter.add_transform(&Transform2D::create_translation(1.0, 2.0));
+
+    let mut builder = Path::builder();
+    builder.move_to(point(0.0, 0.0));
+    builder.line_to(point(10.0, 0.0));
+    builder.line_to(point(10.0, 10.0));
+    builder.close();
+    let path = builder.build();
+
+    exporter.fill(path.clone(), PatternId::Gradient(gradient), None);
+    exporter.push_group(0.5);
+    exporter.stroke(path, PatternId::Color(red), 2.0, Some(transform));
+    exporter.pop_group();
+
+    let svg = exporter.to_svg_string();
+    assert!(svg.contains("<path d=\"M 0 0 L 10 0 L 10 10 Z\" fill=\"url(#gradient0)\"/>"));
+    assert!(svg.contains("stroke=\"#ff0000\""));
+    assert!(svg.contains("transform=\"matrix(1 0 0 1 1 2)\""));
+    assert!(svg.contains("  <g opacity=\"0.5\">\n    <path d="));
+
+    // Importing the exported document produces the same content.
+    let mut reimported = SvgExporter::new();
+    import_svg(&svg, &mut reimported).unwrap();
+    assert_eq!(reimported.to_svg_string(), svg);
+}
+
+#[test]
+fn test_export_unbalanced_groups() {
+    let mut exporter = SvgExporter::new();
+    exporter.pop_group();
+    exporter.push_group(0.5);
+    exporter.pop_group();
+    exporter.pop_group();
+
+    let svg = exporter.to_svg_string();
+    assert_eq!(svg.matches("</g>").count(), 1);
+}
@@ -0,0 +1,939 @@
+//! Import of SVG documents.
+//!
+//! The importer walks the element tree of an SVG document and forwards the drawable content
+//! (shapes, colors, gradients and transforms) to a `VectorImageBuilder`. It supports groups,
+//! the `transform` attribute, fills and strokes (including the `style` attribute), the
+//! basic shapes, paths and linear gradients.
+//!
+//! The importer does not aim at being a complete SVG implementation: units are ignored,
+//! gradients must be defined before they are referenced and unsupported elements are skipped.
+//! Gradient vectors follow `gradientUnits` though: by default they are relative to the
+//! bounding box of each shape that uses them, and percentages in user space are relative to
+//! the `width` and `height` of the root `svg` element.
+
+use svgparser::{self, Tokenize, TextFrame};
+use svgparser::svg::{Token, ElementEnd};
+
+use parser::{AttributeId, AttributeValue, RefAttributeValue, ElementId, ValueId, Color, LengthUnit};
+use parser::{PathTokenizer, StyleTokenizer};
+use core::ArcFlags;
+use core::math::*;
+use path::Path;
+use path_builder::*;
+
+use std::collections::HashMap;
+
+/// A color with an alpha channel.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RgbaColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+/// A color stop of a gradient.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GradientStop {
+    pub offset: f32,
+    pub color: RgbaColor,
+}
+
+/// The paint of a fill or a stroke.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PatternId<ColorId, GradientId> {
+    Color(ColorId),
+    Gradient(GradientId),
+}
+
+/// Receives the content of an imported SVG document.
+///
+/// Transforms are passed in document space, so implementations don't need to keep track
+/// of the group hierarchy.
+pub trait VectorImageBuilder {
+    type ColorId: Copy;
+    type GradientId: Copy;
+    type TransformId: Copy;
+
+    fn add_color(&mut self, color: RgbaColor) -> Self::ColorId;
+
+    fn add_linear_gradient(&mut self, from: Point, to: Point, stops: &[GradientStop]) -> Self::GradientId;
+
+    fn add_transform(&mut self, transform: &Transform2D) -> Self::TransformId;
+
+    fn fill(
+        &mut self,
+        path: Path,
+        pattern: PatternId<Self::ColorId, Self::GradientId>,
+        transform: Option<Self::TransformId>,
+    );
+
+    fn stroke(
+        &mut self,
+        path: Path,
+        pattern: PatternId<Self::ColorId, Self::GradientId>,
+        width: f32,
+        transform: Option<Self::TransformId>,
+    );
+
+    /// Starts a group of shapes that are composited as a unit with the provided opacity,
+    /// so that overlapping shapes of the group don't show through each other.
+    ///
+    /// Returns false if groups are not supported, in which case the opacity is applied to
+    /// each shape of the group instead. The default implementation does that.
+    fn push_group(&mut self, _opacity: f32) -> bool { false }
+
+    /// Ends the last group started with a successful call to `push_group`.
+    fn pop_group(&mut self) {}
+}
+
+/// The handles allocated while importing a document, mapped by the `id` attribute of the
+/// elements that caused their allocation.
+pub struct ImportedHandles<Builder: VectorImageBuilder> {
+    /// Fill colors of the identified shapes, or their stroke colors if they aren't filled
+    /// with a plain color.
+    pub colors: HashMap<String, Builder::ColorId>,
+    /// The identified gradient elements.
+    ///
+    /// Gradients relative to the bounding box of the shapes (the default `gradientUnits`)
+    /// are added to the builder for each shape that uses them, the last one is kept here.
+    pub gradients: HashMap<String, Builder::GradientId>,
+    /// Transforms of the identified elements that have a `transform` attribute.
+    pub transforms: HashMap<String, Builder::TransformId>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ImportError;
+
+/// Parses an SVG document and emits its content into a `VectorImageBuilder`.
+pub fn import_svg<Builder: VectorImageBuilder>(
+    src: &str,
+    builder: &mut Builder,
+) -> Result<ImportedHandles<Builder>, ImportError> {
+    let mut importer = Importer {
+        builder: builder,
+        handles: ImportedHandles {
+            colors: HashMap::new(),
+            gradients: HashMap::new(),
+            transforms: HashMap::new(),
+        },
+        stack: vec![Group::root()],
+        current: None,
+        gradient: None,
+        bbox_gradients: HashMap::new(),
+        viewport: size(0.0, 0.0),
+    };
+
+    let mut tokenizer = svgparser::svg::Tokenizer::from_str(src);
+    loop {
+        let token = match tokenizer.parse_next() {
+            Ok(token) => token,
+            Err(_) => { return Err(ImportError); }
+        };
+        match token {
+            Token::SvgElementStart(id) => {
+                importer.current = Some(Element::new(Some(id)));
+            }
+            Token::XmlElementStart(_) => {
+                importer.current = Some(Element::new(None));
+            }
+            Token::SvgAttribute(attribute, value) => {
+                if let Some(ref mut element) = importer.current {
+                    element.set_attribute(attribute, value)?;
+                }
+            }
+            Token::ElementEnd(ElementEnd::Open) => {
+                if let Some(element) = importer.current.take() {
+                    let group = importer.open_element(element);
+                    importer.stack.push(group);
+                }
+            }
+            Token::ElementEnd(ElementEnd::Empty) => {
+                if let Some(element) = importer.current.take() {
+                    let group = importer.open_element(element);
+                    if group.pushed_group {
+                        importer.builder.pop_group();
+                    }
+                }
+            }
+            Token::ElementEnd(_) => {
+                if let Some(group) = importer.stack.pop() {
+                    importer.close_element(group);
+                }
+            }
+            Token::EndOfStream => {
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    return Ok(importer.handles);
+}
+
+struct Importer<'l, Builder: VectorImageBuilder + 'l> {
+    builder: &'l mut Builder,
+    handles: ImportedHandles<Builder>,
+    // The inherited state of the open elements.
+    stack: Vec<Group<Builder::TransformId>>,
+    // The element which attributes are being parsed.
+    current: Option<Element>,
+    // The linear gradient which stops are being parsed.
+    gradient: Option<LinearGradient>,
+    // The gradients in objectBoundingBox units, which are added for each shape using them.
+    bbox_gradients: HashMap<String, LinearGradient>,
+    // The size percentages in user space are relative to.
+    viewport: Size,
+}
+
+// A linear gradient element, before its vector is resolved.
+struct LinearGradient {
+    name: String,
+    user_space: bool,
+    from: (Coordinate, Coordinate),
+    to: (Coordinate, Coordinate),
+    stops: Vec<GradientStop>,
+}
+
+impl LinearGradient {
+    // The gradient vector in user space, for gradients in userSpaceOnUse units.
+    fn user_space_vector(&self, viewport: Size) -> (Point, Point) {
+        let resolve = |(x, y): (Coordinate, Coordinate)| {
+            point(x.resolve(viewport.width), y.resolve(viewport.height))
+        };
+        return (resolve(self.from), resolve(self.to));
+    }
+
+    // The gradient vector in user space, for gradients in objectBoundingBox units.
+    //
+    // The gradient is defined in the unit square mapped to the bounding box, where its
+    // isolines are perpendicular to the vector. They aren't anymore once the square is
+    // stretched to the box, so the returned vector is the one perpendicular to the
+    // stretched isolines, which gives the same colors.
+    fn bounding_box_vector(&self, bounds: &Rect) -> (Point, Point) {
+        let (w, h) = (bounds.size.width, bounds.size.height);
+        let fraction = |(x, y): (Coordinate, Coordinate)| point(x.resolve(1.0), y.resolve(1.0));
+        let (from, to) = (fraction(self.from), fraction(self.to));
+        let d = to - from;
+        let from = bounds.origin + vec2(from.x * w, from.y * h);
+        let normal = vec2(h * d.x, w * d.y);
+        let square_length = normal.dot(normal);
+        if square_length == 0.0 {
+            return (from, from);
+        }
+        let to = from + normal * (w * h * d.dot(d) / square_length);
+        return (from, to);
+    }
+}
+
+// A coordinate of a gradient vector.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Coordinate {
+    Number(f32),
+    // A percentage of the reference length, as a fraction.
+    Fraction(f32),
+}
+
+impl Coordinate {
+    fn resolve(self, reference: f32) -> f32 {
+        match self {
+            Coordinate::Number(n) => n,
+            Coordinate::Fraction(f) => f * reference,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Paint {
+    None,
+    Color(Color),
+    Url(String),
+}
+
+// Presentation state inherited from the parent elements.
+struct Group<TransformId> {
+    element: Option<ElementId>,
+    transform: Transform2D,
+    transform_id: Option<TransformId>,
+    fill: Paint,
+    stroke: Paint,
+    stroke_width: f32,
+    opacity: f32,
+    fill_opacity: f32,
+    stroke_opacity: f32,
+    // Content of definitions is not rendered directly.
+    hidden: bool,
+    // Whether the element started a group in the builder.
+    pushed_group: bool,
+}
+
+impl<TransformId: Copy> Group<TransformId> {
+    fn root() -> Self {
+        Group {
+            element: None,
+            transform: Transform2D::identity(),
+            transform_id: None,
+            fill: Paint::Color(Color::new(0, 0, 0)),
+            stroke: Paint::None,
+            stroke_width: 1.0,
+            opacity: 1.0,
+            fill_opacity: 1.0,
+            stroke_opacity: 1.0,
+            hidden: false,
+            pushed_group: false,
+        }
+    }
+}
+
+// The attributes of an element, as they are parsed.
+struct Element {
+    id: Option<ElementId>,
+    name: Option<String>,
+    transform: Option<Transform2D>,
+    fill: Option<Paint>,
+    stroke: Option<Paint>,
+    path: Option<Path>,
+    points: Vec<Point>,
+    numbers: Vec<(AttributeId, f32)>,
+    // The attributes in `numbers` that were given as percentages.
+    percentages: Vec<AttributeId>,
+    user_space_units: bool,
+    stop_color: Option<Color>,
+}
+
+impl Element {
+    fn new(id: Option<ElementId>) -> Self {
+        Element {
+            id: id,
+            name: None,
+            transform: None,
+            fill: None,
+            stroke: None,
+            path: None,
+            points: Vec::new(),
+            numbers: Vec::new(),
+            percentages: Vec::new(),
+            user_space_units: false,
+            stop_color: None,
+        }
+    }
+
+    fn number(&self, attribute: AttributeId) -> Option<f32> {
+        for &(id, value) in &self.numbers {
+            if id == attribute {
+                return Some(value);
+            }
+        }
+        return None;
+    }
+
+    fn number_or_zero(&self, attribute: AttributeId) -> f32 {
+        self.number(attribute).unwrap_or(0.0)
+    }
+
+    fn set_number(&mut self, attribute: AttributeId, value: f32) {
+        self.numbers.retain(|&(id, _)| id != attribute);
+        self.percentages.retain(|&id| id != attribute);
+        self.numbers.push((attribute, value));
+    }
+
+    fn coordinate(&self, attribute: AttributeId, default: Coordinate) -> Coordinate {
+        match self.number(attribute) {
+            Some(n) if self.percentages.contains(&attribute) => Coordinate::Fraction(n / 100.0),
+            Some(n) => Coordinate::Number(n),
+            None => default,
+        }
+    }
+
+    fn set_attribute(&mut self, attribute: AttributeId, value: TextFrame) -> Result<(), ImportError> {
+        match attribute {
+            AttributeId::Id => {
+                self.name = Some(value.slice().to_string());
+            }
+            AttributeId::Transform => {
+                self.transform = Some(parse_transform(value)?);
+            }
+            AttributeId::D => {
+                let mut builder = Path::builder().with_svg();
+                for evt in PathTokenizer::from_frame(value) {
+                    match evt {
+                        Ok(evt) => { builder.svg_event(evt); }
+                        Err(_) => { return Err(ImportError); }
+                    }
+                }
+                self.path = Some(builder.build());
+            }
+            AttributeId::Points => {
+                self.points = parse_points(value.slice())?;
+            }
+            AttributeId::Style => {
+                for attribute in StyleTokenizer::from_frame(value) {
+                    match attribute {
+                        Ok(attribute) => { self.set_value(attribute.id, attribute.value); }
+                        Err(_) => { return Err(ImportError); }
+                    }
+                }
+            }
+            AttributeId::Fill | AttributeId::Stroke => {
+                let element = self.id.unwrap_or(ElementId::Rect);
+                let paint = match RefAttributeValue::from_frame(element, attribute, value) {
+                    Ok(RefAttributeValue::FuncIRI(link)) => Some(Paint::Url(link.to_string())),
+                    Ok(other) => paint_from_value(&AttributeValue::from_ref(other)),
+                    Err(_) => { return Err(ImportError); }
+                };
+                if attribute == AttributeId::Fill {
+                    self.fill = paint.or(self.fill.take());
+                } else {
+                    self.stroke = paint.or(self.stroke.take());
+                }
+            }
+            _ => {
+                let element = self.id.unwrap_or(ElementId::Rect);
+                match RefAttributeValue::from_frame(element, attribute, value) {
+                    Ok(value) => { self.set_value(attribute, AttributeValue::from_ref(value)); }
+                    Err(_) => { return Err(ImportError); }
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn set_value(&mut self, attribute: AttributeId, value: AttributeValue) {
+        match (attribute, value) {
+            (AttributeId::Fill, value) => {
+                self.fill = paint_from_value(&value).or(self.fill.take());
+            }
+            (AttributeId::Stroke, value) => {
+                self.stroke = paint_from_value(&value).or(self.stroke.take());
+            }
+            (AttributeId::StopColor, AttributeValue::RgbColor(color)) => {
+                self.stop_color = Some(color);
+            }
+            (_, AttributeValue::Number(n)) => {
+                self.set_number(attribute, n as f32);
+            }
+            (AttributeId::GradientUnits, AttributeValue::KeyWord(units)) => {
+                self.user_space_units = units == ValueId::UserSpaceOnUse;
+            }
+            (_, AttributeValue::Length(length)) => {
+                self.set_number(attribute, length.num as f32);
+                if length.unit == LengthUnit::Percent {
+                    self.percentages.push(attribute);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Builds the geometry of a shape element.
+    fn shape(&mut self) -> Option<Path> {
+        let id = match self.id {
+            Some(id) => id,
+            None => { return None; }
+        };
+
+        match id {
+            ElementId::Path => {
+                return self.path.take();
+            }
+            ElementId::Rect => {
+                let x = self.number_or_zero(AttributeId::X);
+                let y = self.number_or_zero(AttributeId::Y);
+                let w = self.number_or_zero(AttributeId::Width);
+                let h = self.number_or_zero(AttributeId::Height);
+                if w <= 0.0 || h <= 0.0 {
+                    return None;
+                }
+                let mut builder = Path::builder();
+                builder.move_to(point(x, y));
+                builder.line_to(point(x + w, y));
+                builder.line_to(point(x + w, y + h));
+                builder.line_to(point(x, y + h));
+                builder.close();
+                return Some(builder.build());
+            }
+            ElementId::Circle => {
+                let r = self.number_or_zero(AttributeId::R);
+                return ellipse(self.center(), vec2(r, r));
+            }
+            ElementId::Ellipse => {
+                let rx = self.number_or_zero(AttributeId::Rx);
+                let ry = self.number_or_zero(AttributeId::Ry);
+                return ellipse(self.center(), vec2(rx, ry));
+            }
+            ElementId::Line => {
+                let mut builder = Path::builder();
+                builder.move_to(point(
+                    self.number_or_zero(AttributeId::X1),
+                    self.number_or_zero(AttributeId::Y1),
+                ));
+                builder.line_to(point(
+                    self.number_or_zero(AttributeId::X2),
+                    self.number_or_zero(AttributeId::Y2),
+                ));
+                return Some(builder.build());
+            }
+            ElementId::Polyline | ElementId::Polygon => {
+                if self.points.is_empty() {
+                    return None;
+                }
+                let mut builder = Path::builder();
+                builder.move_to(self.points[0]);
+                for p in &self.points[1..] {
+                    builder.line_to(*p);
+                }
+                if id == ElementId::Polygon {
+                    builder.close();
+                }
+                return Some(builder.build());
+            }
+            _ => {
+                return None;
+            }
+        }
+    }
+
+    fn center(&self) -> Point {
+        point(self.number_or_zero(AttributeId::Cx), self.number_or_zero(AttributeId::Cy))
+    }
+}
+
+impl<'l, Builder: VectorImageBuilder> Importer<'l, Builder> {
+    // Applies an element and returns the state inherited by its children.
+    fn open_element(&mut self, mut element: Element) -> Group<Builder::TransformId> {
+        let group = {
+            let parent = self.stack.last().unwrap();
+            let mut group = Group {
+                element: element.id,
+                transform: parent.transform,
+                transform_id: parent.transform_id,
+                fill: element.fill.take().unwrap_or(parent.fill.clone()),
+                stroke: element.stroke.take().unwrap_or(parent.stroke.clone()),
+                stroke_width: element.number(AttributeId::StrokeWidth).unwrap_or(parent.stroke_width),
+                opacity: parent.opacity * element.number(AttributeId::Opacity).unwrap_or(1.0),
+                fill_opacity: element.number(AttributeId::FillOpacity).unwrap_or(parent.fill_opacity),
+                stroke_opacity: element.number(AttributeId::StrokeOpacity).unwrap_or(parent.stroke_opacity),
+                hidden: parent.hidden,
+                pushed_group: false,
+            };
+
+            if let Some(local) = element.transform {
+                group.transform = local.post_mul(&parent.transform);
+                let transform_id = self.builder.add_transform(&group.transform);
+                group.transform_id = Some(transform_id);
+                if let Some(ref name) = element.name {
+                    self.handles.transforms.insert(name.clone(), transform_id);
+                }
+            }
+
+            group
+        };
+
+        match element.id {
+            Some(ElementId::Defs) | Some(ElementId::ClipPath) | Some(ElementId::Mask)
+            | Some(ElementId::Symbol) | Some(ElementId::Pattern) => {
+                return Group { hidden: true, .. group };
+            }
+            Some(ElementId::Svg) => {
+                let is_percentage = |attribute| element.percentages.contains(&attribute);
+                if self.stack.len() == 1 && !is_percentage(AttributeId::Width) && !is_percentage(AttributeId::Height) {
+                    self.viewport = size(
+                        element.number_or_zero(AttributeId::Width),
+                        element.number_or_zero(AttributeId::Height),
+                    );
+                }
+            }
+            Some(ElementId::LinearGradient) => {
+                // The vector goes from 0% to 100% of the width by default.
+                let zero = Coordinate::Fraction(0.0);
+                self.gradient = Some(LinearGradient {
+                    name: element.name.take().unwrap_or(String::new()),
+                    user_space: element.user_space_units,
+                    from: (element.coordinate(AttributeId::X1, zero), element.coordinate(AttributeId::Y1, zero)),
+                    to: (
+                        element.coordinate(AttributeId::X2, Coordinate::Fraction(1.0)),
+                        element.coordinate(AttributeId::Y2, zero),
+                    ),
+                    stops: Vec::new(),
+                });
+                return Group { hidden: true, .. group };
+            }
+            Some(ElementId::Stop) => {
+                if let Some(LinearGradient { ref mut stops, .. }) = self.gradient {
+                    let color = element.stop_color.unwrap_or(Color::new(0, 0, 0));
+                    let opacity = element.number(AttributeId::StopOpacity).unwrap_or(1.0);
+                    stops.push(GradientStop {
+                        offset: element.number_or_zero(AttributeId::Offset).max(0.0).min(1.0),
+                        color: rgba(color, opacity),
+                    });
+                }
+                return group;
+            }
+            _ => {}
+        }
+
+        if group.hidden {
+            return group;
+        }
+
+        if element.id == Some(ElementId::G) {
+            let opacity = element.number(AttributeId::Opacity).unwrap_or(1.0);
+            if opacity < 1.0 && self.builder.push_group(opacity.max(0.0)) {
+                // The builder applies the opacity to the group as a whole.
+                let parent_opacity = self.stack.last().unwrap().opacity;
+                return Group { opacity: parent_opacity, pushed_group: true, .. group };
+            }
+        }
+
+        if let Some(path) = element.shape() {
+            let is_line = element.id == Some(ElementId::Line);
+            let bounds = path.bounding_rect();
+            let fill = if is_line { None } else { self.pattern(&group.fill, group.opacity * group.fill_opacity, &bounds) };
+            let stroke = self.pattern(&group.stroke, group.opacity * group.stroke_opacity, &bounds);
+
+            if let Some(name) = element.name {
+                match (fill, stroke) {
+                    (Some(PatternId::Color(color)), _) | (_, Some(PatternId::Color(color))) => {
+                        self.handles.colors.insert(name, color);
+                    }
+                    _ => {}
+                }
+            }
+
+            match (fill, stroke) {
+                (Some(fill), Some(stroke)) => {
+                    self.builder.fill(path.clone(), fill, group.transform_id);
+                    self.builder.stroke(path, stroke, group.stroke_width, group.transform_id);
+                }
+                (Some(fill), None) => {
+                    self.builder.fill(path, fill, group.transform_id);
+                }
+                (None, Some(stroke)) => {
+                    self.builder.stroke(path, stroke, group.stroke_width, group.transform_id);
+                }
+                (None, None) => {}
+            }
+        }
+
+        return group;
+    }
+
+    fn close_element(&mut self, group: Group<Builder::TransformId>) {
+        if group.pushed_group {
+            self.builder.pop_group();
+        }
+
+        if group.element != Some(ElementId::LinearGradient) {
+            return;
+        }
+
+        let gradient = match self.gradient.take() {
+            Some(gradient) => gradient,
+            None => { return; }
+        };
+        if !gradient.user_space {
+            // The vector depends on the shapes using the gradient.
+            if !gradient.name.is_empty() {
+                self.bbox_gradients.insert(gradient.name.clone(), gradient);
+            }
+            return;
+        }
+
+        let (from, to) = gradient.user_space_vector(self.viewport);
+        let id = self.builder.add_linear_gradient(from, to, &gradient.stops);
+        if !gradient.name.is_empty() {
+            self.bbox_gradients.remove(&gradient.name);
+            self.handles.gradients.insert(gradient.name, id);
+        }
+    }
+
+    fn pattern(&mut self, paint: &Paint, opacity: f32, bounds: &Rect) -> Option<PatternId<Builder::ColorId, Builder::GradientId>> {
+        match *paint {
+            Paint::None => None,
+            Paint::Color(color) => {
+                Some(PatternId::Color(self.builder.add_color(rgba(color, opacity))))
+            }
+            Paint::Url(ref link) => {
+                if let Some(gradient) = self.bbox_gradients.get(link) {
+                    // Like in browsers, bounding box units don't work with flat shapes.
+                    if bounds.size.width <= 0.0 || bounds.size.height <= 0.0 {
+                        return None;
+                    }
+                    let (from, to) = gradient.bounding_box_vector(bounds);
+                    let id = self.builder.add_linear_gradient(from, to, &gradient.stops);
+                    self.handles.gradients.insert(link.clone(), id);
+                    return Some(PatternId::Gradient(id));
+                }
+                self.handles.gradients.get(link).map(|gradient| PatternId::Gradient(*gradient))
+            }
+        }
+    }
+}
+
+fn paint_from_value(value: &AttributeValue) -> Option<Paint> {
+    match *value {
+        AttributeValue::RgbColor(color) => Some(Paint::Color(color)),
+        AttributeValue::KeyWord(ValueId::None) => Some(Paint::None),
+        _ => None,
+    }
+}
+
+fn rgba(color: Color, opacity: f32) -> RgbaColor {
+    RgbaColor {
+        r: color.red,
+        g: color.green,
+        b: color.blue,
+        a: (opacity.max(0.0).min(1.0) * 255.0).round() as u8,
+    }
+}
+
+fn ellipse(center: Point, radii: Vec2) -> Option<Path> {
+    if radii.x <= 0.0 || radii.y <= 0.0 {
+        return None;
+    }
+
+    let flags = ArcFlags { large_arc: false, sweep: true };
+    let mut builder = Path::builder().with_svg();
+    builder.move_to(point(center.x + radii.x, center.y));
+    builder.arc_to(radii, Radians::new(0.0), flags, point(center.x - radii.x, center.y));
+    builder.arc_to(radii, Radians::new(0.0), flags, point(center.x + radii.x, center.y));
+    builder.close();
+    return Some(builder.build());
+}
+
+fn parse_transform(frame: TextFrame) -> Result<Transform2D, ImportError> {
+    use svgparser::transform::{Tokenizer, Token};
+
+    let mut transform = Transform2D::identity();
+    let mut tokenizer = Tokenizer::from_frame(frame);
+    loop {
+        let local = match tokenizer.parse_next() {
+            Ok(Token::Matrix { a, b, c, d, e, f }) => {
+                Transform2D::row_major(a as f32, b as f32, c as f32, d as f32, e as f32, f as f32)
+            }
+            Ok(Token::Translate { tx, ty }) => {
+                Transform2D::create_translation(tx as f32, ty as f32)
+            }
+            Ok(Token::Scale { sx, sy }) => {
+                Transform2D::create_scale(sx as f32, sy as f32)
+            }
+            Ok(Token::Rotate { angle }) => {
+                let (sin, cos) = (angle.to_radians() as f32).sin_cos();
+                Transform2D::row_major(cos, sin, -sin, cos, 0.0, 0.0)
+            }
+            Ok(Token::SkewX { angle }) => {
+                Transform2D::row_major(1.0, 0.0, (angle.to_radians() as f32).tan(), 1.0, 0.0, 0.0)
+            }
+            Ok(Token::SkewY { angle }) => {
+                Transform2D::row_major(1.0, (angle.to_radians() as f32).tan(), 0.0, 1.0, 0.0, 0.0)
+            }
+            Ok(Token::EndOfStream) => {
+                return Ok(transform);
+            }
+            Err(_) => {
+                return Err(ImportError);
+            }
+        };
+        // In a transform list, the rightmost transform is applied first.
+        transform = local.post_mul(&transform);
+    }
+}
+
+fn parse_points(src: &str) -> Result<Vec<Point>, ImportError> {
+    let mut numbers = Vec::new();
+    for item in src.split(|c: char| c == ',' || c.is_whitespace()) {
+        if item.is_empty() {
+            continue;
+        }
+        match item.parse::<f32>() {
+            Ok(n) => { numbers.push(n); }
+            Err(_) => { return Err(ImportError); }
+        }
+    }
+
+    return Ok(numbers.chunks(2).filter(|pair| pair.len() == 2).map(|pair| point(pair[0], pair[1])).collect());
+}
+
+#[cfg(test)]
+#[derive(Default)]
+struct Recorder {
+    colors: Vec<RgbaColor>,
+    gradients: Vec<(Point, Point, Vec<GradientStop>)>,
+    transforms: Vec<Transform2D>,
+    fills: Vec<(Path, PatternId<usize, usize>, Option<usize>)>,
+    strokes: Vec<(Path, PatternId<usize, usize>, f32, Option<usize>)>,
+    // The opacity of the pushed groups and the number of fills before them.
+    groups: Vec<(f32, usize)>,
+    group_depth: usize,
+}
+
+#[cfg(test)]
+impl VectorImageBuilder for Recorder {
+    type ColorId = usize;
+    type GradientId = usize;
+    type TransformId = usize;
+
+    fn add_color(&mut self, color: RgbaColor) -> usize {
+        self.colors.push(color);
+        return self.colors.len() - 1;
+    }
+
+    fn add_linear_gradient(&mut self, from: Point, to: Point, stops: &[GradientStop]) -> usize {
+        self.gradients.push((from, to, stops.to_vec()));
+        return self.gradients.len() - 1;
+    }
+
+    fn add_transform(&mut self, transform: &Transform2D) -> usize {
+        self.transforms.push(*transform);
+        return self.transforms.len() - 1;
+    }
+
+    fn fill(&mut self, path: Path, pattern: PatternId<usize, usize>, transform: Option<usize>) {
+        self.fills.push((path, pattern, transform));
+    }
+
+    fn stroke(&mut self, path: Path, pattern: PatternId<usize, usize>, width: f32, transform: Option<usize>) {
+        self.strokes.push((path, pattern, width, transform));
+    }
+
+    fn push_group(&mut self, opacity: f32) -> bool {
+        self.groups.push((opacity, self.fills.len()));
+        self.group_depth += 1;
+        return true;
+    }
+
+    fn pop_group(&mut self) {
+        self.group_depth -= 1;
+    }
+}
+
+#[test]
+fn test_import_svg() {
+    let src = r##"
+        <svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+          <defs>
+            <linearGradient id="grad" gradientUnits="userSpaceOnUse" x1="0" y1="0" x2="10" y2="0">
+              <stop offset="0" stop-color="#ff0000"/>
+              <stop offset="1" stop-color="#0000ff" stop-opacity="0.5"/>
+            </linearGradient>
+            <rect id="hidden" width="10" height="10"/>
+          </defs>
+          <g id="group" transform="translate(10 20)" fill="#00ff00">
+            <rect id="r" x="1" y="2" width="3" height="4" transform="scale(2)"/>
+            <polygon points="0,0 1,0 1,1" style="fill:none;stroke:#ff0000;stroke-width:3"/>
+          </g>
+          <circle id="c" cx="5" cy="5" r="5" fill="url(#grad)" stroke="#000000"/>
+        </svg>
+    "##;
+
+    let mut recorder = Recorder::default();
+    let handles = import_svg(src, &mut recorder).unwrap();
+
+    assert_eq!(recorder.gradients.len(), 1);
+    assert_eq!(recorder.gradients[0].0, point(0.0, 0.0));
+    assert_eq!(recorder.gradients[0].1, point(10.0, 0.0));
+    assert_eq!(
+        recorder.gradients[0].2,
+        vec![
+            GradientStop { offset: 0.0, color: RgbaColor { r: 255, g: 0, b: 0, a: 255 } },
+            GradientStop { offset: 1.0, color: RgbaColor { r: 0, g: 0, b: 255, a: 128 } },
+        ]
+    );
+
+    // The group transform and the rect transform composed with it.
+    assert_eq!(recorder.transforms.len(), 2);
+    assert_eq!(recorder.transforms[1].transform_point(&point(1.0, 1.0)), point(12.0, 22.0));
+    assert_eq!(handles.transforms["group"], 0);
+    assert_eq!(handles.transforms["r"], 1);
+
+    // The rect and the circle are filled, the rect in defs is not rendered.
+    assert_eq!(recorder.fills.len(), 2);
+    assert_eq!(recorder.fills[0].0.points()[0], point(1.0, 2.0));
+    assert_eq!(recorder.fills[0].2, Some(1));
+    assert_eq!(recorder.colors[handles.colors["r"]], RgbaColor { r: 0, g: 255, b: 0, a: 255 });
+    assert_eq!(recorder.fills[1].1, PatternId::Gradient(handles.gradients["grad"]));
+    assert_eq!(recorder.fills[1].2, None);
+
+    // The polygon and the circle are stroked.
+    assert_eq!(recorder.strokes.len(), 2);
+    assert_eq!(recorder.strokes[0].2, 3.0);
+    assert_eq!(recorder.strokes[0].3, Some(0));
+    assert_eq!(recorder.colors[handles.colors["c"]], RgbaColor { r: 0, g: 0, b: 0, a: 255 });
+    assert!(!handles.colors.contains_key("hidden"));
+}
+
+#[test]
+fn test_import_group_opacity() {
+    let src = r##"
+        <svg xmlns="http://www.w3.org/2000/svg">
+          <rect width="1" height="1" fill="#ff0000" opacity="0.5"/>
+          <g opacity="0.5">
+            <rect width="1" height="1" fill="#ff0000"/>
+            <g opacity="0.5" fill-opacity="0.5">
+              <rect width="1" height="1" fill="#ff0000"/>
+            </g>
+            <g opacity="0.5"/>
+          </g>
+          <g opacity="1">
+            <rect width="1" height="1" fill="#ff0000"/>
+          </g>
+        </svg>
+    "##;
+
+    let mut recorder = Recorder::default();
+    import_svg(src, &mut recorder).unwrap();
+
+    // Only groups with an opacity are pushed, standalone shapes get their opacity
+    // applied directly.
+    assert_eq!(recorder.groups, vec![(0.5, 1), (0.5, 2), (0.5, 3)]);
+    assert_eq!(recorder.group_depth, 0);
+    let alphas: Vec<u8> = recorder.colors.iter().map(|color| color.a).collect();
+    assert_eq!(alphas, vec![128, 255, 128, 255]);
+}
+
+#[test]
+fn test_import_gradient_units() {
+    let src = r##"
+        <svg xmlns="http://www.w3.org/2000/svg" width="200" height="100">
+          <linearGradient id="user" gradientUnits="userSpaceOnUse" x1="10%" x2="50%">
+            <stop offset="0" stop-color="#ff0000"/>
+          </linearGradient>
+          <linearGradient id="bbox">
+            <stop offset="0" stop-color="#ff0000"/>
+          </linearGradient>
+          <linearGradient id="diagonal" gradientUnits="objectBoundingBox" x1="0" y1="0" x2="1" y2="1">
+            <stop offset="0" stop-color="#ff0000"/>
+          </linearGradient>
+          <rect width="1" height="1" fill="url(#user)"/>
+          <rect x="10" y="20" width="40" height="10" fill="url(#bbox)"/>
+          <rect width="20" height="10" fill="url(#diagonal)"/>
+          <line x1="0" y1="0" x2="10" y2="0" stroke="url(#bbox)"/>
+        </svg>
+    "##;
+
+    let mut recorder = Recorder::default();
+    let handles = import_svg(src, &mut recorder).unwrap();
+
+    let approx_eq = |a: Point, b: Point| (a - b).length() < 0.0001;
+
+    // Percentages in user space are relative to the viewport.
+    assert_eq!(recorder.fills[0].1, PatternId::Gradient(0));
+    assert!(approx_eq(recorder.gradients[0].0, point(20.0, 0.0)));
+    assert!(approx_eq(recorder.gradients[0].1, point(100.0, 0.0)));
+
+    // By default the vector spans the width of the bounding box.
+    assert_eq!(recorder.fills[1].1, PatternId::Gradient(1));
+    assert!(approx_eq(recorder.gradients[1].0, point(10.0, 20.0)));
+    assert!(approx_eq(recorder.gradients[1].1, point(50.0, 20.0)));
+
+    // The diagonal ends on the isoline that goes through the opposite corner.
+    assert_eq!(recorder.fills[2].1, PatternId::Gradient(2));
+    assert!(approx_eq(recorder.gradients[2].0, point(0.0, 0.0)));
+    assert!(approx_eq(recorder.gradients[2].1, point(8.0, 16.0)));
+    assert_eq!(handles.gradients["diagonal"], 2);
+
+    // The line has no height, it is not stroked with the gradient.
+    assert_eq!(recorder.gradients.len(), 3);
+    assert!(recorder.strokes.is_empty());
+}
@@ -5,11 +5,19 @@
 //! Utilities to facilitate interfacing with SVG.
 //! At the moment this is mostly a wrapper around the [svgparser](https://crates.io/crates/svgparser)
 //! crate.
+//!
+//! The `importer` module walks SVG documents and forwards their shapes, styles and transforms
+//! to a `VectorImageBuilder`, and the `exporter` module writes the content of a vector image
+//! back into an SVG document.
 
 #![allow(dead_code)]
 extern crate lyon_core as core;
 extern crate lyon_path_builder as path_builder;
+extern crate lyon_path as path;
 
 extern crate svgparser;
 
 pub mod parser;
+pub mod serializer;
+pub mod importer;
+pub mod exporter;
@@ -13,5 +13,5 @@ pub use self::attribute::{
     Attribute, AttributeId, AttributeValue, RefAttributeValue,
 };
 
-pub use self::path::{PathTokenizer, build_path, ParserError};
+pub use self::path::{PathTokenizer, build_path, parse_path, ParserError};
 pub use self::style::StyleTokenizer;
@@ -5,6 +5,7 @@ use core::SvgEvent;
 use core::math;
 use core::ArcFlags;
 use path_builder::SvgBuilder;
+use path::Path;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct ParserError;
@@ -23,6 +24,13 @@ where
     Ok(builder.build())
 }
 
+/// Parses the content of the `d` attribute of an svg path element into a `Path`.
+///
+/// Relative commands, shorthand curves and arcs are converted into absolute
+/// line segments and bézier curves.
+pub fn parse_path(src: &str) -> Result<Path, ParserError> {
+    build_path(Path::builder().with_svg(), src)
+}
 
 pub struct PathTokenizer<'l> {
     tokenizer: Tokenizer<'l>
@@ -0,0 +1,83 @@
+//! Serialization of paths into the SVG path data format, as used in the `d` attribute
+//! of svg path elements.
+
+use core::PathEvent;
+use core::math::Point;
+use path::{Path, PathSlice};
+
+use std::fmt::Write;
+
+/// Appends the SVG path data representation of a sequence of path events to a string.
+pub fn write_path_data<Iter>(events: Iter, output: &mut String)
+where
+    Iter: IntoIterator<Item = PathEvent>,
+{
+    for evt in events {
+        if !output.is_empty() {
+            output.push(' ');
+        }
+        match evt {
+            PathEvent::MoveTo(to) => {
+                output.push('M');
+                write_points(output, &[to]);
+            }
+            PathEvent::LineTo(to) => {
+                output.push('L');
+                write_points(output, &[to]);
+            }
+            PathEvent::QuadraticTo(ctrl, to) => {
+                output.push('Q');
+                write_points(output, &[ctrl, to]);
+            }
+            PathEvent::CubicTo(ctrl1, ctrl2, to) => {
+                output.push('C');
+                write_points(output, &[ctrl1, ctrl2, to]);
+            }
+            PathEvent::Close => {
+                output.push('Z');
+            }
+        }
+    }
+}
+
+/// Returns the SVG path data representation of a sequence of path events.
+pub fn path_data<Iter>(events: Iter) -> String
+where
+    Iter: IntoIterator<Item = PathEvent>,
+{
+    let mut output = String::new();
+    write_path_data(events, &mut output);
+    return output;
+}
+
+/// Types that can be serialized into SVG path data.
+pub trait ToSvgString {
+    fn to_svg_string(&self) -> String;
+}
+
+impl ToSvgString for Path {
+    fn to_svg_string(&self) -> String { path_data(self.iter()) }
+}
+
+impl<'l> ToSvgString for PathSlice<'l> {
+    fn to_svg_string(&self) -> String { path_data(self.iter()) }
+}
+
+fn write_points(output: &mut String, points: &[Point]) {
+    for p in points {
+        write!(output, " {} {}", p.x, p.y).unwrap();
+    }
+}
+
+#[test]
+fn test_path_data_round_trip() {
+    use parser::parse_path;
+
+    let path = parse_path("M 10 20 l 5 0 h 5 v 5 Q 30 30 25 35 c 0 5 -5 5 -5 0 z").unwrap();
+    let data = path.to_svg_string();
+    assert_eq!(data, "M 10 20 L 15 20 L 20 20 L 20 25 Q 30 30 25 35 C 25 40 20 40 20 35 Z");
+
+    let reparsed = parse_path(&data).unwrap();
+    assert_eq!(reparsed.points(), path.points());
+    assert_eq!(reparsed.verbs(), path.verbs());
+}
@@ -9,8 +9,10 @@ use math_utils::compute_normal;
 use math::*;
 use path_builder::BaseBuilder;
 use path_iterator::FromPolyline;
-use {FillVertex, StrokeVertex, StrokeOptions, Side};
-use bezier::{Arc, Radians};
+use path_offset::{offset_path, OffsetOptions};
+use core::FlattenedEvent;
+use {FillVertex, StrokeVertex, StrokeOptions, Side, LineJoin};
+use bezier::Radians;
 
 use std::f32::consts::PI;
 
@@ -269,64 +271,193 @@ impl BorderRadii {
     }
 }
 
+/// The horizontal and vertical radii of each corner of a rounded rectangle.
+///
+/// This allows elliptical corners, like the CSS `border-radius` property.
+pub struct EllipticalBorderRadii {
+    pub top_left: Size,
+    pub top_right: Size,
+    pub bottom_left: Size,
+    pub bottom_right: Size,
+}
+
+impl EllipticalBorderRadii {
+    pub fn new(
+        top_left: Size,
+        top_right: Size,
+        bottom_left: Size,
+        bottom_right: Size,
+    ) -> Self {
+        EllipticalBorderRadii {
+            top_left: abs_size(top_left),
+            top_right: abs_size(top_right),
+            bottom_left: abs_size(bottom_left),
+            bottom_right: abs_size(bottom_right),
+        }
+    }
+
+    pub fn new_all_same(radii: Size) -> Self {
+        let r = abs_size(radii);
+        EllipticalBorderRadii {
+            top_left: r,
+            top_right: r,
+            bottom_left: r,
+            bottom_right: r,
+        }
+    }
+}
+
+impl<'l> From<&'l BorderRadii> for EllipticalBorderRadii {
+    fn from(radii: &'l BorderRadii) -> Self {
+        EllipticalBorderRadii::new(
+            size(radii.top_left, radii.top_left),
+            size(radii.top_right, radii.top_right),
+            size(radii.bottom_left, radii.bottom_left),
+            size(radii.bottom_right, radii.bottom_right),
+        )
+    }
+}
+
+fn abs_size(s: Size) -> Size { size(s.width.abs(), s.height.abs()) }
+
+// Scales the radii down if they don't fit in the rectangle, following the
+// CSS specification: all radii are reduced by the same factor so that the
+// sum of two adjacent radii along a side never exceeds the size of this side.
+//
+// Returns the radii in the order top-left, top-right, bottom-right, bottom-left.
+fn clamp_border_radii(rect: &Rect, radii: &EllipticalBorderRadii) -> [Size; 4] {
+    let w = rect.size.width;
+    let h = rect.size.height;
+    let tl = abs_size(radii.top_left);
+    let tr = abs_size(radii.top_right);
+    let br = abs_size(radii.bottom_right);
+    let bl = abs_size(radii.bottom_left);
+
+    let mut scale: f32 = 1.0;
+    for &(sum, side) in &[
+        (tl.width + tr.width, w),
+        (bl.width + br.width, w),
+        (tl.height + bl.height, h),
+        (tr.height + br.height, h),
+    ] {
+        if sum > side {
+            scale = scale.min(side / sum);
+        }
+    }
+
+    return [tl * scale, tr * scale, br * scale, bl * scale];
+}
+
+/// Computes a rounded rectangle that is concentric to the provided one, at a given
+/// distance from it.
+///
+/// A positive `offset` produces an outset outline (for example a focus ring around
+/// a button), a negative one an inset outline. Rounded corners grow or shrink by
+/// the offset so that the outline keeps a constant distance to the shape, while sharp
+/// corners remain sharp. The result can be tessellated with
+/// `fill_elliptical_rounded_rectangle` or `stroke_elliptical_rounded_rectangle`.
+pub fn rounded_rectangle_outline(
+    rect: &Rect,
+    radii: &EllipticalBorderRadii,
+    offset: f32,
+) -> (Rect, EllipticalBorderRadii) {
+    let radii = clamp_border_radii(rect, radii);
+
+    let mut outline = rect.inflate(offset, offset);
+    if outline.size.width < 0.0 {
+        outline.origin.x += outline.size.width * 0.5;
+        outline.size.width = 0.0;
+    }
+    if outline.size.height < 0.0 {
+        outline.origin.y += outline.size.height * 0.5;
+        outline.size.height = 0.0;
+    }
+
+    let offset_radius = |r: Size| {
+        let w = r.width + offset;
+        let h = r.height + offset;
+        if r.width > 0.0 && r.height > 0.0 && w > 0.0 && h > 0.0 {
+            size(w, h)
+        } else {
+            size(0.0, 0.0)
+        }
+    };
+
+    return (
+        outline,
+        EllipticalBorderRadii {
+            top_left: offset_radius(radii[0]),
+            top_right: offset_radius(radii[1]),
+            bottom_right: offset_radius(radii[2]),
+            bottom_left: offset_radius(radii[3]),
+        }
+    );
+}
+
+/// Computes an outline at a given distance from an arbitrary flattened path and writes
+/// it into a path builder.
+///
+/// This is the equivalent of `rounded_rectangle_outline` for other shapes: a positive
+/// `offset` produces an outset outline and a negative one an inset outline. The corners
+/// that expand are rounded so that the outline keeps a constant distance to the shape.
+/// See `offset_path` for the details and the other line joins.
+pub fn path_outline<Iter, Output>(
+    path: Iter,
+    offset: f32,
+    tolerance: f32,
+    output: &mut Output,
+) where
+    Iter: Iterator<Item = FlattenedEvent>,
+    Output: BaseBuilder,
+{
+    let options = OffsetOptions::default()
+        .with_line_join(LineJoin::Round)
+        .with_tolerance(tolerance);
+
+    offset_path(path, offset, &options, output);
+}
+
 /// Tessellate an axis-aligned rounded rectangle.
 pub fn fill_rounded_rectangle<Output: GeometryBuilder<FillVertex>>(
     rect: &Rect,
     radii: &BorderRadii,
     tolerance: f32,
     output: &mut Output,
+) -> Count {
+    fill_elliptical_rounded_rectangle(rect, &radii.into(), tolerance, output)
+}
+
+/// Tessellate an axis-aligned rounded rectangle with elliptical corners.
+pub fn fill_elliptical_rounded_rectangle<Output: GeometryBuilder<FillVertex>>(
+    rect: &Rect,
+    radii: &EllipticalBorderRadii,
+    tolerance: f32,
+    output: &mut Output,
 ) -> Count {
     output.begin_geometry();
 
-    let w = rect.size.width;
-    let h = rect.size.height;
     let x_min = rect.min_x();
     let y_min = rect.min_y();
     let x_max = rect.max_x();
     let y_max = rect.max_y();
-    let min_wh = w.min(h);
-    let mut tl = radii.top_left.abs().min(min_wh);
-    let mut tr = radii.top_right.abs().min(min_wh);
-    let mut bl = radii.bottom_left.abs().min(min_wh);
-    let mut br = radii.bottom_right.abs().min(min_wh);
-
-    // clamp border radii if they don't fit in the rectangle.
-    if tl + tr > w {
-        let x = (tl + tr - w) * 0.5;
-        tl -= x;
-        tr -= x;
-    }
-    if bl + br > w {
-        let x = (bl + br - w) * 0.5;
-        bl -= x;
-        br -= x;
-    }
-    if tr + br > h {
-        let x = (tr + br - h) * 0.5;
-        tr -= x;
-        br -= x;
-    }
-    if tl + bl > h {
-        let x = (tl + bl - h) * 0.5;
-        tl -= x;
-        bl -= x;
-    }
+    let radii = clamp_border_radii(rect, radii);
+    let (tl, tr, br, bl) = (radii[0], radii[1], radii[2], radii[3]);
 
     // top
-    let p1 = point(x_min + tl, y_min);
-    let p2 = point(x_max - tr, y_min);
+    let p1 = point(x_min + tl.width, y_min);
+    let p2 = point(x_max - tr.width, y_min);
 
     // bottom
-    let p6 = point(x_min + bl, y_max);
-    let p5 = point(x_max - br, y_max);
+    let p6 = point(x_min + bl.width, y_max);
+    let p5 = point(x_max - br.width, y_max);
 
     // left
-    let p0 = point(x_min, y_min + tl);
-    let p7 = point(x_min, y_max - bl);
+    let p0 = point(x_min, y_min + tl.height);
+    let p7 = point(x_min, y_max - bl.height);
 
     // right
-    let p3 = point(x_max, y_min + tr);
-    let p4 = point(x_max, y_max - br);
+    let p3 = point(x_max, y_min + tr.height);
+    let p4 = point(x_max, y_max - br.height);
 
     let up = vec2(0.0, -1.0);
     let down = vec2(0.0, 1.0);
@@ -352,7 +483,6 @@ pub fn fill_rounded_rectangle<Output: GeometryBuilder<FillVertex>>(
     output.add_triangle(v[5], v[2], v[4]);
     output.add_triangle(v[4], v[2], v[3]);
 
-    let radii = [tl, tr, br, bl];
     let angles = [
         (PI, 1.5 * PI),
         (1.5* PI, 2.0 * PI),
@@ -368,9 +498,9 @@ pub fn fill_rounded_rectangle<Output: GeometryBuilder<FillVertex>>(
     ];
 
     for i in 0..4 {
-        let radius = radii[i];
-        if radius > 0.0 {
-
+        let r = radii[i];
+        if r.width > 0.0 && r.height > 0.0 {
+            let radius = r.width.max(r.height);
             let arc_len = 0.5 * PI * radius;
 
             let step = circle_flattening_step(radius, tolerance);
@@ -381,7 +511,7 @@ pub fn fill_rounded_rectangle<Output: GeometryBuilder<FillVertex>>(
             fill_border_radius(
                 centers[i],
                 angles[i],
-                radius,
+                vec2(r.width, r.height),
                 v[i*2],
                 v[i*2 + 1],
                 num_recursions,
@@ -397,7 +527,7 @@ pub fn fill_rounded_rectangle<Output: GeometryBuilder<FillVertex>>(
 fn fill_border_radius<Output: GeometryBuilder<FillVertex>>(
     center: Point,
     angle: (f32, f32),
-    radius: f32,
+    radii: Vec2,
     va: VertexId,
     vb: VertexId,
     num_recursions: u32,
@@ -410,7 +540,7 @@ fn fill_border_radius<Output: GeometryBuilder<FillVertex>>(
     let mid_angle = (angle.0 + angle.1) * 0.5;
 
     let normal = vec2(mid_angle.cos(), mid_angle.sin());
-    let pos = center + normal * radius;
+    let pos = center + vec2(normal.x * radii.x, normal.y * radii.y);
 
     let vertex = output.add_vertex(FillVertex {
         position: pos,
@@ -422,7 +552,7 @@ fn fill_border_radius<Output: GeometryBuilder<FillVertex>>(
     fill_border_radius(
         center,
         (angle.0, mid_angle),
-        radius,
+        radii,
         va,
         vertex,
         num_recursions - 1,
@@ -431,7 +561,7 @@ fn fill_border_radius<Output: GeometryBuilder<FillVertex>>(
     fill_border_radius(
         center,
         (mid_angle, angle.1),
-        radius,
+        radii,
         vertex,
         vb,
         num_recursions - 1,
@@ -445,58 +575,41 @@ pub fn stroke_rounded_rectangle<Output: GeometryBuilder<StrokeVertex>>(
     radii: &BorderRadii,
     options: &StrokeOptions,
     output: &mut Output,
+) -> Count {
+    stroke_elliptical_rounded_rectangle(rect, &radii.into(), options, output)
+}
+
+/// Tessellate the stroke of an axis-aligned rounded rectangle with elliptical corners.
+pub fn stroke_elliptical_rounded_rectangle<Output: GeometryBuilder<StrokeVertex>>(
+    rect: &Rect,
+    radii: &EllipticalBorderRadii,
+    options: &StrokeOptions,
+    output: &mut Output,
 ) -> Count {
     output.begin_geometry();
 
-    let w = rect.size.width;
-    let h = rect.size.height;
     let x_min = rect.min_x();
     let y_min = rect.min_y();
     let x_max = rect.max_x();
     let y_max = rect.max_y();
-    let min_wh = w.min(h);
-    let mut tl = radii.top_left.abs().min(min_wh);
-    let mut tr = radii.top_right.abs().min(min_wh);
-    let mut bl = radii.bottom_left.abs().min(min_wh);
-    let mut br = radii.bottom_right.abs().min(min_wh);
-
-    // clamp border radii if they don't fit in the rectangle.
-    if tl + tr > w {
-        let x = (tl + tr - w) * 0.5;
-        tl -= x;
-        tr -= x;
-    }
-    if bl + br > w {
-        let x = (bl + br - w) * 0.5;
-        bl -= x;
-        br -= x;
-    }
-    if tr + br > h {
-        let x = (tr + br - h) * 0.5;
-        tr -= x;
-        br -= x;
-    }
-    if tl + bl > h {
-        let x = (tl + bl - h) * 0.5;
-        tl -= x;
-        bl -= x;
-    }
+    let radii = clamp_border_radii(rect, radii);
+    let (tl, tr, br, bl) = (radii[0], radii[1], radii[2], radii[3]);
 
     // top
-    let p1 = point(x_min + tl, y_min);
-    let p2 = point(x_max - tr, y_min);
+    let p1 = point(x_min + tl.width, y_min);
+    let p2 = point(x_max - tr.width, y_min);
 
     // bottom
-    let p6 = point(x_min + bl, y_max);
-    let p5 = point(x_max - br, y_max);
+    let p6 = point(x_min + bl.width, y_max);
+    let p5 = point(x_max - br.width, y_max);
 
     // left
-    let p0 = point(x_min, y_min + tl);
-    let p7 = point(x_min, y_max - bl);
+    let p0 = point(x_min, y_min + tl.height);
+    let p7 = point(x_min, y_max - bl.height);
 
     // right
-    let p3 = point(x_max, y_min + tr);
-    let p4 = point(x_max, y_max - br);
+    let p3 = point(x_max, y_min + tr.height);
+    let p4 = point(x_max, y_max - br.height);
 
     let sides = &[
         [p1, p2],
@@ -505,7 +618,6 @@ pub fn stroke_rounded_rectangle<Output: GeometryBuilder<StrokeVertex>>(
         [p0, p7],
     ];
 
-    let radii = [tl, tr, br, bl];
     let angles = [
         (PI, 1.5 * PI),
         (1.5* PI, 2.0 * PI),
@@ -520,8 +632,9 @@ pub fn stroke_rounded_rectangle<Output: GeometryBuilder<StrokeVertex>>(
         point(p6.x, p7.y),
     ];
 
-    let mut nums = radii.iter().map(|&radius| {
-        if radius > 0.0 {
+    let mut nums = radii.iter().map(|r| {
+        if r.width > 0.0 && r.height > 0.0 {
+            let radius = r.width.max(r.height);
             let arc_len = 0.5 * PI * radius;
             let step = circle_flattening_step(radius, options.tolerance);
             (arc_len / step).ceil() as u32  - 1
@@ -537,7 +650,7 @@ pub fn stroke_rounded_rectangle<Output: GeometryBuilder<StrokeVertex>>(
             stroke_border_radius(
                 centers[i],
                 angles[i],
-                radii[i],
+                vec2(radii[i].width, radii[i].height),
                 nums.next().unwrap(),
                 &mut builder,
             );
@@ -606,7 +719,7 @@ pub fn fill_circle<Output: GeometryBuilder<FillVertex>>(
         fill_border_radius(
             center,
             angles[i],
-            radius,
+            vec2(radius, radius),
             v[i],
             v[(i + 1) % 4],
             num_recursions,
@@ -646,7 +759,7 @@ pub fn stroke_circle<Output>(
         stroke_border_radius(
             center,
             angle,
-            radius,
+            vec2(radius, radius),
             num_points,
             &mut builder,
         );
@@ -660,7 +773,7 @@ pub fn stroke_circle<Output>(
 fn stroke_border_radius<Output: GeometryBuilder<StrokeVertex>>(
     center: Point,
     angle: (f32, f32),
-    radius: f32,
+    radii: Vec2,
     num_points: u32,
     builder: &mut StrokeBuilder<Output>,
 ) {
@@ -672,7 +785,7 @@ fn stroke_border_radius<Output: GeometryBuilder<StrokeVertex>>(
         let normal =
         vec2(new_angle.cos(),
         new_angle.sin());
-        center + normal * radius
+        center + vec2(normal.x * radii.x, normal.y * radii.y)
     });
 
     for point in points {
@@ -681,6 +794,7 @@ fn stroke_border_radius<Output: GeometryBuilder<StrokeVertex>>(
 
 }
 
+/// Tessellate an ellipse.
 pub fn fill_ellipse<Output: GeometryBuilder<FillVertex>>(
     center: Point,
     radii: Vec2,
@@ -688,38 +802,36 @@ pub fn fill_ellipse<Output: GeometryBuilder<FillVertex>>(
     tolerance: f32,
     output: &mut Output,
 ) -> Count {
-    // TODO: This is far from optimal compared to the circle tessellation, but it
-    // correctly takes the tolerance threshold into account which is harder to do
-    // than with circles.
-
-    let arc = Arc {
-        center,
-        radii,
-        x_rotation,
-        start_angle: Radians::new(0.0),
-        sweep_angle: Radians::new(2.0 * PI-0.01),
-    };
+    output.begin_geometry();
 
-    use path_builder::{PathBuilder, FlatteningBuilder};
-    use path_fill::EventsBuilder;
+    let rx = radii.x.abs();
+    let ry = radii.y.abs();
+    if rx == 0.0 || ry == 0.0 {
+        return output.end_geometry();
+    }
 
-    let mut path = FlatteningBuilder::new(EventsBuilder::new(), tolerance).with_svg();
+    // The ellipse is convex, so we can tessellate it as a triangle fan.
+    let num_points = ellipse_num_points(rx.max(ry), tolerance);
+    let rotation = x_rotation.get().sin_cos();
 
-    path.move_to(arc.sample(0.0));
-    arc.to_quadratic_beziers(&mut|ctrl, to| {
-        path.quadratic_bezier_to(ctrl, to);
-    });
-    path.close();
+    let vertex = |i: u32| {
+        let angle = 2.0 * PI * i as f32 / num_points as f32;
+        let (position, normal) = ellipse_point(center, rx, ry, rotation, angle);
+        FillVertex { position: position, normal: normal }
+    };
 
-    let events = path.build();
+    let first = output.add_vertex(vertex(0));
+    let mut previous = output.add_vertex(vertex(1));
+    for i in 2..num_points {
+        let current = output.add_vertex(vertex(i));
+        output.add_triangle(first, previous, current);
+        previous = current;
+    }
 
-    return FillTessellator::new().tessellate_events(
-        &events,
-        &FillOptions::tolerance(tolerance),
-        output,
-    ).unwrap();
+    return output.end_geometry();
 }
 
+/// Tessellate the stroke of an ellipse.
 pub fn stroke_ellipse<Output: GeometryBuilder<StrokeVertex>>(
     center: Point,
     radii: Vec2,
@@ -727,35 +839,55 @@ pub fn stroke_ellipse<Output: GeometryBuilder<StrokeVertex>>(
     options: &StrokeOptions,
     output: &mut Output,
 ) -> Count {
-    // TODO: This is far from optimal compared to the circle tessellation, but it
-    // correctly takes the tolerance threshold into account which is harder to do
-    // than with circles.
+    output.begin_geometry();
 
-    let arc = Arc {
-        center,
-        radii,
-        x_rotation,
-        start_angle: Radians::new(0.0),
-        sweep_angle: Radians::new(2.0 * PI-0.01),
-    };
+    let rx = radii.x.abs();
+    let ry = radii.y.abs();
+    if rx == 0.0 && ry == 0.0 {
+        return output.end_geometry();
+    }
 
-    use path_builder::{PathBuilder, FlatteningBuilder};
-    use path_fill::EventsBuilder;
+    let num_points = ellipse_num_points(rx.max(ry), options.tolerance);
+    let rotation = x_rotation.get().sin_cos();
 
-    output.begin_geometry();
-    {
-        let mut path = FlatteningBuilder::new(StrokeBuilder::new(options, output), options.tolerance).with_svg();
+    { // output borrow scope start
+        let mut builder = StrokeBuilder::new(options, output);
+        builder.move_to(ellipse_point(center, rx, ry, rotation, 0.0).0);
+        for i in 1..num_points {
+            let angle = 2.0 * PI * i as f32 / num_points as f32;
+            builder.line_to(ellipse_point(center, rx, ry, rotation, angle).0);
+        }
+        builder.close();
+        builder.build();
+    } // output borrow scope end
 
-        path.move_to(arc.sample(0.0));
-        arc.to_quadratic_beziers(&mut|ctrl, to| {
-            path.quadratic_bezier_to(ctrl, to);
-        });
-        path.close();
+    return output.end_geometry();
+}
 
-        let _ = path.build();
+// Number of points to approximate a full ellipse with the given maximum radius.
+fn ellipse_num_points(max_radius: f32, tolerance: f32) -> u32 {
+    if tolerance * 0.5 >= max_radius {
+        return 4;
     }
+    let step = circle_flattening_step(max_radius, tolerance);
+    let n = (2.0 * PI * max_radius / step).ceil() as u32;
+    return n.max(4);
+}
 
-    return output.end_geometry();
+// Returns the position and outward normal of the point at a given angle on the ellipse.
+fn ellipse_point(center: Point, rx: f32, ry: f32, rotation: (f32, f32), angle: f32) -> (Point, Vec2) {
+    let (sin, cos) = angle.sin_cos();
+    let (rot_sin, rot_cos) = rotation;
+    let rotate = |v: Vec2| vec2(v.x * rot_cos - v.y * rot_sin, v.x * rot_sin + v.y * rot_cos);
+
+    let position = center + rotate(vec2(rx * cos, ry * sin));
+    let normal = if rx > 0.0 && ry > 0.0 {
+        rotate(vec2(cos / rx, sin / ry)).normalize()
+    } else {
+        rotate(vec2(cos, sin))
+    };
+
+    return (position, normal);
 }
 
 /// Tessellate a convex shape that is discribed by an iterator of points.
@@ -860,3 +992,146 @@ pub(crate) fn circle_flattening_step(radius:f32, tolerance: f32) -> f32 {
     2.0 * (2.0 * tolerance * radius - tolerance * tolerance).sqrt()
 }
 
+
+#[test]
+fn test_elliptical_border_radii_clamping() {
+    let radii = clamp_border_radii(
+        &rect(0.0, 0.0, 100.0, 50.0),
+        &EllipticalBorderRadii::new(
+            size(80.0, 10.0),
+            size(80.0, 10.0),
+            size(10.0, 40.0),
+            size(10.0, 40.0),
+        ),
+    );
+
+    // The top radii sum up to 160 for a width of 100, so everything is scaled by 100/160.
+    let scale = 100.0 / 160.0;
+    assert_eq!(radii[0], size(80.0 * scale, 10.0 * scale));
+    assert_eq!(radii[1], size(80.0 * scale, 10.0 * scale));
+    assert_eq!(radii[2], size(10.0 * scale, 40.0 * scale));
+    assert_eq!(radii[3], size(10.0 * scale, 40.0 * scale));
+
+    // Radii that fit are left untouched.
+    let radii = clamp_border_radii(
+        &rect(0.0, 0.0, 100.0, 50.0),
+        &EllipticalBorderRadii::new_all_same(size(20.0, 10.0)),
+    );
+    for r in &radii {
+        assert_eq!(*r, size(20.0, 10.0));
+    }
+}
+
+#[test]
+fn test_fill_elliptical_rounded_rectangle() {
+    use geometry_builder::{VertexBuffers, simple_builder};
+
+    let mut buffers: VertexBuffers<FillVertex> = VertexBuffers::new();
+    fill_elliptical_rounded_rectangle(
+        &rect(0.0, 0.0, 100.0, 50.0),
+        &EllipticalBorderRadii::new_all_same(size(20.0, 10.0)),
+        0.1,
+        &mut simple_builder(&mut buffers),
+    );
+
+    for v in &buffers.vertices {
+        assert!(v.position.x >= -0.001 && v.position.x <= 100.001);
+        assert!(v.position.y >= -0.001 && v.position.y <= 50.001);
+    }
+    assert!(buffers.vertices.len() > 8);
+}
+
+#[test]
+fn test_rounded_rectangle_outline() {
+    // The arguments are in top-left, top-right, bottom-left, bottom-right order.
+    let radii = EllipticalBorderRadii::new(
+        size(10.0, 10.0),
+        size(0.0, 0.0),
+        size(10.0, 10.0),
+        size(5.0, 2.0),
+    );
+
+    let (outset, outset_radii) = rounded_rectangle_outline(&rect(0.0, 0.0, 100.0, 50.0), &radii, 3.0);
+    assert_eq!(outset, rect(-3.0, -3.0, 106.0, 56.0));
+    assert_eq!(outset_radii.top_left, size(13.0, 13.0));
+    assert_eq!(outset_radii.top_right, size(0.0, 0.0));
+    assert_eq!(outset_radii.bottom_left, size(13.0, 13.0));
+    assert_eq!(outset_radii.bottom_right, size(8.0, 5.0));
+
+    // Radii smaller than the inset become sharp corners.
+    let (inset, inset_radii) = rounded_rectangle_outline(&rect(0.0, 0.0, 100.0, 50.0), &radii, -3.0);
+    assert_eq!(inset, rect(3.0, 3.0, 94.0, 44.0));
+    assert_eq!(inset_radii.top_left, size(7.0, 7.0));
+    assert_eq!(inset_radii.top_right, size(0.0, 0.0));
+    assert_eq!(inset_radii.bottom_left, size(7.0, 7.0));
+    assert_eq!(inset_radii.bottom_right, size(0.0, 0.0));
+
+    let (empty, _) = rounded_rectangle_outline(&rect(0.0, 0.0, 100.0, 50.0), &radii, -30.0);
+    assert_eq!(empty.size.height, 0.0);
+    assert_eq!(empty.origin.y, 25.0);
+}
+
+#[test]
+fn test_path_outline() {
+    use path::Path;
+
+    let triangle = [point(0.0, 0.0), point(10.0, 0.0), point(5.0, 8.0)];
+    let events = vec![
+        FlattenedEvent::MoveTo(triangle[0]),
+        FlattenedEvent::LineTo(triangle[1]),
+        FlattenedEvent::LineTo(triangle[2]),
+        FlattenedEvent::Close,
+    ];
+
+    let distance_to_triangle = |p: Point| {
+        let mut d = ::std::f32::MAX;
+        for i in 0..3 {
+            let a = triangle[i];
+            let b = triangle[(i + 1) % 3];
+            let t = ((p - a).dot(b - a) / (b - a).square_length()).max(0.0).min(1.0);
+            d = d.min((a + (b - a) * t - p).length());
+        }
+        d
+    };
+
+    let mut builder = Path::builder();
+    path_outline(events.clone().into_iter(), 1.0, 0.05, &mut builder);
+    let outset = builder.build();
+    // Each corner is rounded.
+    assert!(outset.points().len() > 6);
+    for p in outset.points() {
+        assert!((distance_to_triangle(*p) - 1.0).abs() < 0.001);
+    }
+
+    let mut builder = Path::builder();
+    path_outline(events.into_iter(), -1.0, 0.05, &mut builder);
+    let inset = builder.build();
+    // The corners of an inset don't expand, so they stay sharp.
+    assert_eq!(inset.points().len(), 3);
+    for p in inset.points() {
+        assert!((distance_to_triangle(*p) - 1.0).abs() < 0.001);
+    }
+}
+
+#[test]
+fn test_fill_ellipse() {
+    use geometry_builder::{VertexBuffers, simple_builder};
+
+    let mut buffers: VertexBuffers<FillVertex> = VertexBuffers::new();
+    let count = fill_ellipse(
+        point(10.0, 20.0),
+        vec2(50.0, 10.0),
+        Radians::new(PI * 0.5),
+        0.1,
+        &mut simple_builder(&mut buffers),
+    );
+
+    assert_eq!(count.indices, (count.vertices - 2) * 3);
+    for v in &buffers.vertices {
+        // The ellipse is rotated by 90 degrees.
+        let p = v.position - vec2(10.0, 20.0);
+        let d = (p.x / 10.0) * (p.x / 10.0) + (p.y / 50.0) * (p.y / 50.0);
+        assert!((d - 1.0).abs() < 0.001);
+        assert!(v.normal.dot(p) > 0.0);
+    }
+}
@@ -23,8 +23,8 @@
 //!
 //! This modules provides with a basic implementation of these traits through the following types:
 //!
-//! * The struct [`VertexBuffers<T>`](struct.VertexBuffers.html) is a simple pair of vectors of u16
-//!   indices and T (generic parameter) vertices.
+//! * The struct [`VertexBuffers<T>`](struct.VertexBuffers.html) is a simple pair of vectors of
+//!   indices (u16 by default, u32 is also supported) and T (generic parameter) vertices.
 //! * The struct [`BuffersBuilder`](struct.BuffersBuilder.html) which implements
 //!   [`BezierGeometryBuilder`](trait.BezierGeometryBuilder.html) and writes into a
 //!   [`VertexBuffers`](struct.VertexBuffers.html).
@@ -36,6 +36,9 @@
 //!   Another, simpler example of vertex constructor is the [`Identity`](struct.Identity.html)
 //!   constructor which just returns its input, untransformed.
 //!   `VertexConstructor<Input, Ouput>` is implemented for all closures `Fn(Input) -> Output`.
+//! * The struct [`StreamingBuilder`](struct.StreamingBuilder.html) which, instead of keeping
+//!   the whole geometry in memory, hands it over in fixed-size chunks to a
+//!   [`GeometryReceiver`](trait.GeometryReceiver.html) as it is produced.
 //!
 //! Geometry builders are a practical way to add one last step to the tessellation pipeline,
 //! such as applying a transform or clipping the geometry.
@@ -224,6 +227,12 @@
 
 use std::marker::PhantomData;
 use std::ops::Add;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::{f32, u16};
+
+use math::{Point, Vec2, Rect, point, rect, vec2};
+use {FillVertex, StrokeVertex, UvFillVertex, Side};
 
 pub type Index = u16;
 
@@ -239,6 +248,9 @@ impl VertexId {
     pub fn offset(&self) -> u16 { self.0 }
 }
 
+// The number of vertices a geometry can have, since `VertexId`s are 16 bits offsets.
+const MAX_GEOMETRY_VERTICES: u32 = u16::MAX as u32 + 1;
+
 /// An interface separating tessellators and other geometry generation algorithms from the
 /// actual vertex construction.
 ///
@@ -282,12 +294,37 @@ pub trait BezierGeometryBuilder<Input>: GeometryBuilder<Input> {
     fn add_quadratic_bezier(&mut self, from: VertexId, to: VertexId, ctrl: Input);
 }
 
+/// Integer types that can be used to store indices in `VertexBuffers`.
+///
+/// Implemented for `u16` (the default) and `u32`.
+pub trait GeometryIndex: Copy {
+    /// Converts an index, returning `None` if it doesn't fit in this type.
+    fn from_u32(index: u32) -> Option<Self>;
+}
+
+impl GeometryIndex for u16 {
+    fn from_u32(index: u32) -> Option<u16> {
+        if index > u16::MAX as u32 {
+            return None;
+        }
+        return Some(index as u16);
+    }
+}
+
+impl GeometryIndex for u32 {
+    fn from_u32(index: u32) -> Option<u32> { Some(index) }
+}
+
 /// Structure that holds the vertex and index data.
 ///
 /// Usually writen into though temporary `BuffersBuilder` objects.
-pub struct VertexBuffers<VertexType> {
+///
+/// Indices are `u16` by default, which limits the vertex buffer to 65536 vertices.
+/// Use `u32` indices (see `with_capacity_and_index_type`) when accumulating more geometry
+/// than that in a single pair of buffers.
+pub struct VertexBuffers<VertexType, IndexType = Index> {
     pub vertices: Vec<VertexType>,
-    pub indices: Vec<Index>,
+    pub indices: Vec<IndexType>,
 }
 
 impl<VertexType> VertexBuffers<VertexType> {
@@ -296,6 +333,14 @@ impl<VertexType> VertexBuffers<VertexType> {
 
     /// Constructor
     pub fn with_capacity(num_vertices: usize, num_indices: usize) -> VertexBuffers<VertexType> {
+        VertexBuffers::with_capacity_and_index_type(num_vertices, num_indices)
+    }
+}
+
+impl<VertexType, IndexType> VertexBuffers<VertexType, IndexType> {
+    /// Constructor for any index type, for example:
+    /// `VertexBuffers::<MyVertex, u32>::with_capacity_and_index_type(512, 1024)`.
+    pub fn with_capacity_and_index_type(num_vertices: usize, num_indices: usize) -> Self {
         VertexBuffers {
             vertices: Vec::with_capacity(num_vertices),
             indices: Vec::with_capacity(num_indices),
@@ -315,39 +360,67 @@ impl<VertexType> VertexBuffers<VertexType> {
 /// vertex attributes. The `VertexConstructor` does the translation from generic `Input` to `VertexType`.
 /// If your logic generates the actual vertex type directly, you can use the `SimpleBuffersBuilder`
 /// convenience typedef.
-pub struct BuffersBuilder<'l, VertexType: 'l, Input, Ctor: VertexConstructor<Input, VertexType>> {
-    buffers: &'l mut VertexBuffers<VertexType>,
-    vertex_offset: Index,
-    index_offset: Index,
+///
+/// If the vertex buffer grows past what the index type can address, or a geometry has more
+/// vertices than `VertexId` can represent, the geometry is discarded in `end_geometry` and
+/// `overflowed` returns true. The geometry can then be regenerated with a larger index type,
+/// or split into smaller geometries.
+pub struct BuffersBuilder<'l, VertexType: 'l, Input, Ctor, IndexType: 'l = Index>
+where
+    Ctor: VertexConstructor<Input, VertexType>
+{
+    buffers: &'l mut VertexBuffers<VertexType, IndexType>,
+    vertex_offset: u32,
+    index_offset: u32,
+    overflowed: bool,
+    // Whether the current geometry overflowed.
+    geometry_overflowed: bool,
     vertex_constructor: Ctor,
     _marker: PhantomData<Input>,
 }
 
-impl<'l, VertexType: 'l, Input, Ctor: VertexConstructor<Input, VertexType>>
-    BuffersBuilder<'l, VertexType, Input, Ctor> {
+impl<'l, VertexType: 'l, Input, Ctor, IndexType: 'l> BuffersBuilder<'l, VertexType, Input, Ctor, IndexType>
+where
+    Ctor: VertexConstructor<Input, VertexType>,
+    IndexType: GeometryIndex,
+{
     pub fn new(
-        buffers: &'l mut VertexBuffers<VertexType>,
+        buffers: &'l mut VertexBuffers<VertexType, IndexType>,
         ctor: Ctor,
-    ) -> BuffersBuilder<'l, VertexType, Input, Ctor> {
-        let vertex_offset = buffers.vertices.len() as Index;
-        let index_offset = buffers.indices.len() as Index;
+    ) -> BuffersBuilder<'l, VertexType, Input, Ctor, IndexType> {
+        let vertex_offset = buffers.vertices.len() as u32;
+        let index_offset = buffers.indices.len() as u32;
         BuffersBuilder {
             buffers: buffers,
             vertex_offset: vertex_offset,
             index_offset: index_offset,
+            overflowed: false,
+            geometry_overflowed: false,
             vertex_constructor: ctor,
             _marker: PhantomData,
         }
     }
+
+    /// Returns true if some vertices could not be addressed with the index type or with
+    /// `VertexId`.
+    ///
+    /// When this happens the geometries that overflowed are discarded.
+    pub fn overflowed(&self) -> bool { self.overflowed }
+
+    /// The buffers the geometry is written into.
+    ///
+    /// Allows wrappers to post-process the vertices of a geometry after `end_geometry`.
+    pub fn buffers_mut(&mut self) -> &mut VertexBuffers<VertexType, IndexType> { self.buffers }
 }
 
 /// Creates a `BuffersBuilder`.
-pub fn vertex_builder<VertexType, Input, Ctor>(
-    buffers: &mut VertexBuffers<VertexType>,
+pub fn vertex_builder<VertexType, Input, Ctor, IndexType>(
+    buffers: &mut VertexBuffers<VertexType, IndexType>,
     ctor: Ctor,
-) -> BuffersBuilder<VertexType, Input, Ctor>
+) -> BuffersBuilder<VertexType, Input, Ctor, IndexType>
 where
-    Ctor: VertexConstructor<Input, VertexType>
+    Ctor: VertexConstructor<Input, VertexType>,
+    IndexType: GeometryIndex,
 {
     BuffersBuilder::new(buffers, ctor)
 }
@@ -363,6 +436,18 @@ impl<T> VertexConstructor<T, T> for Identity {
     fn new_vertex(&mut self, input: T) -> T { input }
 }
 
+/// A vertex constructor that only keeps the position of the vertices.
+///
+/// Passes that don't shade the geometry, like stencil clip passes or shadow volumes, don't
+/// need the normals and advancement, and this halves the size of the vertices.
+pub struct PositionOnly;
+impl VertexConstructor<FillVertex, Point> for PositionOnly {
+    fn new_vertex(&mut self, input: FillVertex) -> Point { input.position }
+}
+impl VertexConstructor<StrokeVertex, Point> for PositionOnly {
+    fn new_vertex(&mut self, input: StrokeVertex) -> Point { input.position }
+}
+
 impl<F, Input, VertexType> VertexConstructor<Input, VertexType> for F
     where F: Fn(Input) -> VertexType
 {
@@ -372,20 +457,12 @@ impl<F, Input, VertexType> VertexConstructor<Input, VertexType> for F
 }
 
 /// A `BuffersBuilder` that takes the actual vertex type as input.
-pub type SimpleBuffersBuilder<'l, VertexType> = BuffersBuilder<'l, VertexType, VertexType, Identity>;
+pub type SimpleBuffersBuilder<'l, VertexType, IndexType = Index> = BuffersBuilder<'l, VertexType, VertexType, Identity, IndexType>;
 
 /// Creates a `SimpleBuffersBuilder`.
-pub fn simple_builder<VertexType>(buffers: &mut VertexBuffers<VertexType>)
-    -> SimpleBuffersBuilder<VertexType> {
-    let vertex_offset = buffers.vertices.len() as Index;
-    let index_offset = buffers.indices.len() as Index;
-    BuffersBuilder {
-        buffers: buffers,
-        vertex_offset: vertex_offset,
-        index_offset: index_offset,
-        vertex_constructor: Identity,
-        _marker: PhantomData,
-    }
+pub fn simple_builder<VertexType, IndexType: GeometryIndex>(buffers: &mut VertexBuffers<VertexType, IndexType>)
+    -> SimpleBuffersBuilder<VertexType, IndexType> {
+    BuffersBuilder::new(buffers, Identity)
 }
 
 /// Number of vertices and indices added during the tessellation.
@@ -405,53 +482,705 @@ impl Add for Count {
     }
 }
 
-impl<'l, VertexType, Input, Ctor> GeometryBuilder<Input>
-    for BuffersBuilder<'l, VertexType, Input, Ctor>
+impl<'l, VertexType, Input, Ctor, IndexType> GeometryBuilder<Input>
+    for BuffersBuilder<'l, VertexType, Input, Ctor, IndexType>
 where
     VertexType: 'l + Clone,
     Ctor: VertexConstructor<Input, VertexType>,
+    IndexType: 'l + GeometryIndex,
 {
     fn begin_geometry(&mut self) {
-        self.vertex_offset = self.buffers.vertices.len() as Index;
-        self.index_offset = self.buffers.indices.len() as Index;
+        self.vertex_offset = self.buffers.vertices.len() as u32;
+        self.index_offset = self.buffers.indices.len() as u32;
+        self.geometry_overflowed = false;
     }
 
     fn end_geometry(&mut self) -> Count {
+        if self.geometry_overflowed {
+            self.abort_geometry();
+            return Count { vertices: 0, indices: 0 };
+        }
+
         return Count {
-                   vertices: self.buffers.vertices.len() as u32 - self.vertex_offset as u32,
-                   indices: self.buffers.indices.len() as u32 - self.index_offset as u32,
+                   vertices: self.buffers.vertices.len() as u32 - self.vertex_offset,
+                   indices: self.buffers.indices.len() as u32 - self.index_offset,
                };
     }
 
     fn add_vertex(&mut self, v: Input) -> VertexId {
         self.buffers.vertices.push(self.vertex_constructor.new_vertex(v));
-        return VertexId(self.buffers.vertices.len() as Index - 1 - self.vertex_offset);
+        let index = self.buffers.vertices.len() as u32 - 1;
+        let offset = index - self.vertex_offset;
+        if offset >= MAX_GEOMETRY_VERTICES || IndexType::from_u32(index).is_none() {
+            self.overflowed = true;
+            self.geometry_overflowed = true;
+        }
+        return VertexId(offset as u16);
     }
 
     fn add_triangle(&mut self, a: VertexId, b: VertexId, c: VertexId) {
-        self.buffers.indices.push(a.offset() + self.vertex_offset);
-        self.buffers.indices.push(b.offset() + self.vertex_offset);
-        self.buffers.indices.push(c.offset() + self.vertex_offset);
+        if self.geometry_overflowed {
+            // The ids may have wrapped around and the geometry is discarded anyway.
+            return;
+        }
+        let offset = self.vertex_offset;
+        match (
+            IndexType::from_u32(a.offset() as u32 + offset),
+            IndexType::from_u32(b.offset() as u32 + offset),
+            IndexType::from_u32(c.offset() as u32 + offset),
+        ) {
+            (Some(a), Some(b), Some(c)) => {
+                self.buffers.indices.push(a);
+                self.buffers.indices.push(b);
+                self.buffers.indices.push(c);
+            }
+            _ => {
+                self.overflowed = true;
+                self.geometry_overflowed = true;
+            }
+        }
     }
 
     fn abort_geometry(&mut self) {
         self.buffers.vertices.truncate(self.vertex_offset as usize);
         self.buffers.indices.truncate(self.index_offset as usize);
+        self.geometry_overflowed = false;
     }
 }
 
 
-impl<'l, VertexType, Input, Ctor> BezierGeometryBuilder<Input>
-    for BuffersBuilder<'l, VertexType, Input, Ctor>
+impl<'l, VertexType, Input, Ctor, IndexType> BezierGeometryBuilder<Input>
+    for BuffersBuilder<'l, VertexType, Input, Ctor, IndexType>
 where
     VertexType: 'l + Clone,
     Ctor: VertexConstructor<Input, VertexType>,
+    IndexType: 'l + GeometryIndex,
 {
     fn add_quadratic_bezier(&mut self, _from: VertexId, _to: VertexId, _ctrl: Input) {
         unimplemented!();
     }
 }
 
+/// Receives the geometry produced by a `StreamingBuilder` chunk by chunk.
+pub trait GeometryReceiver<VertexType, IndexType = Index> {
+    /// Receives a chunk of vertices, `first_vertex` being the index of the first one.
+    fn receive_vertices(&mut self, first_vertex: u32, vertices: &[VertexType]);
+
+    /// Receives a chunk of indices, `first_index` being the offset of the first one.
+    fn receive_indices(&mut self, first_index: u32, indices: &[IndexType]);
+
+    /// Called when a geometry is aborted after some of its chunks were received.
+    ///
+    /// The vertices and indices past `vertex_count` and `index_count` are not valid anymore
+    /// and will be overwritten by the next geometry.
+    fn truncate(&mut self, _vertex_count: u32, _index_count: u32) {}
+}
+
+/// A `GeometryBuilder` that sends vertices and indices to a `GeometryReceiver` in chunks
+/// as they are produced, rather than accumulating the whole geometry in memory.
+///
+/// At most `chunk_size` vertices and `chunk_size` indices are kept in memory. The
+/// pending chunks are sent when full and at the end of each geometry.
+///
+/// Indices are relative to the first vertex ever received, so a receiver can for example
+/// upload each chunk into a large GPU buffer at the provided offset. Like with
+/// `BuffersBuilder`, geometries that the index type or `VertexId` can't address are
+/// discarded and reported by `overflowed`.
+pub struct StreamingBuilder<'l, VertexType, Input, Ctor, Receiver: 'l, IndexType = Index>
+where
+    Ctor: VertexConstructor<Input, VertexType>
+{
+    receiver: &'l mut Receiver,
+    vertices: Vec<VertexType>,
+    indices: Vec<IndexType>,
+    chunk_size: usize,
+    vertex_count: u32,
+    index_count: u32,
+    flushed_vertices: u32,
+    flushed_indices: u32,
+    vertex_offset: u32,
+    index_offset: u32,
+    overflowed: bool,
+    geometry_overflowed: bool,
+    vertex_constructor: Ctor,
+    _marker: PhantomData<Input>,
+}
+
+impl<'l, VertexType, Input, Ctor, Receiver: 'l, IndexType> StreamingBuilder<'l, VertexType, Input, Ctor, Receiver, IndexType>
+where
+    Ctor: VertexConstructor<Input, VertexType>,
+    Receiver: GeometryReceiver<VertexType, IndexType>,
+    IndexType: GeometryIndex,
+{
+    pub fn new(chunk_size: usize, receiver: &'l mut Receiver, ctor: Ctor) -> Self {
+        assert!(chunk_size > 0);
+        StreamingBuilder {
+            receiver: receiver,
+            vertices: Vec::with_capacity(chunk_size),
+            indices: Vec::with_capacity(chunk_size),
+            chunk_size: chunk_size,
+            vertex_count: 0,
+            index_count: 0,
+            flushed_vertices: 0,
+            flushed_indices: 0,
+            vertex_offset: 0,
+            index_offset: 0,
+            overflowed: false,
+            geometry_overflowed: false,
+            vertex_constructor: ctor,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns true if some vertices could not be addressed with the index type or with
+    /// `VertexId`.
+    ///
+    /// When this happens the geometries that overflowed are discarded.
+    pub fn overflowed(&self) -> bool { self.overflowed }
+
+    /// Total number of vertices produced so far.
+    pub fn vertex_count(&self) -> u32 { self.vertex_count }
+
+    /// Total number of indices produced so far.
+    pub fn index_count(&self) -> u32 { self.index_count }
+
+    fn flush_vertices(&mut self) {
+        if !self.vertices.is_empty() {
+            self.receiver.receive_vertices(self.flushed_vertices, &self.vertices[..]);
+            self.flushed_vertices = self.vertex_count;
+            self.vertices.clear();
+        }
+    }
+
+    fn flush_indices(&mut self) {
+        if !self.indices.is_empty() {
+            self.receiver.receive_indices(self.flushed_indices, &self.indices[..]);
+            self.flushed_indices = self.index_count;
+            self.indices.clear();
+        }
+    }
+}
+
+impl<'l, VertexType, Input, Ctor, Receiver: 'l, IndexType> GeometryBuilder<Input>
+    for StreamingBuilder<'l, VertexType, Input, Ctor, Receiver, IndexType>
+where
+    Ctor: VertexConstructor<Input, VertexType>,
+    Receiver: GeometryReceiver<VertexType, IndexType>,
+    IndexType: GeometryIndex,
+{
+    fn begin_geometry(&mut self) {
+        self.vertex_offset = self.vertex_count;
+        self.index_offset = self.index_count;
+        self.geometry_overflowed = false;
+    }
+
+    fn end_geometry(&mut self) -> Count {
+        if self.geometry_overflowed {
+            self.abort_geometry();
+            return Count { vertices: 0, indices: 0 };
+        }
+
+        self.flush_vertices();
+        self.flush_indices();
+        return Count {
+            vertices: self.vertex_count - self.vertex_offset,
+            indices: self.index_count - self.index_offset,
+        };
+    }
+
+    fn add_vertex(&mut self, v: Input) -> VertexId {
+        self.vertices.push(self.vertex_constructor.new_vertex(v));
+        self.vertex_count += 1;
+        if self.vertices.len() >= self.chunk_size {
+            self.flush_vertices();
+        }
+        let index = self.vertex_count - 1;
+        let offset = index - self.vertex_offset;
+        if offset >= MAX_GEOMETRY_VERTICES || IndexType::from_u32(index).is_none() {
+            self.overflowed = true;
+            self.geometry_overflowed = true;
+        }
+        return VertexId(offset as u16);
+    }
+
+    fn add_triangle(&mut self, a: VertexId, b: VertexId, c: VertexId) {
+        if self.geometry_overflowed {
+            return;
+        }
+        let offset = self.vertex_offset;
+        match (
+            IndexType::from_u32(a.offset() as u32 + offset),
+            IndexType::from_u32(b.offset() as u32 + offset),
+            IndexType::from_u32(c.offset() as u32 + offset),
+        ) {
+            (Some(a), Some(b), Some(c)) => {
+                self.indices.push(a);
+                self.indices.push(b);
+                self.indices.push(c);
+                self.index_count += 3;
+                if self.indices.len() >= self.chunk_size {
+                    self.flush_indices();
+                }
+            }
+            _ => {
+                self.overflowed = true;
+                self.geometry_overflowed = true;
+            }
+        }
+    }
+
+    fn abort_geometry(&mut self) {
+        self.geometry_overflowed = false;
+        let rollback = self.flushed_vertices > self.vertex_offset
+            || self.flushed_indices > self.index_offset;
+
+        // The previous geometry was flushed in end_geometry so the pending chunks only
+        // contain data from the aborted geometry.
+        self.vertices.clear();
+        self.indices.clear();
+        self.vertex_count = self.vertex_offset;
+        self.index_count = self.index_offset;
+        self.flushed_vertices = self.vertex_offset;
+        self.flushed_indices = self.index_offset;
+
+        if rollback {
+            self.receiver.truncate(self.vertex_offset, self.index_offset);
+        }
+    }
+}
+
+impl<VertexType: Clone, IndexType: Clone> GeometryReceiver<VertexType, IndexType> for VertexBuffers<VertexType, IndexType> {
+    fn receive_vertices(&mut self, first_vertex: u32, vertices: &[VertexType]) {
+        debug_assert_eq!(first_vertex as usize, self.vertices.len());
+        self.vertices.extend_from_slice(vertices);
+    }
+
+    fn receive_indices(&mut self, first_index: u32, indices: &[IndexType]) {
+        debug_assert_eq!(first_index as usize, self.indices.len());
+        self.indices.extend_from_slice(indices);
+    }
+
+    fn truncate(&mut self, vertex_count: u32, index_count: u32) {
+        self.vertices.truncate(vertex_count as usize);
+        self.indices.truncate(index_count as usize);
+    }
+}
+
+/// A `GeometryBuilder` adapter that computes normalized texture coordinates for the
+/// fill vertices it receives and forwards them as `UvFillVertex` to another builder.
+///
+/// The texture coordinates are relative to either a user-provided rectangle, or to
+/// the bounding box of the geometry. In the latter case the bounding box isn't known
+/// until the end of the tessellation, so the vertices and triangles are retained and
+/// only forwarded to the output in `end_geometry`. Retained geometries with more vertices
+/// than `VertexId` can represent are aborted and reported by `overflowed`.
+pub struct UvBuilder<'l, Output: 'l> {
+    output: &'l mut Output,
+    rect: Option<Rect>,
+    vertices: Vec<FillVertex>,
+    triangles: Vec<(VertexId, VertexId, VertexId)>,
+    overflowed: bool,
+}
+
+impl<'l, Output: 'l + GeometryBuilder<UvFillVertex>> UvBuilder<'l, Output> {
+    /// Generates texture coordinates relative to the bounding box of each geometry.
+    pub fn new(output: &'l mut Output) -> Self {
+        UvBuilder {
+            output: output,
+            rect: None,
+            vertices: Vec::new(),
+            triangles: Vec::new(),
+            overflowed: false,
+        }
+    }
+
+    /// Generates texture coordinates relative to the provided rectangle.
+    pub fn with_rect(rect: Rect, output: &'l mut Output) -> Self {
+        UvBuilder {
+            output: output,
+            rect: Some(rect),
+            vertices: Vec::new(),
+            triangles: Vec::new(),
+            overflowed: false,
+        }
+    }
+
+    /// Returns true if a retained geometry had more vertices than `VertexId` can represent.
+    ///
+    /// Overflows of the output are reported by the output.
+    pub fn overflowed(&self) -> bool { self.overflowed }
+}
+
+/// Maps a position to texture coordinates such that the rectangle's origin is
+/// at (0, 0) and its bottom-right corner at (1, 1).
+pub fn normalized_uv(position: Point, rect: &Rect) -> Point {
+    let w = if rect.size.width == 0.0 { 1.0 } else { rect.size.width };
+    let h = if rect.size.height == 0.0 { 1.0 } else { rect.size.height };
+    return point(
+        (position.x - rect.origin.x) / w,
+        (position.y - rect.origin.y) / h,
+    );
+}
+
+fn uv_vertex(vertex: FillVertex, rect: &Rect) -> UvFillVertex {
+    UvFillVertex {
+        position: vertex.position,
+        normal: vertex.normal,
+        uv: normalized_uv(vertex.position, rect),
+    }
+}
+
+impl<'l, Output: 'l + GeometryBuilder<UvFillVertex>> GeometryBuilder<FillVertex> for UvBuilder<'l, Output> {
+    fn begin_geometry(&mut self) {
+        self.vertices.clear();
+        self.triangles.clear();
+        self.output.begin_geometry();
+    }
+
+    fn end_geometry(&mut self) -> Count {
+        if self.vertices.len() > MAX_GEOMETRY_VERTICES as usize {
+            self.overflowed = true;
+            self.abort_geometry();
+            return Count { vertices: 0, indices: 0 };
+        }
+
+        if self.rect.is_none() {
+            let mut min = point(f32::MAX, f32::MAX);
+            let mut max = point(f32::MIN, f32::MIN);
+            for v in &self.vertices {
+                min.x = min.x.min(v.position.x);
+                min.y = min.y.min(v.position.y);
+                max.x = max.x.max(v.position.x);
+                max.y = max.y.max(v.position.y);
+            }
+            let bounds = if self.vertices.is_empty() {
+                rect(0.0, 0.0, 0.0, 0.0)
+            } else {
+                rect(min.x, min.y, max.x - min.x, max.y - min.y)
+            };
+
+            for v in self.vertices.drain(..) {
+                self.output.add_vertex(uv_vertex(v, &bounds));
+            }
+            for (a, b, c) in self.triangles.drain(..) {
+                self.output.add_triangle(a, b, c);
+            }
+        }
+
+        return self.output.end_geometry();
+    }
+
+    fn add_vertex(&mut self, vertex: FillVertex) -> VertexId {
+        if let Some(rect) = self.rect {
+            return self.output.add_vertex(uv_vertex(vertex, &rect));
+        }
+
+        self.vertices.push(vertex);
+        return VertexId(self.vertices.len() as u16 - 1);
+    }
+
+    fn add_triangle(&mut self, a: VertexId, b: VertexId, c: VertexId) {
+        if self.rect.is_some() {
+            self.output.add_triangle(a, b, c);
+        } else {
+            self.triangles.push((a, b, c));
+        }
+    }
+
+    fn abort_geometry(&mut self) {
+        self.vertices.clear();
+        self.triangles.clear();
+        self.output.abort_geometry();
+    }
+}
+
+/// Vertex types that can be deduplicated by a `WeldingBuilder`.
+pub trait Weldable {
+    type Key: Hash + Eq;
+    /// Two vertices with the same key are considered identical.
+    fn weld_key(&self) -> Self::Key;
+}
+
+impl Weldable for Point {
+    type Key = (u32, u32);
+    fn weld_key(&self) -> (u32, u32) { (self.x.to_bits(), self.y.to_bits()) }
+}
+
+impl Weldable for FillVertex {
+    type Key = (u32, u32, u32, u32);
+    fn weld_key(&self) -> Self::Key {
+        (
+            self.position.x.to_bits(), self.position.y.to_bits(),
+            self.normal.x.to_bits(), self.normal.y.to_bits(),
+        )
+    }
+}
+
+impl Weldable for StrokeVertex {
+    type Key = (u32, u32, u32, u32, u32, bool);
+    fn weld_key(&self) -> Self::Key {
+        (
+            self.position.x.to_bits(), self.position.y.to_bits(),
+            self.normal.x.to_bits(), self.normal.y.to_bits(),
+            self.advancement.to_bits(),
+            self.side == Side::Left,
+        )
+    }
+}
+
+/// A `GeometryBuilder` adapter that merges identical vertices before forwarding them
+/// to another builder.
+///
+/// Vertices are only merged within a geometry (between `begin_geometry` and
+/// `end_geometry`). Triangles that become degenerate after merging are discarded.
+/// This is useful to reduce the size of the vertex buffers when tessellators produce a
+/// lot of duplicated vertices, at the cost of a hash map lookup per vertex.
+pub struct WeldingBuilder<'l, Input: Weldable, Output: 'l> {
+    output: &'l mut Output,
+    vertices: HashMap<Input::Key, VertexId>,
+}
+
+impl<'l, Input: Weldable, Output: 'l + GeometryBuilder<Input>> WeldingBuilder<'l, Input, Output> {
+    pub fn new(output: &'l mut Output) -> Self {
+        WeldingBuilder {
+            output: output,
+            vertices: HashMap::new(),
+        }
+    }
+}
+
+impl<'l, Input: Weldable, Output: 'l + GeometryBuilder<Input>> GeometryBuilder<Input>
+    for WeldingBuilder<'l, Input, Output>
+{
+    fn begin_geometry(&mut self) {
+        self.vertices.clear();
+        self.output.begin_geometry();
+    }
+
+    fn end_geometry(&mut self) -> Count {
+        self.vertices.clear();
+        return self.output.end_geometry();
+    }
+
+    fn add_vertex(&mut self, vertex: Input) -> VertexId {
+        let key = vertex.weld_key();
+        if let Some(&id) = self.vertices.get(&key) {
+            return id;
+        }
+
+        let id = self.output.add_vertex(vertex);
+        self.vertices.insert(key, id);
+        return id;
+    }
+
+    fn add_triangle(&mut self, a: VertexId, b: VertexId, c: VertexId) {
+        if a == b || b == c || a == c {
+            return;
+        }
+        self.output.add_triangle(a, b, c);
+    }
+
+    fn abort_geometry(&mut self) {
+        self.vertices.clear();
+        self.output.abort_geometry();
+    }
+}
+
+/// Vertex types that have a position, used by `AaSkirtBuilder` to generate
+/// displaced copies of the vertices.
+pub trait VertexPosition: Copy {
+    fn position(&self) -> Point;
+    fn with_position(&self, position: Point) -> Self;
+}
+
+impl VertexPosition for Point {
+    fn position(&self) -> Point { *self }
+    fn with_position(&self, position: Point) -> Self { position }
+}
+
+impl VertexPosition for FillVertex {
+    fn position(&self) -> Point { self.position }
+    fn with_position(&self, position: Point) -> Self {
+        FillVertex { position: position, .. *self }
+    }
+}
+
+impl VertexPosition for StrokeVertex {
+    fn position(&self) -> Point { self.position }
+    fn with_position(&self, position: Point) -> Self {
+        StrokeVertex { position: position, .. *self }
+    }
+}
+
+/// A vertex with an opacity coefficient, produced by `AaSkirtBuilder`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AntiAliasedVertex<V> {
+    pub vertex: V,
+    /// 1.0 for the vertices of the original geometry, 0.0 on the outer side of the skirt.
+    pub alpha: f32,
+}
+
+/// A `GeometryBuilder` adapter that adds an anti-aliasing "skirt" around the geometry.
+///
+/// The skirt is a strip of triangles of a given width along the boundary of the geometry,
+/// with the alpha attribute ramping from one on the geometry's edges to zero on the outer
+/// side of the skirt. Rendering it with alpha blending produces smooth edges without
+/// relying on MSAA. The width is expressed in the geometry's coordinate space, so it should
+/// typically correspond to one pixel after transformation.
+///
+/// The boundary is only known once the whole geometry has been produced, so the vertices
+/// and triangles are retained and forwarded to the output in `end_geometry`. Geometries
+/// with more vertices than `VertexId` can represent are aborted and reported by
+/// `overflowed`.
+pub struct AaSkirtBuilder<'l, Input, Output: 'l> {
+    output: &'l mut Output,
+    width: f32,
+    vertices: Vec<Input>,
+    triangles: Vec<[VertexId; 3]>,
+    overflowed: bool,
+}
+
+impl<'l, Input, Output> AaSkirtBuilder<'l, Input, Output>
+where
+    Input: VertexPosition,
+    Output: 'l + GeometryBuilder<AntiAliasedVertex<Input>>,
+{
+    pub fn new(width: f32, output: &'l mut Output) -> Self {
+        AaSkirtBuilder {
+            output: output,
+            width: width,
+            vertices: Vec::new(),
+            triangles: Vec::new(),
+            overflowed: false,
+        }
+    }
+
+    /// Returns true if a geometry had more vertices than `VertexId` can represent.
+    ///
+    /// Overflows of the output, which also receives the vertices of the skirt, are
+    /// reported by the output.
+    pub fn overflowed(&self) -> bool { self.overflowed }
+
+    fn add_skirt(&mut self) {
+        // Find the edges that belong to a single triangle.
+        let mut edge_map: HashMap<(u16, u16), usize> = HashMap::new();
+        let mut edges: Vec<(VertexId, VertexId, VertexId, u32)> = Vec::new();
+        for tri in &self.triangles {
+            for &(a, b, c) in &[(tri[0], tri[1], tri[2]), (tri[1], tri[2], tri[0]), (tri[2], tri[0], tri[1])] {
+                let key = (a.0.min(b.0), a.0.max(b.0));
+                if let Some(&idx) = edge_map.get(&key) {
+                    edges[idx].3 += 1;
+                    continue;
+                }
+                edge_map.insert(key, edges.len());
+                edges.push((a, b, c, 1));
+            }
+        }
+
+        // Accumulate the outward normals of the boundary edges at each vertex.
+        let mut normals: Vec<Option<(Vec2, Vec2)>> = vec![None; self.vertices.len()];
+        for &(a, b, c, count) in &edges {
+            if count != 1 || a == b {
+                continue;
+            }
+            let pa = self.vertices[a.0 as usize].position();
+            let pb = self.vertices[b.0 as usize].position();
+            let pc = self.vertices[c.0 as usize].position();
+            let e = pb - pa;
+            if e.square_length() == 0.0 {
+                continue;
+            }
+            let mut n = vec2(-e.y, e.x).normalize();
+            if n.dot(pc - pa) > 0.0 {
+                n = -n;
+            }
+            for &v in &[a, b] {
+                let entry = &mut normals[v.0 as usize];
+                *entry = Some(match *entry {
+                    Some((sum, first)) => (sum + n, first),
+                    None => (n, n),
+                });
+            }
+        }
+
+        // Add the outer vertices of the skirt.
+        let mut skirt_ids: Vec<Option<VertexId>> = vec![None; self.vertices.len()];
+        for (idx, normal) in normals.iter().enumerate() {
+            if let Some((sum, first)) = *normal {
+                let n = if sum.square_length() > 1e-6 { sum.normalize() } else { first };
+                // Keep the skirt's width constant at corners (clamped for very sharp ones).
+                let miter = 1.0 / n.dot(first).max(0.25);
+                let vertex = self.vertices[idx];
+                let position = vertex.position() + n * (self.width * miter);
+                skirt_ids[idx] = Some(self.output.add_vertex(AntiAliasedVertex {
+                    vertex: vertex.with_position(position),
+                    alpha: 0.0,
+                }));
+            }
+        }
+
+        for &(a, b, _, count) in &edges {
+            if count != 1 {
+                continue;
+            }
+            if let (Some(a2), Some(b2)) = (skirt_ids[a.0 as usize], skirt_ids[b.0 as usize]) {
+                self.output.add_triangle(a, b, b2);
+                self.output.add_triangle(a, b2, a2);
+            }
+        }
+    }
+}
+
+impl<'l, Input, Output> GeometryBuilder<Input> for AaSkirtBuilder<'l, Input, Output>
+where
+    Input: VertexPosition,
+    Output: 'l + GeometryBuilder<AntiAliasedVertex<Input>>,
+{
+    fn begin_geometry(&mut self) {
+        self.vertices.clear();
+        self.triangles.clear();
+        self.output.begin_geometry();
+    }
+
+    fn end_geometry(&mut self) -> Count {
+        if self.vertices.len() > MAX_GEOMETRY_VERTICES as usize {
+            self.overflowed = true;
+            self.abort_geometry();
+            return Count { vertices: 0, indices: 0 };
+        }
+
+        for i in 0..self.vertices.len() {
+            self.output.add_vertex(AntiAliasedVertex { vertex: self.vertices[i], alpha: 1.0 });
+        }
+        for i in 0..self.triangles.len() {
+            let tri = self.triangles[i];
+            self.output.add_triangle(tri[0], tri[1], tri[2]);
+        }
+
+        self.add_skirt();
+
+        self.vertices.clear();
+        self.triangles.clear();
+
+        return self.output.end_geometry();
+    }
+
+    fn add_vertex(&mut self, vertex: Input) -> VertexId {
+        self.vertices.push(vertex);
+        return VertexId(self.vertices.len() as u16 - 1);
+    }
+
+    fn add_triangle(&mut self, a: VertexId, b: VertexId, c: VertexId) {
+        self.triangles.push([a, b, c]);
+    }
+
+    fn abort_geometry(&mut self) {
+        self.vertices.clear();
+        self.triangles.clear();
+        self.output.abort_geometry();
+    }
+}
+
 #[test]
 fn test_simple_quad() {
     #[derive(Copy, Clone, PartialEq, Debug)]
@@ -598,3 +1327,208 @@ fn test_closure() {
         point(1.0, 1.0),
     ]);
 }
+
+#[test]
+fn test_uv_builder() {
+    use basic_shapes::fill_rectangle;
+
+    let mut buffers: VertexBuffers<UvFillVertex> = VertexBuffers::new();
+
+    // Relative to the bounding box.
+    fill_rectangle(
+        &rect(10.0, 20.0, 10.0, 5.0),
+        &mut UvBuilder::new(&mut simple_builder(&mut buffers)),
+    );
+
+    assert_eq!(buffers.vertices.len(), 4);
+    assert_eq!(&buffers.indices[..], &[0, 1, 2, 0, 2, 3]);
+    for v in &buffers.vertices {
+        assert!(v.uv == point(0.0, 0.0) || v.uv == point(1.0, 0.0)
+            || v.uv == point(1.0, 1.0) || v.uv == point(0.0, 1.0));
+    }
+
+    // Relative to a user-provided rectangle.
+    fill_rectangle(
+        &rect(10.0, 20.0, 10.0, 5.0),
+        &mut UvBuilder::with_rect(rect(10.0, 20.0, 20.0, 20.0), &mut simple_builder(&mut buffers)),
+    );
+
+    assert_eq!(buffers.vertices.len(), 8);
+    assert_eq!(&buffers.indices[6..], &[4, 5, 6, 4, 6, 7]);
+    for v in &buffers.vertices[4..] {
+        assert_eq!(v.uv, normalized_uv(v.position, &rect(10.0, 20.0, 20.0, 20.0)));
+        assert!(v.uv.x <= 0.5 && v.uv.y <= 0.25);
+    }
+}
+
+#[test]
+fn test_index_overflow() {
+    use math::{Point, point};
+
+    fn add_triangles<Builder: GeometryBuilder<Point>>(builder: &mut Builder, count: u32) {
+        builder.begin_geometry();
+        for _ in 0..count {
+            let a = builder.add_vertex(point(0.0, 0.0));
+            let b = builder.add_vertex(point(1.0, 0.0));
+            let c = builder.add_vertex(point(1.0, 1.0));
+            builder.add_triangle(a, b, c);
+        }
+        builder.end_geometry();
+    }
+
+    // Two geometries that each fit in u16 indices but not together.
+    let mut buffers16: VertexBuffers<Point> = VertexBuffers::new();
+    {
+        let mut builder = simple_builder(&mut buffers16);
+        add_triangles(&mut builder, 20000);
+        assert!(!builder.overflowed());
+    }
+    {
+        let mut builder = simple_builder(&mut buffers16);
+        add_triangles(&mut builder, 20000);
+        assert!(builder.overflowed());
+    }
+    // The geometry that overflowed is discarded.
+    assert_eq!(buffers16.vertices.len(), 60000);
+    assert_eq!(buffers16.indices.len(), 60000);
+
+    let mut buffers32: VertexBuffers<Point, u32> = VertexBuffers::with_capacity_and_index_type(0, 0);
+    for _ in 0..2 {
+        let mut builder = simple_builder(&mut buffers32);
+        add_triangles(&mut builder, 20000);
+        assert!(!builder.overflowed());
+    }
+    assert_eq!(buffers32.indices.len(), 120000);
+    assert_eq!(buffers32.indices[119999], 119999);
+
+    // A single geometry can't have more vertices than VertexId can represent, whatever
+    // the index type.
+    {
+        let mut builder = simple_builder(&mut buffers32);
+        add_triangles(&mut builder, 25000);
+        assert!(builder.overflowed());
+    }
+    assert_eq!(buffers32.vertices.len(), 120000);
+    assert_eq!(buffers32.indices.len(), 120000);
+
+    let mut streamed: VertexBuffers<Point, u32> = VertexBuffers::with_capacity_and_index_type(0, 0);
+    {
+        let mut builder = StreamingBuilder::new(1024, &mut streamed, Identity);
+        add_triangles(&mut builder, 25000);
+        assert!(builder.overflowed());
+        add_triangles(&mut builder, 10);
+    }
+    assert_eq!(streamed.vertices.len(), 30);
+    assert_eq!(streamed.indices.len(), 30);
+    assert_eq!(streamed.indices[29], 29);
+
+    let mut uv_buffers: VertexBuffers<UvFillVertex, u32> = VertexBuffers::with_capacity_and_index_type(0, 0);
+    {
+        let mut output = simple_builder(&mut uv_buffers);
+        let mut builder = UvBuilder::new(&mut output);
+        builder.begin_geometry();
+        for _ in 0..(MAX_GEOMETRY_VERTICES + 1) {
+            builder.add_vertex(FillVertex { position: point(0.0, 0.0), normal: vec2(0.0, 0.0) });
+        }
+        let count = builder.end_geometry();
+        assert_eq!(count, Count { vertices: 0, indices: 0 });
+        assert!(builder.overflowed());
+    }
+    assert!(uv_buffers.vertices.is_empty());
+}
+
+#[test]
+fn test_welding_builder() {
+    use math::{Point, point};
+
+    let mut buffers: VertexBuffers<Point> = VertexBuffers::new();
+    let count = {
+        let mut simple = simple_builder(&mut buffers);
+        let mut builder = WeldingBuilder::new(&mut simple);
+        builder.begin_geometry();
+        let a = builder.add_vertex(point(0.0, 0.0));
+        let b = builder.add_vertex(point(1.0, 0.0));
+        let c = builder.add_vertex(point(1.0, 1.0));
+        builder.add_triangle(a, b, c);
+        let a = builder.add_vertex(point(0.0, 0.0));
+        let c = builder.add_vertex(point(1.0, 1.0));
+        let d = builder.add_vertex(point(0.0, 1.0));
+        builder.add_triangle(a, c, d);
+        // Degenerate after welding.
+        let e = builder.add_vertex(point(0.0, 1.0));
+        builder.add_triangle(a, d, e);
+        builder.end_geometry()
+    };
+
+    assert_eq!(count.vertices, 4);
+    assert_eq!(count.indices, 6);
+    assert_eq!(&buffers.indices[..], &[0, 1, 2, 0, 2, 3]);
+}
+
+#[test]
+fn test_aa_skirt() {
+    use basic_shapes::fill_rectangle;
+
+    let mut buffers: VertexBuffers<AntiAliasedVertex<FillVertex>> = VertexBuffers::new();
+    let count = fill_rectangle(
+        &rect(0.0, 0.0, 10.0, 10.0),
+        &mut AaSkirtBuilder::new(1.0, &mut simple_builder(&mut buffers)),
+    );
+
+    // 4 vertices for the rectangle and 4 for the skirt,
+    // 2 triangles for the rectangle and 2 per edge for the skirt.
+    assert_eq!(count.vertices, 8);
+    assert_eq!(count.indices, 30);
+
+    for v in &buffers.vertices[..4] {
+        assert_eq!(v.alpha, 1.0);
+    }
+    for v in &buffers.vertices[4..] {
+        assert_eq!(v.alpha, 0.0);
+        let p = v.vertex.position;
+        // The outer vertices are at the corners of the 1-unit wide skirt.
+        assert!((p.x + 1.0).abs() < 1e-4 || (p.x - 11.0).abs() < 1e-4);
+        assert!((p.y + 1.0).abs() < 1e-4 || (p.y - 11.0).abs() < 1e-4);
+    }
+}
+
+#[test]
+fn test_streaming_builder() {
+    use basic_shapes::fill_circle;
+
+    let mut expected: VertexBuffers<FillVertex> = VertexBuffers::new();
+    fill_circle(point(0.0, 0.0), 10.0, 0.01, &mut simple_builder(&mut expected));
+    fill_circle(point(5.0, 5.0), 3.0, 0.01, &mut simple_builder(&mut expected));
+
+    let mut received: VertexBuffers<FillVertex> = VertexBuffers::new();
+    {
+        let mut builder = StreamingBuilder::new(16, &mut received, Identity);
+        fill_circle(point(0.0, 0.0), 10.0, 0.01, &mut builder);
+
+        // Aborting a geometry after some of its chunks were sent.
+        builder.begin_geometry();
+        for _ in 0..40 {
+            builder.add_vertex(FillVertex { position: point(1.0, 2.0), normal: vec2(0.0, 0.0) });
+        }
+        builder.abort_geometry();
+
+        fill_circle(point(5.0, 5.0), 3.0, 0.01, &mut builder);
+        assert!(!builder.overflowed());
+    }
+
+    assert!(expected.vertices.len() > 16);
+    assert_eq!(received.vertices, expected.vertices);
+    assert_eq!(received.indices, expected.indices);
+}
+
+#[test]
+fn test_position_only() {
+    use basic_shapes::fill_rectangle;
+
+    let mut buffers: VertexBuffers<Point> = VertexBuffers::new();
+    let count = fill_rectangle(&rect(1.0, 2.0, 3.0, 4.0), &mut BuffersBuilder::new(&mut buffers, PositionOnly));
+
+    assert_eq!(count.vertices, 4);
+    assert_eq!(buffers.indices.len(), 6);
+    assert!(buffers.vertices.contains(&point(4.0, 6.0)));
+}
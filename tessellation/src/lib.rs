@@ -38,6 +38,9 @@
 //!   provides an interface for types that help with building and assembling the vertices and triangles that
 //!   form the tessellation, usually in the form of arbitrary vertex and index buffers.
 //! * The various specialised tessellators in the [`basic_shapes`](basic_shapes/index.html) modules.
+//! * [`offset_path`](fn.offset_path.html) - Computes inset or outset versions of flattened paths.
+//! * [`stroke_to_path`](fn.stroke_to_path.html) - Computes the outline of a stroke as a fillable path.
+//! * [`path_boolean`](fn.path_boolean.html) - Computes the union, intersection, difference or xor of two paths.
 //!
 //! ## The tessellation pipeline
 //!
@@ -175,15 +178,18 @@ extern crate lyon_path_builder as path_builder;
 extern crate lyon_path_iterator as path_iterator;
 extern crate lyon_bezier as bezier;
 
-#[cfg(test)]
 extern crate lyon_path as path;
 #[cfg(test)]
 extern crate lyon_extra as extra;
 
 pub mod basic_shapes;
 pub mod geometry_builder;
+pub mod stats;
 mod path_fill;
 mod path_stroke;
+mod path_offset;
+mod path_markers;
+mod path_boolean;
 mod math_utils;
 
 pub use core::*;
@@ -195,7 +201,19 @@ pub use path_fill::*;
 pub use path_stroke::*;
 
 #[doc(inline)]
-pub use geometry_builder::{GeometryBuilder, BezierGeometryBuilder, VertexBuffers, BuffersBuilder, VertexConstructor, Count};
+pub use path_offset::*;
+
+#[doc(inline)]
+pub use path_boolean::*;
+
+#[doc(inline)]
+pub use path_markers::*;
+
+#[doc(inline)]
+pub use stats::{TessellationStats, TessellationProfile};
+
+#[doc(inline)]
+pub use geometry_builder::{GeometryBuilder, BezierGeometryBuilder, VertexBuffers, BuffersBuilder, VertexConstructor, Count, UvBuilder, WeldingBuilder, AaSkirtBuilder, StreamingBuilder, GeometryReceiver};
 
 /// Left or right.
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -232,6 +250,20 @@ pub struct FillVertex {
     pub normal: math::Vec2,
 }
 
+/// A fill vertex with normalized texture coordinates.
+///
+/// See [`UvBuilder`](geometry_builder/struct.UvBuilder.html).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UvFillVertex {
+    /// Position of the vertex (on the path).
+    pub position: math::Point,
+    /// See `FillVertex::normal`.
+    pub normal: math::Vec2,
+    /// Position of the vertex relative to the reference rectangle, such that the
+    /// rectangle's origin is at (0, 0) and its bottom-right corner at (1, 1).
+    pub uv: math::Point,
+}
+
 /// Line cap as defined by the SVG specification.
 ///
 /// See: https://svgwg.org/specs/strokes/#StrokeLinecapProperty
@@ -280,14 +312,15 @@ pub enum LineCap {
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum LineJoin {
     /// A sharp corner is to be used to join path segments.
+    /// If the miter limit is exceeded, a bevel join is used instead.
     Miter,
-    /// [Not implemented] Same as a miter join, but if the miter limit is exceeded,
+    /// Same as a miter join, but if the miter limit is exceeded,
     /// the miter is clipped at a miter length equal to the miter limit value
     /// multiplied by the stroke width.
     MiterClip,
     /// A round corner is to be used to join path segments.
     Round,
-    /// [Not implemented] A bevelled corner is to be used to join path segments.
+    /// A bevelled corner is to be used to join path segments.
     /// The bevel shape is a triangle that fills the area between the two stroked
     /// segments.
     Bevel,
@@ -308,9 +341,10 @@ pub struct StrokeOptions {
     /// Line width
     pub line_width: f32,
 
-    /// See the SVG specification.
+    /// The ratio between the miter length and the line width above which
+    /// miter joins are replaced with bevel joins (or clipped, with `LineJoin::MiterClip`).
     ///
-    /// Not implemented yet!
+    /// See the SVG specification.
     pub miter_limit: f32,
 
     /// Maximum allowed distance to the path when building an approximation.
@@ -331,6 +365,15 @@ pub struct StrokeOptions {
     /// the vertex normal multiplied by the line with to each vertex position.
     pub apply_line_width: bool,
 
+    /// How to deal with input that can't be tessellated.
+    ///
+    /// Like `FillOptions::error_policy`, dropping a bad sub-path is often preferable to
+    /// producing vertices that are not finite. `StrokeTessellator::report` tells what was
+    /// left out.
+    ///
+    /// Default value: `StrokeErrorPolicy::Unchecked`.
+    pub error_policy: StrokeErrorPolicy,
+
     // To be able to add fields without making it a breaking change, add an empty private field
     // which makes it impossible to create a StrokeOptions without calling the constructor.
     _private: (),
@@ -347,6 +390,7 @@ impl StrokeOptions {
             tolerance: 0.1,
             vertex_aa: false,
             apply_line_width: true,
+            error_policy: StrokeErrorPolicy::Unchecked,
             _private: (),
         }
     }
@@ -400,4 +444,9 @@ impl StrokeOptions {
         self.apply_line_width = false;
         return self;
     }
+
+    pub fn with_error_policy(mut self, policy: StrokeErrorPolicy) -> StrokeOptions {
+        self.error_policy = policy;
+        return self;
+    }
 }
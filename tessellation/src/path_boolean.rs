@@ -0,0 +1,476 @@
+//! Boolean operations on paths.
+
+use math::*;
+use core::FlattenedEvent;
+use path_builder::BaseBuilder;
+use path_fill::FillRule;
+
+use std::collections::{HashMap, HashSet};
+use std::f32;
+
+/// The boolean operations supported by `path_boolean`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BooleanOp {
+    /// The areas covered by either path.
+    Union,
+    /// The areas covered by both paths.
+    Intersection,
+    /// The areas covered by the first path and not by the second one.
+    Difference,
+    /// The areas covered by exactly one of the paths.
+    Xor,
+}
+
+impl BooleanOp {
+    fn apply(self, a: bool, b: bool) -> bool {
+        match self {
+            BooleanOp::Union => a || b,
+            BooleanOp::Intersection => a && b,
+            BooleanOp::Difference => a && !b,
+            BooleanOp::Xor => a != b,
+        }
+    }
+}
+
+/// Computes a boolean operation between the fills of two flattened paths and writes the
+/// outline of the result into a path builder.
+///
+/// All sub-paths are considered closed, like when filling them. The sub-paths produced
+/// have the filled area on their left (counter-clockwise outlines and clockwise holes
+/// assuming y points upwards), so the result can be filled with either fill rule.
+///
+/// The edges of both paths are split at their intersections, and each piece of edge is kept
+/// if the result of the operation differs on its two sides. The cost is quadratic with
+/// the number of edges.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut builder = Path::builder();
+/// path_boolean(
+///     a.path_iter().flattened(0.05), FillRule::EvenOdd,
+///     b.path_iter().flattened(0.05), FillRule::NonZero,
+///     BooleanOp::Difference,
+///     &mut builder,
+/// );
+/// let result = builder.build();
+/// ```
+pub fn path_boolean<A, B, Output>(
+    a: A,
+    a_fill_rule: FillRule,
+    b: B,
+    b_fill_rule: FillRule,
+    op: BooleanOp,
+    output: &mut Output,
+) where
+    A: Iterator<Item = FlattenedEvent>,
+    B: Iterator<Item = FlattenedEvent>,
+    Output: BaseBuilder,
+{
+    let mut edges = Vec::new();
+    collect_edges(a, &mut edges);
+    let num_a_edges = edges.len();
+    collect_edges(b, &mut edges);
+
+    let (shape_a, shape_b) = edges.split_at(num_a_edges);
+    let is_in_result = |p: Point| {
+        op.apply(
+            is_inside(p, shape_a, a_fill_rule),
+            is_inside(p, shape_b, b_fill_rule),
+        )
+    };
+
+    let sample_distance = sample_distance(&edges);
+    let mut kept = Vec::new();
+    let mut known = HashSet::new();
+    for edge in split_edges(&edges) {
+        let v = edge.to - edge.from;
+        let length = v.length();
+        let normal = vec2(-v.y, v.x) * ((length * 0.25).min(sample_distance) / length);
+        let mid = point((edge.from.x + edge.to.x) * 0.5, (edge.from.y + edge.to.y) * 0.5);
+
+        let left = is_in_result(mid + normal);
+        let right = is_in_result(mid - normal);
+        if left == right {
+            continue;
+        }
+
+        let edge = if left { edge } else { Edge { from: edge.to, to: edge.from } };
+        // Overlapping edges of the two paths produce the same piece twice.
+        if known.insert((point_key(edge.from), point_key(edge.to))) {
+            kept.push(edge);
+        }
+    }
+
+    build_outlines(&kept, output);
+}
+
+// The distance at which the sides of the edges are sampled, relative to the size of the
+// geometry.
+const SAMPLE_DISTANCE: f32 = 0.000001;
+const EPSILON: f64 = 0.000001;
+
+#[derive(Copy, Clone, Debug)]
+struct Edge {
+    from: Point,
+    to: Point,
+}
+
+fn collect_edges<Iter: Iterator<Item = FlattenedEvent>>(path: Iter, edges: &mut Vec<Edge>) {
+    let mut first = point(0.0, 0.0);
+    let mut current = first;
+    for evt in path {
+        match evt {
+            FlattenedEvent::MoveTo(to) => {
+                push_edge(edges, current, first);
+                first = to;
+                current = to;
+            }
+            FlattenedEvent::LineTo(to) => {
+                push_edge(edges, current, to);
+                current = to;
+            }
+            FlattenedEvent::Close => {
+                push_edge(edges, current, first);
+                current = first;
+            }
+        }
+    }
+    push_edge(edges, current, first);
+}
+
+// Scales SAMPLE_DISTANCE with the bounding box of the edges, so that the samples are
+// neither further than the features of small shapes nor lost in the precision of large
+// coordinates.
+fn sample_distance(edges: &[Edge]) -> f32 {
+    if edges.is_empty() {
+        return 0.0;
+    }
+
+    let mut min = edges[0].from;
+    let mut max = min;
+    for edge in edges {
+        for p in &[edge.from, edge.to] {
+            min = point(min.x.min(p.x), min.y.min(p.y));
+            max = point(max.x.max(p.x), max.y.max(p.y));
+        }
+    }
+    let size = (max.x - min.x).max(max.y - min.y);
+    let magnitude = min.x.abs().max(min.y.abs()).max(max.x.abs()).max(max.y.abs());
+
+    return (size * SAMPLE_DISTANCE).max(magnitude * f32::EPSILON * 4.0);
+}
+
+fn push_edge(edges: &mut Vec<Edge>, from: Point, to: Point) {
+    if from != to {
+        edges.push(Edge { from: from, to: to });
+    }
+}
+
+// Splits the edges at their intersections. Pieces that meet share bit-identical endpoints.
+fn split_edges(edges: &[Edge]) -> Vec<Edge> {
+    let mut splits: Vec<Vec<(f64, Point)>> = vec![Vec::new(); edges.len()];
+    for i in 0..edges.len() {
+        for j in (i + 1)..edges.len() {
+            let (a, b) = (edges[i], edges[j]);
+            for (t, p, on_a) in intersections(a, b) {
+                if on_a {
+                    splits[i].push((t, p));
+                } else {
+                    splits[j].push((t, p));
+                }
+            }
+        }
+    }
+
+    let mut result = Vec::with_capacity(edges.len());
+    for (edge, edge_splits) in edges.iter().zip(splits.iter_mut()) {
+        edge_splits.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        let mut from = edge.from;
+        for &(_, p) in edge_splits.iter() {
+            push_edge(&mut result, from, p);
+            from = p;
+        }
+        push_edge(&mut result, from, edge.to);
+    }
+
+    return result;
+}
+
+// Returns the points where a must be split (on_a = true) or b must be split (on_a = false),
+// along with the position of the split on the edge.
+fn intersections(a: Edge, b: Edge) -> Vec<(f64, Point, bool)> {
+    let mut result = Vec::new();
+
+    let p = (a.from.x as f64, a.from.y as f64);
+    let r = ((a.to.x - a.from.x) as f64, (a.to.y - a.from.y) as f64);
+    let q = (b.from.x as f64, b.from.y as f64);
+    let s = ((b.to.x - b.from.x) as f64, (b.to.y - b.from.y) as f64);
+    let qp = (q.0 - p.0, q.1 - p.1);
+
+    let cross = |u: (f64, f64), v: (f64, f64)| u.0 * v.1 - u.1 * v.0;
+    let dot = |u: (f64, f64), v: (f64, f64)| u.0 * v.0 + u.1 * v.1;
+    let r_len = dot(r, r).sqrt();
+    let s_len = dot(s, s).sqrt();
+
+    let denom = cross(r, s);
+    if denom.abs() <= EPSILON * r_len * s_len {
+        // Parallel edges only need to be split if they overlap.
+        if cross(qp, r).abs() > EPSILON * r_len {
+            return result;
+        }
+        for &end in &[b.from, b.to] {
+            let t = dot(((end.x as f64) - p.0, (end.y as f64) - p.1), r) / (r_len * r_len);
+            if t > EPSILON && t < 1.0 - EPSILON {
+                result.push((t, end, true));
+            }
+        }
+        for &end in &[a.from, a.to] {
+            let u = dot(((end.x as f64) - q.0, (end.y as f64) - q.1), s) / (s_len * s_len);
+            if u > EPSILON && u < 1.0 - EPSILON {
+                result.push((u, end, false));
+            }
+        }
+        return result;
+    }
+
+    let t = cross(qp, s) / denom;
+    let u = cross(qp, r) / denom;
+    if t < -EPSILON || t > 1.0 + EPSILON || u < -EPSILON || u > 1.0 + EPSILON {
+        return result;
+    }
+
+    let a_end = t <= EPSILON || t >= 1.0 - EPSILON;
+    let b_end = u <= EPSILON || u >= 1.0 - EPSILON;
+    if a_end && b_end {
+        return result;
+    }
+
+    if b_end {
+        // An endpoint of b lies on a, use it as is to avoid precision issues.
+        result.push((t, if u < 0.5 { b.from } else { b.to }, true));
+    } else if a_end {
+        result.push((u, if t < 0.5 { a.from } else { a.to }, false));
+    } else {
+        let x = point((p.0 + r.0 * t) as f32, (p.1 + r.1 * t) as f32);
+        result.push((t, x, true));
+        result.push((u, x, false));
+    }
+
+    return result;
+}
+
+fn winding_number(p: Point, edges: &[Edge]) -> i32 {
+    let mut winding = 0;
+    for edge in edges {
+        let side = (edge.to - edge.from).cross(p - edge.from);
+        if edge.from.y <= p.y {
+            if edge.to.y > p.y && side > 0.0 {
+                winding += 1;
+            }
+        } else if edge.to.y <= p.y && side < 0.0 {
+            winding -= 1;
+        }
+    }
+    return winding;
+}
+
+fn is_inside(p: Point, edges: &[Edge], fill_rule: FillRule) -> bool {
+    let winding = winding_number(p, edges);
+    return match fill_rule {
+        FillRule::EvenOdd => winding % 2 != 0,
+        FillRule::NonZero => winding != 0,
+    };
+}
+
+fn point_key(p: Point) -> (u32, u32) {
+    (p.x.to_bits(), p.y.to_bits())
+}
+
+// Connects the edges into closed sub-paths.
+//
+// The edges are indexed by their start point and each one is taken out of the index once,
+// so this is linear with the number of edges. Chains of edges that don't come back to their
+// start can't bound an area and are dropped rather than closed with an edge that is not in
+// the result.
+fn build_outlines<Output: BaseBuilder>(edges: &[Edge], output: &mut Output) {
+    let mut outgoing: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+    for (i, edge) in edges.iter().enumerate().rev() {
+        outgoing.entry(point_key(edge.from)).or_insert_with(Vec::new).push(i);
+    }
+
+    let mut used = vec![false; edges.len()];
+    let mut outline = Vec::new();
+    for start in 0..edges.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+
+        let first = edges[start].from;
+        let mut current = edges[start].to;
+        outline.clear();
+        outline.push(first);
+        let mut closed = true;
+        while current != first {
+            outline.push(current);
+            match next_edge(&mut outgoing, &used, current) {
+                Some(e) => {
+                    used[e] = true;
+                    current = edges[e].to;
+                }
+                None => {
+                    closed = false;
+                    break;
+                }
+            }
+        }
+
+        if !closed {
+            continue;
+        }
+        output.move_to(outline[0]);
+        for &p in &outline[1..] {
+            output.line_to(p);
+        }
+        output.close();
+    }
+}
+
+// Takes an unused edge starting at a point out of the index.
+fn next_edge(outgoing: &mut HashMap<(u32, u32), Vec<usize>>, used: &[bool], from: Point) -> Option<usize> {
+    let candidates = match outgoing.get_mut(&point_key(from)) {
+        Some(candidates) => candidates,
+        None => { return None; }
+    };
+    while let Some(e) = candidates.pop() {
+        if !used[e] {
+            return Some(e);
+        }
+    }
+
+    return None;
+}
+
+#[cfg(test)]
+use path::Path;
+
+#[cfg(test)]
+fn square_events(x: f32, y: f32, size: f32, ccw: bool) -> Vec<FlattenedEvent> {
+    let mut points = vec![point(x, y), point(x + size, y), point(x + size, y + size), point(x, y + size)];
+    if !ccw {
+        points.reverse();
+    }
+    let mut events = vec![FlattenedEvent::MoveTo(points[0])];
+    for &p in &points[1..] {
+        events.push(FlattenedEvent::LineTo(p));
+    }
+    events.push(FlattenedEvent::Close);
+    return events;
+}
+
+#[cfg(test)]
+fn signed_area(path: &Path) -> f32 {
+    let mut area = 0.0;
+    let mut first = point(0.0, 0.0);
+    let mut current = first;
+    for evt in path.iter() {
+        match evt {
+            ::core::PathEvent::MoveTo(to) => {
+                first = to;
+                current = to;
+            }
+            ::core::PathEvent::LineTo(to) => {
+                area += current.x * to.y - to.x * current.y;
+                current = to;
+            }
+            ::core::PathEvent::Close => {
+                area += current.x * first.y - first.x * current.y;
+                current = first;
+            }
+            _ => { panic!(); }
+        }
+    }
+    return area * 0.5;
+}
+
+#[test]
+fn test_boolean_squares() {
+    let cases = [
+        (BooleanOp::Union, 175.0),
+        (BooleanOp::Intersection, 25.0),
+        (BooleanOp::Difference, 75.0),
+        (BooleanOp::Xor, 150.0),
+    ];
+
+    for &b_ccw in &[true, false] {
+        for &(op, expected_area) in &cases {
+            let a = square_events(0.0, 0.0, 10.0, true);
+            let b = square_events(5.0, 5.0, 10.0, b_ccw);
+
+            let mut builder = Path::builder();
+            path_boolean(
+                a.into_iter(), FillRule::EvenOdd,
+                b.into_iter(), FillRule::NonZero,
+                op,
+                &mut builder,
+            );
+            let path = builder.build();
+
+            assert!((signed_area(&path) - expected_area).abs() < 0.01);
+        }
+    }
+}
+
+#[test]
+fn test_boolean_hole() {
+    // Cutting a hole in a square.
+    let a = square_events(0.0, 0.0, 10.0, true);
+    let b = square_events(2.0, 2.0, 6.0, true);
+
+    let mut builder = Path::builder();
+    path_boolean(
+        a.into_iter(), FillRule::EvenOdd,
+        b.into_iter(), FillRule::EvenOdd,
+        BooleanOp::Difference,
+        &mut builder,
+    );
+    let path = builder.build();
+
+    assert_eq!(path.points().len(), 8);
+    assert!((signed_area(&path) - 64.0).abs() < 0.01);
+}
+
+#[test]
+fn test_boolean_scale() {
+    // The sides of the edges are sampled relative to the size of the shapes.
+    for &size in &[0.001, 1.0, 1000000.0] {
+        let a = square_events(0.0, 0.0, size, true);
+        let b = square_events(size * 0.5, size * 0.5, size, true);
+
+        let mut builder = Path::builder();
+        path_boolean(
+            a.into_iter(), FillRule::EvenOdd,
+            b.into_iter(), FillRule::EvenOdd,
+            BooleanOp::Intersection,
+            &mut builder,
+        );
+        let path = builder.build();
+
+        assert_eq!(path.points().len(), 4);
+        let expected_area = size * size * 0.25;
+        assert!((signed_area(&path) - expected_area).abs() < expected_area * 0.01);
+    }
+}
+
+#[test]
+fn test_boolean_open_chain() {
+    // A chain of edges that doesn't come back to its start produces no outline.
+    let edges = [
+        Edge { from: point(0.0, 0.0), to: point(1.0, 0.0) },
+        Edge { from: point(1.0, 0.0), to: point(1.0, 1.0) },
+    ];
+    let mut builder = Path::builder();
+    build_outlines(&edges, &mut builder);
+    assert!(builder.build().points().is_empty());
+}
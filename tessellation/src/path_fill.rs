@@ -24,11 +24,15 @@ use std::f32::consts::PI;
 use std::mem::{replace, swap};
 use std::cmp::{PartialOrd, Ordering};
 use std::cmp;
+use std::time::Instant;
+use std::ops::Range;
 
 use FillVertex as Vertex;
 use Side;
 use math::*;
+use core::FillRule;
 use geometry_builder::{GeometryBuilder, Count, VertexId};
+use stats::TessellationStats;
 use core::FlattenedEvent;
 use bezier::utils::{directed_angle, directed_angle2};
 use math_utils::{line_horizontal_intersection_fixed, segment_intersection};
@@ -71,6 +75,44 @@ pub enum FillError {
     Unknown,
 }
 
+/// How the fill tessellator deals with input it can't tessellate.
+///
+/// See `FillOptions::error_policy`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FillErrorPolicy {
+    /// Stop at the first error and discard the geometry.
+    FailFast,
+    /// Skip the sub-paths that have invalid coordinates (not finite or too large for the
+    /// tessellator's fixed point representation) or that fail to tessellate on their own,
+    /// and tessellate the rest.
+    SkipSubPath,
+    /// Like `SkipSubPath`, and if the remaining sub-paths still fail to tessellate
+    /// together, keep the geometry that was produced before the error.
+    BestEffort,
+}
+
+/// What the fill tessellator had to leave out to recover from errors.
+///
+/// See `FillTessellator::report`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FillReport {
+    /// Indices of the sub-paths that were skipped, in increasing order.
+    pub skipped_sub_paths: Vec<usize>,
+    /// Whether the geometry is incomplete because the tessellation stopped at an error.
+    pub partial: bool,
+}
+
+impl FillReport {
+    pub fn is_empty(&self) -> bool {
+        self.skipped_sub_paths.is_empty() && !self.partial
+    }
+
+    fn clear(&mut self) {
+        self.skipped_sub_paths.clear();
+        self.partial = false;
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 struct Edge {
     upper: TessPoint,
@@ -170,6 +212,13 @@ struct EdgeBelow {
 /// # }
 /// ```
 ///
+/// # Reusing the tessellator
+///
+/// The tessellator keeps its internal buffers (sorted events, sweep line, etc.) between
+/// tessellations and clears them instead of dropping them. When tessellating many paths,
+/// keeping a long-lived `FillTessellator` around and reusing it avoids most of the
+/// allocations.
+///
 /// # How the fill tessellator works
 ///
 /// Learn more about how the algrorithm works on the [tessellator wiki page](https://github.com/nical/lyon/wiki/Tessellator).
@@ -182,6 +231,8 @@ pub struct FillTessellator {
     below: Vec<EdgeBelow>,
     previous_position: TessPoint,
     error: Option<FillError>,
+    report: FillReport,
+    stats: Option<TessellationStats>,
     log: bool,
     pub _handle_intersections: bool,
 }
@@ -197,6 +248,8 @@ impl FillTessellator {
             intersections: Vec::with_capacity(8),
             previous_position: TessPoint::new(FixedPoint32::min_val(), FixedPoint32::min_val()),
             error: None,
+            report: FillReport::default(),
+            stats: None,
             log: false,
             _handle_intersections: true,
         }
@@ -227,6 +280,25 @@ impl FillTessellator {
         options: &FillOptions,
         output: &mut Output,
     ) -> FillResult
+    where
+        Iter: Iterator<Item = FlattenedEvent>,
+        Output: GeometryBuilder<Vertex>,
+    {
+        self.report.clear();
+        if options.error_policy == FillErrorPolicy::FailFast {
+            return self.tessellate_iter(it, options, output);
+        }
+
+        let path: Vec<FlattenedEvent> = it.collect();
+        return self.tessellate_recovering(&path, options, output);
+    }
+
+    fn tessellate_iter<Iter, Output>(
+        &mut self,
+        it: Iter,
+        options: &FillOptions,
+        output: &mut Output,
+    ) -> FillResult
     where
         Iter: Iterator<Item = FlattenedEvent>,
         Output: GeometryBuilder<Vertex>,
@@ -234,19 +306,82 @@ impl FillTessellator {
         let mut events = replace(&mut self.events, FillEvents::new());
         events.clear();
         events.set_path_iter(it);
-        let result = self.tessellate_events(&events, options, output);
+        let result = self.tessellate_events_impl(&events, options, output);
         self.events = events;
         return result;
     }
 
+    // Implements the SkipSubPath and BestEffort policies.
+    fn tessellate_recovering<Output>(
+        &mut self,
+        path: &[FlattenedEvent],
+        options: &FillOptions,
+        output: &mut Output,
+    ) -> FillResult
+    where
+        Output: GeometryBuilder<Vertex>,
+    {
+        let sub_paths = sub_path_ranges(path);
+        let mut kept = Vec::with_capacity(sub_paths.len());
+        for (idx, range) in sub_paths.iter().enumerate() {
+            if path[range.clone()].iter().all(is_valid_event) {
+                kept.push(idx);
+            } else {
+                self.report.skipped_sub_paths.push(idx);
+            }
+        }
+
+        let fail_fast = FillOptions { error_policy: FillErrorPolicy::FailFast, .. *options };
+        {
+            let events = kept.iter().flat_map(|&idx| path[sub_paths[idx].clone()].iter().cloned());
+            if let Ok(count) = self.tessellate_iter(events, &fail_fast, output) {
+                return Ok(count);
+            }
+        }
+
+        // Find the sub-paths that can't be tessellated on their own.
+        let mut remaining = Vec::with_capacity(kept.len());
+        for idx in kept {
+            let events = path[sub_paths[idx].clone()].iter().cloned();
+            if self.tessellate_iter(events, &fail_fast, &mut NoOutput { vertices: 0 }).is_ok() {
+                remaining.push(idx);
+            } else {
+                self.report.skipped_sub_paths.push(idx);
+            }
+        }
+        self.report.skipped_sub_paths.sort();
+
+        let events = remaining.iter().flat_map(|&idx| path[sub_paths[idx].clone()].iter().cloned());
+        return self.tessellate_iter(events, options, output);
+    }
+
+    /// What was left out of the last tessellation to recover from errors, see
+    /// `FillOptions::error_policy`.
+    pub fn report(&self) -> &FillReport { &self.report }
 
     /// Compute the tessellation from pre-sorted events.
+    ///
+    /// Sub-paths can't be skipped at this stage, so the `SkipSubPath` error policy behaves
+    /// like `FailFast` here.
     pub fn tessellate_events<Output>(
         &mut self,
         events: &FillEvents,
         options: &FillOptions,
         output: &mut Output,
     ) -> FillResult
+    where
+        Output: GeometryBuilder<Vertex>,
+    {
+        self.report.clear();
+        self.tessellate_events_impl(events, options, output)
+    }
+
+    fn tessellate_events_impl<Output>(
+        &mut self,
+        events: &FillEvents,
+        options: &FillOptions,
+        output: &mut Output,
+    ) -> FillResult
     where
         Output: GeometryBuilder<Vertex>,
     {
@@ -258,6 +393,11 @@ impl FillTessellator {
             println!("warning: Fill rule {:?} is not supported yet.", options.fill_rule);
         }
 
+        let start = Instant::now();
+        if let Some(ref mut stats) = self.stats {
+            *stats = TessellationStats::default();
+        }
+
         self.begin_tessellation(output);
 
         self.tessellator_loop(events, output);
@@ -265,12 +405,28 @@ impl FillTessellator {
         let mut error = None;
         swap(&mut error, &mut self.error);
         if let Some(err) = error {
-            output.abort_geometry();
             self.reset();
+            if options.error_policy == FillErrorPolicy::BestEffort {
+                // Keep what was produced so far.
+                self.report.partial = true;
+                let res = output.end_geometry();
+                if let Some(ref mut stats) = self.stats {
+                    stats.set_output(res, start);
+                }
+                return Ok(res);
+            }
+
+            output.abort_geometry();
+            if let Some(ref mut stats) = self.stats {
+                stats.set_output(Count { vertices: 0, indices: 0 }, start);
+            }
             return Err(err);
         }
 
         let res = self.end_tessellation(output);
+        if let Some(ref mut stats) = self.stats {
+            stats.set_output(res, start);
+        }
         self.reset();
         return Ok(res);
     }
@@ -278,10 +434,23 @@ impl FillTessellator {
     /// Enable some verbose logging during the tessellation, for debugging purposes.
     pub fn enable_logging(&mut self) { self.log = true; }
 
+    /// Collect statistics about each tessellation, see `stats`.
+    pub fn enable_stats(&mut self) {
+        if self.stats.is_none() {
+            self.stats = Some(TessellationStats::default());
+        }
+    }
+
+    /// The statistics of the last tessellation, if `enable_stats` was called.
+    pub fn stats(&self) -> Option<&TessellationStats> { self.stats.as_ref() }
+
     fn reset(&mut self) {
         self.sweep_line.clear();
         self.monotone_tessellators.clear();
         self.below.clear();
+        self.intersections.clear();
+        self.previous_position = TessPoint::new(FixedPoint32::min_val(), FixedPoint32::min_val());
+        self.error = None;
     }
 
     fn begin_tessellation<Output: GeometryBuilder<Vertex>>(&mut self, output: &mut Output) {
@@ -387,6 +556,11 @@ impl FillTessellator {
                 let num_intersections = self.intersections.len();
                 self.process_vertex(current_position, output);
 
+                if let Some(ref mut stats) = self.stats {
+                    stats.events += 1;
+                    stats.intersections += (self.intersections.len() - num_intersections) as u32;
+                }
+
                 if num_intersections != self.intersections.len() {
                     // We found an intersection durign process_vertex, it has been added
                     // to self.intersections.
@@ -1228,6 +1402,53 @@ pub fn is_after<T: PartialOrd, U>(a: TypedPoint2D<T, U>, b: TypedPoint2D<T, U>)
     a.y > b.y || (a.y == b.y && a.x > b.x)
 }
 
+// Splits a path into the ranges of its sub-paths.
+fn sub_path_ranges(path: &[FlattenedEvent]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    for (i, evt) in path.iter().enumerate() {
+        if let FlattenedEvent::MoveTo(_) = *evt {
+            if i > start {
+                ranges.push(start..i);
+            }
+            start = i;
+        }
+    }
+    if path.len() > start {
+        ranges.push(start..path.len());
+    }
+
+    return ranges;
+}
+
+// Whether the coordinates of an event can be represented by the tessellator.
+fn is_valid_event(evt: &FlattenedEvent) -> bool {
+    // The largest value of 16.16 fixed point numbers.
+    const MAX_COORDINATE: f32 = 32767.0;
+    match *evt {
+        FlattenedEvent::MoveTo(p) | FlattenedEvent::LineTo(p) => {
+            p.x.is_finite() && p.y.is_finite() && p.x.abs() < MAX_COORDINATE && p.y.abs() < MAX_COORDINATE
+        }
+        FlattenedEvent::Close => true,
+    }
+}
+
+// Discards the geometry, used to check whether a sub-path can be tessellated.
+struct NoOutput {
+    vertices: u16,
+}
+
+impl GeometryBuilder<Vertex> for NoOutput {
+    fn begin_geometry(&mut self) { self.vertices = 0; }
+    fn end_geometry(&mut self) -> Count { Count { vertices: 0, indices: 0 } }
+    fn add_vertex(&mut self, _vertex: Vertex) -> VertexId {
+        self.vertices = self.vertices.wrapping_add(1);
+        VertexId(self.vertices - 1)
+    }
+    fn add_triangle(&mut self, _a: VertexId, _b: VertexId, _c: VertexId) {}
+    fn abort_geometry(&mut self) {}
+}
+
 // translate to and from the internal coordinate system.
 #[inline]
 fn to_internal(v: Point) -> TessPoint { TessPoint::new(fixed(v.x), fixed(v.y)) }
@@ -1460,15 +1681,6 @@ fn test_iter_builder() {
     tess.tessellate_events(&events, &FillOptions::default(), &mut vertex_builder).unwrap();
 }
 
-/// The fill rule defines how to determine what is inside and what is outside of the shape.
-///
-/// See the SVG specification.
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub enum FillRule {
-    EvenOdd,
-    NonZero,
-}
-
 /// Parameters for the tessellator.
 pub struct FillOptions {
     /// Maximum allowed distance to the path when building an approximation.
@@ -1487,6 +1699,14 @@ pub struct FillOptions {
     /// Not implemented yet!
     pub vertex_aa: bool,
 
+    /// How to deal with input that can't be tessellated.
+    ///
+    /// When processing a lot of content of unknown quality, dropping a bad sub-path is often
+    /// preferable to losing the whole shape. `FillTessellator::report` tells what was left out.
+    ///
+    /// Default value: `FillErrorPolicy::FailFast`.
+    pub error_policy: FillErrorPolicy,
+
     // To be able to add fields without making it a breaking change, add an empty private field
     // which makes it impossible to create a FillOptions without the calling constructor.
     _private: (),
@@ -1500,6 +1720,7 @@ impl FillOptions {
             tolerance: 0.1,
             fill_rule: FillRule::EvenOdd,
             vertex_aa: false,
+            error_policy: FillErrorPolicy::FailFast,
             _private: (),
         }
     }
@@ -1523,6 +1744,11 @@ impl FillOptions {
         self.vertex_aa = true;
         return self;
     }
+
+    pub fn with_error_policy(mut self, policy: FillErrorPolicy) -> FillOptions {
+        self.error_policy = policy;
+        return self;
+    }
 }
 
 impl Side {
@@ -1760,6 +1986,32 @@ fn test_path_with_rotations(path: Path, step: f32, expected_triangle_count: Opti
     }
 }
 
+#[test]
+fn test_reuse_tessellator() {
+    let mut path = Path::builder().flattened(0.05).with_svg();
+    build_logo_path(&mut path);
+    let path = path.build();
+
+    let mut tess = FillTessellator::new();
+    let mut first: VertexBuffers<Vertex> = VertexBuffers::new();
+    let mut second: VertexBuffers<Vertex> = VertexBuffers::new();
+
+    tess.tessellate_flattened_path(
+        path.path_iter().flattened(0.05),
+        &FillOptions::default(),
+        &mut simple_builder(&mut first)
+    ).unwrap();
+
+    tess.tessellate_flattened_path(
+        path.path_iter().flattened(0.05),
+        &FillOptions::default(),
+        &mut simple_builder(&mut second)
+    ).unwrap();
+
+    assert_eq!(first.vertices, second.vertices);
+    assert_eq!(first.indices, second.indices);
+}
+
 #[test]
 fn test_simple_triangle() {
     let mut path = Path::builder();
@@ -2610,3 +2862,60 @@ fn test_no_close() {
 fn test_empty_path() {
     test_path(Path::new().as_slice(), Some(0));
 }
+
+#[test]
+fn test_stats() {
+    let mut builder = Path::builder();
+    builder.move_to(point(0.0, 0.0));
+    builder.line_to(point(1.0, 0.0));
+    builder.line_to(point(1.0, 1.0));
+    builder.line_to(point(0.0, 1.0));
+    builder.close();
+    let path = builder.build();
+
+    let mut buffers: VertexBuffers<Vertex> = VertexBuffers::new();
+    let mut tess = FillTessellator::new();
+    assert!(tess.stats().is_none());
+
+    tess.enable_stats();
+    tess.tessellate_path(
+        path.path_iter(),
+        &FillOptions::default(),
+        &mut simple_builder(&mut buffers),
+    ).unwrap();
+
+    let stats = *tess.stats().unwrap();
+    assert_eq!(stats.events, 4);
+    assert_eq!(stats.intersections, 0);
+    assert_eq!(stats.vertices, 4);
+    assert_eq!(stats.triangles, 2);
+}
+
+#[test]
+fn test_skip_invalid_sub_path() {
+    let path = vec![
+        FlattenedEvent::MoveTo(point(0.0, 0.0)),
+        FlattenedEvent::LineTo(point(1.0, 0.0)),
+        FlattenedEvent::LineTo(point(1.0, 1.0)),
+        FlattenedEvent::LineTo(point(0.0, 1.0)),
+        FlattenedEvent::Close,
+        FlattenedEvent::MoveTo(point(10.0, 10.0)),
+        FlattenedEvent::LineTo(point(1000000.0, 10.0)),
+        FlattenedEvent::LineTo(point(10.0, 20.0)),
+        FlattenedEvent::Close,
+    ];
+
+    for &policy in &[FillErrorPolicy::SkipSubPath, FillErrorPolicy::BestEffort] {
+        let mut buffers: VertexBuffers<Vertex> = VertexBuffers::new();
+        let mut tess = FillTessellator::new();
+        let count = tess.tessellate_flattened_path(
+            path.iter().cloned(),
+            &FillOptions::default().with_error_policy(policy),
+            &mut simple_builder(&mut buffers),
+        ).unwrap();
+
+        assert_eq!(count.indices, 6);
+        assert_eq!(tess.report().skipped_sub_paths, vec![1]);
+        assert!(!tess.report().partial);
+    }
+}
@@ -0,0 +1,230 @@
+//! Markers placed along paths (arrowheads, dots, etc.).
+//!
+//! Markers are shapes drawn at the vertices of a path, oriented along it, like SVG's
+//! `marker-start`, `marker-mid` and `marker-end` properties. The placements are computed
+//! from the flattened path, and the marker shapes are filled at each placement.
+
+use math::*;
+use core::FlattenedEvent;
+use geometry_builder::{GeometryBuilder, Count};
+use path_fill::{FillTessellator, FillOptions, FillError};
+use FillVertex;
+
+/// Which vertices of a sub-path a marker is placed at.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MarkerPosition {
+    /// The first vertex of each sub-path.
+    Start,
+    /// All other vertices.
+    Mid,
+    /// The last vertex of each sub-path.
+    End,
+}
+
+/// The position and orientation of a marker.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MarkerPlacement {
+    pub position: Point,
+    /// Normalized direction of the path at the marker.
+    ///
+    /// At the vertices between two segments this is the bisector of the incoming and outgoing
+    /// directions, as with SVG's `orient="auto"`.
+    pub direction: Vec2,
+    pub kind: MarkerPosition,
+}
+
+impl MarkerPlacement {
+    /// The transform that places a marker shape, defined with its anchor at the origin and
+    /// pointing towards the positive x axis, at this placement.
+    pub fn transform(&self, scale: f32) -> Transform2D {
+        Transform2D::row_major(
+            self.direction.x * scale, self.direction.y * scale,
+            -self.direction.y * scale, self.direction.x * scale,
+            self.position.x, self.position.y,
+        )
+    }
+}
+
+/// Computes the placements of the markers along a flattened path.
+///
+/// Zero-length segments are ignored. For closed sub-paths the start and end markers are both
+/// placed on the first vertex, oriented along the bisector of the closing segment and the
+/// first segment.
+pub fn marker_placements<Iter>(path: Iter) -> Vec<MarkerPlacement>
+where
+    Iter: Iterator<Item = FlattenedEvent>,
+{
+    let mut placements = Vec::new();
+    let mut points: Vec<Point> = Vec::new();
+    for evt in path {
+        match evt {
+            FlattenedEvent::MoveTo(to) => {
+                add_sub_path(&points, false, &mut placements);
+                points.clear();
+                points.push(to);
+            }
+            FlattenedEvent::LineTo(to) => {
+                if points.last() != Some(&to) {
+                    points.push(to);
+                }
+            }
+            FlattenedEvent::Close => {
+                add_sub_path(&points, true, &mut placements);
+                points.clear();
+            }
+        }
+    }
+    add_sub_path(&points, false, &mut placements);
+
+    return placements;
+}
+
+fn add_sub_path(points: &[Point], closed: bool, output: &mut Vec<MarkerPlacement>) {
+    let mut points = points;
+    if closed && points.len() > 1 && points.first() == points.last() {
+        points = &points[..points.len() - 1];
+    }
+    if points.len() < 2 {
+        return;
+    }
+
+    let n = points.len();
+    let dir = |from: usize, to: usize| (points[to] - points[from]).normalize();
+    let first = dir(0, 1);
+    let last = if closed { dir(n - 1, 0) } else { dir(n - 2, n - 1) };
+
+    let start_direction = if closed { bisector(last, first) } else { first };
+    output.push(MarkerPlacement { position: points[0], direction: start_direction, kind: MarkerPosition::Start });
+
+    for i in 1..(n - 1) {
+        output.push(MarkerPlacement {
+            position: points[i],
+            direction: bisector(dir(i - 1, i), dir(i, i + 1)),
+            kind: MarkerPosition::Mid,
+        });
+    }
+
+    if closed {
+        output.push(MarkerPlacement {
+            position: points[n - 1],
+            direction: bisector(dir(n - 2, n - 1), last),
+            kind: MarkerPosition::Mid,
+        });
+        output.push(MarkerPlacement { position: points[0], direction: start_direction, kind: MarkerPosition::End });
+    } else {
+        output.push(MarkerPlacement { position: points[n - 1], direction: last, kind: MarkerPosition::End });
+    }
+}
+
+fn bisector(a: Vec2, b: Vec2) -> Vec2 {
+    let sum = a + b;
+    // U-turn, keep the incoming direction.
+    if sum.square_length() < 1e-10 {
+        return a;
+    }
+
+    return sum.normalize();
+}
+
+/// Returns a triangular arrowhead pointing towards the positive x axis, with its tip at the
+/// origin.
+pub fn arrowhead(length: f32, width: f32) -> Vec<FlattenedEvent> {
+    vec![
+        FlattenedEvent::MoveTo(point(0.0, 0.0)),
+        FlattenedEvent::LineTo(point(-length, width * 0.5)),
+        FlattenedEvent::LineTo(point(-length, -width * 0.5)),
+        FlattenedEvent::Close,
+    ]
+}
+
+/// Fills a marker shape at each of the placements of a given kind.
+///
+/// The marker is defined with its anchor at the origin, pointing towards the positive x
+/// axis, and is scaled by `scale` (typically the stroke width). Each marker is a separate
+/// geometry of the output.
+pub fn fill_markers<Output>(
+    marker: &[FlattenedEvent],
+    placements: &[MarkerPlacement],
+    kind: MarkerPosition,
+    scale: f32,
+    tessellator: &mut FillTessellator,
+    options: &FillOptions,
+    output: &mut Output,
+) -> Result<Count, FillError>
+where
+    Output: GeometryBuilder<FillVertex>,
+{
+    let mut count = Count { vertices: 0, indices: 0 };
+    for placement in placements.iter().filter(|p| p.kind == kind) {
+        let transform = placement.transform(scale);
+        let events = marker.iter().map(|evt| match *evt {
+            FlattenedEvent::MoveTo(p) => FlattenedEvent::MoveTo(transform.transform_point(&p)),
+            FlattenedEvent::LineTo(p) => FlattenedEvent::LineTo(transform.transform_point(&p)),
+            FlattenedEvent::Close => FlattenedEvent::Close,
+        });
+        count = count + tessellator.tessellate_flattened_path(events, options, output)?;
+    }
+
+    return Ok(count);
+}
+
+#[test]
+fn test_marker_placements() {
+    let placements = marker_placements(vec![
+        FlattenedEvent::MoveTo(point(0.0, 0.0)),
+        FlattenedEvent::LineTo(point(10.0, 0.0)),
+        FlattenedEvent::LineTo(point(10.0, 0.0)),
+        FlattenedEvent::LineTo(point(10.0, 10.0)),
+    ].into_iter());
+
+    assert_eq!(placements.len(), 3);
+    assert_eq!(placements[0].kind, MarkerPosition::Start);
+    assert_eq!(placements[0].direction, vec2(1.0, 0.0));
+    assert_eq!(placements[1].kind, MarkerPosition::Mid);
+    assert_eq!(placements[1].position, point(10.0, 0.0));
+    assert!((placements[1].direction - vec2(1.0, 1.0).normalize()).length() < 1e-5);
+    assert_eq!(placements[2].kind, MarkerPosition::End);
+    assert_eq!(placements[2].direction, vec2(0.0, 1.0));
+
+    // The arrowhead's tip lands on the end of the path, pointing along it.
+    let transform = placements[2].transform(2.0);
+    let tip = transform.transform_point(&point(0.0, 0.0));
+    let back = transform.transform_point(&point(-1.0, 0.0));
+    assert_eq!(tip, point(10.0, 10.0));
+    assert!((back - point(10.0, 8.0)).length() < 1e-5);
+
+    let closed = marker_placements(vec![
+        FlattenedEvent::MoveTo(point(0.0, 0.0)),
+        FlattenedEvent::LineTo(point(10.0, 0.0)),
+        FlattenedEvent::LineTo(point(10.0, 10.0)),
+        FlattenedEvent::Close,
+    ].into_iter());
+    let kinds: Vec<MarkerPosition> = closed.iter().map(|p| p.kind).collect();
+    assert_eq!(kinds, vec![MarkerPosition::Start, MarkerPosition::Mid, MarkerPosition::Mid, MarkerPosition::End]);
+    assert_eq!(closed[3].position, point(0.0, 0.0));
+}
+
+#[test]
+fn test_fill_markers() {
+    use geometry_builder::{VertexBuffers, simple_builder};
+
+    let placements = marker_placements(vec![
+        FlattenedEvent::MoveTo(point(0.0, 0.0)),
+        FlattenedEvent::LineTo(point(10.0, 0.0)),
+        FlattenedEvent::LineTo(point(10.0, 10.0)),
+    ].into_iter());
+
+    let mut buffers: VertexBuffers<FillVertex> = VertexBuffers::new();
+    let count = fill_markers(
+        &arrowhead(3.0, 2.0),
+        &placements,
+        MarkerPosition::End,
+        1.0,
+        &mut FillTessellator::new(),
+        &FillOptions::default(),
+        &mut simple_builder(&mut buffers),
+    ).unwrap();
+
+    assert_eq!(count.indices, 3);
+    assert!(buffers.vertices.iter().any(|v| v.position == point(10.0, 10.0)));
+}
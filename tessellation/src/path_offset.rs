@@ -0,0 +1,805 @@
+//! Path offsetting (inset/outset).
+
+use math::*;
+use core::FlattenedEvent;
+use path_builder::BaseBuilder;
+use geometry_builder::{GeometryBuilder, Count};
+use path_stroke::StrokeTessellator;
+use {LineCap, LineJoin, StrokeOptions, StrokeVertex};
+
+use std::f32::consts::PI;
+
+/// Parameters for the path offsetting routines.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OffsetOptions {
+    /// How to join the offset edges at the corners that expand.
+    pub line_join: LineJoin,
+
+    /// The ratio between the miter length and the offset distance above which
+    /// miter joins are replaced with bevel joins (or clipped, with `LineJoin::MiterClip`).
+    pub miter_limit: f32,
+
+    /// Maximum allowed distance between round joins and their flattened approximation.
+    pub tolerance: f32,
+
+    // To be able to add fields without making it a breaking change, add an empty private field
+    // which makes it impossible to create an OffsetOptions without calling the constructor.
+    _private: (),
+}
+
+impl OffsetOptions {
+    pub fn default() -> OffsetOptions {
+        OffsetOptions {
+            line_join: LineJoin::Miter,
+            miter_limit: 10.0,
+            tolerance: 0.1,
+            _private: (),
+        }
+    }
+
+    pub fn with_line_join(mut self, join: LineJoin) -> OffsetOptions {
+        self.line_join = join;
+        return self;
+    }
+
+    pub fn with_miter_limit(mut self, limit: f32) -> OffsetOptions {
+        self.miter_limit = limit;
+        return self;
+    }
+
+    pub fn with_tolerance(mut self, tolerance: f32) -> OffsetOptions {
+        self.tolerance = tolerance;
+        return self;
+    }
+}
+
+/// Computes the offset of a flattened path and writes the result into a path builder.
+///
+/// A positive distance grows the closed sub-paths (outset) and a negative distance shrinks
+/// them (inset), regardless of their winding order. Open sub-paths are offset to the right
+/// of their direction (assuming y points upwards) for positive distances.
+///
+/// Edges that are reversed by the offset (typically short edges in concave regions of an
+/// inset) are removed and their neighbours are extended until they intersect. Sub-paths
+/// that collapse entirely are dropped. Self-intersections between distant parts of a
+/// sub-path are not resolved.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut builder = Path::builder();
+/// offset_path(path.path_iter().flattened(0.05), 2.0, &OffsetOptions::default(), &mut builder);
+/// let outline = builder.build();
+/// ```
+pub fn offset_path<Iter, Output>(
+    path: Iter,
+    distance: f32,
+    options: &OffsetOptions,
+    output: &mut Output,
+) where
+    Iter: Iterator<Item = FlattenedEvent>,
+    Output: BaseBuilder,
+{
+    let mut polyline = Vec::new();
+    for evt in path {
+        match evt {
+            FlattenedEvent::MoveTo(to) => {
+                offset_polyline(&polyline, false, distance, options, output);
+                polyline.clear();
+                polyline.push(to);
+            }
+            FlattenedEvent::LineTo(to) => {
+                polyline.push(to);
+            }
+            FlattenedEvent::Close => {
+                offset_polyline(&polyline, true, distance, options, output);
+                // The next sub-path starts at the first point of this one unless
+                // there is a MoveTo.
+                let first = polyline.first().cloned();
+                polyline.clear();
+                polyline.extend(first);
+            }
+        }
+    }
+    offset_polyline(&polyline, false, distance, options, output);
+}
+
+/// Computes the offset of a polyline and writes the result into a path builder.
+///
+/// See `offset_path`.
+pub fn offset_polyline<Output: BaseBuilder>(
+    points: &[Point],
+    is_closed: bool,
+    distance: f32,
+    options: &OffsetOptions,
+    output: &mut Output,
+) {
+    let mut polyline: Vec<Point> = Vec::with_capacity(points.len());
+    for &p in points {
+        if polyline.last() != Some(&p) {
+            polyline.push(p);
+        }
+    }
+    if is_closed && polyline.len() > 1 && polyline.first() == polyline.last() {
+        polyline.pop();
+    }
+
+    let min_points = if is_closed { 3 } else { 2 };
+    if polyline.len() < min_points {
+        return;
+    }
+
+    let area = signed_area(&polyline);
+    let orientation = if !is_closed || area >= 0.0 { 1.0 } else { -1.0 };
+
+    let num_edges = if is_closed { polyline.len() } else { polyline.len() - 1 };
+    let mut edges = Vec::with_capacity(num_edges);
+    for i in 0..num_edges {
+        let from = polyline[i];
+        let to = polyline[(i + 1) % polyline.len()];
+        let dir = (to - from).normalize();
+        edges.push(Edge {
+            from: from,
+            to: to,
+            dir: dir,
+            offset: vec2(dir.y, -dir.x) * orientation * distance,
+        });
+    }
+
+    // Remove the edges that are flipped by the offset.
+    let min_edges = if is_closed { 3 } else { 1 };
+    let mut active: Vec<usize> = (0..num_edges).collect();
+    loop {
+        let len = active.len();
+        if len < min_edges {
+            return;
+        }
+
+        let mut collapsed = None;
+        for i in 0..len {
+            // Removing an edge can leave opposite parallel edges next to each other. Their
+            // offset lines crossed each other so they collapse as well.
+            if is_closed || i + 1 < len {
+                let (a, b) = (active[i], active[(i + 1) % len]);
+                if !is_adjacent(a, b, num_edges)
+                    && edges[a].dir.cross(edges[b].dir).abs() < 1e-5
+                    && edges[a].dir.dot(edges[b].dir) < 0.0 {
+                    collapsed = Some((i, Some((i + 1) % len)));
+                    break;
+                }
+            }
+
+            let edge = &edges[active[i]];
+            let start = if is_closed || i > 0 {
+                let prev = active[(i + len - 1) % len];
+                join_anchors(&edges[prev], edge, is_adjacent(prev, active[i], num_edges)).1
+            } else {
+                edge.from + edge.offset
+            };
+            let end = if is_closed || i + 1 < len {
+                let next = active[(i + 1) % len];
+                join_anchors(edge, &edges[next], is_adjacent(active[i], next, num_edges)).0
+            } else {
+                edge.to + edge.offset
+            };
+            if (end - start).dot(edge.dir) < 0.0 {
+                collapsed = Some((i, None));
+                break;
+            }
+        }
+
+        match collapsed {
+            Some((i, None)) => {
+                active.remove(i);
+            }
+            Some((i, Some(j))) => {
+                active.remove(i.max(j));
+                active.remove(i.min(j));
+            }
+            None => {
+                break;
+            }
+        }
+    }
+
+    let len = active.len();
+    let mut result = Vec::with_capacity(len * 2);
+    if !is_closed {
+        let first = &edges[active[0]];
+        push_point(&mut result, first.from + first.offset);
+    }
+    let first_join = if is_closed { 0 } else { 1 };
+    for i in first_join..len {
+        let prev = active[(i + len - 1) % len];
+        let current = active[i];
+        let (a, b) = (&edges[prev], &edges[current]);
+        let (end_a, start_b, corner) = join_anchors(a, b, is_adjacent(prev, current, num_edges));
+        if corner {
+            add_join(a, b, distance, options, &mut result);
+        } else {
+            push_point(&mut result, end_a);
+            push_point(&mut result, start_b);
+        }
+    }
+    if !is_closed {
+        let last = &edges[active[len - 1]];
+        push_point(&mut result, last.to + last.offset);
+    }
+
+    if is_closed {
+        if result.len() > 1 && result.first() == result.last() {
+            result.pop();
+        }
+        // If the orientation flipped, the offset sub-path collapsed entirely.
+        if result.len() < 3 || signed_area(&result) * area <= 0.0 {
+            return;
+        }
+    }
+
+    output.move_to(result[0]);
+    for &p in &result[1..] {
+        output.line_to(p);
+    }
+    if is_closed {
+        output.close();
+    }
+}
+
+/// One of the lines of a composite stroke, for example the casing or the inline of a road,
+/// or one of the rails of a railway.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ParallelStroke {
+    /// Distance between this line and the path (see `offset_path` for the sign convention).
+    pub offset: f32,
+    /// Width, joins and caps of this line.
+    pub options: StrokeOptions,
+}
+
+/// Tessellates a composite stroke made of several lines parallel to a flattened path.
+///
+/// Each line is tessellated in its own `begin_geometry`/`end_geometry` pair, in order,
+/// and the returned counts let the caller tell which vertices and indices belong to which
+/// line (for example to give them different colors).
+pub fn stroke_parallel_lines<Iter, Output>(
+    path: Iter,
+    lines: &[ParallelStroke],
+    output: &mut Output,
+) -> Vec<Count>
+where
+    Iter: Iterator<Item = FlattenedEvent> + Clone,
+    Output: GeometryBuilder<StrokeVertex>,
+{
+    let mut tessellator = StrokeTessellator::new();
+    let mut offset_events = EventCollector::new();
+    let mut counts = Vec::with_capacity(lines.len());
+    for line in lines {
+        let count = if line.offset == 0.0 {
+            tessellator.tessellate_flattened_path(path.clone(), &line.options, output)
+        } else {
+            let offset_options = OffsetOptions::default()
+                .with_line_join(line.options.line_join)
+                .with_miter_limit(line.options.miter_limit)
+                .with_tolerance(line.options.tolerance);
+            offset_path(path.clone(), line.offset, &offset_options, &mut offset_events);
+            let events = offset_events.build_and_reset();
+            tessellator.tessellate_flattened_path(events.into_iter(), &line.options, output)
+        };
+        counts.push(count);
+    }
+
+    return counts;
+}
+
+/// Computes the outline of the stroke of a flattened path and writes it into a path builder.
+///
+/// The result is a fillable path (with the non-zero fill rule) covering the same area as
+/// the stroke tessellator's output, using the width, joins and caps of `options`. This is
+/// useful for boolean operations on strokes, to export outlines, or to fill strokes with
+/// patterns that only the fill pipeline supports.
+///
+/// Open sub-paths produce one closed sub-path going along one side of the stroke and back
+/// along the other, and closed sub-paths produce an outer and an inner sub-path of opposite
+/// windings. All the outlines wind the same way so that overlapping strokes don't cancel
+/// each other out. The width is always applied, regardless of `options.apply_line_width`.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut builder = Path::builder();
+/// stroke_to_path(path.path_iter().flattened(options.tolerance), &options, &mut builder);
+/// let outline = builder.build();
+/// ```
+pub fn stroke_to_path<Iter, Output>(
+    path: Iter,
+    options: &StrokeOptions,
+    output: &mut Output,
+) where
+    Iter: Iterator<Item = FlattenedEvent>,
+    Output: BaseBuilder,
+{
+    let mut polyline = Vec::new();
+    // Where a sub-path continuing after a close starts, without a point of its own so that
+    // it doesn't get caps if nothing follows.
+    let mut restart = None;
+    for evt in path {
+        match evt {
+            FlattenedEvent::MoveTo(to) => {
+                stroke_polyline_outline(&polyline, false, options, output);
+                polyline.clear();
+                polyline.push(to);
+                restart = None;
+            }
+            FlattenedEvent::LineTo(to) => {
+                if polyline.is_empty() {
+                    polyline.extend(restart.take());
+                }
+                polyline.push(to);
+            }
+            FlattenedEvent::Close => {
+                stroke_polyline_outline(&polyline, true, options, output);
+                restart = polyline.first().cloned().or(restart);
+                polyline.clear();
+            }
+        }
+    }
+    stroke_polyline_outline(&polyline, false, options, output);
+}
+
+fn stroke_polyline_outline<Output: BaseBuilder>(
+    points: &[Point],
+    is_closed: bool,
+    options: &StrokeOptions,
+    output: &mut Output,
+) {
+    let half_width = options.line_width * 0.5;
+    if points.is_empty() || half_width <= 0.0 {
+        return;
+    }
+
+    let offset_options = OffsetOptions::default()
+        .with_line_join(options.line_join)
+        .with_miter_limit(options.miter_limit)
+        .with_tolerance(options.tolerance);
+
+    let mut polyline: Vec<Point> = Vec::with_capacity(points.len());
+    for &p in points {
+        if polyline.last() != Some(&p) {
+            polyline.push(p);
+        }
+    }
+    if is_closed && polyline.len() > 1 && polyline.first() == polyline.last() {
+        polyline.pop();
+    }
+
+    if is_closed && polyline.len() > 2 {
+        // Outer sub-path with a positive area, inner one with a negative area.
+        let mut outer = offset_points(&polyline, true, half_width, &offset_options);
+        let mut inner = offset_points(&polyline, true, -half_width, &offset_options);
+        if signed_area(&outer) < 0.0 {
+            outer.reverse();
+        }
+        if signed_area(&inner) > 0.0 {
+            inner.reverse();
+        }
+        add_sub_path(&outer, output);
+        add_sub_path(&inner, output);
+        return;
+    }
+
+    if polyline.len() == 1 {
+        // A zero length sub-path only has its caps.
+        let center = polyline[0];
+        let mut outline = Vec::new();
+        match options.start_cap {
+            LineCap::Butt => { return; }
+            LineCap::Square => {
+                for &(x, y) in &[(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                    outline.push(center + vec2(x, y) * half_width);
+                }
+            }
+            LineCap::Round => {
+                add_round_cap(center, vec2(1.0, 0.0), half_width, options.tolerance, &mut outline);
+                add_round_cap(center, vec2(-1.0, 0.0), half_width, options.tolerance, &mut outline);
+            }
+        }
+        add_sub_path(&outline, output);
+        return;
+    }
+
+    // The right side forward and the left side backward, joined by the caps.
+    let right = offset_points(&polyline, false, half_width, &offset_options);
+    let mut left = offset_points(&polyline, false, -half_width, &offset_options);
+    if right.is_empty() || left.is_empty() {
+        return;
+    }
+    left.reverse();
+
+    let n = polyline.len();
+    let end_dir = (polyline[n - 1] - polyline[n - 2]).normalize();
+    let start_dir = (polyline[0] - polyline[1]).normalize();
+
+    let mut outline = right;
+    add_cap(polyline[n - 1], end_dir, half_width, options.end_cap, options.tolerance, &mut outline);
+    outline.extend(left);
+    add_cap(polyline[0], start_dir, half_width, options.start_cap, options.tolerance, &mut outline);
+    add_sub_path(&outline, output);
+}
+
+// Adds the points of a cap at the end of a side of the stroke, going around `dir` (the
+// outward direction) from the right side of the stroke to its left side.
+fn add_cap(center: Point, dir: Vec2, half_width: f32, cap: LineCap, tolerance: f32, output: &mut Vec<Point>) {
+    let right = vec2(dir.y, -dir.x) * half_width;
+    match cap {
+        LineCap::Butt => {}
+        LineCap::Square => {
+            push_point(output, center + right + dir * half_width);
+            push_point(output, center - right + dir * half_width);
+        }
+        LineCap::Round => {
+            add_round_cap(center, dir, half_width, tolerance, output);
+        }
+    }
+}
+
+// Adds a half circle around `dir`, from the right of the center to its left.
+fn add_round_cap(center: Point, dir: Vec2, radius: f32, tolerance: f32, output: &mut Vec<Point>) {
+    let right = vec2(dir.y, -dir.x);
+    let step = if tolerance < radius {
+        2.0 * (1.0 - tolerance / radius).acos()
+    } else {
+        PI * 0.5
+    };
+    let num_segments = (PI / step).ceil().max(2.0) as u32;
+    for i in 0..(num_segments + 1) {
+        let (sin, cos) = (PI * i as f32 / num_segments as f32).sin_cos();
+        push_point(output, center + (right * cos + dir * sin) * radius);
+    }
+}
+
+// Runs offset_polyline and returns the points of the offset sub-path.
+fn offset_points(points: &[Point], is_closed: bool, distance: f32, options: &OffsetOptions) -> Vec<Point> {
+    let mut collector = EventCollector::new();
+    offset_polyline(points, is_closed, distance, options, &mut collector);
+    let mut result = Vec::new();
+    for evt in collector.build() {
+        match evt {
+            FlattenedEvent::MoveTo(p) | FlattenedEvent::LineTo(p) => { result.push(p); }
+            FlattenedEvent::Close => {}
+        }
+    }
+
+    return result;
+}
+
+fn add_sub_path<Output: BaseBuilder>(points: &[Point], output: &mut Output) {
+    if points.len() < 3 {
+        return;
+    }
+    output.move_to(points[0]);
+    for &p in &points[1..] {
+        output.line_to(p);
+    }
+    output.close();
+}
+
+// Stores the output of offset_path so that it can be fed to another tessellator.
+struct EventCollector {
+    events: Vec<FlattenedEvent>,
+    current: Point,
+    first: Point,
+}
+
+impl EventCollector {
+    fn new() -> Self {
+        EventCollector {
+            events: Vec::new(),
+            current: point(0.0, 0.0),
+            first: point(0.0, 0.0),
+        }
+    }
+}
+
+impl BaseBuilder for EventCollector {
+    type PathType = Vec<FlattenedEvent>;
+
+    fn move_to(&mut self, to: Point) {
+        self.events.push(FlattenedEvent::MoveTo(to));
+        self.current = to;
+        self.first = to;
+    }
+
+    fn line_to(&mut self, to: Point) {
+        self.events.push(FlattenedEvent::LineTo(to));
+        self.current = to;
+    }
+
+    fn close(&mut self) {
+        self.events.push(FlattenedEvent::Close);
+        self.current = self.first;
+    }
+
+    fn build(self) -> Vec<FlattenedEvent> { self.events }
+
+    fn build_and_reset(&mut self) -> Vec<FlattenedEvent> {
+        self.current = point(0.0, 0.0);
+        self.first = point(0.0, 0.0);
+        return ::std::mem::replace(&mut self.events, Vec::new());
+    }
+
+    fn current_position(&self) -> Point { self.current }
+}
+
+struct Edge {
+    from: Point,
+    to: Point,
+    dir: Vec2,
+    offset: Vec2,
+}
+
+fn is_adjacent(a: usize, b: usize, num_edges: usize) -> bool {
+    (a + 1) % num_edges == b
+}
+
+// Returns the end of the offset edge a, the start of the offset edge b, and whether a
+// join must be inserted between them.
+fn join_anchors(a: &Edge, b: &Edge, adjacent: bool) -> (Point, Point, bool) {
+    let end_a = a.to + a.offset;
+    let start_b = b.from + b.offset;
+    let cross = a.dir.cross(b.dir);
+    let expands = if cross.abs() < 1e-5 {
+        // Collinear or u-turn.
+        a.dir.dot(b.dir) < 0.0
+    } else {
+        cross * a.dir.cross(a.offset) < 0.0
+    };
+
+    if adjacent && expands {
+        return (end_a, start_b, true);
+    }
+
+    match line_intersection(a.from + a.offset, a.dir, b.from + b.offset, b.dir) {
+        Some(p) => (p, p, false),
+        None => (end_a, start_b, false),
+    }
+}
+
+fn line_intersection(a: Point, a_dir: Vec2, b: Point, b_dir: Vec2) -> Option<Point> {
+    let cross = a_dir.cross(b_dir);
+    if cross.abs() < 1e-5 {
+        return None;
+    }
+    let t = (b - a).cross(b_dir) / cross;
+    return Some(a + a_dir * t);
+}
+
+fn add_join(a: &Edge, b: &Edge, distance: f32, options: &OffsetOptions, output: &mut Vec<Point>) {
+    let pivot = a.to;
+    let radius = distance.abs();
+    let end_a = pivot + a.offset;
+    let start_b = pivot + b.offset;
+    match options.line_join {
+        LineJoin::Miter | LineJoin::MiterClip => {
+            let miter = line_intersection(a.from + a.offset, a.dir, b.from + b.offset, b.dir);
+            if let Some(miter) = miter {
+                if (miter - pivot).length() <= options.miter_limit * radius {
+                    push_point(output, miter);
+                    return;
+                }
+            }
+            if options.line_join == LineJoin::MiterClip && miter.is_some() {
+                let bisector = (a.offset + b.offset).normalize();
+                let limit = options.miter_limit * radius;
+                let s_a = (limit - a.offset.dot(bisector)) / a.dir.dot(bisector);
+                let s_b = (limit - b.offset.dot(bisector)) / -b.dir.dot(bisector);
+                push_point(output, end_a + a.dir * s_a.max(0.0));
+                push_point(output, start_b - b.dir * s_b.max(0.0));
+                return;
+            }
+            push_point(output, end_a);
+            push_point(output, start_b);
+        }
+        LineJoin::Round => {
+            let sign = if a.offset.cross(a.dir) >= 0.0 { 1.0 } else { -1.0 };
+            let angle = a.offset.cross(b.offset).abs().atan2(a.offset.dot(b.offset)) * sign;
+            let step = if options.tolerance < radius {
+                2.0 * (1.0 - options.tolerance / radius).acos()
+            } else {
+                PI * 0.5
+            };
+            let num_segments = (angle.abs() / step).ceil().max(1.0) as u32;
+            push_point(output, end_a);
+            for i in 1..num_segments {
+                let (sin, cos) = (angle * i as f32 / num_segments as f32).sin_cos();
+                let v = a.offset;
+                push_point(output, pivot + vec2(v.x * cos - v.y * sin, v.x * sin + v.y * cos));
+            }
+            push_point(output, start_b);
+        }
+        LineJoin::Bevel => {
+            push_point(output, end_a);
+            push_point(output, start_b);
+        }
+    }
+}
+
+fn push_point(points: &mut Vec<Point>, p: Point) {
+    if points.last() != Some(&p) {
+        points.push(p);
+    }
+}
+
+fn signed_area(points: &[Point]) -> f32 {
+    let mut area = 0.0;
+    for i in 0..points.len() {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        area += a.x * b.y - b.x * a.y;
+    }
+    return area * 0.5;
+}
+
+#[cfg(test)]
+use path::Path;
+
+#[cfg(test)]
+fn offset_square(square: &[Point], distance: f32, options: &OffsetOptions) -> Path {
+    let mut events = vec![FlattenedEvent::MoveTo(square[0])];
+    for &p in &square[1..] {
+        events.push(FlattenedEvent::LineTo(p));
+    }
+    events.push(FlattenedEvent::Close);
+
+    let mut builder = Path::builder();
+    offset_path(events.into_iter(), distance, options, &mut builder);
+    return builder.build();
+}
+
+#[test]
+fn test_offset_square() {
+    let ccw = [point(0.0, 0.0), point(10.0, 0.0), point(10.0, 10.0), point(0.0, 10.0)];
+    let cw = [point(0.0, 0.0), point(0.0, 10.0), point(10.0, 10.0), point(10.0, 0.0)];
+
+    for square in &[&ccw[..], &cw[..]] {
+        let outset = offset_square(square, 1.0, &OffsetOptions::default());
+        assert_eq!(outset.points().len(), 4);
+        for p in outset.points() {
+            assert!((p.x + 1.0).abs() < 0.001 || (p.x - 11.0).abs() < 0.001);
+            assert!((p.y + 1.0).abs() < 0.001 || (p.y - 11.0).abs() < 0.001);
+        }
+
+        let inset = offset_square(square, -1.0, &OffsetOptions::default());
+        assert_eq!(inset.points().len(), 4);
+        for p in inset.points() {
+            assert!((p.x - 1.0).abs() < 0.001 || (p.x - 9.0).abs() < 0.001);
+            assert!((p.y - 1.0).abs() < 0.001 || (p.y - 9.0).abs() < 0.001);
+        }
+
+        // The inset is larger than the shape.
+        let collapsed = offset_square(square, -6.0, &OffsetOptions::default());
+        assert!(collapsed.points().is_empty());
+
+        let bevel = offset_square(square, 1.0, &OffsetOptions::default().with_line_join(LineJoin::Bevel));
+        assert_eq!(bevel.points().len(), 8);
+
+        let round = offset_square(square, 1.0, &OffsetOptions::default().with_line_join(LineJoin::Round));
+        assert!(round.points().len() > 8);
+        for p in round.points() {
+            let d = vec2(p.x.max(0.0).min(10.0) - p.x, p.y.max(0.0).min(10.0) - p.y).length();
+            assert!((d - 1.0).abs() < 0.001);
+        }
+    }
+}
+
+#[test]
+fn test_offset_removes_flipped_edges() {
+    // A slot narrower than twice the offset distance gets filled.
+    let slotted = [
+        point(0.0, 0.0), point(4.0, 0.0), point(4.0, 5.0), point(5.0, 5.0),
+        point(5.0, 0.0), point(10.0, 0.0), point(10.0, 10.0), point(0.0, 10.0),
+    ];
+    let outset = offset_square(&slotted, 2.0, &OffsetOptions::default());
+    assert_eq!(outset.points().len(), 6);
+    for p in outset.points() {
+        let on_x = (p.x + 2.0).abs() < 0.001 || (p.x - 12.0).abs() < 0.001;
+        let on_y = (p.y + 2.0).abs() < 0.001 || (p.y - 12.0).abs() < 0.001;
+        assert!(on_x || on_y);
+    }
+}
+
+#[test]
+fn test_parallel_strokes() {
+    use geometry_builder::{VertexBuffers, simple_builder};
+
+    let path = vec![
+        FlattenedEvent::MoveTo(point(0.0, 0.0)),
+        FlattenedEvent::LineTo(point(10.0, 0.0)),
+    ];
+
+    let rail = StrokeOptions::default().with_line_width(1.0);
+    let lines = [
+        ParallelStroke { offset: 2.0, options: rail },
+        ParallelStroke { offset: -2.0, options: rail },
+        ParallelStroke { offset: 0.0, options: StrokeOptions::default().with_line_width(3.0) },
+    ];
+
+    let mut buffers: VertexBuffers<StrokeVertex> = VertexBuffers::new();
+    let counts = stroke_parallel_lines(path.into_iter(), &lines, &mut simple_builder(&mut buffers));
+
+    assert_eq!(counts.len(), 3);
+    let mut start = 0;
+    for (count, &(min, max)) in counts.iter().zip(&[(-2.5, -1.5), (1.5, 2.5), (-1.5, 1.5)]) {
+        assert!(count.vertices > 0);
+        for v in &buffers.vertices[start..(start + count.vertices as usize)] {
+            assert!(v.position.y > min - 0.001 && v.position.y < max + 0.001);
+        }
+        start += count.vertices as usize;
+    }
+}
+
+#[test]
+fn test_stroke_to_path() {
+    let line = vec![
+        FlattenedEvent::MoveTo(point(0.0, 0.0)),
+        FlattenedEvent::LineTo(point(10.0, 0.0)),
+    ];
+    let outline = |events: &[FlattenedEvent], options: &StrokeOptions| {
+        let mut builder = Path::builder();
+        stroke_to_path(events.iter().cloned(), options, &mut builder);
+        builder.build()
+    };
+
+    let butt = outline(&line, &StrokeOptions::default().with_line_width(2.0));
+    assert_eq!(butt.points().len(), 4);
+    assert!(signed_area(butt.points()) > 0.0);
+    for p in butt.points() {
+        assert!(p.x.abs() < 0.001 || (p.x - 10.0).abs() < 0.001);
+        assert!((p.y.abs() - 1.0).abs() < 0.001);
+    }
+
+    let square = outline(&line, &StrokeOptions::default().with_line_width(2.0).with_line_cap(LineCap::Square));
+    assert_eq!(square.points().len(), 8);
+    for p in square.points() {
+        assert!(p.x >= -1.001 && p.x <= 11.001);
+        assert!((p.y.abs() - 1.0).abs() < 0.001);
+    }
+    assert!(square.points().iter().any(|p| (p.x + 1.0).abs() < 0.001));
+    assert!(square.points().iter().any(|p| (p.x - 11.0).abs() < 0.001));
+
+    let round = outline(&line, &StrokeOptions::default().with_line_width(2.0).with_line_cap(LineCap::Round));
+    assert!(round.points().len() > 8);
+    for p in round.points() {
+        let d = (*p - point(p.x.max(0.0).min(10.0), 0.0)).length();
+        assert!((d - 1.0).abs() < 0.001);
+    }
+
+    // A closed square produces an outer and an inner outline of opposite windings.
+    let closed = vec![
+        FlattenedEvent::MoveTo(point(0.0, 0.0)),
+        FlattenedEvent::LineTo(point(0.0, 10.0)),
+        FlattenedEvent::LineTo(point(10.0, 10.0)),
+        FlattenedEvent::LineTo(point(10.0, 0.0)),
+        FlattenedEvent::Close,
+    ];
+    let ring = outline(&closed, &StrokeOptions::default().with_line_width(2.0));
+    assert_eq!(ring.points().len(), 8);
+    assert!(signed_area(&ring.points()[0..4]) > 0.0);
+    assert!(signed_area(&ring.points()[4..8]) < 0.0);
+    for p in &ring.points()[0..4] {
+        assert!((p.x + 1.0).abs() < 0.001 || (p.x - 11.0).abs() < 0.001);
+    }
+    for p in &ring.points()[4..8] {
+        assert!((p.x - 1.0).abs() < 0.001 || (p.x - 9.0).abs() < 0.001);
+    }
+
+    // Closed sub-paths don't have caps.
+    for &cap in &[LineCap::Square, LineCap::Round] {
+        let capped = outline(&closed, &StrokeOptions::default().with_line_width(2.0).with_line_cap(cap));
+        assert_eq!(capped.points(), ring.points());
+    }
+
+    // Zero length sub-paths only have caps.
+    let dot = vec![FlattenedEvent::MoveTo(point(5.0, 5.0)), FlattenedEvent::LineTo(point(5.0, 5.0))];
+    assert!(outline(&dot, &StrokeOptions::default()).points().is_empty());
+    let square_dot = outline(&dot, &StrokeOptions::default().with_line_width(2.0).with_line_cap(LineCap::Square));
+    assert_eq!(square_dot.points().len(), 4);
+}
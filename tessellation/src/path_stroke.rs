@@ -2,13 +2,90 @@ use math::*;
 use core::FlattenedEvent;
 use bezier::utils::{normalized_tangent, directed_angle};
 use geometry_builder::{VertexId, GeometryBuilder, Count};
+use stats::TessellationStats;
 use basic_shapes::circle_flattening_step;
 use path_builder::BaseBuilder;
 use path_iterator::PathIterator;
+use path::AttributedPath;
 use StrokeVertex as Vertex;
 use {Side, LineCap, LineJoin, StrokeOptions};
 
 use std::f32::consts::PI;
+use std::mem;
+use std::time::Instant;
+
+/// How the stroke tessellator deals with input it can't tessellate.
+///
+/// Unlike fills, strokes don't fail to tessellate, but sub-paths with coordinates that are
+/// not finite produce vertices that are not finite either. See `StrokeOptions::error_policy`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StrokeErrorPolicy {
+    /// Tessellate all the sub-paths as they are.
+    Unchecked,
+    /// Skip the sub-paths that have coordinates that are not finite, and tessellate the rest.
+    SkipSubPath,
+}
+
+/// What the stroke tessellator had to leave out to recover from errors.
+///
+/// See `StrokeTessellator::report`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StrokeReport {
+    /// Indices of the sub-paths that were skipped, in increasing order.
+    pub skipped_sub_paths: Vec<usize>,
+}
+
+impl StrokeReport {
+    pub fn is_empty(&self) -> bool { self.skipped_sub_paths.is_empty() }
+}
+
+/// Which attributes of an `AttributedPath` override the line join and cap at its endpoints,
+/// see `StrokeTessellator::tessellate_attributed_path`.
+///
+/// Joins are selected with 0 for `Miter`, 1 for `MiterClip`, 2 for `Round` and 3 for
+/// `Bevel`, caps with 0 for `Butt`, 1 for `Square` and 2 for `Round`. Other values, like
+/// -1, keep the join or cap of the `StrokeOptions`, and so do indices that are out of range
+/// of the path's attributes. Caps only apply to the first and last endpoints of the
+/// sub-paths that aren't closed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct StrokeAttributes {
+    /// The index of the attribute selecting the line join, if any.
+    pub line_join: Option<usize>,
+    /// The index of the attribute selecting the line cap, if any.
+    pub line_cap: Option<usize>,
+}
+
+impl StrokeAttributes {
+    // Ignores the indices that don't select one of the `num_attributes` attributes.
+    fn in_range(&self, num_attributes: usize) -> StrokeAttributes {
+        StrokeAttributes {
+            line_join: self.line_join.and_then(|idx| if idx < num_attributes { Some(idx) } else { None }),
+            line_cap: self.line_cap.and_then(|idx| if idx < num_attributes { Some(idx) } else { None }),
+        }
+    }
+
+    fn line_join(&self, attributes: &[f32]) -> Option<LineJoin> {
+        match self.line_join.map(|idx| attributes[idx]) {
+            Some(v) if v == 0.0 => Some(LineJoin::Miter),
+            Some(v) if v == 1.0 => Some(LineJoin::MiterClip),
+            Some(v) if v == 2.0 => Some(LineJoin::Round),
+            Some(v) if v == 3.0 => Some(LineJoin::Bevel),
+            _ => None,
+        }
+    }
+
+    fn line_cap(&self, attributes: &[f32]) -> Option<LineCap> {
+        match self.line_cap.map(|idx| attributes[idx]) {
+            Some(v) if v == 0.0 => Some(LineCap::Butt),
+            Some(v) if v == 1.0 => Some(LineCap::Square),
+            Some(v) if v == 2.0 => Some(LineCap::Round),
+            _ => None,
+        }
+    }
+}
+
+// A flattened event with the join and cap overrides at its endpoint.
+type StrokeEvent = (FlattenedEvent, Option<LineJoin>, Option<LineCap>);
 
 /// A Context object that can tessellate stroke operations for complex paths.
 ///
@@ -85,10 +162,37 @@ use std::f32::consts::PI;
 ///
 /// # }
 /// ```
-pub struct StrokeTessellator {}
+pub struct StrokeTessellator {
+    stats: Option<TessellationStats>,
+    report: StrokeReport,
+    // Scratch buffers, kept to avoid reallocating them for each path.
+    attributed_events: Vec<StrokeEvent>,
+    sub_path: Vec<StrokeEvent>,
+}
 
 impl StrokeTessellator {
-    pub fn new() -> StrokeTessellator { StrokeTessellator {} }
+    pub fn new() -> StrokeTessellator {
+        StrokeTessellator {
+            stats: None,
+            report: StrokeReport::default(),
+            attributed_events: Vec::new(),
+            sub_path: Vec::new(),
+        }
+    }
+
+    /// Collect statistics about each tessellation, see `stats`.
+    pub fn enable_stats(&mut self) {
+        if self.stats.is_none() {
+            self.stats = Some(TessellationStats::default());
+        }
+    }
+
+    /// The statistics of the last tessellation, if `enable_stats` was called.
+    pub fn stats(&self) -> Option<&TessellationStats> { self.stats.as_ref() }
+
+    /// What was left out of the last tessellation to recover from errors, see
+    /// `StrokeOptions::error_policy`.
+    pub fn report(&self) -> &StrokeReport { &self.report }
 
     /// Compute the tessellation from a path iterator.
     pub fn tessellate_path<Input, Output>(
@@ -119,17 +223,131 @@ impl StrokeTessellator {
         Input: Iterator<Item = FlattenedEvent>,
         Output: GeometryBuilder<Vertex>,
     {
+        self.tessellate_events(input.map(|evt| (evt, None, None)), options, builder)
+    }
+
+    /// Compute the tessellation of a path which attributes override the line join or cap
+    /// at some of its endpoints, for example to round specific corners of a shape.
+    ///
+    /// The attributes of the points added when flattening curves are interpolated, so
+    /// they usually don't select anything.
+    pub fn tessellate_attributed_path<Output>(
+        &mut self,
+        path: &AttributedPath,
+        attributes: &StrokeAttributes,
+        options: &StrokeOptions,
+        builder: &mut Output,
+    ) -> Count
+    where
+        Output: GeometryBuilder<Vertex>,
+    {
+        let attributes = attributes.in_range(path.num_attributes());
+
+        // The flattened events are produced by a callback, so they are buffered before
+        // being streamed into the tessellator.
+        let mut events = mem::replace(&mut self.attributed_events, Vec::new());
+        events.clear();
+        path.for_each_flattened(options.tolerance, &mut |evt, values| {
+            events.push((evt, attributes.line_join(values), attributes.line_cap(values)));
+        });
+
+        let count = self.tessellate_events(events.drain(..), options, builder);
+        self.attributed_events = events;
+
+        return count;
+    }
+
+    fn tessellate_events<Input, Output>(
+        &mut self,
+        input: Input,
+        options: &StrokeOptions,
+        builder: &mut Output,
+    ) -> Count
+    where
+        Input: Iterator<Item = StrokeEvent>,
+        Output: GeometryBuilder<Vertex>,
+    {
+        let start = Instant::now();
+        let mut events = 0;
+        self.report.skipped_sub_paths.clear();
         builder.begin_geometry();
         {
             let mut stroker = StrokeBuilder::new(options, builder);
 
-            for evt in input {
-                stroker.flat_event(evt);
+            if options.error_policy == StrokeErrorPolicy::SkipSubPath {
+                // Sub-paths are buffered until they are known to be valid.
+                let sub_path = &mut self.sub_path;
+                sub_path.clear();
+                let mut sub_path_idx = 0;
+                for evt in input {
+                    if let FlattenedEvent::MoveTo(_) = evt.0 {
+                        if !sub_path.is_empty() {
+                            stroke_valid_sub_path(&mut stroker, sub_path, sub_path_idx, &mut self.report);
+                            sub_path_idx += 1;
+                        }
+                    }
+                    sub_path.push(evt);
+                    events += 1;
+                }
+                if !sub_path.is_empty() {
+                    stroke_valid_sub_path(&mut stroker, sub_path, sub_path_idx, &mut self.report);
+                }
+            } else {
+                for evt in input {
+                    stroke_event(&mut stroker, evt);
+                    events += 1;
+                }
             }
 
             stroker.build();
         }
-        return builder.end_geometry();
+        let count = builder.end_geometry();
+
+        if let Some(ref mut stats) = self.stats {
+            *stats = TessellationStats::default();
+            stats.events = events;
+            stats.set_output(count, start);
+        }
+
+        return count;
+    }
+}
+
+fn stroke_event<'l, Output: 'l + GeometryBuilder<Vertex>>(
+    stroker: &mut StrokeBuilder<'l, Output>,
+    (evt, join, cap): StrokeEvent,
+) {
+    stroker.flat_event(evt);
+    if let FlattenedEvent::Close = evt {
+        return;
+    }
+    if let Some(join) = join {
+        stroker.set_current_line_join(join);
+    }
+    if let Some(cap) = cap {
+        stroker.set_current_line_cap(cap);
+    }
+}
+
+// Strokes the events of a sub-path if all their coordinates are finite, records it as
+// skipped otherwise, and clears the events.
+fn stroke_valid_sub_path<'l, Output: 'l + GeometryBuilder<Vertex>>(
+    stroker: &mut StrokeBuilder<'l, Output>,
+    sub_path: &mut Vec<StrokeEvent>,
+    sub_path_idx: usize,
+    report: &mut StrokeReport,
+) {
+    let is_valid = sub_path.iter().all(|evt| match evt.0 {
+        FlattenedEvent::MoveTo(p) | FlattenedEvent::LineTo(p) => p.x.is_finite() && p.y.is_finite(),
+        FlattenedEvent::Close => true,
+    });
+    if is_valid {
+        for evt in sub_path.drain(..) {
+            stroke_event(stroker, evt);
+        }
+    } else {
+        report.skipped_sub_paths.push(sub_path_idx);
+        sub_path.clear();
     }
 }
 
@@ -160,6 +378,10 @@ pub struct StrokeBuilder<'l, Output: 'l> {
     sub_path_idx: u32,
     length: f32,
     sub_path_start_length: f32,
+    first_join: Option<LineJoin>,
+    current_join: Option<LineJoin>,
+    start_cap: Option<LineCap>,
+    end_cap: Option<LineCap>,
     options: StrokeOptions,
     output: &'l mut Output,
 }
@@ -169,6 +391,10 @@ impl<'l, Output: 'l + GeometryBuilder<Vertex>> BaseBuilder for StrokeBuilder<'l,
 
     fn move_to(&mut self, to: Point) {
         self.finish();
+        self.first_join = None;
+        self.current_join = None;
+        self.start_cap = None;
+        self.end_cap = None;
 
         self.first = to;
         self.current = to;
@@ -176,13 +402,18 @@ impl<'l, Output: 'l + GeometryBuilder<Vertex>> BaseBuilder for StrokeBuilder<'l,
         self.sub_path_start_length = self.length;
     }
 
-    fn line_to(&mut self, to: Point) { self.edge_to(to); }
+    fn line_to(&mut self, to: Point) {
+        // The cap override was for the previous endpoint.
+        self.end_cap = None;
+        self.edge_to(to);
+    }
 
     fn close(&mut self) {
         let first = self.first;
         self.edge_to(first);
         if self.nth > 1 {
             let second = self.second;
+            self.current_join = self.first_join.take();
             self.edge_to(second);
 
             let first_left_id = add_vertex!(
@@ -211,6 +442,10 @@ impl<'l, Output: 'l + GeometryBuilder<Vertex>> BaseBuilder for StrokeBuilder<'l,
         self.current = self.first;
         self.sub_path_start_length = self.length;
         self.sub_path_idx += 1;
+        self.first_join = None;
+        self.current_join = None;
+        self.start_cap = None;
+        self.end_cap = None;
     }
 
     fn current_position(&self) -> Point { self.current }
@@ -228,6 +463,10 @@ impl<'l, Output: 'l + GeometryBuilder<Vertex>> BaseBuilder for StrokeBuilder<'l,
         self.nth = 0;
         self.length = 0.0;
         self.sub_path_start_length = 0.0;
+        self.first_join = None;
+        self.current_join = None;
+        self.start_cap = None;
+        self.end_cap = None;
     }
 }
 
@@ -248,6 +487,10 @@ impl<'l, Output: 'l + GeometryBuilder<Vertex>> StrokeBuilder<'l, Output> {
             sub_path_idx: 0,
             length: 0.0,
             sub_path_start_length: 0.0,
+            first_join: None,
+            current_join: None,
+            start_cap: None,
+            end_cap: None,
             options: *options,
             output: builder,
         };
@@ -255,6 +498,31 @@ impl<'l, Output: 'l + GeometryBuilder<Vertex>> StrokeBuilder<'l, Output> {
 
     pub fn set_options(&mut self, options: &StrokeOptions) { self.options = *options; }
 
+    /// Overrides the line join at the current position (the end of the last
+    /// `move_to` or `line_to`) instead of using the one from the options.
+    ///
+    /// This makes it possible to use different joins at specific corners of a shape.
+    pub fn set_current_line_join(&mut self, join: LineJoin) {
+        if self.nth == 0 {
+            self.first_join = Some(join);
+        } else {
+            self.current_join = Some(join);
+        }
+    }
+
+    /// Overrides the line cap at the current position instead of using the one from the
+    /// options: the start cap right after a `move_to`, the end cap otherwise.
+    ///
+    /// The end cap only applies if the current position is the last one of the sub-path,
+    /// and caps don't apply to closed sub-paths.
+    pub fn set_current_line_cap(&mut self, cap: LineCap) {
+        if self.nth == 0 {
+            self.start_cap = Some(cap);
+        } else {
+            self.end_cap = Some(cap);
+        }
+    }
+
     fn tessellate_empty_square_cap(&mut self) {
         let a = add_vertex!(
             self,
@@ -321,8 +589,10 @@ impl<'l, Output: 'l + GeometryBuilder<Vertex>> StrokeBuilder<'l, Output> {
     }
 
     fn finish(&mut self) {
+        let start_cap = self.start_cap.unwrap_or(self.options.start_cap);
+        let end_cap = self.end_cap.unwrap_or(self.options.end_cap);
         if self.nth == 0 && self.sub_path_idx > 0 {
-            match self.options.start_cap {
+            match start_cap {
                 LineCap::Square => {
                     // Even if there is no edge, if we are using square caps we have to place a square
                     // at the current position.
@@ -340,7 +610,7 @@ impl<'l, Output: 'l + GeometryBuilder<Vertex>> StrokeBuilder<'l, Output> {
         if self.nth > 0 {
             let current = self.current;
             let d = self.current - self.previous;
-            if self.options.end_cap == LineCap::Square {
+            if end_cap == LineCap::Square {
                 // The easiest way to implement square caps is to lie about the current position
                 // and move it slightly to accommodate for the width/2 extra length.
                 let hw = if self.options.apply_line_width {
@@ -355,7 +625,7 @@ impl<'l, Output: 'l + GeometryBuilder<Vertex>> StrokeBuilder<'l, Output> {
             // Restore the real current position.
             self.current = current;
 
-            if self.options.end_cap == LineCap::Round {
+            if end_cap == LineCap::Round {
                 let left_id = self.previous_left_id;
                 let right_id = self.previous_right_id;
                 self.tessellate_round_cap(current, d, left_id, right_id, false);
@@ -367,7 +637,7 @@ impl<'l, Output: 'l + GeometryBuilder<Vertex>> StrokeBuilder<'l, Output> {
             let mut first = self.first;
             let d = first - self.second;
 
-            if self.options.start_cap == LineCap::Square {
+            if start_cap == LineCap::Square {
                 let hw = if self.options.apply_line_width {
                     self.options.line_width * 0.5
                 } else {
@@ -400,7 +670,7 @@ impl<'l, Output: 'l + GeometryBuilder<Vertex>> StrokeBuilder<'l, Output> {
             );
 
 
-            if self.options.start_cap == LineCap::Round {
+            if start_cap == LineCap::Round {
                 self.tessellate_round_cap(first, d, first_left_id, first_right_id, true);
             }
 
@@ -573,8 +843,46 @@ impl<'l, Output: 'l + GeometryBuilder<Vertex>> StrokeBuilder<'l, Output> {
             }
         );
 
-        let (start_vertex, end_vertex) = match self.options.line_join {
-            LineJoin::Miter => {
+        // The miter length relative to the line width is the length of the miter normal.
+        let miter_exceeded = normal.length() > self.options.miter_limit;
+
+        let line_join = self.current_join.take().unwrap_or(self.options.line_join);
+
+        let (start_vertex, end_vertex) = match line_join {
+            LineJoin::Miter if !miter_exceeded => {
+                let v = add_vertex!(
+                    self,
+                    Vertex {
+                        position: self.current,
+                        normal: normal,
+                        advancement: self.length,
+                        side: front_side,
+                    }
+                );
+
+                (v, v)
+            }
+
+            LineJoin::Miter | LineJoin::Bevel => {
+                let (n_a, n_b) = get_front_normals(a_line, b_line, normal);
+                self.tessellate_bevel_join(back_vertex, n_a, n_b, front_side)
+            }
+
+            LineJoin::MiterClip if miter_exceeded => {
+                let (n_a, n_b) = get_front_normals(a_line, b_line, normal);
+                let miter_dir = normal.normalize();
+                let limit = self.options.miter_limit;
+                // Move the vertices along the edges until they reach the line that clips
+                // the miter at a distance of miter_limit (in half line width units) from
+                // the join.
+                let t_a = a_line.normalize();
+                let t_b = -b_line.normalize();
+                let s_a = ((limit - n_a.dot(miter_dir)) / t_a.dot(miter_dir)).max(0.0);
+                let s_b = ((limit - n_b.dot(miter_dir)) / t_b.dot(miter_dir)).max(0.0);
+                self.tessellate_bevel_join(back_vertex, n_a + t_a * s_a, n_b + t_b * s_b, front_side)
+            }
+
+            LineJoin::MiterClip => {
                 let v = add_vertex!(
                     self,
                     Vertex {
@@ -659,22 +967,6 @@ impl<'l, Output: 'l + GeometryBuilder<Vertex>> StrokeBuilder<'l, Output> {
                     (v, v)
                 }
             }
-
-            // Fallback to Miter for unimplemented line joins
-            _ => {
-                println!("[StrokeTessellator] unimplemented line join.");
-                let v = add_vertex!(
-                    self,
-                    Vertex {
-                        position: self.current,
-                        normal: normal,
-                        advancement: self.length,
-                        side: front_side,
-                    }
-                );
-
-                (v, v)
-            }
         };
 
         match front_side {
@@ -682,6 +974,37 @@ impl<'l, Output: 'l + GeometryBuilder<Vertex>> StrokeBuilder<'l, Output> {
             Side::Right => (back_vertex, start_vertex, back_vertex, end_vertex),
         }
     }
+
+    fn tessellate_bevel_join(
+        &mut self,
+        back_vertex: VertexId,
+        start_normal: Vec2,
+        end_normal: Vec2,
+        front_side: Side,
+    ) -> (VertexId, VertexId) {
+        let start_vertex = add_vertex!(
+            self,
+            Vertex {
+                position: self.current,
+                normal: start_normal,
+                advancement: self.length,
+                side: front_side,
+            }
+        );
+        let end_vertex = add_vertex!(
+            self,
+            Vertex {
+                position: self.current,
+                normal: end_normal,
+                advancement: self.length,
+                side: front_side,
+            }
+        );
+
+        self.output.add_triangle(back_vertex, start_vertex, end_vertex);
+
+        (start_vertex, end_vertex)
+    }
 }
 
 fn get_angle_normal(previous: Point, current: Point, next: Point) -> Vec2 {
@@ -709,6 +1032,21 @@ fn get_angle_normal(previous: Point, current: Point, next: Point) -> Vec2 {
     return n / inv_len;
 }
 
+// Returns the unit normals of the incoming and outgoing edges, oriented towards the
+// front side of the join.
+fn get_front_normals(a_line: Vec2, b_line: Vec2, front_normal: Vec2) -> (Vec2, Vec2) {
+    let mut n_a = vec2(-a_line.y, a_line.x).normalize();
+    let mut n_b = vec2(-b_line.y, b_line.x).normalize();
+    if n_a.dot(front_normal) < 0.0 {
+        n_a = -n_a;
+    }
+    if n_b.dot(front_normal) < 0.0 {
+        n_b = -n_b;
+    }
+
+    (n_a, n_b)
+}
+
 /// Computes the max angle of a radius segment for a given tolerance
 pub fn compute_max_radius_segment_angle(radius: f32, tolerance: f32) -> f32 {
     let t = radius - tolerance;
@@ -769,3 +1107,185 @@ fn tess_round_cap<Output: GeometryBuilder<Vertex>>(
         output
     );
 }
+
+#[cfg(test)]
+use path::Path;
+#[cfg(test)]
+use geometry_builder::{VertexBuffers, simple_builder};
+
+#[cfg(test)]
+fn test_acute_join(join: LineJoin, miter_limit: f32) -> VertexBuffers<Vertex> {
+    let mut builder = Path::builder();
+    builder.move_to(point(0.0, 0.0));
+    builder.line_to(point(10.0, 0.5));
+    builder.line_to(point(0.0, 1.0));
+    let path = builder.build();
+
+    let mut buffers: VertexBuffers<Vertex> = VertexBuffers::new();
+    StrokeTessellator::new().tessellate_path(
+        path.path_iter(),
+        &StrokeOptions::default().with_line_join(join).with_miter_limit(miter_limit),
+        &mut simple_builder(&mut buffers),
+    );
+
+    return buffers;
+}
+
+#[test]
+fn test_miter_limit() {
+    // Without a limit, the miter of an acute join extends far beyond the path.
+    let miter = test_acute_join(LineJoin::Miter, 1000.0);
+    assert!(miter.vertices.iter().any(|v| v.position.x > 15.0));
+
+    // With the default limit the join falls back to a bevel.
+    let bevel = test_acute_join(LineJoin::Miter, 10.0);
+    for v in &bevel.vertices {
+        assert!(v.position.x < 11.0);
+    }
+    assert_eq!(bevel.vertices.len(), miter.vertices.len() + 1);
+
+    // Clipped miters stop at miter_limit * line_width / 2 from the join.
+    let clipped = test_acute_join(LineJoin::MiterClip, 4.0);
+    for v in &clipped.vertices {
+        assert!(v.position.x < 10.0 + 2.0 + 0.01);
+    }
+    assert!(clipped.vertices.iter().any(|v| v.position.x > 11.5));
+}
+
+#[test]
+fn test_per_vertex_line_join() {
+    let mut buffers: VertexBuffers<Vertex> = VertexBuffers::new();
+    let count = {
+        let mut output = simple_builder(&mut buffers);
+        output.begin_geometry();
+        {
+            let mut stroker = StrokeBuilder::new(&StrokeOptions::default(), &mut output);
+            stroker.move_to(point(0.0, 0.0));
+            stroker.line_to(point(10.0, 0.0));
+            stroker.set_current_line_join(LineJoin::Bevel);
+            stroker.line_to(point(10.0, 10.0));
+            stroker.line_to(point(0.0, 10.0));
+            stroker.build();
+        }
+        output.end_geometry()
+    };
+
+    // Two joins: a bevel (3 vertices) and a miter (2 vertices), plus two
+    // vertices at each end.
+    assert_eq!(count.vertices, 9);
+}
+
+#[test]
+fn test_attributed_joins_and_caps() {
+    let build = |join: f32, cap: f32| {
+        let mut builder = AttributedPath::builder(2);
+        builder.move_to(point(0.0, 0.0), &[-1.0, cap]);
+        builder.line_to(point(10.0, 0.0), &[join, -1.0]);
+        builder.line_to(point(10.0, 10.0), &[-1.0, -1.0]);
+        builder.build()
+    };
+    let tessellate = |path: &AttributedPath| {
+        let mut buffers: VertexBuffers<Vertex> = VertexBuffers::new();
+        StrokeTessellator::new().tessellate_attributed_path(
+            path,
+            &StrokeAttributes { line_join: Some(0), line_cap: Some(1) },
+            &StrokeOptions::default(),
+            &mut simple_builder(&mut buffers),
+        );
+        buffers
+    };
+    let min_x = |buffers: &VertexBuffers<Vertex>| {
+        buffers.vertices.iter().fold(f32::max_value(), |x, v| x.min(v.position.x))
+    };
+
+    // Without overrides, the options apply.
+    let path = build(-1.0, -1.0);
+    let plain = tessellate(&path);
+    let mut expected: VertexBuffers<Vertex> = VertexBuffers::new();
+    StrokeTessellator::new().tessellate_path(
+        path.path().path_iter(),
+        &StrokeOptions::default(),
+        &mut simple_builder(&mut expected),
+    );
+    assert_eq!(plain.vertices.len(), expected.vertices.len());
+    assert!(min_x(&plain).abs() < 1e-4);
+
+    // A bevel join at the corner has one more vertex than the default miter.
+    let bevel = tessellate(&build(3.0, -1.0));
+    assert_eq!(bevel.vertices.len(), plain.vertices.len() + 1);
+
+    // A square start cap extends the stroke by half of its width.
+    let square = tessellate(&build(-1.0, 1.0));
+    assert!((min_x(&square) + 0.5).abs() < 1e-4);
+
+    // Indices that are out of range don't override anything, and the scratch buffers of
+    // the tessellator don't leak events from one path to the next.
+    let mut tess = StrokeTessellator::new();
+    for _ in 0..2 {
+        let mut buffers: VertexBuffers<Vertex> = VertexBuffers::new();
+        tess.tessellate_attributed_path(
+            &build(3.0, 1.0),
+            &StrokeAttributes { line_join: Some(2), line_cap: Some(5) },
+            &StrokeOptions::default(),
+            &mut simple_builder(&mut buffers),
+        );
+        assert_eq!(buffers.vertices.len(), plain.vertices.len());
+        assert!(min_x(&buffers).abs() < 1e-4);
+    }
+}
+
+#[test]
+fn test_stats() {
+    let mut buffers: VertexBuffers<Vertex> = VertexBuffers::new();
+    let mut tess = StrokeTessellator::new();
+    tess.enable_stats();
+
+    let count = tess.tessellate_flattened_path(
+        vec![
+            FlattenedEvent::MoveTo(point(0.0, 0.0)),
+            FlattenedEvent::LineTo(point(10.0, 0.0)),
+            FlattenedEvent::LineTo(point(10.0, 10.0)),
+        ].into_iter(),
+        &StrokeOptions::default(),
+        &mut simple_builder(&mut buffers),
+    );
+
+    let stats = tess.stats().unwrap();
+    assert_eq!(stats.events, 3);
+    assert_eq!(stats.vertices, count.vertices);
+    assert_eq!(stats.triangles, count.indices / 3);
+}
+
+#[test]
+fn test_skip_invalid_sub_paths() {
+    use std::f32::NAN;
+
+    let path = vec![
+        FlattenedEvent::MoveTo(point(0.0, 0.0)),
+        FlattenedEvent::LineTo(point(10.0, 0.0)),
+        FlattenedEvent::MoveTo(point(0.0, 5.0)),
+        FlattenedEvent::LineTo(point(NAN, 5.0)),
+        FlattenedEvent::MoveTo(point(0.0, 10.0)),
+        FlattenedEvent::LineTo(point(10.0, 10.0)),
+    ];
+
+    let mut tess = StrokeTessellator::new();
+    let mut unchecked: VertexBuffers<Vertex> = VertexBuffers::new();
+    tess.tessellate_flattened_path(
+        path.iter().cloned(),
+        &StrokeOptions::default(),
+        &mut simple_builder(&mut unchecked),
+    );
+    assert!(tess.report().is_empty());
+
+    let mut buffers: VertexBuffers<Vertex> = VertexBuffers::new();
+    let count = tess.tessellate_flattened_path(
+        path.iter().cloned(),
+        &StrokeOptions::default().with_error_policy(StrokeErrorPolicy::SkipSubPath),
+        &mut simple_builder(&mut buffers),
+    );
+    assert!(count.indices > 0);
+    assert!(buffers.vertices.iter().all(|v| v.position.x.is_finite() && v.position.y.is_finite()));
+    assert!(buffers.vertices.iter().any(|v| v.position.y > 9.0));
+    assert_eq!(tess.report().skipped_sub_paths, vec![1]);
+}
@@ -0,0 +1,102 @@
+//! Profiling statistics collected by the tessellators.
+//!
+//! Collecting statistics is opt-in, see `FillTessellator::enable_stats` and
+//! `StrokeTessellator::enable_stats`. The statistics of each tessellation can be recorded
+//! into a `TessellationProfile` to find the shapes that are the most expensive to tessellate.
+
+use geometry_builder::Count;
+
+use std::time::{Duration, Instant};
+
+/// Statistics about a single tessellation.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TessellationStats {
+    /// Number of events processed: sweep-line positions for fills, path events for strokes.
+    pub events: u32,
+    /// Number of intersections found by the fill tessellator.
+    pub intersections: u32,
+    /// Number of vertices emitted.
+    pub vertices: u32,
+    /// Number of triangles emitted.
+    pub triangles: u32,
+    /// Time spent tessellating, in nanoseconds.
+    pub time_ns: u64,
+}
+
+impl TessellationStats {
+    pub fn add(&mut self, other: &TessellationStats) {
+        self.events += other.events;
+        self.intersections += other.intersections;
+        self.vertices += other.vertices;
+        self.triangles += other.triangles;
+        self.time_ns += other.time_ns;
+    }
+
+    pub(crate) fn set_output(&mut self, count: Count, start: Instant) {
+        self.vertices = count.vertices;
+        self.triangles = count.indices / 3;
+        self.time_ns = duration_ns(start.elapsed());
+    }
+}
+
+fn duration_ns(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64
+}
+
+/// Accumulates the statistics of many tessellations, for example all the shapes of a scene.
+pub struct TessellationProfile<Key> {
+    records: Vec<(Key, TessellationStats)>,
+}
+
+impl<Key: Copy> TessellationProfile<Key> {
+    pub fn new() -> Self {
+        TessellationProfile { records: Vec::new() }
+    }
+
+    /// Records the statistics of the tessellation of a shape.
+    pub fn record(&mut self, key: Key, stats: &TessellationStats) {
+        self.records.push((key, *stats));
+    }
+
+    pub fn records(&self) -> &[(Key, TessellationStats)] { &self.records }
+
+    /// The sum of all the recorded statistics.
+    pub fn total(&self) -> TessellationStats {
+        let mut total = TessellationStats::default();
+        for &(_, ref stats) in &self.records {
+            total.add(stats);
+        }
+
+        return total;
+    }
+
+    /// The `count` shapes that took the longest to tessellate, slowest first.
+    pub fn worst(&self, count: usize) -> Vec<(Key, TessellationStats)> {
+        let mut records = self.records.clone();
+        records.sort_by(|a, b| b.1.time_ns.cmp(&a.1.time_ns));
+        records.truncate(count);
+
+        return records;
+    }
+
+    pub fn clear(&mut self) { self.records.clear(); }
+}
+
+#[test]
+fn profile_worst_shapes() {
+    let mut profile = TessellationProfile::new();
+    for (i, &time_ns) in [10, 500, 20, 300].iter().enumerate() {
+        profile.record(i, &TessellationStats {
+            events: 1,
+            intersections: 0,
+            vertices: 3,
+            triangles: 1,
+            time_ns: time_ns,
+        });
+    }
+
+    let worst: Vec<usize> = profile.worst(2).iter().map(|&(key, _)| key).collect();
+    assert_eq!(worst, vec![1, 3]);
+    assert_eq!(profile.total().triangles, 4);
+    assert_eq!(profile.total().time_ns, 830);
+}